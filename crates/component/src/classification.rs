@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Upstream keywords and the `info.classification` they point to. Earlier entries win
+/// ties, so the specific classifications come before the catch-all ones.
+const KEYWORD_CLASSIFICATIONS: &[(&str, &str)] = &[
+    ("python", "Development/Python"),
+    ("pypi", "Development/Python"),
+    ("perl", "Development/Perl"),
+    ("cpan", "Development/Perl"),
+    ("ruby", "Development/Ruby"),
+    ("rubygems", "Development/Ruby"),
+    ("java", "Development/Java"),
+    ("jvm", "Development/Java"),
+    ("git", "Development/Source Code Management"),
+    ("vcs", "Development/Source Code Management"),
+    ("version-control", "Development/Source Code Management"),
+    ("database", "System/Databases"),
+    ("databases", "System/Databases"),
+    ("database-implementations", "System/Databases"),
+    ("sql", "System/Databases"),
+    ("cryptography", "System/Security"),
+    ("crypto", "System/Security"),
+    ("security", "System/Security"),
+    ("tls", "System/Security"),
+    ("ssl", "System/Security"),
+    ("web-server", "Web Services/Application and Web Servers"),
+    ("http-server", "Web Services/Application and Web Servers"),
+    ("www", "Applications/Internet"),
+    ("http", "Applications/Internet"),
+    ("internet", "Applications/Internet"),
+    ("web", "Applications/Internet"),
+    ("network-programming", "Applications/Internet"),
+    ("networking", "Applications/Internet"),
+    ("net", "Applications/Internet"),
+    ("shell", "System/Shells"),
+    ("shells", "System/Shells"),
+    ("filesystem", "System/File System"),
+    ("filesystems", "System/File System"),
+    ("audio", "Applications/Sound and Video"),
+    ("video", "Applications/Sound and Video"),
+    ("multimedia", "Applications/Sound and Video"),
+    ("sound", "Applications/Sound and Video"),
+    ("graphics", "Applications/Graphics and Imaging"),
+    ("image", "Applications/Graphics and Imaging"),
+    ("images", "Applications/Graphics and Imaging"),
+    ("multimedia-images", "Applications/Graphics and Imaging"),
+    ("text-processing", "System/Text Tools"),
+    ("text", "System/Text Tools"),
+    ("textproc", "System/Text Tools"),
+    ("parsing", "System/Text Tools"),
+    ("sysadmin", "System/Administration and Configuration"),
+    (
+        "system-administration",
+        "System/Administration and Configuration",
+    ),
+    ("administration", "System/Administration and Configuration"),
+    ("command-line-utilities", "Applications/System Utilities"),
+    ("command-line-tool", "Applications/System Utilities"),
+    ("cli", "Applications/System Utilities"),
+    ("utilities", "Applications/System Utilities"),
+    ("sysutils", "Applications/System Utilities"),
+    ("compression", "Applications/System Utilities"),
+    ("archivers", "Applications/System Utilities"),
+    ("library", "System/Libraries"),
+    ("libraries", "System/Libraries"),
+    ("libs", "System/Libraries"),
+    ("devel", "System/Libraries"),
+];
+
+/// A classification derived from upstream metadata together with the keywords that led
+/// to it, for packagers to confirm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ClassificationSuggestion {
+    pub classification: String,
+    pub matched: Vec<String>,
+}
+
+/// Split topics, categories and classifiers like `Topic :: Internet :: WWW/HTTP` into
+/// lower case keywords. Slugs like `command-line-utilities` stay whole.
+fn keywords<S: AsRef<str>>(topics: &[S]) -> Vec<String> {
+    topics
+        .iter()
+        .flat_map(|t| {
+            t.as_ref()
+                .split(|c: char| c.is_whitespace() || matches!(c, ':' | '/' | ',' | ';'))
+                .map(|k| k.trim().to_lowercase().replace('_', "-"))
+                .filter(|k| !k.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Suggest the `info.classification` of a component from the topics of its upstream,
+/// like GitHub topics, crates.io categories and keywords, PyPI classifiers or repology
+/// categories. The classification most keywords point to wins.
+pub fn suggest_classification<S: AsRef<str>>(topics: &[S]) -> Option<ClassificationSuggestion> {
+    let keywords = keywords(topics);
+    let mut best: Option<(usize, ClassificationSuggestion)> = None;
+    for (rank, (_, classification)) in KEYWORD_CLASSIFICATIONS.iter().enumerate() {
+        if KEYWORD_CLASSIFICATIONS[..rank]
+            .iter()
+            .any(|(_, c)| c == classification)
+        {
+            continue;
+        }
+        let matched = KEYWORD_CLASSIFICATIONS
+            .iter()
+            .filter(|(keyword, c)| c == classification && keywords.iter().any(|k| k == keyword))
+            .map(|(keyword, _)| keyword.to_string())
+            .collect::<Vec<_>>();
+        if matched.is_empty()
+            || best
+                .as_ref()
+                .is_some_and(|(count, _)| *count >= matched.len())
+        {
+            continue;
+        }
+        best = Some((
+            matched.len(),
+            ClassificationSuggestion {
+                classification: classification.to_string(),
+                matched,
+            },
+        ));
+    }
+    best.map(|(_, suggestion)| suggestion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_matching_keywords_win() {
+        let suggestion = suggest_classification(&[
+            "Topic :: Internet :: WWW/HTTP",
+            "Programming Language :: Python :: 3",
+            "web",
+        ])
+        .unwrap();
+        assert_eq!(suggestion.classification, "Applications/Internet");
+        assert_eq!(suggestion.matched, vec!["www", "http", "internet", "web"]);
+    }
+
+    #[test]
+    fn specific_classifications_win_ties() {
+        let suggestion = suggest_classification(&["command-line-utilities", "database"]).unwrap();
+        assert_eq!(suggestion.classification, "System/Databases");
+
+        let suggestion = suggest_classification(&["Library", "rust"]).unwrap();
+        assert_eq!(suggestion.classification, "System/Libraries");
+        assert_eq!(suggestion.matched, vec!["library"]);
+    }
+
+    #[test]
+    fn unknown_topics_have_no_suggestion() {
+        assert_eq!(suggest_classification(&["rust", "async"]), None);
+        assert_eq!(suggest_classification::<&str>(&[]), None);
+    }
+}
@@ -0,0 +1,372 @@
+use diff::Diff;
+
+use crate::{BuildSection, DependencyKind, Recipe, RecipeDiff};
+
+/// Describe a stored [`RecipeDiff`] against the recipe it was taken from, one line per
+/// change, e.g. `version: 1.2 → 1.3` or `added dependency openssl`.
+pub fn describe_recipe_diff(before: &Recipe, diff: &RecipeDiff) -> Vec<String> {
+    describe_recipe_changes(before, &before.apply_new(diff))
+}
+
+/// Describe what changed between two versions of a recipe, one line per change.
+pub fn describe_recipe_changes(before: &Recipe, after: &Recipe) -> Vec<String> {
+    let mut lines = vec![];
+    if before.name != after.name {
+        lines.push(format!("name: {} → {}", before.name, after.name));
+    }
+    let values = [
+        ("version", &before.version, &after.version),
+        ("revision", &before.revision, &after.revision),
+        ("project name", &before.project_name, &after.project_name),
+        ("summary", &before.summary, &after.summary),
+        (
+            "classification",
+            &before.classification,
+            &after.classification,
+        ),
+        ("license", &before.license, &after.license),
+        ("license file", &before.license_file, &after.license_file),
+        ("prefix", &before.prefix, &after.prefix),
+        ("project url", &before.project_url, &after.project_url),
+    ];
+    for (field, before, after) in values {
+        if before != after {
+            lines.push(format!(
+                "{}: {} → {}",
+                field,
+                before.as_deref().unwrap_or("unset"),
+                after.as_deref().unwrap_or("unset")
+            ));
+        }
+    }
+    if before.seperate_build_dir != after.seperate_build_dir {
+        lines.push(format!(
+            "separate build directory {}",
+            if after.seperate_build_dir {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        ));
+    }
+    if before.metadata != after.metadata {
+        lines.push(String::from("metadata changed"));
+    }
+
+    describe_list(
+        &mut lines,
+        "maintainer",
+        &before.maintainers,
+        &after.maintainers,
+    );
+    describe_list(
+        &mut lines,
+        "architecture",
+        &before.architectures,
+        &after.architectures,
+    );
+    describe_list(
+        &mut lines,
+        "additional license",
+        &license_names(before),
+        &license_names(after),
+    );
+
+    let skipped = |recipe: &Recipe| -> Vec<String> {
+        recipe
+            .skipped_steps
+            .iter()
+            .map(|skip| skip.step.clone())
+            .collect()
+    };
+    let (skipped_before, skipped_after) = (skipped(before), skipped(after));
+    for step in missing(&skipped_before, &skipped_after) {
+        lines.push(format!("no longer skipping step {}", step));
+    }
+    for step in missing(&skipped_after, &skipped_before) {
+        lines.push(format!("skipping step {}", step));
+    }
+
+    describe_list(
+        &mut lines,
+        "source",
+        &source_lines(before),
+        &source_lines(after),
+    );
+    describe_dependencies(&mut lines, before, after);
+    describe_build(&mut lines, before, after);
+
+    match (&before.bootstrap, &after.bootstrap) {
+        (None, Some(_)) => lines.push(String::from("added bootstrap build")),
+        (Some(_), None) => lines.push(String::from("removed bootstrap build")),
+        (Some(old), Some(new)) if old != new => lines.push(String::from("bootstrap build changed")),
+        _ => {}
+    }
+
+    let package_name = |name: &Option<String>, recipe: &Recipe| {
+        name.clone().unwrap_or_else(|| recipe.name.clone())
+    };
+    for package in &after.package_sections {
+        let name = package_name(&package.name, after);
+        match before
+            .package_sections
+            .iter()
+            .find(|old| old.name == package.name)
+        {
+            None => lines.push(format!("added package {}", name)),
+            Some(old) if old != package => lines.push(format!("package {} changed", name)),
+            Some(_) => {}
+        }
+    }
+    for package in &before.package_sections {
+        if !after
+            .package_sections
+            .iter()
+            .any(|new| new.name == package.name)
+        {
+            lines.push(format!(
+                "removed package {}",
+                package_name(&package.name, before)
+            ));
+        }
+    }
+
+    for check in &after.checks {
+        match before.checks.iter().find(|old| old.name == check.name) {
+            None => lines.push(format!("added check {}", check.name)),
+            Some(old) if old != check => lines.push(format!("check {} changed", check.name)),
+            Some(_) => {}
+        }
+    }
+    for check in &before.checks {
+        if !after.checks.iter().any(|new| new.name == check.name) {
+            lines.push(format!("removed check {}", check.name));
+        }
+    }
+
+    lines
+}
+
+/// The entries of `of` that `other` does not have, in the order of `of`.
+fn missing<'a, T: PartialEq>(of: &'a [T], other: &'a [T]) -> impl Iterator<Item = &'a T> {
+    of.iter().filter(move |item| !other.contains(item))
+}
+
+fn describe_list(lines: &mut Vec<String>, what: &str, before: &[String], after: &[String]) {
+    for item in missing(after, before) {
+        lines.push(format!("added {} {}", what, item));
+    }
+    for item in missing(before, after) {
+        lines.push(format!("removed {} {}", what, item));
+    }
+}
+
+fn license_names(recipe: &Recipe) -> Vec<String> {
+    recipe
+        .additional_licenses
+        .iter()
+        .map(|license| format!("{} ({})", license.name, license.file))
+        .collect()
+}
+
+fn source_lines(recipe: &Recipe) -> Vec<String> {
+    recipe
+        .sources
+        .iter()
+        .flat_map(|section| section.sources.iter())
+        .map(|source| source.to_node().to_string().trim().to_string())
+        .collect()
+}
+
+fn kind_name(kind: &DependencyKind) -> &'static str {
+    match kind {
+        DependencyKind::Require => "require",
+        DependencyKind::Incorporate => "incorporate",
+        DependencyKind::Optional => "optional",
+    }
+}
+
+fn describe_dependencies(lines: &mut Vec<String>, before: &Recipe, after: &Recipe) {
+    for dependency in &after.dependencies {
+        let Some(old) = before
+            .dependencies
+            .iter()
+            .find(|old| old.name == dependency.name)
+        else {
+            let mut line = format!("added dependency {}", dependency.name);
+            if dependency.kind != DependencyKind::Require {
+                line.push_str(&format!(" ({})", kind_name(&dependency.kind)));
+            }
+            if dependency.dev {
+                line.push_str(" for building");
+            }
+            lines.push(line);
+            continue;
+        };
+        if old.kind != dependency.kind {
+            lines.push(format!(
+                "dependency {}: {} → {}",
+                dependency.name,
+                kind_name(&old.kind),
+                kind_name(&dependency.kind)
+            ));
+        }
+        if old.dev != dependency.dev {
+            lines.push(format!(
+                "dependency {} {} needed for building",
+                dependency.name,
+                if dependency.dev { "now" } else { "no longer" }
+            ));
+        }
+    }
+    for dependency in &before.dependencies {
+        if !after
+            .dependencies
+            .iter()
+            .any(|new| new.name == dependency.name)
+        {
+            lines.push(format!("removed dependency {}", dependency.name));
+        }
+    }
+}
+
+fn configure_flags(recipe: &Recipe) -> Vec<String> {
+    recipe
+        .build_sections
+        .iter()
+        .filter_map(|section| section.configure.as_ref())
+        .flat_map(|configure| configure.flags.iter())
+        .map(|flag| match &flag.flag_name {
+            Some(name) => format!("{} ({})", flag.flag, name),
+            None => flag.flag.clone(),
+        })
+        .collect()
+}
+
+fn configure_options(recipe: &Recipe) -> Vec<String> {
+    recipe
+        .build_sections
+        .iter()
+        .filter_map(|section| section.configure.as_ref())
+        .flat_map(|configure| configure.options.iter())
+        .map(|option| option.option.clone())
+        .collect()
+}
+
+/// The build sections without the flags and options, which are described one by one.
+fn build_steps(recipe: &Recipe) -> Vec<BuildSection> {
+    recipe
+        .build_sections
+        .iter()
+        .cloned()
+        .map(|mut section| {
+            if let Some(configure) = section.configure.as_mut() {
+                configure.flags.clear();
+                configure.options.clear();
+            }
+            section
+        })
+        .collect()
+}
+
+fn describe_build(lines: &mut Vec<String>, before: &Recipe, after: &Recipe) {
+    let (flags_before, flags_after) = (configure_flags(before), configure_flags(after));
+    for flag in missing(&flags_after, &flags_before) {
+        lines.push(format!("build flag {} added", flag));
+    }
+    for flag in missing(&flags_before, &flags_after) {
+        lines.push(format!("build flag {} removed", flag));
+    }
+    let (options_before, options_after) = (configure_options(before), configure_options(after));
+    for option in missing(&options_after, &options_before) {
+        lines.push(format!("configure option {} added", option));
+    }
+    for option in missing(&options_before, &options_after) {
+        lines.push(format!("configure option {} removed", option));
+    }
+    if build_steps(before) != build_steps(after) {
+        lines.push(String::from("build steps changed"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEFORE: &str = r#"
+name "library/foo"
+version "1.2"
+maintainer "alice"
+source {
+    archive "https://example.com/foo-1.2.tar.gz" sha512="abc"
+}
+dependency "library/zlib" kind="require"
+dependency "developer/gcc" dev=true kind="require"
+build {
+    configure {
+        option "--disable-static"
+        flag "-O2" name="CFLAGS"
+    }
+}
+check "tests" {
+    command "make check"
+}
+"#;
+
+    const AFTER: &str = r#"
+name "library/foo"
+version "1.3"
+maintainer "alice"
+maintainer "bob"
+source {
+    archive "https://example.com/foo-1.3.tar.gz" sha512="def"
+}
+dependency "library/zlib" kind="incorporate"
+dependency "library/openssl" kind="require"
+build {
+    configure {
+        option "--disable-static"
+        option "--with-ssl"
+    }
+}
+"#;
+
+    #[test]
+    fn recipe_changes_are_described() -> miette::Result<()> {
+        let before = knuffel::parse::<Recipe>("package.kdl", BEFORE)?;
+        let after = knuffel::parse::<Recipe>("package.kdl", AFTER)?;
+
+        assert_eq!(
+            describe_recipe_changes(&before, &after),
+            vec![
+                "version: 1.2 → 1.3",
+                "added maintainer bob",
+                r#"added source archive "https://example.com/foo-1.3.tar.gz" sha512="def""#,
+                r#"removed source archive "https://example.com/foo-1.2.tar.gz" sha512="abc""#,
+                "dependency library/zlib: require → incorporate",
+                "added dependency library/openssl",
+                "removed dependency developer/gcc",
+                "build flag -O2 (CFLAGS) removed",
+                "configure option --with-ssl added",
+                "removed check tests",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stored_diffs_are_described() -> miette::Result<()> {
+        let before = knuffel::parse::<Recipe>("package.kdl", BEFORE)?;
+        let after = knuffel::parse::<Recipe>("package.kdl", AFTER)?;
+
+        let diff = before.diff(&after);
+        let stored: RecipeDiff = serde_json::from_value(serde_json::to_value(&diff).unwrap())
+            .expect("recipe diffs survive being stored as JSON");
+        assert_eq!(
+            describe_recipe_diff(&before, &stored),
+            describe_recipe_changes(&before, &after)
+        );
+        assert!(describe_recipe_changes(&before, &before).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,577 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{ComponentError, ComponentResult, DependencyKind, Recipe};
+
+/// One build of a rebuild of many recipes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BuildStep {
+    /// Name of the recipe to build
+    pub name: String,
+    /// Build the bootstrap variant of the recipe instead of the full one
+    pub bootstrap: bool,
+}
+
+/// The order to build `recipes` in so every recipe is built after the recipes it needs
+/// to build. Dependencies on packages none of the recipes deliver are ignored, they
+/// have to be installed on the build host anyway.
+///
+/// Recipes needing each other are ordered by building the bootstrap variant of one of
+/// them first and that recipe again in full after the others of the cycle. Cycles none
+/// of the bootstrap sections break are a [`ComponentError::DependencyCycle`].
+pub fn build_order(recipes: &[Recipe]) -> ComponentResult<Vec<BuildStep>> {
+    let providers = providers(recipes);
+    let needs = build_needs(recipes, &providers);
+    let bootstrap_needs = bootstrap_needs(recipes, &providers, &needs);
+
+    let mut steps = vec![];
+    for members in strongly_connected(recipes, &needs) {
+        if let [member] = members.as_slice() {
+            steps.push(BuildStep {
+                name: recipes[*member].name.clone(),
+                bootstrap: false,
+            });
+        } else {
+            steps.extend(order_cycle(recipes, &members, &needs, &bootstrap_needs)?);
+        }
+    }
+    Ok(steps)
+}
+
+/// The builds needed after the recipes named `changed` changed: those recipes and every
+/// recipe needing one of them, directly or through other recipes, in the order they have
+/// to run. Recipes not affected by the change are not rebuilt, see [`build_order`].
+pub fn rebuild_order(recipes: &[Recipe], changed: &[String]) -> ComponentResult<Vec<BuildStep>> {
+    let providers = providers(recipes);
+    let needs = build_needs(recipes, &providers);
+
+    let mut affected = recipes
+        .iter()
+        .enumerate()
+        .filter(|(_, recipe)| changed.contains(&recipe.name))
+        .map(|(index, _)| index)
+        .collect::<BTreeSet<_>>();
+    loop {
+        let dependents = needs
+            .iter()
+            .enumerate()
+            .filter(|(index, needs)| !affected.contains(index) && !needs.is_disjoint(&affected))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if dependents.is_empty() {
+            break;
+        }
+        affected.extend(dependents);
+    }
+
+    let affected = affected
+        .into_iter()
+        .map(|index| recipes[index].clone())
+        .collect::<Vec<_>>();
+    build_order(&affected)
+}
+
+/// Formats a dependency graph can be rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    #[default]
+    Json,
+    /// Graphviz DOT
+    Dot,
+}
+
+impl Display for GraphFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphFormat::Json => write!(f, "json"),
+            GraphFormat::Dot => write!(f, "dot"),
+        }
+    }
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(GraphFormat::Json),
+            "dot" => Ok(GraphFormat::Dot),
+            _ => Err(format!("unknown graph format {}, use json or dot", s)),
+        }
+    }
+}
+
+/// A dependency of one recipe on a package another recipe delivers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DependencyEdge {
+    /// Name of the recipe declaring the dependency
+    pub from: String,
+    /// Name of the recipe delivering the package
+    pub to: String,
+    /// The dependency as the recipe declares it
+    pub dependency: String,
+    pub kind: DependencyKind,
+    pub dev: bool,
+}
+
+/// A dependency on a package none of the recipes deliver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MissingDependency {
+    /// Name of the recipe declaring the dependency
+    pub component: String,
+    pub dependency: String,
+    pub kind: DependencyKind,
+    pub dev: bool,
+}
+
+/// Recipes needing each other, directly or through other recipes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DependencyCycle {
+    pub members: Vec<String>,
+    /// Whether the bootstrap sections of the members break the cycle so they can be built
+    pub bootstrappable: bool,
+}
+
+/// The recipes and how they depend on each other.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DependencyGraph {
+    /// Names of the recipes
+    pub nodes: Vec<String>,
+    pub edges: Vec<DependencyEdge>,
+    /// Cycles of the dependencies needed to build, optional ones are left out
+    pub cycles: Vec<DependencyCycle>,
+    pub missing: Vec<MissingDependency>,
+}
+
+impl DependencyGraph {
+    /// The graph as a Graphviz digraph. Optional dependencies are dotted, development
+    /// ones dashed and recipes in cycles red.
+    pub fn to_dot(&self, name: &str) -> String {
+        let in_cycle = self
+            .cycles
+            .iter()
+            .flat_map(|c| c.members.iter())
+            .collect::<BTreeSet<_>>();
+        let mut dot = format!("digraph {:?} {{\n", name);
+        for node in self.nodes.iter() {
+            if in_cycle.contains(node) {
+                dot.push_str(&format!("  {:?} [color=red];\n", node));
+            } else {
+                dot.push_str(&format!("  {:?};\n", node));
+            }
+        }
+        for edge in self.edges.iter() {
+            let style = match (&edge.kind, edge.dev) {
+                (DependencyKind::Optional, _) => " [style=dotted]",
+                (_, true) => " [style=dashed]",
+                _ => "",
+            };
+            dot.push_str(&format!("  {:?} -> {:?}{};\n", edge.from, edge.to, style));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// The graph of the dependencies of `recipes` on each other, with the cycles in it and
+/// the dependencies none of the recipes deliver.
+pub fn dependency_graph(recipes: &[Recipe]) -> DependencyGraph {
+    let providers = providers(recipes);
+    let needs = build_needs(recipes, &providers);
+    let bootstrap_needs = bootstrap_needs(recipes, &providers, &needs);
+
+    let mut graph = DependencyGraph::default();
+    for (index, recipe) in recipes.iter().enumerate() {
+        graph.nodes.push(recipe.name.clone());
+        for dependency in recipe.dependencies.iter() {
+            match providers.get(package_name(&dependency.name)) {
+                Some(provider) if *provider == index => {}
+                Some(provider) => graph.edges.push(DependencyEdge {
+                    from: recipe.name.clone(),
+                    to: recipes[*provider].name.clone(),
+                    dependency: dependency.name.clone(),
+                    kind: dependency.kind.clone(),
+                    dev: dependency.dev,
+                }),
+                None => graph.missing.push(MissingDependency {
+                    component: recipe.name.clone(),
+                    dependency: dependency.name.clone(),
+                    kind: dependency.kind.clone(),
+                    dev: dependency.dev,
+                }),
+            }
+        }
+    }
+    graph.nodes.sort();
+    graph
+        .edges
+        .sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+
+    for members in strongly_connected(recipes, &needs) {
+        if members.len() < 2 {
+            continue;
+        }
+        let bootstrappable = order_cycle(recipes, &members, &needs, &bootstrap_needs).is_ok();
+        let mut members = members
+            .into_iter()
+            .map(|m| recipes[m].name.clone())
+            .collect::<Vec<_>>();
+        members.sort();
+        graph.cycles.push(DependencyCycle {
+            members,
+            bootstrappable,
+        });
+    }
+    graph
+}
+
+/// Which recipe delivers each package.
+fn providers(recipes: &[Recipe]) -> HashMap<&str, usize> {
+    let mut providers = HashMap::new();
+    for (index, recipe) in recipes.iter().enumerate() {
+        providers.insert(package_name(&recipe.name), index);
+        for section in recipe.package_sections.iter() {
+            if let Some(name) = &section.name {
+                providers.insert(package_name(name), index);
+            }
+        }
+    }
+    providers
+}
+
+/// The recipes each recipe needs to be built before it.
+fn build_needs(recipes: &[Recipe], providers: &HashMap<&str, usize>) -> Vec<BTreeSet<usize>> {
+    recipes
+        .iter()
+        .enumerate()
+        .map(|(index, recipe)| {
+            resolve(
+                providers,
+                index,
+                recipe
+                    .dependencies
+                    .iter()
+                    .filter(|d| d.kind != DependencyKind::Optional)
+                    .map(|d| &d.name),
+            )
+        })
+        .collect()
+}
+
+/// The recipes the bootstrap variant of each recipe needs, if it has one.
+fn bootstrap_needs(
+    recipes: &[Recipe],
+    providers: &HashMap<&str, usize>,
+    needs: &[BTreeSet<usize>],
+) -> Vec<Option<BTreeSet<usize>>> {
+    recipes
+        .iter()
+        .enumerate()
+        .map(|(index, recipe)| {
+            recipe.bootstrap.as_ref().map(|bootstrap| {
+                let without = resolve(providers, index, bootstrap.without.iter());
+                needs[index].difference(&without).copied().collect()
+            })
+        })
+        .collect()
+}
+
+/// The recipes delivering the packages `names` other than the recipe at `index`.
+fn resolve<'a>(
+    providers: &HashMap<&str, usize>,
+    index: usize,
+    names: impl Iterator<Item = &'a String>,
+) -> BTreeSet<usize> {
+    names
+        .filter_map(|name| providers.get(package_name(name)).copied())
+        .filter(|provider| *provider != index)
+        .collect()
+}
+
+/// Package names as dependencies refer to them: pkg:/library/foo@1.0 -> library/foo
+pub(crate) fn package_name(name: &str) -> &str {
+    let name = name.strip_prefix("pkg:").unwrap_or(name);
+    let name = name.trim_start_matches('/');
+    name.split('@').next().unwrap_or(name)
+}
+
+/// The strongly connected components of the graph with Tarjan's algorithm. A recipe
+/// is only completed after everything it needs, so the components come out in build
+/// order.
+fn strongly_connected(recipes: &[Recipe], needs: &[BTreeSet<usize>]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        needs: &'a [BTreeSet<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, v: usize) {
+            self.index[v] = Some(self.next);
+            self.low[v] = self.next;
+            self.next += 1;
+            self.stack.push(v);
+            self.on_stack[v] = true;
+
+            let needs = self.needs;
+            for &w in needs[v].iter() {
+                match self.index[w] {
+                    None => {
+                        self.visit(w);
+                        self.low[v] = self.low[v].min(self.low[w]);
+                    }
+                    Some(index) if self.on_stack[w] => {
+                        self.low[v] = self.low[v].min(index);
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            if Some(self.low[v]) == self.index[v] {
+                let mut component = vec![];
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        needs,
+        index: vec![None; needs.len()],
+        low: vec![0; needs.len()],
+        on_stack: vec![false; needs.len()],
+        stack: vec![],
+        next: 0,
+        components: vec![],
+    };
+    let mut by_name = (0..recipes.len()).collect::<Vec<_>>();
+    by_name.sort_by(|a, b| recipes[*a].name.cmp(&recipes[*b].name));
+    for v in by_name {
+        if tarjan.index[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+    tarjan.components
+}
+
+/// Order the members of a cycle by bootstrapping as few of them as needed.
+fn order_cycle(
+    recipes: &[Recipe],
+    members: &[usize],
+    needs: &[BTreeSet<usize>],
+    bootstrap_needs: &[Option<BTreeSet<usize>>],
+) -> ComponentResult<Vec<BuildStep>> {
+    let mut members = members.to_vec();
+    members.sort_by(|a, b| recipes[*a].name.cmp(&recipes[*b].name));
+    let cycle = members.iter().copied().collect::<BTreeSet<_>>();
+
+    let mut bootstrapped = BTreeSet::new();
+    loop {
+        let needs_in_cycle = |member: usize| -> BTreeSet<usize> {
+            let member_needs = match &bootstrap_needs[member] {
+                Some(reduced) if bootstrapped.contains(&member) => reduced,
+                _ => &needs[member],
+            };
+            member_needs.intersection(&cycle).copied().collect()
+        };
+
+        // Build whatever has everything it needs, the first by name for stable orders
+        let mut order = vec![];
+        let mut remaining = members.clone();
+        while let Some(position) = remaining
+            .iter()
+            .position(|m| needs_in_cycle(*m).iter().all(|n| order.contains(n)))
+        {
+            order.push(remaining.remove(position));
+        }
+
+        if remaining.is_empty() {
+            let mut steps = order
+                .iter()
+                .map(|m| BuildStep {
+                    name: recipes[*m].name.clone(),
+                    bootstrap: bootstrapped.contains(m),
+                })
+                .collect::<Vec<_>>();
+            steps.extend(
+                order
+                    .iter()
+                    .filter(|m| bootstrapped.contains(*m))
+                    .map(|m| BuildStep {
+                        name: recipes[*m].name.clone(),
+                        bootstrap: false,
+                    }),
+            );
+            return Ok(steps);
+        }
+
+        // Bootstrap the first stuck recipe whose bootstrap variant drops one of the
+        // recipes it waits for
+        let stuck = remaining.iter().copied().collect::<BTreeSet<_>>();
+        let candidate = remaining.iter().copied().find(|m| {
+            !bootstrapped.contains(m)
+                && bootstrap_needs[*m].as_ref().is_some_and(|reduced| {
+                    needs[*m]
+                        .difference(reduced)
+                        .any(|dropped| stuck.contains(dropped))
+                })
+        });
+        match candidate {
+            Some(member) => {
+                bootstrapped.insert(member);
+            }
+            None => {
+                return Err(ComponentError::DependencyCycle(
+                    remaining.iter().map(|m| recipes[*m].name.clone()).collect(),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BootstrapSection, Dependency, RecipeBuilder};
+
+    use super::*;
+
+    fn recipe(name: &str, needs: &[&str], without: Option<&[&str]>) -> Recipe {
+        let mut builder = RecipeBuilder::default();
+        builder.name(name).dependencies(
+            needs
+                .iter()
+                .map(|n| Dependency {
+                    name: n.to_string(),
+                    dev: true,
+                    kind: DependencyKind::Require,
+                })
+                .collect::<Vec<_>>(),
+        );
+        if let Some(without) = without {
+            builder.bootstrap(BootstrapSection {
+                without: without.iter().map(|w| w.to_string()).collect(),
+                options: vec![],
+            });
+        }
+        builder.build().unwrap()
+    }
+
+    fn steps(steps: &[(&str, bool)]) -> Vec<BuildStep> {
+        steps
+            .iter()
+            .map(|(name, bootstrap)| BuildStep {
+                name: name.to_string(),
+                bootstrap: *bootstrap,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_are_built_first() {
+        let recipes = vec![
+            recipe("app", &["library/b", "pkg:/library/a@1.0"], None),
+            recipe("library/b", &["library/a", "system/not-in-gate"], None),
+            recipe("library/a", &[], None),
+        ];
+        assert_eq!(
+            build_order(&recipes).unwrap(),
+            steps(&[("library/a", false), ("library/b", false), ("app", false)])
+        );
+    }
+
+    #[test]
+    fn cycles_without_bootstrap_are_reported() {
+        let recipes = vec![
+            recipe("a", &["b"], None),
+            recipe("b", &["a"], None),
+            recipe("c", &[], None),
+        ];
+        match build_order(&recipes) {
+            Err(ComponentError::DependencyCycle(members)) => {
+                assert_eq!(members, vec!["a".to_string(), "b".to_string()])
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bootstrap_breaks_cycles() {
+        let recipes = vec![
+            recipe("app", &["a"], None),
+            recipe("a", &["b"], None),
+            recipe("b", &["a"], Some(&["a"])),
+        ];
+        assert_eq!(
+            build_order(&recipes).unwrap(),
+            steps(&[("b", true), ("a", false), ("b", false), ("app", false)])
+        );
+    }
+
+    #[test]
+    fn dependents_are_rebuilt_after_the_change() {
+        let recipes = vec![
+            recipe("app", &["library/b"], None),
+            recipe("tool", &["library/c"], None),
+            recipe("library/b", &["library/a"], None),
+            recipe("library/a", &[], None),
+            recipe("library/c", &[], None),
+        ];
+        assert_eq!(
+            rebuild_order(&recipes, &["library/a".to_string()]).unwrap(),
+            steps(&[("library/a", false), ("library/b", false), ("app", false)])
+        );
+        assert_eq!(
+            rebuild_order(&recipes, &["app".to_string()]).unwrap(),
+            steps(&[("app", false)])
+        );
+    }
+
+    #[test]
+    fn graph_flags_cycles_and_missing_dependencies() {
+        let recipes = vec![
+            recipe("app", &["a", "system/not-in-gate"], None),
+            recipe("a", &["b"], None),
+            recipe("b", &["a"], Some(&["a"])),
+            recipe("c", &["d"], None),
+            recipe("d", &["c"], None),
+        ];
+        let graph = dependency_graph(&recipes);
+        assert_eq!(graph.nodes, vec!["a", "app", "b", "c", "d"]);
+        assert_eq!(graph.edges.len(), 5);
+        assert_eq!(graph.missing.len(), 1);
+        assert_eq!(graph.missing[0].dependency, "system/not-in-gate");
+        assert_eq!(
+            graph.cycles,
+            vec![
+                DependencyCycle {
+                    members: vec!["a".to_string(), "b".to_string()],
+                    bootstrappable: true,
+                },
+                DependencyCycle {
+                    members: vec!["c".to_string(), "d".to_string()],
+                    bootstrappable: false,
+                },
+            ]
+        );
+
+        let dot = graph.to_dot("userland");
+        assert!(dot.starts_with("digraph \"userland\" {"));
+        assert!(dot.contains("  \"c\" [color=red];"));
+        assert!(dot.contains("  \"app\" -> \"a\" [style=dashed];"));
+    }
+}
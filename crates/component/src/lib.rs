@@ -15,6 +15,21 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utoipa::ToSchema;
 
+mod classification;
+mod describe;
+mod graph;
+mod license;
+mod sbom;
+
+pub use classification::{suggest_classification, ClassificationSuggestion};
+pub use describe::{describe_recipe_changes, describe_recipe_diff};
+pub use graph::{
+    build_order, dependency_graph, rebuild_order, BuildStep, DependencyCycle, DependencyEdge,
+    DependencyGraph, GraphFormat, MissingDependency,
+};
+pub use license::{normalize_license, LicenseError, LicenseExpression};
+pub use sbom::{render_sbom, SbomEntry, SbomFile, SbomFormat};
+
 #[derive(Error, Debug, Diagnostic)]
 pub enum ComponentError {
     #[error(transparent)]
@@ -41,9 +56,20 @@ pub enum ComponentError {
     #[error(transparent)]
     UninitializedFieldError(#[from] derive_builder::UninitializedFieldError),
 
+    #[error("invalid {0}: {1}")]
+    #[diagnostic(code(component::validation_error))]
+    ValidationError(String, String),
+
     #[error(transparent)]
     #[diagnostic(transparent)]
     Knuffel(#[from] knuffel::Error),
+
+    #[error("build dependency cycle between {}", .0.join(", "))]
+    #[diagnostic(
+        code(component::dependency_cycle),
+        help("add a bootstrap section going without one of the dependencies to one of the recipes")
+    )]
+    DependencyCycle(Vec<String>),
 }
 
 type ComponentResult<T> = Result<T, ComponentError>;
@@ -60,6 +86,34 @@ pub struct Component {
     path: PathBuf,
     pub recipe: Recipe,
     pub package_meta: Option<PackageMeta>,
+    #[serde(skip)]
+    #[schemars(skip)]
+    document: SourceDocument,
+}
+
+/// The package.kdl a component was opened from together with the recipe decoded from it.
+/// Saving applies the changes made to the recipe since then as targeted edits to this
+/// document so hand-written comments and ordering survive. It takes no part in
+/// comparisons or diffs of components.
+#[derive(Debug, Clone, Default)]
+pub struct SourceDocument(Option<(kdl::KdlDocument, Recipe)>);
+
+impl PartialEq for SourceDocument {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Diff for SourceDocument {
+    type Repr = ();
+
+    fn diff(&self, _other: &Self) -> Self::Repr {}
+
+    fn apply(&mut self, _diff: &Self::Repr) {}
+
+    fn identity() -> Self {
+        Self::default()
+    }
 }
 
 impl Component {
@@ -74,6 +128,7 @@ impl Component {
             path,
             recipe: RecipeBuilder::default().name(name).build()?,
             package_meta: None,
+            document: SourceDocument::default(),
         })
     }
 
@@ -106,19 +161,22 @@ impl Component {
             None
         };
 
+        let kdl_document: kdl::KdlDocument = package_document_string.parse()?;
         if path.is_file() {
             let package_document = knuffel::parse::<Recipe>(&name, &package_document_string)?;
             Ok(Self {
                 path: dir.to_path_buf(),
-                recipe: package_document,
+                recipe: package_document.clone(),
                 package_meta,
+                document: SourceDocument(Some((kdl_document, package_document))),
             })
         } else {
             let package_document = knuffel::parse::<Recipe>(&name, &package_document_string)?;
             Ok(Self {
                 path,
-                recipe: package_document,
+                recipe: package_document.clone(),
                 package_meta,
+                document: SourceDocument(Some((kdl_document, package_document))),
             })
         }
     }
@@ -128,11 +186,21 @@ impl Component {
             .into_diagnostic()
             .wrap_err("could not open package document")?;
         self.recipe = knuffel::parse::<Recipe>("package.kdl", &data_string)?;
+        self.document = SourceDocument(Some((data_string.parse()?, self.recipe.clone())));
         Ok(())
     }
 
+    /// Write the recipe to package.kdl. If the component was opened from disk only the
+    /// nodes of changed fields are touched, otherwise the document is generated.
     pub fn save_document(&self) -> ComponentResult<()> {
-        let doc_str = self.recipe.to_document().to_string();
+        let doc_str = match &self.document.0 {
+            Some((document, original)) => {
+                let mut document = document.clone();
+                self.recipe.update_document(original, &mut document);
+                document.to_string()
+            }
+            None => self.recipe.to_document().to_string(),
+        };
         let mut f = File::create(&self.path.join("package.kdl"))?;
         f.write_all(doc_str.as_bytes())?;
         Ok(())
@@ -143,6 +211,7 @@ impl Component {
             src_section.sources.push(node);
         } else {
             let src_section = SourceSection {
+                name: None,
                 sources: vec![node],
             };
             self.recipe.sources.push(src_section);
@@ -160,6 +229,28 @@ impl Component {
         self.recipe.name.clone()
     }
 
+    pub fn supports_arch(&self, arch: &str) -> bool {
+        self.recipe.architectures.is_empty() || self.recipe.architectures.iter().any(|a| a == arch)
+    }
+
+    /// Check the recipe for structural problems including the ones that need
+    /// the component directory like missing patch files.
+    pub fn validate(&self) -> Vec<RecipeViolation> {
+        let mut violations = self.recipe.validate();
+        for section in &self.recipe.sources {
+            for src in &section.sources {
+                if let SourceNode::Patch(patch) = src {
+                    if !patch.get_bundle_path(&self.path).exists() {
+                        violations.push(RecipeViolation::MissingPatchFile {
+                            path: patch.bundle_path.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        violations
+    }
+
     pub fn get_mogrify_manifest(&self) -> Option<PathBuf> {
         let file_path = self.path.join("manifest.mog");
         if file_path.exists() {
@@ -170,6 +261,47 @@ impl Component {
     }
 }
 
+/// A structural problem found in a recipe by [`Recipe::validate`] or [`Component::validate`].
+#[derive(Error, Debug, Clone, Serialize, Deserialize, PartialEq, JsonSchema, ToSchema)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum RecipeViolation {
+    #[error("recipe has build sections but no sources")]
+    NoSources,
+
+    #[error("build section {index} has no build style")]
+    NoBuildStyle { index: usize },
+
+    #[error("build section {index} has more than one build style: {}", .styles.join(", "))]
+    MultipleBuildStyles { index: usize, styles: Vec<String> },
+
+    #[error("patch file {path} does not exist")]
+    MissingPatchFile { path: String },
+
+    #[error("step {step} cannot be skipped, only {} can", SKIPPABLE_STEPS.join(", "))]
+    UnknownSkipStep { step: String },
+
+    #[error("skipping step {step} needs a justification")]
+    UnjustifiedSkip { step: String },
+
+    #[error("bootstrap build goes without {name} which is not a dependency")]
+    UnknownBootstrapDependency { name: String },
+
+    #[error("check {name} has no command, file or script to check")]
+    EmptyCheck { name: String },
+
+    #[error("there is more than one check called {name}")]
+    DuplicateCheck { name: String },
+
+    #[error("there is more than one source section called {name}")]
+    DuplicateSource { name: String },
+
+    #[error("build section {index} builds source {name} which no source section is called")]
+    UnknownBuildSource { index: usize, name: String },
+
+    #[error("build section {index} directory {directory} is not relative to the sources")]
+    InvalidBuildDirectory { index: usize, directory: String },
+}
+
 #[derive(
     Debug,
     knuffel::Decode,
@@ -190,6 +322,10 @@ pub struct PackageMeta {
     name: String,
     fmris: Vec<String>,
     dependencies: Vec<String>,
+    /// SONAMEs of the shared libraries the packages of the component deliver.
+    #[serde(default)]
+    #[builder(default)]
+    libraries: Vec<String>,
 }
 
 impl Default for PackageMeta {
@@ -198,10 +334,29 @@ impl Default for PackageMeta {
             name: "".to_string(),
             fmris: vec![],
             dependencies: vec![],
+            libraries: vec![],
         }
     }
 }
 
+impl PackageMeta {
+    pub fn fmris(&self) -> &[String] {
+        &self.fmris
+    }
+
+    pub fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
+
+    pub fn libraries(&self) -> &[String] {
+        &self.libraries
+    }
+
+    pub fn set_libraries(&mut self, libraries: Vec<String>) {
+        self.libraries = libraries;
+    }
+}
+
 #[derive(
     Debug,
     knuffel::Decode,
@@ -233,6 +388,18 @@ pub struct ComponentMetadataItem {
 ))]
 pub struct ComponentMetadata(#[knuffel(children)] pub Vec<ComponentMetadataItem>);
 
+impl ComponentMetadata {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut metadata_node = kdl::KdlNode::new("metadata");
+        for item in &self.0 {
+            let mut item_node = kdl::KdlNode::new(item.name.clone());
+            item_node.insert(0, item.value.clone());
+            metadata_node.ensure_children().nodes_mut().push(item_node);
+        }
+        metadata_node
+    }
+}
+
 #[derive(
     Debug,
     knuffel::Decode,
@@ -281,6 +448,12 @@ pub struct Recipe {
     #[builder(default)]
     pub license: Option<String>,
 
+    /// Further licenses delivered with every package of the component, for upstreams
+    /// shipping several.
+    #[knuffel(children(name = "additional-license"))]
+    #[builder(default)]
+    pub additional_licenses: Vec<LicenseEntry>,
+
     #[knuffel(child, unwrap(argument))]
     #[builder(default)]
     pub prefix: Option<String>,
@@ -301,6 +474,23 @@ pub struct Recipe {
     #[builder(default)]
     pub seperate_build_dir: bool,
 
+    /// Jobs make and ninja run at once for this component, overriding the gate and the
+    /// settings. Upstreams whose build breaks when run in parallel set it to 1.
+    #[knuffel(child, unwrap(argument))]
+    #[builder(default)]
+    #[serde(default)]
+    pub jobs: Option<u32>,
+
+    /// The architectures the component can be built for. Empty means all of them.
+    #[knuffel(children(name = "arch"), unwrap(argument))]
+    #[builder(default)]
+    pub architectures: Vec<String>,
+
+    /// Standard build steps to leave out, see [`SKIPPABLE_STEPS`].
+    #[knuffel(children(name = "skip"))]
+    #[builder(default)]
+    pub skipped_steps: Vec<SkipStep>,
+
     #[knuffel(children(name = "source"))]
     #[builder(default)]
     pub sources: Vec<SourceSection>,
@@ -309,6 +499,11 @@ pub struct Recipe {
     #[builder(default)]
     pub dependencies: Vec<Dependency>,
 
+    /// A reduced build breaking build dependency cycles, see [`build_order`].
+    #[knuffel(child)]
+    #[builder(default)]
+    pub bootstrap: Option<BootstrapSection>,
+
     #[knuffel(children(name = "build"))]
     #[builder(default)]
     pub build_sections: Vec<BuildSection>,
@@ -316,6 +511,88 @@ pub struct Recipe {
     #[knuffel(children(name = "package"))]
     #[builder(default)]
     pub package_sections: Vec<PackageSection>,
+
+    /// Checks run on the result of the build before it is packaged.
+    #[knuffel(children(name = "check"))]
+    #[builder(default)]
+    pub checks: Vec<CheckSection>,
+}
+
+fn argument_node(name: &str, value: &str) -> kdl::KdlNode {
+    let mut node = kdl::KdlNode::new(name);
+    node.insert(0, value);
+    node
+}
+
+fn jobs_node(jobs: &u32) -> kdl::KdlNode {
+    let mut node = kdl::KdlNode::new("jobs");
+    node.insert(0, i64::from(*jobs));
+    node
+}
+
+/// Replace `node` with `new` while keeping the comments and whitespace around it.
+fn replace_node(node: &mut kdl::KdlNode, mut new: kdl::KdlNode) {
+    if let Some(leading) = node.leading() {
+        new.set_leading(leading);
+    }
+    if let Some(trailing) = node.trailing() {
+        new.set_trailing(trailing);
+    }
+    *node = new;
+}
+
+fn update_nodes<T: PartialEq>(
+    doc: &mut kdl::KdlDocument,
+    name: &str,
+    original: &[T],
+    updated: &[T],
+    to_node: impl Fn(&T) -> kdl::KdlNode,
+) {
+    update_nodes_with(doc, name, original, updated, &to_node, |_, new, node| {
+        replace_node(node, to_node(new))
+    });
+}
+
+/// Bring the top level nodes called `name` in line with `updated`. The nodes are
+/// expected to be the ones `original` was decoded from. Changed elements are handed to
+/// `update_node`, missing ones are appended after the last node of that name and
+/// surplus nodes are removed.
+fn update_nodes_with<T: PartialEq>(
+    doc: &mut kdl::KdlDocument,
+    name: &str,
+    original: &[T],
+    updated: &[T],
+    to_node: impl Fn(&T) -> kdl::KdlNode,
+    update_node: impl Fn(&T, &T, &mut kdl::KdlNode),
+) {
+    let positions = doc
+        .nodes()
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| n.name().value() == name)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    for ((position, old), new) in positions.iter().zip(original).zip(updated) {
+        if old != new {
+            update_node(old, new, &mut doc.nodes_mut()[*position]);
+        }
+    }
+
+    if updated.len() > positions.len() {
+        let mut insert_at = positions
+            .last()
+            .map(|p| p + 1)
+            .unwrap_or(doc.nodes().len());
+        for new in &updated[positions.len()..] {
+            doc.nodes_mut().insert(insert_at, to_node(new));
+            insert_at += 1;
+        }
+    } else {
+        for position in positions[updated.len()..].iter().rev() {
+            doc.nodes_mut().remove(*position);
+        }
+    }
 }
 
 impl Display for Recipe {
@@ -325,6 +602,12 @@ impl Display for Recipe {
 }
 
 impl Recipe {
+    /// Decode the recipe in `document`, the contents of a package.kdl. Errors name the
+    /// document `name`.
+    pub fn parse(name: &str, document: &str) -> ComponentResult<Self> {
+        Ok(knuffel::parse::<Recipe>(name, document)?)
+    }
+
     pub fn to_document(&self) -> kdl::KdlDocument {
         let pkg_node = self.to_node();
         pkg_node
@@ -333,6 +616,140 @@ impl Recipe {
             .clone()
     }
 
+    /// Apply the differences between `original` and this recipe to `doc`, the document
+    /// `original` was decoded from. Nodes of unchanged fields keep their formatting and
+    /// new nodes are placed after the existing nodes of the same name.
+    pub fn update_document(&self, original: &Recipe, doc: &mut kdl::KdlDocument) {
+        update_nodes(
+            doc,
+            "name",
+            std::slice::from_ref(&original.name),
+            std::slice::from_ref(&self.name),
+            |v| argument_node("name", v),
+        );
+        update_nodes(
+            doc,
+            "metadata",
+            original.metadata.as_slice(),
+            self.metadata.as_slice(),
+            ComponentMetadata::to_node,
+        );
+        for (name, old, new) in [
+            ("project-name", &original.project_name, &self.project_name),
+            ("classification", &original.classification, &self.classification),
+            ("summary", &original.summary, &self.summary),
+            ("license-file", &original.license_file, &self.license_file),
+            ("license", &original.license, &self.license),
+            ("prefix", &original.prefix, &self.prefix),
+            ("version", &original.version, &self.version),
+            ("revision", &original.revision, &self.revision),
+            ("project-url", &original.project_url, &self.project_url),
+        ] {
+            update_nodes(doc, name, old.as_slice(), new.as_slice(), |v| {
+                argument_node(name, v)
+            });
+        }
+        update_nodes(
+            doc,
+            "maintainer",
+            &original.maintainers,
+            &self.maintainers,
+            |v| argument_node("maintainer", v),
+        );
+        update_nodes(
+            doc,
+            "additional-license",
+            &original.additional_licenses,
+            &self.additional_licenses,
+            |l| l.to_node("additional-license"),
+        );
+
+        if original.seperate_build_dir != self.seperate_build_dir {
+            if self.seperate_build_dir {
+                doc.nodes_mut()
+                    .push(kdl::KdlNode::new("seperate-build-dir"));
+            } else {
+                doc.nodes_mut()
+                    .retain(|n| n.name().value() != "seperate-build-dir");
+            }
+        }
+        update_nodes(
+            doc,
+            "jobs",
+            original.jobs.as_slice(),
+            self.jobs.as_slice(),
+            jobs_node,
+        );
+        update_nodes(
+            doc,
+            "arch",
+            &original.architectures,
+            &self.architectures,
+            |v| argument_node("arch", v),
+        );
+        update_nodes(
+            doc,
+            "skip",
+            &original.skipped_steps,
+            &self.skipped_steps,
+            SkipStep::to_node,
+        );
+
+        update_nodes_with(
+            doc,
+            "source",
+            &original.sources,
+            &self.sources,
+            SourceSection::to_node,
+            |old, new, node| {
+                // Sources are usually only added, keep the existing ones untouched then
+                if old.name == new.name && new.sources.starts_with(&old.sources) {
+                    let children = node.ensure_children();
+                    for src in &new.sources[old.sources.len()..] {
+                        children.nodes_mut().push(src.to_node());
+                    }
+                } else {
+                    replace_node(node, new.to_node());
+                }
+            },
+        );
+        update_nodes(
+            doc,
+            "dependency",
+            &original.dependencies,
+            &self.dependencies,
+            Dependency::to_node,
+        );
+        update_nodes(
+            doc,
+            "bootstrap",
+            original.bootstrap.as_slice(),
+            self.bootstrap.as_slice(),
+            BootstrapSection::to_node,
+        );
+        update_nodes(
+            doc,
+            "build",
+            &original.build_sections,
+            &self.build_sections,
+            BuildSection::to_node,
+        );
+        update_nodes(
+            doc,
+            "package",
+            &original.package_sections,
+            &self.package_sections,
+            PackageSection::to_node,
+        );
+        update_nodes(
+            doc,
+            "check",
+            &original.checks,
+            &self.checks,
+            CheckSection::to_node,
+        );
+    }
+
     pub fn insert_metadata(&mut self, key: &str, value: &str) {
         if self.metadata.is_none() {
             self.metadata = Some(ComponentMetadata(vec![]));
@@ -359,13 +776,7 @@ impl Recipe {
         }
 
         if let Some(metadata) = &self.metadata {
-            let mut metadata_node = kdl::KdlNode::new("metadata");
-            for item in &metadata.0 {
-                let mut item_node = kdl::KdlNode::new(item.name.clone());
-                item_node.insert(0, item.value.clone());
-                metadata_node.ensure_children().nodes_mut().push(item_node);
-            }
-            doc.nodes_mut().push(metadata_node);
+            doc.nodes_mut().push(metadata.to_node());
         }
 
         if let Some(classification) = &self.classification {
@@ -392,6 +803,10 @@ impl Recipe {
             doc.nodes_mut().push(license_node);
         }
 
+        for license in &self.additional_licenses {
+            doc.nodes_mut().push(license.to_node("additional-license"));
+        }
+
         if let Some(prefix) = &self.prefix {
             let mut prefix_node = kdl::KdlNode::new("prefix");
             prefix_node.insert(0, prefix.as_str());
@@ -422,6 +837,24 @@ impl Recipe {
             doc.nodes_mut().push(maintainer_node);
         }
 
+        if self.seperate_build_dir {
+            doc.nodes_mut().push(kdl::KdlNode::new("seperate-build-dir"));
+        }
+
+        if let Some(jobs) = &self.jobs {
+            doc.nodes_mut().push(jobs_node(jobs));
+        }
+
+        for arch in self.architectures.iter() {
+            let mut arch_node = kdl::KdlNode::new("arch");
+            arch_node.insert(0, arch.as_str());
+            doc.nodes_mut().push(arch_node);
+        }
+
+        for skip in self.skipped_steps.iter() {
+            doc.nodes_mut().push(skip.to_node());
+        }
+
         for src in &self.sources {
             let source_node = src.to_node();
             doc.nodes_mut().push(source_node);
@@ -437,14 +870,156 @@ impl Recipe {
             doc.nodes_mut().push(dep_node);
         }
 
+        if let Some(bootstrap) = &self.bootstrap {
+            doc.nodes_mut().push(bootstrap.to_node());
+        }
+
         for package in &self.package_sections {
             let package_node = package.to_node();
             doc.nodes_mut().push(package_node);
         }
 
+        for check in &self.checks {
+            doc.nodes_mut().push(check.to_node());
+        }
+
         node
     }
 
+    /// Check the structural invariants of the recipe which can be verified
+    /// without access to the component directory. Meta packages (recipes without
+    /// build sections) do not need any sources.
+    pub fn validate(&self) -> Vec<RecipeViolation> {
+        let mut violations = vec![];
+
+        let has_sources = self.sources.iter().any(|s| !s.sources.is_empty());
+        if !self.build_sections.is_empty() && !has_sources {
+            violations.push(RecipeViolation::NoSources);
+        }
+
+        for (index, section) in self.sources.iter().enumerate() {
+            if let Some(name) = &section.name {
+                if self.sources[..index].iter().any(|s| s.name.as_ref() == Some(name)) {
+                    violations.push(RecipeViolation::DuplicateSource { name: name.clone() });
+                }
+            }
+        }
+
+        for (index, section) in self.build_sections.iter().enumerate() {
+            if let Some(source) = &section.source {
+                if self.source_section(source).is_none() {
+                    violations.push(RecipeViolation::UnknownBuildSource {
+                        index,
+                        name: source.clone(),
+                    });
+                }
+            }
+            if let Some(directory) = &section.directory {
+                let relative = Path::new(directory)
+                    .components()
+                    .all(|c| matches!(c, std::path::Component::Normal(_)));
+                if !relative {
+                    violations.push(RecipeViolation::InvalidBuildDirectory {
+                        index,
+                        directory: directory.clone(),
+                    });
+                }
+            }
+            let styles = section.build_styles();
+            match styles.len() {
+                0 => violations.push(RecipeViolation::NoBuildStyle { index }),
+                1 => {}
+                _ => violations.push(RecipeViolation::MultipleBuildStyles {
+                    index,
+                    styles: styles.into_iter().map(String::from).collect(),
+                }),
+            }
+        }
+
+        for skip in &self.skipped_steps {
+            if !SKIPPABLE_STEPS.contains(&skip.step.as_str()) {
+                violations.push(RecipeViolation::UnknownSkipStep {
+                    step: skip.step.clone(),
+                });
+            } else if skip.justification.as_deref().unwrap_or_default().trim().is_empty() {
+                violations.push(RecipeViolation::UnjustifiedSkip {
+                    step: skip.step.clone(),
+                });
+            }
+        }
+
+        if let Some(bootstrap) = &self.bootstrap {
+            for name in &bootstrap.without {
+                if !self.dependencies.iter().any(|d| &d.name == name) {
+                    violations.push(RecipeViolation::UnknownBootstrapDependency {
+                        name: name.clone(),
+                    });
+                }
+            }
+        }
+
+        for (index, check) in self.checks.iter().enumerate() {
+            if check.command.is_none() && check.files.is_empty() && check.script.is_none() {
+                violations.push(RecipeViolation::EmptyCheck {
+                    name: check.name.clone(),
+                });
+            }
+            if self.checks[..index].iter().any(|c| c.name == check.name) {
+                violations.push(RecipeViolation::DuplicateCheck {
+                    name: check.name.clone(),
+                });
+            }
+        }
+
+        violations
+    }
+
+    /// The source section called `name`.
+    pub fn source_section(&self, name: &str) -> Option<&SourceSection> {
+        self.sources.iter().find(|s| s.name.as_deref() == Some(name))
+    }
+
+    /// Digest identifying the recipe together with its patches, given by name and
+    /// content. Forged records it for stored components so a local checkout can be
+    /// verified against it. Keys are serialized sorted so the order of a map does not
+    /// change the digest.
+    pub fn digest<'a>(&self, patches: impl IntoIterator<Item = (&'a str, &'a [u8])>) -> String {
+        use sha3::Digest;
+
+        let mut hasher = sha3::Sha3_256::new();
+        let recipe = serde_json::to_value(self).unwrap_or_default();
+        hasher.update(recipe.to_string().as_bytes());
+        let mut patches = patches.into_iter().collect::<Vec<_>>();
+        patches.sort_by_key(|(name, _)| *name);
+        for (name, content) in patches {
+            hasher.update(name.as_bytes());
+            hasher.update(content);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// The skip entry for `step` if the recipe skips it.
+    pub fn skips(&self, step: &str) -> Option<&SkipStep> {
+        self.skipped_steps.iter().find(|s| s.step == step)
+    }
+
+    /// The recipe of the bootstrap build: without the dependencies the bootstrap section
+    /// goes without and with its options added to every configure build.
+    pub fn bootstrap_variant(&self) -> Option<Recipe> {
+        let bootstrap = self.bootstrap.as_ref()?;
+        let mut recipe = self.clone();
+        recipe.bootstrap = None;
+        recipe
+            .dependencies
+            .retain(|d| !bootstrap.without.contains(&d.name));
+        for section in recipe.build_sections.iter_mut() {
+            if let Some(configure) = section.configure.as_mut() {
+                configure.options.extend(bootstrap.options.iter().cloned());
+            }
+        }
+        Some(recipe)
+    }
+
     pub fn merge_into_mut(&mut self, other: &Recipe) -> ComponentResult<()> {
         self.name = other.name.clone();
 
@@ -464,6 +1039,10 @@ impl Recipe {
             self.license = Some(license.clone());
         }
 
+        for license in &other.additional_licenses {
+            self.additional_licenses.push(license.clone());
+        }
+
         if let Some(prefix) = &other.prefix {
             self.prefix = Some(prefix.clone());
         }
@@ -480,6 +1059,10 @@ impl Recipe {
             self.project_url = Some(project_url.clone());
         }
 
+        if let Some(jobs) = other.jobs {
+            self.jobs = Some(jobs);
+        }
+
         for maintainer in &other.maintainers {
             self.maintainers.push(maintainer.clone());
         }
@@ -496,10 +1079,218 @@ impl Recipe {
             self.dependencies.push(dep.clone());
         }
 
+        for arch in &other.architectures {
+            self.architectures.push(arch.clone());
+        }
+
+        for skip in &other.skipped_steps {
+            self.skipped_steps.push(skip.clone());
+        }
+
+        if let Some(bootstrap) = &other.bootstrap {
+            self.bootstrap = Some(bootstrap.clone());
+        }
+
+        for check in &other.checks {
+            self.checks.push(check.clone());
+        }
+
         Ok(())
     }
 }
 
+/// The standard build steps a recipe can opt out of with `skip`.
+pub const SKIPPABLE_STEPS: [&str; 3] = ["lint", "dependency-generation", "dependency-resolution"];
+
+/// Opt out of a standard build step for packages where the tooling misbehaves. The
+/// justification ends up in the package manifest.
+#[derive(
+    Debug,
+    knuffel::Decode,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Diff,
+    JsonSchema,
+    Builder,
+    ToSchema,
+)]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
+#[diff(attr(
+# [derive(Debug, Clone, Serialize, Deserialize)]
+))]
+pub struct SkipStep {
+    #[knuffel(argument)]
+    pub step: String,
+    #[knuffel(property)]
+    #[builder(default)]
+    pub justification: Option<String>,
+}
+
+impl SkipStep {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("skip");
+        node.insert(0, self.step.as_str());
+        if let Some(justification) = &self.justification {
+            node.insert("justification", justification.as_str());
+        }
+        node
+    }
+}
+
+/// A license text delivered with a package together with the name of the license.
+#[derive(
+    Debug,
+    knuffel::Decode,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Diff,
+    JsonSchema,
+    Builder,
+    ToSchema,
+)]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
+#[diff(attr(
+# [derive(Debug, Clone, Serialize, Deserialize)]
+))]
+pub struct LicenseEntry {
+    /// Path of the license text relative to the unpacked sources
+    #[knuffel(argument)]
+    pub file: String,
+    #[knuffel(property)]
+    pub name: String,
+}
+
+impl LicenseEntry {
+    pub fn to_node(&self, node_name: &str) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new(node_name);
+        node.insert(0, self.file.as_str());
+        node.insert("name", self.name.as_str());
+        node
+    }
+}
+
+/// A reduced build of the component without some of its dependencies. Used to break
+/// build dependency cycles, the component is built again in full once its dependencies
+/// are built.
+#[derive(
+    Debug,
+    Default,
+    knuffel::Decode,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Diff,
+    JsonSchema,
+    Builder,
+    ToSchema,
+)]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
+#[diff(attr(
+# [derive(Debug, Clone, Serialize, Deserialize)]
+))]
+pub struct BootstrapSection {
+    /// Dependencies the bootstrap build does without
+    #[knuffel(children(name = "without"), unwrap(argument))]
+    #[builder(default, setter(each(name = "without", into)))]
+    pub without: Vec<String>,
+    /// Configure options disabling the features that need them
+    #[knuffel(children(name = "option"))]
+    #[builder(default, setter(each(name = "option", into)))]
+    pub options: Vec<BuildOptionNode>,
+}
+
+impl BootstrapSection {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("bootstrap");
+        let doc = node.ensure_children();
+        for without in self.without.iter() {
+            doc.nodes_mut().push(argument_node("without", without));
+        }
+        for option in self.options.iter() {
+            doc.nodes_mut().push(option.to_node());
+        }
+        node
+    }
+}
+
+/// A check of the build result. Commands and scripts pass when they exit with 0, files
+/// are paths that must exist in the prototype directory.
+///
+/// ```kdl
+/// check "runs" {
+///     command "$PROTO_DIR/usr/bin/foo --version"
+/// }
+/// check "headers" {
+///     file "usr/include/foo.h"
+/// }
+/// ```
+#[derive(
+    Debug,
+    knuffel::Decode,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Diff,
+    JsonSchema,
+    Builder,
+    ToSchema,
+)]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
+#[diff(attr(
+# [derive(Debug, Clone, Serialize, Deserialize)]
+))]
+pub struct CheckSection {
+    #[knuffel(argument)]
+    pub name: String,
+    /// Shell command run in the build directory
+    #[knuffel(child, unwrap(argument))]
+    #[builder(default)]
+    pub command: Option<String>,
+    /// Files the prototype directory has to contain
+    #[knuffel(children(name = "file"), unwrap(argument))]
+    #[builder(default, setter(each(name = "file", into)))]
+    pub files: Vec<String>,
+    /// Smoke test script in the component directory
+    #[knuffel(child, unwrap(argument))]
+    #[builder(default)]
+    pub script: Option<String>,
+}
+
+impl CheckSection {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("check");
+        node.insert(0, self.name.as_str());
+        let doc = node.ensure_children();
+        if let Some(command) = &self.command {
+            doc.nodes_mut().push(argument_node("command", command));
+        }
+        for file in self.files.iter() {
+            doc.nodes_mut().push(argument_node("file", file));
+        }
+        if let Some(script) = &self.script {
+            doc.nodes_mut().push(argument_node("script", script));
+        }
+        node
+    }
+}
+
+impl Display for SkipStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.step,
+            self.justification.as_deref().unwrap_or("no justification given")
+        )
+    }
+}
+
 #[derive(
     Debug,
     knuffel::Decode,
@@ -591,6 +1382,11 @@ impl From<&str> for DependencyKind {
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
 pub struct SourceSection {
+    /// Unpack the section into a directory of this name in the build directory instead
+    /// of the one of the component, so build sections can select it with `source`.
+    #[knuffel(property)]
+    #[serde(default)]
+    pub name: Option<String>,
     #[knuffel(children)]
     pub sources: Vec<SourceNode>,
 }
@@ -598,18 +1394,13 @@ pub struct SourceSection {
 impl SourceSection {
     pub fn to_node(&self) -> kdl::KdlNode {
         let mut source_node = kdl::KdlNode::new("source");
+        if let Some(name) = &self.name {
+            source_node.insert("name", name.as_str());
+        }
 
         for src in &self.sources {
-            let src_node = match src {
-                SourceNode::Archive(s) => s.to_node(),
-                SourceNode::Git(s) => s.to_node(),
-                SourceNode::File(s) => s.to_node(),
-                SourceNode::Patch(s) => s.to_node(),
-                SourceNode::Overlay(s) => s.to_node(),
-                SourceNode::Directory(s) => s.to_node(),
-            };
             let doc = source_node.ensure_children();
-            doc.nodes_mut().push(src_node);
+            doc.nodes_mut().push(src.to_node());
         }
 
         source_node
@@ -631,6 +1422,19 @@ pub enum SourceNode {
     Overlay(OverlaySource),
 }
 
+impl SourceNode {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        match self {
+            SourceNode::Archive(s) => s.to_node(),
+            SourceNode::Git(s) => s.to_node(),
+            SourceNode::File(s) => s.to_node(),
+            SourceNode::Patch(s) => s.to_node(),
+            SourceNode::Overlay(s) => s.to_node(),
+            SourceNode::Directory(s) => s.to_node(),
+        }
+    }
+}
+
 #[derive(
     Debug,
     Default,
@@ -680,7 +1484,7 @@ impl ArchiveSource {
             node.insert("sha256", sha256.as_str());
         }
         if let Some(signature_ext) = &self.signature_url_extension {
-            node.insert("singature-url-extension", signature_ext.as_str());
+            node.insert("signature-url-extension", signature_ext.as_str());
         }
         if let Some(sig_url) = &self.signature_url {
             node.insert("signature-url", sig_url.as_str());
@@ -690,8 +1494,18 @@ impl ArchiveSource {
 }
 
 #[derive(
-    Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq, Diff, ToSchema, JsonSchema,
+    Debug,
+    knuffel::Decode,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Diff,
+    Builder,
+    ToSchema,
+    JsonSchema,
 )]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
@@ -699,16 +1513,28 @@ pub struct GitSource {
     #[knuffel(argument)]
     pub repository: String,
     #[knuffel(property)]
+    #[builder(default)]
     pub branch: Option<String>,
     #[knuffel(property)]
+    #[builder(default)]
     pub tag: Option<String>,
     #[knuffel(property)]
+    #[builder(default)]
     pub archive: Option<bool>,
     #[knuffel(property)]
+    #[builder(default)]
     pub must_stay_as_repo: Option<bool>,
+    /// Commit to check out, pins the sources even if the branch or tag moves
+    #[knuffel(property)]
+    #[builder(default)]
+    pub commit: Option<String>,
+    #[knuffel(property)]
+    #[builder(default)]
+    pub recurse_submodules: Option<bool>,
 
     // Directory where to unpack sources into the first git source can ignore this on the second it is required
     #[knuffel(property)]
+    #[builder(default)]
     pub directory: Option<String>,
 }
 
@@ -727,6 +1553,9 @@ impl GitSource {
 
         if let Some(tag) = &self.tag {
             format!("{}-{}", repo_prefix, tag)
+        } else if let Some(commit) = &self.commit {
+            let short_commit = commit.chars().take(12).collect::<String>();
+            format!("{}-{}", repo_prefix, short_commit)
         } else if let Some(branch) = &self.branch {
             format!("{}-{}", repo_prefix, branch)
         } else {
@@ -749,6 +1578,15 @@ impl GitSource {
         if let Some(must_stay_as_repo) = self.must_stay_as_repo.clone() {
             node.insert("must-stay-as-repo", must_stay_as_repo);
         }
+        if let Some(commit) = &self.commit {
+            node.insert("commit", commit.as_str());
+        }
+        if let Some(recurse_submodules) = self.recurse_submodules {
+            node.insert("recurse-submodules", recurse_submodules);
+        }
+        if let Some(directory) = &self.directory {
+            node.insert("directory", directory.as_str());
+        }
         node
     }
 }
@@ -927,14 +1765,22 @@ impl OverlaySource {
     Builder,
     ToSchema,
 )]
-#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
+#[builder(
+    setter(into, strip_option),
+    build_fn(error = "self::ComponentError", validate = "Self::validate")
+)]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
 pub struct BuildSection {
+    /// The name of the source section to build, the unnamed sources without it.
     #[knuffel(child, unwrap(argument))]
     #[builder(default)]
     pub source: Option<String>,
+    /// Build in this subdirectory of the unpacked sources.
+    #[knuffel(child, unwrap(argument))]
+    #[builder(default)]
+    pub directory: Option<String>,
     #[knuffel(child)]
     #[builder(default)]
     pub configure: Option<ConfigureBuildSection>,
@@ -949,19 +1795,80 @@ pub struct BuildSection {
     pub script: Option<ScriptBuildSection>,
 }
 
+impl BuildSectionBuilder {
+    fn validate(&self) -> ComponentResult<()> {
+        let kinds = [
+            ("configure", matches!(self.configure, Some(Some(_)))),
+            ("cmake", matches!(self.cmake, Some(Some(_)))),
+            ("meson", matches!(self.meson, Some(Some(_)))),
+            ("script", matches!(self.script, Some(Some(_)))),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect::<Vec<_>>();
+
+        if let [first, second, ..] = kinds.as_slice() {
+            return Err(ComponentError::NonMergeableBuildSections(
+                first.to_string(),
+                second.to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 impl BuildSection {
+    /// Names of the build styles configured in this section.
+    pub fn build_styles(&self) -> Vec<&'static str> {
+        let mut styles = vec![];
+        if self.configure.is_some() {
+            styles.push("configure");
+        }
+        if self.cmake.is_some() {
+            styles.push("cmake");
+        }
+        if self.meson.is_some() {
+            styles.push("meson");
+        }
+        if self.script.is_some() {
+            styles.push("script");
+        }
+        styles
+    }
+
     pub fn to_node(&self) -> kdl::KdlNode {
         let mut node = kdl::KdlNode::new("build");
+        let doc = node.ensure_children();
         if let Some(source) = &self.source {
-            node.insert(0, source.as_str());
+            let mut n = kdl::KdlNode::new("source");
+            n.insert(0, source.as_str());
+            doc.nodes_mut().push(n);
         }
-        let doc = node.ensure_children();
+
+        if let Some(directory) = &self.directory {
+            let mut n = kdl::KdlNode::new("directory");
+            n.insert(0, directory.as_str());
+            doc.nodes_mut().push(n);
+        }
+
         if let Some(configure) = &self.configure {
             doc.nodes_mut().push(configure.to_node());
-        } else if let Some(script) = &self.script {
+        }
+
+        if let Some(cmake) = &self.cmake {
+            let mut n = kdl::KdlNode::new("cmake");
+            n.insert(0, cmake.as_str());
+            doc.nodes_mut().push(n);
+        }
+
+        if let Some(meson) = &self.meson {
+            let mut n = kdl::KdlNode::new("meson");
+            n.insert(0, meson.as_str());
+            doc.nodes_mut().push(n);
+        }
+
+        if let Some(script) = &self.script {
             doc.nodes_mut().push(script.to_node());
-        } else {
-            doc.nodes_mut().push(kdl::KdlNode::new("no-build"));
         }
         node
     }
@@ -976,24 +1883,32 @@ impl BuildSection {
     Deserialize,
     PartialEq,
     Diff,
+    Builder,
     ToSchema,
     JsonSchema,
 )]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
 pub struct ConfigureBuildSection {
     #[knuffel(children(name = "option"))]
+    #[builder(default, setter(each(name = "option", into)))]
     pub options: Vec<BuildOptionNode>,
     #[knuffel(children(name = "flag"))]
+    #[builder(default, setter(each(name = "flag", into)))]
     pub flags: Vec<BuildFlagNode>,
     #[knuffel(child, unwrap(argument))]
+    #[builder(default)]
     pub compiler: Option<String>,
     #[knuffel(child, unwrap(argument))]
+    #[builder(default)]
     pub linker: Option<String>,
     #[knuffel(child, default=false)]
+    #[builder(default)]
     pub disable_destdir_configure_option: bool,
     #[knuffel(child, default=false)]
+    #[builder(default)]
     pub enable_large_files: bool,
 }
 
@@ -1022,7 +1937,7 @@ impl ConfigureBuildSection {
         }
 
         if self.disable_destdir_configure_option {
-            let n = kdl::KdlNode::new("disable-destdir-option");
+            let n = kdl::KdlNode::new("disable-destdir-configure-option");
             doc.nodes_mut().push(n);
         }
 
@@ -1044,16 +1959,20 @@ impl ConfigureBuildSection {
     Deserialize,
     PartialEq,
     Diff,
+    Builder,
     ToSchema,
     JsonSchema,
 )]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
 pub struct ScriptBuildSection {
     #[knuffel(children(name = "script"))]
+    #[builder(default, setter(each(name = "script", into)))]
     pub scripts: Vec<ScriptNode>,
     #[knuffel(children(name = "install"))]
+    #[builder(default, setter(each(name = "install_directive", into)))]
     pub install_directives: Vec<InstallDirectiveNode>,
 }
 
@@ -1082,9 +2001,11 @@ impl ScriptBuildSection {
     Deserialize,
     PartialEq,
     Diff,
+    Builder,
     ToSchema,
     JsonSchema,
 )]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
@@ -1096,17 +2017,25 @@ pub struct InstallDirectiveNode {
     #[knuffel(property)]
     pub name: String,
     #[knuffel(property)]
+    #[builder(default)]
     pub pattern: Option<String>,
     #[knuffel(property(name = "match"))]
+    #[builder(default)]
     pub fmatch: Option<String>,
 }
 
 impl InstallDirectiveNode {
     pub fn to_node(&self) -> kdl::KdlNode {
-        let mut node = kdl::KdlNode::new("package-directory");
+        let mut node = kdl::KdlNode::new("install");
         node.insert("src", self.src.as_str());
         node.insert("target", self.target.as_str());
         node.insert("name", self.name.as_str());
+        if let Some(pattern) = &self.pattern {
+            node.insert("pattern", pattern.as_str());
+        }
+        if let Some(fmatch) = &self.fmatch {
+            node.insert("match", fmatch.as_str());
+        }
         node
     }
 }
@@ -1120,9 +2049,11 @@ impl InstallDirectiveNode {
     Deserialize,
     PartialEq,
     Diff,
+    Builder,
     ToSchema,
     JsonSchema,
 )]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
@@ -1131,6 +2062,7 @@ pub struct ScriptNode {
     pub name: String,
     #[schema(value_type = String)]
     #[knuffel(property)]
+    #[builder(default)]
     pub prototype_dir: Option<String>,
 }
 
@@ -1146,8 +2078,18 @@ impl ScriptNode {
 }
 
 #[derive(
-    Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq, Diff, ToSchema, JsonSchema,
+    Debug,
+    knuffel::Decode,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Diff,
+    Builder,
+    ToSchema,
+    JsonSchema,
 )]
+#[builder(setter(into, strip_option), build_fn(error = "self::ComponentError"))]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
@@ -1155,6 +2097,7 @@ pub struct BuildFlagNode {
     #[knuffel(argument)]
     pub flag: String,
     #[knuffel(property(name = "name"))]
+    #[builder(default)]
     pub flag_name: Option<String>,
 }
 
@@ -1162,13 +2105,26 @@ impl BuildFlagNode {
     pub fn to_node(&self) -> kdl::KdlNode {
         let mut node = kdl::KdlNode::new("flag");
         node.insert(0, self.flag.as_str());
+        if let Some(flag_name) = &self.flag_name {
+            node.insert("name", flag_name.as_str());
+        }
         node
     }
 }
 
 #[derive(
-    Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq, Diff, ToSchema, JsonSchema,
+    Debug,
+    knuffel::Decode,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Diff,
+    Builder,
+    ToSchema,
+    JsonSchema,
 )]
+#[builder(setter(into), build_fn(error = "self::ComponentError"))]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
@@ -1177,6 +2133,20 @@ pub struct BuildOptionNode {
     pub option: String,
 }
 
+impl From<&str> for BuildOptionNode {
+    fn from(value: &str) -> Self {
+        Self {
+            option: value.to_string(),
+        }
+    }
+}
+
+impl From<String> for BuildOptionNode {
+    fn from(option: String) -> Self {
+        Self { option }
+    }
+}
+
 impl BuildOptionNode {
     pub fn to_node(&self) -> kdl::KdlNode {
         let mut node = kdl::KdlNode::new("option");
@@ -1194,22 +2164,56 @@ pub struct FileNode {
     pub include: String,
 }
 
-#[derive(Debug, knuffel::Decode, Clone, Serialize, PartialEq, Deserialize, ToSchema, Diff, JsonSchema)]
+#[derive(Debug, knuffel::Decode, Clone, Serialize, PartialEq, Deserialize, ToSchema, Diff, JsonSchema, Builder)]
+#[builder(
+    setter(into, strip_option),
+    build_fn(error = "self::ComponentError", validate = "Self::validate")
+)]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
 pub struct PackageSection {
     #[knuffel(argument)]
+    #[builder(default)]
     pub name: Option<String>,
 
     #[knuffel(children(name = "file"))]
+    #[builder(default, setter(each(name = "file")))]
     pub files: Vec<TransformNode>,
 
     #[knuffel(children(name = "link"))]
+    #[builder(default, setter(each(name = "link")))]
     pub links: Vec<TransformNode>,
 
     #[knuffel(children(name = "hardlinks"))]
+    #[builder(default, setter(each(name = "hardlink")))]
     pub hardlinks: Vec<TransformNode>,
+
+    /// Licenses only this package delivers, next to the ones of the recipe
+    #[knuffel(children(name = "license"))]
+    #[builder(default, setter(each(name = "license")))]
+    pub licenses: Vec<LicenseEntry>,
+}
+
+impl PackageSectionBuilder {
+    fn validate(&self) -> ComponentResult<()> {
+        let lists = [
+            ("file", &self.files),
+            ("link", &self.links),
+            ("hardlinks", &self.hardlinks),
+        ];
+        for (action, nodes) in lists {
+            for node in nodes.iter().flatten() {
+                if node.action != action {
+                    return Err(ComponentError::ValidationError(
+                        "package section".to_string(),
+                        format!("{} transform added to the {} list", node.action, action),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl PackageSection {
@@ -1233,11 +2237,19 @@ impl PackageSection {
             doc.nodes_mut().push(hardlink.to_node());
         }
 
+        for license in &self.licenses {
+            doc.nodes_mut().push(license.to_node("license"));
+        }
+
         node
     }
 }
 
-#[derive(Debug, knuffel::Decode, Clone, Serialize, PartialEq, Deserialize, ToSchema, Diff, JsonSchema)]
+#[derive(Debug, knuffel::Decode, Clone, Serialize, PartialEq, Deserialize, ToSchema, Diff, JsonSchema, Builder)]
+#[builder(
+    setter(into, strip_option),
+    build_fn(error = "self::ComponentError", validate = "Self::validate")
+)]
 #[diff(attr(
 # [derive(Debug, Clone, Serialize, Deserialize)]
 ))]
@@ -1245,9 +2257,31 @@ pub struct TransformNode {
     #[knuffel(node_name)]
     pub action: String,
     #[knuffel(properties)]
+    #[builder(default, setter(each(name = "selector")))]
     pub selectors: HashMap<String, String>,
 }
 
+impl TransformNodeBuilder {
+    fn validate(&self) -> ComponentResult<()> {
+        match self.action.as_deref() {
+            Some("file") | Some("link") | Some("hardlinks") | None => {}
+            Some(action) => {
+                return Err(ComponentError::ValidationError(
+                    "transform".to_string(),
+                    format!("unknown action {action} use one of 'file', 'link', 'hardlinks'"),
+                ))
+            }
+        }
+        if self.selectors.as_ref().map_or(true, |s| s.is_empty()) {
+            return Err(ComponentError::ValidationError(
+                "transform".to_string(),
+                "at least one selector is required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 impl TransformNode {
     pub fn to_node(&self) -> kdl::KdlNode {
         let mut node = kdl::KdlNode::new(self.action.as_str());
@@ -1322,4 +2356,456 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn build_package_section() -> miette::Result<()> {
+        let file = TransformNodeBuilder::default()
+            .action("file")
+            .selector(("path".to_string(), "usr/bin.*".to_string()))
+            .build()?;
+        let section = PackageSectionBuilder::default()
+            .name("library/foo")
+            .file(file.clone())
+            .build()?;
+        assert_eq!(section.files, vec![file.clone()]);
+
+        let misplaced = PackageSectionBuilder::default().link(file).build();
+        assert!(matches!(misplaced, Err(ComponentError::ValidationError(_, _))));
+
+        let unknown = TransformNodeBuilder::default()
+            .action("symlink")
+            .selector(("path".to_string(), "usr/lib".to_string()))
+            .build();
+        assert!(matches!(unknown, Err(ComponentError::ValidationError(_, _))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn recipe_digest() -> miette::Result<()> {
+        let recipe = RecipeBuilder::default().name("library/foo").build()?;
+        let patch: &[u8] = b"--- a\n+++ b\n";
+        let digest = recipe.digest([("01-fix.patch", patch), ("02-more.patch", patch)]);
+        assert_eq!(
+            digest,
+            recipe.digest([("02-more.patch", patch), ("01-fix.patch", patch)])
+        );
+        assert_ne!(digest, recipe.digest([("01-fix.patch", patch)]));
+
+        let mut changed = recipe.clone();
+        changed.summary = Some(String::from("changed"));
+        assert_ne!(recipe.digest([]), changed.digest([]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_recipe() -> miette::Result<()> {
+        let mut recipe = RecipeBuilder::default().name("meta/package").build()?;
+        assert!(recipe.validate().is_empty());
+
+        recipe.build_sections.push(BuildSection {
+            configure: Some(ConfigureBuildSection::default()),
+            cmake: Some(String::new()),
+            ..Default::default()
+        });
+        recipe.build_sections.push(BuildSection::default());
+        recipe.build_sections.push(BuildSection {
+            source: Some(String::from("docs")),
+            directory: Some(String::from("../docs")),
+            script: Some(ScriptBuildSection::default()),
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            recipe.sources.push(SourceSection {
+                name: Some(String::from("tests")),
+                sources: vec![],
+            });
+        }
+        recipe.skipped_steps.push(SkipStepBuilder::default().step("lint").build()?);
+        recipe.skipped_steps.push(
+            SkipStepBuilder::default()
+                .step("publish")
+                .justification("not needed")
+                .build()?,
+        );
+        recipe.checks.push(CheckSectionBuilder::default().name("empty").build()?);
+        for _ in 0..2 {
+            recipe.checks.push(
+                CheckSectionBuilder::default()
+                    .name("runs")
+                    .command("true")
+                    .build()?,
+            );
+        }
+        assert_eq!(
+            recipe.validate(),
+            vec![
+                RecipeViolation::NoSources,
+                RecipeViolation::DuplicateSource {
+                    name: "tests".to_string(),
+                },
+                RecipeViolation::MultipleBuildStyles {
+                    index: 0,
+                    styles: vec!["configure".to_string(), "cmake".to_string()],
+                },
+                RecipeViolation::NoBuildStyle { index: 1 },
+                RecipeViolation::UnknownBuildSource {
+                    index: 2,
+                    name: "docs".to_string(),
+                },
+                RecipeViolation::InvalidBuildDirectory {
+                    index: 2,
+                    directory: "../docs".to_string(),
+                },
+                RecipeViolation::UnjustifiedSkip {
+                    step: "lint".to_string(),
+                },
+                RecipeViolation::UnknownSkipStep {
+                    step: "publish".to_string(),
+                },
+                RecipeViolation::EmptyCheck {
+                    name: "empty".to_string(),
+                },
+                RecipeViolation::DuplicateCheck {
+                    name: "runs".to_string(),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_section_rejects_multiple_build_types() -> miette::Result<()> {
+        let configure = ConfigureBuildSectionBuilder::default()
+            .option("--prefix=/usr")
+            .flag(BuildFlagNodeBuilder::default().flag("-m64").flag_name("CFLAGS").build()?)
+            .build()?;
+        let res = BuildSectionBuilder::default()
+            .configure(configure)
+            .cmake("")
+            .build();
+        assert!(matches!(
+            res,
+            Err(ComponentError::NonMergeableBuildSections(_, _))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn update_document_keeps_comments() -> miette::Result<()> {
+        let source = r#"// The name is given by upstream
+name "library/foo"
+version "1.0.0" // bumped by hand
+summary "Foo library"
+
+source {
+    // the release tarball
+    archive "https://example.com/foo-1.0.0.tar.gz" sha512="abc"
+}
+"#;
+        let mut doc: kdl::KdlDocument = source.parse()?;
+        let original = knuffel::parse::<Recipe>("package.kdl", source)?;
+
+        let mut recipe = original.clone();
+        recipe.version = Some("1.1.0".to_string());
+        recipe.license = Some("MIT".to_string());
+        recipe.sources[0]
+            .sources
+            .push(SourceNode::Patch(PatchSource::new("fix.patch".to_string(), None)?));
+        recipe.update_document(&original, &mut doc);
+
+        let written = doc.to_string();
+        assert!(written.contains("// The name is given by upstream"));
+        assert!(written.contains("// bumped by hand"));
+        assert!(written.contains("// the release tarball"));
+        assert!(written.contains(r#"archive "https://example.com/foo-1.0.0.tar.gz" sha512="abc""#));
+        assert_eq!(knuffel::parse::<Recipe>("package.kdl", &written)?, recipe);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod roundtrip_tests {
+    use std::collections::HashMap;
+
+    use proptest::collection::{hash_map, vec};
+    use proptest::option;
+    use proptest::prelude::*;
+
+    use crate::*;
+
+    fn text() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9 ./:=_${}-]{0,24}"
+    }
+
+    fn ident() -> impl Strategy<Value = String> {
+        "[a-z][a-z0-9_-]{0,12}".prop_filter("reserved kdl keyword", |s| {
+            !matches!(s.as_str(), "true" | "false" | "null")
+        })
+    }
+
+    fn source_node() -> impl Strategy<Value = SourceNode> {
+        prop_oneof![
+            (text(), option::of(text()), option::of(text()), option::of(text()), option::of(text()))
+                .prop_map(|(src, sha512, sha256, signature_url_extension, signature_url)| {
+                    SourceNode::Archive(ArchiveSource {
+                        src,
+                        sha512,
+                        sha256,
+                        signature_url_extension,
+                        signature_url,
+                    })
+                }),
+            (
+                text(),
+                option::of(text()),
+                option::of(text()),
+                option::of(any::<bool>()),
+                option::of(any::<bool>()),
+                option::of(text()),
+                option::of(any::<bool>()),
+                option::of(text())
+            )
+                .prop_map(
+                    |(
+                        repository,
+                        branch,
+                        tag,
+                        archive,
+                        must_stay_as_repo,
+                        commit,
+                        recurse_submodules,
+                        directory,
+                    )| {
+                        SourceNode::Git(GitSource {
+                            repository,
+                            branch,
+                            tag,
+                            archive,
+                            must_stay_as_repo,
+                            commit,
+                            recurse_submodules,
+                            directory,
+                        })
+                    },
+                ),
+            (text(), option::of(text())).prop_map(|(bundle_path, target_path)| {
+                SourceNode::File(FileSource {
+                    bundle_path,
+                    target_path,
+                })
+            }),
+            (text(), option::of(text())).prop_map(|(bundle_path, target_path)| {
+                SourceNode::Directory(DirectorySource {
+                    bundle_path,
+                    target_path,
+                })
+            }),
+            (text(), option::of(0i64..16)).prop_map(|(bundle_path, drop_directories)| {
+                SourceNode::Patch(PatchSource {
+                    bundle_path,
+                    drop_directories,
+                })
+            }),
+            text().prop_map(|bundle_path| SourceNode::Overlay(OverlaySource { bundle_path })),
+        ]
+    }
+
+    fn configure_section() -> impl Strategy<Value = ConfigureBuildSection> {
+        (
+            vec(text().prop_map(|option| BuildOptionNode { option }), 0..4),
+            vec(
+                (text(), option::of(text()))
+                    .prop_map(|(flag, flag_name)| BuildFlagNode { flag, flag_name }),
+                0..4,
+            ),
+            option::of(text()),
+            option::of(text()),
+            any::<bool>(),
+            any::<bool>(),
+        )
+            .prop_map(
+                |(options, flags, compiler, linker, disable_destdir_configure_option, enable_large_files)| {
+                    ConfigureBuildSection {
+                        options,
+                        flags,
+                        compiler,
+                        linker,
+                        disable_destdir_configure_option,
+                        enable_large_files,
+                    }
+                },
+            )
+    }
+
+    fn script_section() -> impl Strategy<Value = ScriptBuildSection> {
+        (
+            vec(
+                (text(), option::of(text()))
+                    .prop_map(|(name, prototype_dir)| ScriptNode { name, prototype_dir }),
+                0..3,
+            ),
+            vec(
+                (text(), text(), text(), option::of(text()), option::of(text())).prop_map(
+                    |(src, target, name, pattern, fmatch)| InstallDirectiveNode {
+                        src,
+                        target,
+                        name,
+                        pattern,
+                        fmatch,
+                    },
+                ),
+                0..3,
+            ),
+        )
+            .prop_map(|(scripts, install_directives)| ScriptBuildSection {
+                scripts,
+                install_directives,
+            })
+    }
+
+    fn build_section() -> impl Strategy<Value = BuildSection> {
+        (
+            option::of(text()),
+            option::of(text()),
+            option::of(configure_section()),
+            option::of(text()),
+            option::of(text()),
+            option::of(script_section()),
+        )
+            .prop_map(|(source, directory, configure, cmake, meson, script)| BuildSection {
+                source,
+                directory,
+                configure,
+                cmake,
+                meson,
+                script,
+            })
+    }
+
+    fn transforms(action: &'static str) -> impl Strategy<Value = Vec<TransformNode>> {
+        vec(
+            hash_map(ident(), text(), 0..3).prop_map(move |selectors: HashMap<String, String>| {
+                TransformNode {
+                    action: action.to_string(),
+                    selectors,
+                }
+            }),
+            0..3,
+        )
+    }
+
+    fn licenses() -> impl Strategy<Value = Vec<LicenseEntry>> {
+        vec(
+            (text(), text()).prop_map(|(file, name)| LicenseEntry { file, name }),
+            0..3,
+        )
+    }
+
+    fn package_section() -> impl Strategy<Value = PackageSection> {
+        (
+            option::of(text()),
+            transforms("file"),
+            transforms("link"),
+            transforms("hardlinks"),
+            licenses(),
+        )
+            .prop_map(|(name, files, links, hardlinks, licenses)| PackageSection {
+                name,
+                files,
+                links,
+                hardlinks,
+                licenses,
+            })
+    }
+
+    fn dependency() -> impl Strategy<Value = Dependency> {
+        (
+            text(),
+            any::<bool>(),
+            prop_oneof![
+                Just(DependencyKind::Require),
+                Just(DependencyKind::Incorporate),
+                Just(DependencyKind::Optional),
+            ],
+        )
+            .prop_map(|(name, dev, kind)| Dependency { name, dev, kind })
+    }
+
+    prop_compose! {
+        // Strategies are grouped in tuples, proptest implements them for up to 12 elements
+        fn recipe()(
+            (name, metadata, project_name, classification, maintainers, summary) in (
+                text(),
+                option::of(vec((ident(), text()).prop_map(|(name, value)| ComponentMetadataItem { name, value }), 0..4)),
+                option::of(text()),
+                option::of(text()),
+                vec(text(), 0..3),
+                option::of(text()),
+            ),
+            (license_file, license, additional_licenses, prefix, version, revision, project_url) in (
+                option::of(text()),
+                option::of(text()),
+                licenses(),
+                option::of(text()),
+                option::of(text()),
+                option::of(text()),
+                option::of(text()),
+            ),
+            (seperate_build_dir, jobs, architectures, skipped_steps, sources, dependencies, bootstrap) in (
+                any::<bool>(),
+                option::of(1..64u32),
+                vec(text(), 0..3),
+                vec((text(), option::of(text())).prop_map(|(step, justification)| SkipStep { step, justification }), 0..3),
+                vec((option::of(text()), vec(source_node(), 0..4)).prop_map(|(name, sources)| SourceSection { name, sources }), 0..2),
+                vec(dependency(), 0..4),
+                option::of((vec(text(), 0..3), vec(text().prop_map(BuildOptionNode::from), 0..3)).prop_map(|(without, options)| BootstrapSection { without, options })),
+            ),
+            (build_sections, package_sections, checks) in (
+                vec(build_section(), 0..2),
+                vec(package_section(), 0..3),
+                vec((text(), option::of(text()), vec(text(), 0..3), option::of(text())).prop_map(|(name, command, files, script)| CheckSection { name, command, files, script }), 0..3),
+            ),
+        ) -> Recipe {
+            Recipe {
+                name,
+                metadata: metadata.map(ComponentMetadata),
+                project_name,
+                classification,
+                maintainers,
+                summary,
+                license_file,
+                license,
+                additional_licenses,
+                prefix,
+                version,
+                revision,
+                project_url,
+                seperate_build_dir,
+                jobs,
+                architectures,
+                skipped_steps,
+                sources,
+                dependencies,
+                bootstrap,
+                build_sections,
+                package_sections,
+                checks,
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn recipe_kdl_roundtrip(recipe in recipe()) {
+            let doc = recipe.to_document().to_string();
+            let parsed = knuffel::parse::<Recipe>("package.kdl", &doc)
+                .map_err(|e| TestCaseError::fail(format!("{:?}\n{}", e, doc)))?;
+            prop_assert_eq!(parsed, recipe);
+        }
+    }
 }
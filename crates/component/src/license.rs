@@ -0,0 +1,396 @@
+use std::fmt::{Display, Formatter};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// Spellings of licenses found in recipes and upstream metadata and the SPDX
+/// expression they mean. Compared ignoring case.
+const LICENSE_ALIASES: &[(&str, &str)] = &[
+    ("GPL", "GPL-1.0-or-later"),
+    ("GPLv2", "GPL-2.0-only"),
+    ("GPLv2+", "GPL-2.0-or-later"),
+    ("GPLv3", "GPL-3.0-only"),
+    ("GPLv3+", "GPL-3.0-or-later"),
+    ("LGPLv2", "LGPL-2.0-only"),
+    ("LGPLv2+", "LGPL-2.0-or-later"),
+    ("LGPLv2.1", "LGPL-2.1-only"),
+    ("LGPLv2.1+", "LGPL-2.1-or-later"),
+    ("LGPLv3", "LGPL-3.0-only"),
+    ("LGPLv3+", "LGPL-3.0-or-later"),
+    ("AGPLv3", "AGPL-3.0-only"),
+    ("AGPLv3+", "AGPL-3.0-or-later"),
+    ("MIT License", "MIT"),
+    ("Expat", "MIT"),
+    ("Apache 2.0", "Apache-2.0"),
+    ("Apache License 2.0", "Apache-2.0"),
+    ("Apache License, Version 2.0", "Apache-2.0"),
+    ("Apache Software License", "Apache-2.0"),
+    ("ASL 2.0", "Apache-2.0"),
+    ("BSD 2-Clause", "BSD-2-Clause"),
+    ("BSD 3-Clause", "BSD-3-Clause"),
+    ("Simplified BSD", "BSD-2-Clause"),
+    ("New BSD", "BSD-3-Clause"),
+    ("MPLv2", "MPL-2.0"),
+    ("MPL 2.0", "MPL-2.0"),
+    ("PSF", "PSF-2.0"),
+    ("Python Software Foundation License", "PSF-2.0"),
+    ("Artistic", "Artistic-1.0-Perl"),
+    ("Perl", "Artistic-1.0-Perl OR GPL-1.0-or-later"),
+    ("CDDL", "CDDL-1.0"),
+    ("zlib/libpng", "Zlib"),
+];
+
+/// Deprecated SPDX identifiers of the GNU licenses, `+` means or later.
+const DEPRECATED_GNU_LICENSES: &[&str] = &[
+    "GPL-1.0", "GPL-2.0", "GPL-3.0", "LGPL-2.0", "LGPL-2.1", "LGPL-3.0", "AGPL-3.0", "GFDL-1.1",
+    "GFDL-1.2", "GFDL-1.3",
+];
+
+#[derive(Debug, Error, Diagnostic, PartialEq)]
+pub enum LicenseError {
+    #[error("license expression is empty")]
+    #[diagnostic(code(component::license::empty))]
+    Empty,
+
+    #[error("license expression {expression} is malformed: {reason}")]
+    #[diagnostic(
+        code(component::license::malformed),
+        help("use SPDX identifiers joined with AND, OR and WITH like `MIT OR Apache-2.0`")
+    )]
+    Malformed { expression: String, reason: String },
+}
+
+/// A parsed SPDX license expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LicenseExpression {
+    /// A license identifier, `or_later` is set for a trailing `+`
+    License {
+        id: String,
+        or_later: bool,
+    },
+    With {
+        license: Box<LicenseExpression>,
+        exception: String,
+    },
+    And(Box<LicenseExpression>, Box<LicenseExpression>),
+    Or(Box<LicenseExpression>, Box<LicenseExpression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Id(String),
+    And,
+    Or,
+    With,
+    Open,
+    Close,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, LicenseError> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            c if c.is_whitespace() => {}
+            c if c.is_ascii_alphanumeric() || "-.+:".contains(c) => {
+                let mut word = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || "-.+:".contains(c)) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                // SPDX wants the operators in upper case, recipes often do not
+                tokens.push(match word.to_ascii_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "WITH" => Token::With,
+                    _ => Token::Id(word),
+                });
+            }
+            c => {
+                return Err(LicenseError::Malformed {
+                    expression: expression.to_string(),
+                    reason: format!("unexpected character {}", c),
+                })
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// The SPDX identifier of `id`: identifiers of the SPDX license list in their canonical
+/// case and the deprecated GNU identifiers as their -only or -or-later successor.
+fn canonical_license(id: &str, or_later: bool) -> (String, bool) {
+    if let Some(deprecated) = DEPRECATED_GNU_LICENSES
+        .iter()
+        .find(|d| d.eq_ignore_ascii_case(id))
+    {
+        let suffix = if or_later { "or-later" } else { "only" };
+        return (format!("{}-{}", deprecated, suffix), false);
+    }
+    match spdx::identifiers::LICENSES
+        .iter()
+        .find(|(name, ..)| name.eq_ignore_ascii_case(id))
+    {
+        Some((known, ..)) => (known.to_string(), or_later),
+        None => (id.to_string(), or_later),
+    }
+}
+
+fn canonical_exception(id: &str) -> String {
+    spdx::identifiers::EXCEPTIONS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(id))
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| id.to_string())
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn malformed(&self, reason: &str) -> LicenseError {
+        LicenseError::Malformed {
+            expression: self.expression.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // OR binds weaker than AND, which binds weaker than WITH
+    fn or(&mut self) -> Result<LicenseExpression, LicenseError> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.advance();
+            left = LicenseExpression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<LicenseExpression, LicenseError> {
+        let mut left = self.with()?;
+        while self.peek() == Some(&Token::And) {
+            self.advance();
+            left = LicenseExpression::And(Box::new(left), Box::new(self.with()?));
+        }
+        Ok(left)
+    }
+
+    fn with(&mut self) -> Result<LicenseExpression, LicenseError> {
+        let license = self.atom()?;
+        if self.peek() != Some(&Token::With) {
+            return Ok(license);
+        }
+        self.advance();
+        match self.advance() {
+            Some(Token::Id(exception)) => Ok(LicenseExpression::With {
+                license: Box::new(license),
+                exception: canonical_exception(&exception),
+            }),
+            _ => Err(self.malformed("WITH needs an exception")),
+        }
+    }
+
+    fn atom(&mut self) -> Result<LicenseExpression, LicenseError> {
+        match self.advance() {
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                match self.advance() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err(self.malformed("unbalanced parentheses")),
+                }
+            }
+            Some(Token::Id(id)) => {
+                let (id, or_later) = match id.strip_suffix('+') {
+                    Some(id) => (id, true),
+                    None => (id.as_str(), false),
+                };
+                if id.is_empty() || id.contains('+') {
+                    return Err(self.malformed("misplaced +"));
+                }
+                let (id, or_later) = canonical_license(id, or_later);
+                Ok(LicenseExpression::License { id, or_later })
+            }
+            Some(_) => Err(self.malformed("expected a license")),
+            None => Err(self.malformed("expression ends early")),
+        }
+    }
+}
+
+impl LicenseExpression {
+    /// Parse an SPDX license expression. Operators may be in any case and identifiers
+    /// of the SPDX license list are put into their canonical case. Unknown identifiers are accepted,
+    /// check them with [`LicenseExpression::unknown_identifiers`].
+    pub fn parse(expression: &str) -> Result<Self, LicenseError> {
+        let tokens = tokenize(expression)?;
+        if tokens.is_empty() {
+            return Err(LicenseError::Empty);
+        }
+        let mut parser = Parser {
+            expression,
+            tokens,
+            position: 0,
+        };
+        let parsed = parser.or()?;
+        if parser.position < parser.tokens.len() {
+            return Err(parser.malformed("unexpected text after the expression"));
+        }
+        Ok(parsed)
+    }
+
+    /// The license identifiers of the expression in order of appearance.
+    pub fn licenses(&self) -> Vec<&str> {
+        match self {
+            LicenseExpression::License { id, .. } => vec![id.as_str()],
+            LicenseExpression::With { license, .. } => license.licenses(),
+            LicenseExpression::And(left, right) | LicenseExpression::Or(left, right) => {
+                let mut licenses = left.licenses();
+                licenses.extend(right.licenses());
+                licenses
+            }
+        }
+    }
+
+    fn exceptions(&self) -> Vec<&str> {
+        match self {
+            LicenseExpression::License { .. } => vec![],
+            LicenseExpression::With { license, exception } => {
+                let mut exceptions = license.exceptions();
+                exceptions.push(exception.as_str());
+                exceptions
+            }
+            LicenseExpression::And(left, right) | LicenseExpression::Or(left, right) => {
+                let mut exceptions = left.exceptions();
+                exceptions.extend(right.exceptions());
+                exceptions
+            }
+        }
+    }
+
+    /// Identifiers and exceptions that are neither on the SPDX license list nor
+    /// `LicenseRef-` or `DocumentRef-` references.
+    pub fn unknown_identifiers(&self) -> Vec<&str> {
+        let is_reference =
+            |id: &str| id.starts_with("LicenseRef-") || id.starts_with("DocumentRef-");
+        let licenses = self
+            .licenses()
+            .into_iter()
+            .filter(|id| !is_reference(id) && spdx::license_id(id).is_none());
+        let exceptions = self
+            .exceptions()
+            .into_iter()
+            .filter(|id| !is_reference(id) && spdx::exception_id(id).is_none());
+        licenses.chain(exceptions).collect()
+    }
+
+    fn fmt_operand(&self, f: &mut Formatter<'_>, parent_is_and: bool) -> std::fmt::Result {
+        match self {
+            LicenseExpression::Or(..) if parent_is_and => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl Display for LicenseExpression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LicenseExpression::License { id, or_later } => {
+                write!(f, "{}{}", id, if *or_later { "+" } else { "" })
+            }
+            LicenseExpression::With { license, exception } => match license.as_ref() {
+                LicenseExpression::License { .. } => write!(f, "{} WITH {}", license, exception),
+                _ => write!(f, "({}) WITH {}", license, exception),
+            },
+            LicenseExpression::And(left, right) => {
+                left.fmt_operand(f, true)?;
+                write!(f, " AND ")?;
+                right.fmt_operand(f, true)
+            }
+            LicenseExpression::Or(left, right) => write!(f, "{} OR {}", left, right),
+        }
+    }
+}
+
+/// Parse a license as recipes and upstream metadata spell it. Common names like
+/// `GPLv2+` or `Apache License 2.0` are mapped to their SPDX expression first.
+pub fn normalize_license(license: &str) -> Result<LicenseExpression, LicenseError> {
+    let license = license.trim();
+    match LICENSE_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(license))
+    {
+        Some((_, spdx)) => LicenseExpression::parse(spdx),
+        None => LicenseExpression::parse(license),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(license: &str) -> String {
+        normalize_license(license).unwrap().to_string()
+    }
+
+    #[test]
+    fn expressions_are_normalized() {
+        assert_eq!(normalized("mit"), "MIT");
+        assert_eq!(normalized("MIT or apache-2.0"), "MIT OR Apache-2.0");
+        assert_eq!(normalized("GPLv2+"), "GPL-2.0-or-later");
+        assert_eq!(normalized("LGPL-2.1+"), "LGPL-2.1-or-later");
+        assert_eq!(normalized("GPL-3.0"), "GPL-3.0-only");
+        assert_eq!(
+            normalized("(MIT OR Apache-2.0) and BSD-3-Clause"),
+            "(MIT OR Apache-2.0) AND BSD-3-Clause"
+        );
+        assert_eq!(
+            normalized("MIT OR Apache-2.0 AND BSD-3-Clause"),
+            "MIT OR Apache-2.0 AND BSD-3-Clause"
+        );
+        assert_eq!(
+            normalized("gpl-2.0-or-later with classpath-exception-2.0"),
+            "GPL-2.0-or-later WITH Classpath-exception-2.0"
+        );
+        assert_eq!(normalized("Apache License 2.0"), "Apache-2.0");
+        assert_eq!(normalized("ssh-openssh"), "SSH-OpenSSH");
+    }
+
+    #[test]
+    fn unknown_identifiers_are_reported() {
+        let expression = normalize_license("MIT AND Foo-1.0 AND LicenseRef-Custom").unwrap();
+        assert_eq!(
+            expression.licenses(),
+            vec!["MIT", "Foo-1.0", "LicenseRef-Custom"]
+        );
+        assert_eq!(expression.unknown_identifiers(), vec!["Foo-1.0"]);
+
+        let expression = normalize_license("GPL-2.0-only WITH Made-Up-exception").unwrap();
+        assert_eq!(expression.unknown_identifiers(), vec!["Made-Up-exception"]);
+    }
+
+    #[test]
+    fn malformed_expressions_fail() {
+        assert_eq!(normalize_license("  "), Err(LicenseError::Empty));
+        assert!(normalize_license("MIT AND").is_err());
+        assert!(normalize_license("(MIT OR Apache-2.0").is_err());
+        assert!(normalize_license("MIT, BSD").is_err());
+        assert!(normalize_license("GPL-2.0 WITH").is_err());
+        assert!(normalize_license("MIT BSD-3-Clause").is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::ToSchema;
+
+use crate::graph::package_name;
+use crate::{PackageMeta, Recipe, SourceNode};
+
+/// Document formats a software bill of materials can be rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SbomFormat {
+    /// CycloneDX 1.5 JSON
+    #[default]
+    CycloneDx,
+    /// SPDX 2.3 JSON
+    Spdx,
+}
+
+impl Display for SbomFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SbomFormat::CycloneDx => write!(f, "cyclonedx"),
+            SbomFormat::Spdx => write!(f, "spdx"),
+        }
+    }
+}
+
+impl FromStr for SbomFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cyclonedx" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => Err(format!("unknown SBOM format {}, use cyclonedx or spdx", s)),
+        }
+    }
+}
+
+/// A file the build of a component delivers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SbomFile {
+    /// Path relative to the root of the image
+    pub path: String,
+    pub sha256: String,
+}
+
+/// A component to describe in a bill of materials.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SbomEntry {
+    pub recipe: Recipe,
+    pub packages: Option<PackageMeta>,
+    /// Empty if the component was not built
+    pub files: Vec<SbomFile>,
+}
+
+impl SbomEntry {
+    fn version(&self) -> &str {
+        self.recipe.version.as_deref().unwrap_or("unknown")
+    }
+
+    /// Packages the component needs, from the recipe and the dependencies resolved
+    /// during its build.
+    fn dependencies(&self) -> BTreeSet<&str> {
+        let resolved = self.packages.iter().flat_map(|p| p.dependencies().iter());
+        self.recipe
+            .dependencies
+            .iter()
+            .filter(|d| !d.dev)
+            .map(|d| &d.name)
+            .chain(resolved)
+            .map(|name| package_name(name))
+            .collect()
+    }
+
+    /// Where the sources of the component are fetched from.
+    fn download_location(&self) -> Option<&str> {
+        self.recipe
+            .sources
+            .iter()
+            .flat_map(|s| s.sources.iter())
+            .find_map(|source| match source {
+                SourceNode::Archive(archive) => Some(archive.src.as_str()),
+                SourceNode::Git(git) => Some(git.repository.as_str()),
+                _ => None,
+            })
+    }
+
+    /// The declared licenses of the recipe as one expression.
+    fn license(&self) -> Option<String> {
+        let licenses = self
+            .recipe
+            .license
+            .iter()
+            .chain(self.recipe.additional_licenses.iter().map(|l| &l.name))
+            .map(|l| l.as_str())
+            .collect::<Vec<_>>();
+        (!licenses.is_empty()).then(|| licenses.join(" AND "))
+    }
+}
+
+/// The entries by the names of the packages they deliver, dependencies on packages none
+/// of the entries deliver are described as components of their own.
+struct Providers<'a>(HashMap<&'a str, &'a str>);
+
+impl<'a> Providers<'a> {
+    fn new(entries: &'a [SbomEntry]) -> Self {
+        let mut providers = HashMap::new();
+        for entry in entries {
+            let name = entry.recipe.name.as_str();
+            providers.insert(package_name(name), name);
+            let sections = entry.recipe.package_sections.iter();
+            for section in sections.filter_map(|s| s.name.as_deref()) {
+                providers.insert(package_name(section), name);
+            }
+            for fmri in entry.packages.iter().flat_map(|p| p.fmris().iter()) {
+                providers.insert(package_name(fmri), name);
+            }
+        }
+        Self(providers)
+    }
+
+    fn resolve(&self, package: &'a str) -> &'a str {
+        self.0.get(package).copied().unwrap_or(package)
+    }
+
+    fn external(&self, entries: &'a [SbomEntry]) -> BTreeSet<&'a str> {
+        entries
+            .iter()
+            .flat_map(|e| e.dependencies())
+            .filter(|d| !self.0.contains_key(d))
+            .collect()
+    }
+}
+
+/// Render a bill of materials called `name` describing `entries` as of `created`, an
+/// RFC 3339 timestamp.
+pub fn render_sbom(format: SbomFormat, name: &str, created: &str, entries: &[SbomEntry]) -> Value {
+    match format {
+        SbomFormat::CycloneDx => cyclonedx(name, created, entries),
+        SbomFormat::Spdx => spdx(name, created, entries),
+    }
+}
+
+fn cyclonedx(name: &str, created: &str, entries: &[SbomEntry]) -> Value {
+    let providers = Providers::new(entries);
+    let mut components = vec![];
+    let mut dependencies = vec![];
+    for entry in entries {
+        let mut component = json!({
+            "type": "application",
+            "bom-ref": entry.recipe.name,
+            "name": entry.recipe.name,
+            "version": entry.version(),
+        });
+        if let Some(summary) = &entry.recipe.summary {
+            component["description"] = json!(summary);
+        }
+        if let Some(license) = entry.license() {
+            component["licenses"] = json!([{ "license": { "name": license } }]);
+        }
+        let mut references = vec![];
+        if let Some(url) = &entry.recipe.project_url {
+            references.push(json!({ "type": "website", "url": url }));
+        }
+        for source in entry.recipe.sources.iter().flat_map(|s| s.sources.iter()) {
+            match source {
+                SourceNode::Archive(archive) => {
+                    let mut reference = json!({ "type": "distribution", "url": archive.src });
+                    let mut hashes = vec![];
+                    if let Some(sha256) = &archive.sha256 {
+                        hashes.push(json!({ "alg": "SHA-256", "content": sha256 }));
+                    }
+                    if let Some(sha512) = &archive.sha512 {
+                        hashes.push(json!({ "alg": "SHA-512", "content": sha512 }));
+                    }
+                    if !hashes.is_empty() {
+                        reference["hashes"] = json!(hashes);
+                    }
+                    references.push(reference);
+                }
+                SourceNode::Git(git) => {
+                    references.push(json!({ "type": "vcs", "url": git.repository }))
+                }
+                _ => {}
+            }
+        }
+        if !references.is_empty() {
+            component["externalReferences"] = json!(references);
+        }
+        let fmris = entry.packages.iter().flat_map(|p| p.fmris().iter());
+        let properties = fmris
+            .map(|fmri| json!({ "name": "forge:fmri", "value": fmri }))
+            .collect::<Vec<_>>();
+        if !properties.is_empty() {
+            component["properties"] = json!(properties);
+        }
+        if !entry.files.is_empty() {
+            component["components"] = entry
+                .files
+                .iter()
+                .map(|file| {
+                    json!({
+                        "type": "file",
+                        "name": file.path,
+                        "hashes": [{ "alg": "SHA-256", "content": file.sha256 }],
+                    })
+                })
+                .collect();
+        }
+        components.push(component);
+
+        let depends_on = entry
+            .dependencies()
+            .into_iter()
+            .map(|d| providers.resolve(d))
+            .filter(|d| *d != entry.recipe.name)
+            .collect::<BTreeSet<_>>();
+        dependencies.push(json!({ "ref": entry.recipe.name, "dependsOn": depends_on }));
+    }
+    for external in providers.external(entries) {
+        components.push(json!({ "type": "application", "bom-ref": external, "name": external }));
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": created,
+            "tools": [{ "name": "forge" }],
+            "component": { "type": "application", "bom-ref": name, "name": name },
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+/// SPDX identifiers may only contain letters, numbers, `.` and `-`.
+fn spdx_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn spdx_id(kind: &str, name: &str) -> String {
+    format!("SPDXRef-{}-{}", kind, spdx_name(name))
+}
+
+fn spdx(name: &str, created: &str, entries: &[SbomEntry]) -> Value {
+    use sha3::Digest;
+
+    let providers = Providers::new(entries);
+    let mut packages = vec![];
+    let mut files = vec![];
+    let mut relationships = vec![];
+    // Documents of the same components at the same time share their namespace
+    let mut hasher = sha3::Sha3_256::new();
+    hasher.update(created.as_bytes());
+    for entry in entries {
+        let id = spdx_id("Package", &entry.recipe.name);
+        hasher.update(entry.recipe.name.as_bytes());
+        hasher.update(entry.version().as_bytes());
+
+        let mut package = json!({
+            "SPDXID": id,
+            "name": entry.recipe.name,
+            "versionInfo": entry.version(),
+            "downloadLocation": entry.download_location().unwrap_or("NOASSERTION"),
+            "homepage": entry.recipe.project_url.as_deref().unwrap_or("NOASSERTION"),
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": entry.license().unwrap_or_else(|| String::from("NOASSERTION")),
+            "copyrightText": "NOASSERTION",
+            "filesAnalyzed": false,
+        });
+        if let Some(summary) = &entry.recipe.summary {
+            package["summary"] = json!(summary);
+        }
+        packages.push(package);
+        relationships.push(json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": id,
+        }));
+
+        for file in entry.files.iter() {
+            let file_id = spdx_id("File", &format!("{}-{}", entry.recipe.name, file.path));
+            files.push(json!({
+                "SPDXID": file_id,
+                "fileName": format!("./{}", file.path),
+                "checksums": [{ "algorithm": "SHA256", "checksumValue": file.sha256 }],
+            }));
+            relationships.push(json!({
+                "spdxElementId": id,
+                "relationshipType": "CONTAINS",
+                "relatedSpdxElement": file_id,
+            }));
+        }
+
+        for dependency in entry.dependencies() {
+            let dependency = providers.resolve(dependency);
+            if dependency == entry.recipe.name {
+                continue;
+            }
+            relationships.push(json!({
+                "spdxElementId": id,
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_id("Package", dependency),
+            }));
+        }
+    }
+    for external in providers.external(entries) {
+        packages.push(json!({
+            "SPDXID": spdx_id("Package", external),
+            "name": external,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+        }));
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": name,
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/{}-{:x}",
+            spdx_name(name),
+            hasher.finalize()
+        ),
+        "creationInfo": {
+            "created": created,
+            "creators": ["Tool: forge"],
+        },
+        "packages": packages,
+        "files": files,
+        "relationships": relationships,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ArchiveSource, Dependency, DependencyKind, PackageMetaBuilder, RecipeBuilder, SourceSection,
+    };
+
+    use super::*;
+
+    fn entries() -> Vec<SbomEntry> {
+        let zlib = RecipeBuilder::default()
+            .name("library/zlib")
+            .version("1.3.1")
+            .license("Zlib")
+            .project_url("https://zlib.net")
+            .sources(vec![SourceSection {
+                name: None,
+                sources: vec![SourceNode::Archive(ArchiveSource {
+                    src: String::from("https://zlib.net/zlib-1.3.1.tar.gz"),
+                    sha256: Some(String::from("9a93b2b7")),
+                    ..Default::default()
+                })],
+            }])
+            .build()
+            .unwrap();
+        let curl = RecipeBuilder::default()
+            .name("web/curl")
+            .version("8.10.1")
+            .dependencies(vec![Dependency {
+                name: String::from("library/zlib"),
+                dev: false,
+                kind: DependencyKind::Require,
+            }])
+            .build()
+            .unwrap();
+        let curl_packages = PackageMetaBuilder::default()
+            .name("web/curl")
+            .fmris(vec![String::from("pkg:/web/curl@8.10.1")])
+            .dependencies(vec![String::from("pkg:/library/security/openssl-3@3.0.15")])
+            .build()
+            .unwrap();
+        vec![
+            SbomEntry {
+                recipe: zlib,
+                packages: None,
+                files: vec![SbomFile {
+                    path: String::from("usr/lib/libz.so.1"),
+                    sha256: String::from("0123abcd"),
+                }],
+            },
+            SbomEntry {
+                recipe: curl,
+                packages: Some(curl_packages),
+                files: vec![],
+            },
+        ]
+    }
+
+    #[test]
+    fn cyclonedx_links_dependencies() {
+        let bom = render_sbom(
+            SbomFormat::CycloneDx,
+            "userland",
+            "2024-10-01T00:00:00Z",
+            &entries(),
+        );
+        assert_eq!(
+            bom["components"][0]["licenses"][0]["license"]["name"],
+            "Zlib"
+        );
+        assert_eq!(
+            bom["components"][0]["externalReferences"][1]["hashes"][0]["content"],
+            "9a93b2b7"
+        );
+        assert_eq!(
+            bom["components"][0]["components"][0]["name"],
+            "usr/lib/libz.so.1"
+        );
+        assert_eq!(
+            bom["components"][2]["bom-ref"],
+            "library/security/openssl-3"
+        );
+        assert_eq!(
+            bom["dependencies"][1],
+            json!({
+                "ref": "web/curl",
+                "dependsOn": ["library/security/openssl-3", "library/zlib"],
+            })
+        );
+    }
+
+    #[test]
+    fn spdx_relates_packages_and_files() {
+        let doc = render_sbom(
+            SbomFormat::Spdx,
+            "userland",
+            "2024-10-01T00:00:00Z",
+            &entries(),
+        );
+        assert_eq!(doc["packages"][0]["SPDXID"], "SPDXRef-Package-library-zlib");
+        assert_eq!(
+            doc["packages"][0]["downloadLocation"],
+            "https://zlib.net/zlib-1.3.1.tar.gz"
+        );
+        assert_eq!(doc["packages"][1]["licenseDeclared"], "NOASSERTION");
+        let relationships = doc["relationships"].as_array().unwrap();
+        assert!(relationships.contains(&json!({
+            "spdxElementId": "SPDXRef-Package-library-zlib",
+            "relationshipType": "CONTAINS",
+            "relatedSpdxElement": "SPDXRef-File-library-zlib-usr-lib-libz.so.1",
+        })));
+        assert!(relationships.contains(&json!({
+            "spdxElementId": "SPDXRef-Package-web-curl",
+            "relationshipType": "DEPENDS_ON",
+            "relatedSpdxElement": "SPDXRef-Package-library-zlib",
+        })));
+        assert_eq!("spdx".parse::<SbomFormat>(), Ok(SbomFormat::Spdx));
+    }
+}
@@ -1,10 +1,15 @@
 use miette::Diagnostic;
 use std::fs::DirBuilder;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use config::{Value, ValueKind};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use workspace::{Workspace, WorkspaceConfig, WorkspaceError};
+use forge::{
+    SourceCredential, REPOSITORY_DIR_ENV, SIGNING_KEY_ENV, SOURCE_CREDENTIALS_FILE_ENV,
+    SOURCE_MIRROR_ENV,
+};
 
 #[derive(Debug, thiserror::Error, Diagnostic)]
 pub enum ConfigError {
@@ -22,6 +27,10 @@ pub enum ConfigError {
 
     #[error(transparent)]
     JsonError(#[from] serde_json::Error),
+
+    #[error("no forge profile named {0}")]
+    #[diagnostic(help("create it with pkgdev forge profile or connect to the forge"))]
+    UnknownForgeProfile(String),
 }
 
 type Result<T> = std::result::Result<T, ConfigError>;
@@ -32,6 +41,8 @@ const APP_NAME: &str = "pkgdev";
 const DEFAULT_WORKSPACE_DIR: &str = "wks";
 const DEFAULT_OUTPUT_DIR_DIR: &str = "output";
 const DEFAULT_REPO_DIR_DIR: &str = "repo";
+const DEFAULT_STAGING_DIR_DIR: &str = "staging";
+const DEFAULT_SNAPSHOT_DIR_DIR: &str = "snapshots";
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Settings {
@@ -40,24 +51,112 @@ pub struct Settings {
     output_dir: Option<String>,
     pub github_token: Option<GitHubToken>,
     search_path: Option<Vec<String>>,
-    pub forges: Vec<ForgeToken>,
+    /// Profiles of the forges pkgdev knows like staging and production
+    pub forges: Vec<ForgeProfile>,
+    /// Name of the profile forge commands use
+    pub selected_forge: Option<String>,
+    configure_cache: Option<bool>,
+    pub remote_repository: Option<RemoteRepository>,
+    /// Credentials for fetching sources of private repositories and archives
+    #[serde(default)]
+    pub source_credentials: Vec<SourceCredential>,
+    /// Url of a forge archives are fetched through, so builds survive upstream outages
+    pub source_mirror: Option<String>,
+    /// Jobs make and ninja run at once unless the gate or the recipe set it, the number
+    /// of CPUs without any
+    pub build_jobs: Option<u32>,
+    /// Address space of every build process in bytes at most
+    pub build_memory_limit: Option<u64>,
+    /// CPU time of every build process in seconds at most
+    pub build_cpu_time_limit: Option<u64>,
+    /// Secret key file the packages pkgdev makes are signed with, as exported by forged
+    /// for the publisher. Unsigned without one
+    pub signing_key: Option<String>,
+    /// Directory the public, staging and snapshot repositories are kept in, the data
+    /// directory of pkgdev if unset. Workers point it at storage they all mount so one
+    /// worker can promote what another one staged
+    pub repository_dir: Option<String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// A pkg depot builds publish to over HTTP instead of the local repository.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RemoteRepository {
+    pub url: String,
+    /// PEM files of the SSL client certificate the depot requires
+    pub ssl_key: Option<String>,
+    pub ssl_cert: Option<String>,
+    /// Sent as bearer token to depots behind an authenticating proxy
+    pub token: Option<String>,
+    /// How often failed requests are retried
+    pub retries: Option<u32>,
+}
+
+/// A forge pkgdev talks to and the defaults of the commands working with it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ForgeProfile {
+    #[serde(default)]
+    pub name: String,
+    /// Base url of the forge like `https://forge.openindiana.org`
+    #[serde(default)]
+    pub url: String,
+    /// Id of the gate used when commands are not given one
+    pub default_gate: Option<String>,
+    /// Publisher used when commands are not given one
+    pub default_publisher: Option<String>,
+    pub token: Option<ForgeToken>,
+}
+
+impl Into<ValueKind> for ForgeProfile {
+    fn into(self) -> ValueKind {
+        ValueKind::Table(config::Map::from([
+            ("name".to_string(), Value::from(self.name)),
+            ("url".to_string(), Value::from(self.url)),
+            ("default_gate".to_string(), Value::from(self.default_gate)),
+            (
+                "default_publisher".to_string(),
+                Value::from(self.default_publisher),
+            ),
+            ("token".to_string(), Value::from(self.token)),
+        ]))
+    }
+}
+
+/// Seconds before expiry a token counts as expired, so requests do not race the expiry.
+const TOKEN_EXPIRY_MARGIN: u64 = 300;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ForgeToken {
+    /// Url of the forge the token was issued by
+    #[serde(default)]
+    pub target: String,
+    pub handle: Option<String>,
     pub access_token: String,
     pub refresh_token: Option<String>,
     pub scope: Option<Vec<String>>,
     pub expires_in: Option<u64>,
+    /// Unix time the access token expires
+    pub expires_at: Option<u64>,
+}
+
+impl ForgeToken {
+    /// Whether the access token has to be refreshed before it is used at unix time `now`.
+    /// Tokens without a known expiry are used until the forge rejects them.
+    pub fn needs_refresh(&self, now: u64) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| now + TOKEN_EXPIRY_MARGIN >= expires_at)
+    }
 }
 
 impl Into<ValueKind> for ForgeToken {
     fn into(self) -> ValueKind {
         ValueKind::Table(config::Map::from([
+            ("target".to_string(), Value::from(self.target)),
+            ("handle".to_string(), Value::from(self.handle)),
             ("access_token".to_string(), Value::from(self.access_token)),
             ("refresh_token".to_string(), Value::from(self.refresh_token)),
             ("scope".to_string(), Value::from(self.scope)),
             ("expires_in".to_string(), Value::from(self.expires_in)),
+            ("expires_at".to_string(), Value::from(self.expires_at)),
         ]))
     }
 }
@@ -71,6 +170,24 @@ pub struct GitHubToken {
     pub expires_in: Option<u64>,
 }
 
+/// The source credentials the worker handed over, see [`take_handed_credentials`].
+static HANDED_CREDENTIALS: OnceLock<Vec<SourceCredential>> = OnceLock::new();
+
+/// Take the source credentials the worker hands to pkgdev for the job being built. The
+/// file is deleted and the variable naming it removed, so none of the steps of the build
+/// can read them. Call it at startup before anything else runs.
+pub fn take_handed_credentials() -> Result<()> {
+    let Some(path) = std::env::var_os(SOURCE_CREDENTIALS_FILE_ENV) else {
+        return Ok(());
+    };
+    std::env::remove_var(SOURCE_CREDENTIALS_FILE_ENV);
+    let content = std::fs::read(&path);
+    std::fs::remove_file(&path)?;
+    let credentials: Vec<SourceCredential> = serde_json::from_slice(&content?)?;
+    let _ = HANDED_CREDENTIALS.set(credentials);
+    Ok(())
+}
+
 impl Settings {
     pub fn open() -> Result<Self> {
         let config_dir = Settings::get_or_create_config_dir()?;
@@ -85,11 +202,26 @@ impl Settings {
                     "/sbin",
                 ]),
             )?
-            .set_default("forges", Vec::<ForgeToken>::new())?
+            .set_default("forges", Vec::<ForgeProfile>::new())?
             .add_source(config::File::from(config_dir.join("config")).required(false))
             .build()?;
 
-        Ok(config.try_deserialize()?)
+        let mut settings: Self = config.try_deserialize()?;
+        // Before profiles forges held bare tokens which do not name their forge
+        settings.forges.retain(|p| !p.name.is_empty());
+        if let Some(credentials) = HANDED_CREDENTIALS.get() {
+            settings.source_credentials.extend(credentials.iter().cloned());
+        }
+        if let Ok(mirror) = std::env::var(SOURCE_MIRROR_ENV) {
+            settings.source_mirror = Some(mirror);
+        }
+        if let Ok(signing_key) = std::env::var(SIGNING_KEY_ENV) {
+            settings.signing_key = Some(signing_key);
+        }
+        if let Ok(repository_dir) = std::env::var(REPOSITORY_DIR_ENV) {
+            settings.repository_dir = Some(repository_dir);
+        }
+        Ok(settings)
     }
 
     fn get_or_create_config_dir() -> Result<PathBuf> {
@@ -126,6 +258,15 @@ impl Settings {
         Ok(archive_dir.to_path_buf())
     }
 
+    pub fn get_or_create_configure_cache_dir() -> Result<PathBuf> {
+        let proj_dir = ProjectDirs::from(QUALIFIER, ORG, APP_NAME).ok_or(ConfigError::NoProjectDir)?;
+        let configure_cache_dir = proj_dir.cache_dir().join("configure");
+        if !configure_cache_dir.exists() {
+            DirBuilder::new().recursive(true).create(&configure_cache_dir)?;
+        }
+        Ok(configure_cache_dir.to_path_buf())
+    }
+
     pub fn get_or_create_output_dir() -> Result<PathBuf> {
         let proj_dir = ProjectDirs::from(QUALIFIER, ORG, APP_NAME).ok_or(ConfigError::NoProjectDir)?;
         let output_dir = proj_dir.data_dir().join(DEFAULT_OUTPUT_DIR_DIR);
@@ -135,13 +276,52 @@ impl Settings {
         Ok(output_dir.to_path_buf())
     }
 
-    pub fn get_or_create_repo_dir() -> Result<PathBuf> {
-        let proj_dir = ProjectDirs::from(QUALIFIER, ORG, APP_NAME).ok_or(ConfigError::NoProjectDir)?;
-        let repo_dir = proj_dir.data_dir().join(DEFAULT_REPO_DIR_DIR);
+    /// The directory the package repositories are kept in, see `repository_dir`.
+    fn get_repository_base_dir(&self) -> Result<PathBuf> {
+        match &self.repository_dir {
+            Some(dir) => Ok(PathBuf::from(dir)),
+            None => Settings::get_or_create_data_dir(),
+        }
+    }
+
+    /// The public repository. Promotions replace it, it may be a symlink to the
+    /// repository promoted last.
+    pub fn get_repo_dir(&self) -> Result<PathBuf> {
+        Ok(self.get_repository_base_dir()?.join(DEFAULT_REPO_DIR_DIR))
+    }
+
+    pub fn get_or_create_repo_dir(&self) -> Result<PathBuf> {
+        let repo_dir = self.get_repo_dir()?;
         if !repo_dir.exists() {
             DirBuilder::new().recursive(true).create(&repo_dir)?;
         }
-        Ok(repo_dir.to_path_buf())
+        Ok(repo_dir)
+    }
+
+    /// The repository packages of one change are published to before they are
+    /// promoted into the repository of get_or_create_repo_dir.
+    pub fn get_staging_repo_dir(&self, name: &str) -> Result<PathBuf> {
+        Ok(self
+            .get_repository_base_dir()?
+            .join(DEFAULT_STAGING_DIR_DIR)
+            .join(name))
+    }
+
+    pub fn get_or_create_staging_repo_dir(&self, name: &str) -> Result<PathBuf> {
+        let staging_dir = self.get_staging_repo_dir(name)?;
+        if !staging_dir.exists() {
+            DirBuilder::new().recursive(true).create(&staging_dir)?;
+        }
+        Ok(staging_dir)
+    }
+
+    /// A copy of the repository of get_or_create_repo_dir as it was when a release was
+    /// published.
+    pub fn get_snapshot_repo_dir(&self, name: &str) -> Result<PathBuf> {
+        Ok(self
+            .get_repository_base_dir()?
+            .join(DEFAULT_SNAPSHOT_DIR_DIR)
+            .join(name))
     }
 
     pub fn get_output_dir(&self) -> String {
@@ -163,6 +343,67 @@ impl Settings {
         }
     }
 
+    pub fn get_forge_profile(&self, name: &str) -> Option<&ForgeProfile> {
+        self.forges.iter().find(|p| p.name == name)
+    }
+
+    /// The selected forge profile, the first one if none is selected.
+    pub fn get_current_forge_profile(&self) -> Option<&ForgeProfile> {
+        match &self.selected_forge {
+            Some(name) => self.get_forge_profile(name),
+            None => self.forges.first(),
+        }
+    }
+
+    /// Store a forge profile, replacing the profile of the same name.
+    pub fn set_forge_profile(&mut self, profile: ForgeProfile) -> Result<()> {
+        if let Some(existing) = self.forges.iter_mut().find(|p| p.name == profile.name) {
+            *existing = profile;
+        } else {
+            self.forges.push(profile);
+        }
+        self.save()
+    }
+
+    /// Make forge commands use the profile `name`.
+    pub fn use_forge_profile(&mut self, name: &str) -> Result<&ForgeProfile> {
+        if self.get_forge_profile(name).is_none() {
+            return Err(ConfigError::UnknownForgeProfile(name.to_string()));
+        }
+        self.selected_forge = Some(name.to_string());
+        self.save()?;
+        Ok(self.get_forge_profile(name).unwrap())
+    }
+
+    /// The token stored for the forge at `target`.
+    pub fn get_forge_token(&self, target: &str) -> Option<&ForgeToken> {
+        self.forges
+            .iter()
+            .find(|p| p.url == target)
+            .and_then(|p| p.token.as_ref())
+    }
+
+    /// Store the token of the forge at `token.target` in its profile. Forges without a
+    /// profile get one named `name`.
+    pub fn set_forge_token(&mut self, name: &str, token: ForgeToken) -> Result<()> {
+        if let Some(profile) = self.forges.iter_mut().find(|p| p.url == token.target) {
+            profile.token = Some(token);
+        } else {
+            self.forges.push(ForgeProfile {
+                name: name.to_string(),
+                url: token.target.clone(),
+                token: Some(token),
+                ..Default::default()
+            });
+        }
+        self.save()
+    }
+
+    /// Whether builds share the autoconf config.cache of earlier builds of a component.
+    pub fn use_configure_cache(&self) -> bool {
+        self.configure_cache.unwrap_or(false)
+    }
+
     pub fn add_path_to_search(&mut self, value: String) {
         if let Some(path) = &mut self.search_path {
             path.push(value);
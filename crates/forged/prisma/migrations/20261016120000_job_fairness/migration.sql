@@ -0,0 +1,31 @@
+-- CreateEnum
+CREATE TYPE "QueuedJobState" AS ENUM ('Pending', 'Running', 'Finished');
+
+-- CreateTable
+CREATE TABLE "SourceRepository" (
+    "id" UUID NOT NULL,
+    "url" TEXT NOT NULL,
+    "maxConcurrentJobs" INTEGER NOT NULL DEFAULT 1,
+    "lastDispatchedAt" TIMESTAMP(3),
+
+    CONSTRAINT "SourceRepository_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "QueuedJob" (
+    "id" UUID NOT NULL,
+    "repositoryId" UUID NOT NULL,
+    "reference" TEXT NOT NULL,
+    "payload" JSONB NOT NULL,
+    "state" "QueuedJobState" NOT NULL DEFAULT 'Pending',
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "dispatchedAt" TIMESTAMP(3),
+
+    CONSTRAINT "QueuedJob_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "SourceRepository_url_key" ON "SourceRepository"("url");
+
+-- AddForeignKey
+ALTER TABLE "QueuedJob" ADD CONSTRAINT "QueuedJob_repositoryId_fkey" FOREIGN KEY ("repositoryId") REFERENCES "SourceRepository"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
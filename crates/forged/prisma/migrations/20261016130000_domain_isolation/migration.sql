@@ -0,0 +1,28 @@
+-- DropIndex
+DROP INDEX "Actor_handle_key";
+
+-- DropIndex
+DROP INDEX "Publisher_name_key";
+
+-- AlterTable
+ALTER TABLE "Publisher" ADD COLUMN     "domainId" UUID;
+
+-- Existing publishers belong to the first domain of the instance
+UPDATE "Publisher" SET "domainId" = (SELECT "id" FROM "Domain" ORDER BY "dnsName" LIMIT 1) WHERE "domainId" IS NULL;
+
+ALTER TABLE "Publisher" ALTER COLUMN "domainId" SET NOT NULL;
+
+-- AlterTable
+ALTER TABLE "ChangeRequest" ADD COLUMN     "domainId" UUID;
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Actor_handle_domainId_key" ON "Actor"("handle", "domainId");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Publisher_name_domainId_key" ON "Publisher"("name", "domainId");
+
+-- AddForeignKey
+ALTER TABLE "Publisher" ADD CONSTRAINT "Publisher_domainId_fkey" FOREIGN KEY ("domainId") REFERENCES "Domain"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "ChangeRequest" ADD CONSTRAINT "ChangeRequest_domainId_fkey" FOREIGN KEY ("domainId") REFERENCES "Domain"("id") ON DELETE SET NULL ON UPDATE CASCADE;
@@ -0,0 +1,17 @@
+-- CreateTable
+CREATE TABLE "GateQualitySnapshot" (
+    "id" UUID NOT NULL,
+    "gateId" UUID NOT NULL,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "components" INTEGER NOT NULL,
+    "missingSummary" INTEGER NOT NULL,
+    "missingLicense" INTEGER NOT NULL,
+    "withoutMaintainer" INTEGER NOT NULL,
+    "staleVersions" INTEGER NOT NULL,
+    "lintWarnings" INTEGER NOT NULL,
+
+    CONSTRAINT "GateQualitySnapshot_pkey" PRIMARY KEY ("id")
+);
+
+-- AddForeignKey
+ALTER TABLE "GateQualitySnapshot" ADD CONSTRAINT "GateQualitySnapshot_gateId_fkey" FOREIGN KEY ("gateId") REFERENCES "Gate"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
@@ -0,0 +1,25 @@
+-- CreateEnum
+CREATE TYPE "StagedPublicationState" AS ENUM ('Building', 'Staged', 'Promoting', 'Promoted', 'Failed');
+
+-- CreateTable
+CREATE TABLE "StagedPublication" (
+    "id" UUID NOT NULL,
+    "changeRequestId" TEXT NOT NULL,
+    "staging" TEXT NOT NULL,
+    "state" "StagedPublicationState" NOT NULL DEFAULT 'Building',
+    "pendingBuilds" INTEGER NOT NULL DEFAULT 0,
+    "packages" TEXT[],
+    "error" TEXT,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "StagedPublication_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "StagedPublication_changeRequestId_key" ON "StagedPublication"("changeRequestId");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "StagedPublication_staging_key" ON "StagedPublication"("staging");
+
+-- AddForeignKey
+ALTER TABLE "StagedPublication" ADD CONSTRAINT "StagedPublication_changeRequestId_fkey" FOREIGN KEY ("changeRequestId") REFERENCES "ChangeRequest"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
@@ -0,0 +1,27 @@
+-- AlterTable
+ALTER TABLE "Domain" ADD COLUMN     "retention" JSONB NOT NULL DEFAULT '{}';
+
+-- AlterTable
+ALTER TABLE "ComponentChange" ADD COLUMN     "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- AlterTable
+ALTER TABLE "ChangeRequest" ADD COLUMN     "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+ADD COLUMN     "updatedAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- CreateTable
+CREATE TABLE "RetentionRun" (
+    "id" UUID NOT NULL,
+    "domainId" UUID NOT NULL,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "jobs" INTEGER NOT NULL,
+    "componentChanges" INTEGER NOT NULL,
+    "changeRequests" INTEGER NOT NULL,
+    "blobs" INTEGER NOT NULL,
+    "reclaimedDatabaseBytes" BIGINT NOT NULL,
+    "reclaimedBlobBytes" BIGINT NOT NULL,
+
+    CONSTRAINT "RetentionRun_pkey" PRIMARY KEY ("id")
+);
+
+-- AddForeignKey
+ALTER TABLE "RetentionRun" ADD CONSTRAINT "RetentionRun_domainId_fkey" FOREIGN KEY ("domainId") REFERENCES "Domain"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "Gate" ADD COLUMN     "maxConcurrentJobs" INTEGER;
+
+-- AlterTable
+ALTER TABLE "QueuedJob" ADD COLUMN     "gateId" UUID,
+ADD COLUMN     "priority" INTEGER NOT NULL DEFAULT 0;
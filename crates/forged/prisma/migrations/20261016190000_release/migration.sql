@@ -0,0 +1,27 @@
+-- CreateEnum
+CREATE TYPE "ReleaseState" AS ENUM ('Reading', 'Building', 'Publishing', 'Published', 'Failed');
+
+-- CreateTable
+CREATE TABLE "Release" (
+    "id" UUID NOT NULL,
+    "gateId" UUID NOT NULL,
+    "tag" TEXT NOT NULL,
+    "staging" TEXT NOT NULL,
+    "state" "ReleaseState" NOT NULL DEFAULT 'Reading',
+    "pendingBuilds" INTEGER NOT NULL DEFAULT 0,
+    "packages" TEXT[],
+    "error" TEXT,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "publishedAt" TIMESTAMP(3),
+
+    CONSTRAINT "Release_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Release_staging_key" ON "Release"("staging");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Release_gateId_tag_key" ON "Release"("gateId", "tag");
+
+-- AddForeignKey
+ALTER TABLE "Release" ADD CONSTRAINT "Release_gateId_fkey" FOREIGN KEY ("gateId") REFERENCES "Gate"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
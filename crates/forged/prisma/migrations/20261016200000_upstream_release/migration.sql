@@ -0,0 +1,19 @@
+-- CreateTable
+CREATE TABLE "UpstreamRelease" (
+    "id" UUID NOT NULL,
+    "gateId" UUID NOT NULL,
+    "name" TEXT NOT NULL,
+    "version" TEXT NOT NULL,
+    "upstreamVersion" TEXT NOT NULL,
+    "source" TEXT NOT NULL,
+    "outdated" BOOLEAN NOT NULL,
+    "checkedAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "UpstreamRelease_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "UpstreamRelease_gateId_name_key" ON "UpstreamRelease"("gateId", "name");
+
+-- AddForeignKey
+ALTER TABLE "UpstreamRelease" ADD CONSTRAINT "UpstreamRelease_gateId_fkey" FOREIGN KEY ("gateId") REFERENCES "Gate"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
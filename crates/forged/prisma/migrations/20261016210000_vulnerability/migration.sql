@@ -0,0 +1,28 @@
+-- AlterTable
+ALTER TABLE "Component" ADD COLUMN     "cpe" TEXT,
+ADD COLUMN     "purl" TEXT;
+
+-- CreateTable
+CREATE TABLE "Vulnerability" (
+    "id" UUID NOT NULL,
+    "componentName" TEXT NOT NULL,
+    "componentVersion" TEXT NOT NULL,
+    "componentRevision" TEXT NOT NULL,
+    "gateId" UUID NOT NULL,
+    "advisoryId" TEXT NOT NULL,
+    "source" TEXT NOT NULL,
+    "aliases" TEXT[],
+    "summary" TEXT,
+    "severity" TEXT,
+    "fixedVersion" TEXT,
+    "url" TEXT,
+    "detectedAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "Vulnerability_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Vulnerability_componentName_gateId_componentVersion_componentRevision_advisoryId_key" ON "Vulnerability"("componentName", "gateId", "componentVersion", "componentRevision", "advisoryId");
+
+-- AddForeignKey
+ALTER TABLE "Vulnerability" ADD CONSTRAINT "Vulnerability_componentName_gateId_componentVersion_componentRevision_fkey" FOREIGN KEY ("componentName", "gateId", "componentVersion", "componentRevision") REFERENCES "Component"("name", "gateId", "version", "revision") ON DELETE CASCADE ON UPDATE CASCADE;
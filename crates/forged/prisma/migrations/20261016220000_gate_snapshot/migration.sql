@@ -0,0 +1,17 @@
+-- CreateTable
+CREATE TABLE "GateSnapshot" (
+    "id" UUID NOT NULL,
+    "gateId" UUID NOT NULL,
+    "name" TEXT NOT NULL,
+    "components" JSONB NOT NULL,
+    "promotedFrom" UUID,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "GateSnapshot_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "GateSnapshot_gateId_name_key" ON "GateSnapshot"("gateId", "name");
+
+-- AddForeignKey
+ALTER TABLE "GateSnapshot" ADD CONSTRAINT "GateSnapshot_gateId_fkey" FOREIGN KEY ("gateId") REFERENCES "Gate"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
@@ -0,0 +1,33 @@
+-- CreateExtension
+CREATE EXTENSION IF NOT EXISTS "pg_trgm";
+
+-- AlterTable
+ALTER TABLE "Component" ADD COLUMN     "classification" TEXT,
+ADD COLUMN     "license" TEXT,
+ADD COLUMN     "maintainers" TEXT[],
+ADD COLUMN     "summary" TEXT;
+
+-- Fill the search columns of the existing components from their recipes
+UPDATE "Component" SET
+    "summary" = "recipe"->>'summary',
+    "license" = "recipe"->>'license',
+    "classification" = "recipe"->>'classification',
+    "maintainers" = ARRAY(SELECT jsonb_array_elements_text(COALESCE("recipe"->'maintainers', '[]'::jsonb)));
+
+-- CreateIndex
+CREATE INDEX "Component_name_trgm_idx" ON "Component" USING GIN ("name" gin_trgm_ops);
+
+-- CreateIndex
+CREATE INDEX "Component_summary_trgm_idx" ON "Component" USING GIN ("summary" gin_trgm_ops);
+
+-- CreateIndex
+CREATE INDEX "Component_project_url_trgm_idx" ON "Component" USING GIN ("project_url" gin_trgm_ops);
+
+-- CreateIndex
+CREATE INDEX "Component_maintainers_idx" ON "Component" USING GIN ("maintainers");
+
+-- CreateIndex
+CREATE INDEX "Component_license_idx" ON "Component"("license");
+
+-- CreateIndex
+CREATE INDEX "Component_classification_idx" ON "Component"("classification");
@@ -0,0 +1,36 @@
+-- CreateTable
+CREATE TABLE "Maintainer" (
+    "id" UUID NOT NULL,
+    "name" TEXT NOT NULL,
+    "domainId" UUID NOT NULL,
+    "actorId" UUID,
+
+    CONSTRAINT "Maintainer_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "_GateAdmins" (
+    "A" UUID NOT NULL,
+    "B" UUID NOT NULL
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Maintainer_name_domainId_key" ON "Maintainer"("name", "domainId");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "_GateAdmins_AB_unique" ON "_GateAdmins"("A", "B");
+
+-- CreateIndex
+CREATE INDEX "_GateAdmins_B_index" ON "_GateAdmins"("B");
+
+-- AddForeignKey
+ALTER TABLE "Maintainer" ADD CONSTRAINT "Maintainer_domainId_fkey" FOREIGN KEY ("domainId") REFERENCES "Domain"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Maintainer" ADD CONSTRAINT "Maintainer_actorId_fkey" FOREIGN KEY ("actorId") REFERENCES "Actor"("id") ON DELETE SET NULL ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_GateAdmins" ADD CONSTRAINT "_GateAdmins_A_fkey" FOREIGN KEY ("A") REFERENCES "Actor"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_GateAdmins" ADD CONSTRAINT "_GateAdmins_B_fkey" FOREIGN KEY ("B") REFERENCES "Gate"("id") ON DELETE CASCADE ON UPDATE CASCADE;
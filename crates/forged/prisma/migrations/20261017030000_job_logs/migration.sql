@@ -0,0 +1,35 @@
+-- CreateEnum
+CREATE TYPE "QueuedJobOutcome" AS ENUM ('Success', 'Failure', 'Cancelled');
+
+-- AlterTable
+ALTER TABLE "QueuedJob" ADD COLUMN     "staging" TEXT,
+ADD COLUMN     "finishedAt" TIMESTAMP(3),
+ADD COLUMN     "outcome" "QueuedJobOutcome",
+ADD COLUMN     "error" TEXT;
+
+-- Jobs queued before carry their staging repository only in the payload
+UPDATE "QueuedJob" SET "staging" = COALESCE(
+    "payload"->'BuildStoredComponent'->>'staging',
+    "payload"->'PromoteStagedPackages'->>'staging',
+    "payload"->'PublishRelease'->>'staging'
+);
+
+-- CreateTable
+CREATE TABLE "JobLogLine" (
+    "id" UUID NOT NULL,
+    "jobId" UUID NOT NULL,
+    "number" INTEGER NOT NULL,
+    "content" TEXT NOT NULL,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "JobLogLine_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "QueuedJob_staging_idx" ON "QueuedJob"("staging");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "JobLogLine_jobId_number_key" ON "JobLogLine"("jobId", "number");
+
+-- AddForeignKey
+ALTER TABLE "JobLogLine" ADD CONSTRAINT "JobLogLine_jobId_fkey" FOREIGN KEY ("jobId") REFERENCES "QueuedJob"("id") ON DELETE CASCADE ON UPDATE CASCADE;
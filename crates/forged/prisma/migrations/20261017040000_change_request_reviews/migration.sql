@@ -0,0 +1,17 @@
+-- CreateEnum
+CREATE TYPE "ReviewVerdict" AS ENUM ('Approve', 'Comment');
+
+-- CreateTable
+CREATE TABLE "ChangeRequestReview" (
+    "id" UUID NOT NULL,
+    "changeRequestId" TEXT NOT NULL,
+    "actor" TEXT NOT NULL,
+    "verdict" "ReviewVerdict" NOT NULL,
+    "body" TEXT NOT NULL,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "ChangeRequestReview_pkey" PRIMARY KEY ("id")
+);
+
+-- AddForeignKey
+ALTER TABLE "ChangeRequestReview" ADD CONSTRAINT "ChangeRequestReview_changeRequestId_fkey" FOREIGN KEY ("changeRequestId") REFERENCES "ChangeRequest"("id") ON DELETE CASCADE ON UPDATE CASCADE;
@@ -0,0 +1,26 @@
+-- CreateEnum
+CREATE TYPE "WebhookDeliveryState" AS ENUM ('Received', 'Processed', 'Failed');
+
+-- CreateTable
+CREATE TABLE "WebhookDelivery" (
+    "id" UUID NOT NULL,
+    "gateId" UUID NOT NULL,
+    "deliveryId" TEXT,
+    "event" TEXT NOT NULL,
+    "headers" JSONB NOT NULL,
+    "payload" TEXT NOT NULL,
+    "state" "WebhookDeliveryState" NOT NULL DEFAULT 'Received',
+    "error" TEXT,
+    "correlationId" TEXT,
+    "attempts" INTEGER NOT NULL DEFAULT 1,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updatedAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "WebhookDelivery_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "WebhookDelivery_gateId_createdAt_idx" ON "WebhookDelivery"("gateId", "createdAt");
+
+-- AddForeignKey
+ALTER TABLE "WebhookDelivery" ADD CONSTRAINT "WebhookDelivery_gateId_fkey" FOREIGN KEY ("gateId") REFERENCES "Gate"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
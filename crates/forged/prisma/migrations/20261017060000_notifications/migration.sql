@@ -0,0 +1,43 @@
+-- CreateEnum
+CREATE TYPE "NotificationState" AS ENUM ('Pending', 'Delivered', 'Failed');
+
+-- CreateTable
+CREATE TABLE "NotificationSubscription" (
+    "id" UUID NOT NULL,
+    "publisherId" UUID NOT NULL,
+    "name" TEXT NOT NULL,
+    "target" JSONB NOT NULL,
+    "events" TEXT[],
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "NotificationSubscription_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "Notification" (
+    "id" UUID NOT NULL,
+    "subscriptionId" UUID NOT NULL,
+    "event" TEXT NOT NULL,
+    "payload" JSONB NOT NULL,
+    "state" "NotificationState" NOT NULL DEFAULT 'Pending',
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "error" TEXT,
+    "nextAttemptAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "deliveredAt" TIMESTAMP(3),
+
+    CONSTRAINT "Notification_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "NotificationSubscription_name_publisherId_key" ON "NotificationSubscription"("name", "publisherId");
+
+-- CreateIndex
+CREATE INDEX "Notification_state_nextAttemptAt_idx" ON "Notification"("state", "nextAttemptAt");
+
+-- AddForeignKey
+ALTER TABLE "NotificationSubscription" ADD CONSTRAINT "NotificationSubscription_publisherId_fkey" FOREIGN KEY ("publisherId") REFERENCES "Publisher"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Notification" ADD CONSTRAINT "Notification_subscriptionId_fkey" FOREIGN KEY ("subscriptionId") REFERENCES "NotificationSubscription"("id") ON DELETE CASCADE ON UPDATE CASCADE;
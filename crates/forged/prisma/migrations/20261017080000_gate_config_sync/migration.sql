@@ -0,0 +1,17 @@
+-- AlterTable
+ALTER TABLE "Gate" ADD COLUMN     "syncedConfig" JSONB;
+
+-- CreateTable
+CREATE TABLE "GateConfigSync" (
+    "id" UUID NOT NULL,
+    "gateId" UUID NOT NULL,
+    "commit" TEXT NOT NULL,
+    "changed" TEXT[],
+    "conflicts" TEXT[],
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "GateConfigSync_pkey" PRIMARY KEY ("id")
+);
+
+-- AddForeignKey
+ALTER TABLE "GateConfigSync" ADD CONSTRAINT "GateConfigSync_gateId_fkey" FOREIGN KEY ("gateId") REFERENCES "Gate"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
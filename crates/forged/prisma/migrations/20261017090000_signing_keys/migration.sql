@@ -0,0 +1,15 @@
+-- CreateTable
+CREATE TABLE "SigningKey" (
+    "id" UUID NOT NULL,
+    "publisherId" UUID NOT NULL,
+    "keyId" TEXT NOT NULL,
+    "publicKey" TEXT NOT NULL,
+    "secretKey" TEXT NOT NULL,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "retiredAt" TIMESTAMP(3),
+
+    CONSTRAINT "SigningKey_pkey" PRIMARY KEY ("id")
+);
+
+-- AddForeignKey
+ALTER TABLE "SigningKey" ADD CONSTRAINT "SigningKey_publisherId_fkey" FOREIGN KEY ("publisherId") REFERENCES "Publisher"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
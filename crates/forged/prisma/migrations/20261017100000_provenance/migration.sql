@@ -0,0 +1,21 @@
+-- CreateTable
+CREATE TABLE "Provenance" (
+    "id" UUID NOT NULL,
+    "componentName" TEXT NOT NULL,
+    "componentVersion" TEXT NOT NULL,
+    "componentRevision" TEXT NOT NULL,
+    "gateId" UUID NOT NULL,
+    "staging" TEXT,
+    "bootstrap" BOOLEAN NOT NULL DEFAULT false,
+    "document" TEXT NOT NULL,
+    "signature" TEXT,
+    "createdAt" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "Provenance_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "Provenance_componentName_gateId_componentVersion_componentRevision_idx" ON "Provenance"("componentName", "gateId", "componentVersion", "componentRevision");
+
+-- AddForeignKey
+ALTER TABLE "Provenance" ADD CONSTRAINT "Provenance_componentName_gateId_componentVersion_componentRevision_fkey" FOREIGN KEY ("componentName", "gateId", "componentVersion", "componentRevision") REFERENCES "Component"("name", "gateId", "version", "revision") ON DELETE CASCADE ON UPDATE CASCADE;
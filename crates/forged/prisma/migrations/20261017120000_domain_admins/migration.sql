@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "Actor" ADD COLUMN     "domainAdmin" BOOLEAN NOT NULL DEFAULT false;
@@ -1,4 +1,4 @@
-use axum::extract::{FromRef, FromRequestParts, Host};
+use axum::extract::{FromRef, FromRequestParts, Host, OriginalUri};
 use axum::http::request::Parts;
 use axum::{async_trait, RequestPartsExt};
 use axum_extra::headers::authorization::Bearer;
@@ -10,11 +10,20 @@ use pasetors::token::{TrustedToken, UntrustedToken};
 use pasetors::version4::V4;
 use pasetors::Public;
 
-use crate::{prisma, AppState, Error};
+use crate::domain::find_domain;
+use crate::prisma;
+use crate::token::{scope_allows, TokenKind};
+use crate::{AppState, Error};
 
 #[allow(dead_code)]
 pub struct Authentication {
     pub token: TrustedToken,
+    /// The domain the token was issued for. All data the request touches must belong to it.
+    pub domain_id: String,
+    /// Handle of the actor the token was issued to
+    pub handle: String,
+    /// Id of the API key the request was made with, unset for login tokens
+    pub api_key: Option<String>,
 }
 
 #[async_trait]
@@ -42,22 +51,113 @@ where
             .await
             .map_err(|_| Error::Unauthorized)?;
 
-        let domain = state
-            .prisma
-            .lock()
+        let db = state.prisma.clone();
+        let domain = find_domain(&db, &host)
             .await
-            .domain()
-            .find_unique(prisma::domain::UniqueWhereParam::DnsNameEquals(host))
-            .exec()
-            .await?
-            .ok_or(Error::Unauthorized)?;
+            .map_err(|_| Error::Unauthorized)?;
 
         let public_key = AsymmetricPublicKey::<V4>::try_from(domain.public_key.as_str())?;
-        let untrusted_token = UntrustedToken::<Public, V4>::try_from(authorization.token())?;
-        let validation_rules = ClaimsValidationRules::new();
-        let token =
-            pasetors::public::verify(&public_key, &untrusted_token, &validation_rules, None, None)?;
+        let token = verify_token(&public_key, authorization.token())?;
+        let claims = token.payload_claims().ok_or(Error::Unauthorized)?;
+        let handle = claims
+            .get_claim("sub")
+            .and_then(|sub| sub.as_str())
+            .ok_or(Error::Unauthorized)?
+            .to_string();
+
+        let api_key = match TokenKind::of(claims) {
+            Some(TokenKind::Access) => None,
+            Some(TokenKind::Refresh) | None => return Err(Error::Unauthorized),
+            Some(TokenKind::ApiKey) => {
+                let key_id = claims
+                    .get_claim("jti")
+                    .and_then(|jti| jti.as_str())
+                    .ok_or(Error::Unauthorized)?;
+                let key = db
+                    .api_key()
+                    .find_first(vec![
+                        prisma::api_key::id::equals(key_id.to_string()),
+                        prisma::api_key::revoked_at::equals(None),
+                        prisma::api_key::actor::is(vec![
+                            prisma::actor::handle::equals(handle.clone()),
+                            prisma::actor::domain_id::equals(domain.id.clone()),
+                        ]),
+                    ])
+                    .exec()
+                    .await?
+                    .ok_or(Error::Unauthorized)?;
+
+                let path = match parts.extensions.get::<OriginalUri>() {
+                    Some(OriginalUri(uri)) => uri.path().to_string(),
+                    None => parts.uri.path().to_string(),
+                };
+                if !scope_allows(&key.scopes, &path) {
+                    return Err(Error::ApiKeyScope(key.name));
+                }
+                db.api_key()
+                    .update(
+                        prisma::api_key::UniqueWhereParam::IdEquals(key.id.clone()),
+                        vec![prisma::api_key::last_used_at::set(Some(
+                            chrono::Utc::now().fixed_offset(),
+                        ))],
+                    )
+                    .exec()
+                    .await?;
+                Some(key.id)
+            }
+        };
+
+        Ok(Self {
+            token,
+            domain_id: domain.id,
+            handle,
+            api_key,
+        })
+    }
+}
+
+/// Verify a token signed with the key of a domain. Invalid and expired tokens are both
+/// unauthorized.
+pub fn verify_token(
+    public_key: &AsymmetricPublicKey<V4>,
+    token: &str,
+) -> Result<TrustedToken, Error> {
+    let untrusted_token =
+        UntrustedToken::<Public, V4>::try_from(token).map_err(|_| Error::Unauthorized)?;
+    let validation_rules = ClaimsValidationRules::new();
+    pasetors::public::verify(public_key, &untrusted_token, &validation_rules, None, None)
+        .map_err(|_| Error::Unauthorized)
+}
+
+/// The domain a request was made for, derived from the `Host` header. Used by the
+/// endpoints that do not require authentication to scope their queries.
+pub struct DomainScope {
+    pub domain_id: String,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for DomainScope
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = crate::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Host(host) = parts
+            .extract::<Host>()
+            .await
+            .map_err(|_| Error::NoDomainFound)?;
+
+        let state = parts
+            .extract_with_state::<AppState, _>(state)
+            .await
+            .map_err(|_| Error::NoDomainFound)?;
+
+        let domain = find_domain(&state.prisma, &host).await?;
 
-        Ok(Self { token })
+        Ok(Self {
+            domain_id: domain.id,
+        })
     }
 }
@@ -1,11 +1,7 @@
-use std::ops::Add;
-
 use axum::extract::{Host, State};
 use axum::routing::post;
 use axum::{Json, Router};
-use chrono::TimeDelta;
 use octorust::auth::Credentials;
-use pasetors::claims::Claims;
 use pasetors::keys::AsymmetricSecretKey;
 use pasetors::version4::V4;
 use serde::{Deserialize, Serialize};
@@ -13,6 +9,7 @@ use tracing::log::debug;
 use utoipa::ToSchema;
 
 use crate::prisma::KeyType;
+use crate::token::issue_login_tokens;
 use crate::{prisma, AppState, Error, Result};
 
 const SSH_RSA: &str = "ssh-rsa";
@@ -44,6 +41,8 @@ pub enum ActorConnectRequest {
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct ActorConnectResponse {
     pub access_token: String,
+    /// RFC 3339 time the access token expires, refresh it before
+    pub access_token_expires_at: String,
     pub refresh_token: String,
     pub ssh_keys: Vec<ActorSSHKeyFingerprint>,
     pub handle: String,
@@ -84,7 +83,7 @@ pub async fn actor_connect(
                 host
             };
 
-            let db_conn = state.prisma.lock().await;
+            let db_conn = state.prisma.clone();
 
             let gh_client =
                 octorust::Client::new(format!("package forge {host}"), Credentials::Token(token))?;
@@ -111,8 +110,9 @@ pub async fn actor_connect(
             // Try to find the user by the handle and domain
             if let Some(existing_actor) = db_conn
                 .actor()
-                .find_unique(prisma::actor::UniqueWhereParam::HandleEquals(
+                .find_unique(prisma::actor::UniqueWhereParam::HandleDomainIdEquals(
                     handle.clone(),
+                    domain_data.id.clone(),
                 ))
                 .with(prisma::actor::keys::fetch(vec![]))
                 .exec()
@@ -125,7 +125,7 @@ pub async fn actor_connect(
                     return Err(Error::UnauthorizedToClaimHandle);
                 }
 
-                let (access_token, refresh_token) = make_login_token(
+                let tokens = issue_login_tokens(
                     &handle,
                     &domain_data.dns_name,
                     &existing_actor.display_name,
@@ -218,8 +218,9 @@ pub async fn actor_connect(
                 }
 
                 Ok(Json(ActorConnectResponse {
-                    access_token,
-                    refresh_token,
+                    access_token: tokens.access_token,
+                    access_token_expires_at: tokens.access_expires_at.to_rfc3339(),
+                    refresh_token: tokens.refresh_token,
                     ssh_keys: resp_ssh_keys,
                     handle: existing_actor.handle,
                 }))
@@ -283,7 +284,7 @@ pub async fn actor_connect(
 
                 db_conn.key().create_many(db_keys).exec().await?;
 
-                let (access_token, refresh_token) = make_login_token(
+                let tokens = issue_login_tokens(
                     &actor.handle,
                     &domain_data.dns_name,
                     &actor.display_name,
@@ -291,8 +292,9 @@ pub async fn actor_connect(
                 )?;
 
                 Ok(Json(ActorConnectResponse {
-                    access_token,
-                    refresh_token,
+                    access_token: tokens.access_token,
+                    access_token_expires_at: tokens.access_expires_at.to_rfc3339(),
+                    refresh_token: tokens.refresh_token,
                     ssh_keys: resp_ssh_keys,
                     handle: actor.handle,
                 }))
@@ -304,41 +306,6 @@ pub async fn actor_connect(
     }
 }
 
-fn make_login_token(
-    handle: &str,
-    domain: &str,
-    display_name: &str,
-    key: &AsymmetricSecretKey<V4>,
-) -> Result<(String, String)> {
-    let now = chrono::Utc::now();
-    let access_expiration = now.add(TimeDelta::hours(8));
-    let refresh_expiration = now.add(TimeDelta::days(90));
-
-    let mut access_claims = Claims::new()?;
-    access_claims.issuer(domain)?;
-    access_claims.subject(handle)?;
-    access_claims.audience(domain)?;
-    access_claims.expiration(access_expiration.format("%+").to_string().as_str())?;
-    access_claims.not_before(now.format("%+").to_string().as_str())?;
-    access_claims.issued_at(now.format("%+").to_string().as_str())?;
-    access_claims.add_additional("displayName", display_name)?;
-
-    let access_token = pasetors::public::sign(key, &access_claims, None, None)?;
-
-    let mut refresh_claims = Claims::new()?;
-    refresh_claims.issuer(domain)?;
-    refresh_claims.subject(handle)?;
-    refresh_claims.audience(domain)?;
-    refresh_claims.expiration(refresh_expiration.format("%+").to_string().as_str())?;
-    refresh_claims.not_before(now.format("%+").to_string().as_str())?;
-    refresh_claims.issued_at(now.format("%+").to_string().as_str())?;
-    refresh_claims.add_additional("displayName", display_name)?;
-
-    let refresh_token = pasetors::public::sign(key, &refresh_claims, None, None)?;
-
-    Ok((access_token, refresh_token))
-}
-
 fn ssh_keys_to_db_format(
     actor_id: &str,
     s: &octorust::types::Key,
@@ -0,0 +1,130 @@
+use crate::api::auth::Authentication;
+use crate::{prisma, AppState, Result};
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+pub fn get_router() -> Router<AppState> {
+    Router::new().route("/", get(list_audit_entries))
+}
+
+/// A mutating operation recorded in the audit log.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct AuditEntry {
+    pub id: String,
+    /// Handle of the actor, unset for operations forged did on its own
+    pub actor: Option<String>,
+    pub action: String,
+    pub object_type: String,
+    pub object_id: String,
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<serde_json::Value>,
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<serde_json::Value>,
+    pub correlation_id: Option<String>,
+    pub created_at: String,
+}
+
+impl From<prisma::audit_entry::Data> for AuditEntry {
+    fn from(value: prisma::audit_entry::Data) -> Self {
+        Self {
+            id: value.id,
+            actor: value.actor,
+            action: value.action,
+            object_type: value.object_type,
+            object_id: value.object_id,
+            before: value.before,
+            after: value.after,
+            correlation_id: value.correlation_id,
+            created_at: value.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Filters of the audit log, every given one has to match.
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditParams {
+    /// Handle of the actor
+    pub actor: Option<String>,
+    /// Action like `gate.update`
+    pub action: Option<String>,
+    /// Type of the object like `gate`, `component`, `change_request` or `job`
+    pub object_type: Option<String>,
+    pub object_id: Option<String>,
+    /// Only entries recorded at or after this RFC 3339 time
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Only entries recorded before this RFC 3339 time
+    pub until: Option<DateTime<FixedOffset>>,
+    /// Id of the last entry of the previous page
+    pub cursor: Option<String>,
+    #[serde(default = "default_audit_limit")]
+    pub limit: i64,
+}
+
+fn default_audit_limit() -> i64 {
+    100
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/audit",
+    responses (
+        (status = 200, description = "Audit log entries of the domain matching the filters, newest first", body = Vec<AuditEntry>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+    ),
+    params(AuditParams)
+)]
+async fn list_audit_entries(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Query(params): Query<AuditParams>,
+) -> Result<Json<Vec<AuditEntry>>> {
+    let mut filter = vec![prisma::audit_entry::domain_id::equals(Some(domain_id))];
+
+    if let Some(actor) = params.actor {
+        filter.push(prisma::audit_entry::actor::equals(Some(actor)))
+    }
+
+    if let Some(action) = params.action {
+        filter.push(prisma::audit_entry::action::equals(action))
+    }
+
+    if let Some(object_type) = params.object_type {
+        filter.push(prisma::audit_entry::object_type::equals(object_type))
+    }
+
+    if let Some(object_id) = params.object_id {
+        filter.push(prisma::audit_entry::object_id::equals(object_id))
+    }
+
+    if let Some(since) = params.since {
+        filter.push(prisma::audit_entry::created_at::gte(since))
+    }
+
+    if let Some(until) = params.until {
+        filter.push(prisma::audit_entry::created_at::lt(until))
+    }
+
+    let db = state.prisma.clone();
+    let mut query = db
+        .audit_entry()
+        .find_many(filter)
+        .order_by(prisma::audit_entry::created_at::order(
+            prisma_client_rust::Direction::Desc,
+        ))
+        .take(params.limit);
+
+    if let Some(cursor) = params.cursor {
+        query = query
+            .cursor(prisma::audit_entry::id::equals(cursor))
+            .skip(1);
+    }
+
+    let entries = query.exec().await?;
+
+    Ok(Json(entries.into_iter().map(AuditEntry::from).collect()))
+}
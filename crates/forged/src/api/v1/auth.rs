@@ -1,15 +1,29 @@
-use axum::extract::{Host, State};
-use axum::routing::get;
+use axum::extract::{Host, Path, State};
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
+use chrono::{DateTime, TimeDelta, Utc};
+use pasetors::keys::{AsymmetricPublicKey, AsymmetricSecretKey};
+use pasetors::version4::V4;
 use serde::{Deserialize, Serialize};
 use tracing::log::debug;
 use utoipa::ToSchema;
+use uuid::Uuid;
 
-use crate::{prisma, Error};
-use crate::{AppState, Result};
+use crate::api::auth::{verify_token, Authentication};
+use crate::audit::{self, AuditEvent};
+use crate::domain::find_domain;
+use crate::ownership::find_actor;
+use crate::token::{
+    issue_api_key_token, issue_login_tokens, TokenKind, ALL_SCOPES, API_KEY_MAX_DAYS,
+};
+use crate::{prisma, AppState, Error, Result};
 
 pub fn get_router() -> Router<AppState> {
-    Router::new().route("/login_info", get(login_info))
+    Router::new()
+        .route("/login_info", get(login_info))
+        .route("/refresh", post(refresh_token))
+        .route("/keys", get(list_api_keys).post(create_api_key))
+        .route("/keys/:id", delete(revoke_api_key))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -38,26 +52,317 @@ pub async fn login_info(
     Host(host): Host,
 ) -> Result<Json<AuthConfig>> {
     debug!("Looking up login details for {}", &host);
-    let host = if let Some((host, _)) = host.split_once(":") {
-        host.to_string()
-    } else {
-        host
+    let domain = find_domain(&state.prisma, &host).await?;
+    let auth_config: AuthConfig = serde_json::from_value(domain.authconf)?;
+
+    Ok(Json(auth_config))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    /// RFC 3339 time the access token expires
+    pub access_token_expires_at: String,
+    /// Replaces the refresh token of the request which cannot be used again
+    pub refresh_token: String,
+    /// RFC 3339 time the refresh token expires
+    pub refresh_token_expires_at: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/refresh",
+    request_body = RefreshRequest,
+    responses (
+        (status = 200, description = "New access and refresh token", body = TokenResponse),
+        (status = 401, description = "The refresh token is invalid, expired or was already used", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+    )
+)]
+pub async fn refresh_token(
+    State(state): State<AppState>,
+    Host(host): Host,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<TokenResponse>> {
+    let db = state.prisma.clone();
+    let domain = find_domain(&db, &host)
+        .await
+        .map_err(|_| Error::Unauthorized)?;
+
+    let public_key = AsymmetricPublicKey::<V4>::try_from(domain.public_key.as_str())?;
+    let token = verify_token(&public_key, &request.refresh_token)?;
+    let claims = token.payload_claims().ok_or(Error::Unauthorized)?;
+    if TokenKind::of(claims) != Some(TokenKind::Refresh) {
+        return Err(Error::Unauthorized);
+    }
+    let claim = |name: &str| {
+        claims
+            .get_claim(name)
+            .and_then(|value| value.as_str())
+            .ok_or(Error::Unauthorized)
     };
+    let token_id = claim("jti")?;
+    let handle = claim("sub")?;
+    let expires_at =
+        DateTime::parse_from_rfc3339(claim("exp")?).map_err(|_| Error::Unauthorized)?;
 
-    let domain = state
-        .prisma
-        .lock()
+    // Refresh tokens are used once, a second use means the token leaked
+    if db
+        .revoked_token()
+        .find_unique(prisma::revoked_token::UniqueWhereParam::IdEquals(
+            token_id.to_string(),
+        ))
+        .exec()
+        .await?
+        .is_some()
+    {
+        debug!("refresh token {} of {} was used again", token_id, handle);
+        return Err(Error::Unauthorized);
+    }
+    db.revoked_token()
+        .create(token_id.to_string(), expires_at, vec![])
+        .exec()
+        .await?;
+
+    let actor = find_actor(&db, handle, &domain.id)
         .await
+        .map_err(|_| Error::Unauthorized)?;
+    let secret_key = AsymmetricSecretKey::<V4>::try_from(domain.private_key.as_str())?;
+    let tokens = issue_login_tokens(
+        &actor.handle,
+        &domain.dns_name,
+        &actor.display_name,
+        &secret_key,
+    )?;
+
+    Ok(Json(TokenResponse {
+        access_token: tokens.access_token,
+        access_token_expires_at: tokens.access_expires_at.to_rfc3339(),
+        refresh_token: tokens.refresh_token,
+        refresh_token_expires_at: tokens.refresh_expires_at.to_rfc3339(),
+    }))
+}
+
+/// A long lived token for CI, the token itself is only shown once on creation.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    /// API areas the key may use like `components` or `gates`, `*` for all of them
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub revoked_at: Option<String>,
+    pub last_used_at: Option<String>,
+}
+
+impl From<prisma::api_key::Data> for ApiKey {
+    fn from(value: prisma::api_key::Data) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            scopes: value.scopes,
+            created_at: value.created_at.to_rfc3339(),
+            expires_at: value.expires_at.to_rfc3339(),
+            revoked_at: value.revoked_at.map(|t| t.to_rfc3339()),
+            last_used_at: value.last_used_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ApiKeyInput {
+    /// Unique name of the key among the keys of the actor like `ci-oi-userland`
+    pub name: String,
+    /// API areas the key may use like `components` or `gates`, `*` for all of them
+    pub scopes: Vec<String>,
+    /// Days until the key expires, at most 730
+    #[serde(default = "default_api_key_days")]
+    pub expires_in_days: i64,
+}
+
+fn default_api_key_days() -> i64 {
+    365
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CreatedApiKey {
+    pub key: ApiKey,
+    /// Bearer token of the key, it cannot be retrieved again
+    pub token: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/keys",
+    responses (
+        (status = 200, description = "API keys of the actor including revoked ones", body = Vec<ApiKey>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+    )
+)]
+pub async fn list_api_keys(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+) -> Result<Json<Vec<ApiKey>>> {
+    let db = state.prisma.clone();
+    let actor = find_actor(&db, &handle, &domain_id).await?;
+    let keys = db
+        .api_key()
+        .find_many(vec![prisma::api_key::actor_id::equals(actor.id)])
+        .order_by(prisma::api_key::created_at::order(
+            prisma_client_rust::Direction::Desc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(keys.into_iter().map(ApiKey::from).collect()))
+}
+
+fn validate_api_key(request: &ApiKeyInput) -> Result<()> {
+    if request.name.trim().is_empty() {
+        return Err(Error::InvalidApiKey(String::from("name is empty")));
+    }
+    if request.scopes.is_empty() {
+        return Err(Error::InvalidApiKey(String::from("no scopes given")));
+    }
+    if let Some(scope) = request
+        .scopes
+        .iter()
+        .find(|s| s.as_str() == "auth" || (s.as_str() != ALL_SCOPES && s.contains('/')))
+    {
+        return Err(Error::InvalidApiKey(format!("{} is not a scope", scope)));
+    }
+    if !(1..=API_KEY_MAX_DAYS).contains(&request.expires_in_days) {
+        return Err(Error::InvalidApiKey(format!(
+            "keys expire after 1 to {} days",
+            API_KEY_MAX_DAYS
+        )));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/keys",
+    request_body = ApiKeyInput,
+    responses (
+        (status = 200, description = "API key and its token", body = CreatedApiKey),
+        (status = 400, description = "Invalid name, scopes or expiry", body = ApiError),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+    )
+)]
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Json(request): Json<ApiKeyInput>,
+) -> Result<Json<CreatedApiKey>> {
+    validate_api_key(&request)?;
+    let db = state.prisma.clone();
+    let domain = db
         .domain()
-        .find_unique(prisma::domain::UniqueWhereParam::DnsNameEquals(host))
+        .find_unique(prisma::domain::UniqueWhereParam::IdEquals(
+            domain_id.clone(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NoDomainFound)?;
+    let actor = find_actor(&db, &handle, &domain_id).await?;
+
+    let expires_at = Utc::now() + TimeDelta::days(request.expires_in_days);
+    let key = db
+        .api_key()
+        .create(
+            prisma::actor::UniqueWhereParam::IdEquals(actor.id),
+            request.name,
+            expires_at.fixed_offset(),
+            vec![prisma::api_key::scopes::set(request.scopes)],
+        )
         .exec()
         .await?;
 
-    if let Some(domain) = domain {
-        let auth_config: AuthConfig = serde_json::from_value(domain.authconf)?;
+    let secret_key = AsymmetricSecretKey::<V4>::try_from(domain.private_key.as_str())?;
+    let token = issue_api_key_token(
+        &handle,
+        &domain.dns_name,
+        &key.id,
+        &key.scopes,
+        expires_at,
+        &secret_key,
+    )?;
+    let key = ApiKey::from(key);
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("api_key.create", "api_key", &key.id).after(&key)?,
+    )
+    .await?;
+
+    Ok(Json(CreatedApiKey { key, token }))
+}
 
-        Ok(Json(auth_config))
-    } else {
-        Err(Error::NoDomainFound)
+#[utoipa::path(
+    delete,
+    path = "/api/v1/auth/keys/{id}",
+    responses (
+        (status = 200, description = "API key revoked, its token is rejected from now on", body = ApiKey),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "The actor has no such key", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("api key 1")))),
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Id of the API key"),
+    )
+)]
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ApiKey>> {
+    let db = state.prisma.clone();
+    let actor = find_actor(&db, &handle, &domain_id).await?;
+    let before = db
+        .api_key()
+        .find_first(vec![
+            prisma::api_key::id::equals(id.to_string()),
+            prisma::api_key::actor_id::equals(actor.id),
+        ])
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("api key {}", id)))?;
+    if before.revoked_at.is_some() {
+        return Ok(Json(ApiKey::from(before)));
     }
+
+    let key = db
+        .api_key()
+        .update(
+            prisma::api_key::UniqueWhereParam::IdEquals(before.id.clone()),
+            vec![prisma::api_key::revoked_at::set(Some(
+                Utc::now().fixed_offset(),
+            ))],
+        )
+        .exec()
+        .await?;
+    let key = ApiKey::from(key);
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("api_key.revoke", "api_key", &key.id)
+            .before(&ApiKey::from(before))?
+            .after(&key)?,
+    )
+    .await?;
+
+    Ok(Json(key))
 }
@@ -0,0 +1,53 @@
+use crate::api::auth::Authentication;
+use crate::blob_gc::{collect_garbage, referenced_blobs};
+use crate::{AppState, Result};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new().route("/gc", post(collect_blob_garbage))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct GarbageCollectionInput {
+    /// Only report what would be removed
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// What a garbage collection of the blob store removed or would remove.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GarbageCollection {
+    pub dry_run: bool,
+    pub blobs: Vec<String>,
+    pub stale_uploads: Vec<String>,
+    pub reclaimed_bytes: i64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/blobs/gc",
+    request_body = GarbageCollectionInput,
+    responses (
+        (status = 200, description = "Unreferenced blobs that were removed", body = GarbageCollection),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized))
+    )
+)]
+async fn collect_blob_garbage(
+    State(state): State<AppState>,
+    _auth: Authentication,
+    Json(request): Json<GarbageCollectionInput>,
+) -> Result<Json<GarbageCollection>> {
+    let referenced = referenced_blobs(&state.prisma).await?;
+    let report = collect_garbage(&state.fs_operator, &referenced, request.dry_run).await?;
+
+    Ok(Json(GarbageCollection {
+        dry_run: request.dry_run,
+        blobs: report.blobs,
+        stale_uploads: report.stale_uploads,
+        reclaimed_bytes: report.reclaimed_bytes as i64,
+    }))
+}
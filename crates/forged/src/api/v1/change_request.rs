@@ -0,0 +1,884 @@
+use crate::api::auth::{Authentication, DomainScope};
+use crate::api::v1::component::{find_component, store_component_file, ComponentIdentifier};
+use crate::audit::{self, AuditEvent};
+use crate::component_helpers::find_latest_component_in_set;
+use crate::domain::find_gate_in_domain;
+use crate::live::LiveUpdate;
+use crate::message_queue::apply_component_changes;
+use crate::ownership::ensure_component_owner;
+use crate::prisma::PrismaClient;
+use crate::quality::latest_components;
+use crate::scheduler::{dispatch_jobs, enqueue_job, JobPriority};
+use crate::{prisma, AppState, Error, Result};
+use axum::extract::{DefaultBodyLimit, Multipart, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use component::{describe_recipe_changes, Recipe};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Largest upload of a component, recipe and archives together.
+const MAX_UPLOAD_SIZE: usize = 600 * 1024 * 1024;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_open_change_requests))
+        .route("/get", post(get_change_request))
+        .route("/review", post(review_change_request))
+        .route("/stage", post(stage_component))
+        .route("/staged", post(get_staged_publication))
+        .route("/promote", post(promote_staged_publication))
+        .route(
+            "/upload",
+            post(upload_change_request).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
+        )
+        .route("/apply", post(apply_change_request))
+}
+
+/// A change request waiting for review.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ChangeRequestSummary {
+    pub id: String,
+    /// Open or Draft
+    pub state: String,
+    /// Whether forged is still reading the recipes of the change request
+    pub processing: bool,
+    pub external_reference: Option<String>,
+    /// Names of the components the change request changes
+    pub components: Vec<String>,
+    pub approvals: i32,
+    pub created_at: String,
+}
+
+/// A patch of a changed component.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReviewPatch {
+    pub name: String,
+    pub content: String,
+}
+
+/// A component as the change request would leave it next to how the gate has it now.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReviewComponentChange {
+    pub name: String,
+    /// Added, Updated or Removed
+    pub kind: String,
+    pub version: String,
+    pub revision: String,
+    /// The recipe of the latest version in the gate as KDL, unset for new components
+    pub current_recipe: Option<String>,
+    /// The recipe the change request proposes as KDL
+    pub proposed_recipe: String,
+    /// What the proposed recipe changes one line each, empty for new components
+    pub summary: Vec<String>,
+    pub patches: Vec<ReviewPatch>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewVerdict {
+    Approve,
+    Comment,
+}
+
+impl From<prisma::ReviewVerdict> for ReviewVerdict {
+    fn from(value: prisma::ReviewVerdict) -> Self {
+        match value {
+            prisma::ReviewVerdict::Approve => ReviewVerdict::Approve,
+            prisma::ReviewVerdict::Comment => ReviewVerdict::Comment,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Review {
+    pub id: String,
+    /// Handle of the reviewer
+    pub actor: String,
+    pub verdict: ReviewVerdict,
+    pub body: String,
+    pub created_at: String,
+}
+
+impl From<prisma::change_request_review::Data> for Review {
+    fn from(value: prisma::change_request_review::Data) -> Self {
+        Self {
+            id: value.id,
+            actor: value.actor,
+            verdict: value.verdict.into(),
+            body: value.body,
+            created_at: value.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// A change request with everything a reviewer needs to look at.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ChangeRequestDetail {
+    pub id: String,
+    pub state: String,
+    pub processing: bool,
+    pub external_reference: Option<String>,
+    pub changes: Vec<ReviewComponentChange>,
+    /// Reviews oldest first
+    pub reviews: Vec<Review>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReviewInput {
+    pub change_request_id: String,
+    pub verdict: ReviewVerdict,
+    #[serde(default)]
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ChangeRequestIdentifier {
+    pub change_request_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct StageComponentRequest {
+    pub change_request_id: String,
+    pub component: ComponentIdentifier,
+}
+
+/// A component uploaded without a pull request, as multipart form. Archives are sent as
+/// files named like the recipe expects them.
+#[allow(dead_code)]
+#[derive(ToSchema, Debug)]
+pub struct ChangeRequestUpload {
+    /// Id of the gate the component is added to or updated in
+    pub gate: String,
+    /// The package.kdl of the component
+    pub recipe: String,
+    /// Source archive of the component, may be sent more than once
+    #[schema(value_type = String, format = Binary)]
+    pub archive: Option<Vec<u8>>,
+}
+
+/// The packages of a change request waiting in their staging repository.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct StagedPublication {
+    pub change_request_id: String,
+    /// Name of the staging repository
+    pub staging: String,
+    /// Building, Staged, Promoting, Promoted or Failed
+    pub state: String,
+    /// Builds into the staging repository that have not reported back yet
+    pub pending_builds: i32,
+    pub packages: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl From<prisma::staged_publication::Data> for StagedPublication {
+    fn from(value: prisma::staged_publication::Data) -> Self {
+        Self {
+            change_request_id: value.change_request_id,
+            staging: value.staging,
+            state: value.state.to_string(),
+            pending_builds: value.pending_builds,
+            packages: value.packages,
+            error: value.error,
+        }
+    }
+}
+
+pub(crate) async fn find_change_request(
+    db: &PrismaClient,
+    change_request_id: &str,
+    domain_id: &str,
+) -> Result<prisma::change_request::Data> {
+    db.change_request()
+        .find_first(vec![
+            prisma::change_request::id::equals(change_request_id.to_string()),
+            prisma::change_request::domain_id::equals(Some(domain_id.to_string())),
+        ])
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!(
+            "change request {}",
+            change_request_id
+        )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/change_requests/",
+    responses (
+        (status = 200, description = "Open and draft change requests of the domain, newest first", body = Vec<ChangeRequestSummary>),
+    )
+)]
+async fn list_open_change_requests(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+) -> Result<Json<Vec<ChangeRequestSummary>>> {
+    let change_requests = state
+        .prisma
+        .change_request()
+        .find_many(vec![
+            prisma::change_request::domain_id::equals(Some(domain_id)),
+            prisma::change_request::state::in_vec(vec![
+                prisma::ChangeRequestState::Open,
+                prisma::ChangeRequestState::Draft,
+            ]),
+        ])
+        .with(prisma::change_request::component_changes::fetch(vec![]))
+        .with(prisma::change_request::reviews::fetch(vec![
+            prisma::change_request_review::verdict::equals(prisma::ReviewVerdict::Approve),
+        ]))
+        .order_by(prisma::change_request::created_at::order(Direction::Desc))
+        .exec()
+        .await?;
+
+    Ok(Json(
+        change_requests
+            .into_iter()
+            .map(|change_request| ChangeRequestSummary {
+                components: change_request
+                    .component_changes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|change| change.name)
+                    .collect(),
+                approvals: change_request.reviews.unwrap_or_default().len() as i32,
+                id: change_request.id,
+                state: change_request.state.to_string(),
+                processing: change_request.processing,
+                external_reference: change_request.external_reference,
+                created_at: change_request.created_at.to_rfc3339(),
+            })
+            .collect(),
+    ))
+}
+
+/// The recipe of the latest version of the component in the gate.
+async fn current_recipe(db: &PrismaClient, gate_id: &str, name: &str) -> Result<Option<Recipe>> {
+    let components = db
+        .component()
+        .find_many(vec![
+            prisma::component::gate_id::equals(gate_id.to_string()),
+            prisma::component::name::equals(name.to_string()),
+        ])
+        .exec()
+        .await?;
+    match latest_components(components).pop() {
+        Some(component) => Ok(Some(serde_json::from_value(component.recipe)?)),
+        None => Ok(None),
+    }
+}
+
+async fn review_change(
+    db: &PrismaClient,
+    change: prisma::component_change::Data,
+) -> Result<ReviewComponentChange> {
+    let current_recipe = match &change.gate_id {
+        Some(gate_id) => current_recipe(db, gate_id, &change.name).await?,
+        None => None,
+    };
+    let proposed_recipe: Recipe = serde_json::from_value(change.recipe)?;
+    let patches: Vec<forge::PatchFile> = if change.patches.is_null() {
+        vec![]
+    } else {
+        serde_json::from_value(change.patches)?
+    };
+    let patches = patches
+        .into_iter()
+        .map(|patch| {
+            let content = base64::engine::general_purpose::STANDARD.decode(patch.content)?;
+            Ok(ReviewPatch {
+                name: patch.name,
+                content: String::from_utf8_lossy(&content).to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let summary = match &current_recipe {
+        Some(current_recipe) => describe_recipe_changes(current_recipe, &proposed_recipe),
+        None => vec![],
+    };
+
+    Ok(ReviewComponentChange {
+        name: change.name,
+        kind: change.kind.to_string(),
+        version: change.version,
+        revision: change.revision,
+        current_recipe: current_recipe.map(|recipe| recipe.to_string()),
+        proposed_recipe: proposed_recipe.to_string(),
+        summary,
+        patches,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/change_requests/get",
+    request_body = ChangeRequestIdentifier,
+    responses (
+        (status = 200, description = "The changes and reviews of the change request", body = ChangeRequestDetail),
+        (status = 404, description = "Change request not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("change request 1"))))
+    )
+)]
+async fn get_change_request(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Json(request): Json<ChangeRequestIdentifier>,
+) -> Result<Json<ChangeRequestDetail>> {
+    let db = state.prisma.clone();
+    let change_request = find_change_request(&db, &request.change_request_id, &domain_id).await?;
+    Ok(Json(change_request_detail(&db, change_request).await?))
+}
+
+async fn change_request_detail(
+    db: &PrismaClient,
+    change_request: prisma::change_request::Data,
+) -> Result<ChangeRequestDetail> {
+    let changes = db
+        .component_change()
+        .find_many(vec![prisma::component_change::change_request_id::equals(
+            change_request.id.clone(),
+        )])
+        .order_by(prisma::component_change::name::order(Direction::Asc))
+        .exec()
+        .await?;
+    let mut review_changes = vec![];
+    for change in changes {
+        review_changes.push(review_change(db, change).await?);
+    }
+
+    let reviews = db
+        .change_request_review()
+        .find_many(vec![
+            prisma::change_request_review::change_request_id::equals(change_request.id.clone()),
+        ])
+        .order_by(prisma::change_request_review::created_at::order(
+            Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(ChangeRequestDetail {
+        id: change_request.id,
+        state: change_request.state.to_string(),
+        processing: change_request.processing,
+        external_reference: change_request.external_reference,
+        changes: review_changes,
+        reviews: reviews.into_iter().map(Review::from).collect(),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/change_requests/review",
+    request_body = ReviewInput,
+    responses (
+        (status = 200, description = "Review recorded", body = Review),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "Only maintainers of the changed components and gate admins may approve", body = ApiError),
+        (status = 404, description = "Change request not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("change request 1"))))
+    )
+)]
+async fn review_change_request(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Json(request): Json<ReviewInput>,
+) -> Result<Json<Review>> {
+    let db = state.prisma.clone();
+    let change_request = find_change_request(&db, &request.change_request_id, &domain_id).await?;
+
+    let verdict = match request.verdict {
+        ReviewVerdict::Approve => {
+            // Everyone may comment but approvals count for the owners of the changes only
+            let changes = db
+                .component_change()
+                .find_many(vec![prisma::component_change::change_request_id::equals(
+                    change_request.id.clone(),
+                )])
+                .exec()
+                .await?;
+            for change in changes {
+                if let Some(gate_id) = &change.gate_id {
+                    ensure_component_owner(&db, gate_id, &change.name, &handle, &domain_id).await?;
+                }
+            }
+            prisma::ReviewVerdict::Approve
+        }
+        ReviewVerdict::Comment => prisma::ReviewVerdict::Comment,
+    };
+
+    let review = db
+        .change_request_review()
+        .create(
+            prisma::change_request::UniqueWhereParam::IdEquals(change_request.id.clone()),
+            handle.clone(),
+            verdict,
+            request.body,
+            vec![],
+        )
+        .exec()
+        .await?;
+    let review = Review::from(review);
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new(
+            "change_request.review",
+            "change_request",
+            &change_request.id,
+        )
+        .after(&review)?,
+    )
+    .await?;
+
+    Ok(Json(review))
+}
+
+async fn find_staged_publication(
+    db: &PrismaClient,
+    change_request_id: &str,
+) -> Result<prisma::staged_publication::Data> {
+    db.staged_publication()
+        .find_unique(
+            prisma::staged_publication::UniqueWhereParam::ChangeRequestIdEquals(
+                change_request_id.to_string(),
+            ),
+        )
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!(
+            "staged packages of change request {}",
+            change_request_id
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/change_requests/stage",
+    request_body = StageComponentRequest,
+    responses (
+        (status = 200, description = "Build into the staging repository of the change request scheduled", body = StagedPublication),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Change request or component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1")))),
+        (status = 409, description = "The staged packages are already promoted", body = ApiError)
+    )
+)]
+async fn stage_component(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Json(request): Json<StageComponentRequest>,
+) -> Result<Json<StagedPublication>> {
+    let db = state.prisma.clone();
+    find_change_request(&db, &request.change_request_id, &domain_id).await?;
+    let component = find_component(&db, &request.component, &domain_id).await?;
+
+    let staging = forge::staging_repository_name(&request.change_request_id);
+    let existing = db
+        .staged_publication()
+        .find_unique(
+            prisma::staged_publication::UniqueWhereParam::ChangeRequestIdEquals(
+                request.change_request_id.clone(),
+            ),
+        )
+        .exec()
+        .await?;
+    if let Some(existing) = &existing {
+        if matches!(
+            existing.state,
+            prisma::StagedPublicationState::Promoting | prisma::StagedPublicationState::Promoted
+        ) {
+            return Err(Error::NotPromotable(
+                request.change_request_id,
+                String::from("its packages are already promoted"),
+            ));
+        }
+    }
+
+    let publication = db
+        .staged_publication()
+        .upsert(
+            prisma::staged_publication::UniqueWhereParam::ChangeRequestIdEquals(
+                request.change_request_id.clone(),
+            ),
+            prisma::staged_publication::create(
+                prisma::change_request::UniqueWhereParam::IdEquals(
+                    request.change_request_id.clone(),
+                ),
+                staging.clone(),
+                vec![prisma::staged_publication::pending_builds::set(1)],
+            ),
+            vec![
+                prisma::staged_publication::pending_builds::increment(1),
+                prisma::staged_publication::state::set(prisma::StagedPublicationState::Building),
+                prisma::staged_publication::error::set(None),
+            ],
+        )
+        .exec()
+        .await?;
+
+    let job = forge::Job::BuildStoredComponent {
+        component_id: forge::ComponentId {
+            name: component.name,
+            version: component.version,
+            revision: component.revision,
+            gate_id: component
+                .gate_id
+                .parse()
+                .map_err(|_| Error::NoIdFoundINGate(component.gate_id.clone()))?,
+        },
+        staging: Some(staging),
+        bootstrap: false,
+    };
+    enqueue_job(&db, &job, JobPriority::Normal).await?;
+    state.github_status.job_queued(&db, &job).await;
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    dispatch_jobs(&db, &channel, &state.job_inbox).await?;
+
+    Ok(Json(publication.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/change_requests/staged",
+    request_body = ChangeRequestIdentifier,
+    responses (
+        (status = 200, description = "The staged packages of the change request", body = StagedPublication),
+        (status = 404, description = "Change request has no staged packages", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    )
+)]
+async fn get_staged_publication(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Json(request): Json<ChangeRequestIdentifier>,
+) -> Result<Json<StagedPublication>> {
+    let db = state.prisma.clone();
+    find_change_request(&db, &request.change_request_id, &domain_id).await?;
+    Ok(Json(
+        find_staged_publication(&db, &request.change_request_id)
+            .await?
+            .into(),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/change_requests/promote",
+    request_body = ChangeRequestIdentifier,
+    responses (
+        (status = 200, description = "Promotion of the staged packages scheduled", body = StagedPublication),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Change request has no staged packages", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1")))),
+        (status = 409, description = "The change request is not applied or its builds did not all succeed", body = ApiError)
+    )
+)]
+async fn promote_staged_publication(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Json(request): Json<ChangeRequestIdentifier>,
+) -> Result<Json<StagedPublication>> {
+    let db = state.prisma.clone();
+    let change_request = find_change_request(&db, &request.change_request_id, &domain_id).await?;
+    let publication = find_staged_publication(&db, &request.change_request_id).await?;
+
+    // Only promote what belongs to an applied change whose builds all succeeded so the
+    // public repository never holds part of a change
+    let refusal = if change_request.state != prisma::ChangeRequestState::Applied {
+        Some(String::from("the change request is not applied"))
+    } else if publication.pending_builds > 0 {
+        Some(format!(
+            "{} builds are still running",
+            publication.pending_builds
+        ))
+    } else if publication.state != prisma::StagedPublicationState::Staged {
+        Some(format!("its packages are {:?}", publication.state))
+    } else if publication.packages.is_empty() {
+        Some(String::from("no packages were staged"))
+    } else {
+        None
+    };
+    if let Some(reason) = refusal {
+        return Err(Error::NotPromotable(request.change_request_id, reason));
+    }
+
+    let publication = db
+        .staged_publication()
+        .update(
+            prisma::staged_publication::UniqueWhereParam::IdEquals(publication.id),
+            vec![prisma::staged_publication::state::set(
+                prisma::StagedPublicationState::Promoting,
+            )],
+        )
+        .exec()
+        .await?;
+
+    let job = forge::Job::PromoteStagedPackages {
+        change_request_id: request.change_request_id,
+        staging: publication.staging.clone(),
+    };
+    enqueue_job(&db, &job, JobPriority::Normal).await?;
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    dispatch_jobs(&db, &channel, &state.job_inbox).await?;
+
+    Ok(Json(publication.into()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/change_requests/upload",
+    request_body(content = ChangeRequestUpload, description = "The gate, the recipe and the archives of the component", content_type = "multipart/form-data"),
+    responses (
+        (status = 200, description = "Change request opened with the uploaded component", body = ChangeRequestDetail),
+        (status = 400, description = "The recipe is invalid or a field is missing", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("upload is invalid: the recipe is missing")))),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "Only maintainers of the component and gate admins may upload it", body = ApiError),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("gate 1"))))
+    )
+)]
+async fn upload_change_request(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    mut multipart: Multipart,
+) -> Result<Json<ChangeRequestDetail>> {
+    let mut gate_id = None;
+    let mut document = None;
+    let mut archives = vec![];
+    while let Some(field) = multipart.next_field().await? {
+        match field.name().map(str::to_string).as_deref() {
+            Some("gate") => gate_id = Some(field.text().await?),
+            Some("recipe") => document = Some(field.text().await?),
+            Some("archive") => {
+                let name = field
+                    .file_name()
+                    .filter(|name| !name.contains('/') && !name.starts_with('.'))
+                    .map(str::to_string)
+                    .ok_or(Error::InvalidUpload(String::from(
+                        "archives need a file name without directories",
+                    )))?;
+                archives.push((name, field.bytes().await?));
+            }
+            _ => return Err(Error::InvalidMultipartRequest),
+        }
+    }
+    let gate_id = gate_id.ok_or(Error::InvalidUpload(String::from("the gate is missing")))?;
+    let document =
+        document.ok_or(Error::InvalidUpload(String::from("the recipe is missing")))?;
+
+    let recipe = Recipe::parse("package.kdl", &document)
+        .map_err(|e| Error::InvalidUpload(format!("package.kdl could not be read: {}", e)))?;
+    let violations = recipe.validate();
+    if !violations.is_empty() {
+        return Err(Error::InvalidRecipe(recipe.name.clone(), violations));
+    }
+    let name = recipe.name.clone();
+    let version = recipe
+        .version
+        .clone()
+        .ok_or(Error::NoVersionFoundInRecipe(name.clone()))?;
+    let revision = recipe.revision.clone().unwrap_or(String::from("0"));
+    if recipe.project_url.is_none() {
+        return Err(Error::NoProjectUrlFoundInRecipe(name));
+    }
+
+    {
+        let db = state.prisma.clone();
+        find_gate_in_domain(&db, &gate_id, &domain_id).await?;
+        ensure_component_owner(&db, &gate_id, &name, &handle, &domain_id).await?;
+    }
+
+    let mut stored = vec![];
+    for (file_name, content) in archives {
+        let file = store_component_file(
+            &state.fs_operator,
+            forge::ComponentFileKind::Archive,
+            &name,
+            file_name,
+            content.to_vec(),
+        )
+        .await?;
+        stored.push(file.to_string());
+    }
+
+    let db = state.prisma.clone();
+    let components = db
+        .component()
+        .find_many(vec![
+            prisma::component::gate_id::equals(gate_id.clone()),
+            prisma::component::name::equals(name.clone()),
+        ])
+        .exec()
+        .await?;
+    if components
+        .iter()
+        .any(|c| c.version == version && c.revision == revision)
+    {
+        return Err(Error::InvalidUpload(format!(
+            "{} is in the gate already, raise the version or revision",
+            recipe
+        )));
+    }
+
+    // Diffed against the gate like the recipes read from a pull request
+    let mut change_params = vec![
+        prisma::component_change::SetParam::ConnectGate(
+            prisma::gate::UniqueWhereParam::IdEquals(gate_id.clone()),
+        ),
+        prisma::component_change::archives::set(stored),
+    ];
+    let (kind, diff) = if components.is_empty() {
+        (prisma::ComponentChangeKind::Added, serde_json::Value::Null)
+    } else {
+        let current = find_latest_component_in_set(components)?;
+        let current_recipe: Recipe = serde_json::from_value(current.recipe)?;
+        change_params.push(prisma::component_change::SetParam::ConnectComponent(
+            prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                current.name,
+                current.gate_id,
+                current.version,
+                current.revision,
+            ),
+        ));
+        (
+            prisma::ComponentChangeKind::Updated,
+            serde_json::to_value(current_recipe.diff(&recipe))?,
+        )
+    };
+
+    let change_request = db
+        .change_request()
+        .create(
+            uuid::Uuid::new_v4().to_string(),
+            vec![
+                prisma::change_request::SetParam::ConnectDomain(
+                    prisma::domain::UniqueWhereParam::IdEquals(domain_id.clone()),
+                ),
+                prisma::change_request::SetParam::SetState(prisma::ChangeRequestState::Open),
+            ],
+        )
+        .exec()
+        .await?;
+    db.component_change()
+        .create(
+            kind,
+            diff,
+            name,
+            serde_json::to_value(&recipe)?,
+            version,
+            revision,
+            serde_json::Value::Null,
+            prisma::change_request::UniqueWhereParam::IdEquals(change_request.id.clone()),
+            change_params,
+        )
+        .exec()
+        .await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("change_request.upload", "change_request", &change_request.id)
+            .after(&change_request)?,
+    )
+    .await?;
+    state
+        .updates
+        .send(&domain_id, LiveUpdate::change_request(&change_request));
+
+    Ok(Json(change_request_detail(&db, change_request).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/change_requests/apply",
+    request_body = ChangeRequestIdentifier,
+    responses (
+        (status = 200, description = "Changes merged into the gate and dependents rebuilt", body = ChangeRequestDetail),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "Only maintainers of the changed components and gate admins may apply", body = ApiError),
+        (status = 404, description = "Change request not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("change request 1")))),
+        (status = 409, description = "The change request has a pull request, is not open or has no approval", body = ApiError)
+    )
+)]
+async fn apply_change_request(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Json(request): Json<ChangeRequestIdentifier>,
+) -> Result<Json<ChangeRequestDetail>> {
+    let db = state.prisma.clone();
+    let change_request = find_change_request(&db, &request.change_request_id, &domain_id).await?;
+
+    let approvals = db
+        .change_request_review()
+        .count(vec![
+            prisma::change_request_review::change_request_id::equals(change_request.id.clone()),
+            prisma::change_request_review::verdict::equals(prisma::ReviewVerdict::Approve),
+        ])
+        .exec()
+        .await?;
+    // Change requests of pull requests are applied when the pull request is merged
+    let refusal = if change_request.external_reference.is_some() {
+        Some(String::from("it is applied by merging its pull request"))
+    } else if change_request.state != prisma::ChangeRequestState::Open {
+        Some(format!("it is {}", change_request.state.to_string()))
+    } else if approvals == 0 {
+        Some(String::from("nobody approved it"))
+    } else {
+        None
+    };
+    if let Some(reason) = refusal {
+        return Err(Error::NotApplicable(request.change_request_id, reason));
+    }
+
+    let changes = db
+        .component_change()
+        .find_many(vec![
+            prisma::component_change::change_request_id::equals(change_request.id.clone()),
+            prisma::component_change::applied::equals(false),
+        ])
+        .exec()
+        .await?;
+    for change in &changes {
+        if let Some(gate_id) = &change.gate_id {
+            ensure_component_owner(&db, gate_id, &change.name, &handle, &domain_id).await?;
+        }
+    }
+
+    let applied = db
+        .change_request()
+        .update(
+            prisma::change_request::UniqueWhereParam::IdEquals(change_request.id.clone()),
+            vec![prisma::change_request::SetParam::SetState(
+                prisma::ChangeRequestState::Applied,
+            )],
+        )
+        .exec()
+        .await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("change_request.apply", "change_request", &applied.id)
+            .before(&change_request)?
+            .after(&applied)?,
+    )
+    .await?;
+    state
+        .updates
+        .send(&domain_id, LiveUpdate::change_request(&applied));
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    apply_component_changes(&db, &channel, &state.job_inbox, &applied.id, changes).await?;
+
+    Ok(Json(change_request_detail(&db, applied).await?))
+}
@@ -1,24 +1,44 @@
-use crate::api::auth::Authentication;
+use crate::api::auth::{Authentication, DomainScope};
+use crate::audit::{self, component_object_id, AuditEvent};
+use crate::component_helpers::recipe_search_params;
+use crate::ownership::{ensure_component_owner, find_actor, maintainer_names};
+use crate::domain::{component_scope, find_gate_in_domain};
+use crate::prisma::{PrismaClient, QueryMode};
+use crate::api::v1::gate::{enqueue_dependents_rebuild, GateRebuild};
+use crate::api::v1::{RebuildParams, SbomParams};
+use crate::scheduler::{dispatch_jobs, enqueue_job};
 use crate::{prisma, AppState, Error, Result};
 use axum::body::Bytes;
-use axum::extract::{DefaultBodyLimit, Path, State};
-use axum::routing::post;
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::routing::{get, post};
 use axum::{Json, Router};
+use base64::Engine;
 use futures::AsyncWriteExt;
-use component::{PackageMeta, Recipe};
+use prisma_client_rust::operator::{and, or};
+use component::{render_sbom, PackageMeta, Recipe, SbomEntry};
 use serde::{Deserialize, Serialize};
 use sha3::Digest;
-use tracing::trace;
+use tracing::{debug, trace};
 use url::Url;
 use utoipa::ToSchema;
 
 pub fn get_router() -> Router<AppState> {
     Router::new()
         .route("/list", post(list_components))
+        .route("/search", post(search_components))
+        .route("/owned", get(list_owned_components))
+        .route("/providers", post(find_library_providers))
         .route("/get", post(get_component))
         .route("/", post(create_component))
         .route("/import", post(import_component))
         .route("/upload/:kind", post(upload_to_component))
+        .route("/bundle", post(get_component_bundle))
+        .route("/file", post(get_component_file))
+        .route("/vulnerabilities", post(list_component_vulnerabilities))
+        .route("/provenance", post(list_component_provenance))
+        .route("/sbom", post(get_component_sbom))
+        .route("/rebuild", post(rebuild_component))
+        .route("/rebuild/dependents", post(rebuild_component_dependents))
         .layer(DefaultBodyLimit::max(629145600))
 }
 
@@ -36,13 +56,38 @@ pub struct Component {
     pub revision: String,
     pub anitya_id: Option<String>,
     pub repology_id: Option<String>,
+    /// Package URL without version advisories are looked up on osv.dev with
+    pub purl: Option<String>,
+    /// CPE up to the product advisories are looked up in the NVD with
+    pub cpe: Option<String>,
     pub project_url: String,
     pub gate_id: String,
     pub recipe: Recipe,
     pub packages: PackageMeta,
+    /// Standard build steps the recipe skips together with their justification.
+    pub warnings: Vec<String>,
+    /// Digest of the recipe and its patches, see `pkgdev build --verify-origin`
+    pub recipe_digest: String,
 }
 
 pub fn component_from_database(component: prisma::component::Data) -> Result<Component> {
+    let recipe: Recipe = serde_json::from_value(component.recipe)?;
+    let warnings = recipe
+        .skipped_steps
+        .iter()
+        .map(|skip| format!("skips {}", skip))
+        .collect();
+    let patches: Vec<forge::PatchFile> = if component.patches.is_null() {
+        vec![]
+    } else {
+        serde_json::from_value(component.patches)?
+    };
+    let patches = patches
+        .into_iter()
+        .map(|p| Ok((p.name, base64::engine::general_purpose::STANDARD.decode(p.content)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let recipe_digest =
+        recipe.digest(patches.iter().map(|(name, content)| (name.as_str(), content.as_slice())));
     let r = Component {
         name: component.name,
         version: component.version,
@@ -50,9 +95,13 @@ pub fn component_from_database(component: prisma::component::Data) -> Result<Com
         revision: component.revision,
         anitya_id: component.anitya_id.clone(),
         repology_id: component.repology_id.clone(),
+        purl: component.purl.clone(),
+        cpe: component.cpe.clone(),
         project_url: component.project_url,
-        recipe: serde_json::from_value(component.recipe)?,
+        recipe,
         packages: serde_json::from_value(component.packages)?,
+        warnings,
+        recipe_digest,
     };
     Ok(r)
 }
@@ -68,21 +117,19 @@ pub fn component_from_database(component: prisma::component::Data) -> Result<Com
 )]
 async fn get_component(
     State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
     Json(request): Json<GetComponentRequest>,
 ) -> Result<Json<Component>> {
     let component = state
         .prisma
-        .lock()
-        .await
         .component()
-        .find_unique(
-            prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
-                request.name,
-                request.version,
-                request.revision,
-                request.gate_id,
-            ),
-        )
+        .find_first(vec![
+            prisma::component::name::equals(request.name),
+            prisma::component::version::equals(request.version),
+            prisma::component::revision::equals(request.revision),
+            prisma::component::gate_id::equals(request.gate_id),
+            component_scope(&domain_id),
+        ])
         .exec()
         .await?;
 
@@ -112,9 +159,13 @@ pub struct ListComponentRequest {
 )]
 async fn list_components(
     State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
     Json(request): Json<ListComponentRequest>,
 ) -> Result<Json<Vec<Component>>> {
-    let mut filter = vec![prisma::component::name::equals(request.name)];
+    let mut filter = vec![
+        prisma::component::name::equals(request.name),
+        component_scope(&domain_id),
+    ];
 
     if let Some(version) = request.version {
         filter.push(prisma::component::version::equals(version))
@@ -130,8 +181,6 @@ async fn list_components(
 
     let components = state
         .prisma
-        .lock()
-        .await
         .component()
         .find_many(filter)
         .exec()
@@ -145,13 +194,236 @@ async fn list_components(
     Ok(Json(components))
 }
 
+/// Filters of a component search, every given one has to match.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct ComponentSearchRequest {
+    /// Text to find in the name or summary of the component, case insensitive
+    pub query: Option<String>,
+    pub maintainer: Option<String>,
+    /// License as the recipe declares it
+    pub license: Option<String>,
+    /// Classification or the start of one like `System/Libraries`
+    pub classification: Option<String>,
+    pub gate_id: Option<String>,
+    /// Part of the url of the upstream project
+    pub upstream_url: Option<String>,
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(default = "default_search_limit")]
+    pub limit: i64,
+}
+
+fn default_search_limit() -> i64 {
+    20
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/search",
+    request_body = ComponentSearchRequest,
+    responses (
+        (status = 200, description = "Components matching every filter ordered by name", body = Vec<Component>),
+    )
+)]
+async fn search_components(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Json(request): Json<ComponentSearchRequest>,
+) -> Result<Json<Vec<Component>>> {
+    let mut filter = vec![component_scope(&domain_id)];
+
+    // Case insensitive substring matches are served by the trigram indexes of the columns
+    if let Some(query) = request.query {
+        filter.push(or(vec![
+            and(vec![
+                prisma::component::name::contains(query.clone()),
+                prisma::component::name::mode(QueryMode::Insensitive),
+            ]),
+            and(vec![
+                prisma::component::summary::contains(query),
+                prisma::component::summary::mode(QueryMode::Insensitive),
+            ]),
+        ]))
+    }
+
+    if let Some(maintainer) = request.maintainer {
+        filter.push(prisma::component::maintainers::has(maintainer))
+    }
+
+    if let Some(license) = request.license {
+        filter.push(prisma::component::license::equals(Some(license)))
+    }
+
+    if let Some(classification) = request.classification {
+        filter.push(prisma::component::classification::starts_with(
+            classification,
+        ))
+    }
+
+    if let Some(gate_id) = request.gate_id {
+        filter.push(prisma::component::gate_id::equals(gate_id))
+    }
+
+    if let Some(upstream_url) = request.upstream_url {
+        filter.push(and(vec![
+            prisma::component::project_url::contains(upstream_url),
+            prisma::component::project_url::mode(QueryMode::Insensitive),
+        ]))
+    }
+
+    let components = state
+        .prisma
+        .component()
+        .find_many(filter)
+        .order_by(prisma::component::name::order(
+            prisma_client_rust::Direction::Asc,
+        ))
+        .skip(request.offset)
+        .take(request.limit)
+        .exec()
+        .await?;
+
+    let components = components
+        .into_iter()
+        .filter_map(|component| component_from_database(component).ok())
+        .collect();
+
+    Ok(Json(components))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/components/owned",
+    responses (
+        (status = 200, description = "Components the caller maintains ordered by name", body = Vec<Component>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+    )
+)]
+async fn list_owned_components(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+) -> Result<Json<Vec<Component>>> {
+    let db = state.prisma.clone();
+    let actor = find_actor(&db, &handle, &domain_id).await?;
+    let names = maintainer_names(&db, &actor).await?;
+
+    let components = db
+        .component()
+        .find_many(vec![
+            component_scope(&domain_id),
+            prisma::component::maintainers::has_some(names),
+        ])
+        .order_by(prisma::component::name::order(
+            prisma_client_rust::Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    let components = components
+        .into_iter()
+        .filter_map(|component| component_from_database(component).ok())
+        .collect();
+
+    Ok(Json(components))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct FindLibraryProvidersRequest {
+    /// SONAMEs of the libraries to look for
+    libraries: Vec<String>,
+    gate_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct LibraryProvider {
+    pub library: String,
+    pub component: String,
+    pub version: String,
+    pub gate_id: String,
+    pub fmris: Vec<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/providers",
+    request_body = FindLibraryProvidersRequest,
+    responses (
+        (status = 200, description = "The components delivering the libraries, libraries no component delivers are left out", body = Vec<LibraryProvider>),
+    )
+)]
+async fn find_library_providers(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Json(request): Json<FindLibraryProvidersRequest>,
+) -> Result<Json<Vec<LibraryProvider>>> {
+    let mut filter = vec![component_scope(&domain_id)];
+
+    if let Some(gate_id) = request.gate_id {
+        filter.push(prisma::component::gate_id::equals(gate_id))
+    }
+
+    // The libraries are part of the package metadata json which cannot be filtered on
+    let components = state
+        .prisma
+        .component()
+        .find_many(filter)
+        .exec()
+        .await?;
+
+    let mut providers = vec![];
+    for component in components {
+        let Ok(packages) = serde_json::from_value::<PackageMeta>(component.packages) else {
+            continue;
+        };
+        for library in request.libraries.iter() {
+            if packages.libraries().contains(library) {
+                providers.push(LibraryProvider {
+                    library: library.clone(),
+                    component: component.name.clone(),
+                    version: component.version.clone(),
+                    gate_id: component.gate_id.clone(),
+                    fmris: packages.fmris().to_vec(),
+                });
+            }
+        }
+    }
+
+    Ok(Json(providers))
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct ComponentInput {
     pub recipe: Recipe,
     pub packages: PackageMeta,
     pub anitya_id: Option<String>,
     pub repology_id: Option<String>,
+    pub purl: Option<String>,
+    pub cpe: Option<String>,
     pub gate: String,
+    /// Topics of the upstream project, the classification is suggested from them when
+    /// the recipe has none
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+impl ComponentInput {
+    /// Fill in the classification the topics suggest if the recipe has none.
+    fn classify(&mut self) {
+        if self.recipe.classification.is_some() {
+            return;
+        }
+        if let Some(suggestion) = component::suggest_classification(&self.topics) {
+            debug!(
+                "classified {} as {} because of {}",
+                self.recipe.name,
+                suggestion.classification,
+                suggestion.matched.join(", ")
+            );
+            self.recipe.classification = Some(suggestion.classification);
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -162,28 +434,393 @@ pub struct ComponentIdentifier {
     pub gate_id: String,
 }
 
+pub(crate) async fn find_component(
+    db: &PrismaClient,
+    identifier: &ComponentIdentifier,
+    domain_id: &str,
+) -> Result<prisma::component::Data> {
+    db.component()
+        .find_first(vec![
+            prisma::component::name::equals(identifier.name.clone()),
+            prisma::component::gate_id::equals(identifier.gate_id.clone()),
+            prisma::component::version::equals(identifier.version.clone()),
+            prisma::component::revision::equals(identifier.revision.clone()),
+            component_scope(domain_id),
+        ])
+        .exec()
+        .await?
+        .ok_or(Error::NoComponentFound)
+}
+
+/// Everything a worker needs to build a component without its source repository.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ComponentBundle {
+    pub recipe: Recipe,
+    pub packages: PackageMeta,
+    /// The patches of the recipe with base64 encoded content
+    #[schema(value_type = Vec<Object>)]
+    pub patches: Vec<forge::PatchFile>,
+    /// The archives and scripts stored for the component
+    pub files: Vec<String>,
+    /// Boilerplate and header the publisher of the gate starts its IPS manifests with
+    #[schema(value_type = Option<Object>)]
+    pub manifest_template: Option<gate::ManifestTemplate>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/bundle",
+    request_body = ComponentIdentifier,
+    responses (
+        (status = 200, description = "Successfully got the component bundle", body = ComponentBundle),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    )
+)]
+async fn get_component_bundle(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Json(request): Json<ComponentIdentifier>,
+) -> Result<Json<ComponentBundle>> {
+    let component = find_component(&state.prisma, &request, &domain_id).await?;
+    let manifest_template = state
+        .prisma
+        .publisher()
+        .find_first(vec![prisma::publisher::gate::some(vec![
+            prisma::gate::id::equals(component.gate_id.clone()),
+        ])])
+        .exec()
+        .await?
+        .and_then(|publisher| publisher.manifest_template)
+        .map(serde_json::from_value)
+        .transpose()?;
+
+    let patches = if component.patches.is_null() {
+        vec![]
+    } else {
+        serde_json::from_value(component.patches)?
+    };
+    let mut files = component.archives;
+    files.extend(component.scripts);
+
+    Ok(Json(ComponentBundle {
+        recipe: serde_json::from_value(component.recipe)?,
+        packages: serde_json::from_value(component.packages)?,
+        patches,
+        files,
+        manifest_template,
+    }))
+}
+
+/// A security advisory that affects a component version.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Vulnerability {
+    /// Id of the advisory at its source like GHSA-xxxx-xxxx-xxxx or CVE-2024-1234
+    pub advisory_id: String,
+    /// Where the advisory was found, osv or nvd
+    pub source: String,
+    pub aliases: Vec<String>,
+    pub summary: Option<String>,
+    pub severity: Option<String>,
+    /// First version the advisory lists as fixed
+    pub fixed_version: Option<String>,
+    pub url: Option<String>,
+    pub detected_at: String,
+}
+
+impl From<prisma::vulnerability::Data> for Vulnerability {
+    fn from(value: prisma::vulnerability::Data) -> Self {
+        Self {
+            advisory_id: value.advisory_id,
+            source: value.source,
+            aliases: value.aliases,
+            summary: value.summary,
+            severity: value.severity,
+            fixed_version: value.fixed_version,
+            url: value.url,
+            detected_at: value.detected_at.to_rfc3339(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/vulnerabilities",
+    request_body = ComponentIdentifier,
+    responses (
+        (status = 200, description = "Advisories affecting the component as of the last check", body = Vec<Vulnerability>),
+        (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    )
+)]
+async fn list_component_vulnerabilities(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Json(request): Json<ComponentIdentifier>,
+) -> Result<Json<Vec<Vulnerability>>> {
+    let db = state.prisma.clone();
+    let component = find_component(&db, &request, &domain_id).await?;
+
+    let vulnerabilities = db
+        .vulnerability()
+        .find_many(vec![
+            prisma::vulnerability::component_name::equals(component.name),
+            prisma::vulnerability::gate_id::equals(component.gate_id),
+            prisma::vulnerability::component_version::equals(component.version),
+            prisma::vulnerability::component_revision::equals(component.revision),
+        ])
+        .order_by(prisma::vulnerability::advisory_id::order(
+            prisma_client_rust::Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(
+        vulnerabilities.into_iter().map(Vulnerability::from).collect(),
+    ))
+}
+
+/// SLSA provenance of the packages of one build of a component.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Provenance {
+    pub id: String,
+    pub staging: Option<String>,
+    pub bootstrap: bool,
+    /// The in-toto statement, verify the signature against it byte for byte
+    pub document: String,
+    /// Detached signify signature by the publisher of the gate, see
+    /// `/api/v1/publishers/{name}/signing_key.pub`
+    pub signature: Option<String>,
+    pub created_at: String,
+}
+
+impl From<prisma::provenance::Data> for Provenance {
+    fn from(value: prisma::provenance::Data) -> Self {
+        Self {
+            id: value.id,
+            staging: value.staging,
+            bootstrap: value.bootstrap,
+            document: value.document,
+            signature: value.signature,
+            created_at: value.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/provenance",
+    request_body = ComponentIdentifier,
+    responses (
+        (status = 200, description = "Provenance of every build of the component, newest first", body = Vec<Provenance>),
+        (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    )
+)]
+async fn list_component_provenance(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Json(request): Json<ComponentIdentifier>,
+) -> Result<Json<Vec<Provenance>>> {
+    let db = state.prisma.clone();
+    let component = find_component(&db, &request, &domain_id).await?;
+
+    let provenance = db
+        .provenance()
+        .find_many(vec![
+            prisma::provenance::component_name::equals(component.name),
+            prisma::provenance::gate_id::equals(component.gate_id),
+            prisma::provenance::component_version::equals(component.version),
+            prisma::provenance::component_revision::equals(component.revision),
+        ])
+        .order_by(prisma::provenance::created_at::order(
+            prisma_client_rust::Direction::Desc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(provenance.into_iter().map(Provenance::from).collect()))
+}
+
+/// The recipe and packages of a stored component to describe in a bill of materials.
+/// Forge does not keep the file manifests of builds, so no files are listed.
+pub(crate) fn sbom_entry(component: prisma::component::Data) -> Result<SbomEntry> {
+    Ok(SbomEntry {
+        recipe: serde_json::from_value(component.recipe)?,
+        packages: serde_json::from_value(component.packages)?,
+        files: vec![],
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/sbom",
+    request_body = ComponentIdentifier,
+    responses (
+        (status = 200, description = "CycloneDX or SPDX document of the component", body = Object),
+        (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        SbomParams,
+    )
+)]
+async fn get_component_sbom(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Query(params): Query<SbomParams>,
+    Json(request): Json<ComponentIdentifier>,
+) -> Result<Json<serde_json::Value>> {
+    let component = find_component(&state.prisma, &request, &domain_id).await?;
+
+    let entry = sbom_entry(component)?;
+    Ok(Json(render_sbom(
+        params.format,
+        &format!("{}@{}", request.name, request.version),
+        &chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        &[entry],
+    )))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GetComponentFileRequest {
+    pub identifier: ComponentIdentifier,
+    pub file: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/file",
+    request_body = GetComponentFileRequest,
+    responses (
+        (status = 200, description = "The content of the file", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Component or file not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    )
+)]
+async fn get_component_file(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Json(request): Json<GetComponentFileRequest>,
+) -> Result<Vec<u8>> {
+    let component =
+        find_component(&state.prisma, &request.identifier, &domain_id).await?;
+
+    // Only hand out files that belong to a component of the domain
+    if !component.archives.contains(&request.file) && !component.scripts.contains(&request.file) {
+        return Err(Error::NotFound(format!("file {}", request.file)));
+    }
+
+    Ok(state.fs_operator.read(&request.file).await?.to_vec())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/rebuild",
+    request_body = ComponentIdentifier,
+    responses (
+        (status = 200, description = "Rebuild scheduled"),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(RebuildParams)
+)]
+async fn rebuild_component(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Query(params): Query<RebuildParams>,
+    Json(request): Json<ComponentIdentifier>,
+) -> Result<()> {
+    let db = state.prisma.clone();
+    let component = find_component(&db, &request, &domain_id).await?;
+
+    let job = forge::Job::BuildStoredComponent {
+        component_id: forge::ComponentId {
+            name: component.name,
+            version: component.version,
+            revision: component.revision,
+            gate_id: component
+                .gate_id
+                .parse()
+                .map_err(|_| Error::NoIdFoundINGate(component.gate_id.clone()))?,
+        },
+        staging: None,
+        bootstrap: false,
+    };
+    enqueue_job(&db, &job, params.priority).await?;
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    dispatch_jobs(&db, &channel, &state.job_inbox).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/components/rebuild/dependents",
+    request_body = ComponentIdentifier,
+    responses (
+        (status = 200, description = "Rebuild of the latest version of the component and of every component of the gate depending on it scheduled", body = GateRebuild),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1")))),
+        (status = 409, description = "The dependents have a build dependency cycle no bootstrap section breaks", body = ApiError)
+    ),
+    params(RebuildParams)
+)]
+async fn rebuild_component_dependents(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Query(params): Query<RebuildParams>,
+    Json(request): Json<ComponentIdentifier>,
+) -> Result<Json<GateRebuild>> {
+    let db = state.prisma.clone();
+    let component = find_component(&db, &request, &domain_id).await?;
+    let builds =
+        enqueue_dependents_rebuild(&db, &component.gate_id, &[component.name], params.priority)
+            .await?;
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    dispatch_jobs(&db, &channel, &state.job_inbox).await?;
+
+    Ok(Json(GateRebuild { builds }))
+}
+
 #[utoipa::path(
     post,
     path = "/api/v1/components/",
     request_body = ComponentInput,
     responses (
         (status = 200, description = "Successfully retrieved component info", body = Component),
+        (status = 400, description = "Recipe failed validation", body = ApiError),
         (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
         (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
     )
 )]
 async fn create_component(
     State(state): State<AppState>,
-    Authentication { .. }: Authentication,
-    Json(request): Json<ComponentInput>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Json(mut request): Json<ComponentInput>,
 ) -> Result<Json<Component>> {
+    request.classify();
+    let violations = request.recipe.validate();
+    if !violations.is_empty() {
+        return Err(Error::InvalidRecipe(request.recipe.name.clone(), violations));
+    }
+
     let encoded_recipe = serde_json::to_value(&request.recipe)?;
     let encoded_package_meta = serde_json::to_value(&request.packages)?;
 
-    let component = state
-        .prisma
-        .lock()
-        .await
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &request.gate, &domain_id).await?;
+    ensure_component_owner(
+        &db,
+        &request.gate,
+        &request.recipe.name,
+        &handle,
+        &domain_id,
+    )
+    .await?;
+
+    let component = db
         .component()
         .create(
             request.recipe.name.clone(),
@@ -204,10 +841,22 @@ async fn create_component(
             encoded_recipe,
             serde_json::Value::Null,
             encoded_package_meta,
-            vec![],
+            recipe_search_params(&request.recipe),
         )
         .exec()
         .await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new(
+            "component.create",
+            "component",
+            &component_object_id(&component),
+        )
+        .after(&component)?,
+    )
+    .await?;
 
     Ok(Json(component_from_database(component)?))
 }
@@ -218,15 +867,24 @@ async fn create_component(
     request_body = ComponentInput,
     responses (
         (status = 200, description = "Successfully retrieved component info", body = Component),
+        (status = 400, description = "Recipe failed validation", body = ApiError),
         (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
         (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
     )
 )]
 async fn import_component(
     State(state): State<AppState>,
-    Authentication { .. }: Authentication,
-    Json(request): Json<ComponentInput>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Json(mut request): Json<ComponentInput>,
 ) -> Result<Json<Component>> {
+    request.classify();
+    let violations = request.recipe.validate();
+    if !violations.is_empty() {
+        return Err(Error::InvalidRecipe(request.recipe.name.clone(), violations));
+    }
+
     let encoded_recipe = serde_json::to_value(&request.recipe)?;
     let encoded_package_meta = serde_json::to_value(&request.packages)?;
 
@@ -250,12 +908,14 @@ async fn import_component(
                 request.recipe.name.clone(),
             ))?;
 
-    let mut optional_params: Vec<prisma::component::SetParam> = vec![];
+    let mut optional_params: Vec<prisma::component::SetParam> =
+        recipe_search_params(&request.recipe);
     let mut update_params: Vec<prisma::component::SetParam> = vec![
         prisma::component::SetParam::SetRecipe(encoded_recipe.clone()),
         prisma::component::SetParam::SetPackages(encoded_package_meta.clone()),
         prisma::component::SetParam::SetProjectUrl(project_url.clone()),
     ];
+    update_params.extend(recipe_search_params(&request.recipe));
 
     if let Some(anytia_id) = request.anitya_id {
         optional_params.push(prisma::component::SetParam::SetAnityaId(Some(
@@ -273,10 +933,33 @@ async fn import_component(
         )));
     }
 
-    let component = state
-        .prisma
-        .lock()
-        .await
+    if let Some(purl) = request.purl {
+        optional_params.push(prisma::component::SetParam::SetPurl(Some(purl.clone())));
+        update_params.push(prisma::component::SetParam::SetPurl(Some(purl)));
+    }
+
+    if let Some(cpe) = request.cpe {
+        optional_params.push(prisma::component::SetParam::SetCpe(Some(cpe.clone())));
+        update_params.push(prisma::component::SetParam::SetCpe(Some(cpe)));
+    }
+
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &gate_id, &domain_id).await?;
+    ensure_component_owner(&db, &gate_id, &name, &handle, &domain_id).await?;
+    let before = db
+        .component()
+        .find_unique(
+            prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                name.clone(),
+                gate_id.clone(),
+                version.clone(),
+                revision.clone(),
+            ),
+        )
+        .exec()
+        .await?;
+
+    let component = db
         .component()
         .upsert(
             prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
@@ -300,6 +983,19 @@ async fn import_component(
         )
         .exec()
         .await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new(
+            "component.import",
+            "component",
+            &component_object_id(&component),
+        )
+        .before(&before)?
+        .after(&component)?,
+    )
+    .await?;
 
     Ok(Json(component_from_database(component)?))
 }
@@ -328,7 +1024,9 @@ pub struct Upload {
 )]
 async fn upload_to_component(
     State(state): State<AppState>,
-    Authentication { .. }: Authentication,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
     Path(kind): Path<String>,
     mut multipart: axum::extract::Multipart,
 ) -> Result<()> {
@@ -355,25 +1053,32 @@ async fn upload_to_component(
         &component_ident.name
     );
 
+    ensure_component_owner(
+        &state.prisma,
+        &component_ident.gate_id,
+        &component_ident.name,
+        &handle,
+        &domain_id,
+    )
+    .await?;
+
     trace!("fetching component from database");
     let component = state
         .prisma
-        .lock()
-        .await
         .component()
-        .find_unique(
-            prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
-                component_ident.name,
-                component_ident.gate_id,
-                component_ident.version,
-                component_ident.revision,
-            ),
-        )
+        .find_first(vec![
+            prisma::component::name::equals(component_ident.name),
+            prisma::component::gate_id::equals(component_ident.gate_id),
+            prisma::component::version::equals(component_ident.version),
+            prisma::component::revision::equals(component_ident.revision),
+            component_scope(&domain_id),
+        ])
         .exec()
         .await?
         .ok_or(Error::NoComponentFound)?;
 
     trace!("component found proceeding with upload");
+    let mut stored_files = vec![];
     for url in urls {
         let url: Url = url.parse()?;
         let filename = url
@@ -423,7 +1128,7 @@ async fn upload_to_component(
             &final_name.to_string()
         );
         // Only copy the file to the final destination if none exists there already
-        if !state.fs_operator.is_exist(&filename.to_string()).await? {
+        if !state.fs_operator.is_exist(&final_name.to_string()).await? {
             state
                 .fs_operator
                 .copy(&tmp_name, &final_name.to_string())
@@ -431,53 +1136,118 @@ async fn upload_to_component(
         }
 
         state.fs_operator.delete(&tmp_name).await?;
+        stored_files.push(final_name);
     }
 
-    for file in files {
-        let tmp_name = format!(
-            "trans/{}:{}:{}",
-            kind,
-            &component.name,
-            uuid::Uuid::new_v4().to_string()
+    for (filename, content) in files {
+        trace!("starting upload of file {}", &filename);
+        stored_files.push(
+            store_component_file(
+                &state.fs_operator,
+                kind.parse()?,
+                &component.name,
+                filename,
+                content.to_vec(),
+            )
+            .await?,
         );
+    }
 
-        trace!("starting upload of file to {}", &tmp_name);
+    let db = state.prisma.clone();
+    let file_names = stored_files
+        .iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>();
+    record_component_files(&db, &component, stored_files).await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new(
+            "component.upload",
+            "component",
+            &component_object_id(&component),
+        )
+        .after(&file_names)?,
+    )
+    .await
+}
 
-        let mut writer = state
-            .fs_operator
-            .writer_with(&tmp_name)
-            .chunk(8 * 1024 * 1024)
-            .await?
-            .into_futures_async_write();
-        let filename = file.0;
-        futures::io::copy(&mut file.1.to_vec().as_slice(), &mut writer).await?;
-        writer.close().await?;
+/// Store a file of a component in the blob store under its hash, keeping the file stored
+/// there already if another upload had the same content.
+pub(crate) async fn store_component_file(
+    op: &opendal::Operator,
+    kind: forge::ComponentFileKind,
+    component: &str,
+    name: String,
+    content: Vec<u8>,
+) -> Result<forge::ComponentFile> {
+    let tmp_name = format!(
+        "trans/{}:{}:{}",
+        kind,
+        component,
+        uuid::Uuid::new_v4().to_string()
+    );
+    let mut writer = op
+        .writer_with(&tmp_name)
+        .chunk(8 * 1024 * 1024)
+        .await?
+        .into_futures_async_write();
+    futures::io::copy(&mut content.as_slice(), &mut writer).await?;
+    writer.close().await?;
 
-        let mut hasher = sha3::Sha3_256::new();
-        hasher.update(state.fs_operator.read(&tmp_name).await?.to_vec());
-        let result = hex::encode(hasher.finalize());
+    let mut hasher = sha3::Sha3_256::new();
+    hasher.update(&content);
+    let file = forge::ComponentFile {
+        kind,
+        component: component.to_string(),
+        name,
+        hash: hex::encode(hasher.finalize()),
+    };
 
-        let final_name = forge::ComponentFile {
-            kind: kind.parse()?,
-            component: component.name.clone(),
-            name: filename,
-            hash: result,
-        };
+    trace!("placing file on the final location {}", &file.to_string());
+    if !op.is_exist(&file.to_string()).await? {
+        op.copy(&tmp_name, &file.to_string()).await?;
+    }
+    op.delete(&tmp_name).await?;
+    Ok(file)
+}
 
-        trace!(
-            "placing file on the final location {}",
-            &final_name.to_string()
-        );
-        // Only copy the file to the final destination if none exists there already
-        if !state.fs_operator.is_exist(&final_name.to_string()).await? {
-            state
-                .fs_operator
-                .copy(&tmp_name, &final_name.to_string())
-                .await?;
+/// Remember stored files on the component so builds can fetch them later.
+pub(crate) async fn record_component_files(
+    db: &PrismaClient,
+    component: &prisma::component::Data,
+    stored_files: Vec<forge::ComponentFile>,
+) -> Result<()> {
+    let mut archives = vec![];
+    let mut scripts = vec![];
+    for file in stored_files {
+        let name = file.to_string();
+        match file.kind {
+            forge::ComponentFileKind::Archive if !component.archives.contains(&name) => {
+                archives.push(name)
+            }
+            forge::ComponentFileKind::Script if !component.scripts.contains(&name) => {
+                scripts.push(name)
+            }
+            _ => {}
         }
-
-        state.fs_operator.delete(&tmp_name).await?;
     }
+    db.component()
+        .update(
+            prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                component.name.clone(),
+                component.gate_id.clone(),
+                component.version.clone(),
+                component.revision.clone(),
+            ),
+            vec![
+                prisma::component::archives::push(archives),
+                prisma::component::scripts::push(scripts),
+            ],
+        )
+        .exec()
+        .await?;
 
     Ok(())
 }
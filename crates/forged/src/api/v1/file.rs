@@ -0,0 +1,319 @@
+use crate::api::auth::Authentication;
+use crate::api::v1::component::{find_component, record_component_files, ComponentIdentifier};
+use crate::{AppState, Error, Result};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path, State};
+use axum::http::HeaderMap;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::AsyncWriteExt;
+use opendal::Operator;
+use serde::{Deserialize, Serialize};
+use sha3::Digest;
+use tracing::{debug, trace};
+use utoipa::ToSchema;
+
+/// Largest chunk one request may send.
+const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Header carrying the offset a chunk starts at, named like in the tus protocol.
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+
+/// Prefix of the state and the chunks of resumable uploads in the blob store.
+pub(crate) const UPLOAD_PREFIX: &str = "uploads/";
+
+pub fn get_router() -> Router<AppState> {
+    Router::new()
+        .route("/uploads", post(create_upload))
+        .route(
+            "/uploads/:id",
+            get(get_upload).patch(append_upload).delete(abort_upload),
+        )
+        .route("/uploads/:id/complete", post(complete_upload))
+        .layer(DefaultBodyLimit::max(MAX_CHUNK_SIZE))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CreateUploadInput {
+    pub component: ComponentIdentifier,
+    /// Kind of the file, archive or script
+    pub kind: String,
+    /// File name the file is stored under
+    pub name: String,
+    /// Size of the whole file in bytes
+    pub size: i64,
+}
+
+/// A resumable upload. Chunks are sent in order with PATCH requests whose
+/// `Upload-Offset` header matches the offset of the upload.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Upload {
+    pub id: String,
+    /// Bytes received so far, the offset the next chunk starts at
+    pub offset: i64,
+    pub size: i64,
+}
+
+/// The stored file an upload was completed to.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct UploadedFile {
+    pub file: String,
+}
+
+/// What forged remembers about an upload between requests.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct UploadState {
+    domain_id: String,
+    component: ComponentIdentifier,
+    kind: forge::ComponentFileKind,
+    name: String,
+    size: i64,
+    offset: i64,
+    chunks: Vec<String>,
+}
+
+impl UploadState {
+    fn to_upload(&self, id: &str) -> Upload {
+        Upload {
+            id: id.to_string(),
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+}
+
+fn state_path(id: &str) -> String {
+    format!("{}{}.json", UPLOAD_PREFIX, id)
+}
+
+/// Uploads of other domains are not found, like their components.
+async fn read_state(op: &Operator, id: &str, domain_id: &str) -> Result<UploadState> {
+    let not_found = || Error::NotFound(format!("upload {}", id));
+    if uuid::Uuid::parse_str(id).is_err() || !op.is_exist(&state_path(id)).await? {
+        return Err(not_found());
+    }
+    let content = op.read(&state_path(id)).await?.to_vec();
+    let upload: UploadState = serde_json::from_slice(&content)?;
+    if upload.domain_id != domain_id {
+        return Err(not_found());
+    }
+    Ok(upload)
+}
+
+async fn write_state(op: &Operator, id: &str, upload: &UploadState) -> Result<()> {
+    op.write(&state_path(id), serde_json::to_vec(upload)?).await?;
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/files/uploads",
+    request_body = CreateUploadInput,
+    responses (
+        (status = 200, description = "Upload created", body = Upload),
+        (status = 400, description = "Unknown kind of file or invalid size", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("upload is invalid: size must not be negative")))),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Component not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("no component found"))))
+    )
+)]
+async fn create_upload(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Json(request): Json<CreateUploadInput>,
+) -> Result<Json<Upload>> {
+    find_component(&state.prisma, &request.component, &domain_id).await?;
+    if request.size < 0 {
+        return Err(Error::InvalidUpload(String::from(
+            "size must not be negative",
+        )));
+    }
+    let kind = request
+        .kind
+        .parse::<forge::ComponentFileKind>()
+        .map_err(|_| {
+            Error::InvalidUpload(format!("{} is not a kind of file", request.kind))
+        })?;
+    if matches!(kind, forge::ComponentFileKind::Patch) {
+        return Err(Error::InvalidUpload(String::from(
+            "patches are part of the recipe and not uploaded",
+        )));
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let upload = UploadState {
+        domain_id,
+        component: request.component,
+        kind,
+        name: request.name,
+        size: request.size,
+        offset: 0,
+        chunks: vec![],
+    };
+    write_state(&state.fs_operator, &id, &upload).await?;
+    debug!("created upload {} of {}", id, upload.name);
+    Ok(Json(upload.to_upload(&id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/files/uploads/{id}",
+    responses (
+        (status = 200, description = "The offset to resume the upload at", body = Upload),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Upload not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("upload 1"))))
+    ),
+    params(
+        ("id" = String, Path, description = "Id of the upload"),
+    )
+)]
+async fn get_upload(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(id): Path<String>,
+) -> Result<Json<Upload>> {
+    let upload = read_state(&state.fs_operator, &id, &domain_id).await?;
+    Ok(Json(upload.to_upload(&id)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/files/uploads/{id}",
+    request_body(content = Vec<u8>, description = "The next chunk of the file", content_type = "application/offset+octet-stream"),
+    responses (
+        (status = 200, description = "Chunk stored", body = Upload),
+        (status = 400, description = "Chunk goes past the size of the upload", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("upload is invalid: chunk ends past the size of the upload")))),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Upload not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("upload 1")))),
+        (status = 409, description = "Upload-Offset is not the offset of the upload", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("upload is at offset 1024 not 0"))))
+    ),
+    params(
+        ("id" = String, Path, description = "Id of the upload"),
+        ("Upload-Offset" = i64, Header, description = "Offset the chunk starts at"),
+    )
+)]
+async fn append_upload(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Upload>> {
+    let mut upload = read_state(&state.fs_operator, &id, &domain_id).await?;
+    let offset = headers
+        .get(UPLOAD_OFFSET)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok())
+        .ok_or(Error::InvalidUpload(format!(
+            "{} header is missing",
+            UPLOAD_OFFSET
+        )))?;
+    // A client that lost the answer to its last chunk resumes from the stored offset
+    if offset != upload.offset {
+        return Err(Error::UploadOffsetMismatch(upload.offset, offset));
+    }
+    if upload.offset + body.len() as i64 > upload.size {
+        return Err(Error::InvalidUpload(String::from(
+            "chunk ends past the size of the upload",
+        )));
+    }
+
+    let chunk = format!("{}{}/{:020}", UPLOAD_PREFIX, id, upload.offset);
+    state.fs_operator.write(&chunk, body.clone()).await?;
+    upload.chunks.push(chunk);
+    upload.offset += body.len() as i64;
+    write_state(&state.fs_operator, &id, &upload).await?;
+    trace!("upload {} is at {} of {}", id, upload.offset, upload.size);
+    Ok(Json(upload.to_upload(&id)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/files/uploads/{id}/complete",
+    responses (
+        (status = 200, description = "File stored and added to the component", body = UploadedFile),
+        (status = 400, description = "Not all chunks were uploaded", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("upload is invalid: 1024 of 2048 bytes were uploaded")))),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Upload not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("upload 1"))))
+    ),
+    params(
+        ("id" = String, Path, description = "Id of the upload"),
+    )
+)]
+async fn complete_upload(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(id): Path<String>,
+) -> Result<Json<UploadedFile>> {
+    let upload = read_state(&state.fs_operator, &id, &domain_id).await?;
+    if upload.offset != upload.size {
+        return Err(Error::InvalidUpload(format!(
+            "{} of {} bytes were uploaded",
+            upload.offset, upload.size
+        )));
+    }
+    let component =
+        find_component(&state.prisma, &upload.component, &domain_id).await?;
+
+    // The chunks are joined with a multipart upload, hashing them on the way
+    let tmp_name = format!("trans/{}:{}:{}", upload.kind, &component.name, id);
+    let mut writer = state
+        .fs_operator
+        .writer_with(&tmp_name)
+        .chunk(8 * 1024 * 1024)
+        .await?
+        .into_futures_async_write();
+    let mut hasher = sha3::Sha3_256::new();
+    for chunk in upload.chunks.iter() {
+        let content = state.fs_operator.read(chunk).await?.to_vec();
+        hasher.update(&content);
+        futures::io::copy(&mut content.as_slice(), &mut writer).await?;
+    }
+    writer.close().await?;
+
+    let file = forge::ComponentFile {
+        kind: upload.kind.clone(),
+        component: component.name.clone(),
+        name: upload.name.clone(),
+        hash: hex::encode(hasher.finalize()),
+    };
+    // Only copy the file to the final destination if none exists there already
+    if !state.fs_operator.is_exist(&file.to_string()).await? {
+        state.fs_operator.copy(&tmp_name, &file.to_string()).await?;
+    }
+    state.fs_operator.delete(&tmp_name).await?;
+    remove_upload(&state.fs_operator, &id, &upload).await?;
+
+    let name = file.to_string();
+    record_component_files(&state.prisma, &component, vec![file]).await?;
+    debug!("completed upload {} to {}", id, name);
+    Ok(Json(UploadedFile { file: name }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/files/uploads/{id}",
+    responses (
+        (status = 200, description = "Upload and its chunks removed"),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Upload not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("upload 1"))))
+    ),
+    params(
+        ("id" = String, Path, description = "Id of the upload"),
+    )
+)]
+async fn abort_upload(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(id): Path<String>,
+) -> Result<()> {
+    let upload = read_state(&state.fs_operator, &id, &domain_id).await?;
+    remove_upload(&state.fs_operator, &id, &upload).await
+}
+
+async fn remove_upload(op: &Operator, id: &str, upload: &UploadState) -> Result<()> {
+    for chunk in upload.chunks.iter() {
+        op.delete(chunk).await?;
+    }
+    op.delete(&state_path(id)).await?;
+    Ok(())
+}
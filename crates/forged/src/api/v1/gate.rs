@@ -1,12 +1,34 @@
-use crate::api::auth::Authentication;
+use crate::api::auth::{Authentication, DomainScope};
+use crate::domain::{find_gate_in_domain, gate_scope};
 use crate::prisma::gate::{SetParam, WhereParam};
+use crate::quality::{latest_components, license_usage};
+use crate::api::v1::component::{sbom_entry, Vulnerability};
+use crate::api::v1::job::QueuedJob;
+use crate::api::v1::snapshot::{find_snapshot_in_domain, CreateSnapshotInput, Snapshot};
+use crate::api::v1::{GraphParams, RebuildParams, SbomParams};
+use crate::audit::{self, AuditEvent};
+use crate::changelog::{changelog_between_dates, changelog_between_snapshots, Changelog};
+use crate::notification::{self, NotificationEvent};
+use crate::ownership::{find_actor, is_domain_admin, is_gate_admin, maintainer_names};
+use crate::scheduler::{dispatch_jobs, enqueue_job, JobPriority};
+use crate::snapshot::freeze_gate;
+use crate::transform_preview::{
+    diff_lines, metadata_manifests, mogrify_manifest, transform_options, MetadataTransform,
+    TransformPreviewChange,
+};
+use crate::prisma::PrismaClient;
 use crate::{prisma, AppState, Error, Result};
-use axum::extract::{Path, State};
-use axum::routing::{post, put};
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
 use axum::{Json, Router};
+use chrono::{DateTime, FixedOffset};
+use component::{dependency_graph, render_sbom, BuildStep, GraphFormat, Recipe};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tracing::error;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 pub fn get_router() -> Router<AppState> {
@@ -15,6 +37,23 @@ pub fn get_router() -> Router<AppState> {
         .route("/list", post(list_gates))
         .route("/", post(create_gate))
         .route("/:id", put(update_gate))
+        .route("/:id/admins", post(add_gate_admin))
+        .route("/:id/quality", get(get_gate_quality))
+        .route("/:id/licenses", get(get_gate_licenses))
+        .route("/:id/rebuild", post(rebuild_gate))
+        .route("/:id/releases", get(list_gate_releases))
+        .route("/:id/config_syncs", get(list_gate_config_syncs))
+        .route(
+            "/:id/snapshots",
+            get(list_gate_snapshots).post(create_gate_snapshot),
+        )
+        .route("/:id/outdated", get(list_outdated_components))
+        .route("/:id/status", get(get_maintainer_status))
+        .route("/:id/changelog", get(get_gate_changelog))
+        .route("/:id/security", get(get_gate_security_report))
+        .route("/:id/sbom", get(get_gate_sbom))
+        .route("/:id/depgraph", get(get_gate_dependency_graph))
+        .route("/:id/transforms/preview", post(preview_gate_transforms))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -31,6 +70,8 @@ pub struct Gate {
     pub branch: String,
     pub publisher: String,
     pub transforms: Vec<String>,
+    /// Jobs of the gate running at once, unlimited if not set
+    pub max_concurrent_jobs: Option<i32>,
 }
 
 #[utoipa::path(
@@ -44,18 +85,18 @@ pub struct Gate {
 )]
 async fn get_gate(
     State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
     Json(request): Json<GateSearchRequest>,
 ) -> Result<Json<Gate>> {
     let gate = state
         .prisma
-        .lock()
-        .await
         .gate()
         .find_first(vec![
             prisma::gate::publisher::is(vec![prisma::publisher::name::equals(
                 request.publisher.clone(),
             )]),
             prisma::gate::name::equals(request.name.clone()),
+            gate_scope(&domain_id),
         ])
         .with(prisma::gate::publisher::fetch())
         .exec()
@@ -73,6 +114,7 @@ async fn get_gate(
         branch: gate.branch,
         publisher: gate.publisher.unwrap().name,
         transforms,
+        max_concurrent_jobs: gate.max_concurrent_jobs,
     }))
 }
 
@@ -92,9 +134,10 @@ pub struct GateListRequest {
 )]
 async fn list_gates(
     State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
     Json(request): Json<GateListRequest>,
 ) -> Result<Json<Vec<Gate>>> {
-    let mut filter: Vec<WhereParam> = vec![];
+    let mut filter: Vec<WhereParam> = vec![gate_scope(&domain_id)];
     if let Some(publisher) = request.publisher {
         filter.push(prisma::gate::publisher::is(vec![
             prisma::publisher::name::equals(publisher),
@@ -103,8 +146,6 @@ async fn list_gates(
 
     let gates = state
         .prisma
-        .lock()
-        .await
         .gate()
         .find_many(filter)
         .with(prisma::gate::publisher::fetch())
@@ -127,6 +168,7 @@ async fn list_gates(
                 branch: g.branch,
                 publisher: g.publisher.unwrap().name,
                 transforms,
+                max_concurrent_jobs: g.max_concurrent_jobs,
             }
         })
         .collect()))
@@ -147,6 +189,8 @@ pub struct UpdateGateInput {
     pub version: Option<String>,
     pub branch: Option<String>,
     pub transforms: Option<Vec<String>>,
+    /// Jobs of the gate running at once, 0 removes the limit
+    pub max_concurrent_jobs: Option<i32>,
 }
 
 #[utoipa::path(
@@ -155,23 +199,32 @@ pub struct UpdateGateInput {
     request_body = CreateGateInput,
     responses (
         (status = 200, description = "Successfully retrieved gate info", body = Gate),
+        (status = 400, description = "A transform failed validation", body = ApiError),
         (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
         (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
     )
 )]
 async fn create_gate(
     State(state): State<AppState>,
-    Authentication { .. }: Authentication,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
     Json(request): Json<CreateGateInput>,
 ) -> Result<Json<Gate>> {
+    validate_transforms(request.transforms.iter().flatten())?;
+    let creator = find_actor(&state.prisma, &handle, &domain_id).await?;
+    // The creator becomes the first administrator of the gate
+    if !is_domain_admin(&state.prisma, &domain_id, &creator.id).await? {
+        return Err(Error::NotDomainAdmin(handle));
+    }
     let encoded_transforms = serde_json::to_value(request.transforms)?;
     if state
         .prisma
-        .lock()
-        .await
         .publisher()
-        .find_unique(prisma::publisher::UniqueWhereParam::NameEquals(
+        .find_unique(prisma::publisher::UniqueWhereParam::NameDomainIdEquals(
             request.publisher.clone(),
+            domain_id.clone(),
         ))
         .exec()
         .await?
@@ -179,30 +232,43 @@ async fn create_gate(
     {
         state
             .prisma
-            .lock()
-            .await
             .publisher()
-            .create(request.publisher.clone(), vec![])
+            .create(
+                request.publisher.clone(),
+                prisma::domain::UniqueWhereParam::IdEquals(domain_id.clone()),
+                vec![],
+            )
             .exec()
             .await?;
     }
 
     let gate = state
         .prisma
-        .lock()
-        .await
         .gate()
         .create(
             request.name,
             request.version,
             request.branch,
-            prisma::publisher::name::equals(request.publisher),
+            prisma::publisher::UniqueWhereParam::NameDomainIdEquals(
+                request.publisher,
+                domain_id.clone(),
+            ),
             encoded_transforms,
-            vec![],
+            // The creator administers the gate until it hands that over
+            vec![prisma::gate::admins::connect(vec![
+                prisma::actor::UniqueWhereParam::IdEquals(creator.id),
+            ])],
         )
         .with(prisma::gate::publisher::fetch())
         .exec()
         .await?;
+    audit::record(
+        &state.prisma,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("gate.create", "gate", &gate.id).after(&gate)?,
+    )
+    .await?;
 
     let transforms: Vec<String> = serde_json::from_value(gate.transforms)?;
     Ok(Json(Gate {
@@ -212,6 +278,7 @@ async fn create_gate(
         branch: gate.branch,
         publisher: gate.publisher.unwrap().name,
         transforms,
+        max_concurrent_jobs: gate.max_concurrent_jobs,
     }))
 }
 
@@ -221,6 +288,7 @@ async fn create_gate(
     request_body = UpdateGateInput,
     responses (
         (status = 200, description = "Successfully retrieved gate info", body = Gate),
+        (status = 400, description = "A transform failed validation", body = ApiError),
         (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
         (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
     ),
@@ -230,7 +298,9 @@ async fn create_gate(
 )]
 async fn update_gate(
     State(state): State<AppState>,
-    Authentication { .. }: Authentication,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
     Path(id): Path<Uuid>,
     Json(request): Json<UpdateGateInput>,
 ) -> Result<Json<Gate>> {
@@ -249,19 +319,35 @@ async fn update_gate(
     }
 
     if let Some(transforms) = request.transforms {
+        validate_transforms(transforms.iter())?;
         let encoded_transforms = serde_json::to_value(&transforms)?;
         updates.push(prisma::gate::transforms::set(encoded_transforms));
     }
 
-    let gate = state
-        .prisma
-        .lock()
-        .await
+    if let Some(max_concurrent_jobs) = request.max_concurrent_jobs {
+        updates.push(prisma::gate::max_concurrent_jobs::set(
+            Some(max_concurrent_jobs).filter(|max| *max > 0),
+        ));
+    }
+
+    let db = state.prisma.clone();
+    let before = find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let gate = db
         .gate()
         .update(prisma::gate::id::equals(id.to_string()), updates)
         .with(prisma::gate::publisher::fetch())
         .exec()
         .await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("gate.update", "gate", &gate.id)
+            .before(&before)?
+            .after(&gate)?,
+    )
+    .await?;
 
     let transforms: Vec<String> = serde_json::from_value(gate.transforms)?;
     Ok(Json(Gate {
@@ -271,5 +357,1093 @@ async fn update_gate(
         branch: gate.branch,
         publisher: gate.publisher.unwrap().name,
         transforms,
+        max_concurrent_jobs: gate.max_concurrent_jobs,
+    }))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GateAdminInput {
+    /// Handle of the actor to make an administrator of the gate
+    pub handle: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/gates/{id}/admins",
+    request_body = GateAdminInput,
+    responses (
+        (status = 200, description = "Handles of the administrators of the gate", body = Vec<String>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the gate, or the domain for gates without administrators", body = ApiError),
+        (status = 404, description = "Gate or actor not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn add_gate_admin(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+    Json(request): Json<GateAdminInput>,
+) -> Result<Json<Vec<String>>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+    let caller = find_actor(&db, &handle, &domain_id).await?;
+    let admins = db
+        .actor()
+        .find_many(vec![prisma::actor::admin_of::some(vec![
+            prisma::gate::id::equals(id.to_string()),
+        ])])
+        .exec()
+        .await?;
+    // Gates without administrators get their first one from a domain administrator
+    if admins.is_empty() {
+        if !is_domain_admin(&db, &domain_id, &caller.id).await? {
+            return Err(Error::NotDomainAdmin(handle));
+        }
+    } else if !is_gate_admin(&db, &id.to_string(), &caller.id).await? {
+        return Err(Error::NotGateAdmin(handle));
+    }
+
+    let actor = find_actor(&db, &request.handle, &domain_id).await?;
+    let gate = db
+        .gate()
+        .update(
+            prisma::gate::UniqueWhereParam::IdEquals(id.to_string()),
+            vec![prisma::gate::admins::connect(vec![
+                prisma::actor::UniqueWhereParam::IdEquals(actor.id),
+            ])],
+        )
+        .with(prisma::gate::admins::fetch(vec![]))
+        .exec()
+        .await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("gate.add_admin", "gate", &gate.id)
+            .before(&admins.iter().map(|a| &a.handle).collect::<Vec<_>>())?
+            .after(&request.handle)?,
+    )
+    .await?;
+
+    Ok(Json(
+        gate.admins
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| a.handle)
+            .collect(),
+    ))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GateQualitySnapshot {
+    pub created_at: String,
+    pub components: i32,
+    pub missing_summary: i32,
+    pub missing_license: i32,
+    pub without_maintainer: i32,
+    pub stale_versions: i32,
+    pub lint_warnings: i32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/quality",
+    responses (
+        (status = 200, description = "Recipe quality history of the gate, oldest first", body = Vec<GateQualitySnapshot>),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn get_gate_quality(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<GateQualitySnapshot>>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let snapshots = db
+        .gate_quality_snapshot()
+        .find_many(vec![prisma::gate_quality_snapshot::gate_id::equals(
+            id.to_string(),
+        )])
+        .order_by(prisma::gate_quality_snapshot::created_at::order(
+            prisma_client_rust::Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(
+        snapshots
+            .into_iter()
+            .map(|s| GateQualitySnapshot {
+                created_at: s.created_at.to_rfc3339(),
+                components: s.components,
+                missing_summary: s.missing_summary,
+                missing_license: s.missing_license,
+                without_maintainer: s.without_maintainer,
+                stale_versions: s.stale_versions,
+                lint_warnings: s.lint_warnings,
+            })
+            .collect(),
+    ))
+}
+
+/// How many components of the gate may be used under a license.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct LicenseCount {
+    /// SPDX identifier
+    pub license: String,
+    pub components: i32,
+}
+
+/// The licenses of the latest version of every component of a gate.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GateLicenses {
+    pub components: i32,
+    /// Names of the components without a license
+    pub unlicensed: Vec<String>,
+    /// Names of the components with a license that is no SPDX expression
+    pub unparsable: Vec<String>,
+    /// Most used first. A component is counted for every license it may be used under
+    pub licenses: Vec<LicenseCount>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/licenses",
+    responses (
+        (status = 200, description = "License statistics of the gate", body = GateLicenses),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn get_gate_licenses(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<GateLicenses>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let components = db
+        .component()
+        .find_many(vec![prisma::component::gate_id::equals(id.to_string())])
+        .exec()
+        .await?;
+    let recipes = latest_components(components)
+        .into_iter()
+        .map(|c| Ok(serde_json::from_value::<Recipe>(c.recipe)?))
+        .collect::<Result<Vec<_>>>()?;
+
+    let usage = license_usage(&recipes);
+    let mut licenses = usage
+        .licenses
+        .into_iter()
+        .map(|(license, components)| LicenseCount {
+            license,
+            components,
+        })
+        .collect::<Vec<_>>();
+    licenses.sort_by(|a, b| b.components.cmp(&a.components));
+    Ok(Json(GateLicenses {
+        components: usage.components,
+        unlicensed: usage.unlicensed,
+        unparsable: usage.unparsable,
+        licenses,
+    }))
+}
+
+/// A tag of the gate repository rebuilt from scratch and published as a snapshot of the
+/// package repository.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Release {
+    pub tag: String,
+    /// Name of the staging repository the release is built into
+    pub staging: String,
+    /// Reading, Building, Publishing, Published or Failed
+    pub state: String,
+    /// Builds into the staging repository that have not reported back yet
+    pub pending_builds: i32,
+    pub packages: Vec<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub published_at: Option<String>,
+}
+
+impl From<prisma::release::Data> for Release {
+    fn from(value: prisma::release::Data) -> Self {
+        Self {
+            tag: value.tag,
+            staging: value.staging,
+            state: value.state.to_string(),
+            pending_builds: value.pending_builds,
+            packages: value.packages,
+            error: value.error,
+            created_at: value.created_at.to_rfc3339(),
+            published_at: value.published_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/releases",
+    responses (
+        (status = 200, description = "Releases of the gate, newest first", body = Vec<Release>),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn list_gate_releases(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Release>>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let releases = db
+        .release()
+        .find_many(vec![prisma::release::gate_id::equals(id.to_string())])
+        .order_by(prisma::release::created_at::order(
+            prisma_client_rust::Direction::Desc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(releases.into_iter().map(Release::from).collect()))
+}
+
+/// A sync of the gate with the gate.kdl of a commit of its repository.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GateConfigSync {
+    pub commit: String,
+    /// Settings gate.kdl changed
+    pub changed: Vec<String>,
+    /// Settings edited outside of gate.kdl since the last sync that the sync overwrote
+    pub conflicts: Vec<String>,
+    pub created_at: String,
+}
+
+impl From<prisma::gate_config_sync::Data> for GateConfigSync {
+    fn from(value: prisma::gate_config_sync::Data) -> Self {
+        Self {
+            commit: value.commit,
+            changed: value.changed,
+            conflicts: value.conflicts,
+            created_at: value.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/config_syncs",
+    responses (
+        (status = 200, description = "Syncs of the gate with its gate.kdl, newest first", body = Vec<GateConfigSync>),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn list_gate_config_syncs(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<GateConfigSync>>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let syncs = db
+        .gate_config_sync()
+        .find_many(vec![prisma::gate_config_sync::gate_id::equals(id.to_string())])
+        .order_by(prisma::gate_config_sync::created_at::order(
+            prisma_client_rust::Direction::Desc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(syncs.into_iter().map(GateConfigSync::from).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/snapshots",
+    responses (
+        (status = 200, description = "Snapshots of the gate, newest first", body = Vec<Snapshot>),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn list_gate_snapshots(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Snapshot>>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let snapshots = db
+        .gate_snapshot()
+        .find_many(vec![prisma::gate_snapshot::gate_id::equals(id.to_string())])
+        .order_by(prisma::gate_snapshot::created_at::order(
+            prisma_client_rust::Direction::Desc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(
+        snapshots
+            .into_iter()
+            .map(Snapshot::try_from)
+            .collect::<Result<Vec<_>>>()?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/gates/{id}/snapshots",
+    request_body = CreateSnapshotInput,
+    responses (
+        (status = 200, description = "The latest version of every component of the gate frozen into a snapshot", body = Snapshot),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1")))),
+        (status = 409, description = "The gate has a snapshot of that name", body = ApiError)
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate to freeze"),
+    )
+)]
+async fn create_gate_snapshot(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+    Json(request): Json<CreateSnapshotInput>,
+) -> Result<Json<Snapshot>> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+    let snapshot = freeze_gate(&db, &gate, &request.name).await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("gate.freeze", "gate_snapshot", &snapshot.id).after(&snapshot)?,
+    )
+    .await?;
+    notification::notify(
+        &db,
+        NotificationEvent::SnapshotCreated {
+            gate_id: snapshot.gate_id.clone(),
+            snapshot: snapshot.name.clone(),
+            promoted_from: None,
+        },
+    )
+    .await;
+    Ok(Json(Snapshot::try_from(snapshot)?))
+}
+
+/// A component of the gate with a newer release upstream.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct OutdatedComponent {
+    pub name: String,
+    /// Latest version of the component in the gate
+    pub version: String,
+    pub upstream_version: String,
+    /// Where the upstream version was found, anitya or libraries.io
+    pub source: String,
+    pub checked_at: String,
+}
+
+impl From<prisma::upstream_release::Data> for OutdatedComponent {
+    fn from(value: prisma::upstream_release::Data) -> Self {
+        Self {
+            name: value.name,
+            version: value.version,
+            upstream_version: value.upstream_version,
+            source: value.source,
+            checked_at: value.checked_at.to_rfc3339(),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/outdated",
+    responses (
+        (status = 200, description = "Components of the gate with a newer upstream release as of the last check", body = Vec<OutdatedComponent>),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn list_outdated_components(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<OutdatedComponent>>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let outdated = db
+        .upstream_release()
+        .find_many(vec![
+            prisma::upstream_release::gate_id::equals(id.to_string()),
+            prisma::upstream_release::outdated::equals(true),
+        ])
+        .order_by(prisma::upstream_release::name::order(
+            prisma_client_rust::Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(
+        outdated.into_iter().map(OutdatedComponent::from).collect(),
+    ))
+}
+
+/// Where a component the caller maintains stands.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct MaintainedComponentStatus {
+    pub name: String,
+    /// Latest version of the component in the gate
+    pub version: String,
+    pub revision: String,
+    /// Latest upstream version as of the last check, unset if it was never checked
+    pub upstream_version: Option<String>,
+    pub outdated: bool,
+    /// Ids of the open and draft change requests changing the component
+    pub open_change_requests: Vec<String>,
+    /// The last build of the latest version of the component
+    pub last_build: Option<QueuedJob>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/status",
+    responses (
+        (status = 200, description = "Status of the components of the gate the caller maintains ordered by name", body = Vec<MaintainedComponentStatus>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn get_maintainer_status(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<MaintainedComponentStatus>>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+    let actor = find_actor(&db, &handle, &domain_id).await?;
+    let names = maintainer_names(&db, &actor).await?;
+
+    let mut components = latest_components(
+        db.component()
+            .find_many(vec![
+                prisma::component::gate_id::equals(id.to_string()),
+                prisma::component::maintainers::has_some(names),
+            ])
+            .exec()
+            .await?,
+    );
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+    let component_names = components
+        .iter()
+        .map(|c| c.name.clone())
+        .collect::<Vec<_>>();
+
+    let upstream = db
+        .upstream_release()
+        .find_many(vec![
+            prisma::upstream_release::gate_id::equals(id.to_string()),
+            prisma::upstream_release::name::in_vec(component_names.clone()),
+        ])
+        .exec()
+        .await?
+        .into_iter()
+        .map(|release| (release.name.clone(), release))
+        .collect::<HashMap<_, _>>();
+
+    let mut open_change_requests: HashMap<String, Vec<String>> = HashMap::new();
+    for change in db
+        .component_change()
+        .find_many(vec![
+            prisma::component_change::gate_id::equals(Some(id.to_string())),
+            prisma::component_change::name::in_vec(component_names),
+            prisma::component_change::applied::equals(false),
+            prisma::component_change::change_request::is(vec![
+                prisma::change_request::state::in_vec(vec![
+                    prisma::ChangeRequestState::Open,
+                    prisma::ChangeRequestState::Draft,
+                ]),
+            ]),
+        ])
+        .exec()
+        .await?
+    {
+        let ids = open_change_requests.entry(change.name).or_default();
+        if !ids.contains(&change.change_request_id) {
+            ids.push(change.change_request_id);
+        }
+    }
+
+    let mut statuses = vec![];
+    for component in components {
+        let component_id = forge::ComponentId {
+            name: component.name.clone(),
+            version: component.version.clone(),
+            revision: component.revision.clone(),
+            gate_id: id,
+        };
+        let last_build = db
+            .queued_job()
+            .find_first(vec![prisma::queued_job::reference::in_vec(vec![
+                component_id.to_string(),
+                format!("{} (bootstrap)", component_id),
+            ])])
+            .order_by(prisma::queued_job::created_at::order(
+                prisma_client_rust::Direction::Desc,
+            ))
+            .exec()
+            .await?
+            .map(QueuedJob::try_from)
+            .transpose()?;
+        let release = upstream.get(&component.name);
+        statuses.push(MaintainedComponentStatus {
+            upstream_version: release.map(|r| r.upstream_version.clone()),
+            // Releases checked before the component was updated are stale
+            outdated: release.is_some_and(|r| r.outdated && r.version == component.version),
+            open_change_requests: open_change_requests
+                .remove(&component.name)
+                .unwrap_or_default(),
+            name: component.name,
+            version: component.version,
+            revision: component.revision,
+            last_build,
+        });
+    }
+
+    Ok(Json(statuses))
+}
+
+/// Query parameters of a changelog, either two snapshots or a time range.
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangelogParams {
+    /// Database id of the snapshot of the gate the changelog starts at
+    pub from_snapshot: Option<Uuid>,
+    /// Database id of the snapshot of the gate the changelog ends at
+    pub to_snapshot: Option<Uuid>,
+    /// Only change requests applied at or after this RFC 3339 time
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Only change requests applied before this RFC 3339 time
+    pub until: Option<DateTime<FixedOffset>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/changelog",
+    responses (
+        (status = 200, description = "Components added, updated and removed with the change requests bringing them, also rendered as Markdown", body = Changelog),
+        (status = 400, description = "Snapshots and a time range given together or only one snapshot", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("invalid changelog range: both snapshots are needed")))),
+        (status = 404, description = "Gate or snapshot not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("snapshot 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+        ChangelogParams
+    )
+)]
+async fn get_gate_changelog(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+    Query(params): Query<ChangelogParams>,
+) -> Result<Json<Changelog>> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let dates = params.since.is_some() || params.until.is_some();
+    let changelog = match (params.from_snapshot, params.to_snapshot, dates) {
+        (Some(from), Some(to), false) => {
+            let from = find_snapshot_in_domain(&db, &from, &domain_id).await?;
+            let to = find_snapshot_in_domain(&db, &to, &domain_id).await?;
+            if from.gate_id != gate.id || to.gate_id != gate.id {
+                return Err(Error::InvalidChangelogRange(String::from(
+                    "the snapshots have to belong to the gate",
+                )));
+            }
+            changelog_between_snapshots(&db, &gate, &from, &to).await?
+        }
+        (None, None, true) => {
+            changelog_between_dates(&db, &gate, params.since, params.until).await?
+        }
+        (None, None, false) => {
+            return Err(Error::InvalidChangelogRange(String::from(
+                "give two snapshots or a time range",
+            )))
+        }
+        (Some(_), Some(_), true) => {
+            return Err(Error::InvalidChangelogRange(String::from(
+                "give either snapshots or a time range",
+            )))
+        }
+        _ => {
+            return Err(Error::InvalidChangelogRange(String::from(
+                "both snapshots are needed",
+            )))
+        }
+    };
+    Ok(Json(changelog))
+}
+
+/// The latest version of a component of the gate with advisories affecting it.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct VulnerableComponent {
+    pub name: String,
+    pub version: String,
+    pub revision: String,
+    pub vulnerabilities: Vec<Vulnerability>,
+}
+
+/// Advisories affecting the latest version of every component of a gate.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SecurityReport {
+    pub components: i32,
+    /// Components with a purl or CPE to look up advisories with
+    pub tracked_components: i32,
+    pub vulnerable_components: Vec<VulnerableComponent>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/security",
+    responses (
+        (status = 200, description = "Advisories affecting the gate as of the last check", body = SecurityReport),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn get_gate_security_report(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SecurityReport>> {
+    let db = state.prisma.clone();
+    find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let components = latest_components(
+        db.component()
+            .find_many(vec![prisma::component::gate_id::equals(id.to_string())])
+            .exec()
+            .await?,
+    );
+    let mut vulnerabilities: HashMap<(String, String, String), Vec<Vulnerability>> =
+        HashMap::new();
+    for vulnerability in db
+        .vulnerability()
+        .find_many(vec![prisma::vulnerability::gate_id::equals(id.to_string())])
+        .exec()
+        .await?
+    {
+        vulnerabilities
+            .entry((
+                vulnerability.component_name.clone(),
+                vulnerability.component_version.clone(),
+                vulnerability.component_revision.clone(),
+            ))
+            .or_default()
+            .push(Vulnerability::from(vulnerability));
+    }
+
+    let mut report = SecurityReport {
+        components: components.len() as i32,
+        tracked_components: 0,
+        vulnerable_components: vec![],
+    };
+    for component in components {
+        if component.purl.is_some() || component.cpe.is_some() {
+            report.tracked_components += 1;
+        }
+        let key = (component.name, component.version, component.revision);
+        if let Some(vulnerabilities) = vulnerabilities.remove(&key) {
+            let (name, version, revision) = key;
+            report.vulnerable_components.push(VulnerableComponent {
+                name,
+                version,
+                revision,
+                vulnerabilities,
+            });
+        }
+    }
+    report
+        .vulnerable_components
+        .sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/sbom",
+    responses (
+        (status = 200, description = "CycloneDX or SPDX document of the latest version of every component of the gate", body = Object),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+        SbomParams,
+    )
+)]
+async fn get_gate_sbom(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+    Query(params): Query<SbomParams>,
+) -> Result<Json<serde_json::Value>> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let mut components = latest_components(
+        db.component()
+            .find_many(vec![prisma::component::gate_id::equals(id.to_string())])
+            .exec()
+            .await?,
+    );
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+    let entries = components
+        .into_iter()
+        .map(sbom_entry)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Json(render_sbom(
+        params.format,
+        &gate.name,
+        &chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        &entries,
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/gates/{id}/depgraph",
+    responses (
+        (status = 200, description = "Dependencies of the latest version of every component of the gate on each other, with the cycles and the dependencies no component of the gate delivers", body = DependencyGraph),
+        (status = 200, description = "The dependency graph as Graphviz DOT", content_type = "text/vnd.graphviz", body = String),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+        GraphParams,
+    )
+)]
+async fn get_gate_dependency_graph(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+    Query(params): Query<GraphParams>,
+) -> Result<Response> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let recipes = latest_components(
+        db.component()
+            .find_many(vec![prisma::component::gate_id::equals(id.to_string())])
+            .exec()
+            .await?,
+    )
+    .into_iter()
+    .map(|c| serde_json::from_value::<Recipe>(c.recipe))
+    .collect::<std::result::Result<Vec<_>, _>>()?;
+    let graph = dependency_graph(&recipes);
+
+    Ok(match params.format {
+        GraphFormat::Json => Json(graph).into_response(),
+        GraphFormat::Dot => (
+            [(header::CONTENT_TYPE, "text/vnd.graphviz")],
+            graph.to_dot(&gate.name),
+        )
+            .into_response(),
+    })
+}
+
+/// The builds a gate rebuild scheduled, in the order they run.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GateRebuild {
+    pub builds: Vec<BuildStep>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/gates/{id}/rebuild",
+    responses (
+        (status = 200, description = "Rebuild of the latest version of every component scheduled", body = GateRebuild),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1")))),
+        (status = 409, description = "The components have a build dependency cycle no bootstrap section breaks", body = ApiError)
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate to rebuild"),
+        RebuildParams,
+    )
+)]
+async fn rebuild_gate(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(id): Path<Uuid>,
+    Query(params): Query<RebuildParams>,
+) -> Result<Json<GateRebuild>> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+    let plan = plan_gate_rebuild(&db, &gate).await?;
+    // Stored component builds share one queue which runs them one at a time in the
+    // order they were queued
+    for (build, component_id) in plan.iter() {
+        let job = forge::Job::BuildStoredComponent {
+            component_id: component_id.clone(),
+            staging: None,
+            bootstrap: build.bootstrap,
+        };
+        enqueue_job(&db, &job, params.priority).await?;
+    }
+    let builds = plan.into_iter().map(|(build, _)| build).collect();
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    dispatch_jobs(&db, &channel, &state.job_inbox).await?;
+
+    Ok(Json(GateRebuild { builds }))
+}
+
+/// The builds of the latest version of every component of the gate in the order they
+/// have to run, with the stored component each one builds.
+pub(crate) async fn plan_gate_rebuild(
+    db: &PrismaClient,
+    gate: &prisma::gate::Data,
+) -> Result<Vec<(BuildStep, forge::ComponentId)>> {
+    let (recipes, component_ids) = latest_gate_recipes(db, gate).await?;
+    let builds = component::build_order(&recipes)
+        .map_err(|e| Error::NotRebuildable(gate.name.clone(), e))?;
+    with_component_ids(builds, &component_ids)
+}
+
+/// The builds of the components named `changed` and of every component of the gate
+/// depending on them, in the order they have to run.
+pub(crate) async fn plan_dependents_rebuild(
+    db: &PrismaClient,
+    gate: &prisma::gate::Data,
+    changed: &[String],
+) -> Result<Vec<(BuildStep, forge::ComponentId)>> {
+    let (recipes, component_ids) = latest_gate_recipes(db, gate).await?;
+    let builds = component::rebuild_order(&recipes, changed)
+        .map_err(|e| Error::NotRebuildable(gate.name.clone(), e))?;
+    with_component_ids(builds, &component_ids)
+}
+
+/// Queue the builds of the components named `changed` and of their dependents in the
+/// gate. The builds still have to be dispatched.
+pub(crate) async fn enqueue_dependents_rebuild(
+    db: &PrismaClient,
+    gate_id: &str,
+    changed: &[String],
+    priority: JobPriority,
+) -> Result<Vec<BuildStep>> {
+    let gate = db
+        .gate()
+        .find_unique(prisma::gate::UniqueWhereParam::IdEquals(
+            gate_id.to_string(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("gate {}", gate_id)))?;
+    let plan = plan_dependents_rebuild(db, &gate, changed).await?;
+    for (build, component_id) in plan.iter() {
+        let job = forge::Job::BuildStoredComponent {
+            component_id: component_id.clone(),
+            staging: None,
+            bootstrap: build.bootstrap,
+        };
+        enqueue_job(db, &job, priority).await?;
+    }
+    Ok(plan.into_iter().map(|(build, _)| build).collect())
+}
+
+/// The recipes of the latest version of every component of the gate with the stored
+/// component of each recipe.
+async fn latest_gate_recipes(
+    db: &PrismaClient,
+    gate: &prisma::gate::Data,
+) -> Result<(Vec<Recipe>, HashMap<String, forge::ComponentId>)> {
+    let gate_id: Uuid = gate
+        .id
+        .parse()
+        .map_err(|_| Error::NoIdFoundINGate(gate.id.clone()))?;
+    let components = db
+        .component()
+        .find_many(vec![prisma::component::gate_id::equals(gate.id.clone())])
+        .exec()
+        .await?;
+
+    let mut recipes = vec![];
+    let mut component_ids = HashMap::new();
+    for component in latest_components(components) {
+        let recipe: Recipe = serde_json::from_value(component.recipe)?;
+        component_ids.insert(
+            recipe.name.clone(),
+            forge::ComponentId {
+                name: component.name,
+                version: component.version,
+                revision: component.revision,
+                gate_id,
+            },
+        );
+        recipes.push(recipe);
+    }
+    Ok((recipes, component_ids))
+}
+
+fn with_component_ids(
+    builds: Vec<BuildStep>,
+    component_ids: &HashMap<String, forge::ComponentId>,
+) -> Result<Vec<(BuildStep, forge::ComponentId)>> {
+    builds
+        .into_iter()
+        .map(|build| {
+            let component_id = component_ids
+                .get(&build.name)
+                .cloned()
+                .ok_or(Error::NotFound(format!("component {}", build.name)))?;
+            Ok((build, component_id))
+        })
+        .collect()
+}
+
+/// Transforms to try on the components of a gate without saving them.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TransformPreviewInput {
+    /// Proposed default transforms, the stored ones if unset
+    pub transforms: Option<Vec<String>>,
+    /// Proposed metadata transforms
+    #[serde(default)]
+    pub metadata_transforms: Vec<MetadataTransform>,
+}
+
+/// What the proposed transforms would change in the packages of a gate.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TransformPreview {
+    /// Number of components the transforms were applied to
+    pub components: usize,
+    pub changes: Vec<TransformPreviewChange>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/gates/{id}/transforms/preview",
+    request_body = TransformPreviewInput,
+    responses (
+        (status = 200, description = "Packages and build options of the latest components the proposed transforms change", body = TransformPreview),
+        (status = 400, description = "A transform failed validation or could not be applied", body = ApiError),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the Gate"),
+    )
+)]
+async fn preview_gate_transforms(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(id): Path<Uuid>,
+    Json(request): Json<TransformPreviewInput>,
+) -> Result<Json<TransformPreview>> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &id.to_string(), &domain_id).await?;
+    let current: Vec<String> = serde_json::from_value(gate.transforms)?;
+    let proposed = request.transforms.unwrap_or(current.clone());
+    validate_transforms(proposed.iter())?;
+
+    let components = db
+        .component()
+        .find_many(vec![prisma::component::gate_id::equals(gate.id.clone())])
+        .exec()
+        .await?;
+    let mut recipes = vec![];
+    for component in latest_components(components) {
+        recipes.push(serde_json::from_value::<Recipe>(component.recipe)?);
+    }
+    recipes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Only the metadata of the packages is known here, their files exist on the
+    // builders alone
+    let mut changes = vec![];
+    for recipe in recipes.iter() {
+        for (package, manifest) in metadata_manifests(recipe, &gate.version, &gate.branch) {
+            let before = mogrify_manifest(&manifest, &current)
+                .map_err(|e| Error::InvalidTransform(current.join("\n"), e))?;
+            let after = mogrify_manifest(&manifest, &proposed)
+                .map_err(|e| Error::InvalidTransform(proposed.join("\n"), e))?;
+            let (removed, added) = diff_lines(&before, &after);
+            if !removed.is_empty() || !added.is_empty() {
+                changes.push(TransformPreviewChange {
+                    component: recipe.name.clone(),
+                    package: Some(package),
+                    removed,
+                    added,
+                });
+            }
+        }
+
+        if !request.metadata_transforms.is_empty() {
+            let (removed, added) = diff_lines(
+                &transform_options(recipe, &[]),
+                &transform_options(recipe, &request.metadata_transforms),
+            );
+            if !removed.is_empty() || !added.is_empty() {
+                changes.push(TransformPreviewChange {
+                    component: recipe.name.clone(),
+                    package: None,
+                    removed,
+                    added,
+                });
+            }
+        }
+    }
+
+    Ok(Json(TransformPreview {
+        components: recipes.len(),
+        changes,
     }))
 }
+
+/// Reject transforms the builders could not apply before they break every build of the
+/// gate.
+fn validate_transforms<'a>(transforms: impl Iterator<Item = &'a String>) -> Result<()> {
+    for transform in transforms {
+        mogrify::validate_transforms(transform)
+            .map_err(|e| Error::InvalidTransform(transform.clone(), e))?;
+    }
+    Ok(())
+}
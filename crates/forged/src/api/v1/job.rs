@@ -0,0 +1,437 @@
+use crate::api::auth::{Authentication, DomainScope};
+use crate::api::v1::change_request::find_change_request;
+use crate::audit::{self, AuditEvent};
+use crate::domain::{find_gate_in_domain, gate_scope};
+use crate::live::JobOutcome;
+use crate::prisma::{self, PrismaClient};
+use crate::scheduler::{self, CancellationState, JobPriority};
+use crate::{AppState, Error, Result};
+use axum::extract::{Path, Query, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use forge::Job;
+use prisma_client_rust::operator::or;
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_jobs))
+        .route("/:id", get(get_job).delete(cancel_job))
+        .route("/:id/logs", get(get_job_logs))
+        .route("/:id/retry", post(retry_job))
+}
+
+/// Where a job is in the queue.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Pending,
+    Running,
+    Finished,
+}
+
+impl From<prisma::QueuedJobState> for JobState {
+    fn from(value: prisma::QueuedJobState) -> Self {
+        match value {
+            prisma::QueuedJobState::Pending => JobState::Pending,
+            prisma::QueuedJobState::Running => JobState::Running,
+            prisma::QueuedJobState::Finished => JobState::Finished,
+        }
+    }
+}
+
+impl From<JobState> for prisma::QueuedJobState {
+    fn from(value: JobState) -> Self {
+        match value {
+            JobState::Pending => prisma::QueuedJobState::Pending,
+            JobState::Running => prisma::QueuedJobState::Running,
+            JobState::Finished => prisma::QueuedJobState::Finished,
+        }
+    }
+}
+
+/// A job forged queued for the workers.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct QueuedJob {
+    pub id: String,
+    /// The reference of the job, like the component it builds
+    pub reference: String,
+    /// The kind of job like `BuildStoredComponent`
+    pub kind: String,
+    pub state: JobState,
+    pub priority: JobPriority,
+    pub gate_id: Option<String>,
+    /// The staging repository the job builds into or promotes
+    pub staging: Option<String>,
+    /// How the job ended, unset until the worker reported it
+    pub outcome: Option<JobOutcome>,
+    pub error: Option<String>,
+    pub correlation_id: Option<String>,
+    pub created_at: String,
+    pub dispatched_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub cancelled_at: Option<String>,
+}
+
+impl TryFrom<prisma::queued_job::Data> for QueuedJob {
+    type Error = Error;
+
+    fn try_from(value: prisma::queued_job::Data) -> Result<Self> {
+        let payload: Job = serde_json::from_value(value.payload)?;
+        Ok(Self {
+            id: value.id,
+            reference: value.reference,
+            kind: payload.kind().to_string(),
+            state: value.state.into(),
+            priority: JobPriority::from_value(value.priority),
+            gate_id: value.gate_id,
+            staging: value.staging,
+            outcome: value.outcome.map(|outcome| match outcome {
+                prisma::QueuedJobOutcome::Success => JobOutcome::Success,
+                prisma::QueuedJobOutcome::Failure => JobOutcome::Failure,
+                prisma::QueuedJobOutcome::Cancelled => JobOutcome::Cancelled,
+            }),
+            error: value.error,
+            correlation_id: value.correlation_id,
+            created_at: value.created_at.to_rfc3339(),
+            dispatched_at: value.dispatched_at.map(|t| t.to_rfc3339()),
+            finished_at: value.finished_at.map(|t| t.to_rfc3339()),
+            cancelled_at: value.cancelled_at.map(|t| t.to_rfc3339()),
+        })
+    }
+}
+
+/// Filters of the job list, every given one has to match.
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobListParams {
+    pub state: Option<JobState>,
+    pub gate_id: Option<Uuid>,
+    /// Only the jobs of the change request, reading its recipes and building or
+    /// promoting its staging repository
+    pub change_request: Option<String>,
+    /// Id of the last job of the previous page
+    pub cursor: Option<String>,
+    #[serde(default = "default_job_limit")]
+    pub limit: i64,
+}
+
+fn default_job_limit() -> i64 {
+    50
+}
+
+/// A line of output of a job.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct JobLogLine {
+    /// Lines of a job are counted from 0
+    pub number: i32,
+    pub content: String,
+}
+
+/// The output of a job the worker sent so far.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct JobLog {
+    pub lines: Vec<JobLogLine>,
+    /// Whether the job finished, the log of a finished job does not grow anymore
+    pub finished: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobLogParams {
+    /// Only lines after this line number, to follow the log of a running job
+    pub after: Option<i32>,
+    #[serde(default = "default_log_limit")]
+    pub limit: i64,
+}
+
+fn default_log_limit() -> i64 {
+    1000
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct JobCancellation {
+    pub id: String,
+    /// The reference of the job, like the component it builds
+    pub reference: String,
+    pub state: CancellationState,
+}
+
+/// Find a queued job by id but only if the gate or change request it works for belongs
+/// to the domain.
+async fn find_job_in_domain(
+    db: &PrismaClient,
+    id: &str,
+    domain_id: &str,
+) -> Result<prisma::queued_job::Data> {
+    let not_found = || Error::NotFound(format!("job {}", id));
+    let job = db
+        .queued_job()
+        .find_unique(prisma::queued_job::UniqueWhereParam::IdEquals(
+            id.to_string(),
+        ))
+        .exec()
+        .await?
+        .ok_or_else(not_found)?;
+
+    let payload: Job = serde_json::from_value(job.payload.clone())?;
+    let found = match (&payload, payload.gate_id()) {
+        (
+            Job::PromoteStagedPackages {
+                change_request_id, ..
+            },
+            _,
+        ) => find_change_request(db, change_request_id, domain_id)
+            .await
+            .map(|_| ()),
+        (_, Some(gate_id)) => find_gate_in_domain(db, &gate_id.to_string(), domain_id)
+            .await
+            .map(|_| ()),
+        (_, None) => Err(not_found()),
+    };
+    // Jobs of other domains are reported as not found like their gates
+    match found {
+        Ok(()) => Ok(job),
+        Err(Error::NotFound(_)) => Err(not_found()),
+        Err(e) => Err(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/",
+    responses (
+        (status = 200, description = "Jobs of the domain matching the filters, newest first", body = Vec<QueuedJob>),
+        (status = 404, description = "Change request or gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("change request 1")))),
+    ),
+    params(JobListParams)
+)]
+async fn list_jobs(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Query(params): Query<JobListParams>,
+) -> Result<Json<Vec<QueuedJob>>> {
+    let db = state.prisma.clone();
+    let mut filter = vec![];
+
+    if let Some(change_request_id) = params.change_request {
+        // Jobs of change requests are found by what they work on, the promotion of the
+        // staging repository has no gate
+        let change_request = find_change_request(&db, &change_request_id, &domain_id).await?;
+        filter.push(or(vec![
+            prisma::queued_job::reference::equals(change_request.id.clone()),
+            prisma::queued_job::staging::equals(Some(forge::staging_repository_name(
+                &change_request.id,
+            ))),
+        ]));
+    } else {
+        let gate_ids = db
+            .gate()
+            .find_many(vec![gate_scope(&domain_id)])
+            .exec()
+            .await?
+            .into_iter()
+            .map(|gate| gate.id)
+            .collect();
+        filter.push(prisma::queued_job::gate_id::in_vec(gate_ids));
+    }
+
+    if let Some(gate_id) = params.gate_id {
+        let gate = find_gate_in_domain(&db, &gate_id.to_string(), &domain_id).await?;
+        filter.push(prisma::queued_job::gate_id::equals(Some(gate.id)));
+    }
+
+    if let Some(job_state) = params.state {
+        filter.push(prisma::queued_job::state::equals(job_state.into()));
+    }
+
+    let mut query = db
+        .queued_job()
+        .find_many(filter)
+        .order_by(prisma::queued_job::created_at::order(Direction::Desc))
+        .take(params.limit);
+
+    if let Some(cursor) = params.cursor {
+        query = query.cursor(prisma::queued_job::id::equals(cursor)).skip(1);
+    }
+
+    let jobs = query
+        .exec()
+        .await?
+        .into_iter()
+        .map(QueuedJob::try_from)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Json(jobs))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{id}",
+    responses (
+        (status = 200, description = "The job", body = QueuedJob),
+        (status = 404, description = "Job not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("job 1")))),
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Id of the queued job"),
+    )
+)]
+async fn get_job(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QueuedJob>> {
+    let db = state.prisma.clone();
+    let job = find_job_in_domain(&db, &id.to_string(), &domain_id).await?;
+    Ok(Json(job.try_into()?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{id}/logs",
+    responses (
+        (status = 200, description = "Output of the job, oldest line first", body = JobLog),
+        (status = 404, description = "Job not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("job 1")))),
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Id of the queued job"),
+        JobLogParams
+    )
+)]
+async fn get_job_logs(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+    Query(params): Query<JobLogParams>,
+) -> Result<Json<JobLog>> {
+    let db = state.prisma.clone();
+    let job = find_job_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let mut filter = vec![prisma::job_log_line::job_id::equals(job.id)];
+    if let Some(after) = params.after {
+        filter.push(prisma::job_log_line::number::gt(after));
+    }
+    let lines = db
+        .job_log_line()
+        .find_many(filter)
+        .order_by(prisma::job_log_line::number::order(Direction::Asc))
+        .take(params.limit)
+        .exec()
+        .await?;
+
+    Ok(Json(JobLog {
+        lines: lines
+            .into_iter()
+            .map(|line| JobLogLine {
+                number: line.number,
+                content: line.content,
+            })
+            .collect(),
+        finished: job.state == prisma::QueuedJobState::Finished,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/jobs/{id}/retry",
+    responses (
+        (status = 200, description = "The job was queued again, returns the new job", body = QueuedJob),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Job not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("job 1")))),
+        (status = 409, description = "The job did not fail or was not cancelled", body = ApiError)
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Id of the queued job"),
+    )
+)]
+async fn retry_job(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QueuedJob>> {
+    let db = state.prisma.clone();
+    let job = find_job_in_domain(&db, &id.to_string(), &domain_id).await?;
+    if job.state != prisma::QueuedJobState::Finished
+        || job.outcome == Some(prisma::QueuedJobOutcome::Success)
+    {
+        return Err(Error::NotRetryable(format!(
+            "job {} did not fail and was not cancelled",
+            job.id
+        )));
+    }
+
+    let payload: Job = serde_json::from_value(job.payload.clone())?;
+    let retry_id =
+        scheduler::enqueue_job(&db, &payload, JobPriority::from_value(job.priority)).await?;
+    state.github_status.job_queued(&db, &payload).await;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("job.retry", "job", &job.id).after(&retry_id)?,
+    )
+    .await?;
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    scheduler::dispatch_jobs(&db, &channel, &state.job_inbox).await?;
+
+    let retry = db
+        .queued_job()
+        .find_unique(prisma::queued_job::UniqueWhereParam::IdEquals(retry_id))
+        .exec()
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("job {}", job.id)))?;
+    Ok(Json(retry.try_into()?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/jobs/{id}",
+    responses (
+        (status = 200, description = "Pending job cancelled or the worker of the running job told to abort it", body = JobCancellation),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Job not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("job 1")))),
+        (status = 409, description = "The job already finished", body = ApiError)
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Id of the queued job"),
+    )
+)]
+async fn cancel_job(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobCancellation>> {
+    let db = state.prisma.clone();
+    let job = find_job_in_domain(&db, &id.to_string(), &domain_id).await?;
+
+    let conn = state.amqp.get().await?;
+    let channel = conn.create_channel().await?;
+    let cancellation = scheduler::cancel_job(&db, &channel, &job).await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("job.cancel", "job", &job.id)
+            .before(&job.state)?
+            .after(&cancellation)?,
+    )
+    .await?;
+    if cancellation == CancellationState::Cancelled {
+        // The slot of the job may be taken by the next one right away
+        scheduler::dispatch_jobs(&db, &channel, &state.job_inbox).await?;
+    }
+
+    Ok(Json(JobCancellation {
+        id: job.id,
+        reference: job.reference,
+        state: cancellation,
+    }))
+}
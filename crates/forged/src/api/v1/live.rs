@@ -0,0 +1,64 @@
+use crate::api::auth::Authentication;
+use crate::live::LiveUpdate;
+use crate::AppState;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tracing::{debug, warn};
+
+pub fn get_router() -> Router<AppState> {
+    Router::new().route("/", get(live_updates))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/live",
+    responses (
+        (status = 101, description = "Websocket sending a LiveUpdate JSON text message for every change request and job update of the domain"),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+    )
+)]
+async fn live_updates(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let updates = state.updates.subscribe();
+    ws.on_upgrade(move |socket| send_updates(socket, updates, domain_id))
+}
+
+async fn send_updates(
+    mut socket: WebSocket,
+    mut updates: Receiver<(String, LiveUpdate)>,
+    domain_id: String,
+) {
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok((update_domain, update)) if update_domain == domain_id => {
+                    let Ok(text) = serde_json::to_string(&update) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("live update client fell behind and missed {} updates", missed);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Clients only listen, pings are answered by axum
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("live update client of domain {} disconnected", domain_id);
+}
@@ -0,0 +1,144 @@
+use crate::api::auth::{Authentication, DomainScope};
+use crate::audit::{self, AuditEvent};
+use crate::ownership::{find_actor, is_domain_admin};
+use crate::{prisma, AppState, Error, Result};
+use axum::extract::State;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new().route("/", get(list_maintainers).put(map_maintainer))
+}
+
+/// A maintainer recipes name and the actor it is mapped to.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Maintainer {
+    /// The maintainer as recipes name it, like `Jane Doe <jane@example.org>`
+    pub name: String,
+    /// Handle of the actor, unset if the maintainer is not mapped to one
+    pub actor: Option<String>,
+}
+
+impl From<prisma::maintainer::Data> for Maintainer {
+    fn from(value: prisma::maintainer::Data) -> Self {
+        Self {
+            name: value.name,
+            actor: value.actor.flatten().map(|a| a.handle),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct MapMaintainerInput {
+    /// The maintainer as recipes name it
+    pub name: String,
+    /// Handle of the actor the maintainer is, unset to remove the mapping
+    pub actor: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/maintainers/",
+    responses (
+        (status = 200, description = "Maintainers of the domain and the actors they are mapped to", body = Vec<Maintainer>),
+    )
+)]
+async fn list_maintainers(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+) -> Result<Json<Vec<Maintainer>>> {
+    let maintainers = state
+        .prisma
+        .maintainer()
+        .find_many(vec![prisma::maintainer::domain_id::equals(domain_id)])
+        .with(prisma::maintainer::actor::fetch())
+        .order_by(prisma::maintainer::name::order(
+            prisma_client_rust::Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(
+        maintainers.into_iter().map(Maintainer::from).collect(),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/maintainers/",
+    request_body = MapMaintainerInput,
+    responses (
+        (status = 200, description = "Maintainer mapped to the actor", body = Maintainer),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Actor not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("actor jane"))))
+    )
+)]
+async fn map_maintainer(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Json(request): Json<MapMaintainerInput>,
+) -> Result<Json<Maintainer>> {
+    let db = state.prisma.clone();
+    let before = db
+        .maintainer()
+        .find_unique(prisma::maintainer::UniqueWhereParam::NameDomainIdEquals(
+            request.name.clone(),
+            domain_id.clone(),
+        ))
+        .with(prisma::maintainer::actor::fetch())
+        .exec()
+        .await?
+        .map(Maintainer::from);
+    // Mapping a maintainer hands out the components it maintains
+    let caller = find_actor(&db, &handle, &domain_id).await?;
+    if !is_domain_admin(&db, &domain_id, &caller.id).await? {
+        return Err(Error::NotDomainAdmin(handle));
+    }
+
+    let actor = match &request.actor {
+        Some(actor) => Some(find_actor(&db, actor, &domain_id).await?),
+        None => None,
+    };
+    let connect = |actor: &prisma::actor::Data| {
+        prisma::maintainer::actor::connect(prisma::actor::UniqueWhereParam::IdEquals(
+            actor.id.clone(),
+        ))
+    };
+    let maintainer = db
+        .maintainer()
+        .upsert(
+            prisma::maintainer::UniqueWhereParam::NameDomainIdEquals(
+                request.name.clone(),
+                domain_id.clone(),
+            ),
+            (
+                request.name,
+                prisma::domain::UniqueWhereParam::IdEquals(domain_id.clone()),
+                actor.iter().map(connect).collect(),
+            ),
+            vec![actor
+                .as_ref()
+                .map(connect)
+                .unwrap_or_else(prisma::maintainer::actor::disconnect)],
+        )
+        .with(prisma::maintainer::actor::fetch())
+        .exec()
+        .await?;
+    let maintainer = Maintainer::from(maintainer);
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("maintainer.map", "maintainer", &maintainer.name)
+            .before(&before)?
+            .after(&maintainer)?,
+    )
+    .await?;
+
+    Ok(Json(maintainer))
+}
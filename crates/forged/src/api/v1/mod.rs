@@ -1,21 +1,49 @@
 pub mod actor;
+pub mod audit;
 pub mod auth;
+pub mod blob;
+pub mod change_request;
 pub mod component;
+pub mod file;
 pub mod gate;
+pub mod job;
+pub mod live;
+pub mod maintainer;
+pub mod notification;
 pub mod publisher;
+pub mod queue;
+pub mod retention;
+pub mod snapshot;
+pub mod source;
+pub mod webhook;
 
+use crate::scheduler::JobPriority;
 use crate::AppState;
 use axum::Router;
+use component::{GraphFormat, SbomFormat};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 pub fn get_v1_router() -> Router<AppState> {
     Router::new()
         .nest("/auth", auth::get_router())
         .nest("/actors", actor::get_router())
+        .nest("/audit", audit::get_router())
         .nest("/components", component::get_router())
+        .nest("/change_requests", change_request::get_router())
         .nest("/publishers", publisher::get_router())
         .nest("/gates", gate::get_router())
+        .nest("/maintainers", maintainer::get_router())
+        .nest("/retention", retention::get_router())
+        .nest("/blobs", blob::get_router())
+        .nest("/queue", queue::get_router())
+        .nest("/jobs", job::get_router())
+        .nest("/live", live::get_router())
+        .nest("/files", file::get_router())
+        .nest("/sources", source::get_router())
+        .nest("/snapshots", snapshot::get_router())
+        .nest("/webhooks", webhook::get_router())
+        .nest("/notifications", notification::get_router())
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -24,6 +52,33 @@ pub struct PaginationInput {
     pub limit: i64,
 }
 
+/// Query parameters of the endpoints scheduling builds.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RebuildParams {
+    /// Priority of the build jobs, `security` rebuilds go before everything else
+    #[serde(default)]
+    pub priority: JobPriority,
+}
+
+/// Query parameters of the endpoints rendering a bill of materials.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SbomParams {
+    /// Document format, `cyclonedx` or `spdx`
+    #[serde(default)]
+    pub format: SbomFormat,
+}
+
+/// Query parameters of the endpoints rendering a dependency graph.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GraphParams {
+    /// Graph format, `json` or `dot`
+    #[serde(default)]
+    pub format: GraphFormat,
+}
+
 impl Default for PaginationInput {
     fn default() -> Self {
         Self {
@@ -0,0 +1,457 @@
+use crate::api::auth::Authentication;
+use crate::audit::{self, AuditEvent};
+use crate::domain::publisher_scope;
+use crate::notification::NotificationTarget;
+use crate::ownership::{find_actor, is_domain_admin};
+use crate::prisma::{self, PrismaClient};
+use crate::{AppState, Error, Result};
+use axum::extract::{Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/subscriptions",
+            get(list_subscriptions).post(create_subscription),
+        )
+        .route(
+            "/subscriptions/:id",
+            get(get_subscription)
+                .put(update_subscription)
+                .delete(delete_subscription),
+        )
+        .route("/subscriptions/:id/notifications", get(list_notifications))
+}
+
+/// Where a publisher is told about events of its gates. Secrets of the target are hidden.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Subscription {
+    pub id: String,
+    pub publisher_id: String,
+    pub name: String,
+    pub target: NotificationTarget,
+    /// Events like `change_request.applied`, `build.failed` or `snapshot.created`,
+    /// every event if empty
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub created_at: String,
+}
+
+impl TryFrom<prisma::notification_subscription::Data> for Subscription {
+    type Error = Error;
+
+    fn try_from(value: prisma::notification_subscription::Data) -> Result<Self> {
+        let target: NotificationTarget = serde_json::from_value(value.target)?;
+        Ok(Self {
+            id: value.id,
+            publisher_id: value.publisher_id,
+            name: value.name,
+            target: target.redacted(),
+            events: value.events,
+            enabled: value.enabled,
+            created_at: value.created_at.to_rfc3339(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CreateSubscriptionInput {
+    pub publisher_id: String,
+    pub name: String,
+    pub target: NotificationTarget,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// Changes to a subscription, unset fields are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct UpdateSubscriptionInput {
+    pub target: Option<NotificationTarget>,
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationState {
+    /// Waiting for its first or next attempt
+    Pending,
+    Delivered,
+    /// Gave up after too many failed attempts
+    Failed,
+}
+
+impl From<prisma::NotificationState> for NotificationState {
+    fn from(value: prisma::NotificationState) -> Self {
+        match value {
+            prisma::NotificationState::Pending => NotificationState::Pending,
+            prisma::NotificationState::Delivered => NotificationState::Delivered,
+            prisma::NotificationState::Failed => NotificationState::Failed,
+        }
+    }
+}
+
+/// An event delivered or to deliver to a subscription.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Notification {
+    pub id: String,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub state: NotificationState,
+    pub attempts: i32,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub next_attempt_at: String,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+impl From<prisma::notification::Data> for Notification {
+    fn from(value: prisma::notification::Data) -> Self {
+        Self {
+            id: value.id,
+            event: value.event,
+            payload: value.payload,
+            state: value.state.into(),
+            attempts: value.attempts,
+            error: value.error,
+            next_attempt_at: value.next_attempt_at.to_rfc3339(),
+            created_at: value.created_at.to_rfc3339(),
+            delivered_at: value.delivered_at.map(|d| d.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriptionListParams {
+    /// Only subscriptions of this publisher
+    pub publisher_id: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationListParams {
+    /// Id of the last notification of the previous page
+    pub cursor: Option<String>,
+    #[serde(default = "default_notification_limit")]
+    pub limit: i64,
+}
+
+fn default_notification_limit() -> i64 {
+    50
+}
+
+/// Subscriptions hold the secrets of their targets, only domain administrators manage
+/// them.
+async fn ensure_domain_admin(db: &PrismaClient, handle: &str, domain_id: &str) -> Result<()> {
+    let actor = find_actor(db, handle, domain_id).await?;
+    if !is_domain_admin(db, domain_id, &actor.id).await? {
+        return Err(Error::NotDomainAdmin(handle.to_string()));
+    }
+    Ok(())
+}
+
+/// The subscription if its publisher belongs to the domain.
+async fn find_subscription_in_domain(
+    db: &PrismaClient,
+    id: &Uuid,
+    domain_id: &str,
+) -> Result<prisma::notification_subscription::Data> {
+    db.notification_subscription()
+        .find_first(vec![
+            prisma::notification_subscription::id::equals(id.to_string()),
+            prisma::notification_subscription::publisher::is(vec![publisher_scope(domain_id)]),
+        ])
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("notification subscription {}", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications/subscriptions",
+    responses (
+        (status = 200, description = "Notification subscriptions of the domain", body = Vec<Subscription>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError)
+    ),
+    params(SubscriptionListParams)
+)]
+pub async fn list_subscriptions(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Query(params): Query<SubscriptionListParams>,
+) -> Result<Json<Vec<Subscription>>> {
+    let db = state.prisma.clone();
+    ensure_domain_admin(&db, &handle, &domain_id).await?;
+
+    let mut filter = vec![prisma::notification_subscription::publisher::is(vec![
+        publisher_scope(&domain_id),
+    ])];
+    if let Some(publisher_id) = params.publisher_id {
+        filter.push(prisma::notification_subscription::publisher_id::equals(
+            publisher_id.to_string(),
+        ));
+    }
+    Ok(Json(
+        db.notification_subscription()
+            .find_many(filter)
+            .order_by(prisma::notification_subscription::name::order(
+                Direction::Asc,
+            ))
+            .exec()
+            .await?
+            .into_iter()
+            .map(Subscription::try_from)
+            .collect::<Result<Vec<_>>>()?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/notifications/subscriptions",
+    request_body = CreateSubscriptionInput,
+    responses (
+        (status = 200, description = "The created subscription", body = Subscription),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Publisher not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("publisher 1"))))
+    )
+)]
+pub async fn create_subscription(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Json(request): Json<CreateSubscriptionInput>,
+) -> Result<Json<Subscription>> {
+    let db = state.prisma.clone();
+    ensure_domain_admin(&db, &handle, &domain_id).await?;
+    let publisher = db
+        .publisher()
+        .find_first(vec![
+            prisma::publisher::id::equals(request.publisher_id.clone()),
+            publisher_scope(&domain_id),
+        ])
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("publisher {}", request.publisher_id)))?;
+
+    let subscription = db
+        .notification_subscription()
+        .create(
+            prisma::publisher::UniqueWhereParam::IdEquals(publisher.id),
+            request.name,
+            serde_json::to_value(&request.target)?,
+            vec![prisma::notification_subscription::events::set(
+                request.events,
+            )],
+        )
+        .exec()
+        .await?;
+    let subscription = Subscription::try_from(subscription)?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new(
+            "notification_subscription.create",
+            "notification_subscription",
+            &subscription.id,
+        )
+        .after(&subscription)?,
+    )
+    .await?;
+    Ok(Json(subscription))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications/subscriptions/{id}",
+    responses (
+        (status = 200, description = "The subscription", body = Subscription),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Subscription not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("notification subscription 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the subscription"),
+    )
+)]
+pub async fn get_subscription(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Subscription>> {
+    let db = state.prisma.clone();
+    ensure_domain_admin(&db, &handle, &domain_id).await?;
+    let subscription = find_subscription_in_domain(&db, &id, &domain_id).await?;
+    Ok(Json(Subscription::try_from(subscription)?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/notifications/subscriptions/{id}",
+    request_body = UpdateSubscriptionInput,
+    responses (
+        (status = 200, description = "The updated subscription", body = Subscription),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Subscription not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("notification subscription 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the subscription"),
+    )
+)]
+pub async fn update_subscription(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+    Json(request): Json<UpdateSubscriptionInput>,
+) -> Result<Json<Subscription>> {
+    let db = state.prisma.clone();
+    ensure_domain_admin(&db, &handle, &domain_id).await?;
+    let before = find_subscription_in_domain(&db, &id, &domain_id).await?;
+
+    let mut update = vec![];
+    if let Some(target) = &request.target {
+        update.push(prisma::notification_subscription::target::set(
+            serde_json::to_value(target)?,
+        ));
+    }
+    if let Some(events) = request.events {
+        update.push(prisma::notification_subscription::events::set(events));
+    }
+    if let Some(enabled) = request.enabled {
+        update.push(prisma::notification_subscription::enabled::set(enabled));
+    }
+    let subscription = db
+        .notification_subscription()
+        .update(
+            prisma::notification_subscription::UniqueWhereParam::IdEquals(before.id.clone()),
+            update,
+        )
+        .exec()
+        .await?;
+    let subscription = Subscription::try_from(subscription)?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new(
+            "notification_subscription.update",
+            "notification_subscription",
+            &subscription.id,
+        )
+        .before(&Subscription::try_from(before)?)?
+        .after(&subscription)?,
+    )
+    .await?;
+    Ok(Json(subscription))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/notifications/subscriptions/{id}",
+    responses (
+        (status = 200, description = "The deleted subscription, its pending notifications are dropped", body = Subscription),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Subscription not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("notification subscription 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the subscription"),
+    )
+)]
+pub async fn delete_subscription(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Subscription>> {
+    let db = state.prisma.clone();
+    ensure_domain_admin(&db, &handle, &domain_id).await?;
+    let subscription = find_subscription_in_domain(&db, &id, &domain_id).await?;
+    db.notification_subscription()
+        .delete(prisma::notification_subscription::UniqueWhereParam::IdEquals(
+            subscription.id.clone(),
+        ))
+        .exec()
+        .await?;
+    let subscription = Subscription::try_from(subscription)?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new(
+            "notification_subscription.delete",
+            "notification_subscription",
+            &subscription.id,
+        )
+        .before(&subscription)?,
+    )
+    .await?;
+    Ok(Json(subscription))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/notifications/subscriptions/{id}/notifications",
+    responses (
+        (status = 200, description = "Notifications of the subscription, newest first", body = Vec<Notification>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Subscription not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("notification subscription 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the subscription"),
+        NotificationListParams
+    )
+)]
+pub async fn list_notifications(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+    Query(params): Query<NotificationListParams>,
+) -> Result<Json<Vec<Notification>>> {
+    let db = state.prisma.clone();
+    ensure_domain_admin(&db, &handle, &domain_id).await?;
+    let subscription = find_subscription_in_domain(&db, &id, &domain_id).await?;
+
+    let mut query = db
+        .notification()
+        .find_many(vec![prisma::notification::subscription_id::equals(
+            subscription.id,
+        )])
+        .order_by(prisma::notification::created_at::order(Direction::Desc))
+        .take(params.limit);
+    if let Some(cursor) = params.cursor {
+        query = query
+            .cursor(prisma::notification::id::equals(cursor))
+            .skip(1);
+    }
+
+    Ok(Json(
+        query
+            .exec()
+            .await?
+            .into_iter()
+            .map(Notification::from)
+            .collect(),
+    ))
+}
@@ -1,17 +1,35 @@
-use axum::extract::State;
+use axum::extract::{Path, State};
+use axum::http::header;
+use axum::response::IntoResponse;
 use axum::routing::{get, post};
 use axum::{Json, Router};
+use prisma_client_rust::Direction;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::api::auth::Authentication;
+use crate::api::auth::{Authentication, DomainScope};
 use crate::api::v1::PaginationInput;
-use crate::{prisma, AppState, Result};
+use crate::audit::{self, AuditEvent};
+use crate::domain::publisher_scope;
+use crate::ownership::{find_actor, is_domain_admin};
+use crate::prisma::PrismaClient;
+use crate::signing::{current_signing_key, rotate_signing_key};
+use crate::{prisma, AppState, Error, Result};
 
 pub fn get_router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_publishers))
         .route("/", post(create_publisher))
+        .route(
+            "/:name/signing_keys",
+            get(list_signing_keys).post(create_signing_key),
+        )
+        .route("/:name/signing_key.pub", get(get_public_key))
+        .route("/:name/signing_key/secret", get(export_secret_key))
+        .route(
+            "/:name/manifest_template",
+            get(get_manifest_template).put(set_manifest_template),
+        )
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
@@ -37,15 +55,17 @@ pub struct CreatePublisherInput {
 )]
 async fn create_publisher(
     State(state): State<AppState>,
-    Authentication { .. }: Authentication,
+    Authentication { domain_id, .. }: Authentication,
     Json(request): Json<CreatePublisherInput>,
 ) -> Result<Json<Publisher>> {
     let publisher = state
         .prisma
-        .lock()
-        .await
         .publisher()
-        .create(request.name, vec![])
+        .create(
+            request.name,
+            prisma::domain::UniqueWhereParam::IdEquals(domain_id),
+            vec![],
+        )
         .exec()
         .await?;
     Ok(Json(Publisher {
@@ -64,13 +84,14 @@ async fn create_publisher(
 )]
 async fn list_publishers(
     State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
     Json(pagination): Json<Option<PaginationInput>>,
 ) -> Result<Json<Vec<Publisher>>> {
-    let db_client = state.prisma.lock().await;
+    let db_client = state.prisma.clone();
     let pagination = pagination.unwrap_or_default();
     let mut query = db_client
         .publisher()
-        .find_many(vec![])
+        .find_many(vec![publisher_scope(&domain_id)])
         .take(pagination.limit);
 
     if let Some(cursor) = pagination.cursor {
@@ -89,3 +110,303 @@ async fn list_publishers(
             .collect(),
     ))
 }
+
+/// A key the packages of a publisher are signed with. The secret half is only handed out
+/// by the export endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SigningKey {
+    /// Key number signatures name the key with, in hex
+    pub key_id: String,
+    /// The public key in the format of signify
+    pub public_key: String,
+    pub created_at: String,
+    /// When a newer key replaced it, packages signed before still verify with it
+    pub retired_at: Option<String>,
+}
+
+impl From<prisma::signing_key::Data> for SigningKey {
+    fn from(value: prisma::signing_key::Data) -> Self {
+        Self {
+            key_id: value.key_id,
+            public_key: value.public_key,
+            created_at: value.created_at.to_rfc3339(),
+            retired_at: value.retired_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+async fn find_publisher_by_name(
+    db: &PrismaClient,
+    name: &str,
+    domain_id: &str,
+) -> Result<prisma::publisher::Data> {
+    db.publisher()
+        .find_first(vec![
+            publisher_scope(domain_id),
+            prisma::publisher::name::equals(name.to_string()),
+        ])
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("publisher {}", name)))
+}
+
+/// Whoever holds the secret key signs in the name of the publisher, only domain
+/// administrators manage the keys.
+async fn ensure_domain_admin(db: &PrismaClient, handle: &str, domain_id: &str) -> Result<()> {
+    let actor = find_actor(db, handle, domain_id).await?;
+    if !is_domain_admin(db, domain_id, &actor.id).await? {
+        return Err(Error::NotDomainAdmin(handle.to_string()));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/publishers/{name}/signing_keys",
+    responses (
+        (status = 200, description = "Signing keys of the publisher, newest first", body = Vec<SigningKey>),
+        (status = 404, description = "Publisher not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("publisher userland"))))
+    ),
+    params(
+        ("name" = String, Path, description = "Name of the publisher"),
+    )
+)]
+async fn list_signing_keys(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<SigningKey>>> {
+    let db = &state.prisma;
+    let publisher = find_publisher_by_name(db, &name, &domain_id).await?;
+    let keys = db
+        .signing_key()
+        .find_many(vec![prisma::signing_key::publisher_id::equals(
+            publisher.id,
+        )])
+        .order_by(prisma::signing_key::created_at::order(Direction::Desc))
+        .exec()
+        .await?;
+    Ok(Json(keys.into_iter().map(SigningKey::from).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/publishers/{name}/signing_keys",
+    responses (
+        (status = 200, description = "The new key the publisher signs with, the previous one is retired", body = SigningKey),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Publisher not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("publisher userland"))))
+    ),
+    params(
+        ("name" = String, Path, description = "Name of the publisher"),
+    )
+)]
+async fn create_signing_key(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(name): Path<String>,
+) -> Result<Json<SigningKey>> {
+    let db = &state.prisma;
+    ensure_domain_admin(db, &handle, &domain_id).await?;
+    let publisher = find_publisher_by_name(db, &name, &domain_id).await?;
+    let key = SigningKey::from(rotate_signing_key(db, &state.secret_key_cipher, &publisher).await?);
+    audit::record(
+        db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("signing_key.create", "publisher", &publisher.id).after(&key)?,
+    )
+    .await?;
+    Ok(Json(key))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/publishers/{name}/signing_key.pub",
+    responses (
+        (status = 200, description = "The public key the publisher signs with now, for signify -V -p", body = String, content_type = "text/plain"),
+        (status = 404, description = "Publisher not found or it has no signing key", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("publisher userland"))))
+    ),
+    params(
+        ("name" = String, Path, description = "Name of the publisher"),
+    )
+)]
+async fn get_public_key(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse> {
+    let db = &state.prisma;
+    let publisher = find_publisher_by_name(db, &name, &domain_id).await?;
+    let key = current_signing_key(db, &publisher.id)
+        .await?
+        .ok_or(Error::NotFound(format!(
+            "signing key of publisher {}",
+            name
+        )))?;
+    Ok(([(header::CONTENT_TYPE, "text/plain")], key.public_key))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/publishers/{name}/signing_key/secret",
+    responses (
+        (status = 200, description = "The secret key the publisher signs with now, for build hosts signing what they make", body = String, content_type = "text/plain"),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Publisher not found or it has no signing key", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("publisher userland"))))
+    ),
+    params(
+        ("name" = String, Path, description = "Name of the publisher"),
+    )
+)]
+async fn export_secret_key(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse> {
+    let db = &state.prisma;
+    ensure_domain_admin(db, &handle, &domain_id).await?;
+    let publisher = find_publisher_by_name(db, &name, &domain_id).await?;
+    let key = current_signing_key(db, &publisher.id)
+        .await?
+        .ok_or(Error::NotFound(format!(
+            "signing key of publisher {}",
+            name
+        )))?;
+    // Every copy of the secret key is recorded, the audit entry holds only its number
+    audit::record(
+        db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("signing_key.export", "publisher", &publisher.id)
+            .after(&SigningKey::from(key.clone()))?,
+    )
+    .await?;
+    let secret_key = state
+        .secret_key_cipher
+        .decrypt(&key.secret_key, &key.key_id)?;
+    Ok(([(header::CONTENT_TYPE, "text/plain")], secret_key))
+}
+
+/// Boilerplate and header the IPS manifests of the publisher start with. Parts left out
+/// are the ones of OpenIndiana, gates override them in their gate.kdl.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct ManifestTemplate {
+    /// License of the manifests themselves, written as a comment block on top
+    pub boilerplate: Option<String>,
+    /// The actions every manifest starts with
+    pub header: Option<String>,
+    /// The variables the header can use as `{name}`, ignored when the template is set
+    #[serde(default)]
+    pub variables: Vec<TemplateVariable>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TemplateVariable {
+    pub name: String,
+    /// What pkgdev substitutes for it
+    pub description: String,
+}
+
+impl From<gate::ManifestTemplate> for ManifestTemplate {
+    fn from(value: gate::ManifestTemplate) -> Self {
+        Self {
+            boilerplate: value.boilerplate,
+            header: value.header,
+            variables: gate::MANIFEST_TEMPLATE_VARIABLES
+                .iter()
+                .map(|(name, description)| TemplateVariable {
+                    name: name.to_string(),
+                    description: description.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/publishers/{name}/manifest_template",
+    responses (
+        (status = 200, description = "The manifest template of the publisher", body = ManifestTemplate),
+        (status = 404, description = "Publisher not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("publisher userland"))))
+    ),
+    params(
+        ("name" = String, Path, description = "Name of the publisher"),
+    )
+)]
+async fn get_manifest_template(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(name): Path<String>,
+) -> Result<Json<ManifestTemplate>> {
+    let publisher = find_publisher_by_name(&state.prisma, &name, &domain_id).await?;
+    let template: gate::ManifestTemplate = publisher
+        .manifest_template
+        .map(serde_json::from_value)
+        .transpose()?
+        .unwrap_or_default();
+    Ok(Json(template.into()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/publishers/{name}/manifest_template",
+    request_body = ManifestTemplate,
+    responses (
+        (status = 200, description = "The manifest template of the publisher was replaced", body = ManifestTemplate),
+        (status = 400, description = "The header uses unknown variables or sets no pkg.fmri", body = ApiError),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the domain", body = ApiError),
+        (status = 404, description = "Publisher not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("publisher userland"))))
+    ),
+    params(
+        ("name" = String, Path, description = "Name of the publisher"),
+    )
+)]
+async fn set_manifest_template(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(name): Path<String>,
+    Json(request): Json<ManifestTemplate>,
+) -> Result<Json<ManifestTemplate>> {
+    let db = &state.prisma;
+    ensure_domain_admin(db, &handle, &domain_id).await?;
+    let before = find_publisher_by_name(db, &name, &domain_id).await?;
+    let template = gate::ManifestTemplate {
+        boilerplate: request.boilerplate,
+        header: request.header,
+    };
+    template
+        .validate()
+        .map_err(|e| Error::InvalidManifestTemplate(e.to_string()))?;
+
+    let after = db
+        .publisher()
+        .update(
+            prisma::publisher::id::equals(before.id.clone()),
+            vec![prisma::publisher::manifest_template::set(Some(
+                serde_json::to_value(&template)?,
+            ))],
+        )
+        .exec()
+        .await?;
+    audit::record(
+        db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("publisher.manifest_template", "publisher", &after.id)
+            .before(&before.manifest_template)?
+            .after(&after.manifest_template)?,
+    )
+    .await?;
+    Ok(Json(template.into()))
+}
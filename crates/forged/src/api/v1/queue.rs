@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use crate::scheduler::queue_metrics;
+use crate::{AppState, Result};
+use axum::extract::State;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new().route("/metrics", get(get_queue_metrics))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct JobKindBacklog {
+    pub pending: i64,
+    pub running: i64,
+}
+
+/// The job queue of all domains as autoscalers of the workers see it.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct QueueMetrics {
+    pub pending: i64,
+    pub running: i64,
+    /// Average seconds jobs dispatched in the last hour waited in the queue
+    pub average_wait_seconds: f64,
+    /// Seconds the oldest pending job has been waiting
+    pub oldest_pending_seconds: i64,
+    /// Backlog by the kind of job
+    pub kinds: BTreeMap<String, JobKindBacklog>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/queue/metrics",
+    responses (
+        (status = 200, description = "Queue depth, wait time and backlog by job kind", body = QueueMetrics),
+    )
+)]
+async fn get_queue_metrics(State(state): State<AppState>) -> Result<Json<QueueMetrics>> {
+    let metrics = queue_metrics(&state.prisma).await?;
+    Ok(Json(QueueMetrics {
+        pending: metrics.pending as i64,
+        running: metrics.running as i64,
+        average_wait_seconds: metrics.average_wait_seconds,
+        oldest_pending_seconds: metrics.oldest_pending_seconds,
+        kinds: metrics
+            .kinds
+            .into_iter()
+            .map(|(kind, backlog)| {
+                (
+                    kind,
+                    JobKindBacklog {
+                        pending: backlog.pending as i64,
+                        running: backlog.running as i64,
+                    },
+                )
+            })
+            .collect(),
+    }))
+}
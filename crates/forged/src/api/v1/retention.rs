@@ -0,0 +1,60 @@
+use crate::api::auth::DomainScope;
+use crate::{prisma, AppState, Result};
+use axum::extract::State;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new().route("/runs", get(list_retention_runs))
+}
+
+/// What one run of the retention policy removed from the database and the blob store.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RetentionRun {
+    pub created_at: String,
+    pub jobs: i32,
+    pub component_changes: i32,
+    pub change_requests: i32,
+    pub blobs: i32,
+    pub reclaimed_database_bytes: i64,
+    pub reclaimed_blob_bytes: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/retention/runs",
+    responses (
+        (status = 200, description = "Retention runs of the domain, oldest first", body = Vec<RetentionRun>),
+        (status = 404, description = "Domain not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("no domain found"))))
+    )
+)]
+async fn list_retention_runs(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+) -> Result<Json<Vec<RetentionRun>>> {
+    let runs = state
+        .prisma
+        .retention_run()
+        .find_many(vec![prisma::retention_run::domain_id::equals(domain_id)])
+        .order_by(prisma::retention_run::created_at::order(
+            prisma_client_rust::Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    Ok(Json(
+        runs.into_iter()
+            .map(|r| RetentionRun {
+                created_at: r.created_at.to_rfc3339(),
+                jobs: r.jobs,
+                component_changes: r.component_changes,
+                change_requests: r.change_requests,
+                blobs: r.blobs,
+                reclaimed_database_bytes: r.reclaimed_database_bytes,
+                reclaimed_blob_bytes: r.reclaimed_blob_bytes,
+            })
+            .collect(),
+    ))
+}
@@ -0,0 +1,180 @@
+use crate::api::auth::{Authentication, DomainScope};
+use crate::audit::{self, AuditEvent};
+use crate::domain::find_gate_in_domain;
+use crate::notification::{self, NotificationEvent};
+use crate::prisma::PrismaClient;
+use crate::snapshot::{
+    compare_snapshots, promote_snapshot, snapshot_components, SnapshotComparison, SnapshotComponent,
+};
+use crate::{prisma, AppState, Error, Result};
+use axum::extract::{Path, Query, State};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new()
+        .route("/compare", get(compare_gate_snapshots))
+        .route("/:id", get(get_snapshot))
+        .route("/:id/promote", post(promote_gate_snapshot))
+}
+
+/// The component versions of a gate frozen at one point in time.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Snapshot {
+    pub id: String,
+    pub gate_id: String,
+    pub name: String,
+    pub components: Vec<SnapshotComponent>,
+    /// Id of the snapshot of another gate this one was promoted from
+    pub promoted_from: Option<String>,
+    pub created_at: String,
+}
+
+impl TryFrom<prisma::gate_snapshot::Data> for Snapshot {
+    type Error = Error;
+
+    fn try_from(value: prisma::gate_snapshot::Data) -> Result<Self> {
+        Ok(Self {
+            components: snapshot_components(&value)?,
+            id: value.id,
+            gate_id: value.gate_id,
+            name: value.name,
+            promoted_from: value.promoted_from,
+            created_at: value.created_at.to_rfc3339(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct CreateSnapshotInput {
+    /// Name of the snapshot, unique in the gate
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PromoteSnapshotInput {
+    /// Database id of the gate the snapshot is promoted to
+    pub gate: String,
+}
+
+/// Query parameters of a snapshot comparison.
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompareSnapshotsParams {
+    /// Database id of the snapshot compared from
+    pub from: Uuid,
+    /// Database id of the snapshot compared to
+    pub to: Uuid,
+}
+
+/// The snapshot if it belongs to a gate of the domain.
+pub(crate) async fn find_snapshot_in_domain(
+    db: &PrismaClient,
+    id: &Uuid,
+    domain_id: &str,
+) -> Result<prisma::gate_snapshot::Data> {
+    let snapshot = db
+        .gate_snapshot()
+        .find_unique(prisma::gate_snapshot::UniqueWhereParam::IdEquals(
+            id.to_string(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("snapshot {}", id)))?;
+    find_gate_in_domain(db, &snapshot.gate_id, domain_id).await?;
+    Ok(snapshot)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/snapshots/{id}",
+    responses (
+        (status = 200, description = "The snapshot", body = Snapshot),
+        (status = 404, description = "Snapshot not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("snapshot 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the snapshot"),
+    )
+)]
+async fn get_snapshot(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Snapshot>> {
+    let db = state.prisma.clone();
+    let snapshot = find_snapshot_in_domain(&db, &id, &domain_id).await?;
+    Ok(Json(Snapshot::try_from(snapshot)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/snapshots/compare",
+    responses (
+        (status = 200, description = "Components added, removed and changed from one snapshot to the other", body = SnapshotComparison),
+        (status = 404, description = "Snapshot not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("snapshot 1"))))
+    ),
+    params(CompareSnapshotsParams)
+)]
+async fn compare_gate_snapshots(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Query(params): Query<CompareSnapshotsParams>,
+) -> Result<Json<SnapshotComparison>> {
+    let db = state.prisma.clone();
+    let from = find_snapshot_in_domain(&db, &params.from, &domain_id).await?;
+    let to = find_snapshot_in_domain(&db, &params.to, &domain_id).await?;
+    Ok(Json(compare_snapshots(
+        &snapshot_components(&from)?,
+        &snapshot_components(&to)?,
+    )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/snapshots/{id}/promote",
+    request_body = PromoteSnapshotInput,
+    responses (
+        (status = 200, description = "The snapshot recorded in the target gate after its components were copied there", body = Snapshot),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Snapshot or gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("snapshot 1")))),
+        (status = 409, description = "The target gate has a snapshot of that name or is the gate of the snapshot", body = ApiError)
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the snapshot to promote"),
+    )
+)]
+async fn promote_gate_snapshot(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+    Json(request): Json<PromoteSnapshotInput>,
+) -> Result<Json<Snapshot>> {
+    let db = state.prisma.clone();
+    let snapshot = find_snapshot_in_domain(&db, &id, &domain_id).await?;
+    let target = find_gate_in_domain(&db, &request.gate, &domain_id).await?;
+    let promoted = promote_snapshot(&db, &snapshot, &target).await?;
+    audit::record(
+        &db,
+        Some(&domain_id),
+        Some(&handle),
+        AuditEvent::new("gate.promote", "gate_snapshot", &promoted.id)
+            .before(&snapshot)?
+            .after(&promoted)?,
+    )
+    .await?;
+    notification::notify(
+        &db,
+        NotificationEvent::SnapshotCreated {
+            gate_id: promoted.gate_id.clone(),
+            snapshot: promoted.name.clone(),
+            promoted_from: Some(snapshot.id.clone()),
+        },
+    )
+    .await;
+    Ok(Json(Snapshot::try_from(promoted)?))
+}
@@ -0,0 +1,70 @@
+use crate::api::auth::Authentication;
+use crate::source_mirror::mirror_source;
+use crate::{AppState, Result};
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new().route("/:sha256", get(get_source))
+}
+
+/// Query parameters of the source mirror.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SourceParams {
+    /// Upstream url the archive is fetched from when the mirror does not have it yet, a
+    /// stored recipe must declare it with the checksum
+    pub url: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/sources/{sha256}",
+    responses (
+        (status = 200, description = "The source archive with the checksum", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 400, description = "Not a sha256 checksum", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("9a93b2b7 is not a sha256 checksum")))),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "No stored recipe declares the url with the checksum", body = ApiError, example = json!(crate::ApiError::BadRequest(String::from("no stored recipe declares https://example.org/foo-1.0.tar.gz with this checksum")))),
+        (status = 404, description = "Archive not mirrored and no url given", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("source 9a93b2b7dfdac77ceba5a558a580e74667dd6fede4585b91eefb60f03b72df38")))),
+        (status = 502, description = "Upstream failed or served an archive with another checksum", body = ApiError, example = json!(crate::ApiError::ServerError(String::from("source could not be fetched from upstream: 404 Not Found"))))
+    ),
+    params(
+        ("sha256" = String, Path, description = "sha256 of the archive"),
+        SourceParams
+    )
+)]
+async fn get_source(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(sha256): Path<String>,
+    Query(params): Query<SourceParams>,
+) -> Result<Response> {
+    let path = mirror_source(
+        &state.fs_operator,
+        &state.prisma,
+        &domain_id,
+        &sha256,
+        params.url.as_deref(),
+    )
+    .await?;
+    let size = state.fs_operator.stat(&path).await?.content_length();
+    let stream = state.fs_operator.reader(&path).await?.into_bytes_stream(..);
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                String::from("application/octet-stream"),
+            ),
+            (header::CONTENT_LENGTH, size.to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use crate::api::auth::Authentication;
+use crate::audit::{self, AuditEvent};
+use crate::domain::find_gate_in_domain;
+use crate::ownership::{find_actor, is_gate_admin};
+use crate::prisma::{self, PrismaClient};
+use crate::{AppState, Error, Result};
+use axum::extract::{Path, Query, State};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub fn get_router() -> Router<AppState> {
+    Router::new()
+        .route("/deliveries", get(list_deliveries).post(record_delivery))
+        .route("/deliveries/:id", put(finish_delivery))
+        .route("/deliveries/:id/replay", post(replay_delivery))
+}
+
+/// How far the processing of a webhook got.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    /// Received but the receiver did not report back yet
+    Received,
+    Processed,
+    Failed,
+}
+
+impl From<prisma::WebhookDeliveryState> for DeliveryState {
+    fn from(value: prisma::WebhookDeliveryState) -> Self {
+        match value {
+            prisma::WebhookDeliveryState::Received => DeliveryState::Received,
+            prisma::WebhookDeliveryState::Processed => DeliveryState::Processed,
+            prisma::WebhookDeliveryState::Failed => DeliveryState::Failed,
+        }
+    }
+}
+
+impl From<DeliveryState> for prisma::WebhookDeliveryState {
+    fn from(value: DeliveryState) -> Self {
+        match value {
+            DeliveryState::Received => prisma::WebhookDeliveryState::Received,
+            DeliveryState::Processed => prisma::WebhookDeliveryState::Processed,
+            DeliveryState::Failed => prisma::WebhookDeliveryState::Failed,
+        }
+    }
+}
+
+/// A webhook the receiver got for a gate.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub gate_id: String,
+    /// The X-GitHub-Delivery header
+    pub delivery_id: Option<String>,
+    pub event: String,
+    pub headers: HashMap<String, String>,
+    /// The body exactly as received
+    pub payload: String,
+    pub state: DeliveryState,
+    /// Why processing the last attempt failed
+    pub error: Option<String>,
+    /// Id the processing of the last attempt logged under
+    pub correlation_id: Option<String>,
+    pub attempts: i32,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl TryFrom<prisma::webhook_delivery::Data> for WebhookDelivery {
+    type Error = Error;
+
+    fn try_from(value: prisma::webhook_delivery::Data) -> Result<Self> {
+        Ok(Self {
+            headers: serde_json::from_value(value.headers)?,
+            id: value.id,
+            gate_id: value.gate_id,
+            delivery_id: value.delivery_id,
+            event: value.event,
+            payload: value.payload,
+            state: value.state.into(),
+            error: value.error,
+            correlation_id: value.correlation_id,
+            attempts: value.attempts,
+            created_at: value.created_at.to_rfc3339(),
+            updated_at: value.updated_at.to_rfc3339(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct RecordDeliveryInput {
+    /// Database id of the gate the receiver serves
+    pub gate_id: String,
+    pub delivery_id: Option<String>,
+    pub event: String,
+    pub headers: HashMap<String, String>,
+    pub payload: String,
+}
+
+/// What came of processing a delivery, reported by the receiver.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct DeliveryResultInput {
+    /// Unset if the webhook was processed
+    pub error: Option<String>,
+    pub correlation_id: Option<String>,
+}
+
+/// Filters of the delivery log, every given one has to match.
+#[derive(Serialize, Deserialize, Debug, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryListParams {
+    pub gate_id: Uuid,
+    pub state: Option<DeliveryState>,
+    /// Id of the last delivery of the previous page
+    pub cursor: Option<String>,
+    #[serde(default = "default_delivery_limit")]
+    pub limit: i64,
+}
+
+fn default_delivery_limit() -> i64 {
+    50
+}
+
+/// The delivery if its gate belongs to the domain.
+async fn find_delivery_in_domain(
+    db: &PrismaClient,
+    id: &Uuid,
+    domain_id: &str,
+) -> Result<prisma::webhook_delivery::Data> {
+    let delivery = db
+        .webhook_delivery()
+        .find_unique(prisma::webhook_delivery::UniqueWhereParam::IdEquals(
+            id.to_string(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("webhook delivery {}", id)))?;
+    find_gate_in_domain(db, &delivery.gate_id, domain_id).await?;
+    Ok(delivery)
+}
+
+/// Only the administrators of a gate see and replay the webhooks of the gate.
+async fn ensure_gate_admin(
+    db: &PrismaClient,
+    gate_id: &str,
+    handle: &str,
+    domain_id: &str,
+) -> Result<()> {
+    let actor = find_actor(db, handle, domain_id).await?;
+    if !is_gate_admin(db, gate_id, &actor.id).await? {
+        return Err(Error::NotGateAdmin(handle.to_string()));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/deliveries",
+    request_body = RecordDeliveryInput,
+    responses (
+        (status = 200, description = "The recorded delivery", body = WebhookDelivery),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    )
+)]
+async fn record_delivery(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Json(request): Json<RecordDeliveryInput>,
+) -> Result<Json<WebhookDelivery>> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &request.gate_id, &domain_id).await?;
+    let delivery = db
+        .webhook_delivery()
+        .create(
+            prisma::gate::UniqueWhereParam::IdEquals(gate.id),
+            request.event,
+            serde_json::to_value(&request.headers)?,
+            request.payload,
+            vec![prisma::webhook_delivery::delivery_id::set(
+                request.delivery_id,
+            )],
+        )
+        .exec()
+        .await?;
+    Ok(Json(WebhookDelivery::try_from(delivery)?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/webhooks/deliveries/{id}",
+    request_body = DeliveryResultInput,
+    responses (
+        (status = 200, description = "The delivery with the result of processing it", body = WebhookDelivery),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 404, description = "Delivery not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("webhook delivery 1"))))
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the delivery"),
+    )
+)]
+async fn finish_delivery(
+    State(state): State<AppState>,
+    Authentication { domain_id, .. }: Authentication,
+    Path(id): Path<Uuid>,
+    Json(request): Json<DeliveryResultInput>,
+) -> Result<Json<WebhookDelivery>> {
+    let db = state.prisma.clone();
+    let delivery = find_delivery_in_domain(&db, &id, &domain_id).await?;
+    let delivery_state = match request.error {
+        Some(_) => prisma::WebhookDeliveryState::Failed,
+        None => prisma::WebhookDeliveryState::Processed,
+    };
+    let delivery = db
+        .webhook_delivery()
+        .update(
+            prisma::webhook_delivery::UniqueWhereParam::IdEquals(delivery.id),
+            vec![
+                prisma::webhook_delivery::state::set(delivery_state),
+                prisma::webhook_delivery::error::set(request.error),
+                prisma::webhook_delivery::correlation_id::set(request.correlation_id),
+            ],
+        )
+        .exec()
+        .await?;
+    Ok(Json(WebhookDelivery::try_from(delivery)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/deliveries",
+    responses (
+        (status = 200, description = "Webhooks received for the gate, newest first", body = Vec<WebhookDelivery>),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the gate", body = ApiError),
+        (status = 404, description = "Gate not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("id = 1"))))
+    ),
+    params(DeliveryListParams)
+)]
+async fn list_deliveries(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Query(params): Query<DeliveryListParams>,
+) -> Result<Json<Vec<WebhookDelivery>>> {
+    let db = state.prisma.clone();
+    let gate = find_gate_in_domain(&db, &params.gate_id.to_string(), &domain_id).await?;
+    ensure_gate_admin(&db, &gate.id, &handle, &domain_id).await?;
+
+    let mut filter = vec![prisma::webhook_delivery::gate_id::equals(gate.id)];
+    if let Some(delivery_state) = params.state {
+        filter.push(prisma::webhook_delivery::state::equals(
+            delivery_state.into(),
+        ));
+    }
+
+    let mut query = db
+        .webhook_delivery()
+        .find_many(filter)
+        .order_by(prisma::webhook_delivery::created_at::order(Direction::Desc))
+        .take(params.limit);
+
+    if let Some(cursor) = params.cursor {
+        query = query
+            .cursor(prisma::webhook_delivery::id::equals(cursor))
+            .skip(1);
+    }
+
+    Ok(Json(
+        query
+            .exec()
+            .await?
+            .into_iter()
+            .map(WebhookDelivery::try_from)
+            .collect::<Result<Vec<_>>>()?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks/deliveries/{id}/replay",
+    responses (
+        (status = 200, description = "The delivery after the receiver processed it again", body = WebhookDelivery),
+        (status = 401, description = "Unauthorized to access the API", body = ApiError, example = json!(crate::ApiError::Unauthorized)),
+        (status = 403, description = "The caller does not administer the gate", body = ApiError),
+        (status = 404, description = "Delivery not found", body = ApiError, example = json!(crate::ApiError::NotFound(String::from("webhook delivery 1")))),
+        (status = 502, description = "No webhook receiver is configured or it could not be reached", body = ApiError)
+    ),
+    params(
+        ("id" = Uuid, Path, description = "Database id of the delivery"),
+    )
+)]
+async fn replay_delivery(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookDelivery>> {
+    let receiver = state
+        .webhook_receiver
+        .clone()
+        .ok_or(Error::WebhookReplay(String::from(
+            "no webhook receiver is configured",
+        )))?;
+
+    let delivery = {
+        let db = state.prisma.clone();
+        let delivery = find_delivery_in_domain(&db, &id, &domain_id).await?;
+        ensure_gate_admin(&db, &delivery.gate_id, &handle, &domain_id).await?;
+        let replayed = db
+            .webhook_delivery()
+            .update(
+                prisma::webhook_delivery::UniqueWhereParam::IdEquals(delivery.id.clone()),
+                vec![
+                    prisma::webhook_delivery::state::set(prisma::WebhookDeliveryState::Received),
+                    prisma::webhook_delivery::error::set(None),
+                    prisma::webhook_delivery::attempts::increment(1),
+                ],
+            )
+            .exec()
+            .await?;
+        audit::record(
+            &db,
+            Some(&domain_id),
+            Some(&handle),
+            AuditEvent::new("webhook.replay", "webhook_delivery", &replayed.id)
+                .before(&WebhookDelivery::try_from(delivery)?)?,
+        )
+        .await?;
+        replayed
+    };
+
+    // The receiver reports the result back while we wait, so the database is not held
+    info!(
+        "replaying {} webhook {} to {}",
+        delivery.event, delivery.id, receiver
+    );
+    let headers: HashMap<String, String> = serde_json::from_value(delivery.headers.clone())?;
+    let mut request = reqwest::Client::new()
+        .post(&receiver)
+        .header(forge::REPLAYED_DELIVERY_HEADER, delivery.id.as_str())
+        .body(delivery.payload.clone());
+    for (name, value) in headers.iter() {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    request
+        .send()
+        .await
+        .map_err(|e| Error::WebhookReplay(e.to_string()))?;
+
+    let db = state.prisma.clone();
+    let delivery = find_delivery_in_domain(&db, &id, &domain_id).await?;
+    Ok(Json(WebhookDelivery::try_from(delivery)?))
+}
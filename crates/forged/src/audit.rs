@@ -0,0 +1,118 @@
+use serde::Serialize;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::prisma::{self, PrismaClient};
+use crate::{correlation, Result};
+
+/// A mutating operation to append to the audit log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    /// What was done like `gate.update` or `job.submit`
+    pub action: &'static str,
+    pub object_type: &'static str,
+    pub object_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: &'static str, object_type: &'static str, object_id: &str) -> Self {
+        Self {
+            action,
+            object_type,
+            object_id: object_id.to_string(),
+            before: None,
+            after: None,
+        }
+    }
+
+    /// The object as it was before the operation.
+    pub fn before<T: Serialize>(mut self, value: &T) -> Result<Self> {
+        self.before = Some(serde_json::to_value(value)?);
+        Ok(self)
+    }
+
+    /// The object as the operation left it.
+    pub fn after<T: Serialize>(mut self, value: &T) -> Result<Self> {
+        self.after = Some(serde_json::to_value(value)?);
+        Ok(self)
+    }
+}
+
+/// Append the event to the audit log of the domain. `actor` is the handle of the actor
+/// that asked for the operation, operations forged does on its own have none.
+pub async fn record(
+    db: &PrismaClient,
+    domain_id: Option<&str>,
+    actor: Option<&str>,
+    event: AuditEvent,
+) -> Result<()> {
+    debug!(
+        "audit: {} {} {} by {}",
+        event.action,
+        event.object_type,
+        event.object_id,
+        actor.unwrap_or("forged")
+    );
+    let mut params = vec![
+        prisma::audit_entry::actor::set(actor.map(str::to_string)),
+        prisma::audit_entry::before::set(event.before),
+        prisma::audit_entry::after::set(event.after),
+        prisma::audit_entry::correlation_id::set(correlation::current()),
+    ];
+    if let Some(domain_id) = domain_id {
+        params.push(prisma::audit_entry::domain::connect(
+            prisma::domain::UniqueWhereParam::IdEquals(domain_id.to_string()),
+        ));
+    }
+    db.audit_entry()
+        .create(
+            event.action.to_string(),
+            event.object_type.to_string(),
+            event.object_id,
+            params,
+        )
+        .exec()
+        .await?;
+    Ok(())
+}
+
+/// How components are identified in the audit log.
+pub fn component_object_id(component: &prisma::component::Data) -> String {
+    format!(
+        "{}/{}@{}-{}",
+        component.gate_id, component.name, component.version, component.revision
+    )
+}
+
+/// The domain of the gate, for operations that only know the gate.
+pub async fn gate_domain(db: &PrismaClient, gate_id: &str) -> Result<Option<String>> {
+    Ok(db
+        .gate()
+        .find_unique(prisma::gate::UniqueWhereParam::IdEquals(
+            gate_id.to_string(),
+        ))
+        .with(prisma::gate::publisher::fetch())
+        .exec()
+        .await?
+        .and_then(|gate| gate.publisher)
+        .map(|publisher| publisher.domain_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_carry_both_states() {
+        let event = AuditEvent::new("gate.update", "gate", "b7f1c7c6")
+            .before(&serde_json::json!({ "branch": "main" }))
+            .unwrap()
+            .after(&serde_json::json!({ "branch": "oi/hipster" }))
+            .unwrap();
+        assert_eq!(event.object_id, "b7f1c7c6");
+        assert_eq!(event.before.unwrap()["branch"], "main");
+        assert_eq!(event.after.unwrap()["branch"], "oi/hipster");
+    }
+}
@@ -1,21 +1,15 @@
 use clap::Parser;
 use forged::*;
-use tracing_subscriber::prelude::*;
 
 #[tokio::main]
 async fn main() -> miette::Result<()> {
     let args = Args::parse();
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                // axum logs rejections from built-in extractors with the `axum::rejection`
-                // target, at `TRACE` level. `axum::rejection=trace` enables showing those events
-                "forge=trace,receiver=trace,github=trace,tower_http=trace,axum::rejection=trace"
-                    .into()
-            }),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    // axum logs rejections from built-in extractors with the `axum::rejection` target, at
+    // `TRACE` level. `axum::rejection=trace` enables showing those events
+    telemetry::init(
+        "forged",
+        "forge=trace,receiver=trace,github=trace,tower_http=trace,axum::rejection=trace",
+    )?;
     let cfg = load_config(&args)?;
     match &args.command {
         Commands::Start => {
@@ -31,6 +25,47 @@ async fn main() -> miette::Result<()> {
         } => {
             set_domain(cfg, name.clone(), gh_client_id.clone()).await?;
         }
+        Commands::SetDomainAdmin {
+            name,
+            handle,
+            revoke,
+        } => {
+            set_domain_admin(cfg, name.clone(), handle.clone(), *revoke).await?;
+        }
+        Commands::SetRepositoryLimit {
+            url,
+            max_concurrent_jobs,
+        } => {
+            set_repository_limit(cfg, url.clone(), *max_concurrent_jobs).await?;
+        }
+        Commands::AnalyzeGates { offline } => {
+            analyze_gates(cfg, *offline).await?;
+        }
+        Commands::SetRetention {
+            name,
+            job_days,
+            component_change_days,
+            change_request_days,
+        } => {
+            let policy = RetentionPolicy {
+                job_days: *job_days,
+                component_change_days: *component_change_days,
+                change_request_days: *change_request_days,
+            };
+            set_retention(cfg, name.clone(), policy).await?;
+        }
+        Commands::Prune { domain, dry_run } => {
+            prune(cfg, domain.clone(), *dry_run).await?;
+        }
+        Commands::CheckUpstream => {
+            check_upstream(cfg).await?;
+        }
+        Commands::CheckVulnerabilities => {
+            check_vulnerabilities(cfg).await?;
+        }
+        Commands::CollectGarbage { dry_run } => {
+            collect_garbage(cfg, *dry_run).await?;
+        }
     }
 
     Ok(())
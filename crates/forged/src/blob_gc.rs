@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use chrono::{TimeDelta, Utc};
+use forge::ComponentFileKind;
+use opendal::Operator;
+use tracing::{debug, info, instrument};
+
+use crate::api::v1::file::UPLOAD_PREFIX as RESUMABLE_UPLOAD_PREFIX;
+use crate::periodic::{self, hours};
+use crate::prisma::PrismaClient;
+use crate::Result;
+
+/// Uploads are stored before the component referencing them is saved. Younger blobs are
+/// left alone so garbage collection cannot race an upload.
+const GRACE_PERIOD_HOURS: i64 = 6;
+
+/// Prefix of the files uploads are written to before they are moved to their blob.
+const UPLOAD_PREFIX: &str = "trans/";
+
+/// Resumable uploads nobody sent a chunk for in this many days are abandoned.
+const RESUMABLE_UPLOAD_EXPIRY_DAYS: i64 = 7;
+
+/// What one garbage collection of the blob store removed or would remove.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GarbageReport {
+    /// Component files no component or component change references
+    pub blobs: Vec<String>,
+    /// Leftovers of uploads that did not finish and abandoned resumable uploads
+    pub stale_uploads: Vec<String>,
+    pub reclaimed_bytes: u64,
+}
+
+impl Display for GarbageReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "unreferenced blobs: {}", self.blobs.len())?;
+        for blob in self.blobs.iter() {
+            writeln!(f, "  {}", blob)?;
+        }
+        writeln!(f, "stale uploads: {}", self.stale_uploads.len())?;
+        for upload in self.stale_uploads.iter() {
+            writeln!(f, "  {}", upload)?;
+        }
+        write!(f, "reclaimed: {} bytes", self.reclaimed_bytes)
+    }
+}
+
+/// Component files are stored as `kind:component:name:hash` in the root of the blob
+/// store. Everything else belongs to someone else, like the package depot.
+fn is_component_file(path: &str) -> bool {
+    !path.contains('/')
+        && path.splitn(4, ':').count() == 4
+        && path
+            .split(':')
+            .next()
+            .is_some_and(|kind| kind.parse::<ComponentFileKind>().is_ok())
+}
+
+/// The component files components and component changes of any domain reference.
+/// Component files are content addressed and shared between domains, so the whole store
+/// is collected at once.
+pub async fn referenced_blobs(db: &PrismaClient) -> Result<HashSet<String>> {
+    let mut referenced = HashSet::new();
+    for component in db.component().find_many(vec![]).exec().await? {
+        referenced.extend(component.scripts.into_iter().chain(component.archives));
+    }
+    for change in db.component_change().find_many(vec![]).exec().await? {
+        referenced.extend(change.scripts.into_iter().chain(change.archives));
+    }
+    debug!("{} blobs are referenced", referenced.len());
+    Ok(referenced)
+}
+
+/// Remove the component files that are not in `referenced` and uploads that never
+/// finished. With `dry_run` nothing is removed and only the report is returned.
+#[instrument(skip(op, referenced))]
+pub async fn collect_garbage(
+    op: &Operator,
+    referenced: &HashSet<String>,
+    dry_run: bool,
+) -> Result<GarbageReport> {
+    let cutoff = Utc::now() - TimeDelta::hours(GRACE_PERIOD_HOURS);
+    let upload_cutoff = Utc::now() - TimeDelta::days(RESUMABLE_UPLOAD_EXPIRY_DAYS);
+    let mut abandoned_uploads: HashMap<String, bool> = HashMap::new();
+    let mut report = GarbageReport::default();
+    for entry in op.list_with("").recursive(true).await? {
+        let path = entry.path().to_string();
+
+        // The state of a resumable upload is written with every chunk
+        if let Some(rest) = path.strip_prefix(RESUMABLE_UPLOAD_PREFIX) {
+            let id = rest
+                .split('/')
+                .next()
+                .unwrap_or(rest)
+                .trim_end_matches(".json");
+            let state = format!("{}{}.json", RESUMABLE_UPLOAD_PREFIX, id);
+            let abandoned = match abandoned_uploads.get(id) {
+                Some(abandoned) => *abandoned,
+                None => {
+                    let abandoned = match op.stat(&state).await {
+                        Ok(metadata) => metadata.last_modified().is_some_and(|m| m < upload_cutoff),
+                        Err(e) if e.kind() == opendal::ErrorKind::NotFound => true,
+                        Err(e) => return Err(e.into()),
+                    };
+                    abandoned_uploads.insert(id.to_string(), abandoned);
+                    abandoned
+                }
+            };
+            if abandoned && entry.metadata().is_file() {
+                let metadata = op.stat(&path).await?;
+                if metadata.last_modified().is_some_and(|m| m < cutoff) {
+                    report.reclaimed_bytes += metadata.content_length();
+                    report.stale_uploads.push(path);
+                }
+            }
+            continue;
+        }
+
+        let stale_upload = path.starts_with(UPLOAD_PREFIX);
+        if !entry.metadata().is_file()
+            || !(stale_upload || is_component_file(&path))
+            || referenced.contains(&path)
+        {
+            continue;
+        }
+
+        let metadata = op.stat(&path).await?;
+        if !metadata.last_modified().is_some_and(|m| m < cutoff) {
+            continue;
+        }
+        report.reclaimed_bytes += metadata.content_length();
+        if stale_upload {
+            report.stale_uploads.push(path);
+        } else {
+            report.blobs.push(path);
+        }
+    }
+
+    if dry_run {
+        debug!("dry run, nothing removed");
+        return Ok(report);
+    }
+
+    for path in report.blobs.iter().chain(report.stale_uploads.iter()) {
+        op.delete(path).await?;
+    }
+    info!(
+        "removed {} unreferenced blobs and {} stale uploads",
+        report.blobs.len(),
+        report.stale_uploads.len()
+    );
+    Ok(report)
+}
+
+/// Collect garbage every `interval_hours` until forged stops. Failed runs are logged
+/// and tried again at the next interval.
+pub async fn run_periodically(db: std::sync::Arc<PrismaClient>, op: Operator, interval_hours: u64) {
+    let (db, op) = (&db, &op);
+    periodic::run_periodically(
+        "blob garbage collection",
+        hours(interval_hours),
+        || async move {
+            let referenced = referenced_blobs(db).await?;
+            let report = collect_garbage(op, &referenced, false).await?;
+            debug!(
+                "blob garbage collection reclaimed {} bytes",
+                report.reclaimed_bytes
+            );
+            Ok(())
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_component_files_are_collected() {
+        assert!(is_component_file(
+            "archive:library_foo:foo-1.2.tar.gz:0123abcd"
+        ));
+        assert!(is_component_file("script:library_foo:build.sh:0123abcd"));
+        assert!(!is_component_file("depot/domain/test/file/0123abcd"));
+        assert!(!is_component_file("trans/archive:library_foo:uuid"));
+        assert!(!is_component_file("catalog.json"));
+        assert!(!is_component_file("unknown:library_foo:foo:0123abcd"));
+    }
+}
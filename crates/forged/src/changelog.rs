@@ -0,0 +1,441 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::{DateTime, FixedOffset};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::prisma::{self, PrismaClient};
+use crate::snapshot::{compare_snapshots, snapshot_components};
+use crate::Result;
+
+/// A component added, updated or removed between the two ends of a changelog.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ChangelogEntry {
+    pub name: String,
+    /// Version and revision at the start, unset for added components
+    pub from: Option<String>,
+    /// Version and revision at the end, unset for removed components
+    pub to: Option<String>,
+    /// External references or ids of the applied change requests bringing the change
+    pub change_requests: Vec<String>,
+}
+
+/// What changed in a gate between two snapshots or two points in time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Changelog {
+    pub gate: String,
+    /// Name of the first snapshot or the start of the time range
+    pub from: String,
+    /// Name of the second snapshot or the end of the time range
+    pub to: String,
+    pub added: Vec<ChangelogEntry>,
+    pub updated: Vec<ChangelogEntry>,
+    pub removed: Vec<ChangelogEntry>,
+    /// The changelog rendered as Markdown for release notes
+    pub markdown: String,
+}
+
+/// A change of a component applied to the gate by a change request.
+#[derive(Debug, Clone)]
+struct AppliedChange {
+    name: String,
+    kind: prisma::ComponentChangeKind,
+    /// The version the change was made against
+    previous: Option<String>,
+    version: String,
+    change_request: String,
+}
+
+fn version_revision(version: &str, revision: &str) -> String {
+    format!("{}-{}", version, revision)
+}
+
+/// How a change request is referred to in the changelog.
+fn change_request_reference(change_request: &prisma::change_request::Data) -> String {
+    change_request
+        .external_reference
+        .clone()
+        .unwrap_or(change_request.id.clone())
+}
+
+impl Changelog {
+    fn new(
+        gate: &str,
+        from: String,
+        to: String,
+        added: Vec<ChangelogEntry>,
+        updated: Vec<ChangelogEntry>,
+        removed: Vec<ChangelogEntry>,
+    ) -> Self {
+        let mut changelog = Self {
+            gate: gate.to_string(),
+            from,
+            to,
+            added,
+            updated,
+            removed,
+            markdown: String::new(),
+        };
+        changelog.markdown = render_markdown(&changelog);
+        changelog
+    }
+}
+
+/// Link GitHub pull requests, other references are written as they are.
+fn markdown_reference(reference: &str) -> String {
+    if let Some(pull_request) = reference.strip_prefix("github:pr:") {
+        if let Some((repository, number)) = pull_request.rsplit_once('/') {
+            return format!(
+                "[{}#{}](https://github.com/{}/pull/{})",
+                repository, number, repository, number
+            );
+        }
+    }
+    reference.to_string()
+}
+
+fn markdown_entry(entry: &ChangelogEntry) -> String {
+    let mut line = format!("- {}", entry.name);
+    match (&entry.from, &entry.to) {
+        (Some(from), Some(to)) => write!(line, " {} → {}", from, to),
+        (None, Some(version)) | (Some(version), None) => write!(line, " {}", version),
+        (None, None) => Ok(()),
+    }
+    .expect("writing to a string does not fail");
+    if !entry.change_requests.is_empty() {
+        let references = entry
+            .change_requests
+            .iter()
+            .map(|reference| markdown_reference(reference))
+            .collect::<Vec<_>>();
+        line.push_str(&format!(" ({})", references.join(", ")));
+    }
+    line
+}
+
+fn render_markdown(changelog: &Changelog) -> String {
+    let mut markdown = format!(
+        "# Changes of {} from {} to {}\n",
+        changelog.gate, changelog.from, changelog.to
+    );
+    let sections = [
+        ("Added", &changelog.added),
+        ("Updated", &changelog.updated),
+        ("Removed", &changelog.removed),
+    ];
+    if sections.iter().all(|(_, entries)| entries.is_empty()) {
+        markdown.push_str("\nNo components changed.\n");
+    }
+    for (title, entries) in sections {
+        if entries.is_empty() {
+            continue;
+        }
+        markdown.push_str(&format!("\n## {}\n\n", title));
+        for entry in entries {
+            markdown.push_str(&markdown_entry(entry));
+            markdown.push('\n');
+        }
+    }
+    markdown
+}
+
+/// Fold the changes applied one after the other into one entry per component, sorted
+/// into added, updated and removed ones.
+fn fold_changes(
+    changes: &[AppliedChange],
+) -> (
+    Vec<ChangelogEntry>,
+    Vec<ChangelogEntry>,
+    Vec<ChangelogEntry>,
+) {
+    let mut entries: BTreeMap<&str, ChangelogEntry> = BTreeMap::new();
+    for change in changes {
+        let to = match change.kind {
+            prisma::ComponentChangeKind::Removed => None,
+            _ => Some(change.version.clone()),
+        };
+        let entry = entries
+            .entry(change.name.as_str())
+            .or_insert_with(|| ChangelogEntry {
+                name: change.name.clone(),
+                from: match change.kind {
+                    prisma::ComponentChangeKind::Added => None,
+                    _ => change.previous.clone(),
+                },
+                to: None,
+                change_requests: vec![],
+            });
+        entry.to = to;
+        if !entry.change_requests.contains(&change.change_request) {
+            entry.change_requests.push(change.change_request.clone());
+        }
+    }
+
+    let (mut added, mut updated, mut removed) = (vec![], vec![], vec![]);
+    for entry in entries.into_values() {
+        match (&entry.from, &entry.to) {
+            (None, Some(_)) => added.push(entry),
+            (Some(_), None) => removed.push(entry),
+            (Some(from), Some(to)) if from != to => updated.push(entry),
+            _ => {}
+        }
+    }
+    (added, updated, removed)
+}
+
+/// The applied change requests that brought this version of the component into the gate.
+async fn change_requests_of(
+    db: &PrismaClient,
+    gate_id: &str,
+    name: &str,
+    version: &str,
+    revision: &str,
+) -> Result<Vec<String>> {
+    let changes = db
+        .component_change()
+        .find_many(vec![
+            prisma::component_change::gate_id::equals(Some(gate_id.to_string())),
+            prisma::component_change::name::equals(name.to_string()),
+            prisma::component_change::version::equals(version.to_string()),
+            prisma::component_change::revision::equals(revision.to_string()),
+            prisma::component_change::applied::equals(true),
+        ])
+        .with(prisma::component_change::change_request::fetch())
+        .exec()
+        .await?;
+    let mut references = vec![];
+    for change in changes {
+        if let Ok(change_request) = change.change_request() {
+            let reference = change_request_reference(change_request);
+            if !references.contains(&reference) {
+                references.push(reference);
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// The changelog of a gate from one of its snapshots to another.
+pub async fn changelog_between_snapshots(
+    db: &PrismaClient,
+    gate: &prisma::gate::Data,
+    from: &prisma::gate_snapshot::Data,
+    to: &prisma::gate_snapshot::Data,
+) -> Result<Changelog> {
+    let comparison = compare_snapshots(&snapshot_components(from)?, &snapshot_components(to)?);
+
+    let mut added = vec![];
+    for component in comparison.added {
+        added.push(ChangelogEntry {
+            change_requests: change_requests_of(
+                db,
+                &gate.id,
+                &component.name,
+                &component.version,
+                &component.revision,
+            )
+            .await?,
+            to: Some(version_revision(&component.version, &component.revision)),
+            from: None,
+            name: component.name,
+        });
+    }
+    let mut updated = vec![];
+    for change in comparison.changed {
+        updated.push(ChangelogEntry {
+            change_requests: change_requests_of(
+                db,
+                &gate.id,
+                &change.name,
+                &change.to_version,
+                &change.to_revision,
+            )
+            .await?,
+            from: Some(version_revision(
+                &change.from_version,
+                &change.from_revision,
+            )),
+            to: Some(version_revision(&change.to_version, &change.to_revision)),
+            name: change.name,
+        });
+    }
+    let removed = comparison
+        .removed
+        .into_iter()
+        .map(|component| ChangelogEntry {
+            from: Some(version_revision(&component.version, &component.revision)),
+            to: None,
+            name: component.name,
+            change_requests: vec![],
+        })
+        .collect();
+
+    Ok(Changelog::new(
+        &gate.name,
+        from.name.clone(),
+        to.name.clone(),
+        added,
+        updated,
+        removed,
+    ))
+}
+
+/// The changelog of a gate from the change requests applied in a time range. Change
+/// requests count at the time they were last updated, which is when they were applied.
+pub async fn changelog_between_dates(
+    db: &PrismaClient,
+    gate: &prisma::gate::Data,
+    since: Option<DateTime<FixedOffset>>,
+    until: Option<DateTime<FixedOffset>>,
+) -> Result<Changelog> {
+    let mut change_request_filter = vec![prisma::change_request::state::equals(
+        prisma::ChangeRequestState::Applied,
+    )];
+    if let Some(since) = since {
+        change_request_filter.push(prisma::change_request::updated_at::gte(since));
+    }
+    if let Some(until) = until {
+        change_request_filter.push(prisma::change_request::updated_at::lt(until));
+    }
+
+    let changes = db
+        .component_change()
+        .find_many(vec![
+            prisma::component_change::gate_id::equals(Some(gate.id.clone())),
+            prisma::component_change::applied::equals(true),
+            prisma::component_change::change_request::is(change_request_filter),
+        ])
+        .with(prisma::component_change::change_request::fetch())
+        .order_by(prisma::component_change::created_at::order(Direction::Asc))
+        .exec()
+        .await?;
+
+    let mut applied = changes
+        .into_iter()
+        .filter_map(|change| {
+            let change_request = change.change_request().ok()?;
+            Some((
+                change_request.updated_at,
+                AppliedChange {
+                    change_request: change_request_reference(change_request),
+                    previous: match (&change.component_version, &change.component_revision) {
+                        (Some(version), Some(revision)) => {
+                            Some(version_revision(version, revision))
+                        }
+                        _ => None,
+                    },
+                    version: version_revision(&change.version, &change.revision),
+                    kind: change.kind,
+                    name: change.name,
+                },
+            ))
+        })
+        .collect::<Vec<_>>();
+    // Changes apply in the order of their change requests
+    applied.sort_by_key(|(applied_at, _)| *applied_at);
+    let applied = applied
+        .into_iter()
+        .map(|(_, change)| change)
+        .collect::<Vec<_>>();
+
+    let (added, updated, removed) = fold_changes(&applied);
+    Ok(Changelog::new(
+        &gate.name,
+        since.map_or(String::from("the beginning"), |since| since.to_rfc3339()),
+        until.map_or(String::from("now"), |until| until.to_rfc3339()),
+        added,
+        updated,
+        removed,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(
+        name: &str,
+        kind: prisma::ComponentChangeKind,
+        previous: Option<&str>,
+        version: &str,
+        change_request: &str,
+    ) -> AppliedChange {
+        AppliedChange {
+            name: name.to_string(),
+            kind,
+            previous: previous.map(str::to_string),
+            version: version.to_string(),
+            change_request: change_request.to_string(),
+        }
+    }
+
+    #[test]
+    fn changes_are_folded_per_component() {
+        use prisma::ComponentChangeKind::{Added, Removed, Updated};
+
+        let changes = vec![
+            change("library/zlib", Updated, Some("1.3-0"), "1.3.1-0", "cr-1"),
+            change("web/curl", Added, None, "8.7.1-0", "cr-2"),
+            change("library/zlib", Updated, Some("1.3.1-0"), "1.3.1-1", "cr-3"),
+            change("sed", Removed, Some("4.9-1"), "4.9-1", "cr-4"),
+            change("gzip", Updated, Some("1.13-0"), "1.14-0", "cr-5"),
+            change("gzip", Updated, Some("1.14-0"), "1.13-0", "cr-6"),
+        ];
+
+        let (added, updated, removed) = fold_changes(&changes);
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].to.as_deref(), Some("8.7.1-0"));
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].from.as_deref(), Some("1.3-0"));
+        assert_eq!(updated[0].to.as_deref(), Some("1.3.1-1"));
+        assert_eq!(updated[0].change_requests, vec!["cr-1", "cr-3"]);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].from.as_deref(), Some("4.9-1"));
+    }
+
+    #[test]
+    fn changelog_renders_as_markdown() {
+        let changelog = Changelog::new(
+            "openindiana",
+            String::from("2026.04"),
+            String::from("2026.10"),
+            vec![ChangelogEntry {
+                name: String::from("web/curl"),
+                from: None,
+                to: Some(String::from("8.7.1-0")),
+                change_requests: vec![String::from("github:pr:oi/userland/2")],
+            }],
+            vec![ChangelogEntry {
+                name: String::from("library/zlib"),
+                from: Some(String::from("1.3-0")),
+                to: Some(String::from("1.3.1-0")),
+                change_requests: vec![String::from("cr-1")],
+            }],
+            vec![],
+        );
+        assert_eq!(
+            changelog.markdown,
+            "# Changes of openindiana from 2026.04 to 2026.10\n\
+             \n\
+             ## Added\n\
+             \n\
+             - web/curl 8.7.1-0 ([oi/userland#2](https://github.com/oi/userland/pull/2))\n\
+             \n\
+             ## Updated\n\
+             \n\
+             - library/zlib 1.3-0 → 1.3.1-0 (cr-1)\n"
+        );
+
+        let empty = Changelog::new(
+            "openindiana",
+            String::from("2026.04"),
+            String::from("2026.10"),
+            vec![],
+            vec![],
+            vec![],
+        );
+        assert!(empty.markdown.ends_with("\nNo components changed.\n"));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{prisma, Error, Result};
+use component::Recipe;
 use itertools::Itertools;
 use prisma::component::Data as DatabaseComponent;
 use semver::{BuildMetadata, Version};
@@ -24,3 +25,13 @@ pub fn find_latest_component_in_set(set: Vec<DatabaseComponent>) -> Result<Datab
 
     comp.map(|data| data.clone()).ok_or(Error::NoComponentFound)
 }
+
+/// The columns of a component searches filter on, copied out of its recipe.
+pub fn recipe_search_params(recipe: &Recipe) -> Vec<prisma::component::SetParam> {
+    vec![
+        prisma::component::summary::set(recipe.summary.clone()),
+        prisma::component::license::set(recipe.license.clone()),
+        prisma::component::classification::set(recipe.classification.clone()),
+        prisma::component::maintainers::set(recipe.maintainers.clone()),
+    ]
+}
@@ -0,0 +1,64 @@
+use std::future::Future;
+
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use deadpool_lapin::lapin::protocol::basic::AMQPProperties;
+use forge::{new_correlation_id, CORRELATION_ID_HEADER};
+use tracing::{info_span, Instrument};
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// The correlation id of the request or message being handled.
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Run `f` with `id` as the correlation id, logging everything in a span carrying it.
+pub async fn scope<F: Future>(id: String, name: &'static str, f: F) -> F::Output {
+    let span = info_span!("correlated", correlation_id = %id, handler = name);
+    CORRELATION_ID.scope(id, f.instrument(span)).await
+}
+
+/// Run `f` handling an AMQP message under its correlation id, in a span continuing the
+/// trace of the service that sent it.
+pub async fn message_scope<F: Future>(properties: &AMQPProperties, f: F) -> F::Output {
+    let id = from_properties(properties);
+    let span = info_span!("correlated", correlation_id = %id, handler = "amqp");
+    telemetry::set_parent(&span, properties);
+    CORRELATION_ID.scope(id, f.instrument(span)).await
+}
+
+/// The correlation id of an AMQP message. Messages of older services without one get
+/// a new id.
+pub fn from_properties(properties: &AMQPProperties) -> String {
+    properties
+        .correlation_id()
+        .as_ref()
+        .map(|id| id.to_string())
+        .unwrap_or_else(new_correlation_id)
+}
+
+/// Properties of a message sent on behalf of `id`, continuing the current trace.
+pub fn properties(id: &str) -> AMQPProperties {
+    telemetry::inject(AMQPProperties::default().with_correlation_id(id.into()))
+}
+
+/// Handle the request under the correlation id of its header or a new one and return
+/// the id in the response so clients can refer to it.
+pub async fn middleware(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(CORRELATION_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(new_correlation_id);
+    let mut response = scope(id.clone(), "http", next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(CORRELATION_ID_HEADER, value);
+    }
+    response
+}
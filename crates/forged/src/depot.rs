@@ -0,0 +1,684 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use opendal::Operator;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use tracing::{debug, info};
+
+use crate::api::auth::{Authentication, DomainScope};
+use crate::domain::publisher_scope;
+use crate::ownership::ensure_publisher_admin;
+use crate::signing::sign_for_publisher;
+use crate::{prisma, AppState, Error, Result};
+
+/// Catalog parts the depot serves besides catalog.attrs.
+const CATALOG_PARTS: [&str; 3] = [
+    "catalog.base.C",
+    "catalog.dependency.C",
+    "catalog.summary.C",
+];
+
+/// Version of the p5i document `publisher/0` answers with.
+const P5I_VERSION: u32 = 1;
+
+/// A pkg(5) depot for the publishers of a domain. Workers publish with the same
+/// transaction operations pkg.depotd offers and clients install from the catalog,
+/// manifests and files it serves. Everything lives in the blob store under
+/// `depot/<domain>/<publisher>`.
+pub fn get_router() -> Router<AppState> {
+    Router::new()
+        .route("/versions/0/", get(versions))
+        .route("/:publisher/versions/0/", get(publisher_versions))
+        .route("/:publisher/publisher/0/", get(publisher_info))
+        .route("/:publisher/catalog/1/:part", get(catalog))
+        .route("/:publisher/manifest/0/:fmri", get(manifest))
+        .route("/:publisher/signature/0/:fmri", get(signature))
+        .route("/:publisher/file/:version/:hash", get(file))
+        .route("/:publisher/open/0/:fmri", get(open_transaction))
+        .route("/:publisher/add/0/:transaction/:action", post(add_action))
+        .route("/:publisher/close/0/:transaction", get(close_transaction))
+        .route(
+            "/:publisher/abandon/0/:transaction",
+            get(abandon_transaction),
+        )
+}
+
+/// A package being published. Actions are added one request at a time, each is stored
+/// on its own next to the transaction so adding one does not rewrite the others.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Transaction {
+    stem: String,
+    version: String,
+}
+
+/// Closing a transaction rewrites the catalog of the publisher. Closes of the same
+/// publisher take turns so a package published at the same time is not dropped again.
+#[derive(Debug, Clone, Default)]
+pub struct CatalogLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl CatalogLocks {
+    async fn lock(&self, root: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(root.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
+
+/// The packages of a publisher. The catalog parts clients download are made from it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+struct CatalogIndex {
+    created: Option<String>,
+    last_modified: Option<String>,
+    /// Versions including the publication timestamp keyed by stem
+    packages: BTreeMap<String, BTreeMap<String, CatalogEntry>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CatalogEntry {
+    /// SHA-1 of the manifest
+    signature: String,
+    dependency_actions: Vec<String>,
+    summary_actions: Vec<String>,
+}
+
+/// Where the depot of a publisher keeps its data in the blob store.
+struct DepotStore<'a> {
+    op: &'a Operator,
+    root: String,
+    publisher_id: String,
+}
+
+impl<'a> DepotStore<'a> {
+    async fn open(state: &'a AppState, domain_id: &str, publisher: &str) -> Result<DepotStore<'a>> {
+        let record = state
+            .prisma
+            .publisher()
+            .find_first(vec![
+                publisher_scope(domain_id),
+                prisma::publisher::name::equals(publisher.to_string()),
+            ])
+            .exec()
+            .await?
+            .ok_or(Error::NotFound(format!("publisher {}", publisher)))?;
+
+        Ok(DepotStore {
+            op: &state.fs_operator,
+            root: format!("depot/{}/{}", domain_id, publisher),
+            publisher_id: record.id,
+        })
+    }
+
+    /// Open the depot to publish to, which only administrators of the publisher may.
+    async fn open_for_publishing(
+        state: &'a AppState,
+        domain_id: &str,
+        publisher: &str,
+        handle: &str,
+    ) -> Result<DepotStore<'a>> {
+        let store = Self::open(state, domain_id, publisher).await?;
+        ensure_publisher_admin(
+            &state.prisma,
+            &store.publisher_id,
+            publisher,
+            handle,
+            domain_id,
+        )
+        .await?;
+        Ok(store)
+    }
+
+    fn file_path(&self, hash: &str) -> String {
+        format!("{}/file/{}", self.root, hash)
+    }
+
+    fn manifest_path(&self, stem: &str, version: &str) -> String {
+        format!("{}/pkg/{}/{}", self.root, encode(stem), encode(version))
+    }
+
+    /// The detached signature of a manifest, made with the key of the publisher.
+    fn signature_path(&self, stem: &str, version: &str) -> String {
+        format!(
+            "{}.{}",
+            self.manifest_path(stem, version),
+            forge::SIGNATURE_EXTENSION
+        )
+    }
+
+    fn transaction_path(&self, id: &str) -> String {
+        format!("{}/trans/{}.json", self.root, id)
+    }
+
+    /// The actions of a transaction, named so they list in the order they were added.
+    fn actions_dir(&self, id: &str) -> String {
+        format!("{}/trans/{}/", self.root, id)
+    }
+
+    fn catalog_path(&self) -> String {
+        format!("{}/catalog.json", self.root)
+    }
+
+    async fn read_json<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Result<Option<T>> {
+        if !self.op.is_exist(path).await? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(
+            &self.op.read(path).await?.to_vec(),
+        )?))
+    }
+
+    async fn write_json<T: Serialize>(&self, path: &str, value: &T) -> Result<()> {
+        self.op.write(path, serde_json::to_vec(value)?).await?;
+        Ok(())
+    }
+
+    async fn transaction(&self, id: &str) -> Result<Transaction> {
+        self.read_json(&self.transaction_path(id))
+            .await?
+            .ok_or(Error::NotFound(format!("transaction {}", id)))
+    }
+
+    async fn actions(&self, id: &str) -> Result<Vec<String>> {
+        let mut paths = self
+            .op
+            .list(&self.actions_dir(id))
+            .await?
+            .into_iter()
+            .map(|entry| entry.path().to_string())
+            .filter(|path| !path.ends_with('/'))
+            .collect::<Vec<_>>();
+        paths.sort();
+        let mut actions = vec![];
+        for path in paths {
+            let content = self.op.read(&path).await?.to_vec();
+            actions.push(String::from_utf8_lossy(&content).into_owned());
+        }
+        Ok(actions)
+    }
+
+    /// Remove a transaction and its actions.
+    async fn delete_transaction(&self, id: &str) -> Result<()> {
+        self.op.remove_all(&self.actions_dir(id)).await?;
+        self.op.delete(&self.transaction_path(id)).await?;
+        Ok(())
+    }
+
+    async fn catalog(&self) -> Result<CatalogIndex> {
+        Ok(self
+            .read_json(&self.catalog_path())
+            .await?
+            .unwrap_or_default())
+    }
+}
+
+async fn versions() -> impl IntoResponse {
+    format!(
+        "pkg-server forged-{}\n\
+         versions 0\n\
+         catalog 1\n\
+         file 0 1\n\
+         manifest 0\n\
+         publisher 0\n\
+         open 0\n\
+         add 0\n\
+         close 0\n\
+         abandon 0\n",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+async fn publisher_versions(Path(_publisher): Path<String>) -> impl IntoResponse {
+    versions().await
+}
+
+async fn publisher_info(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path(publisher): Path<String>,
+) -> Result<Json<Value>> {
+    DepotStore::open(&state, &domain_id, &publisher).await?;
+    Ok(Json(json!({
+        "packages": [],
+        "publishers": [{
+            "alias": null,
+            "name": publisher,
+            "packages": [],
+            "repositories": [],
+        }],
+        "version": P5I_VERSION,
+    })))
+}
+
+async fn catalog(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path((publisher, part)): Path<(String, String)>,
+) -> Result<Json<Value>> {
+    let store = DepotStore::open(&state, &domain_id, &publisher).await?;
+    let index = store.catalog().await?;
+    catalog_part(&publisher, &index, &part)
+        .map(Json)
+        .ok_or(Error::NotFound(format!("catalog part {}", part)))
+}
+
+async fn manifest(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path((publisher, fmri)): Path<(String, String)>,
+) -> Result<Vec<u8>> {
+    let store = DepotStore::open(&state, &domain_id, &publisher).await?;
+    let (stem, version) = parse_fmri(&fmri)?;
+    let path = store.manifest_path(&stem, &version);
+    if !store.op.is_exist(&path).await? {
+        return Err(Error::NotFound(format!("package {}", fmri)));
+    }
+    Ok(store.op.read(&path).await?.to_vec())
+}
+
+/// The signature of a manifest, packages published while the publisher had no signing
+/// key have none.
+async fn signature(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path((publisher, fmri)): Path<(String, String)>,
+) -> Result<Vec<u8>> {
+    let store = DepotStore::open(&state, &domain_id, &publisher).await?;
+    let (stem, version) = parse_fmri(&fmri)?;
+    let path = store.signature_path(&stem, &version);
+    if !store.op.is_exist(&path).await? {
+        return Err(Error::NotFound(format!("signature of package {}", fmri)));
+    }
+    Ok(store.op.read(&path).await?.to_vec())
+}
+
+async fn file(
+    State(state): State<AppState>,
+    DomainScope { domain_id }: DomainScope,
+    Path((publisher, _version, hash)): Path<(String, String, String)>,
+) -> Result<Vec<u8>> {
+    let store = DepotStore::open(&state, &domain_id, &publisher).await?;
+    if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::InvalidPublication(format!("{} is not a hash", hash)));
+    }
+    let path = store.file_path(&hash);
+    if !store.op.is_exist(&path).await? {
+        return Err(Error::NotFound(format!("file {}", hash)));
+    }
+    Ok(store.op.read(&path).await?.to_vec())
+}
+
+async fn open_transaction(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path((publisher, fmri)): Path<(String, String)>,
+) -> Result<impl IntoResponse> {
+    let store = DepotStore::open_for_publishing(&state, &domain_id, &publisher, &handle).await?;
+    let (stem, version) = parse_fmri(&fmri)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    store
+        .write_json(&store.transaction_path(&id), &Transaction { stem, version })
+        .await?;
+    debug!("opened transaction {} for {}", id, fmri);
+    Ok([("Transaction-ID", id)])
+}
+
+/// Add an action to a transaction. Attributes come as numbered `X-IPkg-SetAttr`
+/// headers, the payload of file and license actions as the body. Payloads are stored
+/// compressed under the SHA-1 of their content like pkg.depotd does.
+async fn add_action(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path((publisher, id, name)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<()> {
+    let store = DepotStore::open_for_publishing(&state, &domain_id, &publisher, &handle).await?;
+    store.transaction(&id).await?;
+
+    let mut action = mogrify::Action {
+        name,
+        payload: None,
+        attributes: set_attributes(&headers)?,
+    };
+    if action.name == "file" || action.name == "license" {
+        if body.is_empty() {
+            return Err(Error::InvalidPublication(format!(
+                "{} action {} has no payload",
+                action.name, action
+            )));
+        }
+        let hash = hex::encode(Sha1::digest(&body));
+        let compressed = gzip(&body)?;
+        action.payload = Some(hash.clone());
+        action
+            .attributes
+            .push((String::from("pkg.size"), body.len().to_string()));
+        action
+            .attributes
+            .push((String::from("pkg.csize"), compressed.len().to_string()));
+        action.attributes.push((
+            String::from("chash"),
+            hex::encode(Sha1::digest(&compressed)),
+        ));
+
+        let path = store.file_path(&hash);
+        if !store.op.is_exist(&path).await? {
+            store.op.write(&path, compressed).await?;
+        }
+    }
+
+    let added = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    let path = format!(
+        "{}{:020}-{}",
+        store.actions_dir(&id),
+        added,
+        uuid::Uuid::new_v4()
+    );
+    store
+        .op
+        .write(&path, action.to_string().into_bytes())
+        .await?;
+    Ok(())
+}
+
+async fn close_transaction(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path((publisher, id)): Path<(String, String)>,
+) -> Result<impl IntoResponse> {
+    let store = DepotStore::open_for_publishing(&state, &domain_id, &publisher, &handle).await?;
+    let transaction = store.transaction(&id).await?;
+    let actions = store.actions(&id).await?;
+
+    let now = Utc::now();
+    let version = format!("{}:{}", transaction.version, now.format("%Y%m%dT%H%M%SZ"));
+    let fmri = format!("pkg://{}/{}@{}", publisher, transaction.stem, version);
+    let manifest = std::iter::once(format!("set name=pkg.fmri value={}", fmri))
+        .chain(actions)
+        .map(|line| line + "\n")
+        .collect::<String>();
+
+    store
+        .op
+        .write(
+            &store.manifest_path(&transaction.stem, &version),
+            manifest.clone().into_bytes(),
+        )
+        .await?;
+    if let Some(signature) = sign_for_publisher(
+        &state.prisma,
+        &state.secret_key_cipher,
+        &store.publisher_id,
+        &publisher,
+        manifest.as_bytes(),
+    )
+    .await?
+    {
+        store
+            .op
+            .write(
+                &store.signature_path(&transaction.stem, &version),
+                signature.into_bytes(),
+            )
+            .await?;
+    }
+
+    let _catalog_lock = state.catalog_locks.lock(&store.root).await;
+    let mut index = store.catalog().await?;
+    let timestamp = now.format("%Y%m%dT%H%M%S%.6fZ").to_string();
+    index.created.get_or_insert(timestamp.clone());
+    index.last_modified = Some(timestamp);
+    index
+        .packages
+        .entry(transaction.stem.clone())
+        .or_default()
+        .insert(version, catalog_entry(&manifest));
+    store.write_json(&store.catalog_path(), &index).await?;
+    store.delete_transaction(&id).await?;
+
+    info!("published {}", fmri);
+    Ok([("State", String::from("PUBLISHED")), ("Package-FMRI", fmri)])
+}
+
+async fn abandon_transaction(
+    State(state): State<AppState>,
+    Authentication {
+        domain_id, handle, ..
+    }: Authentication,
+    Path((publisher, id)): Path<(String, String)>,
+) -> Result<impl IntoResponse> {
+    let store = DepotStore::open_for_publishing(&state, &domain_id, &publisher, &handle).await?;
+    store.transaction(&id).await?;
+    store.delete_transaction(&id).await?;
+    debug!("abandoned transaction {}", id);
+    Ok([("State", "ABANDONED")])
+}
+
+fn encode(segment: &str) -> String {
+    url::form_urlencoded::byte_serialize(segment.as_bytes()).collect()
+}
+
+fn gzip(content: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(content)?;
+    Ok(encoder.finish()?)
+}
+
+/// The stem and version of `pkg:/name@version`, with or without scheme and publisher.
+fn parse_fmri(fmri: &str) -> Result<(String, String)> {
+    let name = match fmri.strip_prefix("pkg://") {
+        Some(rest) => rest.split_once('/').map(|(_, name)| name).unwrap_or(rest),
+        None => fmri.strip_prefix("pkg:/").unwrap_or(fmri),
+    };
+    match name.split_once('@') {
+        Some((stem, version)) if !stem.is_empty() && !version.is_empty() => {
+            Ok((stem.to_string(), version.to_string()))
+        }
+        _ => Err(Error::InvalidPublication(format!(
+            "{} is not a package FMRI with a version",
+            fmri
+        ))),
+    }
+}
+
+/// The attributes of the numbered `X-IPkg-SetAttr` headers in order.
+fn set_attributes(headers: &HeaderMap) -> Result<Vec<(String, String)>> {
+    let mut numbered = headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let number = name
+                .as_str()
+                .strip_prefix("x-ipkg-setattr")?
+                .parse::<usize>()
+                .ok()?;
+            Some((number, value))
+        })
+        .collect::<Vec<_>>();
+    numbered.sort_by_key(|(number, _)| *number);
+
+    numbered
+        .into_iter()
+        .map(|(_, value)| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or(Error::InvalidPublication(format!(
+                    "attribute {:?} is not key=value",
+                    value
+                )))
+        })
+        .collect()
+}
+
+/// The actions of the manifest clients need in the catalog to resolve dependencies
+/// and to search without downloading the manifest.
+fn catalog_entry(manifest: &str) -> CatalogEntry {
+    let mut dependency_actions = vec![];
+    let mut summary_actions = vec![];
+    for action in manifest
+        .lines()
+        .filter_map(|l| mogrify::Action::parse(l).ok())
+    {
+        let name = action.values("name").next().cloned().unwrap_or_default();
+        match action.name.as_str() {
+            "depend" => dependency_actions.push(action.to_string()),
+            "set"
+                if name.starts_with("variant.")
+                    || name == "pkg.obsolete"
+                    || name == "pkg.renamed" =>
+            {
+                dependency_actions.push(action.to_string())
+            }
+            "set"
+                if name == "pkg.summary"
+                    || name == "pkg.description"
+                    || name == "info.classification" =>
+            {
+                summary_actions.push(action.to_string())
+            }
+            _ => {}
+        }
+    }
+
+    CatalogEntry {
+        signature: hex::encode(Sha1::digest(manifest.as_bytes())),
+        dependency_actions,
+        summary_actions,
+    }
+}
+
+/// The catalog v1 part `part` of the packages of `publisher`.
+fn catalog_part(publisher: &str, index: &CatalogIndex, part: &str) -> Option<Value> {
+    let last_modified = index
+        .last_modified
+        .clone()
+        .unwrap_or_else(|| Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string());
+
+    if part == "catalog.attrs" {
+        let parts = CATALOG_PARTS
+            .iter()
+            .map(|p| (p.to_string(), json!({ "last-modified": &last_modified })))
+            .collect::<serde_json::Map<_, _>>();
+        return Some(json!({
+            "created": index.created.clone().unwrap_or(last_modified.clone()),
+            "last-modified": &last_modified,
+            "package-count": index.packages.len(),
+            "package-version-count": index.packages.values().map(BTreeMap::len).sum::<usize>(),
+            "parts": parts,
+            "updates": {},
+            "version": 1,
+        }));
+    }
+
+    if !CATALOG_PARTS.contains(&part) {
+        return None;
+    }
+    let packages = index
+        .packages
+        .iter()
+        .map(|(stem, versions)| {
+            let entries = versions
+                .iter()
+                .map(|(version, entry)| match part {
+                    "catalog.base.C" => {
+                        json!({ "version": version, "signature-sha-1": &entry.signature })
+                    }
+                    "catalog.dependency.C" => {
+                        json!({ "version": version, "actions": &entry.dependency_actions })
+                    }
+                    _ => json!({ "version": version, "actions": &entry.summary_actions }),
+                })
+                .collect::<Vec<_>>();
+            (stem.clone(), Value::Array(entries))
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let mut catalog = serde_json::Map::new();
+    catalog.insert(publisher.to_string(), Value::Object(packages));
+    Some(Value::Object(catalog))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fmris() {
+        assert_eq!(
+            parse_fmri("pkg://openindiana.org/library/foo@1.2,5.11-2024.0.0.1").unwrap(),
+            (
+                String::from("library/foo"),
+                String::from("1.2,5.11-2024.0.0.1")
+            )
+        );
+        assert_eq!(
+            parse_fmri("pkg:/library/foo@1.2").unwrap(),
+            (String::from("library/foo"), String::from("1.2"))
+        );
+        assert!(parse_fmri("library/foo").is_err());
+    }
+
+    #[test]
+    fn builds_catalog_parts() {
+        let manifest = "set name=pkg.fmri value=pkg://test/library/foo@1.2:20240101T000000Z\n\
+                        set name=pkg.summary value=\"The foo library\"\n\
+                        set name=variant.arch value=i386\n\
+                        depend fmri=library/bar type=require\n\
+                        file 0123abcd path=usr/lib/libfoo.so.1 mode=0555\n";
+        let entry = catalog_entry(manifest);
+        assert_eq!(
+            entry.dependency_actions,
+            vec![
+                String::from("set name=variant.arch value=i386"),
+                String::from("depend fmri=library/bar type=require"),
+            ]
+        );
+        assert_eq!(
+            entry.summary_actions,
+            vec![String::from(
+                "set name=pkg.summary value=\"The foo library\""
+            )]
+        );
+
+        let mut index = CatalogIndex::default();
+        index
+            .packages
+            .entry(String::from("library/foo"))
+            .or_default()
+            .insert(String::from("1.2:20240101T000000Z"), entry.clone());
+
+        let base = catalog_part("test", &index, "catalog.base.C").unwrap();
+        assert_eq!(
+            base["test"]["library/foo"][0]["signature-sha-1"],
+            json!(entry.signature)
+        );
+        let attrs = catalog_part("test", &index, "catalog.attrs").unwrap();
+        assert_eq!(attrs["package-version-count"], json!(1));
+        assert!(catalog_part("test", &index, "catalog.unknown.C").is_none());
+    }
+}
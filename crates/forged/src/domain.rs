@@ -0,0 +1,158 @@
+use url::Url;
+
+use crate::prisma::{self, PrismaClient};
+use crate::{Error, Result};
+
+/// The dns name of a domain from a `Host` header value which may include a port.
+pub fn dns_name_from_host(host: &str) -> &str {
+    // IPv6 literals like [::1]:3000 contain colons themselves
+    if let Some(rest) = host.strip_prefix('[') {
+        return rest.split_once(']').map(|(addr, _)| addr).unwrap_or(rest);
+    }
+    host.split_once(':').map(|(name, _)| name).unwrap_or(host)
+}
+
+pub async fn find_domain(db: &PrismaClient, host: &str) -> Result<prisma::domain::Data> {
+    db.domain()
+        .find_unique(prisma::domain::UniqueWhereParam::DnsNameEquals(
+            dns_name_from_host(host).to_string(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NoDomainFound)
+}
+
+/// Find the domain an activity belongs to by the host of its id.
+pub async fn find_domain_for_url(db: &PrismaClient, url: &Url) -> Result<prisma::domain::Data> {
+    let host = url.host_str().ok_or(Error::NoDomainFound)?;
+    find_domain(db, host).await
+}
+
+/// Filter for publishers belonging to the domain.
+pub fn publisher_scope(domain_id: &str) -> prisma::publisher::WhereParam {
+    prisma::publisher::domain_id::equals(domain_id.to_string())
+}
+
+/// Filter for gates whose publisher belongs to the domain.
+pub fn gate_scope(domain_id: &str) -> prisma::gate::WhereParam {
+    prisma::gate::publisher::is(vec![publisher_scope(domain_id)])
+}
+
+/// Filter for components whose gate belongs to the domain.
+pub fn component_scope(domain_id: &str) -> prisma::component::WhereParam {
+    prisma::component::gate::is(vec![gate_scope(domain_id)])
+}
+
+/// Find a gate by id but only if it belongs to the domain. Gates of other domains are
+/// reported as not found so their existence does not leak.
+pub async fn find_gate_in_domain(
+    db: &PrismaClient,
+    gate_id: &str,
+    domain_id: &str,
+) -> Result<prisma::gate::Data> {
+    db.gate()
+        .find_first(vec![
+            prisma::gate::id::equals(gate_id.to_string()),
+            gate_scope(domain_id),
+        ])
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("gate {}", gate_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dns_name_strips_port() {
+        assert_eq!(dns_name_from_host("forge.example.com"), "forge.example.com");
+        assert_eq!(
+            dns_name_from_host("forge.example.com:3100"),
+            "forge.example.com"
+        );
+        assert_eq!(dns_name_from_host("[::1]:3100"), "::1");
+        assert_eq!(dns_name_from_host("[::1]"), "::1");
+    }
+
+    /// Runs against a migrated database given in `FORGED_TEST_DATABASE_URL` and is
+    /// skipped otherwise.
+    #[tokio::test]
+    async fn queries_are_isolated_between_domains() -> Result<()> {
+        let Ok(url) = std::env::var("FORGED_TEST_DATABASE_URL") else {
+            return Ok(());
+        };
+        let db = prisma::new_client_with_url(&url).await?;
+
+        let suffix = uuid::Uuid::new_v4().to_string();
+        let mut domain_ids = vec![];
+        for name in ["a", "b"] {
+            let domain = db
+                .domain()
+                .create(
+                    format!("{}-{}.test", name, suffix),
+                    serde_json::json!({}),
+                    String::new(),
+                    String::new(),
+                    vec![],
+                )
+                .exec()
+                .await?;
+            // Both domains use the same publisher and gate names on purpose.
+            let publisher = db
+                .publisher()
+                .create(
+                    "openindiana.org".to_string(),
+                    prisma::domain::UniqueWhereParam::IdEquals(domain.id.clone()),
+                    vec![],
+                )
+                .exec()
+                .await?;
+            db.gate()
+                .create(
+                    "userland".to_string(),
+                    "2024.0.0".to_string(),
+                    "main".to_string(),
+                    prisma::publisher::UniqueWhereParam::IdEquals(publisher.id),
+                    serde_json::json!([]),
+                    vec![],
+                )
+                .exec()
+                .await?;
+            domain_ids.push(domain.id);
+        }
+
+        for domain_id in &domain_ids {
+            let publishers = db
+                .publisher()
+                .find_many(vec![publisher_scope(domain_id)])
+                .exec()
+                .await?;
+            assert_eq!(publishers.len(), 1);
+            assert_eq!(&publishers[0].domain_id, domain_id);
+
+            let gates = db
+                .gate()
+                .find_many(vec![gate_scope(domain_id)])
+                .exec()
+                .await?;
+            assert_eq!(gates.len(), 1);
+        }
+
+        let gate_of_a = db
+            .gate()
+            .find_first(vec![gate_scope(&domain_ids[0])])
+            .exec()
+            .await?
+            .expect("gate of domain a");
+        assert!(find_gate_in_domain(&db, &gate_of_a.id, &domain_ids[0])
+            .await
+            .is_ok());
+        assert!(matches!(
+            find_gate_in_domain(&db, &gate_of_a.id, &domain_ids[1]).await,
+            Err(Error::NotFound(_))
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::audit::{self, AuditEvent};
+use crate::prisma::{self, PrismaClient};
+use crate::{Error, Result};
+
+/// The settings of a gate both its gate.kdl and the API can change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GateConfig {
+    pub version: String,
+    pub branch: String,
+    pub publisher: String,
+    /// Stored like the API stores them, one string per transform
+    pub transforms: Vec<String>,
+}
+
+impl GateConfig {
+    pub fn from_kdl(gate: &gate::Gate) -> Self {
+        Self {
+            version: gate.version.clone(),
+            branch: gate.branch.clone(),
+            publisher: gate.publisher.clone(),
+            transforms: gate
+                .default_transforms
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+        }
+    }
+
+    fn from_record(gate: &prisma::gate::Data, publisher: &str) -> Result<Self> {
+        Ok(Self {
+            version: gate.version.clone(),
+            branch: gate.branch.clone(),
+            publisher: publisher.to_string(),
+            transforms: serde_json::from_value(gate.transforms.clone())?,
+        })
+    }
+
+    /// The names of the settings that differ from `other`.
+    pub fn changed_fields(&self, other: &Self) -> Vec<String> {
+        let fields = [
+            ("version", self.version != other.version),
+            ("branch", self.branch != other.branch),
+            ("publisher", self.publisher != other.publisher),
+            ("transforms", self.transforms != other.transforms),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+/// The settings edited since the last sync, through the API or the database, which the
+/// sync overwrites with another value. Gates never synced have nothing to compare with.
+pub fn config_conflicts(
+    synced: Option<&GateConfig>,
+    current: &GateConfig,
+    incoming: &GateConfig,
+) -> Vec<String> {
+    let Some(synced) = synced else {
+        return vec![];
+    };
+    let overwritten = current.changed_fields(incoming);
+    current
+        .changed_fields(synced)
+        .into_iter()
+        .filter(|field| overwritten.contains(field))
+        .collect()
+}
+
+/// Update the gate to what its gate.kdl says at `commit`. gate.kdl wins over edits made
+/// elsewhere, those are recorded as conflicts of the sync.
+pub async fn sync_gate_config(
+    db: &PrismaClient,
+    gate_id: &Uuid,
+    commit: &str,
+    gate: &gate::Gate,
+) -> Result<prisma::gate_config_sync::Data> {
+    let before = db
+        .gate()
+        .find_unique(prisma::gate::id::equals(gate_id.to_string()))
+        .with(prisma::gate::publisher::fetch())
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("gate {}", gate_id)))?;
+    let publisher = before
+        .publisher
+        .as_deref()
+        .ok_or(Error::NotFound(format!("publisher of gate {}", gate_id)))?;
+    let domain_id = publisher.domain_id.clone();
+
+    let current = GateConfig::from_record(&before, &publisher.name)?;
+    let incoming = GateConfig::from_kdl(gate);
+    let synced = before
+        .synced_config
+        .clone()
+        .map(serde_json::from_value::<GateConfig>)
+        .transpose()?;
+    let changed = current.changed_fields(&incoming);
+    let conflicts = config_conflicts(synced.as_ref(), &current, &incoming);
+    if !conflicts.is_empty() {
+        warn!(
+            "{} of gate {} were edited since the last sync and are overwritten by {} at {}",
+            conflicts.join(", "),
+            before.name,
+            forge::GATE_CONFIG_FILE,
+            commit
+        );
+    }
+
+    let mut updates = vec![prisma::gate::synced_config::set(Some(
+        serde_json::to_value(&incoming)?,
+    ))];
+    if !changed.is_empty() {
+        info!(
+            "updating {} of gate {} from {} at {}",
+            changed.join(", "),
+            before.name,
+            forge::GATE_CONFIG_FILE,
+            commit
+        );
+        if current.publisher != incoming.publisher {
+            // Publishers are only looked up in the domain of the gate
+            db.publisher()
+                .find_unique(prisma::publisher::UniqueWhereParam::NameDomainIdEquals(
+                    incoming.publisher.clone(),
+                    domain_id.clone(),
+                ))
+                .exec()
+                .await?
+                .ok_or(Error::NotFound(format!("publisher {}", incoming.publisher)))?;
+        }
+        updates.extend([
+            prisma::gate::version::set(incoming.version.clone()),
+            prisma::gate::branch::set(incoming.branch.clone()),
+            prisma::gate::transforms::set(serde_json::to_value(&incoming.transforms)?),
+            prisma::gate::SetParam::ConnectPublisher(
+                prisma::publisher::UniqueWhereParam::NameDomainIdEquals(
+                    incoming.publisher.clone(),
+                    domain_id.clone(),
+                ),
+            ),
+        ]);
+    }
+
+    let id = gate_id.to_string();
+    let commit = commit.to_string();
+    let (after, sync) = db
+        ._transaction()
+        .run::<Error, _, _, _>(|db| async move {
+            let after = db
+                .gate()
+                .update(prisma::gate::id::equals(id.clone()), updates)
+                .exec()
+                .await?;
+            let sync = db
+                .gate_config_sync()
+                .create(
+                    prisma::gate::id::equals(id),
+                    commit,
+                    vec![
+                        prisma::gate_config_sync::changed::set(changed),
+                        prisma::gate_config_sync::conflicts::set(conflicts),
+                    ],
+                )
+                .exec()
+                .await?;
+            Ok((after, sync))
+        })
+        .await?;
+    if !sync.changed.is_empty() {
+        audit::record(
+            db,
+            Some(&domain_id),
+            None,
+            AuditEvent::new("gate.config_sync", "gate", &after.id)
+                .before(&before)?
+                .after(&after)?,
+        )
+        .await?;
+    }
+    Ok(sync)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(version: &str, transforms: &[&str]) -> GateConfig {
+        GateConfig {
+            version: version.to_string(),
+            branch: String::from("2024.0.0"),
+            publisher: String::from("userland"),
+            transforms: transforms.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn only_overwritten_edits_conflict() {
+        let synced = config("0.5.11", &["<transform file -> drop>"]);
+        let incoming = config("0.5.12", &["<transform file -> drop>"]);
+
+        // Never synced before
+        assert!(config_conflicts(None, &synced, &incoming).is_empty());
+        // Not edited since the last sync
+        assert!(config_conflicts(Some(&synced), &synced, &incoming).is_empty());
+
+        let edited = config("0.5.12", &[]);
+        assert_eq!(
+            config_conflicts(Some(&synced), &edited, &incoming),
+            vec![String::from("transforms")]
+        );
+        assert_eq!(
+            edited.changed_fields(&incoming),
+            vec![String::from("transforms")]
+        );
+    }
+}
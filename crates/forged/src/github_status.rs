@@ -0,0 +1,442 @@
+use forge::{ChangeRequest, ExternalReference, Job, JobObject, JobReport, JobReportData};
+use octorust::auth::Credentials;
+use octorust::types::{
+    PullsUpdateReviewRequest, ReposCreateCommitStatusRequest, ReposCreateCommitStatusRequestState,
+};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::correlation;
+use crate::prisma::{self, PrismaClient};
+use crate::Result;
+
+/// Context of the status of the job reading the recipes of a pull request.
+const RECIPES_CONTEXT: &str = "forge/recipes";
+
+/// GitHub only keeps this many characters of a status description.
+const MAX_DESCRIPTION: usize = 140;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GitHubStatusConfig {
+    /// Token allowed to write commit statuses and pull request comments of the
+    /// repositories
+    pub token: String,
+    /// Link to the logs of a job, `{correlation_id}` is replaced with the correlation id
+    /// the job logged under
+    #[serde(default)]
+    pub log_url: Option<String>,
+}
+
+/// Where a status is posted to.
+#[derive(Debug, Clone, PartialEq)]
+struct Commit {
+    owner: String,
+    repo: String,
+    sha: String,
+}
+
+/// Reports the jobs of change requests back to their pull requests as commit statuses,
+/// one for reading the recipes and one per changed component, and comments what the
+/// recipes change. Does nothing without a configured token.
+#[derive(Clone)]
+pub struct GitHubStatus {
+    client: Option<octorust::Client>,
+    log_url: Option<String>,
+}
+
+impl std::fmt::Debug for GitHubStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitHubStatus")
+            .field("enabled", &self.client.is_some())
+            .field("log_url", &self.log_url)
+            .finish()
+    }
+}
+
+impl GitHubStatus {
+    pub fn new(config: Option<&GitHubStatusConfig>) -> Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self {
+                client: None,
+                log_url: None,
+            });
+        };
+        Ok(Self {
+            client: Some(octorust::Client::new(
+                "package forge",
+                Credentials::Token(config.token.clone()),
+            )?),
+            log_url: config.log_url.clone(),
+        })
+    }
+
+    /// Mark the statuses of a job forged sent to the workers as pending.
+    pub async fn job_queued(&self, db: &PrismaClient, job: &Job) {
+        if self.client.is_none() {
+            return;
+        }
+        let (commit, context) = match job {
+            Job::GetRecipes { cr, .. } => (
+                parse_github_repository(&cr.git_url).map(|(owner, repo)| Commit {
+                    owner,
+                    repo,
+                    sha: cr.head.sha.clone(),
+                }),
+                RECIPES_CONTEXT.to_string(),
+            ),
+            Job::BuildStoredComponent {
+                component_id,
+                staging: Some(staging),
+                ..
+            } => (
+                self.staging_commit(db, staging).await,
+                component_context(&component_id.name),
+            ),
+            _ => return,
+        };
+        if let Some(commit) = commit {
+            self.post(
+                &commit,
+                &context,
+                ReposCreateCommitStatusRequestState::Pending,
+                "waiting for a worker",
+            )
+            .await;
+        }
+    }
+
+    /// Post the outcome of a change request job.
+    pub async fn job_reported(&self, db: &PrismaClient, report: &JobReport) {
+        if self.client.is_none() {
+            return;
+        }
+        match report {
+            JobReport::Success(JobReportData::GetRecipes {
+                change_request_id,
+                recipes,
+                ..
+            }) => {
+                let Some(commit) = self.change_request_commit(db, change_request_id).await else {
+                    return;
+                };
+                self.post(
+                    &commit,
+                    RECIPES_CONTEXT,
+                    ReposCreateCommitStatusRequestState::Success,
+                    &format!("{} changed components", recipes.len()),
+                )
+                .await;
+                for (_, recipe, _, _) in recipes {
+                    self.post(
+                        &commit,
+                        &component_context(&recipe.name),
+                        ReposCreateCommitStatusRequestState::Success,
+                        &format!(
+                            "recipe {} recorded",
+                            recipe.version.as_deref().unwrap_or_default()
+                        ),
+                    )
+                    .await;
+                }
+            }
+            JobReport::Success(JobReportData::BuildStoredComponent {
+                component_id,
+                staging: Some(staging),
+                packages,
+                ..
+            }) => {
+                if let Some(commit) = self.staging_commit(db, staging).await {
+                    self.post(
+                        &commit,
+                        &component_context(&component_id.name),
+                        ReposCreateCommitStatusRequestState::Success,
+                        &format!("built {}", packages.join(", ")),
+                    )
+                    .await;
+                }
+            }
+            JobReport::Failure { object, error, .. } => {
+                self.post_object(
+                    db,
+                    object,
+                    ReposCreateCommitStatusRequestState::Failure,
+                    error,
+                )
+                .await;
+            }
+            JobReport::Cancelled { object, .. } => {
+                self.post_object(
+                    db,
+                    object,
+                    ReposCreateCommitStatusRequestState::Error,
+                    "cancelled",
+                )
+                .await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn post_object(
+        &self,
+        db: &PrismaClient,
+        object: &JobObject,
+        state: ReposCreateCommitStatusRequestState,
+        description: &str,
+    ) {
+        let (commit, context) = match object {
+            JobObject::ChangeRequest { cr_id, .. } => (
+                self.change_request_commit(db, cr_id.as_str()).await,
+                RECIPES_CONTEXT.to_string(),
+            ),
+            JobObject::Component {
+                component_id,
+                staging: Some(staging),
+                ..
+            } => (
+                self.staging_commit(db, staging).await,
+                component_context(&component_id.name),
+            ),
+            _ => return,
+        };
+        if let Some(commit) = commit {
+            self.post(&commit, &context, state, description).await;
+        }
+    }
+
+    async fn post(
+        &self,
+        commit: &Commit,
+        context: &str,
+        state: ReposCreateCommitStatusRequestState,
+        description: &str,
+    ) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        let target_url = match (&self.log_url, correlation::current()) {
+            (Some(log_url), Some(id)) => log_url.replace("{correlation_id}", &id),
+            _ => String::new(),
+        };
+        let request = ReposCreateCommitStatusRequest {
+            context: context.to_string(),
+            description: description.chars().take(MAX_DESCRIPTION).collect(),
+            state,
+            target_url,
+        };
+        match client
+            .repos()
+            .create_commit_status(&commit.owner, &commit.repo, &commit.sha, &request)
+            .await
+        {
+            Ok(_) => debug!(
+                "posted status {} of {}/{}@{}",
+                context, commit.owner, commit.repo, commit.sha
+            ),
+            Err(e) => warn!(
+                "could not post status {} of {}/{}@{}: {}",
+                context, commit.owner, commit.repo, commit.sha, e
+            ),
+        }
+    }
+
+    /// Comment on the pull request what the recorded recipes change, `changes` holds the
+    /// name of every recipe with the lines describing its changes.
+    pub async fn comment_recipe_changes(
+        &self,
+        db: &PrismaClient,
+        change_request_id: &str,
+        changes: &[(String, Vec<String>)],
+    ) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        if changes.is_empty() {
+            return;
+        }
+        let Some(cr) = self.last_change_request(db, change_request_id).await else {
+            return;
+        };
+        let ExternalReference::GitHub { pull_request } = &cr.external_ref;
+        let Some((owner, repo, number)) = parse_pull_request(pull_request) else {
+            warn!("could not comment on {}: not a pull request", pull_request);
+            return;
+        };
+        let request = PullsUpdateReviewRequest {
+            body: recipe_changes_comment(changes),
+        };
+        match client
+            .issues()
+            .create_comment(&owner, &repo, number, &request)
+            .await
+        {
+            Ok(_) => debug!("commented the recipe changes on {}", pull_request),
+            Err(e) => warn!(
+                "could not comment the recipe changes on {}: {}",
+                pull_request, e
+            ),
+        }
+    }
+
+    /// The change request as recorded in its last recipes job.
+    async fn last_change_request(
+        &self,
+        db: &PrismaClient,
+        change_request_id: &str,
+    ) -> Option<ChangeRequest> {
+        let job = db
+            .queued_job()
+            .find_first(vec![prisma::queued_job::reference::equals(
+                change_request_id.to_string(),
+            )])
+            .order_by(prisma::queued_job::created_at::order(Direction::Desc))
+            .exec()
+            .await
+            .map_err(|e| warn!("could not find the jobs of {}: {}", change_request_id, e))
+            .ok()??;
+        match serde_json::from_value(job.payload).ok()? {
+            Job::GetRecipes { cr, .. } => Some(cr),
+            _ => None,
+        }
+    }
+
+    /// The head commit of the change request, as recorded in its last recipes job.
+    async fn change_request_commit(
+        &self,
+        db: &PrismaClient,
+        change_request_id: &str,
+    ) -> Option<Commit> {
+        let cr = self.last_change_request(db, change_request_id).await?;
+        let (owner, repo) = parse_github_repository(&cr.git_url)?;
+        Some(Commit {
+            owner,
+            repo,
+            sha: cr.head.sha,
+        })
+    }
+
+    /// The head commit of the change request a staging repository belongs to.
+    async fn staging_commit(&self, db: &PrismaClient, staging: &str) -> Option<Commit> {
+        let publication = db
+            .staged_publication()
+            .find_unique(prisma::staged_publication::UniqueWhereParam::StagingEquals(
+                staging.to_string(),
+            ))
+            .exec()
+            .await
+            .map_err(|e| warn!("could not find staging repository {}: {}", staging, e))
+            .ok()??;
+        self.change_request_commit(db, &publication.change_request_id)
+            .await
+    }
+}
+
+fn component_context(name: &str) -> String {
+    format!("forge/{}", name)
+}
+
+/// The owner and name of a repository on GitHub from its ssh or https clone url.
+fn parse_github_repository(git_url: &str) -> Option<(String, String)> {
+    let path = git_url
+        .strip_prefix("git@github.com:")
+        .or_else(|| git_url.strip_prefix("ssh://git@github.com/"))
+        .or_else(|| git_url.strip_prefix("https://github.com/"))?;
+    let (owner, repo) = path.trim_end_matches('/').split_once('/')?;
+    let repo = repo.trim_end_matches(".git");
+    if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+        return None;
+    }
+    Some((owner.to_string(), repo.to_string()))
+}
+
+/// The owner, repository and number of a pull request referenced as `owner/repo/number`.
+fn parse_pull_request(reference: &str) -> Option<(String, String, i64)> {
+    let (repository, number) = reference.rsplit_once('/')?;
+    let (owner, repo) = repository.split_once('/')?;
+    if owner.is_empty() || repo.is_empty() {
+        return None;
+    }
+    Some((owner.to_string(), repo.to_string(), number.parse().ok()?))
+}
+
+/// The markdown of the comment listing what the recipes of a pull request change.
+fn recipe_changes_comment(changes: &[(String, Vec<String>)]) -> String {
+    let mut comment = String::from("Recipes recorded by the forge:\n");
+    for (name, lines) in changes {
+        if lines.is_empty() {
+            comment.push_str(&format!("\n**{}** is unchanged\n", name));
+            continue;
+        }
+        comment.push_str(&format!("\n**{}**\n", name));
+        for line in lines {
+            comment.push_str(&format!("- {}\n", line));
+        }
+    }
+    comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn github_repositories_from_clone_urls() {
+        let expected = Some((String::from("OpenIndiana"), String::from("oi-userland")));
+        assert_eq!(
+            parse_github_repository("git@github.com:OpenIndiana/oi-userland.git"),
+            expected
+        );
+        assert_eq!(
+            parse_github_repository("https://github.com/OpenIndiana/oi-userland"),
+            expected
+        );
+        assert_eq!(
+            parse_github_repository("ssh://git@github.com/OpenIndiana/oi-userland.git"),
+            expected
+        );
+        assert_eq!(
+            parse_github_repository("https://gitlab.com/OpenIndiana/oi-userland"),
+            None
+        );
+        assert_eq!(parse_github_repository("git@github.com:OpenIndiana"), None);
+    }
+
+    #[test]
+    fn pull_request_references() {
+        assert_eq!(
+            parse_pull_request("OpenIndiana/oi-userland/42"),
+            Some((String::from("OpenIndiana"), String::from("oi-userland"), 42))
+        );
+        assert_eq!(parse_pull_request("OpenIndiana/42"), None);
+        assert_eq!(parse_pull_request("OpenIndiana/oi-userland/main"), None);
+    }
+
+    #[test]
+    fn recipe_changes_as_markdown() {
+        let changes = vec![
+            (
+                String::from("library/zlib"),
+                vec![
+                    String::from("version: 1.3 → 1.3.1"),
+                    String::from("added dependency library/openssl"),
+                ],
+            ),
+            (String::from("sed"), vec![String::from("new component")]),
+            (String::from("gzip"), vec![]),
+        ];
+        assert_eq!(
+            recipe_changes_comment(&changes),
+            "Recipes recorded by the forge:\n\
+             \n\
+             **library/zlib**\n\
+             - version: 1.3 → 1.3.1\n\
+             - added dependency library/openssl\n\
+             \n\
+             **sed**\n\
+             - new component\n\
+             \n\
+             **gzip** is unchanged\n"
+        );
+    }
+}
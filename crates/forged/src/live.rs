@@ -0,0 +1,204 @@
+use forge::{JobObject, JobReport, JobReportData};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::trace;
+use utoipa::ToSchema;
+
+use crate::prisma::{self, PrismaClient};
+use crate::Result;
+
+/// How many updates a slow websocket client may fall behind before it misses some.
+const BACKLOG: usize = 1024;
+
+/// How a reported job ended.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobOutcome {
+    Success,
+    Failure,
+    Cancelled,
+}
+
+/// A change forged pushes to the live update clients of a domain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveUpdate {
+    /// A change request was opened, changed its state or finished processing
+    ChangeRequest {
+        id: String,
+        /// open, draft, closed or applied
+        state: String,
+        processing: bool,
+    },
+    /// A worker reported the outcome of a job
+    Job {
+        /// The reference of the job, like the component it built
+        reference: String,
+        kind: String,
+        outcome: JobOutcome,
+        error: Option<String>,
+    },
+}
+
+impl LiveUpdate {
+    pub fn change_request(change_request: &prisma::change_request::Data) -> Self {
+        let state = match change_request.state {
+            prisma::ChangeRequestState::Open => "open",
+            prisma::ChangeRequestState::Draft => "draft",
+            prisma::ChangeRequestState::Closed => "closed",
+            prisma::ChangeRequestState::Applied => "applied",
+        };
+        LiveUpdate::ChangeRequest {
+            id: change_request.id.clone(),
+            state: state.to_string(),
+            processing: change_request.processing,
+        }
+    }
+
+    pub fn job(report: &JobReport) -> Self {
+        let (outcome, error) = match report {
+            JobReport::Success(_) => (JobOutcome::Success, None),
+            JobReport::Failure { error, .. } => (JobOutcome::Failure, Some(error.clone())),
+            JobReport::Cancelled { .. } => (JobOutcome::Cancelled, None),
+        };
+        LiveUpdate::Job {
+            reference: report.reference(),
+            kind: report.kind().to_string(),
+            outcome,
+            error,
+        }
+    }
+}
+
+/// Fans the updates the AMQP consumer records out to the websocket clients. Updates are
+/// only sent to clients of the domain they belong to.
+#[derive(Debug, Clone)]
+pub struct LiveUpdates {
+    sender: broadcast::Sender<(String, LiveUpdate)>,
+}
+
+impl LiveUpdates {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BACKLOG);
+        Self { sender }
+    }
+
+    pub fn send(&self, domain_id: &str, update: LiveUpdate) {
+        trace!("live update for domain {}: {:?}", domain_id, update);
+        // Without connected clients there is nobody to tell
+        let _ = self.sender.send((domain_id.to_string(), update));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(String, LiveUpdate)> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The domain of the gate or change request the reported job worked on.
+pub async fn job_domain(db: &PrismaClient, report: &JobReport) -> Result<Option<String>> {
+    let gate_id = match report {
+        JobReport::Success(JobReportData::GetRecipes { gate_id, .. })
+        | JobReport::Failure {
+            object: JobObject::ChangeRequest { gate_id, .. },
+            ..
+        }
+        | JobReport::Cancelled {
+            object: JobObject::ChangeRequest { gate_id, .. },
+            ..
+        } => gate_id.to_string(),
+        JobReport::Success(JobReportData::BuildStoredComponent { component_id, .. })
+        | JobReport::Failure {
+            object: JobObject::Component { component_id, .. },
+            ..
+        }
+        | JobReport::Cancelled {
+            object: JobObject::Component { component_id, .. },
+            ..
+        } => component_id.gate_id.to_string(),
+        JobReport::Success(JobReportData::SyncGate { gate_id, .. })
+        | JobReport::Success(JobReportData::ReleaseGate { gate_id, .. })
+        | JobReport::Success(JobReportData::PublishRelease { gate_id, .. })
+        | JobReport::Success(JobReportData::SyncGateConfig { gate_id, .. })
+        | JobReport::Failure {
+            object: JobObject::Gate { gate_id, .. } | JobObject::GateConfig { gate_id, .. },
+            ..
+        }
+        | JobReport::Cancelled {
+            object: JobObject::Gate { gate_id, .. } | JobObject::GateConfig { gate_id, .. },
+            ..
+        } => gate_id.to_string(),
+        JobReport::Success(JobReportData::PromoteStagedPackages { staging, .. })
+        | JobReport::Failure {
+            object: JobObject::StagingRepository { name: staging },
+            ..
+        }
+        | JobReport::Cancelled {
+            object: JobObject::StagingRepository { name: staging },
+            ..
+        } => {
+            let publication = db
+                .staged_publication()
+                .find_unique(prisma::staged_publication::UniqueWhereParam::StagingEquals(
+                    staging.clone(),
+                ))
+                .with(prisma::staged_publication::change_request::fetch())
+                .exec()
+                .await?;
+            if let Some(publication) = publication {
+                return Ok(publication.change_request.and_then(|cr| cr.domain_id));
+            }
+            // Releases are staged too
+            match db
+                .release()
+                .find_unique(prisma::release::UniqueWhereParam::StagingEquals(
+                    staging.clone(),
+                ))
+                .exec()
+                .await?
+            {
+                Some(release) => release.gate_id,
+                None => return Ok(None),
+            }
+        }
+    };
+    Ok(db
+        .gate()
+        .find_unique(prisma::gate::UniqueWhereParam::IdEquals(gate_id))
+        .with(prisma::gate::publisher::fetch())
+        .exec()
+        .await?
+        .and_then(|gate| gate.publisher)
+        .map(|publisher| publisher.domain_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use forge::JobKind;
+
+    #[test]
+    fn job_updates_are_tagged() {
+        let report = JobReport::Cancelled {
+            object: JobObject::StagingRepository {
+                name: String::from("pr-12"),
+            },
+            kind: JobKind::PromoteStagedPackages,
+        };
+        assert_eq!(
+            serde_json::to_value(LiveUpdate::job(&report)).unwrap(),
+            serde_json::json!({
+                "type": "job",
+                "reference": "pr-12",
+                "kind": "PromoteStagedPackages",
+                "outcome": "cancelled",
+                "error": null,
+            })
+        );
+    }
+}
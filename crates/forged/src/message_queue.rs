@@ -1,25 +1,94 @@
-use crate::component_helpers::find_latest_component_in_set;
+use crate::api::v1::change_request::find_change_request;
+use crate::api::v1::gate::enqueue_dependents_rebuild;
+use crate::audit::{self, component_object_id, AuditEvent};
+use crate::component_helpers::{find_latest_component_in_set, recipe_search_params};
+use crate::domain::{find_domain_for_url, find_gate_in_domain, gate_scope};
+use crate::gate_config::sync_gate_config;
+use crate::github_status::GitHubStatus;
+use crate::live::{job_domain, LiveUpdate, LiveUpdates};
+use crate::notification::{self, NotificationEvent};
 use crate::prisma::read_filters::{BoolFilter, StringFilter};
 use crate::prisma::{self, PrismaClient};
+use crate::provenance::record_provenance;
+use crate::release::{
+    build_release, fail_release, record_published_release, record_release_build, start_release,
+};
+use crate::scheduler::{dispatch_jobs, enqueue_job, finish_job, JobPriority};
+use crate::signing::SecretKeyCipher;
 use crate::{Error, Result};
-use component::Recipe;
+use component::{describe_recipe_diff, PackageMeta, Recipe};
 use deadpool_lapin::lapin::message::Delivery;
 use deadpool_lapin::lapin::Channel;
 use diff::Diff;
-use forge::{ActivityObject, ChangeRequestState, Event, JobReport, JobReportData};
-use tracing::{debug, error, info, instrument};
+use forge::{
+    ActivityObject, ChangeRequestCommand, ChangeRequestState, CheckResult, CommandEnvelope, Event,
+    Job, JobLogChunk, JobObject, JobReport, JobReportData, PatchFile,
+};
+use prisma_client_rust::Direction;
+use tracing::{debug, error, info, instrument, warn};
 
 #[instrument(skip_all)]
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_message(
     deliver: Delivery,
     routing_key: &str,
     db: &PrismaClient,
-    _channel: &Channel,
+    channel: &Channel,
+    job_inbox: &str,
+    updates: &LiveUpdates,
+    github_status: &GitHubStatus,
+    secret_key_cipher: &SecretKeyCipher,
 ) -> Result<()> {
     let body = deliver.data;
     match routing_key {
+        "forged.job" => {
+            let job: Job = serde_json::from_slice(&body)?;
+            if let Job::SyncGate {
+                gate_id, branch, ..
+            }
+            | Job::SyncGateConfig {
+                gate_id, branch, ..
+            } = &job
+            {
+                // Webhooks send every push, only the branch of the gate is synced
+                let gate = db
+                    .gate()
+                    .find_unique(prisma::gate::UniqueWhereParam::IdEquals(gate_id.to_string()))
+                    .exec()
+                    .await?
+                    .ok_or(Error::NotFound(format!("gate {}", gate_id)))?;
+                if &gate.branch != branch {
+                    debug!(
+                        "gate {} tracks {} not {}, ignoring push",
+                        gate.name, gate.branch, branch
+                    );
+                    return Ok(());
+                }
+            }
+            if let Job::ReleaseGate { gate_id, tag, .. } = &job {
+                // GitHub sends both a create and a release event for a new release
+                if !start_release(db, gate_id, tag).await? {
+                    debug!("release {} of gate {} already started, ignoring", tag, gate_id);
+                    return Ok(());
+                }
+            }
+            enqueue_job(db, &job, JobPriority::Normal).await?;
+            github_status.job_queued(db, &job).await;
+            dispatch_jobs(db, channel, job_inbox).await
+        }
         "forged.jobreport" => {
             let report: JobReport = serde_json::from_slice(&body)?;
+            finish_job(db, &report).await?;
+            dispatch_jobs(db, channel, job_inbox).await?;
+            match job_domain(db, &report).await {
+                Ok(Some(domain_id)) => updates.send(&domain_id, LiveUpdate::job(&report)),
+                Ok(None) => {}
+                Err(e) => warn!("could not find the domain of job {}: {}", report.reference(), e),
+            }
+            github_status.job_reported(db, &report).await;
+            if let Some(event) = NotificationEvent::job_failed(&report) {
+                notification::notify(db, event).await;
+            }
             match report {
                 JobReport::Success(data) => match data {
                     JobReportData::GetRecipes {
@@ -28,8 +97,37 @@ pub async fn handle_message(
                         recipes,
                     } => {
                         debug!("Processing Job report from worker");
+                        let change_request = db
+                            .change_request()
+                            .find_unique(prisma::change_request::UniqueWhereParam::IdEquals(
+                                change_request_id.clone(),
+                            ))
+                            .exec()
+                            .await?
+                            .ok_or(Error::JobSaveErrorNoChangeRequest(
+                                change_request_id.clone(),
+                            ))?;
+
+                        // Check first if we have the gate we are trying to record changes for
+                        // and that it belongs to the same domain as the change request
+                        let mut gate_filter = vec![prisma::gate::id::equals(gate_id.to_string())];
+                        if let Some(domain_id) = &change_request.domain_id {
+                            gate_filter.push(gate_scope(domain_id));
+                        }
+                        if db.gate().find_first(gate_filter).exec().await?.is_none() {
+                            info!(
+                                "No gate record {} found in the domain of change request {} skipping report",
+                                gate_id, change_request_id
+                            );
+                            return Ok(());
+                        }
+
+                        let mut summaries = vec![];
                         for (component_ref, recipe, package_meta, patches) in recipes {
                             debug!("Processing component {component_ref}");
+                            for skip in &recipe.skipped_steps {
+                                warn!("component {} skips {}", recipe.name, skip);
+                            }
                             let name = recipe.name.clone();
                             let version = recipe
                                 .version
@@ -37,20 +135,6 @@ pub async fn handle_message(
                                 .ok_or(Error::NoVersionFoundInRecipe(recipe.name.clone()))?;
                             let revision = recipe.revision.clone().unwrap_or("0".to_string());
 
-                            // Check first if we have the gate we are trying to record a change for
-                            if !db
-                                .gate()
-                                .find_unique(prisma::gate::UniqueWhereParam::IdEquals(
-                                    gate_id.to_string(),
-                                ))
-                                .exec()
-                                .await
-                                .is_ok()
-                            {
-                                info!("No gate record found for requested change to component {} skipping", name);
-                                continue;
-                            }
-
                             let mut set_params = vec![];
                             let components = db
                                 .component()
@@ -93,9 +177,14 @@ pub async fn handle_message(
                                 let existing_recipe: Recipe =
                                     serde_json::from_value(component.recipe)?;
                                 let recipe_diff = existing_recipe.diff(&recipe);
+                                summaries.push((
+                                    name.clone(),
+                                    describe_recipe_diff(&existing_recipe, &recipe_diff),
+                                ));
 
                                 serde_json::to_value(&recipe_diff)?
                             } else {
+                                summaries.push((name.clone(), vec![String::from("new component")]));
                                 serde_json::Value::Null
                             };
 
@@ -172,7 +261,8 @@ pub async fn handle_message(
                                     .await?;
                             }
 
-                            db.change_request()
+                            let processed = db
+                                .change_request()
                                 .update(
                                     prisma::change_request::UniqueWhereParam::IdEquals(
                                         change_request_id.clone(),
@@ -181,8 +271,116 @@ pub async fn handle_message(
                                 )
                                 .exec()
                                 .await?;
+                            if let Some(domain_id) = &processed.domain_id {
+                                updates.send(domain_id, LiveUpdate::change_request(&processed));
+                            }
                         }
 
+                        github_status
+                            .comment_recipe_changes(db, &change_request_id, &summaries)
+                            .await;
+                        Ok(())
+                    }
+                    JobReportData::BuildStoredComponent {
+                        component_id,
+                        packages,
+                        staging,
+                        bootstrap,
+                        checks,
+                        network_hosts,
+                        provenance,
+                    } => {
+                        info!(
+                            "Built {}stored component {} and published {}",
+                            if bootstrap { "bootstrap variant of " } else { "" },
+                            component_id,
+                            packages.join(", ")
+                        );
+                        log_check_results(&checks);
+                        log_network_hosts(&component_id, &network_hosts);
+                        if let Some(statement) = provenance {
+                            record_provenance(db, secret_key_cipher, &statement).await?;
+                        }
+                        if let Some(staging) = staging {
+                            if record_release_build(db, &staging, packages.clone()).await? {
+                                dispatch_jobs(db, channel, job_inbox).await?;
+                            } else {
+                                record_staged_build(db, &staging, packages).await?;
+                            }
+                        }
+                        Ok(())
+                    }
+                    JobReportData::SyncGate {
+                        gate_id,
+                        commit,
+                        recipes,
+                    } => {
+                        info!(
+                            "Syncing {} components of gate {} with commit {}",
+                            recipes.len(),
+                            gate_id,
+                            commit
+                        );
+                        sync_gate_components(db, &gate_id, recipes, true).await?;
+                        dispatch_jobs(db, channel, job_inbox).await
+                    }
+                    JobReportData::SyncGateConfig {
+                        gate_id,
+                        commit,
+                        gate,
+                    } => {
+                        let sync = sync_gate_config(db, &gate_id, &commit, &gate).await?;
+                        if !sync.conflicts.is_empty() {
+                            notification::notify(
+                                db,
+                                NotificationEvent::GateConfigConflict {
+                                    gate_id: gate_id.to_string(),
+                                    commit,
+                                    fields: sync.conflicts,
+                                },
+                            )
+                            .await;
+                        }
+                        Ok(())
+                    }
+                    JobReportData::ReleaseGate {
+                        gate_id,
+                        tag,
+                        recipes,
+                    } => {
+                        build_release(db, &gate_id, &tag, recipes).await?;
+                        dispatch_jobs(db, channel, job_inbox).await
+                    }
+                    JobReportData::PublishRelease {
+                        gate_id,
+                        tag,
+                        staging,
+                        packages,
+                    } => {
+                        info!(
+                            "Published release {} of gate {} with {}",
+                            tag,
+                            gate_id,
+                            packages.join(", ")
+                        );
+                        record_published_release(db, &staging, packages).await
+                    }
+                    JobReportData::PromoteStagedPackages { staging, packages } => {
+                        info!("Promoted {} from {}", packages.join(", "), staging);
+                        db.staged_publication()
+                            .update(
+                                prisma::staged_publication::UniqueWhereParam::StagingEquals(
+                                    staging,
+                                ),
+                                vec![
+                                    prisma::staged_publication::state::set(
+                                        prisma::StagedPublicationState::Promoted,
+                                    ),
+                                    prisma::staged_publication::packages::set(packages),
+                                ],
+                            )
+                            .exec()
+                            .await?;
                         Ok(())
                     }
                 },
@@ -190,18 +388,36 @@ pub async fn handle_message(
                     error,
                     object,
                     kind,
+                    checks,
+                    network_hosts,
                 } => {
                     error!("Job reported an error {error} while processing {kind} for {object}");
-
-                    Ok(())
+                    log_check_results(&checks);
+                    log_network_hosts(&object, &network_hosts);
+                    if fail_release(db, &object, &kind, &error).await? {
+                        return Ok(());
+                    }
+                    fail_staged_publication(db, object, error).await
+                }
+                JobReport::Cancelled { object, kind } => {
+                    warn!("Job {kind} for {object} was cancelled");
+                    if fail_release(db, &object, &kind, "cancelled").await? {
+                        return Ok(());
+                    }
+                    fail_staged_publication(db, object, String::from("cancelled")).await
                 }
             }
         }
+        "forged.joblog" => {
+            let chunk: JobLogChunk = serde_json::from_slice(&body)?;
+            append_job_log(db, chunk).await
+        }
         "forged.event" => {
             let envelope: Event = serde_json::from_slice(&body)?;
             match envelope {
                 Event::Create(envelope) => {
                     debug!("got create event: {:?}", envelope);
+                    let domain = find_domain_for_url(db, &envelope.id).await?;
                     match envelope.object {
                         // We assume Create events are only sent when the webhook records the opening of a PR
                         // All Jobs send updates to the ChangeRequest
@@ -211,6 +427,11 @@ pub async fn handle_message(
                                 .create(
                                     change_request.id,
                                     vec![
+                                        prisma::change_request::SetParam::ConnectDomain(
+                                            prisma::domain::UniqueWhereParam::IdEquals(
+                                                domain.id.clone(),
+                                            ),
+                                        ),
                                         prisma::change_request::SetParam::SetProcessing(true),
                                         prisma::change_request::SetParam::SetExternalReference(
                                             Some(change_request.external_ref.to_string()),
@@ -240,11 +461,26 @@ pub async fn handle_message(
                                 db_cr.id,
                                 change_request.external_ref.to_string()
                             );
+                            audit::record(
+                                db,
+                                Some(&domain.id),
+                                None,
+                                AuditEvent::new(
+                                    "change_request.create",
+                                    "change_request",
+                                    &db_cr.id,
+                                )
+                                .after(&db_cr)?,
+                            )
+                            .await?;
+                            updates.send(&domain.id, LiveUpdate::change_request(&db_cr));
                             Ok(())
                         }
                         ActivityObject::Component { component, gate } => {
+                            find_gate_in_domain(db, &gate, &domain.id).await?;
                             let recipe = &component.recipe;
-                            db.component()
+                            let created = db
+                                .component()
                                 .create(
                                     component.recipe.name.clone(),
                                     recipe.version.clone().ok_or(Error::NoVersionFoundInRecipe(
@@ -258,35 +494,77 @@ pub async fn handle_message(
                                     serde_json::to_value(&component.recipe)?,
                                     serde_json::Value::Null, //TODO Find solution to transport patches over the wire
                                     serde_json::to_value(&component.package_meta)?,
-                                    vec![],
+                                    recipe_search_params(recipe),
                                 )
                                 .exec()
                                 .await?;
+                            audit::record(
+                                db,
+                                Some(&domain.id),
+                                None,
+                                AuditEvent::new(
+                                    "component.create",
+                                    "component",
+                                    &component_object_id(&created),
+                                )
+                                .after(&created)?,
+                            )
+                            .await?;
                             Ok(())
                         }
                         ActivityObject::Gate(gate) => {
-                            db.gate()
+                            let created = db
+                                .gate()
                                 .create(
                                     gate.name,
                                     gate.version,
                                     gate.branch,
-                                    prisma::publisher::UniqueWhereParam::NameEquals(gate.publisher),
+                                    prisma::publisher::UniqueWhereParam::NameDomainIdEquals(
+                                        gate.publisher,
+                                        domain.id.clone(),
+                                    ),
                                     serde_json::to_value(&gate.default_transforms)?,
                                     vec![],
                                 )
                                 .exec()
                                 .await?;
+                            audit::record(
+                                db,
+                                Some(&domain.id),
+                                None,
+                                AuditEvent::new("gate.create", "gate", &created.id)
+                                    .after(&created)?,
+                            )
+                            .await?;
                             Ok(())
                         }
                     }
                 }
                 Event::Update(envelope) => {
                     debug!("got update event: {:?}", envelope);
+                    let domain = find_domain_for_url(db, &envelope.id).await?;
                     match envelope.object {
                         ActivityObject::ChangeRequest(change_request) => {
                             // Take processing Component Changes that are processing and send them to the dedicated handler inbox
                             // Ones that have a defined change kind are to be upserted to the database as they are already processed externally
 
+                            // Never let one domain update the change requests of another
+                            let existing = db
+                                .change_request()
+                                .find_unique(prisma::change_request::UniqueWhereParam::IdEquals(
+                                    change_request.id.clone(),
+                                ))
+                                .exec()
+                                .await?;
+                            if let Some(existing) = &existing {
+                                if existing.domain_id.as_ref().is_some_and(|id| id != &domain.id) {
+                                    return Err(Error::NotFound(format!(
+                                        "change request {}",
+                                        change_request.id
+                                    )));
+                                }
+                            }
+
                             let db_change_request = db
                                 .change_request()
                                 .upsert(
@@ -296,6 +574,11 @@ pub async fn handle_message(
                                     (
                                         change_request.id,
                                         vec![
+                                            prisma::change_request::SetParam::ConnectDomain(
+                                                prisma::domain::UniqueWhereParam::IdEquals(
+                                                    domain.id.clone(),
+                                                ),
+                                            ),
                                             prisma::change_request::SetParam::SetProcessing(true),
                                             prisma::change_request::SetParam::SetExternalReference(
                                                 Some(change_request.external_ref.to_string()),
@@ -319,6 +602,11 @@ pub async fn handle_message(
                                         ],
                                     ),
                                     vec![
+                                        prisma::change_request::SetParam::ConnectDomain(
+                                            prisma::domain::UniqueWhereParam::IdEquals(
+                                                domain.id.clone(),
+                                            ),
+                                        ),
                                         prisma::change_request::SetParam::SetExternalReference(
                                             Some(change_request.external_ref.to_string()),
                                         ),
@@ -352,65 +640,35 @@ pub async fn handle_message(
                                 db_change_request.id,
                                 change_request.external_ref.to_string()
                             );
+                            audit::record(
+                                db,
+                                Some(&domain.id),
+                                None,
+                                AuditEvent::new(
+                                    "change_request.update",
+                                    "change_request",
+                                    &db_change_request.id,
+                                )
+                                .before(&existing)?
+                                .after(&db_change_request)?,
+                            )
+                            .await?;
+                            updates.send(
+                                &domain.id,
+                                LiveUpdate::change_request(&db_change_request),
+                            );
 
                             match db_change_request.state {
                                 prisma::ChangeRequestState::Applied => {
                                     info!("Change Request {} has been applied Checking if any Changes still need applying", &db_change_request.id);
-                                    db._transaction().run::<crate::Error, _, _, _>(|db| async move {
-                                        if let Some(changes) = db_change_request.component_changes {
-                                            for change in changes {
-                                                let recipe: Recipe = serde_json::from_value(change.recipe.clone())?;
-                                                let name = recipe.name.clone();
-                                                let version = recipe
-                                                    .version
-                                                    .clone()
-                                                    .ok_or(Error::NoVersionFoundInRecipe(recipe.name.clone()))?;
-                                                let revision = recipe.revision.clone().unwrap_or("0".to_string());
-                                                let mut component_set_params = vec![];
-                                                if let Some(metadata) = &recipe.metadata {
-                                                    for item in &metadata.0 {
-                                                        match item.name.as_str() {
-                                                            "anitya-id" => {
-                                                                component_set_params.push(
-                                                                    prisma::component::SetParam::SetAnityaId(Some(item.value.clone()))
-                                                                );
-                                                            }
-                                                            "repology-id" => {
-                                                                component_set_params.push(
-                                                                    prisma::component::SetParam::SetRepologyId(Some(item.value.clone()))
-                                                                );
-                                                            }
-                                                            _ => {}
-                                                        }
-                                                    }
-                                                }
-
-                                                info!("Applying Component change for {}@{}-{}", &name, &version, &revision);
-                                                db.component_change().update(
-                                                    prisma::component_change::UniqueWhereParam::IdEquals(change.id),
-                                                    vec![
-                                                        prisma::component_change::SetParam::SetApplied(true)
-                                                    ]
-                                                ).exec().await?;
-
-                                                db.component().create(
-                                                    name.clone(),
-                                                    version,
-                                                    revision,
-                                                    recipe.project_url.ok_or(
-                                                        Error::NoProjectUrlFoundInRecipe(name.clone())
-                                                    )?,
-                                                    prisma::gate::UniqueWhereParam::IdEquals(change.gate_id.unwrap()),
-                                                    change.recipe,
-                                                    change.patches,
-                                                    change.package_meta,
-                                                    component_set_params,
-                                                ).exec().await?;
-                                            }
-                                        }
-                                        Ok(())
-                                    }).await?;
-                                    info!("Merged Changes into Component Database");
+                                    apply_component_changes(
+                                        db,
+                                        channel,
+                                        job_inbox,
+                                        &db_change_request.id,
+                                        db_change_request.component_changes.unwrap_or_default(),
+                                    )
+                                    .await?;
                                 }
                                 _ => {}
                             }
@@ -418,6 +676,7 @@ pub async fn handle_message(
                             Ok(())
                         }
                         ActivityObject::Component { component, gate } => {
+                            find_gate_in_domain(db, &gate, &domain.id).await?;
                             let recipe = &component.recipe;
                             db.component()
                         .update(
@@ -447,25 +706,29 @@ pub async fn handle_message(
                                 prisma::component::SetParam::SetPackages(serde_json::to_value(
                                     &component.package_meta,
                                 )?),
-                            ],
+                            ]
+                            .into_iter()
+                            .chain(recipe_search_params(recipe))
+                            .collect(),
                         )
                         .exec()
                         .await?;
                             Ok(())
                         }
                         ActivityObject::Gate(gate) => {
+                            let gate_id = gate.id.ok_or(Error::NoIdFoundINGate(gate.name.clone()))?;
+                            find_gate_in_domain(db, &gate_id, &domain.id).await?;
                             db.gate()
                                 .update(
-                                    prisma::gate::UniqueWhereParam::IdEquals(
-                                        gate.id.ok_or(Error::NoIdFoundINGate(gate.name.clone()))?,
-                                    ),
+                                    prisma::gate::UniqueWhereParam::IdEquals(gate_id),
                                     vec![
                                         prisma::gate::SetParam::SetVersion(gate.version),
                                         prisma::gate::SetParam::SetName(gate.name),
                                         prisma::gate::SetParam::SetBranch(gate.branch),
                                         prisma::gate::SetParam::ConnectPublisher(
-                                            prisma::publisher::UniqueWhereParam::NameEquals(
+                                            prisma::publisher::UniqueWhereParam::NameDomainIdEquals(
                                                 gate.publisher,
+                                                domain.id,
                                             ),
                                         ),
                                         prisma::gate::SetParam::SetTransforms(
@@ -500,9 +763,429 @@ pub async fn handle_message(
                 }
             }
         }
+        "forged.command" => {
+            let envelope: CommandEnvelope = serde_json::from_slice(&body)?;
+            run_command(db, channel, job_inbox, github_status, envelope).await
+        }
         unknown => {
             error!("unknown routing key {unknown}");
             Ok(())
         }
     }
 }
+
+/// Add the packages of a finished build to its staging repository. Once the last build
+/// reported back the packages are ready to be promoted.
+/// Merge the component changes of an applied change request into the components of their
+/// gates and rebuild what depends on them.
+pub(crate) async fn apply_component_changes(
+    db: &PrismaClient,
+    channel: &Channel,
+    job_inbox: &str,
+    change_request_id: &str,
+    changes: Vec<prisma::component_change::Data>,
+) -> Result<()> {
+    let applied = db
+        ._transaction()
+        .run::<crate::Error, _, _, _>(|db| async move {
+            let mut applied: Vec<(String, String)> = vec![];
+            for change in changes {
+                let recipe: Recipe = serde_json::from_value(change.recipe.clone())?;
+                let name = recipe.name.clone();
+                let version = recipe
+                    .version
+                    .clone()
+                    .ok_or(Error::NoVersionFoundInRecipe(recipe.name.clone()))?;
+                let revision = recipe.revision.clone().unwrap_or("0".to_string());
+                let mut component_set_params = recipe_search_params(&recipe);
+                if let Some(metadata) = &recipe.metadata {
+                    for item in &metadata.0 {
+                        match item.name.as_str() {
+                            "anitya-id" => {
+                                component_set_params.push(
+                                    prisma::component::SetParam::SetAnityaId(Some(
+                                        item.value.clone(),
+                                    )),
+                                );
+                            }
+                            "repology-id" => {
+                                component_set_params.push(
+                                    prisma::component::SetParam::SetRepologyId(Some(
+                                        item.value.clone(),
+                                    )),
+                                );
+                            }
+                            "purl" => {
+                                component_set_params.push(prisma::component::SetParam::SetPurl(
+                                    Some(item.value.clone()),
+                                ));
+                            }
+                            "cpe" => {
+                                component_set_params.push(prisma::component::SetParam::SetCpe(
+                                    Some(item.value.clone()),
+                                ));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                // Files uploaded with the change, like the archive of an uploaded component
+                component_set_params.push(prisma::component::archives::set(change.archives));
+                component_set_params.push(prisma::component::scripts::set(change.scripts));
+
+                info!("Applying Component change for {}@{}-{}", &name, &version, &revision);
+                let gate_id = change.gate_id.clone().ok_or(Error::NotFound(format!(
+                    "gate of component change {}",
+                    change.id
+                )))?;
+                db.component_change()
+                    .update(
+                        prisma::component_change::UniqueWhereParam::IdEquals(change.id),
+                        vec![prisma::component_change::SetParam::SetApplied(true)],
+                    )
+                    .exec()
+                    .await?;
+
+                db.component()
+                    .create(
+                        name.clone(),
+                        version,
+                        revision,
+                        recipe
+                            .project_url
+                            .ok_or(Error::NoProjectUrlFoundInRecipe(name.clone()))?,
+                        prisma::gate::UniqueWhereParam::IdEquals(gate_id.clone()),
+                        change.recipe,
+                        change.patches,
+                        change.package_meta,
+                        component_set_params,
+                    )
+                    .exec()
+                    .await?;
+                applied.push((gate_id, name));
+            }
+            Ok(applied)
+        })
+        .await?;
+    info!("Merged Changes into Component Database");
+
+    // Components depending on the merged ones have to be built against them
+    let mut gates: Vec<String> = applied.iter().map(|(gate_id, _)| gate_id.clone()).collect();
+    gates.sort();
+    gates.dedup();
+    for gate_id in gates {
+        let changed = applied
+            .iter()
+            .filter(|(g, _)| g == &gate_id)
+            .map(|(_, name)| name.clone())
+            .collect::<Vec<_>>();
+        enqueue_dependents_rebuild(db, &gate_id, &changed, JobPriority::Normal).await?;
+        notification::notify(
+            db,
+            NotificationEvent::ChangeRequestApplied {
+                gate_id,
+                change_request_id: change_request_id.to_string(),
+                components: changed,
+            },
+        )
+        .await;
+    }
+    dispatch_jobs(db, channel, job_inbox).await
+}
+
+async fn record_staged_build(
+    db: &PrismaClient,
+    staging: &str,
+    packages: Vec<String>,
+) -> Result<()> {
+    let publication = db
+        .staged_publication()
+        .update(
+            prisma::staged_publication::UniqueWhereParam::StagingEquals(staging.to_string()),
+            vec![
+                prisma::staged_publication::pending_builds::decrement(1),
+                prisma::staged_publication::packages::push(packages),
+            ],
+        )
+        .exec()
+        .await?;
+
+    // A failed build keeps the whole staging repository from being promoted
+    if publication.pending_builds <= 0
+        && publication.state == prisma::StagedPublicationState::Building
+    {
+        db.staged_publication()
+            .update(
+                prisma::staged_publication::UniqueWhereParam::StagingEquals(staging.to_string()),
+                vec![prisma::staged_publication::state::set(
+                    prisma::StagedPublicationState::Staged,
+                )],
+            )
+            .exec()
+            .await?;
+        info!(
+            "Packages of staging repository {} are ready to be promoted",
+            staging
+        );
+    }
+    Ok(())
+}
+
+/// Record the recipes read from the branch of a gate as its components and rebuild
+/// the ones whose recipe or patches changed together with their dependents if asked to.
+/// New versions become new components, the builds of unchanged recipes are kept.
+pub(crate) async fn sync_gate_components(
+    db: &PrismaClient,
+    gate_id: &uuid::Uuid,
+    recipes: Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)>,
+    rebuild: bool,
+) -> Result<()> {
+    let mut changed = vec![];
+    for (component_ref, recipe, package_meta, patches) in recipes {
+        let version = recipe
+            .version
+            .clone()
+            .ok_or(Error::NoVersionFoundInRecipe(recipe.name.clone()))?;
+        let revision = recipe.revision.clone().unwrap_or(String::from("0"));
+        let recipe_value = serde_json::to_value(&recipe)?;
+        let patch_value = serde_json::to_value(&patches)?;
+        let package_meta_value = serde_json::to_value(&package_meta)?;
+
+        let existing = db
+            .component()
+            .find_unique(
+                prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                    recipe.name.clone(),
+                    gate_id.to_string(),
+                    version.clone(),
+                    revision.clone(),
+                ),
+            )
+            .exec()
+            .await?;
+        match existing {
+            Some(component)
+                if component.recipe == recipe_value && component.patches == patch_value =>
+            {
+                debug!("recipe of {} did not change", component_ref);
+                continue;
+            }
+            Some(_) => {
+                debug!("updating the recipe of {}", component_ref);
+                db.component()
+                    .update(
+                        prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                            recipe.name.clone(),
+                            gate_id.to_string(),
+                            version.clone(),
+                            revision.clone(),
+                        ),
+                        vec![
+                            prisma::component::recipe::set(recipe_value),
+                            prisma::component::patches::set(patch_value),
+                            prisma::component::packages::set(package_meta_value),
+                        ]
+                        .into_iter()
+                        .chain(recipe_search_params(&recipe))
+                        .collect(),
+                    )
+                    .exec()
+                    .await?;
+            }
+            None => {
+                debug!("adding {} {}-{}", component_ref, version, revision);
+                db.component()
+                    .create(
+                        recipe.name.clone(),
+                        version.clone(),
+                        revision.clone(),
+                        recipe
+                            .project_url
+                            .clone()
+                            .ok_or(Error::NoProjectUrlFoundInRecipe(recipe.name.clone()))?,
+                        prisma::gate::UniqueWhereParam::IdEquals(gate_id.to_string()),
+                        recipe_value,
+                        patch_value,
+                        package_meta_value,
+                        recipe_search_params(&recipe),
+                    )
+                    .exec()
+                    .await?;
+            }
+        }
+
+        changed.push(recipe.name);
+    }
+
+    if rebuild && !changed.is_empty() {
+        let builds =
+            enqueue_dependents_rebuild(db, &gate_id.to_string(), &changed, JobPriority::Normal)
+                .await?;
+        info!(
+            "{} components of gate {} changed, rebuilding them and their dependents in {} builds",
+            changed.len(),
+            gate_id,
+            builds.len()
+        );
+    }
+    Ok(())
+}
+
+/// Queue the job a command in a comment on a change request asks for. Commands run the
+/// last recipes job of the change request again as it holds the commit to build.
+async fn run_command(
+    db: &PrismaClient,
+    channel: &Channel,
+    job_inbox: &str,
+    github_status: &GitHubStatus,
+    envelope: CommandEnvelope,
+) -> Result<()> {
+    let change_request_id = envelope.change_request_id.to_string();
+    let domain = find_domain_for_url(db, &envelope.change_request_id).await?;
+    find_change_request(db, &change_request_id, &domain.id).await?;
+
+    let last_job = db
+        .queued_job()
+        .find_first(vec![prisma::queued_job::reference::equals(
+            change_request_id.clone(),
+        )])
+        .order_by(prisma::queued_job::created_at::order(Direction::Desc))
+        .exec()
+        .await?
+        .and_then(|job| serde_json::from_value(job.payload).ok());
+    let Some(Job::GetRecipes {
+        cr_id,
+        cr,
+        gate_id: last_gate_id,
+    }) = last_job
+    else {
+        info!(
+            "change request {} has no recipes job to run again, ignoring {:?} of {}",
+            change_request_id, envelope.command, envelope.actor
+        );
+        return Ok(());
+    };
+
+    let gate_id = match &envelope.command {
+        ChangeRequestCommand::Rebuild => envelope.gate_id.to_string(),
+        ChangeRequestCommand::Retry { gate: None } => last_gate_id.to_string(),
+        ChangeRequestCommand::Retry { gate: Some(gate) } => db
+            .gate()
+            .find_first(vec![
+                gate_scope(&domain.id),
+                prisma_client_rust::operator::or(vec![
+                    prisma::gate::name::equals(gate.clone()),
+                    prisma::gate::version::equals(gate.clone()),
+                ]),
+            ])
+            .exec()
+            .await?
+            .ok_or(Error::NotFound(format!("gate {}", gate)))?
+            .id,
+    };
+    let gate = find_gate_in_domain(db, &gate_id, &domain.id).await?;
+
+    info!(
+        "{} asked for {:?} of change request {} on gate {}",
+        envelope.actor, envelope.command, change_request_id, gate.name
+    );
+    let job = Job::GetRecipes {
+        cr_id,
+        cr,
+        gate_id: gate
+            .id
+            .parse()
+            .map_err(|_| Error::NoIdFoundINGate(gate.id.clone()))?,
+    };
+    enqueue_job(db, &job, JobPriority::Normal).await?;
+    github_status.job_queued(db, &job).await;
+    dispatch_jobs(db, channel, job_inbox).await
+}
+
+/// Mark the staged publication a failed or cancelled job worked on as failed.
+async fn fail_staged_publication(db: &PrismaClient, object: JobObject, error: String) -> Result<()> {
+    let failed_staging = match object {
+        JobObject::Component {
+            staging: Some(staging),
+            ..
+        } => Some((staging, true)),
+        JobObject::StagingRepository { name } => Some((name, false)),
+        _ => None,
+    };
+    if let Some((staging, was_build)) = failed_staging {
+        let mut params = vec![
+            prisma::staged_publication::state::set(prisma::StagedPublicationState::Failed),
+            prisma::staged_publication::error::set(Some(error)),
+        ];
+        if was_build {
+            params.push(prisma::staged_publication::pending_builds::decrement(1));
+        }
+        db.staged_publication()
+            .update(
+                prisma::staged_publication::UniqueWhereParam::StagingEquals(staging),
+                params,
+            )
+            .exec()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Store the output a worker sent for a running job. Chunks that are delivered twice
+/// keep their lines numbered the same so the duplicates are skipped.
+async fn append_job_log(db: &PrismaClient, chunk: JobLogChunk) -> Result<()> {
+    let job = db
+        .queued_job()
+        .find_unique(prisma::queued_job::UniqueWhereParam::IdEquals(
+            chunk.job_id.clone(),
+        ))
+        .exec()
+        .await?;
+    if job.is_none() {
+        debug!("job {} is gone, dropping its log", chunk.job_id);
+        return Ok(());
+    }
+
+    let lines = chunk
+        .lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, content)| {
+            (
+                chunk.job_id.clone(),
+                (chunk.first_line as usize + i) as i32,
+                content,
+                vec![],
+            )
+        })
+        .collect::<Vec<_>>();
+    db.job_log_line()
+        .create_many(lines)
+        .skip_duplicates()
+        .exec()
+        .await?;
+    Ok(())
+}
+
+/// Log the hosts a build tried to reach outside of its declared sources, so builds that
+/// are not reproducible from the recipe alone can be audited.
+fn log_network_hosts(build: &impl std::fmt::Display, hosts: &[String]) {
+    if !hosts.is_empty() {
+        warn!(
+            "Build of {} tried to reach {} during the build phase",
+            build,
+            hosts.join(", ")
+        );
+    }
+}
+
+/// Log the outcome of the recipe checks a build ran, with the output of failed ones.
+fn log_check_results(checks: &[CheckResult]) {
+    for check in checks {
+        if check.passed {
+            info!("Check {} passed", check.name);
+        } else {
+            warn!("Check {} failed: {}", check.name, check.output);
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use forge::{JobObject, JobReport};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+
+use crate::periodic;
+use crate::prisma::{self, PrismaClient};
+use crate::{Error, Result};
+
+/// Deliveries are given up after this many failed attempts.
+const MAX_ATTEMPTS: i32 = 8;
+
+/// The longest a failed delivery waits for its next attempt.
+const MAX_RETRY_DELAY_MINUTES: i64 = 6 * 60;
+
+/// Notifications delivered per tick of the notifier.
+const BATCH_SIZE: i64 = 50;
+
+/// Server the email subscriptions are sent over.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Sender of the emails like `forge <forge@example.org>`
+    pub from: String,
+}
+
+/// Where the notifications of a subscription are delivered.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotificationTarget {
+    /// The event is posted as JSON to the url
+    Webhook {
+        url: String,
+        /// Sent as bearer token so the receiver can tell the request comes from forged
+        #[serde(default)]
+        token: Option<String>,
+    },
+    /// A message is sent to the room as the user of the access token
+    Matrix {
+        homeserver: String,
+        room_id: String,
+        access_token: String,
+    },
+    Email {
+        to: Vec<String>,
+    },
+}
+
+impl NotificationTarget {
+    /// The target without its secrets, to show it over the API and in the audit log.
+    pub fn redacted(&self) -> Self {
+        let hidden = || String::from("***");
+        match self {
+            NotificationTarget::Webhook { url, token } => NotificationTarget::Webhook {
+                url: url.clone(),
+                token: token.as_ref().map(|_| hidden()),
+            },
+            NotificationTarget::Matrix {
+                homeserver,
+                room_id,
+                ..
+            } => NotificationTarget::Matrix {
+                homeserver: homeserver.clone(),
+                room_id: room_id.clone(),
+                access_token: hidden(),
+            },
+            NotificationTarget::Email { to } => NotificationTarget::Email { to: to.clone() },
+        }
+    }
+}
+
+/// Something that happened to a gate that its publisher may want to hear about.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "event")]
+pub enum NotificationEvent {
+    /// The components changed by a change request were merged into the gate
+    #[serde(rename = "change_request.applied")]
+    ChangeRequestApplied {
+        gate_id: String,
+        change_request_id: String,
+        components: Vec<String>,
+    },
+    /// A job working on the gate failed
+    #[serde(rename = "build.failed")]
+    BuildFailed {
+        gate_id: String,
+        /// The reference of the job, like the component it built
+        reference: String,
+        kind: String,
+        error: String,
+    },
+    /// A snapshot of the gate was frozen or promoted into it
+    #[serde(rename = "snapshot.created")]
+    SnapshotCreated {
+        gate_id: String,
+        snapshot: String,
+        /// Id of the snapshot of another gate it was promoted from
+        promoted_from: Option<String>,
+    },
+    /// A sync with gate.kdl overwrote settings of the gate edited since the last sync
+    #[serde(rename = "gate.config_conflict")]
+    GateConfigConflict {
+        gate_id: String,
+        commit: String,
+        /// The overwritten settings
+        fields: Vec<String>,
+    },
+}
+
+impl NotificationEvent {
+    /// The name subscriptions select the event with, the `event` field of the payload.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotificationEvent::ChangeRequestApplied { .. } => "change_request.applied",
+            NotificationEvent::BuildFailed { .. } => "build.failed",
+            NotificationEvent::SnapshotCreated { .. } => "snapshot.created",
+            NotificationEvent::GateConfigConflict { .. } => "gate.config_conflict",
+        }
+    }
+
+    pub fn gate_id(&self) -> &str {
+        match self {
+            NotificationEvent::ChangeRequestApplied { gate_id, .. }
+            | NotificationEvent::BuildFailed { gate_id, .. }
+            | NotificationEvent::SnapshotCreated { gate_id, .. }
+            | NotificationEvent::GateConfigConflict { gate_id, .. } => gate_id,
+        }
+    }
+
+    /// One line describing the event for chat messages and email subjects.
+    pub fn summary(&self, gate: &str) -> String {
+        match self {
+            NotificationEvent::ChangeRequestApplied {
+                change_request_id,
+                components,
+                ..
+            } => format!(
+                "{}: change request {} applied, changing {}",
+                gate,
+                change_request_id,
+                components.join(", ")
+            ),
+            NotificationEvent::BuildFailed {
+                reference, kind, ..
+            } => format!("{}: {} of {} failed", gate, kind, reference),
+            NotificationEvent::SnapshotCreated {
+                snapshot,
+                promoted_from,
+                ..
+            } => match promoted_from {
+                Some(_) => format!("{}: snapshot {} promoted", gate, snapshot),
+                None => format!("{}: snapshot {} created", gate, snapshot),
+            },
+            NotificationEvent::GateConfigConflict { commit, fields, .. } => format!(
+                "{}: {} at {} overwrote {} edited since the last sync",
+                gate,
+                forge::GATE_CONFIG_FILE,
+                commit,
+                fields.join(", ")
+            ),
+        }
+    }
+
+    /// The failure of a job that worked on a gate. Jobs on staging repositories are
+    /// reported through the change request they belong to.
+    pub fn job_failed(report: &JobReport) -> Option<Self> {
+        let JobReport::Failure { object, error, .. } = report else {
+            return None;
+        };
+        let gate_id = match object {
+            JobObject::ChangeRequest { gate_id, .. }
+            | JobObject::Gate { gate_id, .. }
+            | JobObject::GateConfig { gate_id, .. } => gate_id.to_string(),
+            JobObject::Component { component_id, .. } => component_id.gate_id.to_string(),
+            JobObject::StagingRepository { .. } => return None,
+        };
+        Some(NotificationEvent::BuildFailed {
+            gate_id,
+            reference: report.reference(),
+            kind: report.kind().to_string(),
+            error: error.clone(),
+        })
+    }
+}
+
+/// Whether a subscription to `events` wants the event.
+pub fn subscribed(events: &[String], event: &str) -> bool {
+    events.is_empty() || events.iter().any(|e| e == event)
+}
+
+/// How long a delivery that failed `attempts` times waits before it is tried again.
+pub fn retry_delay(attempts: i32) -> TimeDelta {
+    let minutes = 1i64
+        .checked_shl(attempts.clamp(0, 30) as u32)
+        .unwrap_or(MAX_RETRY_DELAY_MINUTES);
+    TimeDelta::minutes(minutes.min(MAX_RETRY_DELAY_MINUTES))
+}
+
+/// Queue the event for every enabled subscription of the publisher of its gate that wants
+/// it. Failing to queue is logged, notifications never fail what caused them.
+pub async fn notify(db: &PrismaClient, event: NotificationEvent) {
+    if let Err(e) = queue_notifications(db, &event).await {
+        warn!(
+            "could not queue {} notifications of gate {}: {}",
+            event.name(),
+            event.gate_id(),
+            e
+        );
+    }
+}
+
+async fn queue_notifications(db: &PrismaClient, event: &NotificationEvent) -> Result<()> {
+    let Some(gate) = db
+        .gate()
+        .find_unique(prisma::gate::UniqueWhereParam::IdEquals(
+            event.gate_id().to_string(),
+        ))
+        .exec()
+        .await?
+    else {
+        return Ok(());
+    };
+    let subscriptions = db
+        .notification_subscription()
+        .find_many(vec![
+            prisma::notification_subscription::publisher_id::equals(gate.publisher_id),
+            prisma::notification_subscription::enabled::equals(true),
+        ])
+        .exec()
+        .await?;
+    let payload = serde_json::to_value(event)?;
+    for subscription in subscriptions {
+        if !subscribed(&subscription.events, event.name()) {
+            continue;
+        }
+        debug!(
+            "queueing {} for subscription {}",
+            event.name(),
+            subscription.name
+        );
+        db.notification()
+            .create(
+                prisma::notification_subscription::UniqueWhereParam::IdEquals(subscription.id),
+                event.name().to_string(),
+                payload.clone(),
+                vec![],
+            )
+            .exec()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Delivers the queued notifications to their targets.
+#[derive(Clone)]
+pub struct Notifier {
+    client: reqwest::Client,
+    smtp: Option<(AsyncSmtpTransport<Tokio1Executor>, Mailbox)>,
+}
+
+impl std::fmt::Debug for Notifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Notifier")
+            .field("email", &self.smtp.is_some())
+            .finish()
+    }
+}
+
+impl Notifier {
+    pub fn new(smtp: Option<&SmtpConfig>) -> Result<Self> {
+        let smtp = match smtp {
+            Some(config) => {
+                let mut transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+                    .map_err(|e| Error::Notification(e.to_string()))?;
+                if let Some(port) = config.port {
+                    transport = transport.port(port);
+                }
+                if let (Some(username), Some(password)) = (&config.username, &config.password) {
+                    transport =
+                        transport.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+                let from = config
+                    .from
+                    .parse()
+                    .map_err(|e: lettre::address::AddressError| {
+                        Error::Notification(e.to_string())
+                    })?;
+                Some((transport.build(), from))
+            }
+            None => None,
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            smtp,
+        })
+    }
+
+    /// Deliver one notification of the gate to the target.
+    pub async fn deliver(
+        &self,
+        target: &NotificationTarget,
+        gate: &str,
+        notification: &prisma::notification::Data,
+    ) -> Result<()> {
+        let event: NotificationEvent = serde_json::from_value(notification.payload.clone())?;
+        match target {
+            NotificationTarget::Webhook { url, token } => {
+                let mut request = self
+                    .client
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header("x-forge-event", event.name())
+                    .header("x-forge-notification", notification.id.as_str())
+                    .body(serde_json::to_vec(&notification.payload)?);
+                if let Some(token) = token {
+                    request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+                }
+                request.send().await?.error_for_status()?;
+            }
+            NotificationTarget::Matrix {
+                homeserver,
+                room_id,
+                access_token,
+            } => {
+                // The notification id as transaction id makes retries idempotent
+                let url = format!(
+                    "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+                    homeserver.trim_end_matches('/'),
+                    room_id,
+                    notification.id
+                );
+                let message = serde_json::json!({
+                    "msgtype": "m.notice",
+                    "body": event.summary(gate),
+                });
+                self.client
+                    .put(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(AUTHORIZATION, format!("Bearer {}", access_token))
+                    .body(serde_json::to_vec(&message)?)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            NotificationTarget::Email { to } => {
+                let Some((transport, from)) = &self.smtp else {
+                    return Err(Error::Notification(String::from(
+                        "no smtp server is configured",
+                    )));
+                };
+                let mut message = Message::builder()
+                    .from(from.clone())
+                    .subject(event.summary(gate));
+                for address in to {
+                    message = message.to(address.parse().map_err(
+                        |e: lettre::address::AddressError| Error::Notification(e.to_string()),
+                    )?);
+                }
+                let message = message
+                    .body(serde_json::to_string_pretty(&notification.payload)?)
+                    .map_err(|e| Error::Notification(e.to_string()))?;
+                transport
+                    .send(message)
+                    .await
+                    .map_err(|e| Error::Notification(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver the notifications that are due and record how each went.
+    pub async fn deliver_due(&self, db: &PrismaClient) -> Result<usize> {
+        let due = db
+            .notification()
+            .find_many(vec![
+                prisma::notification::state::equals(prisma::NotificationState::Pending),
+                prisma::notification::next_attempt_at::lte(Utc::now().into()),
+            ])
+            .order_by(prisma::notification::next_attempt_at::order(
+                prisma_client_rust::Direction::Asc,
+            ))
+            .take(BATCH_SIZE)
+            .with(prisma::notification::subscription::fetch())
+            .exec()
+            .await?;
+
+        let mut delivered = 0;
+        for notification in due {
+            let subscription =
+                notification
+                    .subscription
+                    .as_deref()
+                    .ok_or(Error::NotFound(format!(
+                        "subscription of notification {}",
+                        notification.id
+                    )))?;
+            let gate = gate_name(db, &notification).await;
+            let result =
+                match serde_json::from_value::<NotificationTarget>(subscription.target.clone()) {
+                    Ok(target) => self.deliver(&target, &gate, &notification).await,
+                    Err(e) => Err(e.into()),
+                };
+
+            let attempts = notification.attempts + 1;
+            let update = match result {
+                Ok(()) => {
+                    delivered += 1;
+                    vec![
+                        prisma::notification::state::set(prisma::NotificationState::Delivered),
+                        prisma::notification::delivered_at::set(Some(Utc::now().into())),
+                        prisma::notification::error::set(None),
+                    ]
+                }
+                Err(e) => {
+                    warn!(
+                        "delivering notification {} to {} failed: {}",
+                        notification.id, subscription.name, e
+                    );
+                    let mut update = vec![prisma::notification::error::set(Some(e.to_string()))];
+                    if attempts >= MAX_ATTEMPTS {
+                        update.push(prisma::notification::state::set(
+                            prisma::NotificationState::Failed,
+                        ));
+                    } else {
+                        update.push(prisma::notification::next_attempt_at::set(
+                            (Utc::now() + retry_delay(attempts)).into(),
+                        ));
+                    }
+                    update
+                }
+            };
+            db.notification()
+                .update(
+                    prisma::notification::UniqueWhereParam::IdEquals(notification.id.clone()),
+                    update
+                        .into_iter()
+                        .chain([prisma::notification::attempts::set(attempts)])
+                        .collect(),
+                )
+                .exec()
+                .await?;
+        }
+        Ok(delivered)
+    }
+}
+
+/// The name of the gate of a notification for messages, its id if it is gone.
+async fn gate_name(db: &PrismaClient, notification: &prisma::notification::Data) -> String {
+    let gate_id = serde_json::from_value::<NotificationEvent>(notification.payload.clone())
+        .map(|event| event.gate_id().to_string())
+        .unwrap_or_default();
+    match db
+        .gate()
+        .find_unique(prisma::gate::UniqueWhereParam::IdEquals(gate_id.clone()))
+        .exec()
+        .await
+    {
+        Ok(Some(gate)) => format!("{} {}", gate.name, gate.version),
+        _ => gate_id,
+    }
+}
+
+pub async fn run_periodically(db: Arc<PrismaClient>, notifier: Notifier, interval_seconds: u64) {
+    let (db, notifier) = (&db, &notifier);
+    let period = Duration::from_secs(interval_seconds);
+    periodic::run_periodically("notification delivery", period, || async move {
+        match notifier.deliver_due(db).await? {
+            0 => {}
+            delivered => info!("delivered {} notifications", delivered),
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_back_off_up_to_a_limit() {
+        assert_eq!(retry_delay(0), TimeDelta::minutes(1));
+        assert_eq!(retry_delay(3), TimeDelta::minutes(8));
+        assert_eq!(retry_delay(7), TimeDelta::minutes(128));
+        assert_eq!(retry_delay(9), TimeDelta::minutes(MAX_RETRY_DELAY_MINUTES));
+        assert_eq!(retry_delay(64), TimeDelta::minutes(MAX_RETRY_DELAY_MINUTES));
+    }
+
+    #[test]
+    fn redacted_targets_hide_secrets() {
+        let target = NotificationTarget::Matrix {
+            homeserver: String::from("https://matrix.example.org"),
+            room_id: String::from("!abc:example.org"),
+            access_token: String::from("syt_secret"),
+        };
+        assert_eq!(
+            target.redacted(),
+            NotificationTarget::Matrix {
+                homeserver: String::from("https://matrix.example.org"),
+                room_id: String::from("!abc:example.org"),
+                access_token: String::from("***"),
+            }
+        );
+    }
+
+    #[test]
+    fn subscriptions_without_events_get_everything() {
+        let events = vec![String::from("build.failed")];
+        assert!(subscribed(&events, "build.failed"));
+        assert!(!subscribed(&events, "snapshot.created"));
+        assert!(subscribed(&[], "snapshot.created"));
+    }
+
+    #[test]
+    fn events_are_tagged() {
+        let event = NotificationEvent::SnapshotCreated {
+            gate_id: String::from("0b6c1f3e"),
+            snapshot: String::from("2026.10"),
+            promoted_from: None,
+        };
+        assert_eq!(event.name(), "snapshot.created");
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "event": "snapshot.created",
+                "gate_id": "0b6c1f3e",
+                "snapshot": "2026.10",
+                "promoted_from": null,
+            })
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use tracing::debug;
+
+use crate::prisma::{self, PrismaClient};
+use crate::{Error, Result};
+
+/// The actor of the domain with the handle.
+pub async fn find_actor(
+    db: &PrismaClient,
+    handle: &str,
+    domain_id: &str,
+) -> Result<prisma::actor::Data> {
+    db.actor()
+        .find_unique(prisma::actor::UniqueWhereParam::HandleDomainIdEquals(
+            handle.to_string(),
+            domain_id.to_string(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("actor {}", handle)))
+}
+
+/// The maintainers recipes name that are the actor: the ones mapped to it and its handle.
+pub async fn maintainer_names(
+    db: &PrismaClient,
+    actor: &prisma::actor::Data,
+) -> Result<Vec<String>> {
+    let mut names = db
+        .maintainer()
+        .find_many(vec![prisma::maintainer::actor_id::equals(Some(
+            actor.id.clone(),
+        ))])
+        .exec()
+        .await?
+        .into_iter()
+        .map(|m| m.name)
+        .collect::<Vec<_>>();
+    names.push(actor.handle.clone());
+    Ok(names)
+}
+
+pub async fn is_gate_admin(db: &PrismaClient, gate_id: &str, actor_id: &str) -> Result<bool> {
+    Ok(db
+        .gate()
+        .find_first(vec![
+            prisma::gate::id::equals(gate_id.to_string()),
+            prisma::gate::admins::some(vec![prisma::actor::id::equals(actor_id.to_string())]),
+        ])
+        .exec()
+        .await?
+        .is_some())
+}
+
+/// Whether the actor administers the domain. Administering a gate is not enough, the
+/// role is granted with `forged set-domain-admin`.
+pub async fn is_domain_admin(db: &PrismaClient, domain_id: &str, actor_id: &str) -> Result<bool> {
+    Ok(db
+        .actor()
+        .find_first(vec![
+            prisma::actor::id::equals(actor_id.to_string()),
+            prisma::actor::domain_id::equals(domain_id.to_string()),
+            prisma::actor::domain_admin::equals(true),
+        ])
+        .exec()
+        .await?
+        .is_some())
+}
+
+/// Whether one of the maintainers of a component is one of `names`. Components without
+/// maintainers belong to nobody and everybody may update them.
+pub fn owns(maintainers: &[String], names: &[String]) -> bool {
+    maintainers.is_empty() || maintainers.iter().any(|m| names.contains(m))
+}
+
+/// Make sure the actor may update the component `name` of the gate: it maintains the
+/// stored versions of the component or administers the gate. New components may be
+/// added by everyone.
+pub async fn ensure_component_owner(
+    db: &PrismaClient,
+    gate_id: &str,
+    name: &str,
+    handle: &str,
+    domain_id: &str,
+) -> Result<()> {
+    let stored = db
+        .component()
+        .find_many(vec![
+            prisma::component::gate_id::equals(gate_id.to_string()),
+            prisma::component::name::equals(name.to_string()),
+        ])
+        .exec()
+        .await?;
+    if stored.is_empty() {
+        return Ok(());
+    }
+
+    let actor = find_actor(db, handle, domain_id).await?;
+    if is_gate_admin(db, gate_id, &actor.id).await? {
+        debug!("{} administers gate {}", handle, gate_id);
+        return Ok(());
+    }
+    let names = maintainer_names(db, &actor).await?;
+    if stored.iter().all(|c| owns(&c.maintainers, &names)) {
+        Ok(())
+    } else {
+        Err(Error::NotComponentOwner(
+            handle.to_string(),
+            name.to_string(),
+        ))
+    }
+}
+
+/// Publishing in the name of a publisher takes administering one of its gates or the
+/// domain.
+pub async fn ensure_publisher_admin(
+    db: &PrismaClient,
+    publisher_id: &str,
+    publisher: &str,
+    handle: &str,
+    domain_id: &str,
+) -> Result<()> {
+    let actor = find_actor(db, handle, domain_id).await?;
+    if is_domain_admin(db, domain_id, &actor.id).await? {
+        return Ok(());
+    }
+    let gate = db
+        .gate()
+        .find_first(vec![
+            prisma::gate::publisher_id::equals(publisher_id.to_string()),
+            prisma::gate::admins::some(vec![prisma::actor::id::equals(actor.id)]),
+        ])
+        .exec()
+        .await?;
+    match gate {
+        Some(gate) => {
+            debug!(
+                "{} publishes for {} through gate {}",
+                handle, publisher, gate.name
+            );
+            Ok(())
+        }
+        None => Err(Error::NotPublisherAdmin(
+            handle.to_string(),
+            publisher.to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maintainers_own_their_components() {
+        let names = vec![
+            String::from("Jane Doe <jane@example.org>"),
+            String::from("jane"),
+        ];
+        assert!(owns(&[String::from("jane")], &names));
+        assert!(owns(
+            &[
+                String::from("John Doe <john@example.org>"),
+                String::from("Jane Doe <jane@example.org>"),
+            ],
+            &names
+        ));
+        assert!(!owns(
+            &[String::from("John Doe <john@example.org>")],
+            &names
+        ));
+        assert!(owns(&[], &names));
+    }
+}
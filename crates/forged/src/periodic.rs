@@ -0,0 +1,31 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::Result;
+
+/// Run `task` every `period` until forged stops, the first time right away. A period of
+/// zero disables the task. Failed runs are logged and tried again at the next interval.
+pub async fn run_periodically<T, F, Fut>(name: &str, period: Duration, mut task: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    if period.is_zero() {
+        debug!("{} is disabled", name);
+        return;
+    }
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = task().await {
+            warn!("{} failed: {}", name, e);
+        }
+    }
+}
+
+/// A period of `hours` hours.
+pub fn hours(hours: u64) -> Duration {
+    Duration::from_secs(hours * 60 * 60)
+}
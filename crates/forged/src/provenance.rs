@@ -0,0 +1,63 @@
+use forge::provenance::Statement;
+use tracing::info;
+
+use crate::prisma::{self, PrismaClient};
+use crate::signing::{sign_for_publisher, SecretKeyCipher};
+use crate::{Error, Result};
+
+/// Store the provenance a worker reported for a build, signed by the publisher of the
+/// gate. The signature covers the stored document byte for byte, so it is kept as text.
+pub async fn record_provenance(
+    db: &PrismaClient,
+    cipher: &SecretKeyCipher,
+    statement: &Statement,
+) -> Result<prisma::provenance::Data> {
+    let parameters = &statement.predicate.build_definition.external_parameters;
+    let component_id = &parameters.component;
+    let gate = db
+        .gate()
+        .find_unique(prisma::gate::id::equals(component_id.gate_id.to_string()))
+        .with(prisma::gate::publisher::fetch())
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("gate {}", component_id.gate_id)))?;
+    let publisher = gate
+        .publisher
+        .as_deref()
+        .ok_or(Error::NotFound(format!("publisher of gate {}", gate.id)))?;
+
+    let document = serde_json::to_string_pretty(statement)?;
+    let signature = sign_for_publisher(
+        db,
+        cipher,
+        &publisher.id,
+        &publisher.name,
+        document.as_bytes(),
+    )
+    .await?;
+    if signature.is_none() {
+        info!(
+            "publisher {} has no signing key, storing provenance of {} unsigned",
+            publisher.name, component_id
+        );
+    }
+
+    Ok(db
+        .provenance()
+        .create(
+            prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                component_id.name.clone(),
+                component_id.gate_id.to_string(),
+                component_id.version.clone(),
+                component_id.revision.clone(),
+            ),
+            document,
+            vec![
+                prisma::provenance::staging::set(parameters.staging.clone()),
+                prisma::provenance::bootstrap::set(parameters.bootstrap),
+                prisma::provenance::signature::set(signature),
+            ],
+        )
+        .exec()
+        .await?)
+}
@@ -0,0 +1,267 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use component::{normalize_license, Recipe};
+use tracing::{debug, instrument, warn};
+
+use crate::prisma::{self, PrismaClient};
+use crate::upstream::anitya_version;
+use crate::Result;
+
+/// Recipe quality metrics of the latest version of every component in a gate.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GateQuality {
+    pub components: i32,
+    pub missing_summary: i32,
+    pub missing_license: i32,
+    pub without_maintainer: i32,
+    pub stale_versions: i32,
+    pub lint_warnings: i32,
+}
+
+/// Compute the metrics over `recipes`. `upstream` maps component names to the latest
+/// version released upstream, components without an entry are never counted as stale.
+pub fn gate_quality(recipes: &[Recipe], upstream: &HashMap<String, String>) -> GateQuality {
+    let mut quality = GateQuality::default();
+    for recipe in recipes {
+        quality.components += 1;
+        if recipe.summary.is_none() {
+            quality.missing_summary += 1;
+        }
+        if recipe.license.is_none() && recipe.license_file.is_none() {
+            quality.missing_license += 1;
+        }
+        if recipe.maintainers.is_empty() {
+            quality.without_maintainer += 1;
+        }
+        if let (Some(ours), Some(theirs)) = (&recipe.version, upstream.get(&recipe.name)) {
+            if compare_versions(ours, theirs) == Some(Ordering::Less) {
+                quality.stale_versions += 1;
+            }
+        }
+        quality.lint_warnings += recipe.validate().len() as i32;
+    }
+    quality
+}
+
+/// The licenses of the latest version of every component in a gate.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LicenseUsage {
+    pub components: i32,
+    /// Components without a license
+    pub unlicensed: Vec<String>,
+    /// Components with a license that is no SPDX expression
+    pub unparsable: Vec<String>,
+    /// Components per normalized SPDX identifier. A component is counted once for every
+    /// license it may be used under
+    pub licenses: BTreeMap<String, i32>,
+}
+
+pub fn license_usage(recipes: &[Recipe]) -> LicenseUsage {
+    let mut usage = LicenseUsage::default();
+    for recipe in recipes {
+        usage.components += 1;
+        let licenses = recipe
+            .license
+            .iter()
+            .chain(recipe.additional_licenses.iter().map(|l| &l.name))
+            .collect::<Vec<_>>();
+        if licenses.is_empty() {
+            usage.unlicensed.push(recipe.name.clone());
+            continue;
+        }
+
+        let mut identifiers = BTreeSet::new();
+        for license in licenses {
+            match normalize_license(license) {
+                Ok(expression) => {
+                    identifiers.extend(expression.licenses().into_iter().map(String::from))
+                }
+                Err(_) => {
+                    usage.unparsable.push(recipe.name.clone());
+                    break;
+                }
+            }
+        }
+        for identifier in identifiers {
+            *usage.licenses.entry(identifier).or_default() += 1;
+        }
+    }
+    usage
+}
+
+/// Compare dot separated numeric versions. Returns `None` if either is not numeric.
+pub(crate) fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let parse = |v: &str| {
+        v.split('.')
+            .map(|p| p.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    let (mut a, mut b) = (parse(a)?, parse(b)?);
+    // 1.2 and 1.2.0 are the same version
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    Some(a.cmp(&b))
+}
+
+/// Keep only the latest version of each component.
+pub(crate) fn latest_components(
+    components: Vec<prisma::component::Data>,
+) -> Vec<prisma::component::Data> {
+    let mut latest: HashMap<String, prisma::component::Data> = HashMap::new();
+    for component in components {
+        let newer = match latest.get(&component.name) {
+            Some(current) => {
+                compare_versions(&component.version, &current.version) == Some(Ordering::Greater)
+            }
+            None => true,
+        };
+        if newer {
+            latest.insert(component.name.clone(), component);
+        }
+    }
+    latest.into_values().collect()
+}
+
+/// Compute the quality metrics of every gate and store them as a new snapshot. Upstream
+/// versions are looked up on release-monitoring.org unless `offline` is set.
+#[instrument(skip(db))]
+pub async fn analyze_gates(db: &PrismaClient, offline: bool) -> Result<()> {
+    let client = reqwest::Client::new();
+    let gates = db.gate().find_many(vec![]).exec().await?;
+
+    for gate in gates {
+        let components = db
+            .component()
+            .find_many(vec![prisma::component::gate_id::equals(gate.id.clone())])
+            .exec()
+            .await?;
+
+        let mut recipes = vec![];
+        let mut upstream = HashMap::new();
+        for component in latest_components(components) {
+            if !offline {
+                if let Some(anitya_id) = &component.anitya_id {
+                    match anitya_version(&client, anitya_id).await {
+                        Ok(Some(version)) => {
+                            upstream.insert(component.name.clone(), version);
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!(
+                            "could not get upstream version of {}: {}",
+                            component.name, e
+                        ),
+                    }
+                }
+            }
+            match serde_json::from_value::<Recipe>(component.recipe) {
+                Ok(recipe) => recipes.push(recipe),
+                Err(e) => warn!("recipe of {} is malformed: {}", component.name, e),
+            }
+        }
+
+        let quality = gate_quality(&recipes, &upstream);
+        debug!("quality of gate {}: {:?}", gate.name, quality);
+        db.gate_quality_snapshot()
+            .create(
+                prisma::gate::UniqueWhereParam::IdEquals(gate.id),
+                quality.components,
+                quality.missing_summary,
+                quality.missing_license,
+                quality.without_maintainer,
+                quality.stale_versions,
+                quality.lint_warnings,
+                vec![],
+            )
+            .exec()
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use component::RecipeBuilder;
+
+    use super::*;
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(compare_versions("1.10", "1.9"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("2.0", "2.0.1"), Some(Ordering::Less));
+        assert_eq!(compare_versions("2.0rc1", "2.0"), None);
+    }
+
+    #[test]
+    fn licenses_are_counted_normalized() -> miette::Result<()> {
+        let recipes = vec![
+            RecipeBuilder::default()
+                .name("library/zlib")
+                .license("zlib")
+                .build()?,
+            RecipeBuilder::default()
+                .name("developer/rust/ripgrep")
+                .license("MIT OR Unlicense")
+                .build()?,
+            RecipeBuilder::default()
+                .name("library/foo")
+                .license("MIT License")
+                .build()?,
+            RecipeBuilder::default()
+                .name("library/bar")
+                .license("BSD, see COPYING")
+                .build()?,
+            RecipeBuilder::default().name("library/baz").build()?,
+        ];
+
+        let usage = license_usage(&recipes);
+        assert_eq!(usage.components, 5);
+        assert_eq!(usage.unlicensed, vec!["library/baz".to_string()]);
+        assert_eq!(usage.unparsable, vec!["library/bar".to_string()]);
+        assert_eq!(
+            usage.licenses,
+            BTreeMap::from([
+                ("MIT".to_string(), 2),
+                ("Unlicense".to_string(), 1),
+                ("Zlib".to_string(), 1),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn quality_counts_problems() -> miette::Result<()> {
+        let complete = RecipeBuilder::default()
+            .name("library/zlib")
+            .summary("compression library")
+            .license("Zlib")
+            .maintainers(vec!["jane".to_string()])
+            .version("1.3.1")
+            .build()?;
+        let sloppy = RecipeBuilder::default()
+            .name("library/foo")
+            .version("1.0")
+            .build()?;
+
+        let upstream = HashMap::from([
+            ("library/zlib".to_string(), "1.3.1".to_string()),
+            ("library/foo".to_string(), "1.1".to_string()),
+        ]);
+
+        assert_eq!(
+            gate_quality(&[complete, sloppy], &upstream),
+            GateQuality {
+                components: 2,
+                missing_summary: 1,
+                missing_license: 1,
+                without_maintainer: 1,
+                stale_versions: 1,
+                lint_warnings: 0,
+            }
+        );
+        Ok(())
+    }
+}
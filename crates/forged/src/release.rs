@@ -0,0 +1,283 @@
+use component::{PackageMeta, Recipe};
+use forge::{Job, JobKind, JobObject, PatchFile};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::api::v1::gate::plan_gate_rebuild;
+use crate::message_queue::sync_gate_components;
+use crate::prisma::{self, PrismaClient};
+use crate::scheduler::{enqueue_job, JobPriority};
+use crate::{Error, Result};
+
+/// The staging repository the packages of a release are built into.
+fn release_staging_name(gate_id: &Uuid, tag: &str) -> String {
+    format!("release-{}-{}", gate_id, sanitize(tag))
+}
+
+/// The snapshot of the package repository a release is published as.
+fn release_snapshot_name(gate: &prisma::gate::Data, tag: &str) -> String {
+    format!("{}-{}", sanitize(&gate.name), sanitize(tag))
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Record a release of the gate for the tag. Returns false if the tag was already
+/// released or is being released, failed releases are started over.
+pub async fn start_release(db: &PrismaClient, gate_id: &Uuid, tag: &str) -> Result<bool> {
+    let existing = db
+        .release()
+        .find_unique(prisma::release::UniqueWhereParam::GateIdTagEquals(
+            gate_id.to_string(),
+            tag.to_string(),
+        ))
+        .exec()
+        .await?;
+    match existing {
+        Some(release) if release.state != prisma::ReleaseState::Failed => Ok(false),
+        Some(release) => {
+            db.release()
+                .update(
+                    prisma::release::UniqueWhereParam::IdEquals(release.id),
+                    vec![
+                        prisma::release::state::set(prisma::ReleaseState::Reading),
+                        prisma::release::pending_builds::set(0),
+                        prisma::release::packages::set(vec![]),
+                        prisma::release::error::set(None),
+                    ],
+                )
+                .exec()
+                .await?;
+            Ok(true)
+        }
+        None => {
+            db.release()
+                .create(
+                    prisma::gate::UniqueWhereParam::IdEquals(gate_id.to_string()),
+                    tag.to_string(),
+                    release_staging_name(gate_id, tag),
+                    vec![],
+                )
+                .exec()
+                .await?;
+            Ok(true)
+        }
+    }
+}
+
+/// Record the components of the tag and rebuild every component of the gate into the
+/// staging repository of the release.
+pub async fn build_release(
+    db: &PrismaClient,
+    gate_id: &Uuid,
+    tag: &str,
+    recipes: Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)>,
+) -> Result<()> {
+    sync_gate_components(db, gate_id, recipes, false).await?;
+
+    let gate = db
+        .gate()
+        .find_unique(prisma::gate::UniqueWhereParam::IdEquals(
+            gate_id.to_string(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("gate {}", gate_id)))?;
+    let release_id =
+        prisma::release::UniqueWhereParam::GateIdTagEquals(gate_id.to_string(), tag.to_string());
+    let plan = match plan_gate_rebuild(db, &gate).await {
+        Ok(plan) if plan.is_empty() => Err(String::from("the gate has no components")),
+        Ok(plan) => Ok(plan),
+        Err(e @ Error::NotRebuildable(..)) => Err(e.to_string()),
+        Err(e) => return Err(e),
+    };
+    let plan = match plan {
+        Ok(plan) => plan,
+        Err(error) => {
+            warn!("release {} of gate {} failed: {}", tag, gate.name, error);
+            db.release()
+                .update(
+                    release_id,
+                    vec![
+                        prisma::release::state::set(prisma::ReleaseState::Failed),
+                        prisma::release::error::set(Some(error)),
+                    ],
+                )
+                .exec()
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let release = db
+        .release()
+        .update(
+            release_id,
+            vec![
+                prisma::release::state::set(prisma::ReleaseState::Building),
+                prisma::release::pending_builds::set(plan.len() as i32),
+            ],
+        )
+        .exec()
+        .await?;
+    // Stored component builds share one queue which runs them in the order they were
+    // queued, the publication is queued behind them once they all succeeded
+    for (build, component_id) in plan {
+        let job = Job::BuildStoredComponent {
+            component_id,
+            staging: Some(release.staging.clone()),
+            bootstrap: build.bootstrap,
+        };
+        enqueue_job(db, &job, JobPriority::Normal).await?;
+    }
+    info!("building release {} of gate {}", tag, gate.name);
+    Ok(())
+}
+
+/// Count a finished build of a release and queue its publication after the last one.
+/// Returns false if the staging repository does not belong to a release.
+pub async fn record_release_build(
+    db: &PrismaClient,
+    staging: &str,
+    packages: Vec<String>,
+) -> Result<bool> {
+    let staging_id = prisma::release::UniqueWhereParam::StagingEquals(staging.to_string());
+    if db
+        .release()
+        .find_unique(staging_id.clone())
+        .exec()
+        .await?
+        .is_none()
+    {
+        return Ok(false);
+    }
+
+    let release = db
+        .release()
+        .update(
+            staging_id.clone(),
+            vec![
+                prisma::release::pending_builds::decrement(1),
+                prisma::release::packages::push(packages),
+            ],
+        )
+        .exec()
+        .await?;
+    // A failed build fails the whole release
+    if release.pending_builds > 0 || release.state != prisma::ReleaseState::Building {
+        return Ok(true);
+    }
+
+    let gate = db
+        .gate()
+        .find_unique(prisma::gate::UniqueWhereParam::IdEquals(
+            release.gate_id.clone(),
+        ))
+        .exec()
+        .await?
+        .ok_or(Error::NotFound(format!("gate {}", release.gate_id)))?;
+    db.release()
+        .update(
+            staging_id,
+            vec![prisma::release::state::set(
+                prisma::ReleaseState::Publishing,
+            )],
+        )
+        .exec()
+        .await?;
+    let job = Job::PublishRelease {
+        gate_id: release
+            .gate_id
+            .parse()
+            .map_err(|_| Error::NoIdFoundINGate(release.gate_id.clone()))?,
+        snapshot: release_snapshot_name(&gate, &release.tag),
+        tag: release.tag,
+        staging: release.staging,
+    };
+    enqueue_job(db, &job, JobPriority::Normal).await?;
+    Ok(true)
+}
+
+/// Mark the release a published snapshot belongs to as published.
+pub async fn record_published_release(
+    db: &PrismaClient,
+    staging: &str,
+    packages: Vec<String>,
+) -> Result<()> {
+    let release = db
+        .release()
+        .update(
+            prisma::release::UniqueWhereParam::StagingEquals(staging.to_string()),
+            vec![
+                prisma::release::state::set(prisma::ReleaseState::Published),
+                prisma::release::packages::set(packages),
+                prisma::release::published_at::set(Some(chrono::Utc::now().fixed_offset())),
+            ],
+        )
+        .exec()
+        .await?;
+    info!(
+        "published release {} of gate {}",
+        release.tag, release.gate_id
+    );
+    Ok(())
+}
+
+/// Mark the release a failed or cancelled job worked on as failed. Returns false if the
+/// job did not work on a release.
+pub async fn fail_release(
+    db: &PrismaClient,
+    object: &JobObject,
+    kind: &JobKind,
+    error: &str,
+) -> Result<bool> {
+    let (release_id, was_build) = match (object, kind) {
+        (JobObject::Gate { gate_id, commit }, JobKind::ReleaseGate) => (
+            prisma::release::UniqueWhereParam::GateIdTagEquals(gate_id.to_string(), commit.clone()),
+            false,
+        ),
+        (
+            JobObject::Component {
+                staging: Some(staging),
+                ..
+            },
+            _,
+        ) => (
+            prisma::release::UniqueWhereParam::StagingEquals(staging.clone()),
+            true,
+        ),
+        (JobObject::StagingRepository { name }, _) => (
+            prisma::release::UniqueWhereParam::StagingEquals(name.clone()),
+            false,
+        ),
+        _ => return Ok(false),
+    };
+    if db
+        .release()
+        .find_unique(release_id.clone())
+        .exec()
+        .await?
+        .is_none()
+    {
+        return Ok(false);
+    }
+
+    let mut params = vec![
+        prisma::release::state::set(prisma::ReleaseState::Failed),
+        prisma::release::error::set(Some(error.to_string())),
+    ];
+    if was_build {
+        params.push(prisma::release::pending_builds::decrement(1));
+    }
+    db.release().update(release_id, params).exec().await?;
+    Ok(true)
+}
@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use opendal::Operator;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+
+use crate::domain::gate_scope;
+use crate::prisma::{self, PrismaClient};
+use crate::Result;
+
+/// How long a domain keeps data that is no longer needed. Unset values keep the data
+/// forever, which is also what domains without a policy do.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Days finished jobs are kept after they were queued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_days: Option<u32>,
+    /// Days the component changes of applied change requests are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component_change_days: Option<u32>,
+    /// Days closed and applied change requests are kept after their last update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change_request_days: Option<u32>,
+}
+
+/// What one run of the retention policy of a domain removed or would remove.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RetentionReport {
+    pub domain: String,
+    pub jobs: usize,
+    pub component_changes: usize,
+    pub change_requests: usize,
+    pub blobs: Vec<String>,
+    /// Size of the JSON documents of the removed rows
+    pub reclaimed_database_bytes: u64,
+    pub reclaimed_blob_bytes: u64,
+}
+
+impl Display for RetentionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "domain {}:", self.domain)?;
+        writeln!(f, "  jobs: {}", self.jobs)?;
+        writeln!(f, "  component changes: {}", self.component_changes)?;
+        writeln!(f, "  change requests: {}", self.change_requests)?;
+        writeln!(f, "  blobs: {}", self.blobs.len())?;
+        for blob in self.blobs.iter() {
+            writeln!(f, "    {}", blob)?;
+        }
+        write!(
+            f,
+            "  reclaimed: {} bytes of database documents, {} bytes of blobs",
+            self.reclaimed_database_bytes, self.reclaimed_blob_bytes
+        )
+    }
+}
+
+/// Everything older than the returned time is past its retention.
+fn cutoff(now: DateTime<FixedOffset>, days: Option<u32>) -> Option<DateTime<FixedOffset>> {
+    days.map(|days| now - TimeDelta::days(days as i64))
+}
+
+/// Queued jobs are not linked to a domain. Their reference is the id of the change
+/// request, the component (which starts with its gate id) or the staging repository
+/// they worked on.
+fn job_belongs_to_domain(
+    reference: &str,
+    gate_ids: &HashSet<String>,
+    change_request_ids: &HashSet<String>,
+    staging_names: &HashSet<String>,
+) -> bool {
+    change_request_ids.contains(reference)
+        || staging_names.contains(reference)
+        || reference
+            .split_once('/')
+            .is_some_and(|(gate_id, _)| gate_ids.contains(gate_id))
+}
+
+fn json_size(values: &[&serde_json::Value]) -> u64 {
+    values.iter().map(|v| v.to_string().len() as u64).sum()
+}
+
+/// Apply the retention policy of every domain, see [`apply_retention`].
+pub async fn apply_retention_policies(
+    db: &PrismaClient,
+    op: &Operator,
+    dry_run: bool,
+) -> Result<Vec<RetentionReport>> {
+    let mut reports = vec![];
+    for domain in db.domain().find_many(vec![]).exec().await? {
+        reports.push(apply_retention(db, op, &domain, dry_run).await?);
+    }
+    Ok(reports)
+}
+
+/// Remove the jobs, component changes and change requests of the domain that are past
+/// the retention of its policy together with the blobs only they referenced. With
+/// `dry_run` nothing is removed and only the report is returned. Real runs are recorded
+/// as a RetentionRun of the domain.
+#[instrument(skip(db, op, domain), fields(domain = %domain.dns_name))]
+pub async fn apply_retention(
+    db: &PrismaClient,
+    op: &Operator,
+    domain: &prisma::domain::Data,
+    dry_run: bool,
+) -> Result<RetentionReport> {
+    let policy: RetentionPolicy = serde_json::from_value(domain.retention.clone())?;
+    let now = chrono::Utc::now().fixed_offset();
+    let mut report = RetentionReport {
+        domain: domain.dns_name.clone(),
+        ..Default::default()
+    };
+
+    let change_requests = db
+        .change_request()
+        .find_many(vec![prisma::change_request::domain_id::equals(Some(
+            domain.id.clone(),
+        ))])
+        .with(prisma::change_request::staged_publication::fetch())
+        .exec()
+        .await?;
+
+    let mut job_ids = vec![];
+    if let Some(cutoff) = cutoff(now, policy.job_days) {
+        let gate_ids = db
+            .gate()
+            .find_many(vec![gate_scope(&domain.id)])
+            .exec()
+            .await?
+            .into_iter()
+            .map(|g| g.id)
+            .collect::<HashSet<_>>();
+        let change_request_ids = change_requests
+            .iter()
+            .map(|cr| cr.id.clone())
+            .collect::<HashSet<_>>();
+        let staging_names = change_requests
+            .iter()
+            .filter_map(|cr| cr.staged_publication.clone().flatten())
+            .map(|p| p.staging)
+            .collect::<HashSet<_>>();
+
+        let jobs = db
+            .queued_job()
+            .find_many(vec![
+                prisma::queued_job::state::equals(prisma::QueuedJobState::Finished),
+                prisma::queued_job::created_at::lt(cutoff),
+            ])
+            .exec()
+            .await?;
+        for job in jobs.into_iter().filter(|j| {
+            job_belongs_to_domain(&j.reference, &gate_ids, &change_request_ids, &staging_names)
+        }) {
+            report.reclaimed_database_bytes += json_size(&[&job.payload]);
+            job_ids.push(job.id);
+        }
+    }
+
+    // Change requests still building or promoting packages are never touched
+    let mut change_request_ids = vec![];
+    if let Some(cutoff) = cutoff(now, policy.change_request_days) {
+        for cr in change_requests.iter() {
+            let done = matches!(
+                cr.state,
+                prisma::ChangeRequestState::Closed | prisma::ChangeRequestState::Applied
+            );
+            let staging_busy = cr.staged_publication.clone().flatten().is_some_and(|p| {
+                matches!(
+                    p.state,
+                    prisma::StagedPublicationState::Building
+                        | prisma::StagedPublicationState::Promoting
+                )
+            });
+            if done && !cr.processing && !staging_busy && cr.updated_at < cutoff {
+                change_request_ids.push(cr.id.clone());
+            }
+        }
+    }
+
+    // Once applied the recipe lives on in the component, the change only documents it
+    let mut change_filters = vec![];
+    if let Some(cutoff) = cutoff(now, policy.component_change_days) {
+        change_filters.push(prisma::component_change::WhereParam::And(vec![
+            prisma::component_change::applied::equals(true),
+            prisma::component_change::created_at::lt(cutoff),
+        ]));
+    }
+    if !change_request_ids.is_empty() {
+        change_filters.push(prisma::component_change::change_request_id::in_vec(
+            change_request_ids.clone(),
+        ));
+    }
+    let changes = if change_filters.is_empty() {
+        vec![]
+    } else {
+        db.component_change()
+            .find_many(vec![
+                prisma::component_change::change_request::is(vec![
+                    prisma::change_request::domain_id::equals(Some(domain.id.clone())),
+                ]),
+                prisma::component_change::WhereParam::Or(change_filters),
+            ])
+            .exec()
+            .await?
+    };
+
+    let mut change_ids = vec![];
+    let mut blob_candidates = HashSet::new();
+    for change in changes {
+        report.reclaimed_database_bytes += json_size(&[
+            &change.diff,
+            &change.recipe,
+            &change.package_meta,
+            &change.patches,
+        ]);
+        blob_candidates.extend(change.scripts.into_iter().chain(change.archives));
+        change_ids.push(change.id);
+    }
+
+    // Blobs are content addressed and shared, only remove the ones nothing else uses
+    for blob in blob_candidates {
+        let used_by_component = db
+            .component()
+            .find_first(vec![prisma::component::WhereParam::Or(vec![
+                prisma::component::scripts::has(Some(blob.clone())),
+                prisma::component::archives::has(Some(blob.clone())),
+            ])])
+            .exec()
+            .await?
+            .is_some();
+        let used_by_change = db
+            .component_change()
+            .find_first(vec![
+                prisma::component_change::id::not_in_vec(change_ids.clone()),
+                prisma::component_change::WhereParam::Or(vec![
+                    prisma::component_change::scripts::has(Some(blob.clone())),
+                    prisma::component_change::archives::has(Some(blob.clone())),
+                ]),
+            ])
+            .exec()
+            .await?
+            .is_some();
+        if used_by_component || used_by_change {
+            continue;
+        }
+        match op.stat(&blob).await {
+            Ok(metadata) => report.reclaimed_blob_bytes += metadata.content_length(),
+            Err(e) => warn!("could not get the size of blob {}: {}", blob, e),
+        }
+        report.blobs.push(blob);
+    }
+    report.blobs.sort();
+
+    report.jobs = job_ids.len();
+    report.component_changes = change_ids.len();
+    report.change_requests = change_request_ids.len();
+
+    if dry_run {
+        debug!("dry run, nothing removed");
+        return Ok(report);
+    }
+
+    db.queued_job()
+        .delete_many(vec![prisma::queued_job::id::in_vec(job_ids)])
+        .exec()
+        .await?;
+    db.component_change()
+        .delete_many(vec![prisma::component_change::id::in_vec(change_ids)])
+        .exec()
+        .await?;
+    if !change_request_ids.is_empty() {
+        let dependants = db
+            .change_request()
+            .find_many(vec![prisma::change_request::wait_for_request_id::in_vec(
+                change_request_ids.clone(),
+            )])
+            .exec()
+            .await?;
+        for dependant in dependants {
+            db.change_request()
+                .update(
+                    prisma::change_request::UniqueWhereParam::IdEquals(dependant.id),
+                    vec![prisma::change_request::wait_for_request::disconnect()],
+                )
+                .exec()
+                .await?;
+        }
+        db.build_job()
+            .delete_many(vec![prisma::build_job::change_request_id::in_vec(
+                change_request_ids.clone(),
+            )])
+            .exec()
+            .await?;
+        db.staged_publication()
+            .delete_many(vec![prisma::staged_publication::change_request_id::in_vec(
+                change_request_ids.clone(),
+            )])
+            .exec()
+            .await?;
+        db.change_request()
+            .delete_many(vec![prisma::change_request::id::in_vec(change_request_ids)])
+            .exec()
+            .await?;
+    }
+    for blob in report.blobs.iter() {
+        op.delete(blob).await?;
+    }
+
+    db.retention_run()
+        .create(
+            prisma::domain::UniqueWhereParam::IdEquals(domain.id.clone()),
+            report.jobs as i32,
+            report.component_changes as i32,
+            report.change_requests as i32,
+            report.blobs.len() as i32,
+            report.reclaimed_database_bytes as i64,
+            report.reclaimed_blob_bytes as i64,
+            vec![],
+        )
+        .exec()
+        .await?;
+    info!(
+        "removed {} jobs, {} component changes, {} change requests and {} blobs",
+        report.jobs,
+        report.component_changes,
+        report.change_requests,
+        report.blobs.len()
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cutoff_is_unset_without_retention() {
+        let now = DateTime::parse_from_rfc3339("2024-05-10T12:00:00+00:00").unwrap();
+        assert_eq!(cutoff(now, None), None);
+        assert_eq!(
+            cutoff(now, Some(30)),
+            Some(DateTime::parse_from_rfc3339("2024-04-10T12:00:00+00:00").unwrap())
+        );
+    }
+
+    #[test]
+    fn jobs_are_matched_to_domains_by_reference() {
+        let gate_ids = HashSet::from(["9d0f6d4e-0000-4000-8000-000000000001".to_string()]);
+        let change_request_ids =
+            HashSet::from(["https://forge.example.com/objects/changeRequests/a/1".to_string()]);
+        let staging_names = HashSet::from(["1".to_string()]);
+        let belongs = |reference: &str| {
+            job_belongs_to_domain(reference, &gate_ids, &change_request_ids, &staging_names)
+        };
+
+        assert!(belongs(
+            "9d0f6d4e-0000-4000-8000-000000000001/library/zlib@1.3.1-0"
+        ));
+        assert!(belongs(
+            "https://forge.example.com/objects/changeRequests/a/1"
+        ));
+        assert!(belongs("1"));
+        assert!(!belongs(
+            "5b1d0c6e-0000-4000-8000-000000000002/library/zlib@1.3.1-0"
+        ));
+        assert!(!belongs(
+            "https://other.example.com/objects/changeRequests/a/1"
+        ));
+    }
+
+    #[test]
+    fn policy_defaults_to_keeping_everything() -> Result<()> {
+        let policy: RetentionPolicy = serde_json::from_value(serde_json::json!({}))?;
+        assert_eq!(policy, RetentionPolicy::default());
+        let policy: RetentionPolicy =
+            serde_json::from_value(serde_json::json!({ "job_days": 14 }))?;
+        assert_eq!(policy.job_days, Some(14));
+        Ok(())
+    }
+}
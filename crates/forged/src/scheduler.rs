@@ -0,0 +1,611 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use deadpool_lapin::lapin::options::{BasicPublishOptions, QueueDeclareOptions};
+use deadpool_lapin::lapin::protocol::basic::AMQPProperties;
+use deadpool_lapin::lapin::types::{AMQPValue, FieldTable};
+use deadpool_lapin::lapin::Channel;
+use forge::{Job, JobControl, JobReport};
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+use utoipa::ToSchema;
+
+use crate::audit::{self, AuditEvent};
+use crate::correlation;
+use crate::prisma::{self, PrismaClient};
+use crate::{Error, Result};
+
+/// How urgent a job is. Pending jobs of a higher priority are dispatched before those
+/// of lower priorities, security rebuilds before everything else.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Security,
+}
+
+impl JobPriority {
+    /// The value stored in the queue, spaced so levels can be added in between.
+    fn value(self) -> i32 {
+        match self {
+            JobPriority::Low => -10,
+            JobPriority::Normal => 0,
+            JobPriority::High => 10,
+            JobPriority::Security => 20,
+        }
+    }
+
+    /// The level of a stored value, values between levels round down.
+    pub fn from_value(value: i32) -> Self {
+        [
+            JobPriority::Security,
+            JobPriority::High,
+            JobPriority::Normal,
+        ]
+        .into_iter()
+        .find(|p| value >= p.value())
+        .unwrap_or(JobPriority::Low)
+    }
+}
+
+/// Store a job in the queue of its source repository. Jobs are only sent to the
+/// workers by [`dispatch_jobs`] so that one busy repository cannot starve the others.
+/// Returns the id of the queued job.
+#[instrument(skip(db, job))]
+pub async fn enqueue_job(db: &PrismaClient, job: &Job, priority: JobPriority) -> Result<String> {
+    let url = job.source_repository().to_string();
+    let repository = db
+        .source_repository()
+        .upsert(
+            prisma::source_repository::UniqueWhereParam::UrlEquals(url.clone()),
+            (url.clone(), vec![]),
+            vec![],
+        )
+        .exec()
+        .await?;
+
+    let queued = db
+        .queued_job()
+        .create(
+            prisma::source_repository::UniqueWhereParam::IdEquals(repository.id),
+            job.reference(),
+            serde_json::to_value(job)?,
+            vec![
+                prisma::queued_job::correlation_id::set(correlation::current()),
+                prisma::queued_job::trace_context::set(Some(serde_json::to_value(
+                    telemetry::current_context(),
+                )?)),
+                prisma::queued_job::priority::set(priority.value()),
+                prisma::queued_job::gate_id::set(job.gate_id().map(|id| id.to_string())),
+                prisma::queued_job::staging::set(job.staging().map(str::to_string)),
+            ],
+        )
+        .exec()
+        .await?;
+    debug!("queued job {} for repository {}", job.reference(), url);
+
+    let domain_id = match job.gate_id() {
+        Some(gate_id) => audit::gate_domain(db, &gate_id.to_string()).await?,
+        None => None,
+    };
+    audit::record(
+        db,
+        domain_id.as_deref(),
+        None,
+        AuditEvent::new("job.submit", "job", &queued.id).after(job)?,
+    )
+    .await?;
+    Ok(queued.id)
+}
+
+/// Mark the running jobs the report belongs to as finished which frees their slots.
+#[instrument(skip_all)]
+pub async fn finish_job(db: &PrismaClient, report: &JobReport) -> Result<()> {
+    let (outcome, error) = match report {
+        JobReport::Success(_) => (prisma::QueuedJobOutcome::Success, None),
+        JobReport::Failure { error, .. } => {
+            (prisma::QueuedJobOutcome::Failure, Some(error.clone()))
+        }
+        JobReport::Cancelled { .. } => (prisma::QueuedJobOutcome::Cancelled, None),
+    };
+    db.queued_job()
+        .update_many(
+            vec![
+                prisma::queued_job::reference::equals(report.reference()),
+                prisma::queued_job::state::equals(prisma::QueuedJobState::Running),
+            ],
+            vec![
+                prisma::queued_job::state::set(prisma::QueuedJobState::Finished),
+                prisma::queued_job::finished_at::set(Some(chrono::Utc::now().fixed_offset())),
+                prisma::queued_job::outcome::set(Some(outcome)),
+                prisma::queued_job::error::set(error),
+            ],
+        )
+        .exec()
+        .await?;
+    Ok(())
+}
+
+struct PendingJob {
+    id: String,
+    gate_id: Option<String>,
+    priority: i32,
+}
+
+struct RepositorySlots {
+    id: String,
+    free: usize,
+    /// Oldest job first
+    pending: Vec<PendingJob>,
+}
+
+/// Pick the jobs to dispatch, highest priority first. Within a priority the jobs are
+/// picked round-robin over the repositories, which must be sorted by the time they were
+/// last served, least recently served first. Jobs of gates without free slots in
+/// `gate_slots` wait, gates missing there have no limit.
+fn plan_dispatch(
+    mut repositories: Vec<RepositorySlots>,
+    mut gate_slots: HashMap<String, usize>,
+) -> Vec<(String, String)> {
+    let mut priorities = repositories
+        .iter()
+        .flat_map(|r| r.pending.iter().map(|j| j.priority))
+        .collect::<Vec<_>>();
+    priorities.sort_unstable_by(|a, b| b.cmp(a));
+    priorities.dedup();
+
+    let mut planned = vec![];
+    for priority in priorities {
+        loop {
+            let mut progress = false;
+            for repository in repositories.iter_mut() {
+                if repository.free == 0 {
+                    continue;
+                }
+                let next = repository.pending.iter().position(|j| {
+                    j.priority == priority
+                        && j.gate_id
+                            .as_ref()
+                            .and_then(|g| gate_slots.get(g))
+                            .map_or(true, |free| *free > 0)
+                });
+                if let Some(position) = next {
+                    let job = repository.pending.remove(position);
+                    if let Some(free) = job.gate_id.as_ref().and_then(|g| gate_slots.get_mut(g)) {
+                        *free -= 1;
+                    }
+                    repository.free -= 1;
+                    planned.push((repository.id.clone(), job.id));
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+    }
+    planned
+}
+
+/// The free slots of the gates with a concurrency limit that `jobs` belong to.
+async fn gate_slots(
+    db: &PrismaClient,
+    jobs: &[&prisma::queued_job::Data],
+) -> Result<HashMap<String, usize>> {
+    let mut gate_ids = jobs
+        .iter()
+        .filter_map(|j| j.gate_id.clone())
+        .collect::<Vec<_>>();
+    gate_ids.sort();
+    gate_ids.dedup();
+    if gate_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut slots = db
+        .gate()
+        .find_many(vec![prisma::gate::id::in_vec(gate_ids.clone())])
+        .exec()
+        .await?
+        .into_iter()
+        .filter_map(|g| Some((g.id, g.max_concurrent_jobs?.max(0) as usize)))
+        .collect::<HashMap<_, _>>();
+    let running = db
+        .queued_job()
+        .find_many(vec![
+            prisma::queued_job::state::equals(prisma::QueuedJobState::Running),
+            prisma::queued_job::gate_id::in_vec(gate_ids),
+        ])
+        .exec()
+        .await?;
+    for job in running {
+        if let Some(free) = job.gate_id.and_then(|g| slots.get_mut(&g)) {
+            *free = free.saturating_sub(1);
+        }
+    }
+    Ok(slots)
+}
+
+/// Send as many pending jobs to the workers as the per repository and per gate limits
+/// allow.
+#[instrument(skip_all)]
+pub async fn dispatch_jobs(db: &PrismaClient, channel: &Channel, job_inbox: &str) -> Result<()> {
+    let repositories = db
+        .source_repository()
+        .find_many(vec![prisma::source_repository::jobs::some(vec![
+            prisma::queued_job::state::equals(prisma::QueuedJobState::Pending),
+        ])])
+        .with(
+            prisma::source_repository::jobs::fetch(vec![prisma::queued_job::state::not(
+                prisma::QueuedJobState::Finished,
+            )])
+            .order_by(prisma::queued_job::created_at::order(Direction::Asc)),
+        )
+        .order_by(prisma::source_repository::last_dispatched_at::order(
+            Direction::Asc,
+        ))
+        .exec()
+        .await?;
+
+    // Repositories that were never served go first.
+    let (mut never_served, served): (Vec<_>, Vec<_>) = repositories
+        .into_iter()
+        .partition(|r| r.last_dispatched_at.is_none());
+    never_served.extend(served);
+
+    let mut pending_jobs = HashMap::new();
+    let mut slots = vec![];
+    for repository in never_served {
+        let jobs = repository.jobs.unwrap_or_default();
+        let running = jobs
+            .iter()
+            .filter(|j| j.state == prisma::QueuedJobState::Running)
+            .count();
+        let mut pending = vec![];
+        for job in jobs
+            .into_iter()
+            .filter(|j| j.state == prisma::QueuedJobState::Pending)
+        {
+            pending.push(PendingJob {
+                id: job.id.clone(),
+                gate_id: job.gate_id.clone(),
+                priority: job.priority,
+            });
+            pending_jobs.insert(job.id.clone(), job);
+        }
+        slots.push(RepositorySlots {
+            id: repository.id,
+            free: (repository.max_concurrent_jobs.max(0) as usize).saturating_sub(running),
+            pending,
+        });
+    }
+    let gates = gate_slots(db, &pending_jobs.values().collect::<Vec<_>>()).await?;
+
+    for (repository_id, job_id) in plan_dispatch(slots, gates) {
+        let Some(job) = pending_jobs.remove(&job_id) else {
+            continue;
+        };
+        let msg = serde_json::to_vec(&job.payload)?;
+        // The worker logs and reports under the id of what caused the job
+        let correlation_id = job
+            .correlation_id
+            .clone()
+            .unwrap_or_else(forge::new_correlation_id);
+        let trace_context: HashMap<String, String> = job
+            .trace_context
+            .clone()
+            .and_then(|context| serde_json::from_value(context).ok())
+            .unwrap_or_default();
+        // Declared before the job is sent so a cancellation is not lost when it is sent
+        // before the worker started listening
+        declare_control_queue(channel, &job.id).await?;
+        channel
+            .basic_publish(
+                job_inbox,
+                "",
+                BasicPublishOptions::default(),
+                &msg,
+                // The job continues the trace of what queued it, not of this dispatch
+                telemetry::with_context(
+                    AMQPProperties::default().with_correlation_id(correlation_id.as_str().into()),
+                    &trace_context,
+                )
+                .with_message_id(job.id.as_str().into()),
+            )
+            .await?;
+
+        let now = chrono::Utc::now().fixed_offset();
+        db.queued_job()
+            .update(
+                prisma::queued_job::UniqueWhereParam::IdEquals(job.id.clone()),
+                vec![
+                    prisma::queued_job::state::set(prisma::QueuedJobState::Running),
+                    prisma::queued_job::dispatched_at::set(Some(now)),
+                ],
+            )
+            .exec()
+            .await?;
+        db.source_repository()
+            .update(
+                prisma::source_repository::UniqueWhereParam::IdEquals(repository_id.clone()),
+                vec![prisma::source_repository::last_dispatched_at::set(Some(
+                    now,
+                ))],
+            )
+            .exec()
+            .await?;
+        debug!(
+            "dispatched job {} of repository {}",
+            job.reference, repository_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Declare the control queue of a job, see [`forge::job_control_queue`].
+async fn declare_control_queue(channel: &Channel, job_id: &str) -> Result<()> {
+    let mut arguments = FieldTable::default();
+    arguments.insert(
+        "x-expires".into(),
+        AMQPValue::LongLongInt(forge::JOB_CONTROL_QUEUE_EXPIRES_MS),
+    );
+    channel
+        .queue_declare(
+            &forge::job_control_queue(job_id),
+            QueueDeclareOptions::default(),
+            arguments,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Whether a cancelled job was still waiting or has to be aborted by its worker.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CancellationState {
+    /// The job was never dispatched and will not run
+    Cancelled,
+    /// The worker was told to abort the job, it reports the job as cancelled
+    Cancelling,
+}
+
+/// Cancel a queued job. Pending jobs are finished right away, the worker of a running
+/// job is sent [`JobControl::Cancel`] on the control queue of the job.
+#[instrument(skip(db, channel))]
+pub async fn cancel_job(
+    db: &PrismaClient,
+    channel: &Channel,
+    job: &prisma::queued_job::Data,
+) -> Result<CancellationState> {
+    let now = chrono::Utc::now().fixed_offset();
+    match job.state {
+        prisma::QueuedJobState::Pending => {
+            db.queued_job()
+                .update(
+                    prisma::queued_job::UniqueWhereParam::IdEquals(job.id.clone()),
+                    vec![
+                        prisma::queued_job::state::set(prisma::QueuedJobState::Finished),
+                        prisma::queued_job::cancelled_at::set(Some(now)),
+                        prisma::queued_job::finished_at::set(Some(now)),
+                        prisma::queued_job::outcome::set(Some(prisma::QueuedJobOutcome::Cancelled)),
+                    ],
+                )
+                .exec()
+                .await?;
+            debug!("cancelled pending job {}", job.reference);
+            Ok(CancellationState::Cancelled)
+        }
+        prisma::QueuedJobState::Running => {
+            if job.cancelled_at.is_none() {
+                db.queued_job()
+                    .update(
+                        prisma::queued_job::UniqueWhereParam::IdEquals(job.id.clone()),
+                        vec![prisma::queued_job::cancelled_at::set(Some(now))],
+                    )
+                    .exec()
+                    .await?;
+            }
+            declare_control_queue(channel, &job.id).await?;
+            channel
+                .basic_publish(
+                    "",
+                    &forge::job_control_queue(&job.id),
+                    BasicPublishOptions::default(),
+                    &serde_json::to_vec(&JobControl::Cancel)?,
+                    correlation::properties(
+                        &correlation::current().unwrap_or_else(forge::new_correlation_id),
+                    ),
+                )
+                .await?;
+            debug!("asked the worker of job {} to abort it", job.reference);
+            Ok(CancellationState::Cancelling)
+        }
+        prisma::QueuedJobState::Finished => Err(Error::NotCancellable(format!(
+            "job {} already finished",
+            job.id
+        ))),
+    }
+}
+
+/// How far back dispatched jobs count towards the average wait.
+const WAIT_WINDOW_MINUTES: i64 = 60;
+
+/// The backlog of one kind of job.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct KindBacklog {
+    pub pending: usize,
+    pub running: usize,
+}
+
+/// What autoscalers of the workers need to know about the queue.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QueueMetrics {
+    pub pending: usize,
+    pub running: usize,
+    /// Average seconds jobs dispatched in the last hour waited in the queue
+    pub average_wait_seconds: f64,
+    /// Seconds the oldest pending job has been waiting
+    pub oldest_pending_seconds: i64,
+    /// Backlog by the kind of job
+    pub kinds: BTreeMap<String, KindBacklog>,
+}
+
+struct QueuedJobTiming {
+    kind: String,
+    state: prisma::QueuedJobState,
+    created_at: DateTime<FixedOffset>,
+    dispatched_at: Option<DateTime<FixedOffset>>,
+}
+
+fn summarize_queue(jobs: &[QueuedJobTiming], now: DateTime<FixedOffset>) -> QueueMetrics {
+    let mut metrics = QueueMetrics::default();
+    let window_start = now - TimeDelta::minutes(WAIT_WINDOW_MINUTES);
+    let mut waits = vec![];
+    for job in jobs {
+        let backlog = metrics.kinds.entry(job.kind.clone()).or_default();
+        match job.state {
+            prisma::QueuedJobState::Pending => {
+                backlog.pending += 1;
+                metrics.pending += 1;
+                metrics.oldest_pending_seconds = metrics
+                    .oldest_pending_seconds
+                    .max((now - job.created_at).num_seconds());
+            }
+            prisma::QueuedJobState::Running => {
+                backlog.running += 1;
+                metrics.running += 1;
+            }
+            prisma::QueuedJobState::Finished => {}
+        }
+        if let Some(dispatched_at) = job.dispatched_at.filter(|d| *d >= window_start) {
+            waits.push((dispatched_at - job.created_at).num_milliseconds() as f64 / 1000.0);
+        }
+    }
+    if !waits.is_empty() {
+        metrics.average_wait_seconds = waits.iter().sum::<f64>() / waits.len() as f64;
+    }
+    metrics
+}
+
+/// Measure the queue over all repositories. Jobs forged cannot read count as unknown.
+#[instrument(skip_all)]
+pub async fn queue_metrics(db: &PrismaClient) -> Result<QueueMetrics> {
+    let now = chrono::Utc::now().fixed_offset();
+    let jobs = db
+        .queued_job()
+        .find_many(vec![prisma::queued_job::WhereParam::Or(vec![
+            prisma::queued_job::state::not(prisma::QueuedJobState::Finished),
+            prisma::queued_job::dispatched_at::gte(now - TimeDelta::minutes(WAIT_WINDOW_MINUTES)),
+        ])])
+        .exec()
+        .await?
+        .into_iter()
+        .map(|job| QueuedJobTiming {
+            kind: serde_json::from_value::<Job>(job.payload)
+                .map(|j| j.kind().to_string())
+                .unwrap_or(String::from("unknown")),
+            state: job.state,
+            created_at: job.created_at,
+            dispatched_at: job.dispatched_at,
+        })
+        .collect::<Vec<_>>();
+    Ok(summarize_queue(&jobs, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prisma::QueuedJobState::{Finished, Pending, Running};
+
+    fn repository(
+        id: &str,
+        free: usize,
+        jobs: &[(&str, Option<&str>, JobPriority)],
+    ) -> RepositorySlots {
+        RepositorySlots {
+            id: id.to_string(),
+            free,
+            pending: jobs
+                .iter()
+                .map(|(id, gate_id, priority)| PendingJob {
+                    id: id.to_string(),
+                    gate_id: gate_id.map(String::from),
+                    priority: priority.value(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn dispatches_by_priority_within_limits() {
+        use JobPriority::{Normal, Security};
+
+        let planned = plan_dispatch(
+            vec![
+                repository(
+                    "a",
+                    2,
+                    &[("a1", Some("g"), Normal), ("a2", Some("g"), Normal)],
+                ),
+                repository("b", 1, &[("b1", None, Normal), ("b2", Some("h"), Security)]),
+                repository("c", 2, &[("c1", Some("g"), Security), ("c2", None, Normal)]),
+            ],
+            HashMap::from([(String::from("g"), 2), (String::from("h"), 0)]),
+        );
+        let planned = planned.into_iter().map(|(_, job)| job).collect::<Vec<_>>();
+
+        // b2 waits for a slot of its gate, g has room for two jobs
+        assert_eq!(planned, vec!["c1", "a1", "b1", "c2"]);
+    }
+
+    #[test]
+    fn summarizes_backlog_and_waits() {
+        let now = chrono::Utc::now().fixed_offset();
+        let job = |kind: &str, state, created: i64, dispatched: Option<i64>| QueuedJobTiming {
+            kind: kind.to_string(),
+            state,
+            created_at: now - TimeDelta::seconds(created),
+            dispatched_at: dispatched.map(|d| now - TimeDelta::seconds(d)),
+        };
+        let metrics = summarize_queue(
+            &[
+                job("BuildStoredComponent", Pending, 300, None),
+                job("BuildStoredComponent", Running, 100, Some(80)),
+                job("GetRecipes", Finished, 50, Some(10)),
+                job("GetRecipes", Finished, 9000, Some(8000)),
+            ],
+            now,
+        );
+
+        assert_eq!(metrics.pending, 1);
+        assert_eq!(metrics.running, 1);
+        assert_eq!(metrics.oldest_pending_seconds, 300);
+        assert_eq!(metrics.average_wait_seconds, 30.0);
+        assert_eq!(
+            metrics.kinds["BuildStoredComponent"],
+            KindBacklog {
+                pending: 1,
+                running: 1
+            }
+        );
+        assert_eq!(metrics.kinds["GetRecipes"], KindBacklog::default());
+    }
+
+    #[test]
+    fn priorities_round_trip_through_their_values() {
+        use JobPriority::{High, Low, Normal, Security};
+
+        for priority in [Low, Normal, High, Security] {
+            assert_eq!(JobPriority::from_value(priority.value()), priority);
+        }
+        assert_eq!(JobPriority::from_value(15), High);
+        assert_eq!(JobPriority::from_value(-3), Low);
+    }
+}
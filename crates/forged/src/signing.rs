@@ -0,0 +1,210 @@
+use base64::Engine;
+use forge::signing::SigningKey;
+use pasetors::keys::SymmetricKey;
+use pasetors::token::UntrustedToken;
+use pasetors::version4::{LocalToken, V4};
+use pasetors::Local;
+use prisma_client_rust::Direction;
+use tracing::info;
+
+use crate::prisma::{self, PrismaClient};
+use crate::{Error, Result};
+
+/// Encrypts the secret keys of publishers before they are stored in the database, with
+/// the key of the `signing_key_encryption_key` setting.
+#[derive(Clone, Default)]
+pub struct SecretKeyCipher(Option<[u8; 32]>);
+
+impl std::fmt::Debug for SecretKeyCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretKeyCipher(<redacted>)")
+    }
+}
+
+impl SecretKeyCipher {
+    /// The cipher of the base64 encoded 32 byte key. Without a key secret keys can
+    /// neither be stored nor used.
+    pub fn new(key: Option<&str>) -> Result<Self> {
+        let Some(key) = key else {
+            return Ok(Self(None));
+        };
+        let key = base64::engine::general_purpose::STANDARD.decode(key)?;
+        let key = key.try_into().map_err(|_| {
+            Error::SigningKeyEncryption(String::from("the encryption key is not 32 bytes"))
+        })?;
+        Ok(Self(Some(key)))
+    }
+
+    fn key(&self) -> Result<SymmetricKey<V4>> {
+        let key = self
+            .0
+            .as_ref()
+            .ok_or(Error::SigningKeyEncryption(String::from(
+                "signing_key_encryption_key is not configured",
+            )))?;
+        Ok(SymmetricKey::<V4>::from(key)?)
+    }
+
+    /// Encrypt the secret key, bound to the id of the key so it cannot be swapped with
+    /// the one of another publisher.
+    pub fn encrypt(&self, secret_key: &str, key_id: &str) -> Result<String> {
+        Ok(LocalToken::encrypt(
+            &self.key()?,
+            secret_key.as_bytes(),
+            None,
+            Some(key_id.as_bytes()),
+        )?)
+    }
+
+    pub fn decrypt(&self, stored: &str, key_id: &str) -> Result<String> {
+        let token = UntrustedToken::<Local, V4>::try_from(stored)?;
+        let trusted = LocalToken::decrypt(&self.key()?, &token, None, Some(key_id.as_bytes()))?;
+        Ok(trusted.payload().to_string())
+    }
+
+    fn is_encrypted(stored: &str) -> bool {
+        stored.starts_with("v4.local.")
+    }
+}
+
+/// Encrypt the secret keys stored before forged encrypted them. Without an encryption key
+/// forged cannot sign with stored keys, so it refuses to start when there are any.
+pub async fn encrypt_stored_keys(db: &PrismaClient, cipher: &SecretKeyCipher) -> Result<()> {
+    let keys = db.signing_key().find_many(vec![]).exec().await?;
+    if cipher.0.is_none() {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        return Err(Error::SigningKeyEncryption(String::from(
+            "signing keys are stored but signing_key_encryption_key is not configured",
+        )));
+    }
+    for key in keys
+        .into_iter()
+        .filter(|k| !SecretKeyCipher::is_encrypted(&k.secret_key))
+    {
+        info!("encrypting secret key {}", key.key_id);
+        db.signing_key()
+            .update(
+                prisma::signing_key::id::equals(key.id),
+                vec![prisma::signing_key::secret_key::set(
+                    cipher.encrypt(&key.secret_key, &key.key_id)?,
+                )],
+            )
+            .exec()
+            .await?;
+    }
+    Ok(())
+}
+
+/// The comment of the public key of the publisher, signatures refer to it by file name.
+pub fn public_key_comment(publisher: &str) -> String {
+    format!("{} public key", publisher)
+}
+
+fn signature_comment(publisher: &str) -> String {
+    format!("verify with {}.pub", publisher)
+}
+
+/// The key the publisher signs with, publishers without one publish unsigned.
+pub async fn current_signing_key(
+    db: &PrismaClient,
+    publisher_id: &str,
+) -> Result<Option<prisma::signing_key::Data>> {
+    Ok(db
+        .signing_key()
+        .find_first(vec![
+            prisma::signing_key::publisher_id::equals(publisher_id.to_string()),
+            prisma::signing_key::retired_at::equals(None),
+        ])
+        .order_by(prisma::signing_key::created_at::order(Direction::Desc))
+        .exec()
+        .await?)
+}
+
+/// Generate a new key for the publisher and retire the one it replaces.
+pub async fn rotate_signing_key(
+    db: &PrismaClient,
+    cipher: &SecretKeyCipher,
+    publisher: &prisma::publisher::Data,
+) -> Result<prisma::signing_key::Data> {
+    let key = SigningKey::generate();
+    let publisher_id = publisher.id.clone();
+    let public_key = key.public_key(&public_key_comment(&publisher.name));
+    let key_id = key.key_id();
+    let secret_key = cipher.encrypt(
+        &key.secret_key(&format!("{} secret key", publisher.name)),
+        &key_id,
+    )?;
+    let created = db
+        ._transaction()
+        .run::<Error, _, _, _>(|db| async move {
+            db.signing_key()
+                .update_many(
+                    vec![
+                        prisma::signing_key::publisher_id::equals(publisher_id.clone()),
+                        prisma::signing_key::retired_at::equals(None),
+                    ],
+                    vec![prisma::signing_key::retired_at::set(Some(
+                        chrono::Utc::now().fixed_offset(),
+                    ))],
+                )
+                .exec()
+                .await?;
+            Ok(db
+                .signing_key()
+                .create(
+                    prisma::publisher::id::equals(publisher_id),
+                    key_id,
+                    public_key,
+                    secret_key,
+                    vec![],
+                )
+                .exec()
+                .await?)
+        })
+        .await?;
+    info!(
+        "publisher {} signs with key {} now",
+        publisher.name, created.key_id
+    );
+    Ok(created)
+}
+
+/// A detached signature of the message made with the current key of the publisher.
+pub async fn sign_for_publisher(
+    db: &PrismaClient,
+    cipher: &SecretKeyCipher,
+    publisher_id: &str,
+    publisher: &str,
+    message: &[u8],
+) -> Result<Option<String>> {
+    let Some(key) = current_signing_key(db, publisher_id).await? else {
+        return Ok(None);
+    };
+    let key = SigningKey::from_secret_key(&cipher.decrypt(&key.secret_key, &key.key_id)?)?;
+    Ok(Some(key.sign(message, &signature_comment(publisher))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_keys_are_bound_to_their_id() {
+        let cipher =
+            SecretKeyCipher::new(Some("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=")).unwrap();
+        let stored = cipher
+            .encrypt("untrusted comment: secret", "key-1")
+            .unwrap();
+        assert!(SecretKeyCipher::is_encrypted(&stored));
+        assert_eq!(
+            cipher.decrypt(&stored, "key-1").unwrap(),
+            "untrusted comment: secret"
+        );
+        assert!(cipher.decrypt(&stored, "key-2").is_err());
+        assert!(SecretKeyCipher::default()
+            .decrypt(&stored, "key-1")
+            .is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::prisma::{self, PrismaClient};
+use crate::quality::latest_components;
+use crate::{Error, Result};
+
+/// A component version frozen in a snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+pub struct SnapshotComponent {
+    pub name: String,
+    pub version: String,
+    pub revision: String,
+}
+
+/// A component with another version in the second snapshot of a comparison.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct SnapshotChange {
+    pub name: String,
+    pub from_version: String,
+    pub from_revision: String,
+    pub to_version: String,
+    pub to_revision: String,
+}
+
+/// What changed between two snapshots, of the same gate or of a testing and a stable
+/// gate.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct SnapshotComparison {
+    /// Components only in the second snapshot
+    pub added: Vec<SnapshotComponent>,
+    /// Components only in the first snapshot
+    pub removed: Vec<SnapshotComponent>,
+    pub changed: Vec<SnapshotChange>,
+    pub unchanged: usize,
+}
+
+/// The components of a snapshot as stored in the database.
+pub fn snapshot_components(
+    snapshot: &prisma::gate_snapshot::Data,
+) -> Result<Vec<SnapshotComponent>> {
+    Ok(serde_json::from_value(snapshot.components.clone())?)
+}
+
+pub fn compare_snapshots(
+    from: &[SnapshotComponent],
+    to: &[SnapshotComponent],
+) -> SnapshotComparison {
+    let from = from
+        .iter()
+        .map(|c| (&c.name, c))
+        .collect::<BTreeMap<_, _>>();
+    let to = to.iter().map(|c| (&c.name, c)).collect::<BTreeMap<_, _>>();
+    let mut comparison = SnapshotComparison::default();
+    for (name, old) in from.iter() {
+        match to.get(name) {
+            None => comparison.removed.push((*old).clone()),
+            Some(new) if new == old => comparison.unchanged += 1,
+            Some(new) => comparison.changed.push(SnapshotChange {
+                name: old.name.clone(),
+                from_version: old.version.clone(),
+                from_revision: old.revision.clone(),
+                to_version: new.version.clone(),
+                to_revision: new.revision.clone(),
+            }),
+        }
+    }
+    for (name, new) in to.iter() {
+        if !from.contains_key(name) {
+            comparison.added.push((*new).clone());
+        }
+    }
+    comparison
+}
+
+async fn check_name_free(db: &PrismaClient, gate_id: &str, name: &str) -> Result<()> {
+    let existing = db
+        .gate_snapshot()
+        .find_unique(prisma::gate_snapshot::UniqueWhereParam::GateIdNameEquals(
+            gate_id.to_string(),
+            name.to_string(),
+        ))
+        .exec()
+        .await?;
+    if existing.is_some() {
+        return Err(Error::SnapshotConflict(format!(
+            "the gate already has a snapshot {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+async fn create_snapshot(
+    db: &PrismaClient,
+    gate_id: &str,
+    name: &str,
+    components: &[SnapshotComponent],
+    promoted_from: Option<String>,
+) -> Result<prisma::gate_snapshot::Data> {
+    check_name_free(db, gate_id, name).await?;
+    Ok(db
+        .gate_snapshot()
+        .create(
+            prisma::gate::UniqueWhereParam::IdEquals(gate_id.to_string()),
+            name.to_string(),
+            serde_json::to_value(components)?,
+            vec![prisma::gate_snapshot::promoted_from::set(promoted_from)],
+        )
+        .exec()
+        .await?)
+}
+
+/// Freeze the latest version of every component of the gate into a new snapshot.
+pub async fn freeze_gate(
+    db: &PrismaClient,
+    gate: &prisma::gate::Data,
+    name: &str,
+) -> Result<prisma::gate_snapshot::Data> {
+    let components = db
+        .component()
+        .find_many(vec![prisma::component::gate_id::equals(gate.id.clone())])
+        .exec()
+        .await?;
+    let mut frozen = latest_components(components)
+        .into_iter()
+        .map(|c| SnapshotComponent {
+            name: c.name,
+            version: c.version,
+            revision: c.revision,
+        })
+        .collect::<Vec<_>>();
+    frozen.sort();
+    info!(
+        "freezing {} components of gate {} as {}",
+        frozen.len(),
+        gate.name,
+        name
+    );
+    create_snapshot(db, &gate.id, name, &frozen, None).await
+}
+
+/// Copy the component versions of a snapshot into the target gate and record them there
+/// as a snapshot of the same name. Versions the target gate has already are kept.
+pub async fn promote_snapshot(
+    db: &PrismaClient,
+    snapshot: &prisma::gate_snapshot::Data,
+    target: &prisma::gate::Data,
+) -> Result<prisma::gate_snapshot::Data> {
+    if snapshot.gate_id == target.id {
+        return Err(Error::SnapshotConflict(String::from(
+            "a snapshot cannot be promoted to its own gate",
+        )));
+    }
+    // Nothing is copied for a promotion that cannot be recorded
+    check_name_free(db, &target.id, &snapshot.name).await?;
+    let components = snapshot_components(snapshot)?;
+    let mut promoted = Vec::with_capacity(components.len());
+    for frozen in components.iter() {
+        let component = db
+            .component()
+            .find_unique(
+                prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                    frozen.name.clone(),
+                    snapshot.gate_id.clone(),
+                    frozen.version.clone(),
+                    frozen.revision.clone(),
+                ),
+            )
+            .exec()
+            .await?
+            .ok_or(Error::NotFound(format!(
+                "component {} {}-{}",
+                frozen.name, frozen.version, frozen.revision
+            )))?;
+        promoted.push(component);
+    }
+
+    let target_id = target.id.clone();
+    let name = snapshot.name.clone();
+    let snapshot_id = snapshot.id.clone();
+    let count = components.len();
+    let created = db
+        ._transaction()
+        .run::<Error, _, _, _>(|db| async move {
+            for component in promoted {
+                db.component()
+                    .upsert(
+                        prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                            component.name.clone(),
+                            target_id.clone(),
+                            component.version.clone(),
+                            component.revision.clone(),
+                        ),
+                        (
+                            component.name,
+                            component.version,
+                            component.revision,
+                            component.project_url,
+                            prisma::gate::UniqueWhereParam::IdEquals(target_id.clone()),
+                            component.recipe,
+                            component.patches,
+                            component.packages,
+                            vec![
+                                prisma::component::anitya_id::set(component.anitya_id),
+                                prisma::component::repology_id::set(component.repology_id),
+                                prisma::component::purl::set(component.purl),
+                                prisma::component::cpe::set(component.cpe),
+                                prisma::component::scripts::set(component.scripts),
+                                prisma::component::archives::set(component.archives),
+                                prisma::component::summary::set(component.summary),
+                                prisma::component::license::set(component.license),
+                                prisma::component::classification::set(component.classification),
+                                prisma::component::maintainers::set(component.maintainers),
+                            ],
+                        ),
+                        vec![],
+                    )
+                    .exec()
+                    .await?;
+            }
+            create_snapshot(&db, &target_id, &name, &components, Some(snapshot_id)).await
+        })
+        .await?;
+    info!(
+        "promoted snapshot {} with {} components to gate {}",
+        snapshot.name, count, target.name
+    );
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(name: &str, version: &str, revision: &str) -> SnapshotComponent {
+        SnapshotComponent {
+            name: name.to_string(),
+            version: version.to_string(),
+            revision: revision.to_string(),
+        }
+    }
+
+    #[test]
+    fn snapshots_are_compared_by_component() {
+        let testing = vec![
+            component("library/zlib", "1.3.1", "0"),
+            component("library/libxml2", "2.12.6", "1"),
+            component("web/curl", "8.7.1", "0"),
+        ];
+        let stable = vec![
+            component("library/zlib", "1.3.1", "0"),
+            component("library/libxml2", "2.12.5", "0"),
+            component("developer/gcc-13", "13.2.0", "0"),
+        ];
+
+        let comparison = compare_snapshots(&stable, &testing);
+        assert_eq!(comparison.unchanged, 1);
+        assert_eq!(comparison.added, vec![component("web/curl", "8.7.1", "0")]);
+        assert_eq!(
+            comparison.removed,
+            vec![component("developer/gcc-13", "13.2.0", "0")]
+        );
+        assert_eq!(comparison.changed.len(), 1);
+        assert_eq!(comparison.changed[0].from_version, "2.12.5");
+        assert_eq!(comparison.changed[0].to_revision, "1");
+    }
+}
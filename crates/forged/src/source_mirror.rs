@@ -0,0 +1,161 @@
+use opendal::Operator;
+use prisma_client_rust::{raw, PrismaValue};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, instrument, warn};
+use url::Url;
+
+use crate::prisma::PrismaClient;
+use crate::{Error, Result};
+
+/// Prefix the mirrored source archives are stored under, apart from the component files
+/// garbage collection looks at.
+const SOURCE_PREFIX: &str = "sources/";
+
+/// Prefix of the archives being fetched from upstream before their checksum is verified.
+const FETCH_PREFIX: &str = "sources/trans/";
+
+/// Largest archive fetched from upstream, so a declared url cannot fill the blob store.
+const MAX_SOURCE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Whether the checksum is a sha256 in hex like recipes give them.
+pub fn is_sha256(checksum: &str) -> bool {
+    checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Where the archive with the checksum is stored in the blob store.
+pub fn source_path(sha256: &str) -> String {
+    format!("{}{}", SOURCE_PREFIX, sha256.to_ascii_lowercase())
+}
+
+/// Make sure the archive with the checksum is in the blob store, fetching it from `url`
+/// when it is not. Returns the path of the archive. Only urls a stored recipe of the
+/// domain declares for the checksum are fetched, so the mirror cannot be made to request
+/// arbitrary hosts. Archives not matching the checksum are never stored, so the mirror
+/// cannot be poisoned by a changed upstream either.
+#[instrument(skip(op, db))]
+pub async fn mirror_source(
+    op: &Operator,
+    db: &PrismaClient,
+    domain_id: &str,
+    sha256: &str,
+    url: Option<&str>,
+) -> Result<String> {
+    if !is_sha256(sha256) {
+        return Err(Error::InvalidSourceChecksum(sha256.to_string()));
+    }
+    let path = source_path(sha256);
+    if op.is_exist(&path).await? {
+        debug!("serving {} from the mirror", sha256);
+        return Ok(path);
+    }
+    let Some(url) = url else {
+        return Err(Error::NotFound(format!("source {}", sha256)));
+    };
+    // Recipes are matched by the url as written in them
+    if !is_declared(db, domain_id, sha256, url).await? {
+        return Err(Error::UndeclaredSource(url.to_string()));
+    }
+    let url: Url = url.parse()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::UpstreamSource(format!(
+            "{} is not a http url",
+            url.scheme()
+        )));
+    }
+
+    info!("fetching {} from {}", sha256, &url);
+    let fetch_path = format!("{}{}", FETCH_PREFIX, uuid::Uuid::new_v4());
+    // Whatever was written of a failed fetch is removed again
+    let actual = match fetch(op, &fetch_path, &url).await {
+        Ok(actual) => actual,
+        Err(e) => {
+            op.delete(&fetch_path).await?;
+            return Err(e);
+        }
+    };
+    if actual != sha256.to_ascii_lowercase() {
+        warn!("{} does not match {}, got {}", &url, sha256, &actual);
+        op.delete(&fetch_path).await?;
+        return Err(Error::UpstreamSource(format!(
+            "{} has checksum {} not {}",
+            url, actual, sha256
+        )));
+    }
+    // Another request may have mirrored the archive in the meantime, both are the same
+    if !op.is_exist(&path).await? {
+        op.copy(&fetch_path, &path).await?;
+    }
+    op.delete(&fetch_path).await?;
+    Ok(path)
+}
+
+/// Whether a component of the domain has an archive source with the url and checksum.
+async fn is_declared(db: &PrismaClient, domain_id: &str, sha256: &str, url: &str) -> Result<bool> {
+    #[derive(Deserialize)]
+    struct Declaring {
+        #[allow(dead_code)]
+        name: String,
+    }
+
+    let archive = serde_json::json!({
+        "sources": [{ "sources": [{ "Archive": { "src": url, "sha256": sha256 } }] }]
+    });
+    let declaring: Vec<Declaring> = db
+        ._query_raw(raw!(
+            r#"SELECT c."name" FROM "Component" c
+            JOIN "Gate" g ON g."id" = c."gateId"
+            JOIN "Publisher" p ON p."id" = g."publisherId"
+            WHERE p."domainId" = {}::uuid AND c."recipe" @> {}::jsonb
+            LIMIT 1"#,
+            PrismaValue::String(domain_id.to_string()),
+            PrismaValue::String(archive.to_string())
+        ))
+        .exec()
+        .await?;
+    Ok(!declaring.is_empty())
+}
+
+/// Stream the archive at `url` into `fetch_path`, returning its sha256.
+async fn fetch(op: &Operator, fetch_path: &str, url: &Url) -> Result<String> {
+    let upstream_error = |e: reqwest::Error| Error::UpstreamSource(e.to_string());
+    let too_large =
+        || Error::UpstreamSource(format!("{} is larger than {} bytes", url, MAX_SOURCE_SIZE));
+    let mut response = reqwest::get(url.clone())
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(upstream_error)?;
+    if response.content_length().unwrap_or(0) > MAX_SOURCE_SIZE {
+        return Err(too_large());
+    }
+    let mut writer = op.writer_with(fetch_path).chunk(8 * 1024 * 1024).await?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = response.chunk().await.map_err(upstream_error)? {
+        size += chunk.len() as u64;
+        if size > MAX_SOURCE_SIZE {
+            return Err(too_large());
+        }
+        hasher.update(&chunk);
+        writer.write(chunk).await?;
+    }
+    writer.close().await?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_sha256_checksums_are_mirrored() {
+        let sha256 = "9A93B2B7DFDAC77CEBA5A558A580E74667DD6FEDE4585B91EEFB60F03B72DF38";
+        assert!(is_sha256(sha256));
+        assert_eq!(
+            source_path(sha256),
+            "sources/9a93b2b7dfdac77ceba5a558a580e74667dd6fede4585b91eefb60f03b72df38"
+        );
+        assert!(!is_sha256("9a93b2b7"));
+        assert!(!is_sha256(&"../".repeat(22)[..64]));
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use pasetors::claims::Claims;
+use pasetors::keys::AsymmetricSecretKey;
+use pasetors::version4::V4;
+use uuid::Uuid;
+
+use crate::Result;
+
+pub const ACCESS_TOKEN_HOURS: i64 = 8;
+pub const REFRESH_TOKEN_DAYS: i64 = 90;
+pub const API_KEY_MAX_DAYS: i64 = 730;
+/// Scope of API keys that may use every API area
+pub const ALL_SCOPES: &str = "*";
+
+const KIND_CLAIM: &str = "kind";
+const SCOPES_CLAIM: &str = "scopes";
+
+/// What a token may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Short lived token to call the API
+    Access,
+    /// Token that can be exchanged once for a new access and refresh token
+    Refresh,
+    /// Long lived token of an API key, only valid while the key is not revoked
+    ApiKey,
+}
+
+impl TokenKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TokenKind::Access => "access",
+            TokenKind::Refresh => "refresh",
+            TokenKind::ApiKey => "api_key",
+        }
+    }
+
+    /// The kind of a verified token. Tokens issued before kinds existed are access tokens.
+    pub fn of(claims: &Claims) -> Option<Self> {
+        match claims.get_claim(KIND_CLAIM).map(|kind| kind.as_str()) {
+            None | Some(Some("access")) => Some(TokenKind::Access),
+            Some(Some("refresh")) => Some(TokenKind::Refresh),
+            Some(Some("api_key")) => Some(TokenKind::ApiKey),
+            Some(_) => None,
+        }
+    }
+}
+
+/// The tokens an actor gets on login and on refresh.
+#[derive(Debug, Clone)]
+pub struct LoginTokens {
+    pub access_token: String,
+    pub access_expires_at: DateTime<Utc>,
+    pub refresh_token: String,
+    pub refresh_expires_at: DateTime<Utc>,
+}
+
+fn claims(
+    handle: &str,
+    domain: &str,
+    kind: TokenKind,
+    now: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+) -> Result<Claims> {
+    let mut claims = Claims::new()?;
+    claims.issuer(domain)?;
+    claims.subject(handle)?;
+    claims.audience(domain)?;
+    claims.expiration(expires_at.format("%+").to_string().as_str())?;
+    claims.not_before(now.format("%+").to_string().as_str())?;
+    claims.issued_at(now.format("%+").to_string().as_str())?;
+    claims.add_additional(KIND_CLAIM, kind.as_str())?;
+    Ok(claims)
+}
+
+pub fn issue_login_tokens(
+    handle: &str,
+    domain: &str,
+    display_name: &str,
+    key: &AsymmetricSecretKey<V4>,
+) -> Result<LoginTokens> {
+    let now = Utc::now();
+    let access_expires_at = now + TimeDelta::hours(ACCESS_TOKEN_HOURS);
+    let refresh_expires_at = now + TimeDelta::days(REFRESH_TOKEN_DAYS);
+
+    let mut access_claims = claims(handle, domain, TokenKind::Access, now, access_expires_at)?;
+    access_claims.add_additional("displayName", display_name)?;
+
+    let mut refresh_claims = claims(handle, domain, TokenKind::Refresh, now, refresh_expires_at)?;
+    refresh_claims.add_additional("displayName", display_name)?;
+    // The id lets a used refresh token be revoked
+    refresh_claims.token_identifier(&Uuid::new_v4().to_string())?;
+
+    Ok(LoginTokens {
+        access_token: pasetors::public::sign(key, &access_claims, None, None)?,
+        access_expires_at,
+        refresh_token: pasetors::public::sign(key, &refresh_claims, None, None)?,
+        refresh_expires_at,
+    })
+}
+
+/// Sign the token of the API key `key_id`. The token only carries the id, the key in the
+/// database decides whether it is still valid.
+pub fn issue_api_key_token(
+    handle: &str,
+    domain: &str,
+    key_id: &str,
+    scopes: &[String],
+    expires_at: DateTime<Utc>,
+    key: &AsymmetricSecretKey<V4>,
+) -> Result<String> {
+    let mut claims = claims(handle, domain, TokenKind::ApiKey, Utc::now(), expires_at)?;
+    claims.token_identifier(key_id)?;
+    claims.add_additional(SCOPES_CLAIM, scopes.to_vec())?;
+    Ok(pasetors::public::sign(key, &claims, None, None)?)
+}
+
+/// The API area of a path like `components` for `/api/v1/components/search`.
+fn api_area(path: &str) -> &str {
+    path.trim_start_matches("/api/v1/")
+        .split('/')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Whether an API key with the scopes may call the path. API keys never manage API keys
+/// or refresh tokens, so a leaked key cannot be used to mint more of them.
+pub fn scope_allows(scopes: &[String], path: &str) -> bool {
+    let area = api_area(path);
+    area != "auth" && scopes.iter().any(|s| s == ALL_SCOPES || s == area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_keys_are_limited_to_their_areas() {
+        let scopes = vec![String::from("components"), String::from("jobs")];
+        assert!(scope_allows(&scopes, "/api/v1/components/search"));
+        assert!(scope_allows(&scopes, "/api/v1/jobs/0b6c1f3e"));
+        assert!(!scope_allows(&scopes, "/api/v1/gates/"));
+        assert!(scope_allows(&[String::from("*")], "/api/v1/gates/"));
+        assert!(!scope_allows(&[String::from("*")], "/api/v1/auth/keys"));
+    }
+}
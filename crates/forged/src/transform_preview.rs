@@ -0,0 +1,249 @@
+use component::{DependencyKind, Recipe};
+use mogrify::Mogrify;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A gate metadata transform as pkgdev applies it to the configure options of recipes:
+/// options containing `matcher` are replaced by `replacement` or dropped.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct MetadataTransform {
+    pub matcher: String,
+    #[serde(default)]
+    pub replacement: String,
+    #[serde(default)]
+    pub drop: bool,
+}
+
+/// How the output of one package or the build options of one recipe would change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct TransformPreviewChange {
+    pub component: String,
+    /// The package whose manifest changes, none for changed configure options
+    pub package: Option<String>,
+    /// Manifest lines or options only the current transforms produce
+    pub removed: Vec<String>,
+    /// Manifest lines or options only the proposed transforms produce
+    pub added: Vec<String>,
+}
+
+/// The metadata actions pkgdev writes into the base manifest of every package of the
+/// recipe, keyed by package name. The files of the packages only exist on the builders,
+/// so they are not part of it.
+pub fn metadata_manifests(recipe: &Recipe, version: &str, branch: &str) -> Vec<(String, String)> {
+    let names = if recipe.package_sections.is_empty() {
+        vec![recipe.name.clone()]
+    } else {
+        recipe
+            .package_sections
+            .iter()
+            .map(|p| p.name.clone().unwrap_or(recipe.name.clone()))
+            .collect()
+    };
+
+    names
+        .into_iter()
+        .map(|name| {
+            let mut actions = vec![set_action(
+                "pkg.fmri",
+                &format!(
+                    "pkg:/{}@{},{}-{}.{}",
+                    name,
+                    recipe.version.clone().unwrap_or(String::from("0.5.11")),
+                    version,
+                    branch,
+                    recipe.revision.clone().unwrap_or(String::from("1"))
+                ),
+            )];
+            if let Some(summary) = &recipe.summary {
+                actions.push(set_action("pkg.summary", summary));
+            }
+            if let Some(classification) = &recipe.classification {
+                actions.push(set_action(
+                    "info.classification",
+                    &format!("org.opensolaris.category.2008:{}", classification),
+                ));
+            }
+            if let Some(project_url) = &recipe.project_url {
+                actions.push(set_action("info.upstream-url", project_url));
+            }
+            if let (Some(file), Some(license)) = (&recipe.license_file, &recipe.license) {
+                actions.push(mogrify::Action {
+                    name: String::from("license"),
+                    payload: Some(file.clone()),
+                    attributes: vec![(String::from("license"), license.clone())],
+                });
+            }
+            for dependency in recipe.dependencies.iter().filter(|d| !d.dev) {
+                let kind = match dependency.kind {
+                    DependencyKind::Require => "require",
+                    DependencyKind::Incorporate => "incorporate",
+                    DependencyKind::Optional => "optional",
+                };
+                actions.push(mogrify::Action {
+                    name: String::from("depend"),
+                    payload: None,
+                    attributes: vec![
+                        (String::from("fmri"), dependency.name.clone()),
+                        (String::from("type"), String::from(kind)),
+                    ],
+                });
+            }
+
+            let manifest = actions
+                .iter()
+                .map(|a| a.to_string() + "\n")
+                .collect::<String>();
+            (name, manifest)
+        })
+        .collect()
+}
+
+fn set_action(name: &str, value: &str) -> mogrify::Action {
+    mogrify::Action {
+        name: String::from("set"),
+        payload: None,
+        attributes: vec![
+            (String::from("name"), String::from(name)),
+            (String::from("value"), String::from(value)),
+        ],
+    }
+}
+
+/// Apply `transforms` to `manifest`. Includes are left out, the files they refer to
+/// only exist on the builders.
+pub fn mogrify_manifest(
+    manifest: &str,
+    transforms: &[String],
+) -> mogrify::MogrifyResult<Vec<String>> {
+    let mut mogrify = Mogrify::new(vec![]);
+    mogrify.read_str("manifest", manifest)?;
+    for transform in transforms {
+        let without_includes = transform
+            .lines()
+            .filter(|l| !l.trim_start().starts_with("<include"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        mogrify.read_str("transform", &without_includes)?;
+    }
+    Ok(mogrify
+        .render()?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// The configure options of the recipe after the metadata transforms.
+pub fn transform_options(recipe: &Recipe, transforms: &[MetadataTransform]) -> Vec<String> {
+    recipe
+        .build_sections
+        .iter()
+        .filter_map(|s| s.configure.as_ref())
+        .flat_map(|c| c.options.iter())
+        .filter_map(
+            |o| match transforms.iter().find(|t| o.option.contains(&t.matcher)) {
+                Some(t) if t.drop => None,
+                Some(t) => Some(t.replacement.clone()),
+                None => Some(o.option.clone()),
+            },
+        )
+        .collect()
+}
+
+/// The lines of `current` missing in `proposed` and the other way around, each in the
+/// order they appear.
+pub fn diff_lines(current: &[String], proposed: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut added = proposed.to_vec();
+    let mut removed = vec![];
+    for line in current {
+        match added.iter().position(|l| l == line) {
+            Some(position) => {
+                added.remove(position);
+            }
+            None => removed.push(line.clone()),
+        }
+    }
+    (removed, added)
+}
+
+#[cfg(test)]
+mod tests {
+    use component::{
+        BuildOptionNode, BuildSectionBuilder, ConfigureBuildSectionBuilder, RecipeBuilder,
+    };
+
+    use super::*;
+
+    fn recipe() -> Recipe {
+        RecipeBuilder::default()
+            .name("library/foo")
+            .version("1.2")
+            .summary("The foo library")
+            .build_sections(vec![BuildSectionBuilder::default()
+                .configure(
+                    ConfigureBuildSectionBuilder::default()
+                        .options(vec![
+                            BuildOptionNode {
+                                option: String::from("--with-ssl=/usr/ssl"),
+                            },
+                            BuildOptionNode {
+                                option: String::from("--disable-static"),
+                            },
+                        ])
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn previews_changed_metadata() {
+        let manifests = metadata_manifests(&recipe(), "2024.0.0", "0");
+        assert_eq!(manifests.len(), 1);
+        let (name, manifest) = &manifests[0];
+        assert_eq!(name, "library/foo");
+
+        let current = mogrify_manifest(manifest, &[]).unwrap();
+        let proposed = mogrify_manifest(
+            manifest,
+            &[String::from(
+                "<transform set name=pkg.summary -> edit value foo FOO>\n<include gate.mog>",
+            )],
+        )
+        .unwrap();
+        assert_eq!(
+            diff_lines(&current, &proposed),
+            (
+                vec![String::from(
+                    "set name=pkg.summary value=\"The foo library\""
+                )],
+                vec![String::from(
+                    "set name=pkg.summary value=\"The FOO library\""
+                )]
+            )
+        );
+    }
+
+    #[test]
+    fn metadata_transforms_replace_and_drop_options() {
+        let transforms = vec![
+            MetadataTransform {
+                matcher: String::from("--with-ssl"),
+                replacement: String::from("--with-openssl"),
+                drop: false,
+            },
+            MetadataTransform {
+                matcher: String::from("static"),
+                replacement: String::new(),
+                drop: true,
+            },
+        ];
+        assert_eq!(
+            transform_options(&recipe(), &transforms),
+            vec![String::from("--with-openssl")]
+        );
+    }
+}
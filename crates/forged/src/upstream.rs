@@ -0,0 +1,309 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use component::Recipe;
+use serde::Deserialize;
+use tracing::{debug, info, instrument, warn};
+
+use crate::periodic::{self, hours};
+use crate::prisma::{self, PrismaClient};
+use crate::quality::{compare_versions, latest_components};
+use crate::Result;
+
+const ANITYA_VERSIONS_URL: &str = "https://release-monitoring.org/api/v2/versions/";
+const LIBRARIES_IO_SEARCH_URL: &str = "https://libraries.io/api/search";
+
+/// Where the latest upstream version of a component was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpstreamSource {
+    Anitya,
+    LibrariesIo,
+}
+
+impl Display for UpstreamSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpstreamSource::Anitya => write!(f, "anitya"),
+            UpstreamSource::LibrariesIo => write!(f, "libraries.io"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AnityaVersions {
+    latest_version: Option<String>,
+}
+
+/// The latest version release-monitoring.org knows of the project.
+pub(crate) async fn anitya_version(
+    client: &reqwest::Client,
+    anitya_id: &str,
+) -> Result<Option<String>> {
+    let body = client
+        .get(ANITYA_VERSIONS_URL)
+        .query(&[("project_id", anitya_id)])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let versions: AnityaVersions = serde_json::from_str(&body)?;
+    Ok(versions.latest_version)
+}
+
+#[derive(Deserialize)]
+struct LibrariesIoProject {
+    name: String,
+    latest_release_number: Option<String>,
+}
+
+/// Search results are fuzzy, only a project with exactly the name counts.
+fn libraries_io_latest(projects: Vec<LibrariesIoProject>, project: &str) -> Option<String> {
+    projects
+        .into_iter()
+        .find(|p| p.name.eq_ignore_ascii_case(project))
+        .and_then(|p| p.latest_release_number)
+}
+
+async fn libraries_io_version(
+    client: &reqwest::Client,
+    api_key: &str,
+    project: &str,
+) -> Result<Option<String>> {
+    let body = client
+        .get(LIBRARIES_IO_SEARCH_URL)
+        .query(&[("q", project), ("api_key", api_key)])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let projects: Vec<LibrariesIoProject> = serde_json::from_str(&body)?;
+    Ok(libraries_io_latest(projects, project))
+}
+
+/// The name of the component upstream. Recipes name it if it differs, otherwise the last
+/// part of the component name is used.
+fn upstream_project_name(component: &str, recipe: &Recipe) -> String {
+    recipe.project_name.clone().unwrap_or_else(|| {
+        component
+            .rsplit('/')
+            .next()
+            .unwrap_or(component)
+            .to_string()
+    })
+}
+
+/// A component of a gate to look up upstream.
+struct UpstreamTarget {
+    gate_id: String,
+    name: String,
+    version: String,
+    anitya_id: Option<String>,
+    project: String,
+}
+
+/// The upstream version of a component as last checked.
+struct UpstreamCheck {
+    target: UpstreamTarget,
+    upstream_version: String,
+    source: UpstreamSource,
+}
+
+impl UpstreamCheck {
+    fn outdated(&self) -> bool {
+        compare_versions(&self.target.version, &self.upstream_version) == Some(Ordering::Less)
+    }
+}
+
+/// What one check of the upstream versions found.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UpstreamReport {
+    pub checked: usize,
+    /// Components of a gate with a newer upstream release as `gate: name version -> upstream`
+    pub outdated: Vec<String>,
+}
+
+impl Display for UpstreamReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "checked components: {}", self.checked)?;
+        write!(f, "outdated components: {}", self.outdated.len())?;
+        for outdated in self.outdated.iter() {
+            write!(f, "\n  {}", outdated)?;
+        }
+        Ok(())
+    }
+}
+
+async fn upstream_targets(db: &PrismaClient) -> Result<Vec<UpstreamTarget>> {
+    let mut targets = vec![];
+    for gate in db.gate().find_many(vec![]).exec().await? {
+        let components = db
+            .component()
+            .find_many(vec![prisma::component::gate_id::equals(gate.id.clone())])
+            .exec()
+            .await?;
+        for component in latest_components(components) {
+            let recipe = match serde_json::from_value::<Recipe>(component.recipe) {
+                Ok(recipe) => recipe,
+                Err(e) => {
+                    warn!("recipe of {} is malformed: {}", component.name, e);
+                    continue;
+                }
+            };
+            targets.push(UpstreamTarget {
+                gate_id: gate.id.clone(),
+                project: upstream_project_name(&component.name, &recipe),
+                name: component.name,
+                version: component.version,
+                anitya_id: component.anitya_id,
+            });
+        }
+    }
+    Ok(targets)
+}
+
+/// Look up the latest upstream version on release-monitoring.org and fall back to
+/// libraries.io if the component is not monitored there and an API key is configured.
+async fn latest_upstream_version(
+    client: &reqwest::Client,
+    libraries_io_api_key: Option<&str>,
+    target: &UpstreamTarget,
+) -> Result<Option<(String, UpstreamSource)>> {
+    if let Some(anitya_id) = &target.anitya_id {
+        if let Some(version) = anitya_version(client, anitya_id).await? {
+            return Ok(Some((version, UpstreamSource::Anitya)));
+        }
+    }
+    match libraries_io_api_key {
+        Some(api_key) => Ok(libraries_io_version(client, api_key, &target.project)
+            .await?
+            .map(|version| (version, UpstreamSource::LibrariesIo))),
+        None => Ok(None),
+    }
+}
+
+async fn record_upstream_check(db: &PrismaClient, check: &UpstreamCheck) -> Result<()> {
+    let target = &check.target;
+    db.upstream_release()
+        .upsert(
+            prisma::upstream_release::UniqueWhereParam::GateIdNameEquals(
+                target.gate_id.clone(),
+                target.name.clone(),
+            ),
+            prisma::upstream_release::create(
+                prisma::gate::UniqueWhereParam::IdEquals(target.gate_id.clone()),
+                target.name.clone(),
+                target.version.clone(),
+                check.upstream_version.clone(),
+                check.source.to_string(),
+                check.outdated(),
+                vec![],
+            ),
+            vec![
+                prisma::upstream_release::version::set(target.version.clone()),
+                prisma::upstream_release::upstream_version::set(check.upstream_version.clone()),
+                prisma::upstream_release::source::set(check.source.to_string()),
+                prisma::upstream_release::outdated::set(check.outdated()),
+                prisma::upstream_release::checked_at::set(chrono::Utc::now().fixed_offset()),
+            ],
+        )
+        .exec()
+        .await?;
+    Ok(())
+}
+
+/// Look up the latest upstream version of the latest version of every component in every
+/// gate and record it. Components that cannot be looked up are logged and skipped.
+#[instrument(skip(db, libraries_io_api_key))]
+pub async fn check_upstream_versions(
+    db: &PrismaClient,
+    libraries_io_api_key: Option<&str>,
+) -> Result<UpstreamReport> {
+    let client = reqwest::Client::new();
+    let targets = upstream_targets(db).await?;
+
+    let mut checks = vec![];
+    for target in targets {
+        match latest_upstream_version(&client, libraries_io_api_key, &target).await {
+            Ok(Some((upstream_version, source))) => checks.push(UpstreamCheck {
+                target,
+                upstream_version,
+                source,
+            }),
+            Ok(None) => debug!("no upstream version found for {}", target.name),
+            Err(e) => warn!("could not get upstream version of {}: {}", target.name, e),
+        }
+    }
+
+    let mut report = UpstreamReport::default();
+    for check in checks {
+        record_upstream_check(db, &check).await?;
+        report.checked += 1;
+        if check.outdated() {
+            report.outdated.push(format!(
+                "{}: {} {} -> {}",
+                check.target.gate_id,
+                check.target.name,
+                check.target.version,
+                check.upstream_version
+            ));
+        }
+    }
+    info!(
+        "checked {} components upstream, {} are outdated",
+        report.checked,
+        report.outdated.len()
+    );
+    Ok(report)
+}
+
+/// Check the upstream versions every `interval_hours` until forged stops. Failed runs are
+/// logged and tried again at the next interval.
+pub async fn run_periodically(
+    db: Arc<PrismaClient>,
+    interval_hours: u64,
+    libraries_io_api_key: Option<String>,
+) {
+    let (db, libraries_io_api_key) = (&db, libraries_io_api_key.as_deref());
+    periodic::run_periodically("upstream release monitoring", hours(interval_hours), || {
+        check_upstream_versions(db, libraries_io_api_key)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use component::RecipeBuilder;
+
+    use super::*;
+
+    #[test]
+    fn libraries_io_needs_exact_name() -> miette::Result<()> {
+        let projects: Vec<LibrariesIoProject> = serde_json::from_str(
+            r#"[
+                {"name": "zlib-ng", "latest_release_number": "2.2.2"},
+                {"name": "Zlib", "latest_release_number": "1.3.1"}
+            ]"#,
+        )
+        .map_err(|e| miette::miette!("{}", e))?;
+        assert_eq!(
+            libraries_io_latest(projects, "zlib"),
+            Some(String::from("1.3.1"))
+        );
+        assert_eq!(libraries_io_latest(vec![], "zlib"), None);
+
+        let recipe = RecipeBuilder::default().name("library/zlib").build()?;
+        assert_eq!(upstream_project_name("library/zlib", &recipe), "zlib");
+        let recipe = RecipeBuilder::default()
+            .name("library/libxml2")
+            .project_name("libxml2-upstream")
+            .build()?;
+        assert_eq!(
+            upstream_project_name("library/libxml2", &recipe),
+            "libxml2-upstream"
+        );
+        Ok(())
+    }
+}
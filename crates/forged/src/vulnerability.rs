@@ -0,0 +1,485 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{info, instrument, warn};
+
+use crate::periodic::{self, hours};
+use crate::prisma::{self, PrismaClient};
+use crate::quality::latest_components;
+use crate::Result;
+
+const OSV_QUERY_URL: &str = "https://api.osv.dev/v1/query";
+const OSV_VULNERABILITY_URL: &str = "https://osv.dev/vulnerability/";
+const NVD_CVES_URL: &str = "https://services.nvd.nist.gov/rest/json/cves/2.0";
+const NVD_VULNERABILITY_URL: &str = "https://nvd.nist.gov/vuln/detail/";
+
+/// A security advisory affecting a component version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Advisory {
+    pub id: String,
+    /// Where the advisory was found, osv or nvd
+    pub source: &'static str,
+    pub aliases: Vec<String>,
+    pub summary: Option<String>,
+    pub severity: Option<String>,
+    pub fixed_version: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OsvResponse {
+    #[serde(default)]
+    vulns: Vec<OsvVulnerability>,
+}
+
+#[derive(Deserialize)]
+struct OsvVulnerability {
+    id: String,
+    summary: Option<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    affected: Vec<OsvAffected>,
+    #[serde(default)]
+    severity: Vec<OsvSeverity>,
+    database_specific: Option<OsvDatabaseSpecific>,
+}
+
+#[derive(Deserialize)]
+struct OsvAffected {
+    #[serde(default)]
+    ranges: Vec<OsvRange>,
+}
+
+#[derive(Deserialize)]
+struct OsvRange {
+    #[serde(default)]
+    events: Vec<OsvEvent>,
+}
+
+#[derive(Deserialize)]
+struct OsvEvent {
+    fixed: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OsvSeverity {
+    score: String,
+}
+
+#[derive(Deserialize)]
+struct OsvDatabaseSpecific {
+    severity: Option<String>,
+}
+
+impl From<OsvVulnerability> for Advisory {
+    fn from(value: OsvVulnerability) -> Self {
+        let fixed_version = value
+            .affected
+            .iter()
+            .flat_map(|a| a.ranges.iter())
+            .flat_map(|r| r.events.iter())
+            .find_map(|e| e.fixed.clone());
+        // Databases like GHSA rate the severity, others only have a CVSS vector
+        let severity = value
+            .database_specific
+            .and_then(|d| d.severity)
+            .or_else(|| value.severity.into_iter().next().map(|s| s.score));
+        Self {
+            url: Some(format!("{}{}", OSV_VULNERABILITY_URL, value.id)),
+            id: value.id,
+            source: "osv",
+            aliases: value.aliases,
+            summary: value.summary,
+            severity,
+            fixed_version,
+        }
+    }
+}
+
+/// The advisories osv.dev has for the version of the package.
+async fn osv_advisories(
+    client: &reqwest::Client,
+    purl: &str,
+    version: &str,
+) -> Result<Vec<Advisory>> {
+    let body = client
+        .post(OSV_QUERY_URL)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(json!({"version": version, "package": {"purl": purl}}).to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response: OsvResponse = serde_json::from_str(&body)?;
+    Ok(response.vulns.into_iter().map(Advisory::from).collect())
+}
+
+#[derive(Deserialize)]
+struct NvdResponse {
+    #[serde(default)]
+    vulnerabilities: Vec<NvdVulnerability>,
+}
+
+#[derive(Deserialize)]
+struct NvdVulnerability {
+    cve: NvdCve,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdCve {
+    id: String,
+    #[serde(default)]
+    descriptions: Vec<NvdDescription>,
+    metrics: Option<NvdMetrics>,
+}
+
+#[derive(Deserialize)]
+struct NvdDescription {
+    lang: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdMetrics {
+    #[serde(default)]
+    cvss_metric_v31: Vec<NvdCvssMetric>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdCvssMetric {
+    cvss_data: NvdCvssData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdCvssData {
+    base_severity: Option<String>,
+}
+
+impl From<NvdVulnerability> for Advisory {
+    fn from(value: NvdVulnerability) -> Self {
+        let cve = value.cve;
+        Self {
+            url: Some(format!("{}{}", NVD_VULNERABILITY_URL, cve.id)),
+            id: cve.id,
+            source: "nvd",
+            aliases: vec![],
+            summary: cve
+                .descriptions
+                .into_iter()
+                .find(|d| d.lang == "en")
+                .map(|d| d.value),
+            severity: cve
+                .metrics
+                .and_then(|m| m.cvss_metric_v31.into_iter().next())
+                .and_then(|m| m.cvss_data.base_severity),
+            fixed_version: None,
+        }
+    }
+}
+
+/// The CPE name of a version of the product. Components store their CPE up to the
+/// product like `cpe:2.3:a:zlib:zlib`, anything after it is replaced.
+fn cpe_name(cpe: &str, version: &str) -> Option<String> {
+    let parts: Vec<&str> = cpe.split(':').collect();
+    if parts.len() < 5 || parts[0] != "cpe" || parts[1] != "2.3" {
+        return None;
+    }
+    Some(format!(
+        "{}:{}:*:*:*:*:*:*:*",
+        parts[..5].join(":"),
+        version
+    ))
+}
+
+/// The CVEs the NVD lists for the version of the product.
+async fn nvd_advisories(
+    client: &reqwest::Client,
+    api_key: Option<&str>,
+    cpe: &str,
+    version: &str,
+) -> Result<Vec<Advisory>> {
+    let Some(cpe_name) = cpe_name(cpe, version) else {
+        warn!("ignoring malformed CPE {}", cpe);
+        return Ok(vec![]);
+    };
+    let mut request = client
+        .get(NVD_CVES_URL)
+        .query(&[("cpeName", cpe_name.as_str())]);
+    if let Some(api_key) = api_key {
+        request = request.header("apiKey", api_key);
+    }
+    let body = request.send().await?.error_for_status()?.text().await?;
+    let response: NvdResponse = serde_json::from_str(&body)?;
+    Ok(response
+        .vulnerabilities
+        .into_iter()
+        .map(Advisory::from)
+        .collect())
+}
+
+/// OSV advisories name the CVEs they cover as aliases, those are not reported twice.
+fn merge_advisories(osv: Vec<Advisory>, nvd: Vec<Advisory>) -> Vec<Advisory> {
+    let known: HashSet<String> = osv
+        .iter()
+        .flat_map(|a| std::iter::once(a.id.clone()).chain(a.aliases.iter().cloned()))
+        .collect();
+    let mut advisories = osv;
+    advisories.extend(nvd.into_iter().filter(|a| !known.contains(&a.id)));
+    advisories
+}
+
+/// A component version with a purl or CPE to look up advisories for.
+struct VulnerabilityTarget {
+    name: String,
+    gate_id: String,
+    version: String,
+    revision: String,
+    purl: Option<String>,
+    cpe: Option<String>,
+}
+
+async fn vulnerability_targets(db: &PrismaClient) -> Result<Vec<VulnerabilityTarget>> {
+    let mut targets = vec![];
+    for gate in db.gate().find_many(vec![]).exec().await? {
+        let components = db
+            .component()
+            .find_many(vec![prisma::component::gate_id::equals(gate.id.clone())])
+            .exec()
+            .await?;
+        for component in latest_components(components) {
+            if component.purl.is_none() && component.cpe.is_none() {
+                continue;
+            }
+            targets.push(VulnerabilityTarget {
+                name: component.name,
+                gate_id: component.gate_id,
+                version: component.version,
+                revision: component.revision,
+                purl: component.purl,
+                cpe: component.cpe,
+            });
+        }
+    }
+    Ok(targets)
+}
+
+async fn component_advisories(
+    client: &reqwest::Client,
+    nvd_api_key: Option<&str>,
+    target: &VulnerabilityTarget,
+) -> Result<Vec<Advisory>> {
+    let osv = match &target.purl {
+        Some(purl) => osv_advisories(client, purl, &target.version).await?,
+        None => vec![],
+    };
+    let nvd = match &target.cpe {
+        Some(cpe) => nvd_advisories(client, nvd_api_key, cpe, &target.version).await?,
+        None => vec![],
+    };
+    Ok(merge_advisories(osv, nvd))
+}
+
+/// Replace the recorded advisories of the component version. Advisories that were
+/// withdrawn are removed, known ones keep the time they were first detected.
+async fn record_advisories(
+    db: &PrismaClient,
+    target: &VulnerabilityTarget,
+    advisories: &[Advisory],
+) -> Result<()> {
+    db.vulnerability()
+        .delete_many(vec![
+            prisma::vulnerability::component_name::equals(target.name.clone()),
+            prisma::vulnerability::gate_id::equals(target.gate_id.clone()),
+            prisma::vulnerability::component_version::equals(target.version.clone()),
+            prisma::vulnerability::component_revision::equals(target.revision.clone()),
+            prisma::vulnerability::advisory_id::not_in_vec(
+                advisories.iter().map(|a| a.id.clone()).collect(),
+            ),
+        ])
+        .exec()
+        .await?;
+    for advisory in advisories {
+        let params = || {
+            vec![
+                prisma::vulnerability::aliases::set(advisory.aliases.clone()),
+                prisma::vulnerability::summary::set(advisory.summary.clone()),
+                prisma::vulnerability::severity::set(advisory.severity.clone()),
+                prisma::vulnerability::fixed_version::set(advisory.fixed_version.clone()),
+                prisma::vulnerability::url::set(advisory.url.clone()),
+            ]
+        };
+        db.vulnerability()
+            .upsert(
+                prisma::vulnerability::UniqueWhereParam::ComponentNameGateIdComponentVersionComponentRevisionAdvisoryIdEquals(
+                    target.name.clone(),
+                    target.gate_id.clone(),
+                    target.version.clone(),
+                    target.revision.clone(),
+                    advisory.id.clone(),
+                ),
+                prisma::vulnerability::create(
+                    prisma::component::UniqueWhereParam::NameGateIdVersionRevisionEquals(
+                        target.name.clone(),
+                        target.gate_id.clone(),
+                        target.version.clone(),
+                        target.revision.clone(),
+                    ),
+                    advisory.id.clone(),
+                    advisory.source.to_string(),
+                    params(),
+                ),
+                params(),
+            )
+            .exec()
+            .await?;
+    }
+    Ok(())
+}
+
+/// What one check for advisories found.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VulnerabilityReport {
+    pub checked: usize,
+    /// Component versions with advisories as `gate: name@version-revision advisories`
+    pub vulnerable: Vec<String>,
+}
+
+impl Display for VulnerabilityReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "checked components: {}", self.checked)?;
+        write!(f, "vulnerable components: {}", self.vulnerable.len())?;
+        for vulnerable in self.vulnerable.iter() {
+            write!(f, "\n  {}", vulnerable)?;
+        }
+        Ok(())
+    }
+}
+
+/// Look up the advisories of the latest version of every component in every gate that
+/// has a purl or CPE and record them. Components that cannot be looked up are logged and
+/// skipped.
+#[instrument(skip(db, nvd_api_key))]
+pub async fn check_vulnerabilities(
+    db: &PrismaClient,
+    nvd_api_key: Option<&str>,
+) -> Result<VulnerabilityReport> {
+    let client = reqwest::Client::new();
+    let targets = vulnerability_targets(db).await?;
+
+    let mut report = VulnerabilityReport::default();
+    for target in targets {
+        let advisories = match component_advisories(&client, nvd_api_key, &target).await {
+            Ok(advisories) => advisories,
+            Err(e) => {
+                warn!("could not look up advisories of {}: {}", target.name, e);
+                continue;
+            }
+        };
+        record_advisories(db, &target, &advisories).await?;
+        report.checked += 1;
+        if !advisories.is_empty() {
+            report.vulnerable.push(format!(
+                "{}: {}@{}-{} {}",
+                target.gate_id,
+                target.name,
+                target.version,
+                target.revision,
+                advisories
+                    .iter()
+                    .map(|a| a.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+    info!(
+        "checked {} components for advisories, {} are vulnerable",
+        report.checked,
+        report.vulnerable.len()
+    );
+    Ok(report)
+}
+
+/// Check for advisories every `interval_hours` until forged stops. Failed runs are
+/// logged and tried again at the next interval.
+pub async fn run_periodically(
+    db: Arc<PrismaClient>,
+    interval_hours: u64,
+    nvd_api_key: Option<String>,
+) {
+    let (db, nvd_api_key) = (&db, nvd_api_key.as_deref());
+    periodic::run_periodically("vulnerability tracking", hours(interval_hours), || {
+        check_vulnerabilities(db, nvd_api_key)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpe_names_of_versions() {
+        assert_eq!(
+            cpe_name("cpe:2.3:a:zlib:zlib", "1.3.1"),
+            Some(String::from("cpe:2.3:a:zlib:zlib:1.3.1:*:*:*:*:*:*:*"))
+        );
+        assert_eq!(
+            cpe_name("cpe:2.3:a:zlib:zlib:1.2:*:*:*:*:*:*:*", "1.3.1"),
+            Some(String::from("cpe:2.3:a:zlib:zlib:1.3.1:*:*:*:*:*:*:*"))
+        );
+        assert_eq!(cpe_name("cpe:/a:zlib:zlib", "1.3.1"), None);
+    }
+
+    #[test]
+    fn osv_advisories_cover_their_cves() -> std::result::Result<(), serde_json::Error> {
+        let osv: OsvResponse = serde_json::from_str(
+            r#"{"vulns": [{
+                "id": "GHSA-jc36-42cf-vqwj",
+                "summary": "zlib heap buffer overflow",
+                "aliases": ["CVE-2022-37434"],
+                "affected": [{"ranges": [{"events": [{"introduced": "0"}, {"fixed": "1.2.13"}]}]}],
+                "database_specific": {"severity": "CRITICAL"}
+            }]}"#,
+        )?;
+        let nvd: NvdResponse = serde_json::from_str(
+            r#"{"vulnerabilities": [
+                {"cve": {"id": "CVE-2022-37434", "descriptions": [{"lang": "en", "value": "overflow"}]}},
+                {"cve": {"id": "CVE-2023-45853", "descriptions": [{"lang": "en", "value": "minizip overflow"}],
+                 "metrics": {"cvssMetricV31": [{"cvssData": {"baseSeverity": "CRITICAL"}}]}}}
+            ]}"#,
+        )?;
+
+        let advisories = merge_advisories(
+            osv.vulns.into_iter().map(Advisory::from).collect(),
+            nvd.vulnerabilities
+                .into_iter()
+                .map(Advisory::from)
+                .collect(),
+        );
+        assert_eq!(
+            advisories
+                .iter()
+                .map(|a| (
+                    a.id.as_str(),
+                    a.severity.as_deref(),
+                    a.fixed_version.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("GHSA-jc36-42cf-vqwj", Some("CRITICAL"), Some("1.2.13")),
+                ("CVE-2023-45853", Some("CRITICAL"), None),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -5,6 +5,10 @@ use std::{
     str::FromStr,
 };
 
+use component::{
+    BuildFlagNode, ConfigureBuildSection, Dependency, Recipe, RecipeBuilder, SkipStep,
+    SourceSection,
+};
 use miette::Diagnostic;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -26,16 +30,29 @@ pub enum GateError {
     TooManyPackagesWithTheSameName(String, usize),
     #[error("no package with name {0}")]
     NoSuchPackage(String),
-    #[error("distribution type {0} is not known use one of 'tarball', 'ips'")]
+    #[error("the gate has no build profile {0}, it defines {1}")]
+    NoSuchProfile(String, String),
+    #[error("distribution type {0} is not known use one of 'tarball', 'ips', 'deb', 'rpm', 'apk'")]
     UnknownDistributionType(String),
+    #[error("sandbox type {0} is not known use one of 'host', 'chroot', 'zone'")]
+    UnknownSandboxType(String),
+    #[error("network policy {0} is not known use one of 'allow', 'audit', 'deny'")]
+    UnknownNetworkPolicy(String),
+    #[error("the manifest template uses unknown variable {0}, known are {1}")]
+    UnknownTemplateVariable(String, String),
+    #[error("the manifest template sets no pkg.fmri, its packages could not be published")]
+    TemplateWithoutFmri,
     #[error(transparent)]
     #[diagnostic(transparent)]
     Knuffel(#[from] knuffel::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Component(#[from] component::ComponentError),
 }
 
 type GateResult<T> = Result<T, GateError>;
 
-#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize)]
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Gate {
     path: PathBuf,
     #[knuffel(child, unwrap(argument))]
@@ -54,6 +71,23 @@ pub struct Gate {
     pub publisher: String,
     #[knuffel(children(name = "metadata-transform"))]
     pub metadata_transforms: Vec<MetadataTransform>,
+    #[knuffel(children(name = "arch"))]
+    pub architectures: Vec<ArchitectureConfig>,
+    #[knuffel(child)]
+    pub sandbox: Option<Sandbox>,
+    #[knuffel(children(name = "override"))]
+    #[serde(default)]
+    pub overrides: Vec<ComponentOverride>,
+    #[knuffel(children(name = "profile"))]
+    #[serde(default)]
+    pub profiles: Vec<BuildProfile>,
+    #[knuffel(child)]
+    #[serde(default)]
+    pub build_resources: Option<BuildResources>,
+    /// Replaces the header pkgdev starts the IPS manifests of the gate with
+    #[knuffel(child)]
+    #[serde(default)]
+    pub manifest_template: Option<ManifestTemplate>,
 }
 
 impl Default for Gate {
@@ -68,6 +102,12 @@ impl Default for Gate {
             default_transforms: vec![],
             publisher: String::from("userland"),
             metadata_transforms: vec![],
+            architectures: vec![],
+            sandbox: None,
+            overrides: vec![],
+            profiles: vec![],
+            build_resources: None,
+            manifest_template: None,
         }
     }
 }
@@ -97,6 +137,9 @@ impl Gate {
 
         let mut gate = knuffel::parse::<Gate>(&name, &gate_document_contents)?;
         gate.path = path;
+        if let Some(template) = &gate.manifest_template {
+            template.validate()?;
+        }
         Ok(gate)
     }
 
@@ -146,9 +189,70 @@ impl Gate {
             doc.nodes_mut().push(meta_node);
         }
 
+        for arch in &self.architectures {
+            doc.nodes_mut().push(arch.to_node());
+        }
+
+        if let Some(sandbox) = &self.sandbox {
+            doc.nodes_mut().push(sandbox.to_node());
+        }
+
+        for component_override in &self.overrides {
+            doc.nodes_mut().push(component_override.to_node());
+        }
+
+        for profile in &self.profiles {
+            doc.nodes_mut().push(profile.to_node());
+        }
+
+        if let Some(build_resources) = &self.build_resources {
+            doc.nodes_mut().push(build_resources.to_node());
+        }
+
+        if let Some(manifest_template) = &self.manifest_template {
+            doc.nodes_mut().push(manifest_template.to_node());
+        }
+
         node
     }
 
+    /// The build flags the gate sets for all components built for `arch`.
+    pub fn arch_flags(&self, arch: &str) -> Vec<ArchitectureFlag> {
+        self.architectures
+            .iter()
+            .filter(|a| a.name == arch)
+            .flat_map(|a| a.flags.clone())
+            .collect()
+    }
+
+    /// The build profile called `name`.
+    pub fn profile(&self, name: &str) -> GateResult<&BuildProfile> {
+        self.profiles.iter().find(|p| p.name == name).ok_or_else(|| {
+            GateError::NoSuchProfile(
+                name.to_string(),
+                self.profiles.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", "),
+            )
+        })
+    }
+
+    /// Merge the overrides the gate has for the component into its recipe.
+    pub fn apply_overrides(&self, recipe: &mut Recipe) -> GateResult<()> {
+        for component_override in self.overrides.iter().filter(|o| o.component == recipe.name) {
+            recipe.merge_into_mut(&component_override.to_recipe()?)?;
+            for section in recipe.build_sections.iter_mut() {
+                if let Some(configure) = section.configure.as_mut() {
+                    configure
+                        .flags
+                        .extend(component_override.flags.iter().map(|f| BuildFlagNode {
+                            flag: f.flag.clone(),
+                            flag_name: f.flag_name.clone(),
+                        }));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn save(&self) -> GateResult<()> {
         let doc = self.to_document();
         let mut f = File::create(&self.path)?;
@@ -165,7 +269,289 @@ impl Gate {
     }
 }
 
-#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize)]
+/// Changes the gate makes to one of its components without forking the recipe, like
+/// further patches or other compiler flags. Paths of patches and files are relative to
+/// the component like in its recipe.
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ComponentOverride {
+    #[knuffel(argument)]
+    pub component: String,
+    #[knuffel(child, unwrap(argument))]
+    pub summary: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    pub prefix: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    pub revision: Option<String>,
+    #[knuffel(children(name = "source"))]
+    pub sources: Vec<SourceSection>,
+    #[knuffel(children(name = "dependency"))]
+    pub dependencies: Vec<Dependency>,
+    #[knuffel(children(name = "skip"))]
+    pub skipped_steps: Vec<SkipStep>,
+    /// Added to the configure builds of the component after the flags of its recipe
+    #[knuffel(children(name = "flag"))]
+    pub flags: Vec<ArchitectureFlag>,
+}
+
+impl ComponentOverride {
+    /// The override as recipe to merge into the one of the component.
+    fn to_recipe(&self) -> GateResult<Recipe> {
+        let mut builder = RecipeBuilder::default();
+        builder
+            .name(self.component.clone())
+            .sources(self.sources.clone())
+            .dependencies(self.dependencies.clone())
+            .skipped_steps(self.skipped_steps.clone());
+        if let Some(summary) = &self.summary {
+            builder.summary(summary.clone());
+        }
+        if let Some(prefix) = &self.prefix {
+            builder.prefix(prefix.clone());
+        }
+        if let Some(revision) = &self.revision {
+            builder.revision(revision.clone());
+        }
+        Ok(builder.build()?)
+    }
+
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("override");
+        node.insert(0, self.component.as_str());
+        let doc = node.ensure_children();
+        for (name, value) in [
+            ("summary", &self.summary),
+            ("prefix", &self.prefix),
+            ("revision", &self.revision),
+        ] {
+            if let Some(value) = value {
+                let mut value_node = kdl::KdlNode::new(name);
+                value_node.insert(0, value.as_str());
+                doc.nodes_mut().push(value_node);
+            }
+        }
+        for source in &self.sources {
+            doc.nodes_mut().push(source.to_node());
+        }
+        for dependency in &self.dependencies {
+            doc.nodes_mut().push(dependency.to_node());
+        }
+        for skip in &self.skipped_steps {
+            doc.nodes_mut().push(skip.to_node());
+        }
+        for flag in &self.flags {
+            doc.nodes_mut().push(flag.to_node());
+        }
+        node
+    }
+}
+
+/// A named set of compiler and linker flags a build can select, like `debug`, `release`
+/// or `hardened`, with `pkgdev build --profile`.
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BuildProfile {
+    #[knuffel(argument)]
+    pub name: String,
+    /// Used for configure builds whose recipe names no compiler
+    #[knuffel(child, unwrap(argument))]
+    pub compiler: Option<String>,
+    /// Used for configure builds whose recipe names no linker
+    #[knuffel(child, unwrap(argument))]
+    pub linker: Option<String>,
+    #[knuffel(children(name = "flag"))]
+    pub flags: Vec<ArchitectureFlag>,
+}
+
+impl BuildProfile {
+    /// Merge the profile into a configure build section. Its flags go before the ones of
+    /// the recipe so the recipe can add to or override them.
+    pub fn apply(&self, configure: &mut ConfigureBuildSection) {
+        configure.flags.splice(
+            0..0,
+            self.flags.iter().map(|f| BuildFlagNode {
+                flag: f.flag.clone(),
+                flag_name: f.flag_name.clone(),
+            }),
+        );
+        if configure.compiler.is_none() {
+            configure.compiler = self.compiler.clone();
+        }
+        if configure.linker.is_none() {
+            configure.linker = self.linker.clone();
+        }
+    }
+
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("profile");
+        node.insert(0, self.name.as_str());
+        let doc = node.ensure_children();
+        for (name, value) in [("compiler", &self.compiler), ("linker", &self.linker)] {
+            if let Some(value) = value {
+                let mut value_node = kdl::KdlNode::new(name);
+                value_node.insert(0, value.as_str());
+                doc.nodes_mut().push(value_node);
+            }
+        }
+        for flag in &self.flags {
+            doc.nodes_mut().push(flag.to_node());
+        }
+        node
+    }
+}
+
+/// What the builds of the components may use unless the recipe or the command line say
+/// otherwise. The limits apply to every build process on its own.
+#[derive(Debug, Default, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BuildResources {
+    /// Jobs make and ninja run at once
+    #[knuffel(property)]
+    pub jobs: Option<u32>,
+    /// Address space in bytes at most
+    #[knuffel(property(name = "memory-limit"))]
+    pub memory_limit: Option<u64>,
+    /// CPU time in seconds at most
+    #[knuffel(property(name = "cpu-time-limit"))]
+    pub cpu_time_limit: Option<u64>,
+}
+
+impl BuildResources {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("build-resources");
+        if let Some(jobs) = self.jobs {
+            node.insert("jobs", i64::from(jobs));
+        }
+        for (name, value) in [
+            ("memory-limit", self.memory_limit),
+            ("cpu-time-limit", self.cpu_time_limit),
+        ] {
+            if let Some(value) = value {
+                node.insert(name, value as i64);
+            }
+        }
+        node
+    }
+}
+
+/// The variables the header of a [`ManifestTemplate`] can use as `{name}`, with what
+/// pkgdev substitutes for them.
+pub const MANIFEST_TEMPLATE_VARIABLES: &[(&str, &str)] = &[
+    ("name", "name of the package"),
+    ("version", "version of the component"),
+    ("build_version", "version of the gate"),
+    ("branch_version", "branch of the gate"),
+    ("revision", "revision of the component"),
+    ("summary", "summary of the package"),
+    ("classification", "classification of the package"),
+    ("project_url", "project url of the component"),
+    ("source_url", "url of the first source of the component"),
+    ("licenses", "license actions of the package"),
+];
+
+/// How the IPS manifests pkgdev generates for the packages of a gate or publisher start.
+/// Parts left out are the ones of OpenIndiana.
+#[derive(Debug, Default, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ManifestTemplate {
+    /// License of the manifests themselves, written as a comment block on top
+    #[knuffel(child, unwrap(argument))]
+    #[serde(default)]
+    pub boilerplate: Option<String>,
+    /// The actions every manifest starts with, see [`MANIFEST_TEMPLATE_VARIABLES`]
+    #[knuffel(child, unwrap(argument))]
+    #[serde(default)]
+    pub header: Option<String>,
+}
+
+impl ManifestTemplate {
+    /// Unknown variables would silently render empty, so they are refused up front.
+    pub fn validate(&self) -> GateResult<()> {
+        let Some(header) = &self.header else {
+            return Ok(());
+        };
+        for variable in template_variables(header) {
+            if !MANIFEST_TEMPLATE_VARIABLES.iter().any(|(v, _)| *v == variable) {
+                return Err(GateError::UnknownTemplateVariable(
+                    variable.to_string(),
+                    MANIFEST_TEMPLATE_VARIABLES
+                        .iter()
+                        .map(|(v, _)| *v)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ));
+            }
+        }
+        if !header.contains("pkg.fmri") {
+            return Err(GateError::TemplateWithoutFmri);
+        }
+        Ok(())
+    }
+
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("manifest-template");
+        for (name, value) in [("boilerplate", &self.boilerplate), ("header", &self.header)] {
+            if let Some(value) = value {
+                let mut child = kdl::KdlNode::new(name);
+                child.insert(0, value.as_str());
+                node.ensure_children().nodes_mut().push(child);
+            }
+        }
+        node
+    }
+}
+
+/// The `{identifier}`s of a template. Braces around anything else, like the counts of
+/// the regular expressions of transforms, are no variables.
+fn template_variables(template: &str) -> Vec<&str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(inner, _)| inner))
+        .filter(|inner| {
+            inner.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && inner.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .collect()
+}
+
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchitectureConfig {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(children(name = "flag"))]
+    pub flags: Vec<ArchitectureFlag>,
+}
+
+impl ArchitectureConfig {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("arch");
+        node.insert(0, self.name.as_str());
+        for flag in &self.flags {
+            node.ensure_children().nodes_mut().push(flag.to_node());
+        }
+        node
+    }
+}
+
+/// A compiler flag like the `flag` node of a configure build section. Without a name it
+/// applies to all of CFLAGS, CXXFLAGS, CPPFLAGS and FFLAGS.
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchitectureFlag {
+    #[knuffel(argument)]
+    pub flag: String,
+    #[knuffel(property(name = "name"))]
+    pub flag_name: Option<String>,
+}
+
+impl ArchitectureFlag {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("flag");
+        node.insert(0, self.flag.as_str());
+        if let Some(flag_name) = &self.flag_name {
+            node.insert("name", flag_name.as_str());
+        }
+        node
+    }
+}
+
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
 pub struct MetadataTransform {
     #[knuffel(property)]
     pub matcher: String,
@@ -190,7 +576,7 @@ impl MetadataTransform {
     }
 }
 
-#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize)]
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Transform {
     #[knuffel(arguments)]
     actions: Vec<String>,
@@ -222,27 +608,40 @@ impl Transform {
     }
 }
 
-#[derive(Debug, Default, knuffel::Decode, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Distribution {
     #[knuffel(property(name = "type"), default, str)]
     pub distribution_type: DistributionType,
+    /// Directory of a flat apt repository built .deb packages are added to.
+    #[knuffel(property(name = "apt-repository"))]
+    pub apt_repository: Option<String>,
+    /// RSA private key built .apk packages are signed with. The public key has to be
+    /// installed as `<file name>.pub` in /etc/apk/keys.
+    #[knuffel(property(name = "apk-signing-key"))]
+    pub apk_signing_key: Option<String>,
 }
 
 impl Distribution {
     pub fn to_node(&self) -> kdl::KdlNode {
         let mut node = kdl::KdlNode::new("distribution");
-        let doc = node.ensure_children();
-        let mut type_node = kdl::KdlNode::new("type");
-        type_node.insert(0, self.distribution_type.to_string().as_str());
-        doc.nodes_mut().push(type_node);
+        node.insert("type", self.distribution_type.to_string().as_str());
+        if let Some(apt_repository) = &self.apt_repository {
+            node.insert("apt-repository", apt_repository.as_str());
+        }
+        if let Some(apk_signing_key) = &self.apk_signing_key {
+            node.insert("apk-signing-key", apk_signing_key.as_str());
+        }
         node
     }
 }
 
-#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize)]
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
 pub enum DistributionType {
     Tarbball,
     IPS,
+    Deb,
+    Rpm,
+    Apk,
 }
 
 impl Default for DistributionType {
@@ -258,6 +657,9 @@ impl FromStr for DistributionType {
         match s {
             "tarball" | "tar" => Ok(Self::Tarbball),
             "ips" | "IPS" => Ok(Self::IPS),
+            "deb" | "debian" => Ok(Self::Deb),
+            "rpm" => Ok(Self::Rpm),
+            "apk" | "alpine" => Ok(Self::Apk),
             x => Err(GateError::UnknownDistributionType(x.to_string())),
         }
     }
@@ -268,6 +670,396 @@ impl ToString for DistributionType {
         match self {
             DistributionType::Tarbball => String::from("tarball"),
             DistributionType::IPS => String::from("ips"),
+            DistributionType::Deb => String::from("deb"),
+            DistributionType::Rpm => String::from("rpm"),
+            DistributionType::Apk => String::from("apk"),
+        }
+    }
+}
+
+/// Where the components of the gate are built by default. A chroot `root` or a `zone`
+/// must have the workspace mounted at the same path as on the host.
+#[derive(Debug, Default, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Sandbox {
+    #[knuffel(property(name = "type"), default, str)]
+    pub sandbox_type: SandboxType,
+    #[knuffel(property)]
+    pub root: Option<String>,
+    #[knuffel(property)]
+    pub zone: Option<String>,
+    /// Whether the build phase may use the network. Sources are downloaded before.
+    #[knuffel(property, default, str)]
+    #[serde(default)]
+    pub network: NetworkPolicy,
+}
+
+impl Sandbox {
+    pub fn to_node(&self) -> kdl::KdlNode {
+        let mut node = kdl::KdlNode::new("sandbox");
+        node.insert("type", self.sandbox_type.to_string().as_str());
+        if let Some(root) = &self.root {
+            node.insert("root", root.as_str());
+        }
+        if let Some(zone) = &self.zone {
+            node.insert("zone", zone.as_str());
+        }
+        if self.network != NetworkPolicy::Allow {
+            node.insert("network", self.network.to_string().as_str());
+        }
+        node
+    }
+}
+
+/// What happens when a build tries to reach the network after its sources are
+/// downloaded. `audit` records the hosts but lets the build continue, `deny` runs the
+/// build without network access, in a zone without network interfaces or in a network
+/// namespace of its own on Linux.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum NetworkPolicy {
+    Allow,
+    Audit,
+    Deny,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        Self::Allow
+    }
+}
+
+impl FromStr for NetworkPolicy {
+    type Err = GateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "audit" => Ok(Self::Audit),
+            "deny" => Ok(Self::Deny),
+            x => Err(GateError::UnknownNetworkPolicy(x.to_string())),
+        }
+    }
+}
+
+impl ToString for NetworkPolicy {
+    fn to_string(&self) -> String {
+        match self {
+            NetworkPolicy::Allow => String::from("allow"),
+            NetworkPolicy::Audit => String::from("audit"),
+            NetworkPolicy::Deny => String::from("deny"),
+        }
+    }
+}
+
+#[derive(Debug, knuffel::Decode, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SandboxType {
+    Host,
+    Chroot,
+    Zone,
+}
+
+impl Default for SandboxType {
+    fn default() -> Self {
+        Self::Host
+    }
+}
+
+impl FromStr for SandboxType {
+    type Err = GateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "host" | "none" => Ok(Self::Host),
+            "chroot" => Ok(Self::Chroot),
+            "zone" => Ok(Self::Zone),
+            x => Err(GateError::UnknownSandboxType(x.to_string())),
+        }
+    }
+}
+
+impl ToString for SandboxType {
+    fn to_string(&self) -> String {
+        match self {
+            SandboxType::Host => String::from("host"),
+            SandboxType::Chroot => String::from("chroot"),
+            SandboxType::Zone => String::from("zone"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::vec;
+    use proptest::option;
+    use proptest::prelude::*;
+
+    use crate::*;
+
+    fn text() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9 ./:=_${}<>-]{0,24}"
+    }
+
+    fn transform() -> impl Strategy<Value = Transform> {
+        (vec(text(), 0..4), option::of(text()))
+            .prop_map(|(actions, include)| Transform { actions, include })
+    }
+
+    fn metadata_transform() -> impl Strategy<Value = MetadataTransform> {
+        (text(), text(), any::<bool>()).prop_map(|(matcher, replacement, drop)| {
+            MetadataTransform {
+                matcher,
+                replacement,
+                drop,
+            }
+        })
+    }
+
+    fn architecture() -> impl Strategy<Value = ArchitectureConfig> {
+        let flag = (text(), option::of(text()))
+            .prop_map(|(flag, flag_name)| ArchitectureFlag { flag, flag_name });
+        (text(), vec(flag, 0..3)).prop_map(|(name, flags)| ArchitectureConfig { name, flags })
+    }
+
+    fn distribution() -> impl Strategy<Value = Distribution> {
+        (
+            prop_oneof![
+                Just(DistributionType::Tarbball),
+                Just(DistributionType::IPS),
+                Just(DistributionType::Deb),
+                Just(DistributionType::Rpm),
+                Just(DistributionType::Apk)
+            ],
+            option::of(text()),
+            option::of(text()),
+        )
+            .prop_map(
+                |(distribution_type, apt_repository, apk_signing_key)| Distribution {
+                    distribution_type,
+                    apt_repository,
+                    apk_signing_key,
+                },
+            )
+    }
+
+    fn sandbox() -> impl Strategy<Value = Sandbox> {
+        (
+            prop_oneof![
+                Just(SandboxType::Host),
+                Just(SandboxType::Chroot),
+                Just(SandboxType::Zone)
+            ],
+            option::of(text()),
+            option::of(text()),
+            prop_oneof![
+                Just(NetworkPolicy::Allow),
+                Just(NetworkPolicy::Audit),
+                Just(NetworkPolicy::Deny)
+            ],
+        )
+            .prop_map(|(sandbox_type, root, zone, network)| Sandbox {
+                sandbox_type,
+                root,
+                zone,
+                network,
+            })
+    }
+
+    fn component_override() -> impl Strategy<Value = ComponentOverride> {
+        let flag = (text(), option::of(text()))
+            .prop_map(|(flag, flag_name)| ArchitectureFlag { flag, flag_name });
+        (
+            text(),
+            option::of(text()),
+            option::of(text()),
+            option::of(text()),
+            vec(flag, 0..3),
+        )
+            .prop_map(
+                |(component, summary, prefix, revision, flags)| ComponentOverride {
+                    component,
+                    summary,
+                    prefix,
+                    revision,
+                    sources: vec![],
+                    dependencies: vec![],
+                    skipped_steps: vec![],
+                    flags,
+                },
+            )
+    }
+
+    fn profile() -> impl Strategy<Value = BuildProfile> {
+        let flag = (text(), option::of(text()))
+            .prop_map(|(flag, flag_name)| ArchitectureFlag { flag, flag_name });
+        (text(), option::of(text()), option::of(text()), vec(flag, 0..3)).prop_map(
+            |(name, compiler, linker, flags)| BuildProfile {
+                name,
+                compiler,
+                linker,
+                flags,
+            },
+        )
+    }
+
+    fn build_resources() -> impl Strategy<Value = BuildResources> {
+        (
+            option::of(1..64u32),
+            option::of(0..i64::MAX as u64),
+            option::of(0..i64::MAX as u64),
+        )
+            .prop_map(|(jobs, memory_limit, cpu_time_limit)| BuildResources {
+                jobs,
+                memory_limit,
+                cpu_time_limit,
+            })
+    }
+
+    fn manifest_template() -> impl Strategy<Value = ManifestTemplate> {
+        (option::of(text()), option::of(text()))
+            .prop_map(|(boilerplate, header)| ManifestTemplate { boilerplate, header })
+    }
+
+    prop_compose! {
+        fn gate()(
+            id in option::of(text()),
+            name in text(),
+            version in text(),
+            branch in text(),
+            distribution in option::of(distribution()),
+            default_transforms in vec(transform(), 0..4),
+            publisher in text(),
+            metadata_transforms in vec(metadata_transform(), 0..4),
+            architectures in vec(architecture(), 0..3),
+            sandbox in option::of(sandbox()),
+            overrides in vec(component_override(), 0..3),
+            profiles in vec(profile(), 0..3),
+            build_resources in option::of(build_resources()),
+            manifest_template in option::of(manifest_template()),
+        ) -> Gate {
+            Gate {
+                path: PathBuf::new(),
+                id,
+                name,
+                version,
+                branch,
+                distribution,
+                default_transforms,
+                publisher,
+                metadata_transforms,
+                architectures,
+                sandbox,
+                overrides,
+                profiles,
+                build_resources,
+                manifest_template,
+            }
+        }
+    }
+
+    #[test]
+    fn overrides_are_merged_into_the_recipe() -> miette::Result<()> {
+        let gate = knuffel::parse::<Gate>(
+            "gate.kdl",
+            r#"
+            name "userland"
+            version "0.5.11"
+            branch "2024.0.0"
+            publisher "userland"
+            override "library/zlib" {
+                revision "1"
+                source {
+                    patch "../../patches/zlib-solaris.patch" drop-directories=1
+                }
+                flag "-O3" name="CFLAGS"
+            }
+            "#,
+        )?;
+        let mut recipe = component::RecipeBuilder::default()
+            .name("library/zlib")
+            .revision("0")
+            .build_sections(vec![component::BuildSectionBuilder::default()
+                .configure(component::ConfigureBuildSectionBuilder::default().build()?)
+                .build()?])
+            .build()?;
+        gate.apply_overrides(&mut recipe)?;
+
+        assert_eq!(recipe.revision.as_deref(), Some("1"));
+        assert_eq!(recipe.sources.len(), 1);
+        let configure = recipe.build_sections[0].configure.as_ref().unwrap();
+        assert_eq!(configure.flags.last().map(|f| f.flag.as_str()), Some("-O3"));
+        Ok(())
+    }
+
+    #[test]
+    fn profiles_are_merged_into_configure_sections() -> miette::Result<()> {
+        let gate = knuffel::parse::<Gate>(
+            "gate.kdl",
+            r#"
+            name "userland"
+            version "0.5.11"
+            branch "2024.0.0"
+            publisher "userland"
+            profile "debug" {
+                flag "-O0 -g"
+            }
+            profile "hardened" {
+                compiler "gcc-13"
+                flag "-fstack-protector-strong"
+                flag "-Wl,-z,relro" name="LDFLAGS"
+            }
+            "#,
+        )?;
+        let mut configure = component::ConfigureBuildSectionBuilder::default()
+            .flags(vec![component::BuildFlagNode {
+                flag: String::from("-O2"),
+                flag_name: None,
+            }])
+            .linker("ld")
+            .build()?;
+        gate.profile("hardened")?.apply(&mut configure);
+
+        let flags = configure.flags.iter().map(|f| f.flag.as_str()).collect::<Vec<_>>();
+        assert_eq!(flags, ["-fstack-protector-strong", "-Wl,-z,relro", "-O2"]);
+        assert_eq!(configure.compiler.as_deref(), Some("gcc-13"));
+        assert_eq!(configure.linker.as_deref(), Some("ld"));
+        assert!(matches!(
+            gate.profile("release"),
+            Err(GateError::NoSuchProfile(_, defined)) if defined == "debug, hardened"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_templates_only_use_known_variables() {
+        let template = |header: &str| ManifestTemplate {
+            boilerplate: Some(String::from("Copyright 2024 Example Maintainers")),
+            header: Some(header.to_string()),
+        };
+        assert!(template("set name=pkg.fmri value=pkg:/{name}@{version}")
+            .validate()
+            .is_ok());
+        // Counts of regular expressions are no variables
+        assert!(
+            template("set name=pkg.fmri value=pkg:/{name}\n<transform file path=a{2} -> drop>")
+                .validate()
+                .is_ok()
+        );
+        assert!(matches!(
+            template("set name=pkg.fmri value=pkg:/{name}@{vesion}").validate(),
+            Err(GateError::UnknownTemplateVariable(variable, _)) if variable == "vesion"
+        ));
+        assert!(matches!(
+            template("set name=pkg.summary value=\"{summary}\"").validate(),
+            Err(GateError::TemplateWithoutFmri)
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn gate_kdl_roundtrip(gate in gate()) {
+            let doc = gate.to_document().to_string();
+            let parsed = knuffel::parse::<Gate>("gate.kdl", &doc)
+                .map_err(|e| TestCaseError::fail(format!("{:?}\n{}", e, doc)))?;
+            prop_assert_eq!(parsed, gate);
         }
     }
 }
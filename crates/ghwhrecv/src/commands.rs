@@ -0,0 +1,70 @@
+use forge::ChangeRequestCommand;
+use github::AuthorAssociation;
+use tracing::warn;
+
+/// Comment lines starting with this are commands to forge.
+const COMMAND_PREFIX: &str = "/forge";
+
+/// Only people with write access to the repository may start jobs.
+pub fn may_give_commands(association: &AuthorAssociation) -> bool {
+    matches!(
+        association,
+        AuthorAssociation::Owner | AuthorAssociation::Member | AuthorAssociation::Collaborator
+    )
+}
+
+/// The commands of a comment, one per line like `/forge rebuild` or
+/// `/forge retry gate=2024`. Unknown commands are logged and skipped.
+pub fn parse_commands(body: &str) -> Vec<ChangeRequestCommand> {
+    body.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            if words.next() != Some(COMMAND_PREFIX) {
+                return None;
+            }
+            let command = parse_command(words.next()?, words.collect());
+            if command.is_none() {
+                warn!("ignoring unknown command: {}", line.trim());
+            }
+            command
+        })
+        .collect()
+}
+
+fn parse_command(name: &str, args: Vec<&str>) -> Option<ChangeRequestCommand> {
+    match name {
+        "rebuild" if args.is_empty() => Some(ChangeRequestCommand::Rebuild),
+        "retry" => {
+            let mut gate = None;
+            for arg in args {
+                match arg.split_once('=') {
+                    Some(("gate", value)) if !value.is_empty() => gate = Some(value.to_string()),
+                    _ => return None,
+                }
+            }
+            Some(ChangeRequestCommand::Retry { gate })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_from_comments() {
+        let body = "Looks good, but the mirror was down.\r\n/forge retry gate=2024\n/forge rebuild\n/forge retry\n/forge deploy\n/forge retry branch=main\n /forgery rebuild";
+        assert_eq!(
+            parse_commands(body),
+            vec![
+                ChangeRequestCommand::Retry {
+                    gate: Some(String::from("2024"))
+                },
+                ChangeRequestCommand::Rebuild,
+                ChangeRequestCommand::Retry { gate: None },
+            ]
+        );
+        assert!(parse_commands("please /forge rebuild").is_empty());
+    }
+}
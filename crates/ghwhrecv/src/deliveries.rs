@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use axum::http::HeaderMap;
+use github::GitHubWebhookRequest;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Where the received webhooks are recorded.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ForgeConfig {
+    /// Base url of forged
+    pub url: String,
+    /// API key scoped for `webhooks`
+    pub api_key: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RecordDeliveryInput<'a> {
+    gate_id: String,
+    delivery_id: Option<String>,
+    event: String,
+    headers: HashMap<String, String>,
+    payload: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct DeliveryResultInput<'a> {
+    error: Option<String>,
+    correlation_id: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordedDelivery {
+    id: String,
+}
+
+/// Records every webhook in forged with the result of processing it, so deliveries that
+/// could not be passed on can be replayed from there.
+#[derive(Debug, Clone)]
+pub struct DeliveryLog {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+}
+
+/// The headers GitHub describes a webhook with, the ones needed to process it again.
+fn recorded_headers(headers: &HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            name.starts_with("x-github-") || name.starts_with("x-hub-") || name == "content-type"
+        })
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+impl DeliveryLog {
+    pub fn new(config: &ForgeConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: config.url.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+        }
+    }
+
+    /// Record a received webhook and return the id of its delivery in forged. Webhooks
+    /// forged replays are recorded already and keep their id.
+    pub async fn record(
+        &self,
+        gate_id: &Uuid,
+        headers: &HeaderMap,
+        req: &GitHubWebhookRequest,
+    ) -> Option<String> {
+        if let Some(id) = headers
+            .get(forge::REPLAYED_DELIVERY_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            debug!("processing replayed delivery {}", id);
+            return Some(id.to_string());
+        }
+
+        let payload = String::from_utf8_lossy(req.body());
+        let input = RecordDeliveryInput {
+            gate_id: gate_id.to_string(),
+            delivery_id: headers
+                .get(&github::headers::DELIVERY)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            event: req.get_kind().to_string(),
+            headers: recorded_headers(headers),
+            payload: &payload,
+        };
+        let response = self
+            .client
+            .post(format!("{}/api/v1/webhooks/deliveries", self.url))
+            .bearer_auth(&self.api_key)
+            .json(&input)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(response) => match response.json::<RecordedDelivery>().await {
+                Ok(delivery) => Some(delivery.id),
+                Err(e) => {
+                    warn!("could not read the recorded delivery: {}", e);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("could not record the webhook in forged: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Record the result of processing a delivery.
+    pub async fn finish<T, E: std::fmt::Display>(
+        &self,
+        id: &str,
+        correlation_id: &str,
+        result: &Result<T, E>,
+    ) {
+        let input = DeliveryResultInput {
+            error: result.as_ref().err().map(|e| e.to_string()),
+            correlation_id,
+        };
+        if let Err(e) = self
+            .client
+            .put(format!("{}/api/v1/webhooks/deliveries/{}", self.url, id))
+            .bearer_auth(&self.api_key)
+            .json(&input)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            warn!("could not record the result of delivery {}: {}", id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_github_headers_are_recorded() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-github-event", "pull_request".parse().unwrap());
+        headers.insert("x-github-delivery", "72d3162e".parse().unwrap());
+        headers.insert("x-hub-signature-256", "sha256=abc".parse().unwrap());
+        headers.insert("content-type", "application/json".parse().unwrap());
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        headers.insert("host", "hooks.example.com".parse().unwrap());
+
+        let recorded = recorded_headers(&headers);
+        assert_eq!(recorded.len(), 4);
+        assert_eq!(recorded["x-github-event"], "pull_request");
+        assert!(!recorded.contains_key("authorization"));
+    }
+}
@@ -65,6 +65,9 @@ pub mod headers {
         Ping,
         Push,
         PullRequest,
+        IssueComment,
+        Create,
+        Release,
     }
 
     impl Header for Event {
@@ -87,6 +90,9 @@ pub mod headers {
                 "push" => Ok(Self::Push),
                 "ping" => Ok(Self::Ping),
                 "pull_request" => Ok(Self::PullRequest),
+                "issue_comment" => Ok(Self::IssueComment),
+                "create" => Ok(Self::Create),
+                "release" => Ok(Self::Release),
                 _ => Err(axum_extra::headers::Error::invalid()),
             }
         }
@@ -127,6 +133,11 @@ impl GitHubWebhookRequest {
         self.event_kind.clone()
     }
 
+    /// The body exactly as GitHub sent it.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
     pub fn get_event(&self) -> Result<GitHubEvent, GitHubError> {
         trace!("Parsing github event {}", self.event_kind);
         match self.event_kind {
@@ -135,6 +146,11 @@ impl GitHubWebhookRequest {
             Event::PullRequest => Ok(GitHubEvent::PullRequest(serde_json::from_slice(
                 &self.body,
             )?)),
+            Event::IssueComment => Ok(GitHubEvent::IssueComment(serde_json::from_slice(
+                &self.body,
+            )?)),
+            Event::Create => Ok(GitHubEvent::Create(serde_json::from_slice(&self.body)?)),
+            Event::Release => Ok(GitHubEvent::Release(serde_json::from_slice(&self.body)?)),
         }
     }
 }
@@ -143,6 +159,8 @@ pub enum GitHubEvent {
     PullRequest(PullRequestPayload),
     Issue(Issue),
     IssueComment(IssueComment),
+    Create(Create),
+    Release(ReleasePayload),
     Status(Status),
     Push(Push),
     Ping(Ping),
@@ -341,7 +359,7 @@ pub struct Label {
     pub default: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum AuthorAssociation {
     Collaborator,
@@ -390,11 +408,147 @@ pub enum PullRequestState {
 pub struct Issue {}
 
 #[derive(Debug, Deserialize, Clone)]
-pub struct IssueComment {}
+pub struct IssueComment {
+    pub action: IssueCommentAction,
+    /// The issue or pull request the comment belongs to.
+    pub issue: IssueObject,
+    pub comment: Comment,
+    /// The changes to the comment if the action was edited.
+    pub changes: Option<HashMap<String, Value>>,
+    /// The repository on GitHub where the event occurred.
+    pub repository: Repository,
+    /// A GitHub organization. Webhook payloads contain the organization property when the webhook is configured for an organization, or when the event occurs from activity in a repository owned by an organization.
+    pub organization: Option<HashMap<String, Value>>,
+    /// The GitHub App installation. Webhook payloads contain the installation property when the event is configured for and sent to a GitHub App. For more information, see "Using webhooks with GitHub Apps."
+    pub installation: Option<HashMap<String, Value>>,
+    /// An enterprise on GitHub. Webhook payloads contain the enterprise property when the webhook is configured on an enterprise account or an organization that's part of an enterprise account. For more information, see "About enterprise accounts."
+    pub enterprise: Option<HashMap<String, Value>>,
+    /// The GitHub user that triggered the event. This property is included in every webhook payload.
+    pub sender: User,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueCommentAction {
+    Created,
+    Edited,
+    Deleted,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct IssueObject {
+    pub url: String,
+    pub id: i64,
+    pub node_id: String,
+    /// Number uniquely identifying the issue within its repository.
+    pub number: i32,
+    /// State of the issue. Either open or closed.
+    pub state: PullRequestState,
+    pub locked: bool,
+    pub title: String,
+    pub body: Option<String>,
+    pub user: User,
+    pub labels: Vec<Label>,
+    pub milestone: Option<Milestone>,
+    pub author_association: AuthorAssociation,
+    /// Set if the issue is a pull request.
+    pub pull_request: Option<IssuePullRequest>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct IssuePullRequest {
+    pub url: String,
+    pub html_url: String,
+    pub diff_url: String,
+    pub patch_url: String,
+    pub merged_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Comment {
+    pub url: String,
+    pub html_url: String,
+    pub id: i64,
+    pub node_id: String,
+    /// Contents of the comment.
+    pub body: String,
+    pub user: User,
+    /// How the author of the comment is associated with the repository.
+    pub author_association: AuthorAssociation,
+    pub created_at: String,
+    pub updated_at: String,
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Status {}
 
+/// A branch or tag was created.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Create {
+    /// The name of the branch or tag, without refs/heads/ or refs/tags/.
+    #[serde(rename = "ref")]
+    pub ref_name: String,
+    pub ref_type: RefType,
+    /// The name of the repository's default branch (usually main).
+    pub master_branch: String,
+    /// The repository's current description.
+    pub description: Option<String>,
+    /// The pusher type for the event. Can be either user or a deploy key.
+    pub pusher_type: String,
+    /// A git repository
+    pub repository: Repository,
+    /// The GitHub user that triggered the event. This property is included in every webhook payload.
+    pub sender: User,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RefType {
+    Tag,
+    Branch,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReleasePayload {
+    pub action: ReleaseAction,
+    pub release: Release,
+    /// A git repository
+    pub repository: Repository,
+    /// The GitHub user that triggered the event. This property is included in every webhook payload.
+    pub sender: User,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseAction {
+    Created,
+    Deleted,
+    Edited,
+    Prereleased,
+    Published,
+    Released,
+    Unpublished,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Release {
+    pub url: String,
+    pub html_url: String,
+    pub id: i64,
+    pub node_id: String,
+    /// The name of the tag.
+    pub tag_name: String,
+    /// Specifies the commitish value that determines where the Git tag is created from.
+    pub target_commitish: String,
+    pub name: Option<String>,
+    pub body: Option<String>,
+    /// true to create a draft (unpublished) release, false to create a published one.
+    pub draft: bool,
+    /// Whether to identify the release as a prerelease or a full release.
+    pub prerelease: bool,
+    pub author: User,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Push {
     /// The SHA of the most recent commit on ref after the push.
@@ -432,6 +586,16 @@ pub struct Push {
     pub sender: serde_json::Value,
 }
 
+impl Push {
+    /// Whether one of the pushed commits added or modified the file at `path`. Pushes of
+    /// more than 20 commits only list the first ones.
+    pub fn writes_file(&self, path: &str) -> bool {
+        self.commits
+            .iter()
+            .any(|c| c.added.iter().chain(c.modified.iter()).any(|f| f == path))
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Commit {
     /// An array of files added in the commit.
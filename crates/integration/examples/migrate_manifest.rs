@@ -0,0 +1,12 @@
+use integration::{read_forge_manifest, write_forge_manifest};
+use miette::{miette, Result};
+
+/// Rewrite the manifest at the path given as argument in the newest version.
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or(miette!("usage: migrate_manifest .forge/manifest.toml"))?;
+    let manifest = read_forge_manifest(&path)?;
+    write_forge_manifest(&path, &manifest)?;
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    default_components_dir, default_metadata_filename, ForgeIntegrationManifest, ScriptShell,
+};
+
+/// The manifest as written in `.forge/manifest.kdl`. Every field is a node named like
+/// it in kebab-case, lists take their entries as arguments.
+#[derive(Debug, knuffel::Decode)]
+pub(crate) struct KdlManifest {
+    #[knuffel(child, unwrap(argument), default = 1)]
+    version: u32,
+    #[knuffel(child, unwrap(arguments), default)]
+    component_list: Vec<String>,
+    #[knuffel(child, unwrap(arguments), default)]
+    component_globs: Vec<String>,
+    #[knuffel(child, unwrap(arguments), default)]
+    metadata_generation_script: Vec<String>,
+    #[knuffel(child, unwrap(arguments), default)]
+    pre_metadata_hook: Vec<String>,
+    #[knuffel(child, unwrap(arguments), default)]
+    post_metadata_hook: Vec<String>,
+    #[knuffel(child, unwrap(argument), default = default_metadata_filename())]
+    metadata_filename: String,
+    #[knuffel(child, unwrap(argument), default)]
+    change_to_component_dir: bool,
+    #[knuffel(child, unwrap(argument), default = default_components_dir())]
+    components_dir: String,
+    #[knuffel(child, default)]
+    env: KdlEnv,
+    #[knuffel(child, unwrap(argument), default)]
+    native: bool,
+    #[knuffel(child, unwrap(argument), default)]
+    shell: ScriptShell,
+}
+
+/// The variables as children named like them, `env { LANG "C" }`.
+#[derive(Debug, Default, knuffel::Decode)]
+struct KdlEnv(#[knuffel(children)] Vec<KdlEnvVar>);
+
+#[derive(Debug, knuffel::Decode)]
+struct KdlEnvVar {
+    #[knuffel(node_name)]
+    name: String,
+    #[knuffel(argument)]
+    value: String,
+}
+
+impl From<KdlManifest> for ForgeIntegrationManifest {
+    fn from(manifest: KdlManifest) -> Self {
+        Self {
+            version: manifest.version,
+            component_list_script: manifest.component_list,
+            component_globs: manifest.component_globs,
+            component_metadata_gen_script: manifest.metadata_generation_script,
+            pre_metadata_hook: manifest.pre_metadata_hook,
+            post_metadata_hook: manifest.post_metadata_hook,
+            component_metadata_filename: manifest.metadata_filename,
+            change_to_component_dir: manifest.change_to_component_dir,
+            components_dir: manifest.components_dir,
+            env: manifest
+                .env
+                .0
+                .into_iter()
+                .map(|var| (var.name, var.value))
+                .collect::<BTreeMap<_, _>>(),
+            native: manifest.native,
+            shell: manifest.shell,
+        }
+    }
+}
+
+/// The manifest as KDL document that reads back as it is.
+pub(crate) fn to_kdl(manifest: &ForgeIntegrationManifest) -> String {
+    let mut document = format!("version {}\n", manifest.version);
+    let lists = [
+        ("component-list", &manifest.component_list_script),
+        ("component-globs", &manifest.component_globs),
+        (
+            "metadata-generation-script",
+            &manifest.component_metadata_gen_script,
+        ),
+        ("pre-metadata-hook", &manifest.pre_metadata_hook),
+        ("post-metadata-hook", &manifest.post_metadata_hook),
+    ];
+    for (node, entries) in lists.into_iter().filter(|(_, entries)| !entries.is_empty()) {
+        let arguments = entries
+            .iter()
+            .map(|entry| string(entry))
+            .collect::<Vec<_>>();
+        document.push_str(&format!("{} {}\n", node, arguments.join(" ")));
+    }
+    document.push_str(&format!(
+        "metadata-filename {}\n",
+        string(&manifest.component_metadata_filename)
+    ));
+    document.push_str(&format!(
+        "change-to-component-dir {}\n",
+        manifest.change_to_component_dir
+    ));
+    document.push_str(&format!(
+        "components-dir {}\n",
+        string(&manifest.components_dir)
+    ));
+    if manifest.native {
+        document.push_str("native true\n");
+    }
+    if manifest.shell != ScriptShell::Bash {
+        document.push_str(&format!("shell {}\n", string(manifest.shell.name())));
+    }
+    if !manifest.env.is_empty() {
+        document.push_str("env {\n");
+        for (name, value) in &manifest.env {
+            document.push_str(&format!("    {} {}\n", string(name), string(value)));
+        }
+        document.push_str("}\n");
+    }
+    document
+}
+
+/// A quoted KDL string.
+fn string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The manifest in the KDL Schema Language, for editors checking `.forge/manifest.kdl`.
+pub const KDL_SCHEMA: &str = r#"document {
+    info {
+        title "Forge integration manifest" lang="en"
+        description "How forge finds and reads the components of a gate repository" lang="en"
+    }
+    node "version" {
+        max 1
+        value {
+            type "number"
+            min 1
+            max 1
+        }
+    }
+    node "component-list" {
+        max 1
+        value {
+            type "string"
+            min 1
+        }
+    }
+    node "component-globs" {
+        max 1
+        value {
+            type "string"
+            min 1
+        }
+    }
+    node "metadata-generation-script" {
+        max 1
+        value {
+            type "string"
+            min 1
+        }
+    }
+    node "pre-metadata-hook" {
+        max 1
+        value {
+            type "string"
+            min 1
+        }
+    }
+    node "post-metadata-hook" {
+        max 1
+        value {
+            type "string"
+            min 1
+        }
+    }
+    node "metadata-filename" {
+        max 1
+        value {
+            type "string"
+            min 1
+            max 1
+        }
+    }
+    node "change-to-component-dir" {
+        max 1
+        value {
+            type "boolean"
+            min 1
+            max 1
+        }
+    }
+    node "components-dir" {
+        max 1
+        value {
+            type "string"
+            min 1
+            max 1
+        }
+    }
+    node "native" {
+        max 1
+        value {
+            type "boolean"
+            min 1
+            max 1
+        }
+    }
+    node "shell" {
+        max 1
+        value {
+            type "string"
+            enum "bash" "sh" "powershell"
+            min 1
+            max 1
+        }
+    }
+    node "env" {
+        max 1
+        children {
+            node {
+                value {
+                    type "string"
+                    min 1
+                    max 1
+                }
+            }
+        }
+    }
+}
+"#;
@@ -1,4 +1,6 @@
-use std::{fs::read_to_string, path::Path};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
 
 use miette::Diagnostic;
 use schemars::schema::RootSchema;
@@ -6,6 +8,10 @@ use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+mod kdl;
+
+pub use kdl::KDL_SCHEMA;
+
 #[derive(Error, Debug, Diagnostic)]
 pub enum IntegrationError {
     #[error("{0} is not a supported manifest format extension. Use toml,yaml,json or kdl")]
@@ -22,35 +28,271 @@ pub enum IntegrationError {
 
     #[error(transparent)]
     Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    TomlSer(#[from] toml::ser::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Kdl(#[from] knuffel::Error),
+
+    #[error(transparent)]
+    Glob(#[from] glob::GlobError),
+
+    #[error("manifest version {0} is not supported, versions 1 to {} are", MANIFEST_VERSION)]
+    UnsupportedVersion(u32),
+
+    #[error("{0} needs manifest version 2 or newer")]
+    RequiresVersion2(&'static str),
+
+    #[error("the manifest finds no components, set component_list or component_globs")]
+    NoComponentSource,
+
+    #[error("{0} is not a valid component glob: {1}")]
+    InvalidGlob(String, glob::PatternError),
+
+    #[error("{0:?} is not a valid environment variable name")]
+    InvalidEnvName(String),
+
+    #[error("native manifests read the metadata files directly and run no {0}")]
+    NativeWithScript(&'static str),
+}
+
+/// The name of the component in the directory, relative to the components directory.
+/// Its parts are separated by slashes on every platform.
+fn component_name(path: &Path) -> String {
+    path.components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
+/// The newest version of the manifest format. Older manifests are migrated to it when
+/// they are read.
+pub const MANIFEST_VERSION: u32 = 2;
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ForgeIntegrationManifest {
-    #[serde(rename = "component_list")]
+    /// Version of the manifest format, manifests without one are version 1
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(rename = "component_list", default)]
     pub component_list_script: Vec<String>,
-    #[serde(rename = "metadata_generation_script")]
+    /// Globs relative to `components_dir` matching the directories of components, found
+    /// in addition to the ones the component list script prints. Needs version 2
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub component_globs: Vec<String>,
+    #[serde(rename = "metadata_generation_script", default)]
     pub component_metadata_gen_script: Vec<String>,
+    /// Run for every component before the metadata generation script, the same way it
+    /// is run. Needs version 2
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_metadata_hook: Vec<String>,
+    /// Run for every component after the metadata generation script, the same way it
+    /// is run. Needs version 2
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_metadata_hook: Vec<String>,
     #[serde(rename = "metadata_filename", default = "default_metadata_filename")]
     pub component_metadata_filename: String,
+    #[serde(default)]
     pub change_to_component_dir: bool,
+    /// Directory the components listed by the component list script live in, relative
+    /// to the root of the repository
+    #[serde(default = "default_components_dir")]
+    pub components_dir: String,
+    /// Environment variables set for the scripts and hooks. Needs version 2
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// The repository keeps the metadata file of every component, which is read as it
+    /// is without running any script. Components are found by the component globs or
+    /// else by their metadata file. Needs version 2
+    #[serde(default)]
+    pub native: bool,
+    /// The shell the scripts and hooks are written for. Other shells than bash need
+    /// version 2
+    #[serde(default)]
+    pub shell: ScriptShell,
+}
+
+/// Shells the scripts of a manifest can be written for.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    knuffel::DecodeScalar,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptShell {
+    #[default]
+    Bash,
+    /// A POSIX shell, for workers without bash
+    Sh,
+    /// PowerShell, for workers on Windows
+    Powershell,
+}
+
+impl ScriptShell {
+    /// The name of the shell in manifests.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptShell::Bash => "bash",
+            ScriptShell::Sh => "sh",
+            ScriptShell::Powershell => "powershell",
+        }
+    }
+}
+
+fn default_version() -> u32 {
+    1
 }
 
 fn default_metadata_filename() -> String {
     "package.kdl".to_string()
 }
 
+fn default_components_dir() -> String {
+    "components".to_string()
+}
+
+impl ForgeIntegrationManifest {
+    /// The directory of the component relative to the root of the repository.
+    pub fn component_path(&self, component: &str) -> PathBuf {
+        Path::new(&self.components_dir).join(component)
+    }
+
+    /// The component the file at `path` relative to the root of the repository belongs
+    /// to. Of nested components the innermost one owns the file.
+    pub fn component_of<'a>(&self, components: &'a [String], path: &str) -> Option<&'a String> {
+        components
+            .iter()
+            .filter(|component| !component.is_empty())
+            .filter(|component| Path::new(path).starts_with(self.component_path(component)))
+            .max_by_key(|component| Path::new(component.as_str()).components().count())
+    }
+
+    /// The components whose directories below the repository at `root` match the
+    /// component globs.
+    pub fn glob_components(&self, root: &Path) -> Result<Vec<String>> {
+        let base = root.join(&self.components_dir);
+        let escaped_base = glob::Pattern::escape(&base.to_string_lossy());
+        let mut components = BTreeSet::new();
+        for pattern in &self.component_globs {
+            let pattern = format!("{}/{}", escaped_base, pattern);
+            let paths = glob::glob(&pattern)
+                .map_err(|e| IntegrationError::InvalidGlob(pattern.clone(), e))?;
+            for path in paths {
+                let path = path?;
+                if let (true, Ok(component)) = (path.is_dir(), path.strip_prefix(&base)) {
+                    components.insert(component_name(component));
+                }
+            }
+        }
+        Ok(components.into_iter().collect())
+    }
+
+    /// The components of a native repository at `root`, the ones matching the globs or
+    /// without globs every directory below `components_dir` with a metadata file.
+    pub fn native_components(&self, root: &Path) -> Result<Vec<String>> {
+        if !self.component_globs.is_empty() {
+            return self.glob_components(root);
+        }
+        let base = root.join(&self.components_dir);
+        let mut components = vec![];
+        let mut dirs = vec![base.clone()];
+        while let Some(dir) = dirs.pop() {
+            if dir.join(&self.component_metadata_filename).is_file() {
+                if let Ok(component) = dir.strip_prefix(&base) {
+                    components.push(component_name(component));
+                }
+            }
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    dirs.push(entry.path());
+                }
+            }
+        }
+        components.retain(|component| !component.is_empty());
+        components.sort();
+        Ok(components)
+    }
+
+    /// Check that the manifest can be used as it is written, before it is migrated.
+    pub fn validate(&self) -> Result<()> {
+        if self.version == 0 || self.version > MANIFEST_VERSION {
+            return Err(IntegrationError::UnsupportedVersion(self.version));
+        }
+        if self.version < 2 {
+            let v2_fields = [
+                ("component_globs", self.component_globs.is_empty()),
+                ("pre_metadata_hook", self.pre_metadata_hook.is_empty()),
+                ("post_metadata_hook", self.post_metadata_hook.is_empty()),
+                ("env", self.env.is_empty()),
+                ("native", !self.native),
+                ("shell", self.shell == ScriptShell::Bash),
+            ];
+            if let Some((field, _)) = v2_fields.into_iter().find(|(_, unset)| !unset) {
+                return Err(IntegrationError::RequiresVersion2(field));
+            }
+        }
+        if self.native {
+            let scripts = [
+                ("component_list", self.component_list_script.is_empty()),
+                ("metadata_generation_script", self.component_metadata_gen_script.is_empty()),
+                ("pre_metadata_hook", self.pre_metadata_hook.is_empty()),
+                ("post_metadata_hook", self.post_metadata_hook.is_empty()),
+            ];
+            if let Some((script, _)) = scripts.into_iter().find(|(_, unset)| !unset) {
+                return Err(IntegrationError::NativeWithScript(script));
+            }
+        } else if self.component_list_script.is_empty() && self.component_globs.is_empty() {
+            return Err(IntegrationError::NoComponentSource);
+        }
+        for pattern in &self.component_globs {
+            glob::Pattern::new(pattern)
+                .map_err(|e| IntegrationError::InvalidGlob(pattern.clone(), e))?;
+        }
+        if let Some(name) = self
+            .env
+            .keys()
+            .find(|name| name.is_empty() || name.contains(['=', '\0']))
+        {
+            return Err(IntegrationError::InvalidEnvName(name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Bring the manifest to the newest version. Version 1 manifests mean the same in
+    /// version 2, which only adds fields.
+    pub fn migrate(mut self) -> Self {
+        if self.version < 2 {
+            self.version = 2;
+        }
+        self
+    }
+}
+
 pub type Result<T> = miette::Result<T, IntegrationError>;
 
-pub fn read_forge_manifest<P: AsRef<Path>>(path: P) -> Result<ForgeIntegrationManifest> {
-    let path = path.as_ref();
-    let contents = read_to_string(path)?;
-    let config: ForgeIntegrationManifest = match path
+fn manifest_format(path: &Path) -> Result<String> {
+    Ok(path
         .extension()
         .ok_or(IntegrationError::NotSupportedFormat(String::from("None")))?
         .to_string_lossy()
-        .to_string()
-        .as_str()
-    {
+        .to_string())
+}
+
+/// Read and validate the manifest and migrate it to the newest version.
+pub fn read_forge_manifest<P: AsRef<Path>>(path: P) -> Result<ForgeIntegrationManifest> {
+    let path = path.as_ref();
+    let contents = read_to_string(path)?;
+    let config: ForgeIntegrationManifest = match manifest_format(path)?.as_str() {
         "toml" => {
             let fm = toml::from_str(&contents)?;
             Ok(fm)
@@ -63,9 +305,32 @@ pub fn read_forge_manifest<P: AsRef<Path>>(path: P) -> Result<ForgeIntegrationMa
             let fm = serde_json::from_str(&contents)?;
             Ok(fm)
         }
+        "kdl" => {
+            let fm = knuffel::parse::<kdl::KdlManifest>(&path.to_string_lossy(), &contents)?;
+            Ok(fm.into())
+        }
         other => Err(IntegrationError::NotSupportedFormat(other.to_owned())),
     }?;
-    Ok(config)
+    config.validate()?;
+    Ok(config.migrate())
+}
+
+/// Write the manifest in the format of the extension of `path`, like a migrated manifest
+/// back to the repository.
+pub fn write_forge_manifest<P: AsRef<Path>>(
+    path: P,
+    manifest: &ForgeIntegrationManifest,
+) -> Result<()> {
+    let path = path.as_ref();
+    let contents = match manifest_format(path)?.as_str() {
+        "toml" => toml::to_string_pretty(manifest)?,
+        "yaml" | "yml" => serde_yaml::to_string(manifest)?,
+        "json" => serde_json::to_string_pretty(manifest)?,
+        "kdl" => kdl::to_kdl(manifest),
+        other => return Err(IntegrationError::NotSupportedFormat(other.to_owned())),
+    };
+    write(path, contents)?;
+    Ok(())
 }
 
 pub fn emit_schema() -> Result<String> {
@@ -73,6 +338,11 @@ pub fn emit_schema() -> Result<String> {
     Ok(serde_json::to_string_pretty(&schema)?)
 }
 
+/// The schema of `.forge/manifest.kdl` in the KDL Schema Language.
+pub fn emit_kdl_schema() -> &'static str {
+    KDL_SCHEMA
+}
+
 pub fn get_schema() -> RootSchema {
     schema_for!(ForgeIntegrationManifest)
 }
@@ -89,6 +359,212 @@ mod tests {
         Ok(())
     }
 
+    fn manifest() -> ForgeIntegrationManifest {
+        toml::from_str(
+            r#"
+            component_list = ["ls components"]
+            metadata_generation_script = ["true"]
+            change_to_component_dir = true
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn component_of_matches_whole_directories() {
+        let manifest = manifest();
+        let components = vec![String::from("library/zlib"), String::from("library/zlib-ng")];
+        assert_eq!(
+            manifest.component_of(&components, "components/library/zlib-ng/Makefile"),
+            Some(&components[1])
+        );
+        assert_eq!(
+            manifest.component_of(&components, "components/library/zlib/package.kdl"),
+            Some(&components[0])
+        );
+        assert_eq!(manifest.component_of(&components, "tools/library/zlib/x"), None);
+        assert_eq!(manifest.component_of(&components, "components/library/zlib.p5m"), None);
+    }
+
+    #[test]
+    fn component_of_prefers_nested_components() {
+        let manifest = manifest();
+        let components = vec![
+            String::from("python"),
+            String::from("python/pip"),
+            String::from(""),
+        ];
+        assert_eq!(
+            manifest.component_of(&components, "components/python/pip/patches/01.patch"),
+            Some(&components[1])
+        );
+        assert_eq!(
+            manifest.component_of(&components, "components/python/package.kdl"),
+            Some(&components[0])
+        );
+        assert_eq!(manifest.component_of(&components, "README.md"), None);
+    }
+
+    #[test]
+    fn version_1_manifests_are_migrated() -> Result<()> {
+        let manifest = manifest();
+        assert_eq!(manifest.version, 1);
+        manifest.validate()?;
+        assert_eq!(manifest.migrate().version, MANIFEST_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn validate_rejects_invalid_manifests() {
+        let mut versioned = manifest();
+        versioned.env.insert(String::from("CC"), String::from("gcc"));
+        assert!(matches!(
+            versioned.validate(),
+            Err(IntegrationError::RequiresVersion2("env"))
+        ));
+        versioned.version = 2;
+        assert!(versioned.validate().is_ok());
+        versioned.version = 3;
+        assert!(matches!(
+            versioned.validate(),
+            Err(IntegrationError::UnsupportedVersion(3))
+        ));
+
+        let mut globbed = manifest();
+        globbed.version = 2;
+        globbed.component_list_script.clear();
+        assert!(matches!(
+            globbed.validate(),
+            Err(IntegrationError::NoComponentSource)
+        ));
+        globbed.component_globs.push(String::from("*/[a-"));
+        assert!(matches!(
+            globbed.validate(),
+            Err(IntegrationError::InvalidGlob(_, _))
+        ));
+        globbed.component_globs = vec![String::from("*/*")];
+        globbed.env.insert(String::from("A=B"), String::new());
+        assert!(matches!(
+            globbed.validate(),
+            Err(IntegrationError::InvalidEnvName(_))
+        ));
+    }
+
+    #[test]
+    fn glob_components_finds_component_directories() -> Result<()> {
+        let root =
+            std::env::temp_dir().join(format!("forge-integration-{}", std::process::id()));
+        for dir in ["library/zlib", "library/zlib-ng", "python/pip"] {
+            std::fs::create_dir_all(root.join("components").join(dir))?;
+        }
+        std::fs::write(root.join("components/library/Makefile"), "")?;
+        let mut manifest = manifest();
+        manifest.component_globs = vec![String::from("library/*"), String::from("python/*")];
+        let components = manifest.glob_components(&root);
+        std::fs::remove_dir_all(&root)?;
+        assert_eq!(components?, vec!["library/zlib", "library/zlib-ng", "python/pip"]);
+        Ok(())
+    }
+
+    #[test]
+    fn native_components_are_found_by_their_metadata_file() -> Result<()> {
+        let root =
+            std::env::temp_dir().join(format!("forge-integration-native-{}", std::process::id()));
+        for dir in ["library/zlib", "python/pip", "python/pip/vendored", "tools"] {
+            std::fs::create_dir_all(root.join("components").join(dir))?;
+        }
+        for dir in ["library/zlib", "python/pip", "python/pip/vendored"] {
+            std::fs::write(root.join("components").join(dir).join("package.kdl"), "")?;
+        }
+        let mut manifest = manifest();
+        manifest.version = 2;
+        manifest.native = true;
+        manifest.component_list_script.clear();
+        manifest.component_metadata_gen_script.clear();
+        let validated = manifest.validate();
+        let components = manifest.native_components(&root);
+        std::fs::remove_dir_all(&root)?;
+        validated?;
+        assert_eq!(components?, vec!["library/zlib", "python/pip", "python/pip/vendored"]);
+
+        manifest.post_metadata_hook = vec![String::from("true")];
+        assert!(matches!(
+            manifest.validate(),
+            Err(IntegrationError::NativeWithScript("post_metadata_hook"))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn written_manifests_read_back() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("forge-manifest-{}.toml", std::process::id()));
+        let mut manifest = manifest().migrate();
+        manifest.pre_metadata_hook = vec![String::from("gmake prep")];
+        manifest.env.insert(String::from("LANG"), String::from("C"));
+        write_forge_manifest(&path, &manifest)?;
+        let read = read_forge_manifest(&path);
+        std::fs::remove_file(&path)?;
+        let read = read?;
+        assert_eq!(read.version, 2);
+        assert_eq!(read.pre_metadata_hook, manifest.pre_metadata_hook);
+        assert_eq!(read.env, manifest.env);
+        Ok(())
+    }
+
+    #[test]
+    fn kdl_test() -> Result<()> {
+        let result = read_forge_manifest("./examples/oi-userland.kdl")?;
+        assert_eq!(result.component_list_script.len(), 1);
+        assert_eq!(
+            result.component_list_script[0].as_str(),
+            "./tools/bass-o-matic --workspace=./ --components=paths | sed -e 's;./components/;;'"
+        );
+        assert_eq!(result.version, MANIFEST_VERSION);
+        assert_eq!(result.component_metadata_filename, "package.kdl");
+        Ok(())
+    }
+
+    #[test]
+    fn kdl_manifests_read_back() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("forge-manifest-{}.kdl", std::process::id()));
+        let mut manifest = manifest().migrate();
+        manifest.component_globs = vec![String::from("library/*")];
+        manifest.pre_metadata_hook = vec![String::from("echo \"prep $1\"\tdone")];
+        manifest.env.insert(String::from("LANG"), String::from("C"));
+        write_forge_manifest(&path, &manifest)?;
+        let read = read_forge_manifest(&path);
+        std::fs::remove_file(&path)?;
+        let read = read?;
+        assert_eq!(read.component_list_script, manifest.component_list_script);
+        assert_eq!(read.component_globs, manifest.component_globs);
+        assert_eq!(read.pre_metadata_hook, manifest.pre_metadata_hook);
+        assert_eq!(read.change_to_component_dir, manifest.change_to_component_dir);
+        assert_eq!(read.env, manifest.env);
+        Ok(())
+    }
+
+    #[test]
+    fn kdl_schema_covers_every_node() {
+        for node in [
+            "version",
+            "component-list",
+            "component-globs",
+            "metadata-generation-script",
+            "pre-metadata-hook",
+            "post-metadata-hook",
+            "metadata-filename",
+            "change-to-component-dir",
+            "components-dir",
+            "native",
+            "shell",
+            "env",
+        ] {
+            assert!(emit_kdl_schema().contains(&format!("node \"{}\"", node)));
+        }
+    }
+
     #[test]
     fn toml_test() -> Result<()> {
         let result = read_forge_manifest("./examples/oi-userland.toml")?;
@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter};
+
+/// An action of an IPS manifest: `file payload path=usr/bin/foo mode=0555`.
+///
+/// Attributes keep the order they were written in, attributes with several values
+/// appear once per value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Action {
+    pub name: String,
+    pub payload: Option<String>,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Action {
+    /// Parse one action line. Continuation lines have to be joined already.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = split_words(line)?.into_iter();
+        let name = words.next().ok_or(String::from("empty action"))?;
+        if name.contains('=') {
+            return Err(format!("action {} has no name", line.trim()));
+        }
+
+        let mut action = Action {
+            name,
+            payload: None,
+            attributes: vec![],
+        };
+        for (position, word) in words.enumerate() {
+            match word.split_once('=') {
+                Some((key, value)) if !key.is_empty() => {
+                    action.attributes.push((key.to_string(), value.to_string()))
+                }
+                // Only the first word after the name can be a payload reference
+                None if position == 0 => action.payload = Some(word),
+                _ => return Err(format!("{} is not an attribute", word)),
+            }
+        }
+        Ok(action)
+    }
+
+    pub fn values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.attributes
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.values(key).next().is_some()
+    }
+
+    /// Replace all values of `key` with `value`.
+    pub fn set(&mut self, key: &str, value: String) {
+        match self.attributes.iter().position(|(k, _)| k == key) {
+            Some(first) => {
+                self.attributes[first].1 = value;
+                let mut index = 0;
+                self.attributes.retain(|(k, _)| {
+                    index += 1;
+                    index - 1 <= first || k != key
+                });
+            }
+            None => self.attributes.push((key.to_string(), value)),
+        }
+    }
+
+    pub fn add(&mut self, key: &str, value: String) {
+        self.attributes.push((key.to_string(), value));
+    }
+
+    /// Remove the values of `key` matching `value`, or all of them without a matcher.
+    pub fn delete(&mut self, key: &str, value: Option<&regex::Regex>) {
+        self.attributes
+            .retain(|(k, v)| k != key || value.is_some_and(|value| !value.is_match(v)));
+    }
+}
+
+/// Rendered like pkgfmt writes actions, values with whitespace or quotes get quoted.
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(payload) = &self.payload {
+            write!(f, " {}", quote(payload))?;
+        }
+        for (key, value) in self.attributes.iter() {
+            write!(f, " {}={}", key, quote(value))?;
+        }
+        Ok(())
+    }
+}
+
+fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains(char::is_whitespace) || value.contains('"') {
+        if value.contains('"') && !value.contains('\'') {
+            format!("'{}'", value)
+        } else {
+            format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+/// Split a line into words at whitespace. Quotes group words and are removed, inside
+/// quotes a backslash escapes the quote character and itself.
+pub(crate) fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == '\\' => match chars.next() {
+                Some(next) if next == q || next == '\\' => word.push(next),
+                Some(next) => {
+                    word.push('\\');
+                    word.push(next);
+                }
+                None => word.push('\\'),
+            },
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unterminated quote in {}", line.trim()));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
@@ -0,0 +1,409 @@
+//! A native interpreter of the `<transform>` and `<include>` directives of IPS manifests,
+//! compatible with what pkgmogrify does to them.
+mod action;
+mod transform;
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+pub use action::Action;
+pub use transform::{Operation, Transform};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum MogrifyError {
+    #[error("could not read {path}")]
+    #[diagnostic(code(mogrify::io))]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{file}:{line}: {reason}")]
+    #[diagnostic(code(mogrify::parse))]
+    Parse {
+        file: String,
+        line: usize,
+        reason: String,
+    },
+    #[error("invalid transform <transform {transform}>: {reason}")]
+    #[diagnostic(code(mogrify::invalid_transform))]
+    InvalidTransform { transform: String, reason: String },
+    #[error("invalid pattern {pattern}")]
+    #[diagnostic(code(mogrify::pattern))]
+    Pattern {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+    #[error("include {0} was not found in any include directory")]
+    #[diagnostic(
+        code(mogrify::include_not_found),
+        help("pass the directory the file is in as include directory")
+    )]
+    IncludeNotFound(String),
+    #[error("attribute {attribute} is referenced but not set on {action}")]
+    #[diagnostic(code(mogrify::missing_attribute))]
+    MissingAttribute { attribute: String, action: String },
+    #[error("transform exited with code {code}: {}", .message.clone().unwrap_or_default())]
+    #[diagnostic(code(mogrify::exit))]
+    Exit { code: i32, message: Option<String> },
+}
+
+pub type MogrifyResult<T> = Result<T, MogrifyError>;
+
+/// A line of a manifest the transforms are applied to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    Action(Action),
+    /// Comments and blank lines are passed through unchanged
+    Comment(String),
+}
+
+/// Manifests and the transforms to apply to them, read like the file arguments of
+/// pkgmogrify. All transforms apply to all actions in the order they were read, no
+/// matter whether they appear before or after the action.
+#[derive(Debug, Default)]
+pub struct Mogrify {
+    include_dirs: Vec<PathBuf>,
+    macros: HashMap<String, String>,
+    transforms: Vec<Transform>,
+    entries: Vec<Entry>,
+}
+
+impl Mogrify {
+    /// `include_dirs` are searched for `<include>` files like the `-I` option of
+    /// pkgmogrify.
+    pub fn new(include_dirs: Vec<PathBuf>) -> Self {
+        Mogrify {
+            include_dirs,
+            ..Default::default()
+        }
+    }
+
+    /// Replace `$(name)` with `value` in everything read afterwards, like `-D`.
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.macros.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn read_file<P: AsRef<Path>>(&mut self, path: P) -> MogrifyResult<()> {
+        let path = path.as_ref();
+        let content = read_to_string(path).map_err(|source| MogrifyError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        self.read_str(&path.display().to_string(), &content)
+    }
+
+    /// Read the manifest `content`, `name` is only used in error messages.
+    pub fn read_str(&mut self, name: &str, content: &str) -> MogrifyResult<()> {
+        for (line_number, line) in logical_lines(content) {
+            let line = self.expand_macros(&line);
+            let parse_error = |reason: String| MogrifyError::Parse {
+                file: name.to_string(),
+                line: line_number,
+                reason,
+            };
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                self.entries
+                    .push(Entry::Comment(line.trim_end().to_string()));
+            } else if let Some(directive) = trimmed.strip_prefix('<') {
+                let directive = directive
+                    .strip_suffix('>')
+                    .ok_or_else(|| parse_error(String::from("directive is missing its >")))?;
+                if let Some(rule) = directive.strip_prefix("transform") {
+                    self.transforms.push(Transform::parse(rule)?);
+                } else if let Some(file) = directive.strip_prefix("include") {
+                    let file = file.trim();
+                    let path = self.find_include(file)?;
+                    self.read_file(path)?;
+                } else {
+                    return Err(parse_error(format!("unknown directive <{}>", directive)));
+                }
+            } else {
+                self.entries
+                    .push(Entry::Action(Action::parse(trimmed).map_err(parse_error)?));
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_macros(&self, line: &str) -> String {
+        self.macros
+            .iter()
+            .fold(line.to_string(), |line, (name, value)| {
+                line.replace(&format!("$({})", name), value)
+            })
+    }
+
+    fn find_include(&self, file: &str) -> MogrifyResult<PathBuf> {
+        let path = Path::new(file);
+        if path.is_absolute() && path.exists() {
+            return Ok(path.to_path_buf());
+        }
+        self.include_dirs
+            .iter()
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.exists())
+            .ok_or_else(|| MogrifyError::IncludeNotFound(file.to_string()))
+    }
+
+    /// The entries read with all transforms applied.
+    pub fn apply(&self) -> MogrifyResult<Vec<Entry>> {
+        let mut result = vec![];
+        for entry in self.entries.iter() {
+            match entry {
+                Entry::Comment(_) => result.push(entry.clone()),
+                Entry::Action(action) => {
+                    let (action, emitted) = self.apply_to(action.clone())?;
+                    if let Some(action) = action {
+                        result.push(Entry::Action(action));
+                    }
+                    result.extend(emitted);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// `action` after the transforms, if no transform dropped it, and the lines they
+    /// emitted.
+    fn apply_to(&self, mut action: Action) -> MogrifyResult<(Option<Action>, Vec<Entry>)> {
+        let mut emitted = vec![];
+        for transform in self.transforms.iter() {
+            if !transform.matches(&action) {
+                continue;
+            }
+            match &transform.operation {
+                Operation::Drop => return Ok((None, emitted)),
+                Operation::Set { attribute, value } => {
+                    let value = transform::substitute(value, &action)?;
+                    action.set(attribute, value);
+                }
+                Operation::Add { attribute, value } => {
+                    let value = transform::substitute(value, &action)?;
+                    action.add(attribute, value);
+                }
+                Operation::Default { attribute, value } => {
+                    if !action.has(attribute) {
+                        let value = transform::substitute(value, &action)?;
+                        action.add(attribute, value);
+                    }
+                }
+                Operation::Delete { attribute, value } => action.delete(attribute, value.as_ref()),
+                Operation::Edit {
+                    attribute,
+                    pattern,
+                    replacement,
+                } => {
+                    let replacement = transform::substitute(replacement, &action)?;
+                    for (_, value) in action.attributes.iter_mut().filter(|(k, _)| k == attribute) {
+                        let edited = pattern
+                            .replace_all(value, replacement.as_str())
+                            .into_owned();
+                        *value = edited;
+                    }
+                }
+                Operation::Emit(line) => {
+                    let line = transform::substitute(line, &action)?;
+                    let trimmed = line.trim();
+                    emitted.push(if trimmed.is_empty() || trimmed.starts_with('#') {
+                        Entry::Comment(line)
+                    } else {
+                        Entry::Action(Action::parse(trimmed).map_err(|reason| {
+                            MogrifyError::InvalidTransform {
+                                transform: format!("emit {}", line),
+                                reason,
+                            }
+                        })?)
+                    });
+                }
+                Operation::Exit { code, message } => {
+                    return Err(MogrifyError::Exit {
+                        code: *code,
+                        message: message.clone(),
+                    })
+                }
+            }
+        }
+        Ok((Some(action), emitted))
+    }
+
+    /// The transformed manifest with one line per entry.
+    pub fn render(&self) -> MogrifyResult<String> {
+        Ok(self
+            .apply()?
+            .iter()
+            .map(|entry| match entry {
+                Entry::Action(action) => action.to_string() + "\n",
+                Entry::Comment(comment) => comment.clone() + "\n",
+            })
+            .collect())
+    }
+}
+
+/// Check the `<transform>` directives in `content` parse without applying them.
+/// Includes are not followed, they only exist on the hosts building packages.
+pub fn validate_transforms(content: &str) -> MogrifyResult<()> {
+    for (line_number, line) in logical_lines(content) {
+        let trimmed = line.trim();
+        if let Some(directive) = trimmed.strip_prefix("<transform") {
+            let rule = directive.strip_suffix('>').ok_or(MogrifyError::Parse {
+                file: String::from("transform"),
+                line: line_number,
+                reason: String::from("directive is missing its >"),
+            })?;
+            Transform::parse(rule)?;
+        } else if !(trimmed.is_empty()
+            || trimmed.starts_with('#')
+            || trimmed.starts_with("<include"))
+        {
+            return Err(MogrifyError::Parse {
+                file: String::from("transform"),
+                line: line_number,
+                reason: format!("{} is not a transform or include", trimmed),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The lines of `content` with the ones ending in a backslash joined to the next, with
+/// the number of the line they start at.
+fn logical_lines(content: &str) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    let mut current: Option<(usize, String)> = None;
+    for (index, line) in content.lines().enumerate() {
+        let (start, mut joined) = current.take().unwrap_or((index + 1, String::new()));
+        match line.strip_suffix('\\') {
+            Some(continued) => {
+                joined.push_str(continued);
+                current = Some((start, joined));
+            }
+            None => {
+                joined.push_str(line);
+                lines.push((start, joined));
+            }
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mogrify(manifests: &[&str]) -> String {
+        let mut mogrify = Mogrify::new(vec![]);
+        for (index, manifest) in manifests.iter().enumerate() {
+            mogrify.read_str(&index.to_string(), manifest).unwrap();
+        }
+        mogrify.render().unwrap()
+    }
+
+    #[test]
+    fn applies_keep_transforms_of_package_sections() {
+        let base = "set name=pkg.fmri value=pkg:/library/foo@1.0\n\
+            <transform file link hardlink path=.* -> default keep false>\n\
+            <transform file path=usr/lib/.* -> set keep true>\n\
+            <transform file link hardlink keep=false -> drop>\n\
+            <transform file link hardlink keep=true -> delete keep true>\n\
+            <transform dir path=.* -> drop>\n";
+        let filelist = "dir  path=usr owner=root group=bin mode=0755\n\
+            file path=usr/lib/libfoo.so.1 owner=root group=bin mode=0755\n\
+            file path=usr/bin/foo owner=root group=bin mode=0555\n";
+        assert_eq!(
+            mogrify(&[base, filelist]),
+            "set name=pkg.fmri value=pkg:/library/foo@1.0\n\
+             file path=usr/lib/libfoo.so.1 owner=root group=bin mode=0755\n"
+        );
+    }
+
+    #[test]
+    fn later_transforms_apply_to_earlier_actions() {
+        let manifest = "file path=usr/share/man/man1/foo.1 owner=root group=bin mode=0444\n\
+            <transform file path=usr/share/man/.* -> add facet.doc.man true>\n\
+            <transform file path=usr/share/man/.* -> edit path ^usr/share/man/(man\\d)/ usr/man/\\1/>\n";
+        assert_eq!(
+            mogrify(&[manifest]),
+            "file path=usr/man/man1/foo.1 owner=root group=bin mode=0444 facet.doc.man=true\n"
+        );
+    }
+
+    #[test]
+    fn substitutes_attributes_and_emits_lines() {
+        let manifest = "# header\n\
+            link path=usr/bin/vi target=vim\n\
+            <transform link path=usr/bin/(.*) -> emit link path=usr/gnu/bin/%<target> target=../../bin/%{target}>\n\
+            <transform link -> set \"description\" \"link to %{target}\">\n";
+        assert_eq!(
+            mogrify(&[manifest]),
+            "# header\n\
+             link path=usr/bin/vi target=vim description=\"link to vim\"\n\
+             link path=usr/gnu/bin/vim target=../../bin/vim\n"
+        );
+    }
+
+    #[test]
+    fn parses_continuations_payloads_and_quotes() {
+        let manifest = "license LICENSE \\\n  license='GPL v2' \\\n  must-display=true\n";
+        assert_eq!(
+            mogrify(&[manifest]),
+            "license LICENSE license=\"GPL v2\" must-display=true\n"
+        );
+    }
+
+    #[test]
+    fn delete_only_removes_matching_values() {
+        let manifest = "depend fmri=a fmri=b type=require-any\n\
+            <transform depend -> delete fmri b>\n";
+        assert_eq!(mogrify(&[manifest]), "depend fmri=a type=require-any\n");
+    }
+
+    #[test]
+    fn validates_transforms() {
+        assert!(validate_transforms("<transform file path=.* -> drop>\n<include foo.mog>").is_ok());
+        assert!(matches!(
+            validate_transforms("<transform file path=.* -> frobnicate>"),
+            Err(MogrifyError::InvalidTransform { .. })
+        ));
+        assert!(matches!(
+            validate_transforms("<transform file path=( -> drop>"),
+            Err(MogrifyError::Pattern { .. })
+        ));
+        assert!(matches!(
+            validate_transforms("<transform file path=.* drop>"),
+            Err(MogrifyError::InvalidTransform { .. })
+        ));
+    }
+
+    #[test]
+    fn finds_relative_includes_in_include_dirs() {
+        let dir = std::env::temp_dir().join(format!("mogrify-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("transforms")).unwrap();
+        std::fs::write(
+            dir.join("transforms").join("docs.mog"),
+            "<transform file path=usr/share/doc/.* -> drop>\n",
+        )
+        .unwrap();
+
+        let mut mogrify = Mogrify::new(vec![PathBuf::from("/nonexistent"), dir.clone()]);
+        mogrify
+            .read_str(
+                "manifest",
+                "<include transforms/docs.mog>\n\
+                 file path=usr/share/doc/README\n\
+                 file path=usr/bin/foo\n",
+            )
+            .unwrap();
+        let rendered = mogrify.render().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rendered, "file path=usr/bin/foo\n");
+    }
+}
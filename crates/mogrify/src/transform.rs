@@ -0,0 +1,203 @@
+use regex::Regex;
+
+use crate::action::{split_words, Action};
+use crate::{MogrifyError, MogrifyResult};
+
+/// What a transform does to the actions it matches.
+#[derive(Debug, Clone)]
+pub enum Operation {
+    /// Remove the action from the manifest
+    Drop,
+    /// Replace all values of the attribute
+    Set { attribute: String, value: String },
+    /// Add a value to the attribute
+    Add { attribute: String, value: String },
+    /// Set the attribute if the action does not have it
+    Default { attribute: String, value: String },
+    /// Remove the values of the attribute matching the pattern, all without one
+    Delete {
+        attribute: String,
+        value: Option<Regex>,
+    },
+    /// Replace what the pattern matches in the values of the attribute
+    Edit {
+        attribute: String,
+        pattern: Regex,
+        replacement: String,
+    },
+    /// Add a line after the action
+    Emit(String),
+    /// Stop processing the manifests
+    Exit { code: i32, message: Option<String> },
+}
+
+/// A `<transform types matchers -> operation>` rule.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    /// Action names the transform applies to, all if empty
+    pub types: Vec<String>,
+    /// Attributes whose values must all fully match the patterns
+    pub matchers: Vec<(String, Regex)>,
+    pub operation: Operation,
+}
+
+impl Transform {
+    /// Parse what is between `<transform` and `>`.
+    pub fn parse(rule: &str) -> MogrifyResult<Self> {
+        let invalid = |reason: String| MogrifyError::InvalidTransform {
+            transform: rule.trim().to_string(),
+            reason,
+        };
+
+        let (selector, operation) = rule
+            .split_once("->")
+            .ok_or_else(|| invalid(String::from("there is no -> before the operation")))?;
+
+        let mut types = vec![];
+        let mut matchers = vec![];
+        for word in split_words(selector).map_err(invalid)? {
+            match word.split_once('=') {
+                Some((attribute, pattern)) => {
+                    matchers.push((attribute.to_string(), anchored(pattern)?))
+                }
+                None => types.push(word),
+            }
+        }
+
+        let words = split_words(operation).map_err(invalid)?;
+        let argument = |index: usize| -> MogrifyResult<String> {
+            words
+                .get(index)
+                .cloned()
+                .ok_or_else(|| invalid(format!("{} needs {} arguments", words[0], index)))
+        };
+        let value = |index: usize| argument(index).map(|_| words[index..].join(" "));
+        let operation = match words.first().map(String::as_str) {
+            Some("drop") => Operation::Drop,
+            Some("set") => Operation::Set {
+                attribute: argument(1)?,
+                value: value(2)?,
+            },
+            Some("add") => Operation::Add {
+                attribute: argument(1)?,
+                value: value(2)?,
+            },
+            Some("default") => Operation::Default {
+                attribute: argument(1)?,
+                value: value(2)?,
+            },
+            Some("delete") => Operation::Delete {
+                attribute: argument(1)?,
+                value: words.get(2).map(|p| anchored(p)).transpose()?,
+            },
+            Some("edit") => Operation::Edit {
+                attribute: argument(1)?,
+                pattern: compile(&argument(2)?)?,
+                replacement: python_replacement(words.get(3).map(String::as_str).unwrap_or("")),
+            },
+            Some("emit") => Operation::Emit(
+                operation
+                    .trim_start()
+                    .trim_start_matches("emit")
+                    .trim()
+                    .to_string(),
+            ),
+            Some("exit") => Operation::Exit {
+                code: words
+                    .get(1)
+                    .map(|c| c.parse::<i32>())
+                    .transpose()
+                    .map_err(|_| invalid(format!("{} is not an exit code", words[1])))?
+                    .unwrap_or(0),
+                message: (words.len() > 2).then(|| words[2..].join(" ")),
+            },
+            Some(other) => return Err(invalid(format!("unknown operation {}", other))),
+            None => return Err(invalid(String::from("no operation after ->"))),
+        };
+
+        Ok(Transform {
+            types,
+            matchers,
+            operation,
+        })
+    }
+
+    /// Whether the transform applies to `action`. Every value of a matched attribute
+    /// has to match, actions without the attribute are never matched.
+    pub fn matches(&self, action: &Action) -> bool {
+        (self.types.is_empty() || self.types.contains(&action.name))
+            && self.matchers.iter().all(|(attribute, pattern)| {
+                let mut values = action.values(attribute).peekable();
+                values.peek().is_some() && values.all(|v| pattern.is_match(v))
+            })
+    }
+}
+
+/// Replace `%{attribute}` and `%<attribute>` with the values of the attribute of
+/// `action`.
+pub(crate) fn substitute(value: &str, action: &Action) -> MogrifyResult<String> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('%') {
+        let close = match rest[start + 1..].chars().next() {
+            Some('{') => '}',
+            Some('<') => '>',
+            _ => {
+                result.push_str(&rest[..start + 1]);
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+        let Some(end) = rest[start + 2..].find(close) else {
+            break;
+        };
+        let attribute = &rest[start + 2..start + 2 + end];
+        let values = action.values(attribute).cloned().collect::<Vec<_>>();
+        if values.is_empty() {
+            return Err(MogrifyError::MissingAttribute {
+                attribute: attribute.to_string(),
+                action: action.to_string(),
+            });
+        }
+        result.push_str(&rest[..start]);
+        result.push_str(&values.join(" "));
+        rest = &rest[start + 3 + end..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn compile(pattern: &str) -> MogrifyResult<Regex> {
+    Regex::new(pattern).map_err(|source| MogrifyError::Pattern {
+        pattern: pattern.to_string(),
+        source,
+    })
+}
+
+/// Matchers have to match the whole value like Python's re.match with a trailing $.
+fn anchored(pattern: &str) -> MogrifyResult<Regex> {
+    Regex::new(&format!("^(?:{})$", pattern)).map_err(|source| MogrifyError::Pattern {
+        pattern: pattern.to_string(),
+        source,
+    })
+}
+
+/// pkgmogrify replacements refer to groups as `\1`, the regex crate as `${1}`.
+fn python_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                let mut group = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    group.push(digit);
+                }
+                result.push_str(&format!("${{{}}}", group));
+            }
+            '$' => result.push_str("$$"),
+            c => result.push(c),
+        }
+    }
+    result
+}
@@ -1,19 +1,39 @@
 use std::path::PathBuf;
 
-use crate::build::{run_build, BuildArgs};
+use crate::build::{
+    diff_proto, promote_staged_packages, report_elf_dependencies, run_build,
+    verify_reproducibility, BuildArgs, DiffProtoArgs,
+};
+use crate::changelog::{print_changelog, ChangelogArgs};
 use crate::component::open_component_local;
-use crate::create::create_component;
+use crate::create::{create_component, create_component_from_registry, CreateFrom};
+use crate::devshell::{run_devshell, DevshellArgs};
 use crate::forge::{handle_forge_interaction, ForgeArgs};
+use crate::import::{import_component, ImportFrom};
+use crate::lint::{lint_component, LintArgs};
 use crate::metadata;
+use crate::patch::{manage_patches, PatchArgs};
+use crate::review::{review_change_requests, ReviewArgs};
 use crate::modify::{edit_component, EditArgs};
+use crate::sbom::{print_sbom, SbomArgs};
 use crate::sources::download_sources;
+use crate::sources::lock::{handle_sources, SourcesArgs};
+use crate::status::{print_status, StatusArgs};
 use clap::{Parser, Subcommand, ValueEnum};
 use config::Settings;
 use gate::Gate;
 use miette::{Context, IntoDiagnostic};
 use strum::Display;
+use workspace::Architecture;
 
 #[derive(Debug, Parser)]
+#[command(version, after_help = "Exit codes:\n  \
+    0   success\n  \
+    2   invalid arguments\n  \
+    65  user error, the recipe, gate or arguments are wrong\n  \
+    69  environment error, a tool is missing or the build host is not configured\n  \
+    70  internal error, a bug in pkgdev\n  \
+    75  transient error, a network failure worth retrying")]
 pub struct Args {
     #[arg(long, global = true)]
     /// Path to the gate kdl file adding gate wide settings to this all components
@@ -41,6 +61,8 @@ pub enum Commands {
         args: ComponentArgs,
         #[clap(default_value_t = metadata::MetadataFormat::default())]
         format: metadata::MetadataFormat,
+        #[clap(subcommand)]
+        action: Option<metadata::MetadataAction>,
     },
     #[clap(name = "generate")]
     Generate {
@@ -48,10 +70,19 @@ pub enum Commands {
         kind: GenerateSchemaKind,
     },
     #[clap(name = "create")]
+    #[clap(args_conflicts_with_subcommands = true)]
     Create {
-        fmri: String,
+        fmri: Option<String>,
         #[clap(flatten)]
         args: ComponentArgs,
+        #[clap(subcommand)]
+        from: Option<CreateFrom>,
+    },
+    /// Create a component from the package of another packaging system
+    #[clap(name = "import")]
+    Import {
+        #[clap(subcommand)]
+        from: ImportFrom,
     },
     #[clap(name = "edit")]
     Edit {
@@ -73,6 +104,98 @@ pub enum Commands {
         #[command(flatten)]
         args: BuildArgs,
     },
+    #[clap(name = "verify-repro")]
+    VerifyRepro {
+        #[arg(short, long, default_value = ".")]
+        component: PathBuf,
+
+        #[command(flatten)]
+        args: BuildArgs,
+    },
+    #[clap(name = "diff-proto")]
+    DiffProto {
+        #[arg(short, long, default_value = ".")]
+        component: PathBuf,
+
+        #[command(flatten)]
+        args: DiffProtoArgs,
+    },
+    #[clap(name = "elf-deps")]
+    ElfDeps {
+        #[arg(short, long, default_value = ".")]
+        component: PathBuf,
+
+        /// Write the libraries the build delivers to the pkg5 file of the component
+        #[arg(long, default_value = "false")]
+        record: bool,
+
+        /// Architecture of the build to check (i386, amd64, aarch64, sparc)
+        #[arg(long)]
+        arch: Option<Architecture>,
+    },
+    /// Move the packages of a staging repository into the public repository
+    #[clap(name = "promote")]
+    Promote {
+        /// Name of the staging repository the packages were built with `--staging`
+        staging: String,
+
+        /// Keep the staging repository after the packages were promoted
+        #[arg(long, default_value = "false")]
+        keep: bool,
+
+        /// Copy the newest packages of the public repository into a snapshot repository
+        /// of this name after the promotion, used to publish releases
+        #[arg(long)]
+        snapshot: Option<String>,
+    },
+    #[clap(name = "lint")]
+    Lint {
+        #[command(flatten)]
+        args: LintArgs,
+    },
+    /// Print a CycloneDX or SPDX bill of materials of the component or the gate
+    #[clap(name = "sbom")]
+    Sbom {
+        #[command(flatten)]
+        args: SbomArgs,
+    },
+    /// Add, remove, renumber or refresh the patches of the component
+    #[clap(name = "patch")]
+    Patch {
+        #[command(flatten)]
+        args: PatchArgs,
+    },
+    /// Start a shell in the patched sources and turn the changes made in it into a patch
+    #[clap(name = "devshell")]
+    Devshell {
+        #[command(flatten)]
+        args: DevshellArgs,
+    },
+    /// Pin the sources of the component to the archives upstream serves now
+    #[clap(name = "sources")]
+    Sources {
+        #[command(flatten)]
+        args: SourcesArgs,
+    },
+    /// Show the versions, open change requests and last builds of the components you
+    /// maintain in a gate on the forge
+    #[clap(name = "status")]
+    Status {
+        #[command(flatten)]
+        args: StatusArgs,
+    },
+    /// Write what changed in a gate between two snapshots or in a time range as Markdown
+    #[clap(name = "changelog")]
+    Changelog {
+        #[command(flatten)]
+        args: ChangelogArgs,
+    },
+    /// Review the open change requests of the forge in a terminal UI
+    #[clap(name = "review")]
+    Review {
+        #[command(flatten)]
+        args: ReviewArgs,
+    },
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -87,6 +210,8 @@ pub enum GenerateSchemaKind {
     #[default]
     ComponentRecipe,
     ForgeIntegrationManifest,
+    /// The integration manifest in the KDL Schema Language
+    ForgeIntegrationManifestKdl,
 }
 
 pub async fn run(args: Args) -> miette::Result<()> {
@@ -110,7 +235,14 @@ pub async fn run(args: Args) -> miette::Result<()> {
     };
 
     match args.command {
-        Commands::Metadata { args, format } => metadata::print_component(args, format),
+        Commands::Metadata {
+            args,
+            format,
+            action,
+        } => match action {
+            Some(action) => metadata::exchange_recipe(args, action),
+            None => metadata::print_component(args, format),
+        },
         Commands::Generate { kind } => match kind {
             GenerateSchemaKind::ComponentRecipe => {
                 let schema = component::get_schema();
@@ -128,22 +260,75 @@ pub async fn run(args: Args) -> miette::Result<()> {
                 );
                 Ok(())
             }
+            GenerateSchemaKind::ForgeIntegrationManifestKdl => {
+                print!("{}", integration::emit_kdl_schema());
+                Ok(())
+            }
         },
         Commands::Download { component } => {
             let component = open_component_local(&component, &gate)?;
-            download_sources(&component, &wks, true)
+            download_sources(&component, &wks, true, &settings)
                 .await
                 .wrap_err("download failed")
         }
-        Commands::Create { fmri, args } => create_component(args, fmri),
+        Commands::Create { fmri, args, from } => match (from, fmri) {
+            (Some(from), _) => create_component_from_registry(from).await,
+            (None, Some(fmri)) => create_component(args, fmri),
+            (None, None) => Err(miette::miette!(
+                "either an fmri or one of from-crate, from-pypi, from-npm or interactive is needed"
+            )),
+        },
+        Commands::Import { from } => import_component(from).await,
         Commands::Edit { component, args } => edit_component(component, gate, args),
         Commands::Forge { args } => Ok(handle_forge_interaction(&args).await?),
         Commands::Build { component, args } => {
             let component =
                 open_component_local(component, &gate).wrap_err("cannot open component")?;
+            let wks = wks.with_arch(args.arch.unwrap_or_default())?;
             run_build(&component, &gate, &wks, &settings, &args)
                 .await
                 .wrap_err("build failed")
         }
+        Commands::VerifyRepro { component, args } => {
+            let component =
+                open_component_local(component, &gate).wrap_err("cannot open component")?;
+            let wks = wks.with_arch(args.arch.unwrap_or_default())?;
+            verify_reproducibility(&component, &gate, &wks, &settings, &args)
+                .await
+                .wrap_err("reproducibility check failed")
+        }
+        Commands::DiffProto { component, args } => {
+            let component =
+                open_component_local(component, &gate).wrap_err("cannot open component")?;
+            let wks = wks.with_arch(args.arch.unwrap_or_default())?;
+            diff_proto(&component, &wks, &args).wrap_err("comparing the build failed")
+        }
+        Commands::ElfDeps {
+            component,
+            record,
+            arch,
+        } => {
+            let component =
+                open_component_local(component, &gate).wrap_err("cannot open component")?;
+            let wks = wks.with_arch(arch.unwrap_or_default())?;
+            report_elf_dependencies(&component, &gate, &wks, record)
+                .await
+                .wrap_err("ELF dependency check failed")
+        }
+        Commands::Promote {
+            staging,
+            keep,
+            snapshot,
+        } => promote_staged_packages(&settings, &staging, keep, snapshot.as_deref())
+            .map(|_| ())
+            .wrap_err("promotion failed"),
+        Commands::Lint { args } => lint_component(args).await,
+        Commands::Sbom { args } => print_sbom(args, &gate, &wks),
+        Commands::Patch { args } => manage_patches(args, &wks),
+        Commands::Devshell { args } => run_devshell(args, &wks, &settings).await,
+        Commands::Sources { args } => handle_sources(args, &settings).await,
+        Commands::Status { args } => print_status(args, &gate).await,
+        Commands::Changelog { args } => print_changelog(args, &gate).await,
+        Commands::Review { args } => review_change_requests(args).await,
     }
 }
@@ -0,0 +1,317 @@
+use std::fs::{self, DirBuilder, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use component::Component;
+use gate::Gate;
+use miette::{IntoDiagnostic, Result, WrapErr};
+use workspace::Workspace;
+
+use crate::build::normalize::source_date_epoch;
+use crate::build::util::{installed_size, run, runtime_dependencies, PackageNaming};
+
+/// Alpine package names only allow lowercase alphanumerics and `+-._`.
+const NAMING: PackageNaming = PackageNaming {
+    name_chars: |c| matches!(c, 'a'..='z' | '0'..='9' | '+' | '-' | '.' | '_'),
+    lowercase: true,
+    architectures: ["x86", "x86_64", "aarch64", "sparc64"],
+    default_revision: "0",
+};
+
+fn derive_package_name(name: &str) -> String {
+    NAMING.package_name(name)
+}
+
+/// Alpine versions carry the package release as `-r<n>`.
+fn derive_version(pkg: &Component) -> String {
+    let (version, revision) = NAMING.version(pkg);
+    format!("{}-r{}", version, revision)
+}
+
+fn depends(pkg: &Component) -> Vec<String> {
+    runtime_dependencies(pkg)
+        .map(|d| derive_package_name(&d.name))
+        .collect()
+}
+
+/// The .PKGINFO of the control segment of the package.
+fn generate_pkginfo(
+    pkg: &Component,
+    arch: &str,
+    size: u64,
+    build_date: i64,
+    datahash: &str,
+    profile: Option<&str>,
+) -> Result<String> {
+    let recipe = &pkg.recipe;
+    let summary = recipe.summary.clone().ok_or(miette::miette!(
+        "{} has no summary which the package description is made of",
+        pkg.get_name()
+    ))?;
+    let name = derive_package_name(&recipe.name);
+
+    let mut lines = vec![
+        String::from("# Generated by pkgdev"),
+        format!("pkgname = {}", name),
+        format!("pkgver = {}", derive_version(pkg)),
+        format!("pkgdesc = {}", summary),
+    ];
+    if let Some(project_url) = &recipe.project_url {
+        lines.push(format!("url = {}", project_url));
+    }
+    lines.push(format!("builddate = {}", build_date));
+    if let Some(maintainer) = recipe.maintainers.first() {
+        lines.push(format!("packager = {}", maintainer));
+    }
+    lines.push(format!("size = {}", size));
+    lines.push(format!("arch = {}", arch));
+    lines.push(format!("origin = {}", name));
+    if let Some(license) = &recipe.license {
+        lines.push(format!("license = {}", license));
+    }
+    for depend in depends(pkg) {
+        lines.push(format!("depend = {}", depend));
+    }
+    // apk skips keys it does not know
+    if let Some(profile) = profile {
+        lines.push(format!("forge_build_profile = {}", profile));
+    }
+    lines.push(format!("datahash = {}", datahash));
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// An APKBUILD repackaging the prototype directory, for building the same package with
+/// abuild on Alpine itself.
+fn generate_apkbuild(pkg: &Component, arch: &str, proto_dir: &Path) -> String {
+    let recipe = &pkg.recipe;
+    let mut lines = vec![
+        String::from("# Generated by pkgdev"),
+        format!("pkgname={}", derive_package_name(&recipe.name)),
+        format!(
+            "pkgver={}",
+            recipe.version.clone().unwrap_or(String::from("0"))
+        ),
+        format!(
+            "pkgrel={}",
+            recipe.revision.clone().unwrap_or(String::from("0"))
+        ),
+        format!("pkgdesc=\"{}\"", recipe.summary.clone().unwrap_or_default()),
+        format!("url=\"{}\"", recipe.project_url.clone().unwrap_or_default()),
+        format!("arch=\"{}\"", arch),
+        format!("license=\"{}\"", recipe.license.clone().unwrap_or_default()),
+        format!("depends=\"{}\"", depends(pkg).join(" ")),
+        String::from("options=\"!check !strip\""),
+        String::new(),
+        String::from("package() {"),
+        format!("\tcp -a \"{}/.\" \"$pkgdir\"/", proto_dir.display()),
+        String::from("}"),
+    ];
+    if let Some(maintainer) = recipe.maintainers.first() {
+        lines.insert(1, format!("# Maintainer: {}", maintainer));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Write `entries` of `dir` as one gzip stream to `target`. apk packages are gzip
+/// streams concatenated, so all but the data segment are cut with abuild-tar to not end
+/// the tar archive early. The data segment gets the checksums apk verifies files with.
+fn tar_gz(dir: &Path, entries: &[&str], target: &Path, build_date: i64, cut: bool) -> Result<()> {
+    let mut tar = Command::new("tar")
+        .current_dir(dir)
+        .arg("--format=posix")
+        .arg("--sort=name")
+        .arg("--owner=0")
+        .arg("--group=0")
+        .arg("--numeric-owner")
+        .arg(format!("--mtime=@{}", build_date))
+        .arg("-cf")
+        .arg("-")
+        .args(entries)
+        .stdout(Stdio::piped())
+        .spawn()
+        .into_diagnostic()
+        .wrap_err("could not start tar")?;
+    let mut abuild_tar = Command::new("abuild-tar")
+        .arg(if cut { "--cut" } else { "--hash" })
+        .stdin(Stdio::from(
+            tar.stdout
+                .take()
+                .ok_or(miette::miette!("tar has no output"))?,
+        ))
+        .stdout(Stdio::piped())
+        .spawn()
+        .into_diagnostic()
+        .wrap_err("could not start abuild-tar, it is part of the abuild package")?;
+    let gzip = Command::new("gzip")
+        .arg("-9")
+        .arg("-n")
+        .stdin(Stdio::from(
+            abuild_tar
+                .stdout
+                .take()
+                .ok_or(miette::miette!("abuild-tar has no output"))?,
+        ))
+        .stdout(File::create(target).into_diagnostic()?)
+        .status()
+        .into_diagnostic()?;
+
+    let tar = tar.wait().into_diagnostic()?;
+    let abuild_tar = abuild_tar.wait().into_diagnostic()?;
+    if !tar.success() || !abuild_tar.success() || !gzip.success() {
+        return Err(miette::miette!(
+            "could not create {}, check above for errors",
+            target.display()
+        ));
+    }
+    Ok(())
+}
+
+fn sha256sum(path: &Path) -> Result<String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .into_diagnostic()?;
+    if !output.status.success() {
+        return Err(miette::miette!("could not hash {}", path.display()));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(|s| s.to_string())
+        .ok_or(miette::miette!("sha256sum returned no hash"))
+}
+
+/// Build an .apk from the prototype directory into the output directory, signed with
+/// the key of the gate if it has one.
+pub fn make_apk(
+    wks: &Workspace,
+    pkg: &Component,
+    gate: &Option<Gate>,
+    profile: Option<&str>,
+) -> Result<PathBuf> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let arch = NAMING.architecture(wks.get_arch());
+    let package_name = derive_package_name(&pkg.get_name());
+    let build_date = source_date_epoch(pkg);
+
+    let apk_dir = wks
+        .get_or_create_build_dir()?
+        .join("apk")
+        .join(&package_name);
+    if apk_dir.exists() {
+        fs::remove_dir_all(&apk_dir).into_diagnostic()?;
+    }
+    DirBuilder::new()
+        .recursive(true)
+        .create(&apk_dir)
+        .into_diagnostic()?;
+
+    fs::write(
+        apk_dir.join("APKBUILD"),
+        generate_apkbuild(pkg, arch, &proto_dir),
+    )
+    .into_diagnostic()?;
+
+    let data = apk_dir.join("data.tar.gz");
+    // apk expects paths without a leading ./
+    let mut top_level = fs::read_dir(&proto_dir)
+        .into_diagnostic()?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().to_string()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    top_level.sort();
+    let top_level = top_level.iter().map(String::as_str).collect::<Vec<_>>();
+    tar_gz(&proto_dir, &top_level, &data, build_date, false)?;
+
+    let pkginfo = generate_pkginfo(
+        pkg,
+        arch,
+        installed_size(&proto_dir)?,
+        build_date,
+        &sha256sum(&data)?,
+        profile,
+    )?;
+    fs::write(apk_dir.join(".PKGINFO"), pkginfo).into_diagnostic()?;
+    let control = apk_dir.join("control.tar.gz");
+    tar_gz(&apk_dir, &[".PKGINFO"], &control, build_date, true)?;
+
+    let mut segments = vec![];
+    let signing_key = gate
+        .as_ref()
+        .and_then(|g| g.distribution.as_ref())
+        .and_then(|d| d.apk_signing_key.clone());
+    if let Some(key) = signing_key {
+        let key = Path::new(&key);
+        let key_name = key
+            .file_name()
+            .ok_or(miette::miette!("{} is not a key file", key.display()))?
+            .to_string_lossy();
+        let signature_name = format!(".SIGN.RSA.{}.pub", key_name);
+        run(Command::new("openssl")
+            .arg("dgst")
+            .arg("-sha1")
+            .arg("-sign")
+            .arg(key)
+            .arg("-out")
+            .arg(apk_dir.join(&signature_name))
+            .arg(&control))?;
+        let signature = apk_dir.join("signature.tar.gz");
+        tar_gz(&apk_dir, &[&signature_name], &signature, build_date, true)?;
+        segments.push(signature);
+    }
+    segments.push(control);
+    segments.push(data);
+
+    let output_dir = config::Settings::get_or_create_output_dir().into_diagnostic()?;
+    let apk_path = output_dir.join(format!("{}-{}.apk", package_name, derive_version(pkg)));
+    let mut apk = File::create(&apk_path).into_diagnostic()?;
+    for segment in segments {
+        std::io::copy(&mut File::open(segment).into_diagnostic()?, &mut apk).into_diagnostic()?;
+    }
+    println!("Generated Alpine package {}", apk_path.display());
+
+    Ok(apk_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component() -> Component {
+        let mut pkg = Component::new(String::from("library/LibFoo"), None::<&str>).unwrap();
+        pkg.recipe.version = Some(String::from("1.2"));
+        pkg.recipe.summary = Some(String::from("The foo library"));
+        pkg.recipe.license = Some(String::from("MIT"));
+        pkg.recipe.maintainers = vec![String::from("Jane Doe <jane@example.org>")];
+        pkg
+    }
+
+    #[test]
+    fn pkginfo_describes_the_package() {
+        assert_eq!(
+            generate_pkginfo(&component(), "x86_64", 2048, 1700000000, "abc", None).unwrap(),
+            "# Generated by pkgdev\n\
+             pkgname = library-libfoo\n\
+             pkgver = 1.2-r0\n\
+             pkgdesc = The foo library\n\
+             builddate = 1700000000\n\
+             packager = Jane Doe <jane@example.org>\n\
+             size = 2048\n\
+             arch = x86_64\n\
+             origin = library-libfoo\n\
+             license = MIT\n\
+             datahash = abc\n"
+        );
+    }
+
+    #[test]
+    fn apkbuild_copies_the_prototype_directory() {
+        let apkbuild = generate_apkbuild(&component(), "x86_64", Path::new("/tmp/proto"));
+        let lines = apkbuild.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "# Maintainer: Jane Doe <jane@example.org>");
+        assert!(lines.contains(&"pkgname=library-libfoo"));
+        assert!(lines.contains(&"pkgrel=0"));
+        assert!(lines.contains(&"\tcp -a \"/tmp/proto/.\" \"$pkgdir\"/"));
+    }
+}
@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+use forge::provenance::{Artifact, ARTIFACTS_REPORT_FILE};
+use miette::{IntoDiagnostic, Result, WrapErr};
+use workspace::Workspace;
+
+/// Write the digests of the files the build produced to the workspace root, so the
+/// worker can attest where they came from.
+pub fn record_artifacts(wks: &Workspace, files: &[(String, PathBuf)]) -> Result<()> {
+    let artifacts = files
+        .iter()
+        .map(|(name, path)| {
+            Artifact::from_file(name, path)
+                .into_diagnostic()
+                .wrap_err(format!("could not digest {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let report_path = wks.get_root_path().join(ARTIFACTS_REPORT_FILE);
+    let report = serde_json::to_string_pretty(&artifacts).into_diagnostic()?;
+    std::fs::write(&report_path, report)
+        .into_diagnostic()
+        .wrap_err(format!("could not write {}", report_path.display()))
+}
@@ -1,10 +1,15 @@
 use std::{
     collections::HashMap,
     fs::DirBuilder,
-    process::{Command, Stdio},
+    process::Command,
+    time::Instant,
 };
 
-use crate::sources::derive_source_name;
+use crate::build::configure_cache;
+use crate::build::log::run_step;
+use crate::build::normalize::source_date_epoch;
+use crate::build::sandbox::Sandbox;
+use crate::sources::SourceTree;
 use component::{Component, ConfigureBuildSection};
 use config::Settings;
 use miette::{IntoDiagnostic, Result, WrapErr};
@@ -23,19 +28,16 @@ fn get_largefile_flag() -> Result<Vec<String>> {
 pub fn build_using_automake(
     wks: &Workspace,
     pkg: &Component,
+    tree: &SourceTree,
     build_section: &ConfigureBuildSection,
     settings: &Settings,
+    sandbox: &Sandbox,
+    jobs: u32,
 ) -> Result<()> {
-    let build_dir = wks.get_or_create_build_dir()?;
-    let unpack_name = derive_source_name(pkg.recipe.name.clone());
-    let unpack_path = build_dir.join(&unpack_name);
     if pkg.recipe.seperate_build_dir {
-        let out_dir = build_dir.join("out");
-        DirBuilder::new().create(&out_dir).into_diagnostic()?;
-        std::env::set_current_dir(&out_dir).into_diagnostic()?;
-    } else {
-        std::env::set_current_dir(&unpack_path).into_diagnostic()?;
+        DirBuilder::new().create(&tree.out_dir).into_diagnostic()?;
     }
+    std::env::set_current_dir(tree.work_dir(pkg)).into_diagnostic()?;
 
     let mut option_vec: Vec<_> = vec![];
     let mut env_flags: HashMap<String, String> = HashMap::new();
@@ -93,6 +95,10 @@ pub fn build_using_automake(
     }
 
     env_flags.insert("PATH".into(), settings.get_search_path().join(":"));
+    env_flags.insert(
+        String::from("SOURCE_DATE_EPOCH"),
+        source_date_epoch(pkg).to_string(),
+    );
     let proto_dir_path = wks.get_or_create_prototype_dir()?;
     let proto_dir_str = proto_dir_path.to_string_lossy().to_string();
 
@@ -100,11 +106,17 @@ pub fn build_using_automake(
     let destdir_arg = format!("DESTDIR={}", &proto_dir_str);
 
     let bin_path = if pkg.recipe.seperate_build_dir {
-        unpack_path.join("configure").to_string_lossy().to_string()
+        tree.path.join("configure").to_string_lossy().to_string()
     } else {
         String::from("./configure")
     };
 
+    let arch = wks.get_arch().to_string();
+    let configure_cache =
+        configure_cache::prepare(settings, &format!("{}-{}", tree.name, arch), || {
+            configure_cache::fingerprint(&arch, &option_vec, &env_flags)
+        })?;
+
     let mut configure_cmd = Command::new(&bin_path);
     configure_cmd.env_clear();
     configure_cmd.envs(&env_flags);
@@ -113,9 +125,10 @@ pub fn build_using_automake(
         println!("DESTDIR option not injecting into configure script options");
         configure_cmd.arg(&destdir_arg);
     }
-
-    configure_cmd.stdin(Stdio::null());
-    configure_cmd.stdout(Stdio::inherit());
+    if let Some(cache) = &configure_cache {
+        configure_cmd.arg(format!("--cache-file={}", cache.path.display()));
+    }
+    let mut configure_cmd = sandbox.wrap(configure_cmd)?;
 
     println!(
         "Running configure with options {}; {}; env=[{}]",
@@ -128,9 +141,17 @@ pub fn build_using_automake(
             .join(",")
     );
 
-    let status = configure_cmd.status().into_diagnostic()?;
+    let configure_start = Instant::now();
+    let status = run_step(wks, "configure", &mut configure_cmd)?;
     if status.success() {
-        println!("Successfully configured {}", pkg.get_name());
+        println!(
+            "Successfully configured {} in {:.1?} ({})",
+            pkg.get_name(),
+            configure_start.elapsed(),
+            configure_cache
+                .map(|c| c.to_string())
+                .unwrap_or(String::from("config.cache disabled"))
+        );
     } else {
         return Err(miette::miette!(format!(
             "Could not configure {}",
@@ -138,7 +159,7 @@ pub fn build_using_automake(
         )));
     }
 
-    crate::build::compile::run_compile(wks, pkg, settings).wrap_err("compilation step failed")?;
+    crate::build::compile::run_compile(wks, pkg, tree, settings, sandbox, jobs).wrap_err("compilation step failed")?;
 
-    crate::build::install::run_install(wks, pkg, settings).wrap_err("installation step failed")
+    crate::build::install::run_install(wks, pkg, tree, settings, sandbox).wrap_err("installation step failed")
 }
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use component::{BuildFlagNode, Component};
+use config::Settings;
+use miette::{IntoDiagnostic, Result};
+use workspace::Workspace;
+
+use crate::build::log::run_step;
+use crate::build::normalize::source_date_epoch;
+use crate::build::sandbox::Sandbox;
+use crate::build::util::expand_env;
+use crate::sources::SourceTree;
+
+/// The sources of a CMake or Meson build section and how to build them. `options` is
+/// the argument of the section, like `-DBUILD_TESTING=OFF` for CMake or
+/// `-Ddocs=disabled` for Meson.
+pub struct GeneratedBuild<'a> {
+    pub wks: &'a Workspace,
+    pub pkg: &'a Component,
+    pub tree: &'a SourceTree,
+    pub options: &'a str,
+    /// Compiler flags of the gate for the architecture
+    pub flags: &'a [BuildFlagNode],
+    pub settings: &'a Settings,
+    pub sandbox: &'a Sandbox,
+    pub jobs: u32,
+}
+
+/// Configure, build and install a CMake project out of tree into the prototype
+/// directory.
+pub fn build_using_cmake(build: &GeneratedBuild) -> Result<()> {
+    let build_dir = prepare_build_dir(build.tree)?;
+    let env = build_env(build)?;
+
+    let mut configure = Command::new("cmake");
+    configure
+        .arg("-S")
+        .arg(&build.tree.path)
+        .arg("-B")
+        .arg(&build_dir)
+        .arg("-DCMAKE_BUILD_TYPE=Release")
+        .arg(format!("-DCMAKE_INSTALL_PREFIX={}", prefix(build.pkg)))
+        .args(build.options.split_whitespace());
+    run(build, "cmake-configure", configure, &build_dir, &env)?;
+
+    let mut compile = Command::new("cmake");
+    compile
+        .arg("--build")
+        .arg(&build_dir)
+        .arg("--parallel")
+        .arg(build.jobs.to_string());
+    run(build, "cmake-build", compile, &build_dir, &env)?;
+
+    // DESTDIR of the environment points the installation at the prototype directory
+    let mut install = Command::new("cmake");
+    install.arg("--install").arg(&build_dir);
+    run(build, "cmake-install", install, &build_dir, &env)
+}
+
+/// Set up, compile and install a Meson project out of tree into the prototype
+/// directory.
+pub fn build_using_meson(build: &GeneratedBuild) -> Result<()> {
+    let build_dir = prepare_build_dir(build.tree)?;
+    let env = build_env(build)?;
+
+    let mut setup = Command::new("meson");
+    setup
+        .arg("setup")
+        .arg(format!("--prefix={}", prefix(build.pkg)))
+        .arg("--buildtype=release")
+        .args(build.options.split_whitespace())
+        .arg(&build_dir)
+        .arg(&build.tree.path);
+    run(build, "meson-setup", setup, &build_dir, &env)?;
+
+    let mut compile = Command::new("meson");
+    compile
+        .arg("compile")
+        .arg("-C")
+        .arg(&build_dir)
+        .arg("-j")
+        .arg(build.jobs.to_string());
+    run(build, "meson-compile", compile, &build_dir, &env)?;
+
+    let mut install = Command::new("meson");
+    install
+        .arg("install")
+        .arg("-C")
+        .arg(&build_dir)
+        .arg("--no-rebuild");
+    run(build, "meson-install", install, &build_dir, &env)
+}
+
+fn prefix(pkg: &Component) -> String {
+    pkg.recipe.prefix.clone().unwrap_or(String::from("/usr"))
+}
+
+/// Both build systems refuse to build in the source directory.
+fn prepare_build_dir(tree: &SourceTree) -> Result<&Path> {
+    fs::create_dir_all(&tree.out_dir).into_diagnostic()?;
+    Ok(&tree.out_dir)
+}
+
+/// The environment of every step. Flags without a name go into the flags of all
+/// compilers like for configure builds.
+fn build_env(build: &GeneratedBuild) -> Result<HashMap<String, String>> {
+    let mut env: HashMap<String, String> = HashMap::new();
+    for flag in build.flags {
+        let value = expand_env(&flag.flag)?;
+        let names = match &flag.flag_name {
+            Some(name) => vec![name.to_uppercase()],
+            None => ["CFLAGS", "CXXFLAGS", "CPPFLAGS", "FFLAGS"]
+                .map(String::from)
+                .to_vec(),
+        };
+        for name in names {
+            env.entry(name)
+                .and_modify(|flags| {
+                    flags.push(' ');
+                    flags.push_str(&value);
+                })
+                .or_insert(value.clone());
+        }
+    }
+    env.insert(
+        String::from("PATH"),
+        build.settings.get_search_path().join(":"),
+    );
+    env.insert(
+        String::from("SOURCE_DATE_EPOCH"),
+        source_date_epoch(build.pkg).to_string(),
+    );
+    env.insert(
+        String::from("DESTDIR"),
+        build
+            .wks
+            .get_or_create_prototype_dir()?
+            .to_string_lossy()
+            .to_string(),
+    );
+    Ok(env)
+}
+
+fn run(
+    build: &GeneratedBuild,
+    step: &str,
+    mut cmd: Command,
+    dir: &Path,
+    env: &HashMap<String, String>,
+) -> Result<()> {
+    cmd.current_dir(dir).env_clear().envs(env);
+    let mut cmd = build.sandbox.wrap(cmd)?;
+    let status = run_step(build.wks, step, &mut cmd)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(miette::miette!(
+            "{} of {} failed, see the build log",
+            step,
+            build.pkg.get_name()
+        ))
+    }
+}
@@ -0,0 +1,131 @@
+use std::process::Command;
+
+use component::{CheckSection, Component};
+use config::Settings;
+use forge::{CheckResult, CHECKS_REPORT_FILE};
+use miette::{IntoDiagnostic, Result, WrapErr};
+use workspace::Workspace;
+
+use crate::build::normalize::source_date_epoch;
+use crate::build::sandbox::Sandbox;
+use crate::sources::derive_source_name;
+
+/// The variables of the environment of pkgdev that check commands see. Like the build
+/// steps they get nothing else of it.
+const PASSED_ENV: [&str; 3] = ["LANG", "LC_ALL", "TZ"];
+
+/// Run the checks of the recipe against the build and the prototype directory. The
+/// results are written to the workspace root for the worker to report, the build fails
+/// when a check did not pass.
+pub fn run_checks(
+    wks: &Workspace,
+    pkg: &Component,
+    settings: &Settings,
+    sandbox: &Sandbox,
+) -> Result<()> {
+    if pkg.recipe.checks.is_empty() {
+        return Ok(());
+    }
+
+    let results = pkg
+        .recipe
+        .checks
+        .iter()
+        .map(|check| run_check(wks, pkg, settings, sandbox, check))
+        .collect::<Result<Vec<_>>>()?;
+
+    let report_path = wks.get_root_path().join(CHECKS_REPORT_FILE);
+    let report = serde_json::to_string_pretty(&results).into_diagnostic()?;
+    std::fs::write(&report_path, report)
+        .into_diagnostic()
+        .wrap_err(format!("could not write {}", report_path.display()))?;
+
+    for result in &results {
+        println!(
+            "check {} {}",
+            result.name,
+            if result.passed { "passed" } else { "failed" }
+        );
+    }
+
+    let failed = results
+        .iter()
+        .filter(|r| !r.passed)
+        .map(|r| r.name.as_str())
+        .collect::<Vec<_>>();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(miette::miette!(
+            "checks {} of {} failed",
+            failed.join(", "),
+            pkg.get_name()
+        ))
+    }
+}
+
+fn run_check(
+    wks: &Workspace,
+    pkg: &Component,
+    settings: &Settings,
+    sandbox: &Sandbox,
+    check: &CheckSection,
+) -> Result<CheckResult> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let build_dir = wks
+        .get_or_create_build_dir()?
+        .join(derive_source_name(pkg.recipe.name.clone()));
+    let mut output = String::new();
+
+    let missing = check
+        .files
+        .iter()
+        .filter(|f| !proto_dir.join(f.trim_start_matches('/')).exists())
+        .collect::<Vec<_>>();
+    for file in &missing {
+        output.push_str(&format!("{} is missing in the prototype directory\n", file));
+    }
+    let mut passed = missing.is_empty();
+
+    let mut commands = vec![];
+    if let Some(command) = &check.command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        commands.push(cmd);
+    }
+    if let Some(script) = &check.script {
+        let mut cmd = Command::new("sh");
+        cmd.arg(pkg.get_path().join(script));
+        commands.push(cmd);
+    }
+
+    let passed_env = PASSED_ENV
+        .iter()
+        .filter_map(|k| std::env::var_os(k).map(|v| (k, v)))
+        .collect::<Vec<_>>();
+    for mut cmd in commands {
+        cmd.current_dir(&build_dir)
+            .env_clear()
+            .envs(passed_env.iter().cloned())
+            .env("HOME", &build_dir)
+            .env("PROTO_DIR", &proto_dir)
+            .env("BUILD_DIR", &build_dir)
+            .env("COMPONENT_DIR", pkg.get_path())
+            .env("PATH", settings.get_search_path().join(":"))
+            .env("SOURCE_DATE_EPOCH", source_date_epoch(pkg).to_string());
+        let result = sandbox
+            .wrap(cmd)?
+            .output()
+            .into_diagnostic()
+            .wrap_err(format!("could not run check {}", check.name))?;
+        output.push_str(&String::from_utf8_lossy(&result.stdout));
+        output.push_str(&String::from_utf8_lossy(&result.stderr));
+        passed &= result.status.success();
+    }
+
+    Ok(CheckResult {
+        name: check.name.clone(),
+        passed,
+        output,
+    })
+}
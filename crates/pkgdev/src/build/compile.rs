@@ -1,6 +1,9 @@
-use std::{collections::HashMap, process::Stdio};
+use std::collections::HashMap;
 
-use crate::sources::derive_source_name;
+use crate::build::log::run_step;
+use crate::build::normalize::source_date_epoch;
+use crate::build::sandbox::Sandbox;
+use crate::sources::SourceTree;
 use component::Component;
 use config::Settings;
 use miette::{IntoDiagnostic, Result};
@@ -21,23 +24,16 @@ impl ToString for BuildTool {
     }
 }
 
-pub fn run_compile(wks: &Workspace, pkg: &Component, settings: &Settings) -> Result<()> {
-    let build_dir = wks.get_or_create_build_dir()?;
-    let unpack_name = derive_source_name(pkg.recipe.name.clone());
-
-    let unpack_path = build_dir.join(&unpack_name);
-    if pkg.recipe.seperate_build_dir {
-        let out_dir = build_dir.join("out");
-        std::env::set_current_dir(&out_dir).into_diagnostic()?;
-    } else {
-        std::env::set_current_dir(&unpack_path).into_diagnostic()?;
-    }
-
-    let build_tool_check_dir = if pkg.recipe.seperate_build_dir {
-        build_dir.join("out")
-    } else {
-        unpack_path.clone()
-    };
+pub fn run_compile(
+    wks: &Workspace,
+    pkg: &Component,
+    tree: &SourceTree,
+    settings: &Settings,
+    sandbox: &Sandbox,
+    jobs: u32,
+) -> Result<()> {
+    std::env::set_current_dir(tree.work_dir(pkg)).into_diagnostic()?;
+    let build_tool_check_dir = tree.work_dir(pkg);
 
     let build_tool = if build_tool_check_dir.join("Makefile").exists() {
         BuildTool::Make
@@ -49,17 +45,21 @@ pub fn run_compile(wks: &Workspace, pkg: &Component, settings: &Settings) -> Res
 
     let mut env_flags: HashMap<String, String> = HashMap::new();
     env_flags.insert("PATH".into(), settings.get_search_path().join(":"));
+    env_flags.insert(
+        String::from("SOURCE_DATE_EPOCH"),
+        source_date_epoch(pkg).to_string(),
+    );
     let mut build_cmd = Command::new(build_tool.to_string());
+    build_cmd.arg(format!("-j{}", jobs));
     build_cmd.env_clear();
     build_cmd.envs(&env_flags);
-
-    build_cmd.stdin(Stdio::null());
-    build_cmd.stdout(Stdio::inherit());
+    let mut build_cmd = sandbox.wrap(build_cmd)?;
 
     println!(
-        "Running {}; env=[{}]",
+        "Running {} with {} jobs; env=[{}]",
         //option_vec.join(" "),
         build_tool.to_string(),
+        jobs,
         env_flags
             .into_iter()
             .map(|(k, v)| format!("{}={}", k, v))
@@ -67,7 +67,7 @@ pub fn run_compile(wks: &Workspace, pkg: &Component, settings: &Settings) -> Res
             .join(",")
     );
 
-    let status = build_cmd.status().into_diagnostic()?;
+    let status = run_step(wks, "compile", &mut build_cmd)?;
     if status.success() {
         println!("Successfully built {}", pkg.get_name());
     } else {
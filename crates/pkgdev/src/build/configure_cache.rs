@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use config::Settings;
+use miette::{IntoDiagnostic, Result};
+
+/// An autoconf config.cache shared between the builds of one component.
+pub struct ConfigureCache {
+    pub path: PathBuf,
+    pub reused: bool,
+}
+
+impl Display for ConfigureCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.reused {
+            write!(f, "config.cache reused")
+        } else {
+            write!(f, "config.cache fresh")
+        }
+    }
+}
+
+/// Describe everything besides the sources that influences the results configure
+/// caches: the architecture, the compilers and the options and environment passed.
+pub fn fingerprint(arch: &str, options: &[String], env_flags: &HashMap<String, String>) -> String {
+    let path = env_flags.get("PATH").cloned().unwrap_or_default();
+    let mut lines = vec![format!("arch={}", arch)];
+    for (var, default) in [("CC", "gcc"), ("CXX", "g++")] {
+        let tool = env_flags.get(var).map(String::as_str).unwrap_or(default);
+        lines.push(format!("{}={}", var, tool_version(tool, &path)));
+    }
+    lines.extend(options.iter().map(|o| format!("option={}", o)));
+
+    let mut env = env_flags.iter().collect::<Vec<_>>();
+    env.sort();
+    lines.extend(env.into_iter().map(|(k, v)| format!("env {}={}", k, v)));
+
+    lines.join("\n")
+}
+
+fn tool_version(tool: &str, path: &str) -> String {
+    Command::new(tool)
+        .arg("--version")
+        .env("PATH", path)
+        .output()
+        .ok()
+        .and_then(|out| {
+            String::from_utf8_lossy(&out.stdout)
+                .lines()
+                .next()
+                .map(|l| l.to_string())
+        })
+        .unwrap_or_else(|| String::from("unavailable"))
+}
+
+/// Get the config.cache of the component `name`. A cache written under a different
+/// fingerprint is thrown away. Returns `None` if caching is disabled in the settings, the
+/// fingerprint runs the compilers so it is only taken when caching is enabled.
+pub fn prepare(
+    settings: &Settings,
+    name: &str,
+    fingerprint: impl FnOnce() -> String,
+) -> Result<Option<ConfigureCache>> {
+    if !settings.use_configure_cache() {
+        return Ok(None);
+    }
+    let fingerprint = fingerprint();
+
+    let dir = Settings::get_or_create_configure_cache_dir()?.join(name);
+    fs::create_dir_all(&dir).into_diagnostic()?;
+    let fingerprint_path = dir.join("fingerprint");
+    let path = dir.join("config.cache");
+
+    let reused = path.exists()
+        && fs::read_to_string(&fingerprint_path)
+            .map(|existing| existing == fingerprint)
+            .unwrap_or(false);
+    if !reused {
+        if path.exists() {
+            fs::remove_file(&path).into_diagnostic()?;
+        }
+        fs::write(&fingerprint_path, fingerprint).into_diagnostic()?;
+    }
+
+    Ok(Some(ConfigureCache { path, reused }))
+}
@@ -0,0 +1,242 @@
+use std::fs::{self, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use component::Component;
+use gate::Gate;
+use miette::{IntoDiagnostic, Result};
+use workspace::Workspace;
+
+use crate::build::util::{installed_size, run, runtime_dependencies, PackageNaming};
+
+/// Debian package names only allow lowercase alphanumerics and `+-.`.
+const NAMING: PackageNaming = PackageNaming {
+    name_chars: |c| matches!(c, 'a'..='z' | '0'..='9' | '+' | '-' | '.'),
+    lowercase: true,
+    architectures: ["i386", "amd64", "arm64", "sparc64"],
+    default_revision: "1",
+};
+
+fn derive_package_name(name: &str) -> String {
+    NAMING.package_name(name)
+}
+
+fn derive_version(pkg: &Component) -> String {
+    let (version, revision) = NAMING.version(pkg);
+    format!("{}-{}", version, revision)
+}
+
+/// The DEBIAN/control file of the package built from the recipe.
+fn generate_control(
+    pkg: &Component,
+    arch: &str,
+    installed_size: u64,
+    profile: Option<&str>,
+) -> Result<String> {
+    let recipe = &pkg.recipe;
+    let summary = recipe.summary.clone().ok_or(miette::miette!(
+        "{} has no summary which the package description is made of",
+        pkg.get_name()
+    ))?;
+
+    let mut lines = vec![
+        format!("Package: {}", derive_package_name(&recipe.name)),
+        format!("Version: {}", derive_version(pkg)),
+        format!("Architecture: {}", arch),
+        format!(
+            "Maintainer: {}",
+            recipe
+                .maintainers
+                .first()
+                .cloned()
+                .ok_or(miette::miette!("{} has no maintainer", pkg.get_name()))?
+        ),
+        format!("Installed-Size: {}", installed_size),
+    ];
+
+    let depends = runtime_dependencies(pkg)
+        .map(|d| derive_package_name(&d.name))
+        .collect::<Vec<_>>();
+    if !depends.is_empty() {
+        lines.push(format!("Depends: {}", depends.join(", ")));
+    }
+    if let Some(project_url) = &recipe.project_url {
+        lines.push(format!("Homepage: {}", project_url));
+    }
+    if let Some(classification) = &recipe.classification {
+        lines.push(format!("Section: {}", classification.to_lowercase()));
+    }
+    // User defined fields are kept in the package as they are
+    if let Some(profile) = profile {
+        lines.push(format!("X-Forge-Build-Profile: {}", profile));
+    }
+    lines.push(format!("Description: {}", summary));
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Size of the payload in KiB as dpkg expects it in Installed-Size.
+fn installed_kibibytes(dir: &Path) -> Result<u64> {
+    Ok((installed_size(dir)? + 1023) / 1024)
+}
+
+/// Build a .deb from the prototype directory into the output directory and add it to
+/// the apt repository of the gate if it has one.
+pub fn make_deb(
+    wks: &Workspace,
+    pkg: &Component,
+    gate: &Option<Gate>,
+    profile: Option<&str>,
+) -> Result<PathBuf> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let arch = NAMING.architecture(wks.get_arch());
+    let package_name = derive_package_name(&pkg.get_name());
+
+    let staging_dir = wks
+        .get_or_create_build_dir()?
+        .join("deb")
+        .join(&package_name);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir).into_diagnostic()?;
+    }
+    DirBuilder::new()
+        .recursive(true)
+        .create(staging_dir.join("DEBIAN"))
+        .into_diagnostic()?;
+
+    run(Command::new("cp")
+        .arg("-a")
+        .arg(format!("{}/.", proto_dir.to_string_lossy()))
+        .arg(&staging_dir))?;
+
+    let control = generate_control(pkg, arch, installed_kibibytes(&proto_dir)?, profile)?;
+    fs::write(staging_dir.join("DEBIAN").join("control"), control).into_diagnostic()?;
+
+    let output_dir = config::Settings::get_or_create_output_dir().into_diagnostic()?;
+    let deb_path = output_dir.join(format!(
+        "{}_{}_{}.deb",
+        package_name,
+        derive_version(pkg),
+        arch
+    ));
+    run(Command::new("dpkg-deb")
+        .arg("--root-owner-group")
+        .arg("--build")
+        .arg(&staging_dir)
+        .arg(&deb_path))?;
+    println!("Generated Debian package {}", deb_path.display());
+
+    let apt_repository = gate
+        .as_ref()
+        .and_then(|g| g.distribution.as_ref())
+        .and_then(|d| d.apt_repository.clone());
+    if let Some(repo) = apt_repository {
+        add_to_apt_repository(&deb_path, Path::new(&repo))?;
+    }
+
+    Ok(deb_path)
+}
+
+/// Copy the package into a flat repository and regenerate its package index, usable
+/// with `deb [trusted=yes] file:/path/to/repo ./`.
+fn add_to_apt_repository(deb_path: &Path, repo: &Path) -> Result<()> {
+    if !repo.exists() {
+        DirBuilder::new()
+            .recursive(true)
+            .create(repo)
+            .into_diagnostic()?;
+    }
+    let file_name = deb_path
+        .file_name()
+        .ok_or(miette::miette!("{} is not a file", deb_path.display()))?;
+    fs::copy(deb_path, repo.join(file_name)).into_diagnostic()?;
+
+    let packages = Command::new("dpkg-scanpackages")
+        .current_dir(repo)
+        .arg("--multiversion")
+        .arg(".")
+        .stderr(Stdio::inherit())
+        .output()
+        .into_diagnostic()?;
+    if !packages.status.success() {
+        return Err(miette::miette!(
+            "dpkg-scanpackages could not index {}",
+            repo.display()
+        ));
+    }
+    fs::write(repo.join("Packages"), &packages.stdout).into_diagnostic()?;
+    run(Command::new("gzip")
+        .current_dir(repo)
+        .arg("-9")
+        .arg("--keep")
+        .arg("--force")
+        .arg("Packages"))?;
+
+    println!(
+        "Added {} to apt repository {}",
+        file_name.to_string_lossy(),
+        repo.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use component::Dependency;
+
+    use super::*;
+
+    fn component() -> Component {
+        let mut pkg = Component::new(String::from("library/LibFoo"), None::<&str>).unwrap();
+        pkg.recipe.version = Some(String::from("1.2"));
+        pkg.recipe.summary = Some(String::from("The foo library"));
+        pkg.recipe.maintainers = vec![String::from("Jane Doe <jane@example.org>")];
+        pkg.recipe.project_url = Some(String::from("https://foo.example.org"));
+        pkg.recipe.dependencies = vec![
+            Dependency {
+                name: String::from("library/zlib"),
+                dev: false,
+                kind: Default::default(),
+            },
+            Dependency {
+                name: String::from("developer/cmake"),
+                dev: true,
+                kind: Default::default(),
+            },
+        ];
+        pkg
+    }
+
+    #[test]
+    fn control_describes_the_package() {
+        assert_eq!(
+            generate_control(&component(), "amd64", 12, Some("debug")).unwrap(),
+            "Package: library-libfoo\n\
+             Version: 1.2-1\n\
+             Architecture: amd64\n\
+             Maintainer: Jane Doe <jane@example.org>\n\
+             Installed-Size: 12\n\
+             Depends: library-zlib\n\
+             Homepage: https://foo.example.org\n\
+             X-Forge-Build-Profile: debug\n\
+             Description: The foo library\n"
+        );
+    }
+
+    #[test]
+    fn control_needs_a_maintainer() {
+        let mut pkg = component();
+        pkg.recipe.maintainers.clear();
+        assert!(generate_control(&pkg, "amd64", 12, None).is_err());
+    }
+
+    #[test]
+    fn installed_size_is_rounded_up_to_kibibytes() {
+        let dir = std::env::temp_dir().join(format!("pkgdev-deb-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("usr/bin")).unwrap();
+        fs::write(dir.join("usr/bin/foo"), vec![0u8; 1025]).unwrap();
+        let size = installed_kibibytes(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(size, 2);
+    }
+}
@@ -0,0 +1,373 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use component::Component;
+use config::RemoteRepository;
+use miette::{Diagnostic, IntoDiagnostic, Result, WrapErr};
+use mogrify::{Entry, Mogrify};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use thiserror::Error;
+use workspace::Workspace;
+
+use crate::build::ips::ManifestCollection;
+use crate::sources::derive_source_name;
+
+/// How often a failed request is retried when the settings do not say.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// Publication operations a depot has to offer to be published to.
+const PUBLICATION_OPERATIONS: [&str; 3] = ["open 0", "add 0", "close 0"];
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum DepotError {
+    #[error("request to {url} failed")]
+    #[diagnostic(code(pkgdev::depot::request))]
+    Request {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("{url} answered with {status}: {body}")]
+    #[diagnostic(code(pkgdev::depot::status))]
+    Status {
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+
+    #[error("{url} did not return the {header} header")]
+    #[diagnostic(
+        code(pkgdev::depot::missing_header),
+        help("check that the URL points to a pkg depot and not to a proxy or web page")
+    )]
+    MissingHeader { url: String, header: String },
+
+    #[error("the depot at {0} does not accept publications")]
+    #[diagnostic(
+        code(pkgdev::depot::read_only),
+        help("pkg.depotd only accepts publications when it does not run with --readonly")
+    )]
+    ReadOnly(String),
+
+    #[error("no payload {payload} found for action {action}")]
+    #[diagnostic(code(pkgdev::depot::payload))]
+    MissingPayload { payload: String, action: String },
+
+    #[error("manifest {0} has no pkg.fmri")]
+    #[diagnostic(code(pkgdev::depot::no_fmri))]
+    NoFmri(String),
+
+    #[error("could not load the SSL client certificate {cert} with key {key}")]
+    #[diagnostic(code(pkgdev::depot::identity))]
+    Identity {
+        key: String,
+        cert: String,
+        #[source]
+        source: reqwest::Error,
+    },
+}
+
+impl DepotError {
+    /// Connection problems and server errors may go away when asked again.
+    fn is_retryable(&self) -> bool {
+        match self {
+            DepotError::Request { source, .. } => !source.is_builder(),
+            DepotError::Status { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+/// A pkg depot packages are published to with the HTTP publication operations pkgsend
+/// uses for remote repositories.
+pub struct Depot {
+    client: Client,
+    url: String,
+    token: Option<String>,
+    retries: u32,
+}
+
+impl Depot {
+    pub fn new(remote: &RemoteRepository) -> Result<Self> {
+        let mut builder = Client::builder();
+        if let (Some(key), Some(cert)) = (&remote.ssl_key, &remote.ssl_cert) {
+            // rustls wants the key and certificate in one PEM bundle
+            let mut pem = std::fs::read(key)
+                .into_diagnostic()
+                .wrap_err(format!("could not read SSL key {}", key))?;
+            pem.extend(
+                std::fs::read(cert)
+                    .into_diagnostic()
+                    .wrap_err(format!("could not read SSL certificate {}", cert))?,
+            );
+            let identity =
+                reqwest::Identity::from_pem(&pem).map_err(|source| DepotError::Identity {
+                    key: key.clone(),
+                    cert: cert.clone(),
+                    source,
+                })?;
+            builder = builder.identity(identity);
+        } else if remote.ssl_key.is_some() || remote.ssl_cert.is_some() {
+            return Err(miette::miette!(
+                "remote repository {} needs both ssl_key and ssl_cert for client certificates",
+                remote.url
+            ));
+        }
+
+        Ok(Depot {
+            client: builder.build().into_diagnostic()?,
+            url: remote.url.trim_end_matches('/').to_string(),
+            token: remote.token.clone(),
+            retries: remote.retries.unwrap_or(DEFAULT_RETRIES),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Send the request `build` creates, again with growing pauses if it fails in a way
+    /// that may go away.
+    async fn send<F>(&self, method: Method, url: &str, build: F) -> Result<Response, DepotError>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            let mut request = self.client.request(method.clone(), url);
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let err = match build(request).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => DepotError::Status {
+                    url: url.to_string(),
+                    status: response.status(),
+                    body: response.text().await.unwrap_or_default(),
+                },
+                Err(source) => DepotError::Request {
+                    url: url.to_string(),
+                    source,
+                },
+            };
+
+            if attempt >= self.retries || !err.is_retryable() {
+                return Err(err);
+            }
+            attempt += 1;
+            let pause = Duration::from_secs(1 << attempt);
+            println!(
+                "{}, retrying in {}s (attempt {} of {})",
+                err,
+                pause.as_secs(),
+                attempt,
+                self.retries
+            );
+            tokio::time::sleep(pause).await;
+        }
+    }
+
+    /// Make sure the depot answers and accepts publications.
+    pub async fn ensure_publishable(&self) -> Result<(), DepotError> {
+        let url = format!("{}/versions/0/", self.url);
+        let versions = self
+            .send(Method::GET, &url, |r| r)
+            .await?
+            .text()
+            .await
+            .map_err(|source| DepotError::Request {
+                url: url.clone(),
+                source,
+            })?;
+        if PUBLICATION_OPERATIONS
+            .iter()
+            .all(|op| versions.lines().any(|l| l.trim() == *op))
+        {
+            Ok(())
+        } else {
+            Err(DepotError::ReadOnly(self.url.clone()))
+        }
+    }
+
+    fn header(response: &Response, url: &str, header: &str) -> Result<String, DepotError> {
+        response
+            .headers()
+            .get(header)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+            .ok_or(DepotError::MissingHeader {
+                url: url.to_string(),
+                header: header.to_string(),
+            })
+    }
+
+    /// Publish the actions of a manifest, reading the payloads of files and licenses
+    /// from the first of `payload_dirs` that has them. Returns the FMRI the depot
+    /// published the package as.
+    pub async fn publish_manifest(
+        &self,
+        publisher: &str,
+        manifest: &Path,
+        payload_dirs: &[PathBuf],
+    ) -> Result<String> {
+        let mut mogrify = Mogrify::new(vec![]);
+        mogrify.read_file(manifest)?;
+        let actions = mogrify
+            .apply()?
+            .into_iter()
+            .filter_map(|e| match e {
+                Entry::Action(action) => Some(action),
+                Entry::Comment(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let is_fmri = |a: &mogrify::Action| {
+            a.name == "set" && a.values("name").any(|n| n == "pkg.fmri" || n == "fmri")
+        };
+        let fmri = actions
+            .iter()
+            .find(|a| is_fmri(a))
+            .and_then(|a| a.values("value").next().cloned())
+            .ok_or(DepotError::NoFmri(manifest.display().to_string()))?;
+
+        let base = format!("{}/{}", self.url, publisher);
+        let url = format!(
+            "{}/open/0/{}",
+            base,
+            url::form_urlencoded::byte_serialize(fmri.as_bytes()).collect::<String>()
+        );
+        let response = self.send(Method::GET, &url, |r| r).await?;
+        let transaction = Self::header(&response, &url, "Transaction-ID")?;
+        println!("Publishing {} to {}", fmri, self.url);
+
+        let published = self
+            .add_actions(&base, &transaction, &actions, payload_dirs, is_fmri)
+            .await;
+        let published = match published {
+            Ok(()) => {
+                let url = format!("{}/close/0/{}", base, transaction);
+                let response = self.send(Method::GET, &url, |r| r).await?;
+                Self::header(&response, &url, "Package-FMRI")
+            }
+            Err(err) => {
+                // Leave no open transactions behind on the depot
+                let url = format!("{}/abandon/0/{}", base, transaction);
+                if let Err(abandon_err) = self.send(Method::GET, &url, |r| r).await {
+                    println!(
+                        "could not abandon transaction {}: {}",
+                        transaction, abandon_err
+                    );
+                }
+                return Err(err);
+            }
+        }?;
+        println!("Published {}", published);
+        Ok(published)
+    }
+
+    async fn add_actions(
+        &self,
+        base: &str,
+        transaction: &str,
+        actions: &[mogrify::Action],
+        payload_dirs: &[PathBuf],
+        is_fmri: impl Fn(&mogrify::Action) -> bool,
+    ) -> Result<()> {
+        // The depot sets the FMRI itself when the transaction is closed
+        let actions = actions.iter().filter(|a| !is_fmri(a)).collect::<Vec<_>>();
+        for (index, action) in actions.iter().enumerate() {
+            let payload = match action.name.as_str() {
+                "file" | "license" => Some(read_payload(action, payload_dirs)?),
+                _ => None,
+            };
+
+            let url = format!("{}/add/0/{}/{}", base, transaction, action.name);
+            self.send(Method::POST, &url, |mut request| {
+                for (number, (key, value)) in action.attributes.iter().enumerate() {
+                    request = request.header(
+                        format!("X-IPkg-SetAttr{}", number),
+                        format!("{}={}", key, value),
+                    );
+                }
+                if let Some(payload) = &payload {
+                    request = request.body(payload.clone());
+                }
+                request
+            })
+            .await?;
+
+            if (index + 1) % 100 == 0 || index + 1 == actions.len() {
+                println!("Sent {} of {} actions", index + 1, actions.len());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The content of the payload of `action`, found like pkgsend does by the payload
+/// reference or path in the payload directories.
+fn read_payload(action: &mogrify::Action, payload_dirs: &[PathBuf]) -> Result<Vec<u8>> {
+    let payload = action
+        .payload
+        .clone()
+        .or(action.values("path").next().cloned())
+        .ok_or(DepotError::MissingPayload {
+            payload: String::new(),
+            action: action.to_string(),
+        })?;
+    let path = payload_dirs
+        .iter()
+        .map(|dir| dir.join(&payload))
+        .find(|p| p.is_file())
+        .ok_or(DepotError::MissingPayload {
+            payload: payload.clone(),
+            action: action.to_string(),
+        })?;
+    std::fs::read(&path)
+        .into_diagnostic()
+        .wrap_err(format!("could not read payload {}", path.display()))
+}
+
+/// Publish the resolved manifests of the component to the remote repository. Payloads
+/// are looked up in the same directories the local publication uses.
+pub async fn publish_remote(
+    wks: &Workspace,
+    pkg: &Component,
+    publisher: &str,
+    remote: &RemoteRepository,
+    manifests: &[ManifestCollection],
+) -> Result<Vec<String>> {
+    let depot = Depot::new(remote)?;
+    depot
+        .ensure_publishable()
+        .await
+        .wrap_err(format!("cannot publish to {}", depot.url()))?;
+
+    let build_dir = wks.get_or_create_build_dir()?;
+    let payload_dirs = vec![
+        wks.get_or_create_prototype_dir()?,
+        build_dir.join(derive_source_name(pkg.recipe.name.clone())),
+        pkg.get_path().to_path_buf(),
+    ];
+
+    let mut published = vec![];
+    for manifest in manifests {
+        let manifest_path = wks
+            .get_or_create_manifest_dir()?
+            .join(manifest.get_resolved_name());
+        published.push(
+            depot
+                .publish_manifest(publisher, &manifest_path, &payload_dirs)
+                .await
+                .wrap_err(format!("publishing manifest {} failed", manifest))?,
+        );
+        println!(
+            "Install with pkg set-publisher -g {} {}; pkg install {}",
+            depot.url(),
+            publisher,
+            manifest.get_pkg_name()
+        );
+    }
+    Ok(published)
+}
@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use component::Component;
+use config::Settings;
+use miette::{IntoDiagnostic, Result, WrapErr};
+use mogrify::Action;
+use workspace::{Architecture, Workspace};
+
+use crate::build::elf::read_dynamic_section;
+use crate::build::ips;
+use crate::build::manifest::CONTENT_HASH_ATTRIBUTE;
+use crate::build::tool::{run_tool, PackageTool};
+
+/// Attribute the SONAME of shared objects is recorded in when a baseline is saved.
+const SONAME_ATTRIBUTE: &str = "forge.soname";
+
+/// Attributes whose changes are reported besides added and removed paths.
+const COMPARED_ATTRIBUTES: [&str; 5] = ["mode", "owner", "group", "target", CONTENT_HASH_ATTRIBUTE];
+
+#[derive(Debug, Parser)]
+pub struct DiffProtoArgs {
+    /// Compare against this saved manifest instead of the last published version. Only
+    /// saved manifests record the SONAMEs of libraries to compare
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    /// Save the manifest of the current build to this file for later comparisons
+    #[arg(long)]
+    save_baseline: Option<PathBuf>,
+
+    /// Architecture of the build to compare (i386, amd64, aarch64, sparc)
+    #[arg(long)]
+    pub arch: Option<Architecture>,
+}
+
+/// The first value of the attribute, the compared attributes have only one.
+fn attribute<'a>(action: &'a Action, key: &'a str) -> Option<&'a String> {
+    action.values(key).next()
+}
+
+/// Compare the prototype directory of the current build with the last published
+/// version of the component or a saved baseline.
+pub fn diff_proto(component: &Component, wks: &Workspace, args: &DiffProtoArgs) -> Result<()> {
+    ips::run_generate_filelist(wks, component).wrap_err("generating file list failed")?;
+    let filelist = fs::read_to_string(wks.get_or_create_manifest_dir()?.join("filelist.fmt"))
+        .into_diagnostic()?;
+    let mut current = parse_manifest(&filelist);
+    record_sonames(&mut current, &wks.get_or_create_prototype_dir()?);
+
+    if let Some(path) = &args.save_baseline {
+        let manifest = current
+            .iter()
+            .map(|action| action.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(path, manifest + "\n").into_diagnostic()?;
+        println!("Saved baseline to {}", path.display());
+    }
+
+    let (baseline, baseline_name) = match &args.baseline {
+        Some(path) => (
+            fs::read_to_string(path)
+                .into_diagnostic()
+                .wrap_err(format!("could not read baseline {}", path.display()))?,
+            path.display().to_string(),
+        ),
+        None => (
+            published_manifest(component)?,
+            String::from("the last published version"),
+        ),
+    };
+    let baseline = parse_manifest(&baseline);
+
+    let changes = compare(&baseline, &current);
+    if changes.is_empty() {
+        println!("No changes to {}", baseline_name);
+    } else {
+        println!("Changes to {}:", baseline_name);
+        for change in changes {
+            println!("{}", change);
+        }
+    }
+    Ok(())
+}
+
+fn published_manifest(component: &Component) -> Result<String> {
+    let repo_path = Settings::open()
+        .and_then(|settings| settings.get_or_create_repo_dir())
+        .into_diagnostic()?;
+    let mut names = component
+        .recipe
+        .package_sections
+        .iter()
+        .filter_map(|p| p.name.clone())
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        names.push(component.get_name());
+    }
+
+    let mut manifest = String::new();
+    for name in names {
+        let mut cmd = PackageTool::PkgRepo.command();
+        cmd.arg("contents")
+            .arg("-m")
+            .arg("-s")
+            .arg(repo_path.to_string_lossy().to_string())
+            .arg(format!("{}@latest", name));
+        let output = run_tool(PackageTool::PkgRepo, cmd, None).wrap_err(format!(
+            "no published version of {} found, pass --baseline to compare against a saved manifest",
+            name
+        ))?;
+        manifest.push_str(&output.stdout_lossy());
+        manifest.push('\n');
+    }
+    Ok(manifest)
+}
+
+/// The file system actions of a manifest by path.
+fn parse_manifest(manifest: &str) -> BTreeMap<String, Action> {
+    let mut actions = BTreeMap::new();
+    // pkgfmt wraps long actions with a trailing backslash
+    let joined = manifest.replace("\\\n", " ");
+    for line in joined.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with('<') {
+            continue;
+        }
+        let Ok(mut action) = Action::parse(line) else {
+            continue;
+        };
+        if !["file", "dir", "link", "hardlink"].contains(&action.name.as_str()) {
+            continue;
+        }
+        // Payload references differ between builds anyway
+        action.payload = None;
+        if let Some(path) = attribute(&action, "path") {
+            actions.insert(path.trim_start_matches('/').to_string(), action);
+        }
+    }
+    actions
+}
+
+/// Record the DT_SONAME of the shared objects in the prototype directory.
+fn record_sonames(actions: &mut BTreeMap<String, Action>, proto_dir: &Path) {
+    for (path, action) in actions.iter_mut() {
+        if action.name != "file" {
+            continue;
+        }
+        let Ok(contents) = fs::read(proto_dir.join(path.as_str())) else {
+            continue;
+        };
+        if let Some(soname) =
+            read_dynamic_section(PathBuf::from(path.as_str()), &contents).and_then(|o| o.soname)
+        {
+            action.set(SONAME_ATTRIBUTE, soname);
+        }
+    }
+}
+
+/// The library name a SONAME belongs to: libfoo.so.1 -> libfoo
+fn library_stem(soname: &str) -> &str {
+    soname.split(".so").next().unwrap_or(soname)
+}
+
+fn compare(baseline: &BTreeMap<String, Action>, current: &BTreeMap<String, Action>) -> Vec<String> {
+    let mut changes = vec![];
+    for (path, action) in current.iter() {
+        match baseline.get(path) {
+            None => changes.push(format!("+ {} {}", action.name, path)),
+            Some(old) if old.name != action.name => {
+                changes.push(format!("~ {}: {} -> {}", path, old.name, action.name))
+            }
+            Some(old) => {
+                for key in COMPARED_ATTRIBUTES {
+                    let (before, after) = (attribute(old, key), attribute(action, key));
+                    // Attributes the baseline does not know about are not a change
+                    if let (Some(before), Some(after)) = (before, after) {
+                        if before != after {
+                            changes.push(format!("~ {}: {} {} -> {}", path, key, before, after));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for (path, action) in baseline.iter().filter(|(p, _)| !current.contains_key(*p)) {
+        changes.push(format!("- {} {}", action.name, path));
+    }
+
+    // Published manifests do not record SONAMEs, guessing them from file names would
+    // compare guesses with the real ones of the build
+    let sonames = |actions: &BTreeMap<String, Action>| {
+        let mut by_library: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for soname in actions
+            .values()
+            .filter_map(|a| attribute(a, SONAME_ATTRIBUTE))
+        {
+            by_library
+                .entry(library_stem(soname).to_string())
+                .or_default()
+                .insert(soname.clone());
+        }
+        by_library
+    };
+    let (old_sonames, new_sonames) = (sonames(baseline), sonames(current));
+    for (library, new) in new_sonames.iter() {
+        if let Some(old) = old_sonames.get(library) {
+            if old != new {
+                changes.push(format!(
+                    "! SONAME of {} changed: {} -> {}",
+                    library,
+                    old.iter().cloned().collect::<Vec<_>>().join(", "),
+                    new.iter().cloned().collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_manifest_keeps_file_system_actions() {
+        let actions = parse_manifest(
+            "set name=pkg.fmri value=pkg:/library/foo@1.0\n\
+             # a comment\n\
+             file 0123abcd path=usr/lib/libfoo.so.1 owner=root group=bin \\\n    mode=0555\n\
+             dir path=/usr/lib owner=root group=bin mode=0755\n\
+             link path=usr/lib/libfoo.so target=\"libfoo.so.1\"\n",
+        );
+        assert_eq!(
+            actions.keys().collect::<Vec<_>>(),
+            ["usr/lib", "usr/lib/libfoo.so", "usr/lib/libfoo.so.1"]
+        );
+        let file = &actions["usr/lib/libfoo.so.1"];
+        assert_eq!(file.payload, None);
+        assert_eq!(attribute(file, "mode").map(String::as_str), Some("0555"));
+        assert_eq!(
+            attribute(&actions["usr/lib/libfoo.so"], "target").map(String::as_str),
+            Some("libfoo.so.1")
+        );
+    }
+
+    #[test]
+    fn compare_reports_added_removed_and_changed_paths() {
+        let baseline = parse_manifest(
+            "file path=usr/bin/foo mode=0555\n\
+             file path=usr/bin/bar mode=0555\n\
+             file path=usr/share/foo mode=0444\n",
+        );
+        let current = parse_manifest(
+            "file path=usr/bin/foo mode=0755\n\
+             link path=usr/share/foo target=bar\n\
+             file path=usr/bin/baz mode=0555\n",
+        );
+        assert_eq!(
+            compare(&baseline, &current),
+            [
+                "+ file usr/bin/baz",
+                "~ usr/bin/foo: mode 0555 -> 0755",
+                "~ usr/share/foo: file -> link",
+                "- file usr/bin/bar",
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_reports_only_recorded_soname_changes() {
+        let current = parse_manifest("file path=usr/lib/libfoo.so.2 forge.soname=libfoo.so.2\n");
+        // Published manifests carry no SONAME, the file name is not taken for one
+        let published = parse_manifest("file path=usr/lib/libfoo.so.2\n");
+        assert!(compare(&published, &current).is_empty());
+
+        let saved = parse_manifest("file path=usr/lib/libfoo.so.2 forge.soname=libfoo.so.1\n");
+        assert_eq!(
+            compare(&saved, &current),
+            ["! SONAME of libfoo changed: libfoo.so.1 -> libfoo.so.2"]
+        );
+    }
+}
@@ -0,0 +1,517 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use component::Component;
+use gate::Gate;
+use miette::{Diagnostic, IntoDiagnostic, Result};
+use thiserror::Error;
+use workspace::Workspace;
+
+use crate::build::normalize::walk;
+use crate::forge::find_library_providers;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const SHT_DYNAMIC: u32 = 6;
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_SONAME: u64 = 14;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+/// Directories the runtime linker searches after the runpath of an object.
+const DEFAULT_DIRS_32: [&str; 2] = ["/lib", "/usr/lib"];
+const DEFAULT_DIRS_64: [&str; 2] = ["/lib/64", "/usr/lib/64"];
+
+/// The dynamic section of an ELF object in the prototype directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElfObject {
+    /// Path the object is installed at, relative to the root of the image.
+    pub path: PathBuf,
+    pub is_64bit: bool,
+    pub soname: Option<String>,
+    pub needed: Vec<String>,
+    pub runpaths: Vec<String>,
+}
+
+impl ElfObject {
+    /// The directories the runtime linker searches for the dependencies of this object
+    /// in order, with $ORIGIN expanded.
+    pub fn search_dirs(&self) -> Vec<String> {
+        let origin = Path::new("/")
+            .join(&self.path)
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or(String::from("/"));
+        let defaults = if self.is_64bit {
+            DEFAULT_DIRS_64
+        } else {
+            DEFAULT_DIRS_32
+        };
+        self.runpaths
+            .iter()
+            .map(|r| r.replace("${ORIGIN}", &origin).replace("$ORIGIN", &origin))
+            .chain(defaults.iter().map(|d| d.to_string()))
+            .collect()
+    }
+}
+
+/// Where a library an object needs comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Provider {
+    /// Delivered by the component itself.
+    Prototype(String),
+    /// Installed on the build host.
+    Host(String),
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ElfError {
+    #[error("{object} needs {library} which neither the build nor the build host provides")]
+    #[diagnostic(
+        code(pkgdev::elf::unresolved),
+        help("searched {}", searched.join(", "))
+    )]
+    UnresolvedLibrary {
+        object: String,
+        library: String,
+        searched: Vec<String>,
+    },
+
+    #[error(
+        "{object} needs {library} from {provided_by} which is not installed on the build host"
+    )]
+    #[diagnostic(code(pkgdev::elf::not_installed), help("install {provided_by}"))]
+    NotInstalledLibrary {
+        object: String,
+        library: String,
+        provided_by: String,
+    },
+
+    #[error("{} libraries needed by the prototype directory cannot be resolved", libraries.len())]
+    #[diagnostic(
+        code(pkgdev::elf::unresolved_libraries),
+        help("install the packages providing them or add them to the build")
+    )]
+    UnresolvedLibraries {
+        #[related]
+        libraries: Vec<ElfError>,
+    },
+}
+
+/// Read the dynamic section of the ELF file installed at `path`. Returns `None` for
+/// anything that is not a dynamically linked ELF object, including truncated and
+/// malformed ones.
+pub fn read_dynamic_section(path: PathBuf, contents: &[u8]) -> Option<ElfObject> {
+    if !contents.starts_with(ELF_MAGIC) || contents.len() < 0x40 {
+        return None;
+    }
+    let is_64bit = match contents[4] {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let reader = Reader {
+        contents,
+        big_endian: contents[5] == 2,
+    };
+
+    let (shoff, shentsize, shnum) = if is_64bit {
+        (reader.u64(0x28)?, reader.u16(0x3a)?, reader.u16(0x3c)?)
+    } else {
+        (
+            reader.u32(0x20)? as u64,
+            reader.u16(0x2e)?,
+            reader.u16(0x30)?,
+        )
+    };
+
+    let section = |index: u64| -> Option<(u32, u64, u64, u32)> {
+        let base = offset(shoff, index.checked_mul(shentsize as u64)?)?;
+        let field = |at: usize| base.checked_add(at);
+        if is_64bit {
+            Some((
+                reader.u32(field(4)?)?,
+                reader.u64(field(24)?)?,
+                reader.u64(field(32)?)?,
+                reader.u32(field(40)?)?,
+            ))
+        } else {
+            Some((
+                reader.u32(field(4)?)?,
+                reader.u32(field(16)?)? as u64,
+                reader.u32(field(20)?)? as u64,
+                reader.u32(field(24)?)?,
+            ))
+        }
+    };
+
+    let (_, dyn_offset, dyn_size, strtab_index) = (0..shnum as u64)
+        .filter_map(section)
+        .find(|(sh_type, ..)| *sh_type == SHT_DYNAMIC)?;
+    let (_, strtab_offset, ..) = section(strtab_index as u64)?;
+
+    let entry_size = if is_64bit { 16 } else { 8 };
+    let mut soname = None;
+    let mut needed = vec![];
+    let mut runpaths = vec![];
+    for i in 0..dyn_size / entry_size {
+        let base = offset(dyn_offset, i.checked_mul(entry_size)?)?;
+        let (tag, value) = if is_64bit {
+            (reader.u64(base)?, reader.u64(base.checked_add(8)?)?)
+        } else {
+            (
+                reader.u32(base)? as u64,
+                reader.u32(base.checked_add(4)?)? as u64,
+            )
+        };
+        let string = || reader.string(offset(strtab_offset, value)?);
+        match tag {
+            DT_NULL => break,
+            DT_NEEDED => needed.extend(string()),
+            DT_SONAME => soname = string(),
+            DT_RPATH | DT_RUNPATH => {
+                for runpath in string().unwrap_or_default().split(':') {
+                    if !runpath.is_empty() && !runpaths.iter().any(|r| r == runpath) {
+                        runpaths.push(runpath.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(ElfObject {
+        path,
+        is_64bit,
+        soname,
+        needed,
+        runpaths,
+    })
+}
+
+/// `base + relative` as an offset into the file, `None` if it does not fit.
+fn offset(base: u64, relative: u64) -> Option<usize> {
+    usize::try_from(base.checked_add(relative)?).ok()
+}
+
+struct Reader<'a> {
+    contents: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.contents
+            .get(offset..offset.checked_add(N)?)?
+            .try_into()
+            .ok()
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.bytes::<2>(offset)?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.bytes::<4>(offset)?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Option<u64> {
+        let b = self.bytes::<8>(offset)?;
+        Some(if self.big_endian {
+            u64::from_be_bytes(b)
+        } else {
+            u64::from_le_bytes(b)
+        })
+    }
+
+    fn string(&self, offset: usize) -> Option<String> {
+        let rest = self.contents.get(offset..)?;
+        let end = rest.iter().position(|b| *b == 0)?;
+        Some(String::from_utf8_lossy(&rest[..end]).to_string())
+    }
+}
+
+/// All dynamically linked ELF objects in the prototype directory.
+pub fn scan_prototype_dir(wks: &Workspace) -> Result<Vec<ElfObject>> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let mut objects = vec![];
+    for path in walk(&proto_dir)? {
+        let metadata = fs::symlink_metadata(&path).into_diagnostic()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let contents = fs::read(&path).into_diagnostic()?;
+        let relative = path
+            .strip_prefix(&proto_dir)
+            .into_diagnostic()?
+            .to_path_buf();
+        objects.extend(read_dynamic_section(relative, &contents));
+    }
+    Ok(objects)
+}
+
+/// The SONAMEs of the libraries the prototype directory delivers.
+pub fn provided_libraries(objects: &[ElfObject]) -> BTreeSet<String> {
+    objects.iter().filter_map(|o| o.soname.clone()).collect()
+}
+
+/// Find the library `needed` by `object` in the prototype directory or on the host.
+pub fn resolve(
+    proto_dir: &Path,
+    objects: &[ElfObject],
+    object: &ElfObject,
+    needed: &str,
+) -> Option<Provider> {
+    for dir in object.search_dirs() {
+        let candidate = Path::new(&dir).join(needed);
+        let relative = candidate.strip_prefix("/").unwrap_or(&candidate);
+        if proto_dir.join(relative).exists() {
+            return Some(Provider::Prototype(candidate.to_string_lossy().to_string()));
+        }
+        if candidate.exists() {
+            return Some(Provider::Host(candidate.to_string_lossy().to_string()));
+        }
+    }
+    // Libraries delivered under a different file name are still found through
+    // the symlinks the packages deliver
+    objects
+        .iter()
+        .find(|o| o.soname.as_deref() == Some(needed) && o.is_64bit == object.is_64bit)
+        .map(|o| Provider::Prototype(format!("/{}", o.path.display())))
+}
+
+/// Every library some object in the prototype directory needs but that can not be
+/// found in the prototype directory or on the build host.
+pub fn unresolved_libraries(wks: &Workspace, objects: &[ElfObject]) -> Result<Vec<ElfError>> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let mut unresolved = vec![];
+    for object in objects {
+        for needed in object.needed.iter() {
+            if resolve(&proto_dir, objects, object, needed).is_none() {
+                unresolved.push(ElfError::UnresolvedLibrary {
+                    object: format!("/{}", object.path.display()),
+                    library: needed.clone(),
+                    searched: object.search_dirs(),
+                });
+            }
+        }
+    }
+    Ok(unresolved)
+}
+
+/// Fail early with the exact object and search path of every library that
+/// pkgdepend would not be able to resolve.
+pub fn check_prototype_dir(wks: &Workspace) -> Result<()> {
+    let objects = scan_prototype_dir(wks)?;
+    let unresolved = unresolved_libraries(wks, &objects)?;
+    if unresolved.is_empty() {
+        println!(
+            "All libraries needed by {} ELF objects are resolvable",
+            objects.len()
+        );
+        Ok(())
+    } else {
+        Err(ElfError::UnresolvedLibraries {
+            libraries: unresolved,
+        }
+        .into())
+    }
+}
+
+/// Print where every library needed by the prototype directory comes from, asking the
+/// forge for the ones the build does not deliver itself. With `record` the libraries
+/// the build delivers are written to the pkg5 file of the component so importing it
+/// makes them known to the forge.
+pub async fn report_elf_dependencies(
+    component: &Component,
+    gate: &Option<Gate>,
+    wks: &Workspace,
+    record: bool,
+) -> Result<()> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let objects = scan_prototype_dir(wks)?;
+
+    let external = objects
+        .iter()
+        .flat_map(|o| {
+            o.needed.iter().filter(|n| {
+                !matches!(
+                    resolve(&proto_dir, &objects, o, n),
+                    Some(Provider::Prototype(_))
+                )
+            })
+        })
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let gate_id = gate.as_ref().and_then(|g| g.id.clone());
+    let providers = match find_library_providers(gate_id, external.into_iter().collect()).await {
+        Ok(providers) => providers,
+        Err(e) => {
+            println!("Warning: not asking the forge for library providers: {}", e);
+            vec![]
+        }
+    };
+    let forge_provider = |library: &str| {
+        providers
+            .iter()
+            .find(|p| p.library == library)
+            .map(|p| match p.fmris.first() {
+                Some(fmri) => format!("{} ({} {})", fmri, p.component, p.version),
+                None => format!("{} {}", p.component, p.version),
+            })
+    };
+
+    let mut unresolved = vec![];
+    for object in objects.iter() {
+        println!("/{}", object.path.display());
+        for needed in object.needed.iter() {
+            let local = resolve(&proto_dir, &objects, object, needed);
+            let from_forge = forge_provider(needed);
+            match (&local, &from_forge) {
+                (Some(Provider::Prototype(path)), _) => {
+                    println!("    {} => {} (this component)", needed, path)
+                }
+                (Some(Provider::Host(path)), Some(package)) => {
+                    println!("    {} => {} from {}", needed, path, package)
+                }
+                (Some(Provider::Host(path)), None) => {
+                    println!("    {} => {} (build host)", needed, path)
+                }
+                (None, Some(package)) => {
+                    println!("    {} => not installed, delivered by {}", needed, package);
+                    unresolved.push(ElfError::NotInstalledLibrary {
+                        object: format!("/{}", object.path.display()),
+                        library: needed.clone(),
+                        provided_by: package.clone(),
+                    });
+                }
+                (None, None) => {
+                    println!("    {} => unresolved", needed);
+                    unresolved.push(ElfError::UnresolvedLibrary {
+                        object: format!("/{}", object.path.display()),
+                        library: needed.clone(),
+                        searched: object.search_dirs(),
+                    });
+                }
+            }
+        }
+    }
+
+    if record {
+        let mut package_meta = component.package_meta.clone().unwrap_or_default();
+        package_meta.set_libraries(provided_libraries(&objects).into_iter().collect());
+        let file = fs::File::create(component.get_path().join("pkg5")).into_diagnostic()?;
+        serde_json::to_writer_pretty(file, &package_meta).into_diagnostic()?;
+        println!(
+            "Recorded {} libraries in the pkg5 file of {}",
+            package_meta.libraries().len(),
+            component.get_name()
+        );
+    }
+
+    if unresolved.is_empty() {
+        Ok(())
+    } else {
+        Err(ElfError::UnresolvedLibraries {
+            libraries: unresolved,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little endian 64-bit object named libfoo.so.1 needing libc.so.1.
+    fn elf64() -> Vec<u8> {
+        let mut elf = vec![0u8; 0x150];
+        elf[..4].copy_from_slice(ELF_MAGIC);
+        elf[4] = 2;
+        elf[5] = 1;
+        elf[0x28..0x30].copy_from_slice(&0x90u64.to_le_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&3u16.to_le_bytes());
+        // String table
+        elf[0x40..0x57].copy_from_slice(b"\0libc.so.1\0libfoo.so.1\0");
+        // Dynamic section
+        for (i, (tag, value)) in [(DT_NEEDED, 1u64), (DT_SONAME, 11), (DT_NULL, 0)]
+            .into_iter()
+            .enumerate()
+        {
+            let base = 0x60 + i * 16;
+            elf[base..base + 8].copy_from_slice(&tag.to_le_bytes());
+            elf[base + 8..base + 16].copy_from_slice(&value.to_le_bytes());
+        }
+        // Section headers, the first one is empty
+        for (index, sh_type, offset, size, link) in
+            [(1, 3u32, 0x40u64, 0x17u64, 0u32), (2, 6, 0x60, 48, 1)]
+        {
+            let base = 0x90 + index * 64;
+            elf[base + 4..base + 8].copy_from_slice(&sh_type.to_le_bytes());
+            elf[base + 24..base + 32].copy_from_slice(&offset.to_le_bytes());
+            elf[base + 32..base + 40].copy_from_slice(&size.to_le_bytes());
+            elf[base + 40..base + 44].copy_from_slice(&link.to_le_bytes());
+        }
+        elf
+    }
+
+    fn read(contents: &[u8]) -> Option<ElfObject> {
+        read_dynamic_section(PathBuf::from("usr/lib/libfoo.so.1"), contents)
+    }
+
+    #[test]
+    fn reads_the_dynamic_section() {
+        let object = read(&elf64()).unwrap();
+        assert!(object.is_64bit);
+        assert_eq!(object.soname.as_deref(), Some("libfoo.so.1"));
+        assert_eq!(object.needed, ["libc.so.1"]);
+        assert!(object.runpaths.is_empty());
+    }
+
+    #[test]
+    fn truncated_objects_are_skipped() {
+        let elf = elf64();
+        for len in 0..elf.len() {
+            // Must not panic, whatever part is missing
+            let _ = read(&elf[..len]);
+        }
+        assert_eq!(read(&elf[..0x3c]), None);
+        assert_eq!(read(&elf[..0x100]), None);
+    }
+
+    #[test]
+    fn malformed_offsets_are_skipped() {
+        let mut elf = elf64();
+        elf[0x28..0x30].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        assert_eq!(read(&elf), None);
+
+        let mut elf = elf64();
+        elf[0x3a..0x3c].copy_from_slice(&u16::MAX.to_le_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert_eq!(read(&elf), None);
+
+        // A section header pointing the dynamic section past the end of the address space
+        let mut elf = elf64();
+        elf[0x90 + 128 + 24..0x90 + 128 + 32].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(read(&elf), None);
+
+        // Strings past the end of the address space are left out
+        let mut elf = elf64();
+        elf[0x68..0x70].copy_from_slice(&u64::MAX.to_le_bytes());
+        let object = read(&elf).unwrap();
+        assert!(object.needed.is_empty());
+        assert_eq!(object.soname.as_deref(), Some("libfoo.so.1"));
+    }
+}
@@ -1,20 +1,18 @@
+use crate::build::manifest::{generate_actions, render_manifest};
+use crate::build::tool::{run_tool, PackageTool};
 use crate::sources::derive_source_name;
-use component::{Component, SourceNode, TransformNode};
-use config::Settings;
+use component::{Component, LicenseEntry, PackageSection, SourceNode, TransformNode};
 use fs_extra::file::write_all;
-use gate::Gate;
+use gate::{Gate, ManifestTemplate};
 use microtemplate::{render, Substitutions};
-use miette::{IntoDiagnostic, Result};
+use miette::{IntoDiagnostic, Result, WrapErr};
+use mogrify::Mogrify;
 use std::fmt::{Display, Formatter};
-use std::{
-    fs::File,
-    path::PathBuf,
-    process::{Command, Stdio},
-};
+use std::path::{Path, PathBuf};
 use workspace::Workspace;
 
-const DEFAULT_IPS_TEMPLATE: &str = r#"
-#
+/// License of the manifests themselves unless the gate or its publisher has its own.
+const DEFAULT_BOILERPLATE: &str = r#"#
 # This file and its contents are supplied under the terms of the
 # Common Development and Distribution License ("CDDL"), version 1.0.
 # You may only use this file in accordance with the terms of version
@@ -27,15 +25,16 @@ const DEFAULT_IPS_TEMPLATE: &str = r#"
 
 #
 # Copyright 2024 OpenIndiana Maintainers
-#
+#"#;
 
-set name=pkg.fmri value=pkg:/{name}@{version},{build_version}-{branch_version}.{revision}
+/// The actions manifests start with, the variables are [`gate::MANIFEST_TEMPLATE_VARIABLES`].
+const DEFAULT_IPS_HEADER: &str = r#"set name=pkg.fmri value=pkg:/{name}@{version},{build_version}-{branch_version}.{revision}
 set name=pkg.summary value="{summary}"
 set name=info.classification value="org.opensolaris.category.2008:{classification}"
 set name=info.upstream-url value="{project_url}"
 set name=info.source-url value="{source_url}"
 
-license {license_file_name} license='{license_name}'
+{licenses}
 
 "#;
 //TODO implement ips component version formatter. build_num (year)
@@ -51,8 +50,51 @@ struct StringInterpolationVars<'a> {
     pub classification: &'a str,
     pub project_url: &'a str,
     pub source_url: &'a str,
-    pub license_file_name: &'a str,
-    pub license_name: &'a str,
+    pub licenses: &'a str,
+}
+
+/// The template the manifests of the gate are rendered from. Parts gate.kdl sets win over
+/// the ones of the publisher forged passed in [`forge::MANIFEST_TEMPLATE_ENV`], which win
+/// over the OpenIndiana ones.
+fn manifest_template(gate: &Option<Gate>) -> Result<String> {
+    let publisher = match std::env::var(forge::MANIFEST_TEMPLATE_ENV) {
+        Ok(json) => {
+            let template: ManifestTemplate = serde_json::from_str(&json)
+                .into_diagnostic()
+                .wrap_err(format!("invalid {}", forge::MANIFEST_TEMPLATE_ENV))?;
+            template.validate()?;
+            template
+        }
+        Err(_) => ManifestTemplate::default(),
+    };
+    let gate = gate
+        .as_ref()
+        .and_then(|g| g.manifest_template.clone())
+        .unwrap_or_default();
+
+    let boilerplate = gate
+        .boilerplate
+        .or(publisher.boilerplate)
+        .unwrap_or(DEFAULT_BOILERPLATE.to_string());
+    let header = gate
+        .header
+        .or(publisher.header)
+        .unwrap_or(DEFAULT_IPS_HEADER.to_string());
+    Ok(format!("\n{}\n\n{}", comment_block(&boilerplate), header))
+}
+
+/// Turn the lines of the boilerplate into comments, the ones that already are stay as is.
+fn comment_block(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.is_empty() || line.starts_with('#') {
+                line.to_string()
+            } else {
+                format!("# {}", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 fn get_source_url<'a>(src: &'a SourceNode) -> &'a str {
@@ -63,6 +105,34 @@ fn get_source_url<'a>(src: &'a SourceNode) -> &'a str {
     }
 }
 
+/// The license actions of a package: the license of the recipe, its additional licenses
+/// and the licenses of the package section.
+fn license_lines(pkg: &Component, section: Option<&PackageSection>) -> Result<String> {
+    let recipe = &pkg.recipe;
+    let mut licenses = match (&recipe.license_file, &recipe.license) {
+        (Some(file), Some(name)) => vec![LicenseEntry {
+            file: file.clone(),
+            name: name.clone(),
+        }],
+        (Some(_), None) => return Err(miette::miette!("no license specified")),
+        (None, Some(_)) => return Err(miette::miette!("no license_file specified")),
+        (None, None) => vec![],
+    };
+    licenses.extend(recipe.additional_licenses.iter().cloned());
+    if let Some(section) = section {
+        licenses.extend(section.licenses.iter().cloned());
+    }
+    if licenses.is_empty() {
+        return Err(miette::miette!("no license specified"));
+    }
+
+    Ok(licenses
+        .iter()
+        .map(|l| format!("license {} license='{}'", l.file, l.name))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
 pub struct ManifestCollection {
     pkg_name: String,
     name: String,
@@ -107,31 +177,24 @@ impl ManifestCollection {
     }
 }
 
+/// Write the actions of everything in the prototype directory to filelist.fmt. Generated
+/// natively in the format of pkgfmt so diffs and reproducibility checks work without
+/// the IPS tools installed.
 pub fn run_generate_filelist(wks: &Workspace, pkg: &Component) -> Result<()> {
     let proto_path = wks.get_or_create_prototype_dir()?;
     let manifest_path = wks.get_or_create_manifest_dir()?;
 
-    let formatted_manifest = File::create(manifest_path.join("filelist.fmt")).into_diagnostic()?;
-
-    let pkg_send_cmd = Command::new("pkgsend")
-        .arg("generate")
-        .arg(proto_path.to_string_lossy().to_string())
-        .stdout(Stdio::piped())
-        .spawn()
-        .into_diagnostic()?;
-
-    let pkg_fmt_cmd_status = Command::new("pkgfmt")
-        .stdin(pkg_send_cmd.stdout.unwrap())
-        .stdout(formatted_manifest)
-        .status()
+    let actions = generate_actions(&proto_path)?;
+    std::fs::write(manifest_path.join("filelist.fmt"), render_manifest(&actions))
         .into_diagnostic()?;
+    println!("Generated filelist for {}", pkg.get_name());
+    Ok(())
+}
 
-    if pkg_fmt_cmd_status.success() {
-        println!("Generated filelist for {}", pkg.get_name());
-        Ok(())
-    } else {
-        Err(miette::miette!("non zero code returned from pkgfmt"))
-    }
+/// Run a manifest through pkgfmt and write it to `target`.
+fn format_manifest(manifest: Vec<u8>, target: &Path) -> Result<()> {
+    let formatted = run_tool(PackageTool::PkgFmt, PackageTool::PkgFmt.command(), Some(manifest))?;
+    std::fs::write(target, formatted.stdout).into_diagnostic()
 }
 
 pub fn generate_manifest_files(
@@ -139,8 +202,10 @@ pub fn generate_manifest_files(
     pkg: &Component,
     gate: &Option<Gate>,
     transform_includes: Option<PathBuf>,
+    profile: Option<&str>,
 ) -> Result<Vec<ManifestCollection>> {
     let manifest_path = wks.get_or_create_manifest_dir()?;
+    let template = manifest_template(gate)?;
 
     let manifests = if pkg.recipe.package_sections.is_empty() {
         let name = pkg.get_name();
@@ -166,21 +231,14 @@ pub fn generate_manifest_files(
                 .clone()
                 .ok_or(miette::miette!("no project_url specified"))?,
             source_url: get_source_url(&pkg.recipe.sources[0].sources[0]),
-            license_file_name: &pkg
-                .recipe
-                .license_file
-                .clone()
-                .ok_or(miette::miette!("no license_file specified"))?,
-            license_name: &pkg
-                .recipe
-                .license
-                .clone()
-                .ok_or(miette::miette!("no license specified"))?,
+            licenses: &license_lines(pkg, None)?,
         };
-        let mut manifest = render(DEFAULT_IPS_TEMPLATE, vars);
+        let mut manifest = render(&template, vars);
 
         let drop_dir_line = "\n<transform dir path=.* -> drop>";
         manifest.push_str(drop_dir_line);
+        generate_skipped_step_lines(&mut manifest, pkg);
+        generate_profile_line(&mut manifest, profile);
 
         let manifest_collection = ManifestCollection::new(&pkg.get_name());
 
@@ -216,18 +274,9 @@ pub fn generate_manifest_files(
                     .clone()
                     .ok_or(miette::miette!("no project_url specified"))?,
                 source_url: get_source_url(&pkg.recipe.sources[0].sources[0]),
-                license_file_name: &pkg
-                    .recipe
-                    .license_file
-                    .clone()
-                    .ok_or(miette::miette!("no license_file specified"))?,
-                license_name: &pkg
-                    .recipe
-                    .license
-                    .clone()
-                    .ok_or(miette::miette!("no license specified"))?,
+                licenses: &license_lines(pkg, Some(p))?,
             };
-            let mut manifest = render(DEFAULT_IPS_TEMPLATE, vars);
+            let mut manifest = render(&template, vars);
             let default_action_keep_line = "\n<transform file link hardlink path=.* -> default keep false>";
             manifest.push_str(default_action_keep_line);
 
@@ -242,6 +291,8 @@ pub fn generate_manifest_files(
 
             let drop_dir_line = "\n<transform dir path=.* -> drop>";
             manifest.push_str(drop_dir_line);
+            generate_skipped_step_lines(&mut manifest, pkg);
+            generate_profile_line(&mut manifest, profile);
 
             let manifest_collection = ManifestCollection::new(&name);
             write_all(
@@ -277,54 +328,33 @@ pub fn generate_manifest_files(
         None
     };
 
+    // pkgmogrify ran in the parent directory, relative includes of recipes are found there
+    let include_dirs = transform_includes
+        .into_iter()
+        .chain([PathBuf::from("..")])
+        .collect::<Vec<_>>();
     for manifest in manifests.iter() {
-        let mogrified_manifest =
-            File::create(manifest_path.join(manifest.get_mogrified_name())).into_diagnostic()?;
-        let mut pkg_mogrify_cmd = Command::new("pkgmogrify");
-
-        if let Some(includes_path) = transform_includes.clone() {
-            pkg_mogrify_cmd.arg("-I").arg(&includes_path);
-        }
-        pkg_mogrify_cmd
-            .current_dir("..")
-            .arg(
-                manifest_path
-                    .join(manifest.get_base_manifest_name())
-                    .to_string_lossy()
-                    .to_string(),
-            )
-            .arg(
-                manifest_path
-                    .join("filelist.fmt")
-                    .to_string_lossy()
-                    .to_string(),
-            );
+        let mut mogrify = Mogrify::new(include_dirs.clone());
+        mogrify.read_file(manifest_path.join(manifest.get_base_manifest_name()))?;
+        mogrify.read_file(manifest_path.join("filelist.fmt"))?;
 
         if let Some(includes) = include_path.clone() {
-            pkg_mogrify_cmd.arg(&includes);
+            mogrify.read_file(includes)?;
         }
 
         if let Some(mog_file_path) = pkg.get_mogrify_manifest() {
-            pkg_mogrify_cmd.arg(&mog_file_path.to_string_lossy().to_string());
+            mogrify.read_file(mog_file_path)?;
         }
 
-        pkg_mogrify_cmd.stdout(Stdio::piped());
-        let pkg_mogrify_status = pkg_mogrify_cmd.spawn().into_diagnostic()?;
-
-        let pkg_fmt_cmd_status = Command::new("pkgfmt")
-            .stdin(pkg_mogrify_status.stdout.unwrap())
-            .stdout(mogrified_manifest)
-            .status()
-            .into_diagnostic()?;
-
-        if pkg_fmt_cmd_status.success() {
-            println!(
-                "Finished manifest transformations for manifest {}",
-                &manifest
-            );
-        } else {
-            return Err(miette::miette!("non zero code returned from pkgfmt"));
-        }
+        let mogrified = mogrify.render()?;
+        format_manifest(
+            mogrified.into_bytes(),
+            &manifest_path.join(manifest.get_mogrified_name()),
+        )?;
+        println!(
+            "Finished manifest transformations for manifest {}",
+            &manifest
+        );
     }
 
     Ok(manifests)
@@ -342,15 +372,60 @@ fn generate_transform_lines(manifest: &mut String, nodes: &Vec<TransformNode>) {
     }
 }
 
+/// Record the build steps the recipe skipped and why in the package metadata.
+fn generate_skipped_step_lines(manifest: &mut String, pkg: &Component) {
+    for skip in pkg.recipe.skipped_steps.iter() {
+        let line = format!(
+            "\nset name=org.openindiana.forge.skipped-step value=\"{}\"",
+            skip.to_string().replace('"', "\\\"")
+        );
+        manifest.push_str(&line);
+    }
+}
+
+/// Record the build profile the package was built with in the package metadata.
+fn generate_profile_line(manifest: &mut String, profile: Option<&str>) {
+    if let Some(profile) = profile {
+        manifest.push_str(&format!(
+            "\nset name=org.openindiana.forge.build-profile value=\"{}\"",
+            profile.replace('"', "\\\"")
+        ));
+    }
+}
+
+/// Use the mogrified manifests as they are when dependency generation is skipped.
+pub fn skip_generate_pkgdepend(wks: &Workspace, manifests: &[ManifestCollection]) -> Result<()> {
+    let manifest_path = wks.get_or_create_manifest_dir()?;
+    for manifest in manifests {
+        std::fs::copy(
+            manifest_path.join(manifest.get_mogrified_name()),
+            manifest_path.join(manifest.get_depend_name()),
+        )
+        .into_diagnostic()?;
+    }
+    Ok(())
+}
+
+/// Use the manifests with unresolved dependencies when resolution is skipped.
+pub fn skip_resolve_dependencies(wks: &Workspace, manifests: &[ManifestCollection]) -> Result<()> {
+    let manifest_path = wks.get_or_create_manifest_dir()?;
+    for manifest in manifests {
+        std::fs::copy(
+            manifest_path.join(manifest.get_depend_name()),
+            manifest_path.join(manifest.get_resolved_name()),
+        )
+        .into_diagnostic()?;
+    }
+    Ok(())
+}
+
 pub fn run_generate_pkgdepend(wks: &Workspace, manifests: &[ManifestCollection]) -> Result<()> {
     let manifest_path = wks.get_or_create_manifest_dir()?;
     let prototype_path = wks.get_or_create_prototype_dir()?;
 
     for manifest in manifests {
-        let depend_manifest =
-            File::create(manifest_path.join(manifest.get_depend_name())).into_diagnostic()?;
-
-        let pkg_depend_cmd = Command::new("pkgdepend")
+        let mut pkg_depend_cmd = PackageTool::PkgDepend.command();
+        pkg_depend_cmd
             .arg("generate")
             .arg("-m")
             .arg("-d")
@@ -360,25 +435,15 @@ pub fn run_generate_pkgdepend(wks: &Workspace, manifests: &[ManifestCollection])
                     .join(manifest.get_mogrified_name())
                     .to_string_lossy()
                     .to_string(),
-            )
-            .stdout(Stdio::piped())
-            .spawn()
-            .into_diagnostic()?;
-
-        let pkg_fmt_cmd_status = Command::new("pkgfmt")
-            .stdin(pkg_depend_cmd.stdout.unwrap())
-            .stdout(depend_manifest)
-            .status()
-            .into_diagnostic()?;
-
-        if pkg_fmt_cmd_status.success() {
-            println!("Generated dependency entries for manifest {}", manifest);
-        } else {
-            return Err(miette::miette!(
-                "dependency generation failed for manifest {}",
-                manifest
-            ));
-        }
+            );
+        let dependencies = run_tool(PackageTool::PkgDepend, pkg_depend_cmd, None)?;
+        eprint!("{}", dependencies.stderr_lossy());
+
+        format_manifest(
+            dependencies.stdout,
+            &manifest_path.join(manifest.get_depend_name()),
+        )?;
+        println!("Generated dependency entries for manifest {}", manifest);
     }
     Ok(())
 }
@@ -387,25 +452,20 @@ pub fn run_resolve_dependencies(wks: &Workspace, manifests: &[ManifestCollection
     let manifest_path = wks.get_or_create_manifest_dir()?;
 
     println!("Attempting to resolve runtime dependencies");
-    let pkg_depend_cmd = Command::new("pkgdepend")
+    let mut pkg_depend_cmd = PackageTool::PkgDepend.command();
+    pkg_depend_cmd
         .arg("resolve")
         .arg("-m")
         .arg("-v")
         .args(manifests.iter().map(|manifest| manifest_path
             .join(manifest.get_depend_name())
             .to_string_lossy()
-            .to_string()).collect::<Vec<_>>())
-        .stdout(Stdio::inherit())
-        .status()
-        .into_diagnostic()?;
+            .to_string()).collect::<Vec<_>>());
+    let output = run_tool(PackageTool::PkgDepend, pkg_depend_cmd, None)?;
+    print!("{}", output.stdout_lossy());
+    eprint!("{}", output.stderr_lossy());
 
-    if pkg_depend_cmd.success() {
-        println!("Resolved dependencies");
-    } else {
-        return Err(miette::miette!(
-                "failed to resolve dependencies",
-            ));
-    }
+    println!("Resolved dependencies");
     Ok(())
 }
 
@@ -413,57 +473,40 @@ pub fn run_lint(wks: &Workspace, manifests: &[ManifestCollection]) -> Result<()>
     let manifest_path = wks.get_or_create_manifest_dir()?;
 
     for manifest in manifests {
-        let pkg_lint_cmd = Command::new("pkglint")
-            .arg(
-                manifest_path
-                    .join(manifest.get_resolved_name())
-                    .to_string_lossy()
-                    .to_string(),
-            )
-            .stdout(Stdio::inherit())
-            .status()
-            .into_diagnostic()?;
-
-        if pkg_lint_cmd.success() {
-            println!("Lint success for manifest {}", manifest);
-        } else {
-            return Err(miette::miette!("Lint failed for manifest {}", manifest));
-        }
+        let mut pkg_lint_cmd = PackageTool::PkgLint.command();
+        pkg_lint_cmd.arg(
+            manifest_path
+                .join(manifest.get_resolved_name())
+                .to_string_lossy()
+                .to_string(),
+        );
+        let output = run_tool(PackageTool::PkgLint, pkg_lint_cmd, None)?;
+        print!("{}", output.stdout_lossy());
+        eprint!("{}", output.stderr_lossy());
+
+        println!("Lint success for manifest {}", manifest);
     }
     Ok(())
 }
 
-pub fn ensure_repo_with_publisher_exists(publisher: &str) -> Result<()> {
-    let repo_base = Settings::get_or_create_repo_dir().into_diagnostic()?;
+pub fn ensure_repo_with_publisher_exists(repo_base: &Path, publisher: &str) -> Result<()> {
 
     if !repo_base.join("pkg5.repository").exists() {
-        let pkg_repo_status = Command::new("pkgrepo")
+        let mut pkg_repo_cmd = PackageTool::PkgRepo.command();
+        pkg_repo_cmd
             .arg("create")
-            .arg(&repo_base.to_string_lossy().to_string())
-            .stdout(Stdio::inherit())
-            .status()
-            .into_diagnostic()?;
-        if !pkg_repo_status.success() {
-            return Err(miette::miette!(
-                "pkgrepo create failed with non zero exit code"
-            ));
-        }
+            .arg(&repo_base.to_string_lossy().to_string());
+        run_tool(PackageTool::PkgRepo, pkg_repo_cmd, None)?;
     }
 
     if !repo_base.join("publisher").join(publisher).exists() {
-        let pkg_repo_status = Command::new("pkgrepo")
+        let mut pkg_repo_cmd = PackageTool::PkgRepo.command();
+        pkg_repo_cmd
             .arg("add-publisher")
             .arg("-s")
             .arg(&repo_base.to_string_lossy().to_string())
-            .arg(publisher)
-            .stdout(Stdio::inherit())
-            .status()
-            .into_diagnostic()?;
-        if !pkg_repo_status.success() {
-            return Err(miette::miette!(
-                "pkgrepo create failed with non zero exit code"
-            ));
-        }
+            .arg(publisher);
+        run_tool(PackageTool::PkgRepo, pkg_repo_cmd, None)?;
     }
 
     Ok(())
@@ -473,20 +516,21 @@ pub fn publish(
     wks: &Workspace,
     pkg: &Component,
     publisher: &str,
+    repo_path: &Path,
     manifests: &[ManifestCollection],
 ) -> Result<()> {
     let proto_dir = wks.get_or_create_prototype_dir()?;
     let build_dir = wks.get_or_create_build_dir()?;
     let unpack_name = derive_source_name(pkg.recipe.name.clone());
     let unpack_path = build_dir.join(&unpack_name);
-    let repo_path = Settings::get_or_create_repo_dir().into_diagnostic()?;
 
     for manifest in manifests {
         let manifest_path = wks
             .get_or_create_manifest_dir()?
             .join(manifest.get_resolved_name());
 
-        let pkgsend_status = Command::new("pkgsend")
+        let mut pkgsend_cmd = PackageTool::PkgSend.command();
+        pkgsend_cmd
             .arg("publish")
             .arg("-d")
             .arg(&proto_dir.to_string_lossy().to_string())
@@ -496,22 +540,17 @@ pub fn publish(
             .arg(&pkg.get_path())
             .arg("-s")
             .arg(&repo_path.to_string_lossy().to_string())
-            .arg(&manifest_path.to_string_lossy().to_string())
-            .stdout(Stdio::inherit())
-            .status()
-            .into_diagnostic()?;
-
-        if pkgsend_status.success() {
-            println!("Published manifest {}", manifest);
-            println!(
-                "Install with pkg set-publisher {}; pkg install -g {} {}",
-                publisher,
-                repo_path.display(),
-                manifest.get_pkg_name()
-            );
-        } else {
-            return Err(miette::miette!("publish failed for {}", manifest));
-        }
+            .arg(&manifest_path.to_string_lossy().to_string());
+        let output = run_tool(PackageTool::PkgSend, pkgsend_cmd, None)?;
+        print!("{}", output.stdout_lossy());
+
+        println!("Published manifest {}", manifest);
+        println!(
+            "Install with pkg set-publisher {}; pkg install -g {} {}",
+            publisher,
+            repo_path.display(),
+            manifest.get_pkg_name()
+        );
     }
     Ok(())
 }
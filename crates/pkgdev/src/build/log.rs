@@ -0,0 +1,118 @@
+use std::fs::{DirBuilder, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use miette::{IntoDiagnostic, Result};
+use workspace::Workspace;
+
+/// Lines of a failed step printed again, so the error shows without opening the log.
+const TAIL_LINES: usize = 30;
+
+/// The directory in the workspace the build steps write their logs to.
+pub fn get_or_create_log_dir(wks: &Workspace) -> Result<PathBuf> {
+    let log_dir = wks.get_root_path().join("logs");
+    if !log_dir.exists() {
+        DirBuilder::new()
+            .recursive(true)
+            .create(&log_dir)
+            .into_diagnostic()?;
+    }
+    Ok(log_dir)
+}
+
+/// Run a step of the build, writing what it prints on stdout and stderr into a log of
+/// its own while still showing it. The end of the log is printed again if it fails.
+pub fn run_step(wks: &Workspace, step: &str, cmd: &mut Command) -> Result<ExitStatus> {
+    let path = get_or_create_log_dir(wks)?.join(format!(
+        "{}-{}.log",
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        step.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_")
+    ));
+    let log = Arc::new(Mutex::new(File::create(&path).into_diagnostic()?));
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .into_diagnostic()?;
+    let copies = [
+        child
+            .stdout
+            .take()
+            .map(|out| tee(out, std::io::stdout(), log.clone())),
+        child
+            .stderr
+            .take()
+            .map(|err| tee(err, std::io::stderr(), log.clone())),
+    ];
+    let status = child.wait().into_diagnostic()?;
+    for copy in copies.into_iter().flatten() {
+        let _ = copy.join();
+    }
+
+    if !status.success() {
+        let contents = std::fs::read_to_string(&path).into_diagnostic()?;
+        eprintln!("Last lines of the {} log {}:", step, path.display());
+        for line in tail(&contents, TAIL_LINES) {
+            eprintln!("    {}", line);
+        }
+    }
+    Ok(status)
+}
+
+/// Copy the lines of `from` to `to` and the log. Whole lines go to the log at once, so
+/// the lines of stdout and stderr do not mix.
+fn tee<R, W>(from: R, mut to: W, log: Arc<Mutex<File>>) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    std::thread::spawn(move || {
+        for mut line in BufReader::new(from).split(b'\n').map_while(|l| l.ok()) {
+            line.push(b'\n');
+            let _ = to.write_all(&line);
+            if let Ok(mut log) = log.lock() {
+                let _ = log.write_all(&line);
+            }
+        }
+    })
+}
+
+/// The last `lines` lines of `contents`.
+pub(crate) fn tail(contents: &str, lines: usize) -> Vec<&str> {
+    let all = contents.lines().collect::<Vec<_>>();
+    all[all.len().saturating_sub(lines)..].to_vec()
+}
+
+/// Pack the logs of the build steps into [`forge::BUILD_LOGS_FILE`] in the workspace
+/// root, to attach them to the report of a job.
+pub fn bundle_logs(wks: &Workspace) -> Result<PathBuf> {
+    let log_dir = get_or_create_log_dir(wks)?;
+    let bundle_path = wks.get_root_path().join(forge::BUILD_LOGS_FILE);
+    let bundle = File::create(&bundle_path).into_diagnostic()?;
+    let mut archive = tar::Builder::new(GzEncoder::new(bundle, Compression::default()));
+    archive.append_dir_all("logs", &log_dir).into_diagnostic()?;
+    archive
+        .into_inner()
+        .and_then(|gz| gz.finish())
+        .into_diagnostic()?;
+    Ok(bundle_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_keeps_the_last_lines() {
+        assert_eq!(tail("a\nb\nc\n", 2), ["b", "c"]);
+        assert_eq!(tail("a\n", 5), ["a"]);
+        assert!(tail("", 5).is_empty());
+    }
+}
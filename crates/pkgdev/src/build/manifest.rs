@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component as PathComponent, Path};
+
+use miette::{IntoDiagnostic, Result};
+use sha2::{Digest, Sha256};
+
+use crate::build::normalize::walk;
+
+/// Attribute the SHA-256 of the content of a file is recorded in.
+pub const CONTENT_HASH_ATTRIBUTE: &str = "forge.content-hash";
+
+/// Owner and group pkgsend generate gives every file and directory. Transforms of the
+/// recipe or gate change them where needed.
+const DEFAULT_OWNER: &str = "root";
+const DEFAULT_GROUP: &str = "bin";
+
+/// A file system action of an IPS manifest as pkgsend generate would emit it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestAction {
+    Dir {
+        path: String,
+        mode: u32,
+    },
+    File {
+        path: String,
+        mode: u32,
+        hash: String,
+    },
+    Link {
+        path: String,
+        target: String,
+    },
+    Hardlink {
+        path: String,
+        target: String,
+    },
+}
+
+impl ManifestAction {
+    pub fn path(&self) -> &str {
+        match self {
+            ManifestAction::Dir { path, .. }
+            | ManifestAction::File { path, .. }
+            | ManifestAction::Link { path, .. }
+            | ManifestAction::Hardlink { path, .. } => path,
+        }
+    }
+}
+
+/// Rendered the way pkgfmt formats actions. Files have no payload reference, pkgsend
+/// publish then looks the payload up by the path.
+impl Display for ManifestAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestAction::Dir { path, mode } => write!(
+                f,
+                "dir  path={} owner={} group={} mode={:04o}",
+                quote(path),
+                DEFAULT_OWNER,
+                DEFAULT_GROUP,
+                mode
+            ),
+            ManifestAction::File { path, mode, hash } => write!(
+                f,
+                "file path={} owner={} group={} mode={:04o} {}=sha256:{}",
+                quote(path),
+                DEFAULT_OWNER,
+                DEFAULT_GROUP,
+                mode,
+                CONTENT_HASH_ATTRIBUTE,
+                hash
+            ),
+            ManifestAction::Link { path, target } => {
+                write!(f, "link path={} target={}", quote(path), quote(target))
+            }
+            ManifestAction::Hardlink { path, target } => {
+                write!(f, "hardlink path={} target={}", quote(path), quote(target))
+            }
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains(char::is_whitespace) || value.contains('"') {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The actions delivering everything in the prototype directory, sorted by path.
+///
+/// Files sharing an inode are delivered once as file, the other names become hardlinks
+/// to it.
+pub fn generate_actions(proto_dir: &Path) -> Result<Vec<ManifestAction>> {
+    let mut entries = walk(proto_dir)?;
+    entries.sort();
+
+    let mut actions = vec![];
+    let mut inodes: HashMap<(u64, u64), String> = HashMap::new();
+    for entry in entries {
+        let metadata = fs::symlink_metadata(&entry).into_diagnostic()?;
+        let path = entry
+            .strip_prefix(proto_dir)
+            .into_diagnostic()?
+            .to_string_lossy()
+            .to_string();
+        let mode = metadata.permissions().mode() & 0o7777;
+
+        let action = if metadata.file_type().is_symlink() {
+            ManifestAction::Link {
+                path,
+                target: fs::read_link(&entry)
+                    .into_diagnostic()?
+                    .to_string_lossy()
+                    .to_string(),
+            }
+        } else if metadata.is_dir() {
+            ManifestAction::Dir { path, mode }
+        } else if let Some(first) = (metadata.nlink() > 1)
+            .then(|| inodes.get(&(metadata.dev(), metadata.ino())))
+            .flatten()
+        {
+            ManifestAction::Hardlink {
+                target: relative_target(&path, first),
+                path,
+            }
+        } else {
+            if metadata.nlink() > 1 {
+                inodes.insert((metadata.dev(), metadata.ino()), path.clone());
+            }
+            ManifestAction::File {
+                hash: hash_file(&entry)?,
+                path,
+                mode,
+            }
+        };
+        actions.push(action);
+    }
+    Ok(actions)
+}
+
+/// The manifest of `actions` with one action per line.
+pub fn render_manifest(actions: &[ManifestAction]) -> String {
+    actions
+        .iter()
+        .map(|a| a.to_string() + "\n")
+        .collect::<String>()
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).into_diagnostic()?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).into_diagnostic()?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hardlink targets are relative to the directory of the hardlink.
+/// usr/bin/b -> usr/lib/a gives ../lib/a
+fn relative_target(path: &str, target: &str) -> String {
+    let parent = Path::new(path).parent().unwrap_or(Path::new(""));
+    let from = parent.components().collect::<Vec<_>>();
+    let to = Path::new(target).components().collect::<Vec<_>>();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = vec![String::from(".."); from.len() - common];
+    relative.extend(to[common..].iter().filter_map(|c| match c {
+        PathComponent::Normal(s) => Some(s.to_string_lossy().to_string()),
+        _ => None,
+    }));
+    relative.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn relative_targets() {
+        assert_eq!(relative_target("usr/bin/b", "usr/bin/a"), "a");
+        assert_eq!(relative_target("usr/bin/b", "usr/lib/a"), "../lib/a");
+        assert_eq!(relative_target("b", "usr/lib/a"), "usr/lib/a");
+        assert_eq!(relative_target("usr/share/man/b", "a"), "../../../a");
+    }
+
+    #[test]
+    fn renders_like_pkgfmt() {
+        let actions = vec![
+            ManifestAction::Dir {
+                path: String::from("usr/bin"),
+                mode: 0o755,
+            },
+            ManifestAction::File {
+                path: String::from("usr/bin/with space"),
+                mode: 0o555,
+                hash: String::from("abc"),
+            },
+            ManifestAction::Link {
+                path: String::from("usr/bin/l"),
+                target: String::from("with space"),
+            },
+        ];
+        assert_eq!(
+            render_manifest(&actions),
+            "dir  path=usr/bin owner=root group=bin mode=0755\n\
+             file path=\"usr/bin/with space\" owner=root group=bin mode=0555 forge.content-hash=sha256:abc\n\
+             link path=usr/bin/l target=\"with space\"\n"
+        );
+    }
+
+    #[test]
+    fn generates_actions_from_prototype_dir() {
+        let proto_dir =
+            std::env::temp_dir().join(format!("pkgdev-manifest-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(proto_dir.join("usr/bin")).unwrap();
+        fs::write(proto_dir.join("usr/bin/a"), "hello\n").unwrap();
+        fs::set_permissions(
+            proto_dir.join("usr/bin/a"),
+            fs::Permissions::from_mode(0o555),
+        )
+        .unwrap();
+        fs::hard_link(proto_dir.join("usr/bin/a"), proto_dir.join("usr/bin/b")).unwrap();
+        symlink("a", proto_dir.join("usr/bin/c")).unwrap();
+
+        let actions = generate_actions(&proto_dir).unwrap();
+        fs::remove_dir_all(&proto_dir).unwrap();
+
+        assert_eq!(
+            actions.iter().map(|a| a.path()).collect::<Vec<_>>(),
+            vec!["usr", "usr/bin", "usr/bin/a", "usr/bin/b", "usr/bin/c"]
+        );
+        assert_eq!(
+            actions[2],
+            ManifestAction::File {
+                path: String::from("usr/bin/a"),
+                mode: 0o555,
+                hash: String::from(
+                    "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+                ),
+            }
+        );
+        assert_eq!(
+            actions[3],
+            ManifestAction::Hardlink {
+                path: String::from("usr/bin/b"),
+                target: String::from("a"),
+            }
+        );
+        assert_eq!(
+            actions[4],
+            ManifestAction::Link {
+                path: String::from("usr/bin/c"),
+                target: String::from("a"),
+            }
+        );
+    }
+}
@@ -1,14 +1,34 @@
+mod apk;
+mod artifacts;
 mod automake;
+mod buildsystem;
+mod checks;
 mod compile;
+mod configure_cache;
+mod deb;
+mod depot;
 mod dependencies;
+mod diff;
+mod elf;
 mod install;
 mod ips;
+mod log;
+mod manifest;
+mod network;
+mod normalize;
+mod promote;
+mod repro;
+mod resources;
+mod rpm;
+mod sandbox;
 mod script;
+mod sign;
 mod tarball;
+mod tool;
 mod util;
 
 use clap::{Parser, ValueEnum};
-use workspace::Workspace;
+use workspace::{Architecture, Workspace};
 
 #[derive(Debug, Clone, ValueEnum, PartialEq, Eq, PartialOrd, Ord)]
 pub enum BuildSteps {
@@ -17,7 +37,7 @@ pub enum BuildSteps {
     Build,
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 pub struct BuildArgs {
     #[arg(long = "step", short)]
     stop_on_step: Option<BuildSteps>,
@@ -30,30 +50,141 @@ pub struct BuildArgs {
 
     #[arg(short = 'I', long = "include")]
     transform_include_dir: Option<PathBuf>,
+
+    /// Architecture to build for (i386, amd64, aarch64, sparc)
+    #[arg(long)]
+    pub arch: Option<Architecture>,
+
+    /// Where to run the build (host, chroot, zone). Defaults to the sandbox of the gate
+    #[arg(long)]
+    sandbox: Option<SandboxType>,
+
+    /// The chroot directory or zone name of the sandbox
+    #[arg(long)]
+    sandbox_target: Option<String>,
+
+    /// Whether the build phase may use the network (allow, audit, deny). Defaults to the
+    /// network policy of the gate sandbox
+    #[arg(long)]
+    network: Option<NetworkPolicy>,
+
+    /// Publish into the staging repository with this name instead of the public
+    /// repository. Staged packages are made public with `pkgdev promote`
+    #[arg(long)]
+    staging: Option<String>,
+
+    /// Build the reduced variant of the bootstrap section of the recipe, used to break
+    /// build dependency cycles
+    #[arg(long, default_value = "false")]
+    bootstrap: bool,
+
+    /// Refuse to build when the recipe or the patches differ from the version recorded
+    /// in the selected forge. Needs a gate with an id
+    #[arg(long, default_value = "false")]
+    verify_origin: bool,
+
+    /// Build with the compiler and linker flags of this profile of the gate, like debug,
+    /// release or hardened. The profile is recorded in the package metadata
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Jobs make and ninja run at once. Defaults to the jobs of the recipe, the gate or
+    /// the settings, the number of CPUs without any
+    #[arg(long, short)]
+    jobs: Option<u32>,
+
+    /// Address space of every build process in bytes at most
+    #[arg(long)]
+    memory_limit: Option<u64>,
+
+    /// CPU time of every build process in seconds at most
+    #[arg(long)]
+    cpu_time_limit: Option<u64>,
+
+    /// Pack the logs of the build steps into logs.tar.gz in the workspace, also when the
+    /// build fails, to attach them to the report of a job
+    #[arg(long, default_value = "false")]
+    bundle_logs: bool,
 }
 
 use std::path::PathBuf;
 
 use crate::build::dependencies::ensure_packages_are_installed;
-use crate::sources::{download_sources, unpack};
+use crate::forge::verify_component_origin;
+use crate::sources::{download_sources, unpack, SourceTree};
 use automake::build_using_automake;
-use component::Component;
-use component::SourceSection;
+use buildsystem::{build_using_cmake, build_using_meson, GeneratedBuild};
+use component::{BuildFlagNode, Component, SourceSection};
 use config::Settings;
-use gate::Gate;
+use forge::provenance::ARTIFACTS_REPORT_FILE;
+use gate::{BuildProfile, Gate, NetworkPolicy, SandboxType};
 use miette::{IntoDiagnostic, Result, WrapErr};
+use network::NetworkMonitor;
+use resources::Resources;
+use sandbox::Sandbox;
 use script::build_using_scripts;
 
-pub fn build_package_sources(wks: &Workspace, pkg: &Component, settings: &Settings) -> Result<()> {
+pub use depot::DepotError;
+pub use diff::{diff_proto, DiffProtoArgs};
+pub use elf::{report_elf_dependencies, ElfError};
+pub use manifest::{generate_actions, ManifestAction};
+pub use promote::promote_staged_packages;
+pub use repro::verify_reproducibility;
+pub use tool::ToolError;
+
+pub fn build_package_sources(
+    wks: &Workspace,
+    pkg: &Component,
+    gate: &Option<Gate>,
+    settings: &Settings,
+    sandbox: &Sandbox,
+    profile: Option<&BuildProfile>,
+    jobs: u32,
+) -> Result<()> {
+    let arch_flags = gate
+        .as_ref()
+        .map(|g| g.arch_flags(&wks.get_arch().to_string()))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|f| BuildFlagNode {
+            flag: f.flag,
+            flag_name: f.flag_name,
+        })
+        .collect::<Vec<_>>();
+
     for section in pkg.recipe.build_sections.iter() {
-        if let Some(c) = section.configure.clone() {
-            build_using_automake(wks, pkg, &c, settings)?;
-        } else if let Some(_) = section.cmake {
-            unimplemented!();
-        } else if let Some(_) = section.meson {
-            unimplemented!();
+        let tree = SourceTree::resolve(wks, pkg, section)?;
+        if let Some(mut c) = section.configure.clone() {
+            // The flags of the gate go first so the recipe can add to them
+            c.flags.splice(0..0, arch_flags.iter().cloned());
+            if let Some(profile) = profile {
+                profile.apply(&mut c);
+            }
+            build_using_automake(wks, pkg, &tree, &c, settings, sandbox, jobs)?;
+        } else if let Some(options) = &section.cmake {
+            build_using_cmake(&GeneratedBuild {
+                wks,
+                pkg,
+                tree: &tree,
+                options,
+                flags: &arch_flags,
+                settings,
+                sandbox,
+                jobs,
+            })?;
+        } else if let Some(options) = &section.meson {
+            build_using_meson(&GeneratedBuild {
+                wks,
+                pkg,
+                tree: &tree,
+                options,
+                flags: &arch_flags,
+                settings,
+                sandbox,
+                jobs,
+            })?;
         } else if let Some(script) = section.script.clone() {
-            build_using_scripts(wks, pkg, &script, settings)?;
+            build_using_scripts(wks, pkg, &tree, &script, settings, sandbox)?;
         }
     }
 
@@ -67,6 +198,29 @@ pub async fn run_build(
     settings: &Settings,
     args: &BuildArgs,
 ) -> Result<()> {
+    let built = build_component(component, gate, wks, settings, args).await;
+    if args.bundle_logs {
+        let bundle = log::bundle_logs(wks).wrap_err("could not bundle the build logs")?;
+        println!("Build logs bundled into {}", bundle.display());
+    }
+    built
+}
+
+async fn build_component(
+    component: &Component,
+    gate: &Option<Gate>,
+    wks: &Workspace,
+    settings: &Settings,
+    args: &BuildArgs,
+) -> Result<()> {
+    if args.verify_origin {
+        let gate = gate
+            .as_ref()
+            .ok_or(miette::miette!("--verify-origin needs a gate"))?;
+        verify_component_origin(component, gate).await?;
+        println!("{} matches the recipe recorded in the forge", component.get_name());
+    }
+
     let transform_include_dir =
         args.transform_include_dir
             .clone()
@@ -82,6 +236,52 @@ pub async fn run_build(
                 }
             });
 
+    let component = &if args.bootstrap {
+        let mut bootstrapped = component.clone();
+        bootstrapped.recipe = component.recipe.bootstrap_variant().ok_or(miette::miette!(
+            "{} has no bootstrap section to build",
+            component.get_name()
+        ))?;
+        println!("Building the bootstrap variant of {}", component.get_name());
+        bootstrapped
+    } else {
+        component.clone()
+    };
+
+    let profile = match &args.profile {
+        Some(name) => Some(
+            gate.as_ref()
+                .ok_or(miette::miette!("--profile needs a gate defining the profile"))?
+                .profile(name)?,
+        ),
+        None => None,
+    };
+    if let Some(profile) = profile {
+        println!("Building {} with profile {}", component.get_name(), profile.name);
+    }
+    let profile_name = profile.map(|p| p.name.as_str());
+
+    let arch = wks.get_arch().to_string();
+    if !component.supports_arch(&arch) {
+        return Err(miette::miette!(
+            "{} cannot be built for {}, supported architectures are {}",
+            component.get_name(),
+            arch,
+            component.recipe.architectures.join(", ")
+        ));
+    }
+
+    let resources = Resources::resolve(args, &component.recipe, gate, settings);
+    let sandbox = Sandbox::new(args.sandbox.clone(), args.sandbox_target.clone(), gate)?
+        .with_env(resources.env())
+        .with_limits(resources.memory_limit, resources.cpu_time_limit);
+    println!(
+        "Building {} in sandbox {} with {} jobs",
+        component.get_name(),
+        sandbox,
+        resources.jobs
+    );
+
     if !args.no_clean {
         std::fs::remove_dir_all(wks.get_or_create_download_dir()?)
             .into_diagnostic()
@@ -95,13 +295,16 @@ pub async fn run_build(
         std::fs::remove_dir_all(wks.get_or_create_manifest_dir()?)
             .into_diagnostic()
             .wrap_err("could not clean the manifest directory")?;
+        std::fs::remove_dir_all(log::get_or_create_log_dir(wks)?)
+            .into_diagnostic()
+            .wrap_err("could not clean the log directory")?;
     }
 
     ensure_packages_are_installed(wks, false, &component)?;
 
     let sources: Vec<SourceSection> = component.recipe.sources.clone();
 
-    download_sources(component, wks, args.archive_clean)
+    download_sources(component, wks, args.archive_clean, settings)
         .await
         .wrap_err("download and verify failed")?;
 
@@ -119,7 +322,37 @@ pub async fn run_build(
         }
     }
 
-    build_package_sources(&wks, &component, &settings).wrap_err("configure step failed")?;
+    let network_policy = args
+        .network
+        .clone()
+        .or(gate.as_ref().and_then(|g| g.sandbox.as_ref()).map(|s| s.network.clone()))
+        .unwrap_or_default();
+    // A report of an earlier build in the same workspace must not be picked up
+    let _ = std::fs::remove_file(wks.get_root_path().join(forge::NETWORK_REPORT_FILE));
+    let network_monitor = NetworkMonitor::start(network_policy.clone())?;
+    let build_sandbox = match (&network_policy, &network_monitor) {
+        (NetworkPolicy::Deny, _) => sandbox.without_network()?,
+        (_, Some(monitor)) => sandbox.with_env(monitor.env()),
+        (_, None) => sandbox.clone(),
+    };
+    let built = build_package_sources(
+        &wks,
+        &component,
+        gate,
+        &settings,
+        &build_sandbox,
+        profile,
+        resources.jobs,
+    );
+    if let Some(monitor) = network_monitor {
+        monitor.finish(&wks)?;
+    }
+    built.wrap_err("configure step failed")?;
+
+    normalize::normalize_prototype_dir(&wks, normalize::source_date_epoch(&component))
+        .wrap_err("normalizing the prototype directory failed")?;
+
+    checks::run_checks(&wks, &component, &settings, &sandbox).wrap_err("checks failed")?;
 
     if let Some(stop_on_step) = &args.stop_on_step {
         if stop_on_step == &BuildSteps::Build {
@@ -136,42 +369,124 @@ pub async fn run_build(
         .distribution_type
         .clone();
 
+    if args.staging.is_some() && distribution_type != gate::DistributionType::IPS {
+        return Err(miette::miette!(
+            "only IPS packages can be published to a staging repository"
+        ));
+    }
+
+    // Artifacts of an earlier build in the same workspace must not be attested
+    let _ = std::fs::remove_file(wks.get_root_path().join(ARTIFACTS_REPORT_FILE));
     match distribution_type {
         gate::DistributionType::Tarbball => {
-            tarball::make_release_tarball(&wks, &component)?;
+            let tarball = tarball::make_release_tarball(&wks, &component)?;
+            sign::sign_file(settings, &tarball).wrap_err("signing the tarball failed")?;
+            artifacts::record_artifacts(&wks, &[artifact_of_file(tarball)])?;
         }
         gate::DistributionType::IPS => {
-            run_ips_actions(&wks, &component, gate, transform_include_dir)?;
+            run_ips_actions(
+                &wks,
+                &component,
+                gate,
+                settings,
+                transform_include_dir,
+                args.staging.as_deref(),
+                profile_name,
+            )
+            .await?;
+        }
+        gate::DistributionType::Deb => {
+            let deb = deb::make_deb(&wks, &component, gate, profile_name)?;
+            artifacts::record_artifacts(&wks, &[artifact_of_file(deb)])?;
+        }
+        gate::DistributionType::Rpm => {
+            let rpm = rpm::make_rpm(&wks, &component, profile_name)?;
+            artifacts::record_artifacts(&wks, &[artifact_of_file(rpm)])?;
+        }
+        gate::DistributionType::Apk => {
+            let apk = apk::make_apk(&wks, &component, gate, profile_name)?;
+            artifacts::record_artifacts(&wks, &[artifact_of_file(apk)])?;
         }
     }
 
     Ok(())
 }
 
-fn run_ips_actions(
+/// Package archives are attested under their file name.
+fn artifact_of_file(path: PathBuf) -> (String, PathBuf) {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    (name, path)
+}
+
+async fn run_ips_actions(
     wks: &Workspace,
     pkg: &Component,
     gate: &Option<Gate>,
+    settings: &Settings,
     transform_include_dir: Option<PathBuf>,
+    staging: Option<&str>,
+    profile: Option<&str>,
 ) -> Result<()> {
     ips::run_generate_filelist(wks, pkg).wrap_err("generating file list failed")?;
 
-    let manifests = ips::generate_manifest_files(wks, pkg, gate, transform_include_dir)
+    let manifests = ips::generate_manifest_files(wks, pkg, gate, transform_include_dir, profile)
         .wrap_err("mogrify failed")?;
 
-    ips::run_generate_pkgdepend(wks, manifests.as_slice())
-        .wrap_err("failed to generate dependency entries")?;
+    if let Some(skip) = pkg.recipe.skips("dependency-generation") {
+        println!("Warning: skipping dependency generation ({})", skip);
+        ips::skip_generate_pkgdepend(wks, manifests.as_slice())?;
+    } else {
+        elf::check_prototype_dir(wks).wrap_err("checking ELF dependencies failed")?;
+        ips::run_generate_pkgdepend(wks, manifests.as_slice())
+            .wrap_err("failed to generate dependency entries")?;
+    }
 
-    ips::run_resolve_dependencies(wks, manifests.as_slice())
-        .wrap_err("failed to resolve dependencies")?;
+    if let Some(skip) = pkg.recipe.skips("dependency-resolution") {
+        println!("Warning: skipping dependency resolution ({})", skip);
+        ips::skip_resolve_dependencies(wks, manifests.as_slice())?;
+    } else {
+        ips::run_resolve_dependencies(wks, manifests.as_slice())
+            .wrap_err("failed to resolve dependencies")?;
+    }
 
-    ips::run_lint(wks, manifests.as_slice()).wrap_err("lint failed")?;
+    if let Some(skip) = pkg.recipe.skips("lint") {
+        println!("Warning: skipping lint ({})", skip);
+    } else {
+        ips::run_lint(wks, manifests.as_slice()).wrap_err("lint failed")?;
+    }
+
+    // The resolved manifests are what gets published, the payloads they reference by hash
+    let manifest_dir = wks.get_or_create_manifest_dir()?;
+    let published = manifests
+        .iter()
+        .map(|m| (m.get_pkg_name(), manifest_dir.join(m.get_resolved_name())))
+        .collect::<Vec<_>>();
+    artifacts::record_artifacts(wks, &published)?;
 
     let publisher = gate.clone().unwrap_or_default().publisher;
-    ips::ensure_repo_with_publisher_exists(&publisher)
+
+    // Staged packages are only promoted into the remote repository later on
+    if let (None, Some(remote)) = (staging, &settings.remote_repository) {
+        depot::publish_remote(wks, pkg, &publisher, remote, manifests.as_slice())
+            .await
+            .wrap_err("package publish failed")?;
+        return Ok(());
+    }
+
+    let repo_path = match staging {
+        Some(name) => settings.get_or_create_staging_repo_dir(name),
+        None => settings.get_or_create_repo_dir(),
+    }
+    .into_diagnostic()?;
+
+    ips::ensure_repo_with_publisher_exists(&repo_path, &publisher)
         .wrap_err("failed to ensure repository exists")?;
 
-    ips::publish(wks, pkg, &publisher, manifests.as_slice()).wrap_err("package publish failed")?;
+    ips::publish(wks, pkg, &publisher, &repo_path, manifests.as_slice())
+        .wrap_err("package publish failed")?;
 
     Ok(())
 }
@@ -0,0 +1,187 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use forge::NETWORK_REPORT_FILE;
+use gate::NetworkPolicy;
+use miette::{IntoDiagnostic, Result, WrapErr};
+use workspace::Workspace;
+
+/// A proxy on the loopback interface the build phase is pointed at through the usual
+/// proxy environment variables. It records the host of every request and forwards it.
+/// Tools that ignore the proxy variables are not seen, so the audit covers the
+/// downloads of build systems and language package managers, not a determined build
+/// script. Denying the network is up to the sandbox.
+pub struct NetworkMonitor {
+    proxy_url: String,
+    hosts: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl NetworkMonitor {
+    /// Start monitoring for the policy, only `audit` needs a monitor.
+    pub fn start(policy: NetworkPolicy) -> Result<Option<Self>> {
+        if policy != NetworkPolicy::Audit {
+            return Ok(None);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .into_diagnostic()
+            .wrap_err("could not start the network monitor")?;
+        let proxy_url = format!("http://{}", listener.local_addr().into_diagnostic()?);
+        let hosts = Arc::new(Mutex::new(BTreeSet::new()));
+
+        let accepted_hosts = hosts.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let hosts = accepted_hosts.clone();
+                thread::spawn(move || handle_connection(stream, hosts));
+            }
+        });
+
+        Ok(Some(Self { proxy_url, hosts }))
+    }
+
+    /// The environment pointing the build commands at the monitor.
+    pub fn env(&self) -> Vec<(String, String)> {
+        [
+            "http_proxy",
+            "https_proxy",
+            "ftp_proxy",
+            "all_proxy",
+            "HTTP_PROXY",
+            "HTTPS_PROXY",
+            "FTP_PROXY",
+            "ALL_PROXY",
+        ]
+        .iter()
+        .map(|key| (key.to_string(), self.proxy_url.clone()))
+        .chain([
+            (String::from("no_proxy"), String::new()),
+            (String::from("NO_PROXY"), String::new()),
+        ])
+        .collect()
+    }
+
+    /// Write the hosts the build reached to the workspace root for the worker to report.
+    pub fn finish(self, wks: &Workspace) -> Result<()> {
+        let hosts = self
+            .hosts
+            .lock()
+            .map(|hosts| hosts.iter().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let report_path = wks.get_root_path().join(NETWORK_REPORT_FILE);
+        let report = serde_json::to_string_pretty(&hosts).into_diagnostic()?;
+        std::fs::write(&report_path, report)
+            .into_diagnostic()
+            .wrap_err(format!("could not write {}", report_path.display()))?;
+
+        if hosts.is_empty() {
+            return Ok(());
+        }
+
+        println!(
+            "Warning: the build reached {} instead of using declared sources",
+            hosts.join(", ")
+        );
+        Ok(())
+    }
+}
+
+fn handle_connection(stream: TcpStream, hosts: Arc<Mutex<BTreeSet<String>>>) {
+    let mut reader = BufReader::new(stream);
+    let mut head = Vec::new();
+    loop {
+        let mut line = Vec::new();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        head.extend_from_slice(&line);
+        if line == b"\r\n" || line == b"\n" || head.len() > 64 * 1024 {
+            break;
+        }
+    }
+
+    let request_line = String::from_utf8_lossy(&head)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let mut words = request_line.split_whitespace();
+    let method = words.next().unwrap_or_default().to_string();
+    let Some((host, port)) = words
+        .next()
+        .and_then(|target| request_target(&method, target))
+    else {
+        return;
+    };
+
+    if let Ok(mut hosts) = hosts.lock() {
+        hosts.insert(host.clone());
+    }
+
+    let client = reader.get_ref().try_clone();
+    let (Ok(mut client), Ok(mut upstream)) = (client, TcpStream::connect((host.as_str(), port)))
+    else {
+        return;
+    };
+    let written = if method.eq_ignore_ascii_case("CONNECT") {
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+    } else {
+        upstream.write_all(&head)
+    }
+    .and_then(|_| upstream.write_all(reader.buffer()));
+    if written.is_err() {
+        return;
+    }
+
+    let (Ok(mut upstream_reader), Ok(mut client_writer)) =
+        (upstream.try_clone(), client.try_clone())
+    else {
+        return;
+    };
+    let downstream = thread::spawn(move || {
+        let _ = std::io::copy(&mut upstream_reader, &mut client_writer);
+        let _ = client_writer.shutdown(Shutdown::Write);
+    });
+    let mut client_reader = reader.into_inner();
+    let _ = std::io::copy(&mut client_reader, &mut upstream);
+    let _ = upstream.shutdown(Shutdown::Write);
+    let _ = downstream.join();
+}
+
+/// The host and port of a proxy request, `host:port` for `CONNECT` and an absolute URL
+/// otherwise.
+fn request_target(method: &str, target: &str) -> Option<(String, u16)> {
+    if method.eq_ignore_ascii_case("CONNECT") {
+        let (host, port) = target.rsplit_once(':')?;
+        Some((
+            host.trim_matches(['[', ']']).to_string(),
+            port.parse().ok()?,
+        ))
+    } else {
+        let url = url::Url::parse(target).ok()?;
+        Some((url.host_str()?.to_string(), url.port_or_known_default()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_targets() {
+        assert_eq!(
+            request_target("CONNECT", "crates.io:443"),
+            Some((String::from("crates.io"), 443))
+        );
+        assert_eq!(
+            request_target("GET", "http://example.org/file.tar.gz"),
+            Some((String::from("example.org"), 80))
+        );
+        assert_eq!(request_target("GET", "/relative"), None);
+    }
+}
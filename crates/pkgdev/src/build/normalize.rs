@@ -0,0 +1,175 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use component::Component;
+use miette::{IntoDiagnostic, Result};
+use workspace::Workspace;
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_HEADER_LEN: usize = 60;
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// The timestamp builds should embed instead of the current time. Taken from the
+/// environment if set, otherwise from the last commit touching the component and as
+/// a last resort from the modification time of its recipe.
+pub fn source_date_epoch(pkg: &Component) -> i64 {
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+    {
+        return epoch;
+    }
+
+    let git_epoch = Command::new("git")
+        .arg("-C")
+        .arg(pkg.get_path())
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%ct")
+        .arg("--")
+        .arg(".")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| {
+            String::from_utf8_lossy(&out.stdout)
+                .trim()
+                .parse::<i64>()
+                .ok()
+        });
+    if let Some(epoch) = git_epoch {
+        return epoch;
+    }
+
+    fs::metadata(pkg.get_path().join("package.kdl"))
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Make the prototype directory independent of when it was built: strip the
+/// timestamps from ar and gzip archives and set all modification times to `epoch`.
+pub fn normalize_prototype_dir(wks: &Workspace, epoch: i64) -> Result<()> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let mtime = UNIX_EPOCH + Duration::from_secs(epoch.max(0) as u64);
+
+    let mut stripped = 0;
+    let entries = walk(&proto_dir)?;
+    for path in entries.iter() {
+        // A symlink may point at a file outside of the prototype directory
+        let is_file = fs::symlink_metadata(path)
+            .into_diagnostic()?
+            .file_type()
+            .is_file();
+        if is_file && strip_archive_timestamps(path)? {
+            stripped += 1;
+        }
+    }
+
+    // Children first so setting a time does not get undone by touching the parent
+    for path in entries.iter().rev().chain(std::iter::once(&proto_dir)) {
+        set_mtime(path, mtime)?;
+    }
+
+    println!(
+        "Normalized {} files in the prototype directory to SOURCE_DATE_EPOCH={} ({} archives stripped)",
+        entries.len(),
+        epoch,
+        stripped
+    );
+    Ok(())
+}
+
+/// All entries below `dir` without following symlinks. Parents come before children.
+pub fn walk(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut children = fs::read_dir(&current)
+            .into_diagnostic()?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .into_diagnostic()?;
+        children.sort();
+        for child in children {
+            let file_type = fs::symlink_metadata(&child).into_diagnostic()?.file_type();
+            if file_type.is_dir() {
+                pending.push(child.clone());
+            }
+            entries.push(child);
+        }
+    }
+    Ok(entries)
+}
+
+fn set_mtime(path: &Path, mtime: SystemTime) -> Result<()> {
+    // Symlinks cannot be touched without following them
+    if fs::symlink_metadata(path)
+        .into_diagnostic()?
+        .file_type()
+        .is_symlink()
+    {
+        return Ok(());
+    }
+    File::open(path)
+        .and_then(|f| f.set_modified(mtime))
+        .into_diagnostic()
+}
+
+/// Returns whether the file was an archive that had to be rewritten.
+fn strip_archive_timestamps(path: &Path) -> Result<bool> {
+    let mut contents = fs::read(path).into_diagnostic()?;
+    let changed = if contents.starts_with(AR_MAGIC) {
+        strip_ar_timestamps(&mut contents)
+    } else if contents.starts_with(GZIP_MAGIC) && contents.len() >= 10 {
+        strip_gzip_timestamp(&mut contents)
+    } else {
+        false
+    };
+
+    if changed {
+        let permissions = fs::metadata(path).into_diagnostic()?.permissions();
+        fs::write(path, &contents).into_diagnostic()?;
+        fs::set_permissions(path, permissions).into_diagnostic()?;
+    }
+    Ok(changed)
+}
+
+/// Zero the modification time, uid and gid of every member like `ar -D` does.
+fn strip_ar_timestamps(contents: &mut [u8]) -> bool {
+    let mut changed = false;
+    let mut pos = AR_MAGIC.len();
+    while pos + AR_HEADER_LEN <= contents.len() {
+        let header = &mut contents[pos..pos + AR_HEADER_LEN];
+        let Some(size) = std::str::from_utf8(&header[48..58])
+            .ok()
+            .and_then(|s| s.trim().parse::<usize>().ok())
+        else {
+            break;
+        };
+
+        for (start, len) in [(16, 12), (28, 6), (34, 6)] {
+            let field = format!("{:<width$}", 0, width = len);
+            if &header[start..start + len] != field.as_bytes() {
+                header[start..start + len].copy_from_slice(field.as_bytes());
+                changed = true;
+            }
+        }
+
+        // Members are aligned to even offsets
+        pos += AR_HEADER_LEN + size + size % 2;
+    }
+    changed
+}
+
+fn strip_gzip_timestamp(contents: &mut [u8]) -> bool {
+    if contents[4..8] == [0, 0, 0, 0] {
+        return false;
+    }
+    contents[4..8].copy_from_slice(&[0, 0, 0, 0]);
+    true
+}
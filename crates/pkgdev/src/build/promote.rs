@@ -0,0 +1,277 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::os::unix::io::AsRawFd;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Utc;
+use config::Settings;
+use miette::{IntoDiagnostic, Result, WrapErr};
+
+use crate::build::ips;
+use crate::build::normalize::walk;
+use crate::build::tool::{run_tool, PackageTool};
+
+/// Move the packages of a staging repository into the public repository.
+///
+/// The staging repository is verified and its packages are received into a copy of the
+/// public repository, which then replaces the public repository by swapping a symlink.
+/// Clients see either the old or the new repository, and a broken or conflicting
+/// package leaves the public repository untouched. Workers sharing the repository
+/// directory promote one after another. With a snapshot name the newest packages of
+/// the public repository are copied into a new snapshot repository afterwards. Returns
+/// the promoted FMRIs.
+pub fn promote_staged_packages(
+    settings: &Settings,
+    name: &str,
+    keep: bool,
+    snapshot: Option<&str>,
+) -> Result<Vec<String>> {
+    let staging = settings.get_staging_repo_dir(name).into_diagnostic()?;
+    if !staging.join("pkg5.repository").exists() {
+        return Err(miette::miette!(
+            "no staging repository {} found at {}",
+            name,
+            staging.display()
+        ));
+    }
+
+    let mut verify_cmd = PackageTool::PkgRepo.command();
+    verify_cmd.arg("verify").arg("-s").arg(&staging);
+    run_tool(PackageTool::PkgRepo, verify_cmd, None)
+        .wrap_err(format!("staging repository {} is damaged", name))?;
+
+    let fmris = staged_fmris(&staging)?;
+    if fmris.is_empty() {
+        return Err(miette::miette!("staging repository {} is empty", name));
+    }
+
+    let repo_link = settings.get_repo_dir().into_diagnostic()?;
+    let _lock = PromotionLock::acquire(&repo_link)?;
+    let current = fs::canonicalize(&repo_link).ok();
+    let candidate = versioned(&repo_link)?;
+
+    let receive = || -> Result<()> {
+        if let Some(current) = &current {
+            copy_repository(current, &candidate)?;
+        }
+        for publisher in fmris.iter().filter_map(|f| fmri_publisher(f)) {
+            ips::ensure_repo_with_publisher_exists(&candidate, publisher)
+                .wrap_err("failed to ensure repository exists")?;
+        }
+
+        let mut cmd = PackageTool::PkgRecv.command();
+        cmd.arg("-s")
+            .arg(&staging)
+            .arg("-d")
+            .arg(&candidate)
+            .args(&fmris);
+        run_tool(PackageTool::PkgRecv, cmd, None)?;
+
+        let mut refresh_cmd = PackageTool::PkgRepo.command();
+        refresh_cmd.arg("refresh").arg("-s").arg(&candidate);
+        run_tool(PackageTool::PkgRepo, refresh_cmd, None)?;
+        Ok(())
+    };
+    if let Err(e) = receive() {
+        let _ = fs::remove_dir_all(&candidate);
+        return Err(e.wrap_err(format!(
+            "packages of {} cannot be promoted, the public repository was not changed",
+            name
+        )));
+    }
+    let replaced = swap_repository(&repo_link, &candidate)
+        .wrap_err(format!("promoting packages of {} failed", name))?;
+    if let Some(replaced) = replaced {
+        fs::remove_dir_all(&replaced).into_diagnostic()?;
+    }
+
+    for fmri in fmris.iter() {
+        println!("Promoted {}", fmri);
+    }
+
+    if let Some(snapshot) = snapshot {
+        snapshot_repository(settings, &candidate, snapshot)?;
+    }
+
+    if !keep {
+        fs::remove_dir_all(&staging).into_diagnostic()?;
+    }
+
+    Ok(fmris)
+}
+
+/// Held while a promotion replaces the public repository. The lock is released when
+/// the file is closed.
+struct PromotionLock(fs::File);
+
+impl PromotionLock {
+    fn acquire(repo_link: &Path) -> Result<Self> {
+        let path = repo_link.with_file_name(format!(".{}.lock", file_name(repo_link)?));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).into_diagnostic()?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&path)
+            .into_diagnostic()?;
+        // fcntl locks hold across NFS mounts, flock locks do not
+        let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+        lock.l_type = libc::F_WRLCK as _;
+        lock.l_whence = libc::SEEK_SET as _;
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLKW, &lock) } == -1 {
+            return Err(std::io::Error::last_os_error())
+                .into_diagnostic()
+                .wrap_err(format!("failed to lock {}", path.display()));
+        }
+        Ok(Self(file))
+    }
+}
+
+/// Copy the repository at `from` to `to`. Package payloads never change once they are
+/// published, they are hard linked instead of copied.
+fn copy_repository(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).into_diagnostic()?;
+    for entry in walk(from)? {
+        let relative = entry.strip_prefix(from).into_diagnostic()?;
+        let target = to.join(relative);
+        let file_type = fs::symlink_metadata(&entry).into_diagnostic()?.file_type();
+        if file_type.is_dir() {
+            fs::create_dir_all(&target).into_diagnostic()?;
+        } else if file_type.is_symlink() {
+            symlink(fs::read_link(&entry).into_diagnostic()?, &target).into_diagnostic()?;
+        } else if is_payload(relative) {
+            fs::hard_link(&entry, &target).into_diagnostic()?;
+        } else {
+            fs::copy(&entry, &target).into_diagnostic()?;
+        }
+    }
+    Ok(())
+}
+
+/// publisher/openindiana.org/file/ab/ab12... is a payload, catalogs and manifests are not.
+fn is_payload(relative: &Path) -> bool {
+    let mut components = relative.components();
+    components.next() == Some(Component::Normal(OsStr::new("publisher")))
+        && components.nth(1) == Some(Component::Normal(OsStr::new("file")))
+}
+
+/// Point the symlink `repo_link` at `candidate` and return the repository it replaced.
+/// Renaming a symlink over another one is atomic. A public repository that is still a
+/// directory is moved aside first.
+fn swap_repository(repo_link: &Path, candidate: &Path) -> Result<Option<PathBuf>> {
+    let next_link = repo_link.with_file_name(format!(".{}.next", file_name(repo_link)?));
+    let _ = fs::remove_file(&next_link);
+    // Relative so the repository directory can be mounted anywhere
+    symlink(file_name(candidate)?, &next_link).into_diagnostic()?;
+    let replaced = match fs::symlink_metadata(repo_link) {
+        Ok(metadata) if metadata.is_dir() => {
+            let moved = versioned(repo_link)?;
+            fs::rename(repo_link, &moved).into_diagnostic()?;
+            Some(moved)
+        }
+        Ok(_) => fs::canonicalize(repo_link).ok(),
+        Err(_) => None,
+    };
+    fs::rename(&next_link, repo_link).into_diagnostic()?;
+    Ok(replaced)
+}
+
+/// A new name next to the public repository, repo.20240101T120000.000Z
+fn versioned(repo_link: &Path) -> Result<PathBuf> {
+    Ok(repo_link.with_file_name(format!(
+        "{}.{}",
+        file_name(repo_link)?,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    )))
+}
+
+fn file_name(path: &Path) -> Result<&str> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| miette::miette!("{} has no file name", path.display()))
+}
+
+/// Copy the newest version of every package of the repository into the snapshot
+/// repository `name`, which must not exist yet.
+fn snapshot_repository(settings: &Settings, repo_path: &Path, name: &str) -> Result<()> {
+    let snapshot = settings.get_snapshot_repo_dir(name).into_diagnostic()?;
+    if snapshot.exists() {
+        return Err(miette::miette!(
+            "snapshot {} already exists at {}",
+            name,
+            snapshot.display()
+        ));
+    }
+
+    let fmris = staged_fmris(repo_path)?;
+    for publisher in fmris.iter().filter_map(|f| fmri_publisher(f)) {
+        ips::ensure_repo_with_publisher_exists(&snapshot, publisher)
+            .wrap_err("failed to create the snapshot repository")?;
+    }
+
+    let mut cmd = PackageTool::PkgRecv.command();
+    cmd.arg("-s")
+        .arg(repo_path)
+        .arg("-d")
+        .arg(&snapshot)
+        .args(&fmris);
+    run_tool(PackageTool::PkgRecv, cmd, None)
+        .wrap_err(format!("creating snapshot {} failed", name))?;
+
+    let mut refresh_cmd = PackageTool::PkgRepo.command();
+    refresh_cmd.arg("refresh").arg("-s").arg(&snapshot);
+    run_tool(PackageTool::PkgRepo, refresh_cmd, None)?;
+
+    println!("Snapshot {} at {}", name, snapshot.display());
+    Ok(())
+}
+
+/// The newest version of every package in the repository.
+fn staged_fmris(staging: &Path) -> Result<Vec<String>> {
+    let mut cmd = PackageTool::PkgRecv.command();
+    cmd.arg("-s").arg(staging).arg("--newest");
+    let output = run_tool(PackageTool::PkgRecv, cmd, None)?;
+    Ok(output
+        .stdout_lossy()
+        .lines()
+        .map(str::trim)
+        .filter(|l| l.starts_with("pkg:/"))
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// pkg://openindiana.org/library/foo@1.0 -> openindiana.org
+fn fmri_publisher(fmri: &str) -> Option<&str> {
+    fmri.strip_prefix("pkg://")?.split('/').next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn copies_repository_linking_payloads() {
+        let dir = std::env::temp_dir().join(format!("pkgdev-promote-{}", uuid::Uuid::new_v4()));
+        let repo = dir.join("repo");
+        let payload = repo.join("publisher/openindiana.org/file/ab/ab12");
+        let catalog = repo.join("publisher/openindiana.org/catalog/catalog.attrs");
+        fs::create_dir_all(payload.parent().unwrap()).unwrap();
+        fs::create_dir_all(catalog.parent().unwrap()).unwrap();
+        fs::write(&payload, "payload").unwrap();
+        fs::write(&catalog, "catalog").unwrap();
+
+        let copy = dir.join("copy");
+        copy_repository(&repo, &copy).unwrap();
+        let inode = |path: &Path| fs::metadata(path).unwrap().ino();
+        let copied_payload = copy.join("publisher/openindiana.org/file/ab/ab12");
+        let copied_catalog = copy.join("publisher/openindiana.org/catalog/catalog.attrs");
+        assert_eq!(inode(&payload), inode(&copied_payload));
+        assert_ne!(inode(&catalog), inode(&copied_catalog));
+        assert_eq!(fs::read_to_string(copied_catalog).unwrap(), "catalog");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use component::Component;
+use config::Settings;
+use gate::Gate;
+use miette::{IntoDiagnostic, Result, WrapErr};
+use workspace::Workspace;
+
+use crate::build::normalize::walk;
+use crate::build::{ips, run_build, BuildArgs, BuildSteps};
+
+/// What one build produced.
+struct BuildResult {
+    filelist: String,
+    payload: PathBuf,
+}
+
+/// Build the component twice and report every difference between the file lists
+/// and the contents of the prototype directories.
+pub async fn verify_reproducibility(
+    component: &Component,
+    gate: &Option<Gate>,
+    wks: &Workspace,
+    settings: &Settings,
+    args: &BuildArgs,
+) -> Result<()> {
+    let mut args = args.clone();
+    args.stop_on_step = Some(BuildSteps::Build);
+    args.no_clean = false;
+
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let first_payload = proto_dir.with_extension("first");
+    if first_payload.exists() {
+        fs::remove_dir_all(&first_payload).into_diagnostic()?;
+    }
+
+    println!("Reproducibility check: first build");
+    let first = build_once(component, gate, wks, settings, &args).await?;
+    fs::rename(&first.payload, &first_payload).into_diagnostic()?;
+    let first = BuildResult {
+        payload: first_payload,
+        ..first
+    };
+
+    println!("Reproducibility check: second build");
+    let second = build_once(component, gate, wks, settings, &args).await?;
+
+    let mut differences = diff_lines(&first.filelist, &second.filelist);
+    differences.extend(diff_payloads(&first.payload, &second.payload)?);
+
+    if differences.is_empty() {
+        println!("{} builds reproducibly", component.get_name());
+        fs::remove_dir_all(&first.payload).into_diagnostic()?;
+        Ok(())
+    } else {
+        for difference in differences.iter() {
+            println!("{}", difference);
+        }
+        Err(miette::miette!(
+            "{} is not reproducible: {} differences, the first payload is kept in {}",
+            component.get_name(),
+            differences.len(),
+            first.payload.display()
+        ))
+    }
+}
+
+async fn build_once(
+    component: &Component,
+    gate: &Option<Gate>,
+    wks: &Workspace,
+    settings: &Settings,
+    args: &BuildArgs,
+) -> Result<BuildResult> {
+    run_build(component, gate, wks, settings, args).await?;
+    ips::run_generate_filelist(wks, component).wrap_err("generating file list failed")?;
+    let filelist = fs::read_to_string(wks.get_or_create_manifest_dir()?.join("filelist.fmt"))
+        .into_diagnostic()?;
+    Ok(BuildResult {
+        filelist,
+        payload: wks.get_or_create_prototype_dir()?,
+    })
+}
+
+fn diff_lines(first: &str, second: &str) -> Vec<String> {
+    let mut differences = vec![];
+    for line in first.lines().filter(|l| !second.lines().any(|s| s == *l)) {
+        differences.push(format!("manifest - {}", line));
+    }
+    for line in second.lines().filter(|l| !first.lines().any(|f| f == *l)) {
+        differences.push(format!("manifest + {}", line));
+    }
+    differences
+}
+
+#[derive(PartialEq)]
+enum Entry {
+    Directory,
+    Symlink(PathBuf),
+    File {
+        contents: Vec<u8>,
+        mtime: Option<std::time::SystemTime>,
+    },
+}
+
+fn read_tree(root: &Path) -> Result<BTreeMap<PathBuf, Entry>> {
+    let mut tree = BTreeMap::new();
+    for path in walk(root)? {
+        let metadata = fs::symlink_metadata(&path).into_diagnostic()?;
+        let entry = if metadata.file_type().is_symlink() {
+            Entry::Symlink(fs::read_link(&path).into_diagnostic()?)
+        } else if metadata.is_dir() {
+            Entry::Directory
+        } else {
+            Entry::File {
+                contents: fs::read(&path).into_diagnostic()?,
+                mtime: metadata.modified().ok(),
+            }
+        };
+        let relative = path.strip_prefix(root).into_diagnostic()?.to_path_buf();
+        tree.insert(relative, entry);
+    }
+    Ok(tree)
+}
+
+fn same_contents(entry: &Entry, contents: &[u8]) -> bool {
+    matches!(entry, Entry::File { contents: c, .. } if c.as_slice() == contents)
+}
+
+fn diff_payloads(first: &Path, second: &Path) -> Result<Vec<String>> {
+    let first = read_tree(first)?;
+    let second = read_tree(second)?;
+
+    let mut differences = vec![];
+    for (path, entry) in first.iter() {
+        match second.get(path) {
+            None => differences.push(format!("payload - {}", path.display())),
+            Some(other) if other == entry => {}
+            Some(Entry::File { contents, .. }) if same_contents(entry, contents) => {
+                differences.push(format!("payload ~ {} (modification time)", path.display()))
+            }
+            Some(_) => differences.push(format!("payload ~ {}", path.display())),
+        }
+    }
+    for path in second.keys().filter(|p| !first.contains_key(*p)) {
+        differences.push(format!("payload + {}", path.display()));
+    }
+    Ok(differences)
+}
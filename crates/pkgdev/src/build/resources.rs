@@ -0,0 +1,111 @@
+use component::Recipe;
+use config::Settings;
+use gate::Gate;
+
+use crate::build::BuildArgs;
+
+/// What the build processes of a component may use. Each setting is taken from the
+/// command line, the recipe, the gate and the settings, whichever has it first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resources {
+    /// Jobs make and ninja run at once
+    pub jobs: u32,
+    /// Address space of every build process in bytes at most
+    pub memory_limit: Option<u64>,
+    /// CPU time of every build process in seconds at most
+    pub cpu_time_limit: Option<u64>,
+}
+
+impl Resources {
+    /// Recipes only set the jobs, the limits are up to whoever runs the builder.
+    pub fn resolve(
+        args: &BuildArgs,
+        recipe: &Recipe,
+        gate: &Option<Gate>,
+        settings: &Settings,
+    ) -> Self {
+        let gate = gate
+            .as_ref()
+            .and_then(|g| g.build_resources.clone())
+            .unwrap_or_default();
+        let jobs = args
+            .jobs
+            .or(recipe.jobs)
+            .or(gate.jobs)
+            .or(settings.build_jobs)
+            .unwrap_or_else(available_cpus)
+            .max(1);
+        Self {
+            jobs,
+            memory_limit: args
+                .memory_limit
+                .or(gate.memory_limit)
+                .or(settings.build_memory_limit),
+            cpu_time_limit: args
+                .cpu_time_limit
+                .or(gate.cpu_time_limit)
+                .or(settings.build_cpu_time_limit),
+        }
+    }
+
+    /// The environment telling build systems started by scripts how many jobs to run.
+    pub fn env(&self) -> Vec<(String, String)> {
+        vec![
+            (String::from("MAKE_JOBS"), self.jobs.to_string()),
+            (
+                String::from("CMAKE_BUILD_PARALLEL_LEVEL"),
+                self.jobs.to_string(),
+            ),
+        ]
+    }
+}
+
+fn available_cpus() -> u32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use component::RecipeBuilder;
+    use gate::BuildResources;
+
+    use super::*;
+
+    #[test]
+    fn command_line_goes_over_recipe_over_gate_over_settings() -> miette::Result<()> {
+        let mut settings = Settings::default();
+        settings.build_jobs = Some(2);
+        settings.build_memory_limit = Some(1 << 30);
+        settings.build_cpu_time_limit = Some(3600);
+        let mut gate = Gate::default();
+        gate.build_resources = Some(BuildResources {
+            jobs: Some(4),
+            memory_limit: Some(1 << 32),
+            cpu_time_limit: None,
+        });
+        let gate = Some(gate);
+        let mut recipe = RecipeBuilder::default().name("library/zlib").build()?;
+        let args = BuildArgs::parse_from(["build"]);
+
+        let resources = Resources::resolve(&args, &recipe, &None, &settings);
+        assert_eq!(resources.jobs, 2);
+        assert_eq!(resources.memory_limit, Some(1 << 30));
+
+        let resources = Resources::resolve(&args, &recipe, &gate, &settings);
+        assert_eq!(resources.jobs, 4);
+        assert_eq!(resources.memory_limit, Some(1 << 32));
+        assert_eq!(resources.cpu_time_limit, Some(3600));
+
+        recipe.jobs = Some(1);
+        assert_eq!(Resources::resolve(&args, &recipe, &gate, &settings).jobs, 1);
+
+        let args = BuildArgs::parse_from(["build", "--jobs", "8", "--cpu-time-limit", "60"]);
+        let resources = Resources::resolve(&args, &recipe, &gate, &settings);
+        assert_eq!(resources.jobs, 8);
+        assert_eq!(resources.cpu_time_limit, Some(60));
+        Ok(())
+    }
+}
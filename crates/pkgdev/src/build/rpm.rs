@@ -0,0 +1,288 @@
+use std::fs::{self, DirBuilder};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use component::Component;
+use miette::{IntoDiagnostic, Result};
+use workspace::Workspace;
+
+use crate::build::normalize::walk;
+use crate::build::util::{run, runtime_dependencies, PackageNaming};
+
+/// RPM package names allow alphanumerics and `-._+`.
+const NAMING: PackageNaming = PackageNaming {
+    name_chars: |c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_'),
+    lowercase: false,
+    architectures: ["i686", "x86_64", "aarch64", "sparc64"],
+    default_revision: "1",
+};
+
+fn derive_package_name(name: &str) -> String {
+    NAMING.package_name(name)
+}
+
+/// Version and Release may not contain dashes.
+fn derive_version(pkg: &Component) -> (String, String) {
+    let (version, revision) = NAMING.version(pkg);
+    (version.replace('-', "_"), revision.replace('-', "_"))
+}
+
+/// Directories the filesystem package of the distro owns, besides the sections of the
+/// manual and the message catalogs of the locales.
+const SYSTEM_DIRS: [&str; 27] = [
+    "/bin",
+    "/boot",
+    "/etc",
+    "/lib",
+    "/lib64",
+    "/opt",
+    "/sbin",
+    "/srv",
+    "/usr",
+    "/usr/bin",
+    "/usr/include",
+    "/usr/lib",
+    "/usr/lib/pkgconfig",
+    "/usr/lib64",
+    "/usr/lib64/pkgconfig",
+    "/usr/libexec",
+    "/usr/sbin",
+    "/usr/share",
+    "/usr/share/doc",
+    "/usr/share/info",
+    "/usr/share/licenses",
+    "/usr/share/locale",
+    "/usr/share/man",
+    "/usr/share/pkgconfig",
+    "/var",
+    "/var/lib",
+    "/var/log",
+];
+
+fn is_system_dir(path: &str) -> bool {
+    if SYSTEM_DIRS.contains(&path) {
+        return true;
+    }
+    if let Some(section) = path.strip_prefix("/usr/share/man/") {
+        return !section.contains('/');
+    }
+    if let Some(locale) = path.strip_prefix("/usr/share/locale/") {
+        return match locale.split_once('/') {
+            None => true,
+            Some((_, rest)) => rest == "LC_MESSAGES",
+        };
+    }
+    false
+}
+
+/// The %files list of everything in the prototype directory. Directories of the system
+/// are only listed when empty, the others belong to the package.
+fn generate_file_list(proto_dir: &Path) -> Result<Vec<String>> {
+    let mut files = vec![];
+    for path in walk(proto_dir)? {
+        let metadata = fs::symlink_metadata(&path).into_diagnostic()?;
+        let relative = path.strip_prefix(proto_dir).into_diagnostic()?;
+        let absolute = format!("/{}", relative.to_string_lossy());
+        if !metadata.is_dir() {
+            files.push(format!("\"{}\"", absolute));
+        } else if !is_system_dir(&absolute)
+            || fs::read_dir(&path).into_diagnostic()?.next().is_none()
+        {
+            files.push(format!("%dir \"{}\"", absolute));
+        }
+    }
+    Ok(files)
+}
+
+/// The spec file of the package built from the recipe. The build already happened, so
+/// %install only copies the prototype directory into the buildroot.
+fn generate_spec(
+    pkg: &Component,
+    arch: &str,
+    proto_dir: &Path,
+    profile: Option<&str>,
+) -> Result<String> {
+    let recipe = &pkg.recipe;
+    let summary = recipe.summary.clone().ok_or(miette::miette!(
+        "{} has no summary which the package description is made of",
+        pkg.get_name()
+    ))?;
+    let license = recipe.license.clone().ok_or(miette::miette!(
+        "{} has no license which rpm requires",
+        pkg.get_name()
+    ))?;
+    let (version, release) = derive_version(pkg);
+
+    let mut lines = vec![
+        format!("Name: {}", derive_package_name(&recipe.name)),
+        format!("Version: {}", version),
+        format!("Release: {}", release),
+        format!("Summary: {}", summary),
+        format!("License: {}", license),
+        format!("BuildArch: {}", arch),
+    ];
+    if let Some(project_url) = &recipe.project_url {
+        lines.push(format!("URL: {}", project_url));
+    }
+    if let Some(maintainer) = recipe.maintainers.first() {
+        lines.push(format!("Packager: {}", maintainer));
+    }
+    for dependency in runtime_dependencies(pkg) {
+        lines.push(format!(
+            "Requires: {}",
+            derive_package_name(&dependency.name)
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push(String::from("%description"));
+    lines.push(summary);
+    // rpm has no tag for it, so the profile goes into the description
+    if let Some(profile) = profile {
+        lines.push(String::new());
+        lines.push(format!("Built with the {} build profile.", profile));
+    }
+    lines.push(String::new());
+    lines.push(String::from("%install"));
+    lines.push(String::from("mkdir -p %{buildroot}"));
+    lines.push(format!(
+        "cp -a \"{}/.\" %{{buildroot}}/",
+        proto_dir.display()
+    ));
+    lines.push(String::new());
+    lines.push(String::from("%files"));
+    lines.push(String::from("%defattr(-,root,root,-)"));
+    lines.extend(generate_file_list(proto_dir)?);
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Build an .rpm from the prototype directory into the output directory.
+pub fn make_rpm(wks: &Workspace, pkg: &Component, profile: Option<&str>) -> Result<PathBuf> {
+    let proto_dir = wks.get_or_create_prototype_dir()?;
+    let arch = NAMING.architecture(wks.get_arch());
+    let package_name = derive_package_name(&pkg.get_name());
+
+    let top_dir = wks.get_or_create_build_dir()?.join("rpm");
+    if top_dir.exists() {
+        fs::remove_dir_all(&top_dir).into_diagnostic()?;
+    }
+    DirBuilder::new()
+        .recursive(true)
+        .create(top_dir.join("SPECS"))
+        .into_diagnostic()?;
+
+    let spec_path = top_dir.join("SPECS").join(format!("{}.spec", package_name));
+    fs::write(&spec_path, generate_spec(pkg, arch, &proto_dir, profile)?).into_diagnostic()?;
+
+    let output_dir = config::Settings::get_or_create_output_dir().into_diagnostic()?;
+    // The prototype directory is already normalized, rpm must not strip or otherwise
+    // post-process it
+    run(Command::new("rpmbuild")
+        .arg("-bb")
+        .arg("--target")
+        .arg(arch)
+        .arg("--define")
+        .arg(format!("_topdir {}", top_dir.display()))
+        .arg("--define")
+        .arg(format!("_rpmdir {}", output_dir.display()))
+        .arg("--define")
+        .arg("debug_package %{nil}")
+        .arg("--define")
+        .arg("__os_install_post %{nil}")
+        .arg("--define")
+        .arg("_build_id_links none")
+        .arg(&spec_path))?;
+
+    let (version, release) = derive_version(pkg);
+    let rpm_path = output_dir.join(arch).join(format!(
+        "{}-{}-{}.{}.rpm",
+        package_name, version, release, arch
+    ));
+    println!("Generated RPM package {}", rpm_path.display());
+
+    Ok(rpm_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use component::Dependency;
+
+    use super::*;
+
+    fn component() -> Component {
+        let mut pkg = Component::new(String::from("library/libfoo"), None::<&str>).unwrap();
+        pkg.recipe.version = Some(String::from("1.2-rc1"));
+        pkg.recipe.summary = Some(String::from("The foo library"));
+        pkg.recipe.license = Some(String::from("MIT"));
+        pkg.recipe.dependencies = vec![
+            Dependency {
+                name: String::from("library/zlib"),
+                dev: false,
+                kind: Default::default(),
+            },
+            Dependency {
+                name: String::from("developer/cmake"),
+                dev: true,
+                kind: Default::default(),
+            },
+        ];
+        pkg
+    }
+
+    #[test]
+    fn system_dirs_are_left_to_the_filesystem_package() {
+        assert!(is_system_dir("/usr/lib"));
+        assert!(is_system_dir("/usr/share/man/man3"));
+        assert!(is_system_dir("/usr/share/locale/de/LC_MESSAGES"));
+        assert!(!is_system_dir("/usr/lib/foo"));
+        assert!(!is_system_dir("/usr/share/doc/libfoo"));
+        assert!(!is_system_dir("/usr/share/locale/de/LC_MESSAGES/foo"));
+    }
+
+    #[test]
+    fn file_list_owns_the_directories_of_the_component() {
+        let proto_dir = std::env::temp_dir().join(format!("pkgdev-rpm-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(proto_dir.join("usr/lib/foo/plugins")).unwrap();
+        fs::create_dir_all(proto_dir.join("usr/share/empty")).unwrap();
+        fs::write(proto_dir.join("usr/lib/libfoo.so.1"), "").unwrap();
+        fs::write(proto_dir.join("usr/lib/foo/plugins/bar.so"), "").unwrap();
+
+        let mut files = generate_file_list(&proto_dir).unwrap();
+        fs::remove_dir_all(&proto_dir).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "\"/usr/lib/foo/plugins/bar.so\"",
+                "\"/usr/lib/libfoo.so.1\"",
+                "%dir \"/usr/lib/foo\"",
+                "%dir \"/usr/lib/foo/plugins\"",
+                "%dir \"/usr/share/empty\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn spec_describes_the_package() {
+        let proto_dir = std::env::temp_dir().join(format!("pkgdev-rpm-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&proto_dir).unwrap();
+        let spec = generate_spec(&component(), "x86_64", &proto_dir, Some("debug")).unwrap();
+        fs::remove_dir_all(&proto_dir).unwrap();
+
+        let lines = spec.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"Name: library-libfoo"));
+        assert!(lines.contains(&"Version: 1.2_rc1"));
+        assert!(lines.contains(&"Release: 1"));
+        assert!(lines.contains(&"Requires: library-zlib"));
+        assert!(!spec.contains("cmake"));
+        assert!(lines.contains(&"Built with the debug build profile."));
+    }
+
+    #[test]
+    fn spec_needs_a_license() {
+        let mut pkg = component();
+        pkg.recipe.license = None;
+        assert!(generate_spec(&pkg, "x86_64", Path::new("/nonexistent"), None).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use gate::{Gate, SandboxType};
+use miette::{IntoDiagnostic, Result};
+
+/// Where the build commands of a component run. Inside a chroot or zone the commands
+/// only see the tools installed there and the environment the build passes explicitly.
+/// The workspace and the component must be mounted at the same paths as on the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sandbox {
+    kind: SandboxKind,
+    /// Environment added to every command, like the proxy of the network monitor
+    env: Vec<(String, String)>,
+    /// Address space of every command in bytes at most
+    memory_limit: Option<u64>,
+    /// CPU time of every command in seconds at most
+    cpu_time_limit: Option<u64>,
+    /// Whether the commands may reach the network
+    network: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SandboxKind {
+    Host,
+    Chroot { root: PathBuf },
+    Zone { name: String },
+}
+
+impl Display for Sandbox {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            SandboxKind::Host => write!(f, "host"),
+            SandboxKind::Chroot { root } => write!(f, "chroot {}", root.display()),
+            SandboxKind::Zone { name } => write!(f, "zone {}", name),
+        }
+    }
+}
+
+impl Sandbox {
+    /// Pick the sandbox from the command line falling back to the defaults of the gate.
+    /// `target` is the chroot directory or the zone name.
+    pub fn new(
+        sandbox_type: Option<SandboxType>,
+        target: Option<String>,
+        gate: &Option<Gate>,
+    ) -> Result<Self> {
+        let gate_sandbox = gate.as_ref().and_then(|g| g.sandbox.clone());
+        let sandbox_type = sandbox_type
+            .or(gate_sandbox.as_ref().map(|s| s.sandbox_type.clone()))
+            .unwrap_or_default();
+
+        let kind = match sandbox_type {
+            SandboxType::Host => SandboxKind::Host,
+            SandboxType::Chroot => {
+                let root = target
+                    .or(gate_sandbox.and_then(|s| s.root))
+                    .ok_or(miette::miette!(
+                        "chroot sandbox needs a root directory, pass --sandbox-target or set root in the gate"
+                    ))?;
+                SandboxKind::Chroot {
+                    root: PathBuf::from(root),
+                }
+            }
+            SandboxType::Zone => {
+                let name = target
+                    .or(gate_sandbox.and_then(|s| s.zone))
+                    .ok_or(miette::miette!(
+                    "zone sandbox needs a zone name, pass --sandbox-target or set zone in the gate"
+                ))?;
+                SandboxKind::Zone { name }
+            }
+        };
+        let sandbox = Sandbox {
+            kind,
+            env: vec![],
+            memory_limit: None,
+            cpu_time_limit: None,
+            network: true,
+        };
+        sandbox.check()?;
+        Ok(sandbox)
+    }
+
+    /// The same sandbox with `env` added to every command run in it.
+    pub fn with_env(&self, env: Vec<(String, String)>) -> Self {
+        let mut sandbox = self.clone();
+        sandbox.env.extend(env);
+        sandbox
+    }
+
+    /// The same sandbox with the limits set on every command run in it. Commands started
+    /// by the commands inherit them, each has its own budget.
+    pub fn with_limits(&self, memory_limit: Option<u64>, cpu_time_limit: Option<u64>) -> Self {
+        let mut sandbox = self.clone();
+        sandbox.memory_limit = memory_limit;
+        sandbox.cpu_time_limit = cpu_time_limit;
+        sandbox
+    }
+
+    /// The same sandbox with commands that cannot reach the network. Zones must have no
+    /// network interfaces, on Linux every command gets a network namespace of its own.
+    /// Elsewhere the host and chroots cannot be cut off.
+    pub fn without_network(&self) -> Result<Self> {
+        match &self.kind {
+            SandboxKind::Zone { name } => {
+                for resource in ["net", "anet"] {
+                    let output = Command::new("zonecfg")
+                        .arg("-z")
+                        .arg(name)
+                        .arg("info")
+                        .arg(resource)
+                        .stderr(Stdio::null())
+                        .output()
+                        .into_diagnostic()?;
+                    if !output.status.success() {
+                        return Err(miette::miette!(
+                            "could not read the configuration of sandbox zone {}",
+                            name
+                        ));
+                    }
+                    if !output.stdout.is_empty() {
+                        return Err(miette::miette!(
+                            "the network policy denies network access but sandbox zone {} has network interfaces",
+                            name
+                        ));
+                    }
+                }
+            }
+            _ if cfg!(target_os = "linux") => {}
+            _ => {
+                return Err(miette::miette!(
+                    "the network policy denies network access, which needs a zone sandbox without network interfaces"
+                ))
+            }
+        }
+        let mut sandbox = self.clone();
+        sandbox.network = false;
+        Ok(sandbox)
+    }
+
+    fn check(&self) -> Result<()> {
+        match &self.kind {
+            SandboxKind::Host => Ok(()),
+            SandboxKind::Chroot { root } => {
+                if root.is_dir() {
+                    Ok(())
+                } else {
+                    Err(miette::miette!(
+                        "chroot sandbox root {} does not exist",
+                        root.display()
+                    ))
+                }
+            }
+            SandboxKind::Zone { name } => {
+                let output = Command::new("zoneadm")
+                    .arg("-z")
+                    .arg(name)
+                    .arg("list")
+                    .arg("-p")
+                    .stderr(Stdio::null())
+                    .output()
+                    .into_diagnostic()?;
+                let state = String::from_utf8_lossy(&output.stdout)
+                    .split(':')
+                    .nth(2)
+                    .map(|s| s.to_string());
+                if state.as_deref() == Some("running") {
+                    Ok(())
+                } else {
+                    Err(miette::miette!("sandbox zone {} is not running", name))
+                }
+            }
+        }
+    }
+
+    /// Turn a fully set up command into one running inside the sandbox. Only the
+    /// environment set explicitly on `cmd` is passed along. Stdio must be configured
+    /// on the returned command.
+    pub fn wrap(&self, mut cmd: Command) -> Result<Command> {
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if self.kind == SandboxKind::Host {
+            return self.limit(cmd);
+        }
+
+        let dir = match cmd.get_current_dir() {
+            Some(dir) => dir.to_path_buf(),
+            None => std::env::current_dir().into_diagnostic()?,
+        };
+
+        let mut words = vec![String::from("/usr/bin/env"), String::from("-i")];
+        for (key, value) in cmd.get_envs() {
+            if let Some(value) = value {
+                words.push(format!(
+                    "{}={}",
+                    key.to_string_lossy(),
+                    value.to_string_lossy()
+                ));
+            }
+        }
+        words.push(cmd.get_program().to_string_lossy().to_string());
+        words.extend(cmd.get_args().map(|a| a.to_string_lossy().to_string()));
+
+        let script = format!(
+            "cd {} && exec {}",
+            shell_quote(&dir.to_string_lossy()),
+            words
+                .iter()
+                .map(|w| shell_quote(w))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+
+        let mut wrapped = Command::new("pfexec");
+        match &self.kind {
+            SandboxKind::Host => unreachable!(),
+            SandboxKind::Chroot { root } => {
+                wrapped
+                    .arg("/usr/sbin/chroot")
+                    .arg(root)
+                    .arg("/bin/sh")
+                    .arg("-c")
+                    .arg(script);
+            }
+            SandboxKind::Zone { name } => {
+                wrapped.arg("/usr/sbin/zlogin").arg(name).arg(script);
+            }
+        }
+        // Limits survive the exec of chroot and zlogin
+        self.limit(wrapped)
+    }
+
+    #[cfg(unix)]
+    fn limit(&self, mut cmd: Command) -> Result<Command> {
+        use forge::limits::set_limit;
+        use std::os::unix::process::CommandExt;
+
+        let (memory_limit, cpu_time_limit) = (self.memory_limit, self.cpu_time_limit);
+        // Zones are cut off by their configuration
+        let unshare_network = !self.network && !matches!(self.kind, SandboxKind::Zone { .. });
+        if memory_limit.is_none() && cpu_time_limit.is_none() && !unshare_network {
+            return Ok(cmd);
+        }
+        // SAFETY: the closure only makes system calls, which are safe after fork
+        unsafe {
+            cmd.pre_exec(move || {
+                if unshare_network {
+                    unshare_network_namespace()?;
+                }
+                if let Some(bytes) = memory_limit {
+                    set_limit(libc::RLIMIT_AS, bytes)?;
+                }
+                if let Some(seconds) = cpu_time_limit {
+                    set_limit(libc::RLIMIT_CPU, seconds)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(cmd)
+    }
+
+    #[cfg(not(unix))]
+    fn limit(&self, cmd: Command) -> Result<Command> {
+        if self.memory_limit.is_some() || self.cpu_time_limit.is_some() || !self.network {
+            return Err(miette::miette!("build limits are only supported on unix"));
+        }
+        Ok(cmd)
+    }
+}
+
+/// Move the calling process into a new network namespace, which only has a loopback
+/// interface that is down. Without the privileges for that a user namespace is created
+/// along with it, which needs unprivileged user namespaces to be enabled.
+#[cfg(target_os = "linux")]
+fn unshare_network_namespace() -> std::io::Result<()> {
+    // SAFETY: unshare only changes the namespaces of the calling process
+    if unsafe { libc::unshare(libc::CLONE_NEWNET) } == 0 {
+        return Ok(());
+    }
+    match unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn unshare_network_namespace() -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "network namespaces are only supported on Linux",
+    ))
+}
+
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
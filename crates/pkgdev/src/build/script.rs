@@ -1,10 +1,10 @@
-use std::{
-    fs::DirBuilder,
-    process::{Command, Stdio},
-};
+use std::{fs::DirBuilder, process::Command};
 
+use crate::build::log::run_step;
+use crate::build::normalize::source_date_epoch;
+use crate::build::sandbox::Sandbox;
 use crate::build::util::copy_with_rsync;
-use crate::sources::derive_source_name;
+use crate::sources::SourceTree;
 use component::{Component, ScriptBuildSection};
 use config::Settings;
 use miette::{IntoDiagnostic, Result};
@@ -13,17 +13,17 @@ use workspace::Workspace;
 pub fn build_using_scripts(
     wks: &Workspace,
     pkg: &Component,
+    tree: &SourceTree,
     build_section: &ScriptBuildSection,
     settings: &Settings,
+    sandbox: &Sandbox,
 ) -> Result<()> {
-    let build_dir = wks.get_or_create_build_dir()?;
-    let unpack_name = derive_source_name(pkg.recipe.name.clone());
-    let unpack_path = build_dir.join(&unpack_name);
-    std::env::set_current_dir(&unpack_path).into_diagnostic()?;
+    let unpack_path = &tree.path;
+    std::env::set_current_dir(unpack_path).into_diagnostic()?;
 
     for script in &build_section.scripts {
-        let status = Command::new(pkg.get_path().join(&script.name))
-            .stdout(Stdio::inherit())
+        let mut script_cmd = Command::new(pkg.get_path().join(&script.name));
+        script_cmd
             .env(
                 "PROTO_DIR",
                 wks.get_or_create_prototype_dir()
@@ -32,8 +32,9 @@ pub fn build_using_scripts(
             )
             .env("UNPACK_DIR", &unpack_path.clone().into_os_string())
             .env("PATH", settings.get_search_path().join(":"))
-            .status()
-            .into_diagnostic()?;
+            .env("SOURCE_DATE_EPOCH", source_date_epoch(pkg).to_string());
+        let mut script_cmd = sandbox.wrap(script_cmd)?;
+        let status = run_step(wks, &format!("script-{}", script.name), &mut script_cmd)?;
 
         if status.success() {
             println!(
@@ -0,0 +1,32 @@
+use std::path::{Path, PathBuf};
+
+use config::Settings;
+use forge::signing::SigningKey;
+use miette::{IntoDiagnostic, Result, WrapErr};
+
+/// Write a detached signature next to a file pkgdev made, if the settings name a signing
+/// key. Consumers verify it with `signify -V -p <publisher>.pub -m <file>`.
+pub fn sign_file(settings: &Settings, path: &Path) -> Result<Option<PathBuf>> {
+    let Some(key_path) = &settings.signing_key else {
+        return Ok(None);
+    };
+    let key = std::fs::read_to_string(key_path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("reading the signing key {}", key_path))?;
+    let key = SigningKey::from_secret_key(&key)?;
+
+    let content = std::fs::read(path).into_diagnostic()?;
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let signature = key.sign(
+        &content,
+        &format!("verify {} with key {}", file_name, key.key_id()),
+    );
+    let signature_path =
+        PathBuf::from(format!("{}.{}", path.display(), forge::SIGNATURE_EXTENSION));
+    std::fs::write(&signature_path, signature).into_diagnostic()?;
+    println!("Signed {} with key {}", path.display(), key.key_id());
+    Ok(Some(signature_path))
+}
@@ -1,7 +1,10 @@
-use std::process::{Command, Stdio};
+use std::fs::File;
+use std::path::PathBuf;
 
 use component::Component;
-use miette::IntoDiagnostic;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use miette::{Context, IntoDiagnostic};
 use workspace::Workspace;
 
 fn derive_output_name(pkg: &Component) -> String {
@@ -12,46 +15,33 @@ fn derive_output_name(pkg: &Component) -> String {
     }
 }
 
-pub fn make_release_tarball(wks: &Workspace, pkg: &Component) -> miette::Result<()> {
+pub fn make_release_tarball(wks: &Workspace, pkg: &Component) -> miette::Result<PathBuf> {
     let proto_dir = wks.get_or_create_prototype_dir()?;
     let output_dir = config::Settings::get_or_create_output_dir().into_diagnostic()?;
-    let tarball_path_string = output_dir
-        .join(derive_output_name(pkg))
-        .to_string_lossy()
-        .to_string();
+    let tarball_path = output_dir.join(derive_output_name(pkg));
 
-    let dirs = std::fs::read_dir(&proto_dir)
-        .into_diagnostic()?
-        .into_iter()
-        .map(|p| {
-            p.unwrap()
-                .path()
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string()
-        })
-        .collect::<Vec<String>>();
-
-    let mut tar_cmd = Command::new("gtar");
-    tar_cmd.current_dir(&proto_dir);
-    tar_cmd.arg("-czf");
-    tar_cmd.arg(&tarball_path_string);
-    tar_cmd.args(
-        dirs.iter()
-            .map(|p| p.as_str())
-            .collect::<Vec<&str>>()
-            .as_slice(),
-    );
-    tar_cmd.stdout(Stdio::inherit());
-    let tar_cmd_status = tar_cmd.status().into_diagnostic()?;
-
-    if tar_cmd_status.success() {
-        println!("Generated Output tarball {}", tarball_path_string);
-        Ok(())
-    } else {
-        Err(miette::miette!(
-            "gtar returned error code check above for error"
-        ))
+    // Written with the tar library, so no GNU tar is needed on macOS and Windows
+    let tarball = File::create(&tarball_path).into_diagnostic()?;
+    let mut archive = tar::Builder::new(GzEncoder::new(tarball, Compression::default()));
+    archive.follow_symlinks(false);
+    for entry in std::fs::read_dir(&proto_dir).into_diagnostic()? {
+        let entry = entry.into_diagnostic()?;
+        let name = entry.file_name();
+        let added = if entry.file_type().into_diagnostic()?.is_dir() {
+            archive.append_dir_all(&name, entry.path())
+        } else {
+            archive.append_path_with_name(entry.path(), &name)
+        };
+        added
+            .into_diagnostic()
+            .wrap_err_with(|| format!("adding {} to the tarball", entry.path().display()))?;
     }
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .into_diagnostic()
+        .wrap_err("finishing the tarball")?;
+
+    println!("Generated Output tarball {}", tarball_path.display());
+    Ok(tarball_path)
 }
@@ -0,0 +1,250 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::build::log::tail;
+
+/// How many lines of stderr are kept in the errors of a failed tool.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// How often a tool talking to repositories runs before a transient failure is given up on.
+const ATTEMPTS: u32 = 3;
+
+/// The wait before the second attempt, doubled for each one after it.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// What pkg(7) prints when it could not reach a repository.
+const TRANSPORT_ERRORS: [&str; 3] = ["transport error", "framework error", "unable to contact"];
+
+/// The pkg(7) tools used to assemble and publish packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageTool {
+    PkgSend,
+    PkgFmt,
+    PkgDepend,
+    PkgLint,
+    PkgRepo,
+    PkgRecv,
+}
+
+impl PackageTool {
+    pub fn program(&self) -> &'static str {
+        match self {
+            PackageTool::PkgSend => "pkgsend",
+            PackageTool::PkgFmt => "pkgfmt",
+            PackageTool::PkgDepend => "pkgdepend",
+            PackageTool::PkgLint => "pkglint",
+            PackageTool::PkgRepo => "pkgrepo",
+            PackageTool::PkgRecv => "pkgrecv",
+        }
+    }
+
+    /// How long the tool may run before it is considered hung. Publishing, dependency
+    /// resolution and lint work on whole packages or images so they get much longer.
+    pub fn timeout(&self) -> Duration {
+        match self {
+            PackageTool::PkgFmt => Duration::from_secs(120),
+            PackageTool::PkgRepo => Duration::from_secs(300),
+            PackageTool::PkgSend
+            | PackageTool::PkgDepend
+            | PackageTool::PkgLint
+            | PackageTool::PkgRecv => Duration::from_secs(1800),
+        }
+    }
+
+    pub fn command(&self) -> Command {
+        Command::new(self.program())
+    }
+
+    /// Only tools that talk to repositories fail transiently, and of them pkgsend may
+    /// have published part of a package already.
+    fn is_retryable(&self) -> bool {
+        matches!(self, PackageTool::PkgRepo | PackageTool::PkgRecv)
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ToolError {
+    #[error("could not start {tool}: {source}")]
+    #[diagnostic(
+        code(pkgdev::tool::spawn),
+        help("make sure {tool} is installed and in PATH")
+    )]
+    Spawn {
+        tool: String,
+        source: std::io::Error,
+    },
+
+    #[error("{tool} {args} did not finish within {}s\n{stderr_tail}", timeout.as_secs())]
+    #[diagnostic(code(pkgdev::tool::timeout))]
+    TimedOut {
+        tool: String,
+        args: String,
+        timeout: Duration,
+        stderr_tail: String,
+    },
+
+    #[error("{tool} {args} failed with exit code {}\n{stderr_tail}", exit_code.map(|c| c.to_string()).unwrap_or(String::from("none (killed by signal)")))]
+    #[diagnostic(code(pkgdev::tool::failed))]
+    Failed {
+        tool: String,
+        args: String,
+        exit_code: Option<i32>,
+        stderr_tail: String,
+    },
+}
+
+impl ToolError {
+    /// Whether the tool may succeed when run again: it hung or could not reach a
+    /// repository.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ToolError::Spawn { .. } => false,
+            ToolError::TimedOut { .. } => true,
+            ToolError::Failed { stderr_tail, .. } => {
+                let stderr_tail = stderr_tail.to_lowercase();
+                TRANSPORT_ERRORS.iter().any(|e| stderr_tail.contains(e))
+            }
+        }
+    }
+}
+
+/// The captured output of a successful tool run.
+pub struct ToolOutput {
+    pub stdout: Vec<u8>,
+    /// Warnings, pkglint and pkgdepend report on stderr even when they succeed.
+    pub stderr: Vec<u8>,
+}
+
+impl ToolOutput {
+    pub fn stdout_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).to_string()
+    }
+
+    pub fn stderr_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).to_string()
+    }
+}
+
+/// Run `cmd`, which must have been created with [`PackageTool::command`], feeding it
+/// `stdin` and capturing its output. The tool is killed once its timeout expires, tools
+/// talking to repositories are run again after transient failures.
+pub fn run_tool(
+    tool: PackageTool,
+    mut cmd: Command,
+    stdin: Option<Vec<u8>>,
+) -> Result<ToolOutput, ToolError> {
+    let mut attempt = 1;
+    loop {
+        match run_once(tool, &mut cmd, stdin.clone()) {
+            Err(err) if tool.is_retryable() && err.is_transient() && attempt < ATTEMPTS => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt - 1);
+                eprintln!("{}\nTrying again in {}s", err, delay.as_secs());
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn run_once(
+    tool: PackageTool,
+    cmd: &mut Command,
+    stdin: Option<Vec<u8>>,
+) -> Result<ToolOutput, ToolError> {
+    let args = cmd
+        .get_args()
+        .map(|a| a.to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    cmd.stdin(if stdin.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
+    let mut child = cmd.spawn().map_err(|source| ToolError::Spawn {
+        tool: tool.program().to_string(),
+        source,
+    })?;
+
+    let writer = match (stdin, child.stdin.take()) {
+        (Some(input), Some(mut pipe)) => Some(thread::spawn(move || {
+            // The tool closing its stdin early shows up in its exit code
+            let _ = pipe.write_all(&input);
+        })),
+        _ => None,
+    };
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let status = wait_with_timeout(&mut child, tool.timeout());
+
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr_bytes = stderr.join().unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr_bytes).to_string();
+
+    match status {
+        None => Err(ToolError::TimedOut {
+            tool: tool.program().to_string(),
+            args,
+            timeout: tool.timeout(),
+            stderr_tail: tail(&stderr, STDERR_TAIL_LINES).join("\n"),
+        }),
+        Some(Ok(status)) if status.success() => Ok(ToolOutput {
+            stdout,
+            stderr: stderr_bytes,
+        }),
+        Some(Ok(status)) => Err(ToolError::Failed {
+            tool: tool.program().to_string(),
+            args,
+            exit_code: status.code(),
+            stderr_tail: tail(&stderr, STDERR_TAIL_LINES).join("\n"),
+        }),
+        Some(Err(source)) => Err(ToolError::Spawn {
+            tool: tool.program().to_string(),
+            source,
+        }),
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Returns `None` if the child had to be killed.
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> Option<std::io::Result<std::process::ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(Ok(status)),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(100)),
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
@@ -1,11 +1,15 @@
 use std::{
+    fs,
     io::Write,
     path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 
+use component::{Component, Dependency};
 use miette::{IntoDiagnostic, Result};
-use workspace::Workspace;
+use workspace::{Architecture, Workspace};
+
+use crate::build::normalize::walk;
 
 pub fn copy_with_rsync<P: AsRef<Path>>(
     wks: &Workspace,
@@ -76,3 +80,144 @@ pub fn expand_env(value: &str) -> Result<String> {
         Ok(value.to_string())
     }
 }
+
+/// How a package format names packages, architectures and versions.
+pub struct PackageNaming {
+    /// Characters package names may contain, the others become dashes
+    pub name_chars: fn(char) -> bool,
+    pub lowercase: bool,
+    /// The names of i386, amd64, aarch64 and sparc
+    pub architectures: [&'static str; 4],
+    /// Revision of recipes without one
+    pub default_revision: &'static str,
+}
+
+impl PackageNaming {
+    pub fn package_name(&self, name: &str) -> String {
+        let name = name.trim_start_matches('/');
+        let name = if self.lowercase {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        };
+        name.chars()
+            .map(|c| if (self.name_chars)(c) { c } else { '-' })
+            .collect()
+    }
+
+    pub fn architecture(&self, arch: Architecture) -> &'static str {
+        match arch {
+            Architecture::I386 => self.architectures[0],
+            Architecture::Amd64 => self.architectures[1],
+            Architecture::Aarch64 => self.architectures[2],
+            Architecture::Sparc => self.architectures[3],
+        }
+    }
+
+    /// The version and revision of the recipe.
+    pub fn version(&self, pkg: &Component) -> (String, String) {
+        (
+            pkg.recipe.version.clone().unwrap_or(String::from("0")),
+            pkg.recipe
+                .revision
+                .clone()
+                .unwrap_or(String::from(self.default_revision)),
+        )
+    }
+}
+
+/// The dependencies a package needs installed. Build dependencies are only needed on
+/// the build host.
+pub fn runtime_dependencies(pkg: &Component) -> impl Iterator<Item = &Dependency> {
+    pkg.recipe.dependencies.iter().filter(|d| !d.dev)
+}
+
+/// Bytes of the regular files below `dir`.
+pub fn installed_size(dir: &Path) -> Result<u64> {
+    let mut bytes = 0;
+    for path in walk(dir)? {
+        let metadata = fs::symlink_metadata(&path).into_diagnostic()?;
+        if metadata.is_file() {
+            bytes += metadata.len();
+        }
+    }
+    Ok(bytes)
+}
+
+/// Run a command with its output on the terminal.
+pub fn run(cmd: &mut Command) -> Result<()> {
+    let status = cmd.stdout(Stdio::inherit()).status().into_diagnostic()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(miette::miette!(
+            "{} returned error code check above for error",
+            cmd.get_program().to_string_lossy()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMING: PackageNaming = PackageNaming {
+        name_chars: |c| c.is_ascii_alphanumeric() || c == '-',
+        lowercase: true,
+        architectures: ["i386", "amd64", "arm64", "sparc64"],
+        default_revision: "1",
+    };
+
+    #[test]
+    fn names_follow_the_package_format() {
+        assert_eq!(NAMING.package_name("/library/Lib_Foo"), "library-lib-foo");
+        assert_eq!(NAMING.architecture(Architecture::Aarch64), "arm64");
+    }
+
+    #[test]
+    fn versions_default_to_the_format() {
+        let mut pkg = Component::new(String::from("library/foo"), None::<&str>).unwrap();
+        assert_eq!(NAMING.version(&pkg), (String::from("0"), String::from("1")));
+        pkg.recipe.version = Some(String::from("2.0"));
+        pkg.recipe.revision = Some(String::from("3"));
+        assert_eq!(
+            NAMING.version(&pkg),
+            (String::from("2.0"), String::from("3"))
+        );
+    }
+
+    #[test]
+    fn build_dependencies_are_not_needed_at_runtime() {
+        let mut pkg = Component::new(String::from("library/foo"), None::<&str>).unwrap();
+        pkg.recipe.dependencies = vec![
+            Dependency {
+                name: String::from("library/zlib"),
+                dev: false,
+                kind: Default::default(),
+            },
+            Dependency {
+                name: String::from("developer/cmake"),
+                dev: true,
+                kind: Default::default(),
+            },
+        ];
+        assert_eq!(
+            runtime_dependencies(&pkg)
+                .map(|d| d.name.as_str())
+                .collect::<Vec<_>>(),
+            ["library/zlib"]
+        );
+    }
+
+    #[test]
+    fn installed_size_counts_regular_files() {
+        let dir = std::env::temp_dir().join(format!("pkgdev-util-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("usr/bin")).unwrap();
+        fs::write(dir.join("usr/bin/foo"), "12345").unwrap();
+        fs::write(dir.join("usr/bin/bar"), "123").unwrap();
+        std::os::unix::fs::symlink("foo", dir.join("usr/bin/baz")).unwrap();
+        let size = installed_size(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(size, 8);
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset};
+use clap::Parser;
+use gate::Gate;
+use miette::IntoDiagnostic;
+
+use crate::forge::{gate_changelog, ChangelogQuery};
+
+#[derive(Debug, Parser, Clone)]
+pub struct ChangelogArgs {
+    /// Id of the gate on the forge, the one of the gate given with --gate or the default
+    /// gate of the forge profile if not given
+    #[arg(long)]
+    pub gate_id: Option<String>,
+
+    /// Id of the snapshot the changelog starts at
+    #[arg(long, requires = "to", conflicts_with_all = ["since", "until"])]
+    pub from: Option<String>,
+
+    /// Id of the snapshot the changelog ends at
+    #[arg(long, requires = "from")]
+    pub to: Option<String>,
+
+    /// Only change requests applied at or after this RFC 3339 time
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    pub since: Option<DateTime<FixedOffset>>,
+
+    /// Only change requests applied before this RFC 3339 time
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    pub until: Option<DateTime<FixedOffset>>,
+
+    /// Print the changelog as JSON instead of Markdown
+    #[arg(long)]
+    pub json: bool,
+
+    /// Write the changelog to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Print which components were added, updated or removed in the gate between two
+/// snapshots or in a time range, as Markdown for release notes.
+pub async fn print_changelog(args: ChangelogArgs, gate: &Option<Gate>) -> miette::Result<()> {
+    let gate_id = args
+        .gate_id
+        .or(gate.as_ref().and_then(|gate| gate.id.clone()));
+    let query = ChangelogQuery {
+        from_snapshot: args.from,
+        to_snapshot: args.to,
+        since: args.since.map(|since| since.to_rfc3339()),
+        until: args.until.map(|until| until.to_rfc3339()),
+    };
+    let changelog = gate_changelog(gate_id, &query).await?;
+
+    let text = if args.json {
+        serde_json::to_string_pretty(&changelog).into_diagnostic()?
+    } else {
+        changelog.markdown
+    };
+    match args.output {
+        Some(path) => std::fs::write(path, text).into_diagnostic()?,
+        None => println!("{}", text.trim_end()),
+    }
+    Ok(())
+}
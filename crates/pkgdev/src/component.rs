@@ -16,5 +16,9 @@ pub(crate) fn open_component_local<P: AsRef<std::path::Path>>(
 
     let full_component_path = full_component_path.canonicalize().into_diagnostic()?;
 
-    Ok(Component::open_local(full_component_path.as_path())?)
+    let mut component = Component::open_local(full_component_path.as_path())?;
+    if let Some(gate) = gate {
+        gate.apply_overrides(&mut component.recipe)?;
+    }
+    Ok(component)
 }
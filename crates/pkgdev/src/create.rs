@@ -1,9 +1,720 @@
-use component::Component;
+use std::fs;
+use std::io::{BufRead, Cursor, Write};
+use std::os::unix::fs::PermissionsExt;
+
+use base64::Engine;
+use clap::Subcommand;
+use component::{
+    suggest_classification, ArchiveSourceBuilder, BuildSectionBuilder, Component,
+    ConfigureBuildSectionBuilder, DependencyBuilder, ScriptBuildSectionBuilder, ScriptNodeBuilder,
+    SourceNode, SourceSection,
+};
+use miette::{Diagnostic, IntoDiagnostic};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
 
 use crate::args::ComponentArgs;
 
+const USER_AGENT: &str = concat!("pkgdev/", env!("CARGO_PKG_VERSION"));
+const BUILD_SCRIPT: &str = "build.sh";
+
+#[derive(Debug, Subcommand)]
+pub enum CreateFrom {
+    /// Create a component from the latest release of a crate on crates.io
+    #[clap(name = "from-crate")]
+    FromCrate {
+        name: String,
+        #[clap(flatten)]
+        args: ComponentArgs,
+    },
+    /// Create a component from the latest release of a package on PyPI
+    #[clap(name = "from-pypi")]
+    FromPypi {
+        name: String,
+        #[clap(flatten)]
+        args: ComponentArgs,
+    },
+    /// Create a component from the latest release of a package on npm
+    #[clap(name = "from-npm")]
+    FromNpm {
+        name: String,
+        #[clap(flatten)]
+        args: ComponentArgs,
+    },
+    /// Ask for the details of the component and probe its source archive
+    #[clap(name = "interactive")]
+    Interactive {
+        #[clap(flatten)]
+        args: ComponentArgs,
+    },
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum RegistryError {
+    #[error("could not query {registry} for {name}: {source}")]
+    Request {
+        registry: &'static str,
+        name: String,
+        source: reqwest::Error,
+    },
+
+    #[error("{registry} has no {field} for {name}")]
+    #[diagnostic(help("the package might not have a release usable as source"))]
+    MissingField {
+        registry: &'static str,
+        name: String,
+        field: &'static str,
+    },
+
+    #[error("{name} is not a valid {registry} package name")]
+    #[diagnostic(help("npm package names are lowercase and URL safe, like @scope/name"))]
+    InvalidName {
+        registry: &'static str,
+        name: String,
+    },
+
+    #[error("npm returned an integrity value that is not a sha512 hash: {0}")]
+    UnsupportedIntegrity(String),
+
+    #[error("could not download {url}: {source}")]
+    Download { url: String, source: reqwest::Error },
+
+    #[error("{url} is not an archive pkgdev can unpack: {source}")]
+    #[diagnostic(help("choose the build style by hand, the archive is still used as source"))]
+    UnreadableArchive {
+        url: String,
+        source: compress_tools::Error,
+    },
+}
+
+/// The package registries of language ecosystems components can be created from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Registry {
+    CratesIo,
+    Pypi,
+    Npm,
+}
+
+/// What a registry knows about the latest release of a package.
+struct Release {
+    version: String,
+    summary: Option<String>,
+    license: Option<String>,
+    project_url: Option<String>,
+    archive: String,
+    sha256: Option<String>,
+    sha512: Option<String>,
+    /// Categories, keywords and classifiers the classification is suggested from
+    topics: Vec<String>,
+}
+
+impl Registry {
+    fn name(&self) -> &'static str {
+        match self {
+            Registry::CratesIo => "crates.io",
+            Registry::Pypi => "PyPI",
+            Registry::Npm => "npm",
+        }
+    }
+
+    fn metadata_url(&self, name: &str) -> String {
+        match self {
+            Registry::CratesIo => format!("https://crates.io/api/v1/crates/{}", name),
+            Registry::Pypi => format!("https://pypi.org/pypi/{}/json", name),
+            Registry::Npm => format!("https://registry.npmjs.org/{}", name),
+        }
+    }
+
+    fn fmri(&self, name: &str) -> String {
+        let name = name
+            .trim_start_matches('@')
+            .replace('/', "-")
+            .to_lowercase();
+        match self {
+            Registry::CratesIo => format!("developer/rust/{}", name),
+            Registry::Pypi => format!("library/python/{}", name),
+            Registry::Npm => format!("library/nodejs/{}", name),
+        }
+    }
+
+    /// npm names end up in the build script, so only the names npm accepts pass.
+    fn check_name(&self, name: &str) -> Result<(), RegistryError> {
+        let valid = match self {
+            Registry::Npm => is_npm_name(name),
+            Registry::CratesIo | Registry::Pypi => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(RegistryError::InvalidName {
+                registry: self.name(),
+                name: name.to_string(),
+            })
+        }
+    }
+
+    fn build_dependency(&self) -> &'static str {
+        match self {
+            Registry::CratesIo => "developer/lang/rustc",
+            Registry::Pypi => "library/python/pip",
+            Registry::Npm => "runtime/nodejs",
+        }
+    }
+
+    /// The script building the unpacked release into the prototype directory.
+    fn build_script(&self, name: &str) -> String {
+        let commands = match self {
+            Registry::CratesIo => String::from(
+                "cargo install --locked --root \"${PROTO_DIR}/usr\" --path \"${UNPACK_DIR}\"\n\
+                 rm -f \"${PROTO_DIR}/usr/.crates.toml\" \"${PROTO_DIR}/usr/.crates2.json\"\n",
+            ),
+            Registry::Pypi => String::from(
+                "python3 -m pip install --no-deps --no-build-isolation --root \"${PROTO_DIR}\" --prefix /usr \"${UNPACK_DIR}\"\n",
+            ),
+            Registry::Npm => format!(
+                "target=\"${{PROTO_DIR}}/usr/lib/node_modules/{name}\"\n\
+                 mkdir -p \"${{target}}\"\n\
+                 cp -R \"${{UNPACK_DIR}}/.\" \"${{target}}\"\n\
+                 cd \"${{target}}\" && npm install --omit=dev --ignore-scripts\n",
+                name = name
+            ),
+        };
+        format!("#!/bin/sh\nset -e\n{}", commands)
+    }
+
+    async fn latest_release(&self, name: &str) -> Result<Release, RegistryError> {
+        let request_error = |source| RegistryError::Request {
+            registry: self.name(),
+            name: name.to_string(),
+            source,
+        };
+        let missing = |field| RegistryError::MissingField {
+            registry: self.name(),
+            name: name.to_string(),
+            field,
+        };
+
+        let metadata: Value = reqwest::Client::new()
+            .get(self.metadata_url(name))
+            .header(reqwest::header::USER_AGENT, USER_AGENT)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(request_error)?
+            .json()
+            .await
+            .map_err(request_error)?;
+
+        let text = |value: &Value| {
+            value
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        let texts = |value: &Value| {
+            value
+                .as_array()
+                .map(|v| v.iter().filter_map(text).collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+
+        match self {
+            Registry::CratesIo => {
+                let krate = &metadata["crate"];
+                let version = text(&krate["max_stable_version"])
+                    .or(text(&krate["newest_version"]))
+                    .ok_or(missing("version"))?;
+                let release = metadata["versions"]
+                    .as_array()
+                    .and_then(|v| {
+                        v.iter()
+                            .find(|v| text(&v["num"]).as_ref() == Some(&version))
+                    })
+                    .ok_or(missing("release"))?;
+                Ok(Release {
+                    summary: text(&krate["description"]).map(|s| s.trim().to_string()),
+                    license: text(&release["license"]),
+                    project_url: text(&krate["homepage"]).or(text(&krate["repository"])),
+                    archive: format!(
+                        "https://static.crates.io/crates/{name}/{name}-{version}.crate",
+                        name = name,
+                        version = version
+                    ),
+                    sha256: Some(text(&release["checksum"]).ok_or(missing("checksum"))?),
+                    sha512: None,
+                    topics: texts(&krate["categories"])
+                        .into_iter()
+                        .chain(texts(&krate["keywords"]))
+                        .collect(),
+                    version,
+                })
+            }
+            Registry::Pypi => {
+                let info = &metadata["info"];
+                let sdist = metadata["urls"]
+                    .as_array()
+                    .and_then(|u| u.iter().find(|u| u["packagetype"] == "sdist"))
+                    .ok_or(missing("source distribution"))?;
+                Ok(Release {
+                    version: text(&info["version"]).ok_or(missing("version"))?,
+                    summary: text(&info["summary"]),
+                    license: text(&info["license"]),
+                    project_url: text(&info["home_page"])
+                        .or(text(&info["project_urls"]["Homepage"]))
+                        .or(text(&info["project_url"])),
+                    archive: text(&sdist["url"]).ok_or(missing("source url"))?,
+                    sha256: Some(text(&sdist["digests"]["sha256"]).ok_or(missing("checksum"))?),
+                    sha512: None,
+                    topics: texts(&info["classifiers"])
+                        .into_iter()
+                        .chain(text(&info["keywords"]))
+                        .collect(),
+                })
+            }
+            Registry::Npm => {
+                let version =
+                    text(&metadata["dist-tags"]["latest"]).ok_or(missing("latest version"))?;
+                let release = &metadata["versions"][&version];
+                let integrity = text(&release["dist"]["integrity"]).ok_or(missing("checksum"))?;
+                Ok(Release {
+                    summary: text(&release["description"]),
+                    license: text(&release["license"]),
+                    project_url: text(&release["homepage"]),
+                    archive: text(&release["dist"]["tarball"]).ok_or(missing("tarball"))?,
+                    sha256: None,
+                    sha512: Some(sha512_from_integrity(&integrity)?),
+                    topics: texts(&release["keywords"]),
+                    version,
+                })
+            }
+        }
+    }
+}
+
 pub(crate) fn create_component(arg: ComponentArgs, fmri: String) -> miette::Result<()> {
     let c = Component::new(fmri, Some(arg.component))?;
     c.save_document()?;
     Ok(())
 }
+
+/// Create a component for the latest release of a package in a language registry with
+/// a script build section fitting the ecosystem.
+pub(crate) async fn create_component_from_registry(from: CreateFrom) -> miette::Result<()> {
+    let (registry, name, args) = match from {
+        CreateFrom::FromCrate { name, args } => (Registry::CratesIo, name, args),
+        CreateFrom::FromPypi { name, args } => (Registry::Pypi, name, args),
+        CreateFrom::FromNpm { name, args } => (Registry::Npm, name, args),
+        CreateFrom::Interactive { args } => return create_component_interactive(args).await,
+    };
+
+    registry.check_name(&name)?;
+    let release = registry.latest_release(&name).await?;
+    println!(
+        "Creating {} from {} {} on {}",
+        registry.fmri(&name),
+        name,
+        release.version,
+        registry.name()
+    );
+
+    let mut c = Component::new(registry.fmri(&name), Some(&args.component))?;
+    c.recipe.project_name = Some(name.clone());
+    c.recipe.version = Some(release.version.clone());
+    c.recipe.summary = release.summary;
+    c.recipe.license = release.license;
+    c.recipe.project_url = release.project_url;
+    if let Some(suggestion) = suggest_classification(&release.topics) {
+        println!(
+            "Classified as {} because of {}, change it with pkgdev edit if it does not fit",
+            suggestion.classification,
+            suggestion.matched.join(", ")
+        );
+        c.recipe.classification = Some(suggestion.classification);
+    }
+
+    let mut archive = ArchiveSourceBuilder::default();
+    archive.src(release.archive);
+    if let Some(sha256) = release.sha256 {
+        archive.sha256(sha256);
+    }
+    if let Some(sha512) = release.sha512 {
+        archive.sha512(sha512);
+    }
+    c.recipe.sources.push(SourceSection {
+        name: None,
+        sources: vec![SourceNode::Archive(archive.build()?)],
+    });
+
+    c.recipe.dependencies.push(
+        DependencyBuilder::default()
+            .name(registry.build_dependency())
+            .dev(true)
+            .build()?,
+    );
+
+    c.recipe.build_sections.push(
+        BuildSectionBuilder::default()
+            .script(
+                ScriptBuildSectionBuilder::default()
+                    .script(ScriptNodeBuilder::default().name(BUILD_SCRIPT).build()?)
+                    .build()?,
+            )
+            .build()?,
+    );
+
+    let script_path = args.component.join(BUILD_SCRIPT);
+    fs::write(&script_path, registry.build_script(&name)).into_diagnostic()?;
+    fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755)).into_diagnostic()?;
+
+    c.save_document()?;
+    Ok(())
+}
+
+/// The build styles a component can be created with interactively.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BuildStyle {
+    Configure,
+    Meson,
+    Cmake,
+    Cargo,
+}
+
+impl BuildStyle {
+    const ALL: [BuildStyle; 4] = [
+        BuildStyle::Configure,
+        BuildStyle::Meson,
+        BuildStyle::Cmake,
+        BuildStyle::Cargo,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            BuildStyle::Configure => "configure",
+            BuildStyle::Meson => "meson",
+            BuildStyle::Cmake => "cmake",
+            BuildStyle::Cargo => "cargo",
+        }
+    }
+
+    /// The file at the top of the source tree that gives the build system away.
+    fn marker(&self) -> &'static str {
+        match self {
+            BuildStyle::Configure => "configure",
+            BuildStyle::Meson => "meson.build",
+            BuildStyle::Cmake => "CMakeLists.txt",
+            BuildStyle::Cargo => "Cargo.toml",
+        }
+    }
+
+    fn build_dependency(&self) -> Option<&'static str> {
+        match self {
+            BuildStyle::Configure => None,
+            BuildStyle::Meson => Some("developer/build/meson"),
+            BuildStyle::Cmake => Some("developer/build/cmake"),
+            BuildStyle::Cargo => Some(Registry::CratesIo.build_dependency()),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<BuildStyle> {
+        BuildStyle::ALL
+            .into_iter()
+            .find(|style| style.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// Detect the build system from the paths in a source archive. Only the top of the source
+/// tree counts, which is the root or the single directory archives usually unpack to.
+/// Projects shipping several build systems get the one listed first in [`BuildStyle::ALL`].
+fn detect_build_style<S: AsRef<str>>(paths: &[S]) -> Option<BuildStyle> {
+    let top_level = paths
+        .iter()
+        .map(|path| path.as_ref().trim_start_matches("./"))
+        .filter(|path| path.trim_end_matches('/').matches('/').count() <= 1)
+        .map(|path| path.rsplit('/').next().unwrap_or(path))
+        .collect::<Vec<_>>();
+    BuildStyle::ALL
+        .into_iter()
+        .find(|style| top_level.contains(&style.marker()))
+}
+
+/// Guess the version from the file name of a release archive like `zlib-1.3.1.tar.xz`.
+fn guess_version(archive_url: &str) -> Option<String> {
+    const EXTENSIONS: [&str; 9] = [
+        ".tar.gz", ".tar.xz", ".tar.bz2", ".tar.zst", ".tar.lz", ".tgz", ".txz", ".zip", ".crate",
+    ];
+
+    let file_name = archive_url.rsplit('/').next()?;
+    let stem = EXTENSIONS
+        .iter()
+        .find_map(|ext| file_name.strip_suffix(ext))?;
+    let version = match stem.rsplit_once(['-', '_']) {
+        Some((_, version)) => version,
+        None => stem,
+    };
+    let version = version.trim_start_matches('v');
+    version
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then(|| version.to_string())
+}
+
+/// Ask a question on the terminal. An empty answer takes the default, without a default
+/// the question is asked again.
+fn prompt(question: &str, default: Option<&str>) -> miette::Result<String> {
+    loop {
+        let answer = prompt_optional(question, default)?;
+        if let Some(answer) = answer {
+            return Ok(answer);
+        }
+    }
+}
+
+fn prompt_optional(question: &str, default: Option<&str>) -> miette::Result<Option<String>> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    std::io::stdout().flush().into_diagnostic()?;
+
+    let mut answer = String::new();
+    if std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .into_diagnostic()?
+        == 0
+    {
+        return Err(miette::miette!(
+            "input ended before {} was answered",
+            question
+        ));
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() {
+        default.map(|d| d.to_string())
+    } else {
+        Some(answer.to_string())
+    })
+}
+
+async fn download_archive(url: &str) -> Result<Vec<u8>, RegistryError> {
+    let download_error = |source| RegistryError::Download {
+        url: url.to_string(),
+        source,
+    };
+    reqwest::Client::new()
+        .get(url)
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(download_error)?
+        .bytes()
+        .await
+        .map(|archive| archive.to_vec())
+        .map_err(download_error)
+}
+
+/// Download a source archive to get the sha256 its source instruction needs.
+pub(crate) async fn archive_sha256(url: &str) -> Result<String, RegistryError> {
+    Ok(hex::encode(Sha256::digest(&download_archive(url).await?)))
+}
+
+/// Download the source archive to suggest its checksum and build style.
+async fn probe_archive(url: &str) -> Result<(String, Option<BuildStyle>), RegistryError> {
+    let archive = download_archive(url).await?;
+    let sha256 = hex::encode(Sha256::digest(&archive));
+    let paths = compress_tools::list_archive_files(Cursor::new(&archive)).map_err(|source| {
+        RegistryError::UnreadableArchive {
+            url: url.to_string(),
+            source,
+        }
+    })?;
+    Ok((sha256, detect_build_style(&paths)))
+}
+
+/// Create a component from the answers to a few questions. The source archive is
+/// downloaded to suggest its sha256 and the build style the recipe is written with.
+async fn create_component_interactive(args: ComponentArgs) -> miette::Result<()> {
+    let fmri = prompt("Component name (e.g. library/zlib)", None)?;
+    let project_url = prompt_optional("Upstream project URL", None)?;
+    let archive_url = prompt("Source archive URL", None)?;
+
+    println!("Downloading {} to inspect it", archive_url);
+    let (suggested_sha256, detected_style) = match probe_archive(&archive_url).await {
+        Ok((sha256, style)) => (Some(sha256), style),
+        Err(RegistryError::UnreadableArchive { url, source }) => {
+            println!("{} could not be inspected: {}", url, source);
+            (None, None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let version = prompt("Version", guess_version(&archive_url).as_deref())?;
+    let sha256 = prompt("sha256 of the archive", suggested_sha256.as_deref())?;
+    let summary = prompt_optional("Summary", None)?;
+    let license = prompt_optional("License", None)?;
+
+    let styles = BuildStyle::ALL.map(|s| s.name()).join(", ");
+    let build_style = loop {
+        let answer = prompt(
+            &format!("Build style ({})", styles),
+            Some(detected_style.unwrap_or(BuildStyle::Configure).name()),
+        )?;
+        match BuildStyle::from_name(&answer) {
+            Some(style) => break style,
+            None => println!("{} is not one of {}", answer, styles),
+        }
+    };
+
+    let mut c = Component::new(fmri, Some(&args.component))?;
+    c.recipe.version = Some(version);
+    c.recipe.summary = summary;
+    c.recipe.license = license;
+    c.recipe.project_url = project_url;
+
+    c.recipe.sources.push(SourceSection {
+        name: None,
+        sources: vec![SourceNode::Archive(
+            ArchiveSourceBuilder::default()
+                .src(archive_url)
+                .sha256(sha256)
+                .build()?,
+        )],
+    });
+
+    if let Some(dependency) = build_style.build_dependency() {
+        c.recipe.dependencies.push(
+            DependencyBuilder::default()
+                .name(dependency)
+                .dev(true)
+                .build()?,
+        );
+    }
+
+    let mut build_section = BuildSectionBuilder::default();
+    match build_style {
+        BuildStyle::Configure => {
+            build_section.configure(ConfigureBuildSectionBuilder::default().build()?);
+        }
+        BuildStyle::Meson => {
+            build_section.meson("");
+        }
+        BuildStyle::Cmake => {
+            build_section.cmake("");
+        }
+        BuildStyle::Cargo => {
+            build_section.script(
+                ScriptBuildSectionBuilder::default()
+                    .script(ScriptNodeBuilder::default().name(BUILD_SCRIPT).build()?)
+                    .build()?,
+            );
+            let script_path = args.component.join(BUILD_SCRIPT);
+            fs::write(&script_path, Registry::CratesIo.build_script("")).into_diagnostic()?;
+            fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))
+                .into_diagnostic()?;
+        }
+    }
+    c.recipe.build_sections.push(build_section.build()?);
+
+    c.save_document()?;
+    println!(
+        "Created {} with a {} build, try it with pkgdev build",
+        c.recipe.name,
+        build_style.name()
+    );
+    Ok(())
+}
+
+/// Lowercase and URL safe names of at most 214 characters, optionally in a scope.
+fn is_npm_name(name: &str) -> bool {
+    let is_part = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with(['.', '_'])
+            && part
+                .chars()
+                .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '-' | '.' | '_' | '~'))
+    };
+    let unscoped = match name.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('/') {
+            Some((scope, unscoped)) if is_part(scope) => unscoped,
+            _ => return false,
+        },
+        None => name,
+    };
+    name.len() <= 214 && is_part(unscoped)
+}
+
+/// npm publishes a Subresource Integrity value, the archive source wants a hex digest.
+fn sha512_from_integrity(integrity: &str) -> Result<String, RegistryError> {
+    let unsupported = || RegistryError::UnsupportedIntegrity(integrity.to_string());
+    let encoded = integrity.strip_prefix("sha512-").ok_or_else(unsupported)?;
+    let digest = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| unsupported())?;
+    if digest.len() != 64 {
+        return Err(unsupported());
+    }
+    Ok(hex::encode(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_npm_integrity() {
+        // @alloc/quick-lru 5.2.0
+        let integrity = "sha512-UrcABB+4bUrFABwbluTIBErXwvbsU/V7TZWfmbgJfbkwiBuziS9gxdODUyuiecfdGQ85jglMW6juS3+z5TsKLw==";
+        assert_eq!(
+            sha512_from_integrity(integrity).unwrap(),
+            "52b700041fb86d4ac5001c1b96e4c8044ad7c2f6ec53f57b4d959f99b8097db9\
+             30881bb3892f60c5d383532ba279c7dd190f398e094c5ba8ee4b7fb3e53b0a2f"
+        );
+        assert!(sha512_from_integrity("sha1-UrcABB+4bUrFABwbluTIBErXwvbs").is_err());
+        assert!(sha512_from_integrity("sha512-not base64").is_err());
+        assert!(sha512_from_integrity("sha512-UrcABB+4bUrFABwb").is_err());
+    }
+
+    #[test]
+    fn accepts_only_npm_names() {
+        assert!(is_npm_name("left-pad"));
+        assert!(is_npm_name("@alloc/quick-lru"));
+        assert!(!is_npm_name("Left-Pad"));
+        assert!(!is_npm_name("@alloc/"));
+        assert!(!is_npm_name("@alloc"));
+        assert!(!is_npm_name("../etc"));
+        assert!(!is_npm_name("foo\"; rm -rf /; \""));
+        assert!(!is_npm_name("$(reboot)"));
+    }
+
+    #[test]
+    fn detects_build_style_at_top_of_archive() {
+        let autotools = [
+            "zlib-1.3.1/",
+            "zlib-1.3.1/configure",
+            "zlib-1.3.1/CMakeLists.txt",
+            "zlib-1.3.1/contrib/minizip/Makefile",
+        ];
+        assert_eq!(detect_build_style(&autotools), Some(BuildStyle::Configure));
+
+        let meson = ["./meson.build", "./src/main.c"];
+        assert_eq!(detect_build_style(&meson), Some(BuildStyle::Meson));
+
+        let nested = ["ripgrep-14.1.0/crates/cli/Cargo.toml"];
+        assert_eq!(detect_build_style(&nested), None);
+    }
+
+    #[test]
+    fn guesses_version_from_archive_name() {
+        assert_eq!(
+            guess_version("https://zlib.net/zlib-1.3.1.tar.xz").as_deref(),
+            Some("1.3.1")
+        );
+        assert_eq!(
+            guess_version("https://github.com/x/y/archive/refs/tags/v2.0.tar.gz").as_deref(),
+            Some("2.0")
+        );
+        assert_eq!(guess_version("https://example.org/source.tar.gz"), None);
+        assert_eq!(guess_version("https://example.org/download"), None);
+    }
+}
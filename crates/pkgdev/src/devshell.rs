@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::Parser;
+use component::Component;
+use config::Settings;
+use miette::{IntoDiagnostic, WrapErr};
+use workspace::Workspace;
+
+use crate::patch::append_patch;
+use crate::sources::unpack::unpack_sources;
+use crate::sources::{derive_source_name, download_sources};
+
+#[derive(Debug, Parser, Clone)]
+pub struct DevshellArgs {
+    #[arg(short, long, default_value = ".")]
+    pub component: PathBuf,
+
+    /// Name of the patch generated from the changes, numbered after the existing patches
+    #[arg(short, long, default_value = "devshell")]
+    pub name: String,
+
+    /// Shell to start in the sources, $SHELL or /bin/sh by default
+    #[arg(long)]
+    pub shell: Option<String>,
+}
+
+/// Run git in the unpacked sources without any of the settings of the user getting in the
+/// way of the throwaway repository.
+fn git(dir: &Path, args: &[&str]) -> miette::Result<Vec<u8>> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args([
+            "-c",
+            "user.name=pkgdev",
+            "-c",
+            "user.email=pkgdev@localhost",
+            "-c",
+            "commit.gpgsign=false",
+            "-c",
+            "core.autocrlf=false",
+        ])
+        .args(args)
+        .output()
+        .into_diagnostic()
+        .wrap_err("could not run git")?;
+    if !output.status.success() {
+        return Err(miette::miette!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Unpack the sources with the existing patches applied, start a shell in them and turn
+/// the changes made in the shell into a new patch of the component. The changes are
+/// tracked with a git repository that is removed again afterwards.
+pub async fn run_devshell(
+    args: DevshellArgs,
+    wks: &Workspace,
+    settings: &Settings,
+) -> miette::Result<()> {
+    let mut c = Component::open_local(&args.component)?;
+
+    download_sources(&c, wks, false, settings)
+        .await
+        .wrap_err("download and verify failed")?;
+
+    let unpack_path = wks
+        .get_or_create_build_dir()?
+        .join(derive_source_name(c.recipe.name.clone()));
+    if unpack_path.exists() {
+        println!(
+            "Removing {} to unpack the sources again",
+            unpack_path.display()
+        );
+        fs::remove_dir_all(&unpack_path).into_diagnostic()?;
+    }
+    unpack_sources(&c, wks, &c.recipe.sources).wrap_err("unpack step failed")?;
+
+    git(&unpack_path, &["init", "-q"])?;
+    git(&unpack_path, &["add", "-A"])?;
+    git(
+        &unpack_path,
+        &["commit", "-q", "--allow-empty", "-m", "patched sources"],
+    )?;
+
+    let shell = args
+        .shell
+        .or(std::env::var("SHELL").ok())
+        .unwrap_or(String::from("/bin/sh"));
+    println!(
+        "Starting {} in {}, exit it to turn your changes into a patch",
+        shell,
+        unpack_path.display()
+    );
+    let status = Command::new(&shell)
+        .current_dir(&unpack_path)
+        .status()
+        .into_diagnostic()
+        .wrap_err(format!("could not start {}", shell))?;
+    if !status.success() {
+        println!("{} exited with {}", shell, status);
+    }
+
+    git(&unpack_path, &["add", "-A"])?;
+    let diff = git(
+        &unpack_path,
+        &["diff", "--cached", "--no-color", "--no-ext-diff"],
+    )?;
+    fs::remove_dir_all(unpack_path.join(".git")).into_diagnostic()?;
+
+    if diff.is_empty() {
+        println!("No changes were made, the recipe stays as it is");
+        return Ok(());
+    }
+    let file_name = append_patch(&mut c, &args.name, &diff)?;
+    c.save_document()?;
+    println!("Added your changes as {}", file_name);
+    Ok(())
+}
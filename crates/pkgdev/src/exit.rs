@@ -0,0 +1,262 @@
+use std::error::Error as StdError;
+use std::io::ErrorKind;
+
+use ::component::ComponentError;
+pub use forge::FailureCategory;
+use gate::GateError;
+use mogrify::MogrifyError;
+use workspace::PatchError;
+
+use crate::build::{DepotError, ElfError, ToolError};
+use crate::create::RegistryError;
+use crate::metadata::RepologyError;
+
+/// The category of the first error in the chain of the report pkgdev knows how to blame.
+///
+/// Errors pkgdev raises itself without a dedicated type are almost all complaints about
+/// the recipe or a failed build step of it, so they count as user errors. Internal errors
+/// are reserved for panics, which pkgdev reports through [`install_panic_hook`].
+pub fn categorize(report: &miette::Report) -> FailureCategory {
+    report
+        .chain()
+        .find_map(categorize_error)
+        .unwrap_or(FailureCategory::User)
+}
+
+fn categorize_error(err: &(dyn StdError + 'static)) -> Option<FailureCategory> {
+    if let Some(err) = err.downcast_ref::<ToolError>() {
+        return Some(match err {
+            ToolError::Spawn { .. } => FailureCategory::Environment,
+            // pkg tools hang or fail to connect on unreachable repositories and origins
+            _ if err.is_transient() => FailureCategory::Transient,
+            _ => FailureCategory::User,
+        });
+    }
+    if let Some(err) = err.downcast_ref::<PatchError>() {
+        return Some(match err {
+            PatchError::Read { source, .. } => categorize_io(source),
+            PatchError::Spawn(_) => FailureCategory::Environment,
+            _ => FailureCategory::User,
+        });
+    }
+    if let Some(err) = err.downcast_ref::<ElfError>() {
+        return Some(match err {
+            ElfError::NotInstalledLibrary { .. } => FailureCategory::Environment,
+            _ => FailureCategory::User,
+        });
+    }
+    if let Some(err) = err.downcast_ref::<DepotError>() {
+        return match err {
+            // The request error is next in the chain
+            DepotError::Request { .. } => None,
+            DepotError::Status { status, .. } if status.is_server_error() => {
+                Some(FailureCategory::Transient)
+            }
+            DepotError::Status { .. }
+            | DepotError::MissingPayload { .. }
+            | DepotError::NoFmri(_) => Some(FailureCategory::User),
+            DepotError::MissingHeader { .. }
+            | DepotError::ReadOnly(_)
+            | DepotError::Identity { .. } => Some(FailureCategory::Environment),
+        };
+    }
+    if let Some(err) = err.downcast_ref::<RegistryError>() {
+        return match err {
+            // The request error is next in the chain
+            RegistryError::Request { .. } => None,
+            _ => Some(FailureCategory::User),
+        };
+    }
+    if let Some(err) = err.downcast_ref::<crate::forge::Error>() {
+        return match err {
+            crate::forge::Error::IO(err) => Some(categorize_io(err)),
+            crate::forge::Error::Reqwest(err) => Some(categorize_reqwest(err)),
+            crate::forge::Error::Octocrab(_) | crate::forge::Error::Progenitor(_) => {
+                Some(FailureCategory::Transient)
+            }
+            crate::forge::Error::PkgDevError(_) => None,
+            crate::forge::Error::NoForgeConnected
+            | crate::forge::Error::OAuthProviderNotConnected => Some(FailureCategory::Environment),
+            _ => Some(FailureCategory::User),
+        };
+    }
+    if let Some(err) = err.downcast_ref::<crate::Error>() {
+        return Some(match err {
+            crate::Error::NoHomeDefined => FailureCategory::Environment,
+            crate::Error::ComponentError(_) => FailureCategory::User,
+            crate::Error::StdFsError(err) => categorize_io(err),
+        });
+    }
+    if err.downcast_ref::<ComponentError>().is_some()
+        || err.downcast_ref::<GateError>().is_some()
+        || err.downcast_ref::<RepologyError>().is_some()
+        || err.downcast_ref::<MogrifyError>().is_some()
+    {
+        return Some(FailureCategory::User);
+    }
+    if err.downcast_ref::<config::ConfigError>().is_some() {
+        return Some(FailureCategory::Environment);
+    }
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return Some(categorize_reqwest(err));
+    }
+    if let Some(err) = err.downcast_ref::<reqwest_12::Error>() {
+        return Some(
+            if err.status().is_some_and(|s| s.is_client_error()) || err.is_builder() {
+                FailureCategory::User
+            } else {
+                FailureCategory::Transient
+            },
+        );
+    }
+    if let Some(err) = err.downcast_ref::<std::io::Error>() {
+        return Some(categorize_io(err));
+    }
+    None
+}
+
+/// Answers other than 4xx are the server or the network acting up.
+fn categorize_reqwest(err: &reqwest::Error) -> FailureCategory {
+    if err.status().is_some_and(|s| s.is_client_error()) || err.is_builder() {
+        FailureCategory::User
+    } else {
+        FailureCategory::Transient
+    }
+}
+
+fn categorize_io(err: &std::io::Error) -> FailureCategory {
+    match err.kind() {
+        ErrorKind::NotFound | ErrorKind::InvalidInput | ErrorKind::InvalidData => {
+            FailureCategory::User
+        }
+        ErrorKind::TimedOut
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::Interrupted => FailureCategory::Transient,
+        _ => FailureCategory::Environment,
+    }
+}
+
+/// Exit with the code of an internal error on panics, after the default hook printed
+/// the panic.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        std::process::exit(FailureCategory::Internal.exit_code() as i32);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use miette::WrapErr;
+
+    use super::*;
+
+    fn failed(stderr_tail: &str) -> ToolError {
+        ToolError::Failed {
+            tool: String::from("pkgrecv"),
+            args: String::from("-s https://pkg.example.org"),
+            exit_code: Some(1),
+            stderr_tail: stderr_tail.to_string(),
+        }
+    }
+
+    fn io(kind: ErrorKind) -> crate::Error {
+        crate::Error::StdFsError(std::io::Error::from(kind))
+    }
+
+    #[test]
+    fn errors_are_categorized() {
+        let cases: Vec<(miette::Report, FailureCategory)> = vec![
+            (
+                miette::Report::new(ToolError::Spawn {
+                    tool: String::from("pkgsend"),
+                    source: std::io::Error::from(ErrorKind::NotFound),
+                }),
+                FailureCategory::Environment,
+            ),
+            (
+                miette::Report::new(ToolError::TimedOut {
+                    tool: String::from("pkgrecv"),
+                    args: String::new(),
+                    timeout: Duration::from_secs(1800),
+                    stderr_tail: String::new(),
+                }),
+                FailureCategory::Transient,
+            ),
+            (
+                miette::Report::new(failed("pkgrecv: Framework error: code: 7")),
+                FailureCategory::Transient,
+            ),
+            (
+                miette::Report::new(failed("pkgrecv: no matching packages")),
+                FailureCategory::User,
+            ),
+            (
+                miette::Report::new(ElfError::NotInstalledLibrary {
+                    object: String::from("usr/bin/foo"),
+                    library: String::from("libfoo.so.1"),
+                    provided_by: String::from("library/foo"),
+                }),
+                FailureCategory::Environment,
+            ),
+            (
+                miette::Report::new(ElfError::UnresolvedLibrary {
+                    object: String::from("usr/bin/foo"),
+                    library: String::from("libfoo.so.1"),
+                    searched: vec![],
+                }),
+                FailureCategory::User,
+            ),
+            (
+                miette::Report::new(crate::Error::NoHomeDefined),
+                FailureCategory::Environment,
+            ),
+            (
+                miette::Report::new(io(ErrorKind::ConnectionRefused)),
+                FailureCategory::Transient,
+            ),
+            (
+                miette::Report::new(io(ErrorKind::PermissionDenied)),
+                FailureCategory::Environment,
+            ),
+            (
+                miette::Report::new(io(ErrorKind::NotFound)),
+                FailureCategory::User,
+            ),
+            (
+                miette::miette!("recipe has no sources"),
+                FailureCategory::User,
+            ),
+        ];
+        for (report, category) in cases {
+            assert_eq!(categorize(&report), category, "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn context_does_not_hide_the_category() {
+        let report = Err::<(), _>(io(ErrorKind::ConnectionReset))
+            .wrap_err("could not fetch the sources")
+            .unwrap_err();
+        assert_eq!(categorize(&report), FailureCategory::Transient);
+    }
+
+    #[test]
+    fn exit_codes_follow_sysexits() {
+        for (category, code) in [
+            (FailureCategory::User, 65),
+            (FailureCategory::Environment, 69),
+            (FailureCategory::Transient, 75),
+            (FailureCategory::Internal, 70),
+        ] {
+            assert_eq!(category.exit_code(), code);
+            assert_eq!(FailureCategory::from_exit_code(code as i32), Some(category));
+        }
+        assert_eq!(FailureCategory::from_exit_code(1), None);
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use clap::{Subcommand, ValueEnum};
 use miette::Diagnostic;
@@ -10,7 +11,8 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use url::{ParseError, Url};
 
-use component::{Component, ComponentError};
+use component::{Component, ComponentError, SourceNode};
+use config::{ForgeProfile, ForgeToken, Settings};
 use forge::AuthConfig;
 use gate::{Gate, GateError};
 
@@ -33,6 +35,39 @@ pub enum ForgeArgs {
         handle: String,
         #[arg(short, long)]
         display_name: Option<String>,
+        /// Name of the profile the forge is stored as, its host if not given
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Make forge commands use the forge of a profile like `staging` or `production`
+    Use {
+        profile: String,
+    },
+    /// Create or change a forge profile
+    Profile {
+        name: String,
+        /// Base url of the forge
+        #[arg(long)]
+        url: Option<String>,
+        /// Id of the gate commands use when not given one
+        #[arg(long)]
+        default_gate: Option<String>,
+        /// Publisher commands use when not given one
+        #[arg(long)]
+        default_publisher: Option<String>,
+    },
+    /// Renew the login to a forge. Without `--device` the stored refresh token is used
+    Login {
+        /// Url of the forge, the selected one if not given
+        target: Option<String>,
+        /// Log in again with the device flow of the provider, for builders without a browser
+        #[arg(long)]
+        device: bool,
+        #[arg(short, long, default_value = "github")]
+        provider: LoginProvider,
+        /// Handle on the forge, the one of the last login if not given
+        #[arg(long)]
+        handle: Option<String>,
     },
     DefineGate {
         #[arg(short, long)]
@@ -59,6 +94,120 @@ pub enum ForgeArgs {
         #[arg(short, long)]
         file: Option<PathBuf>,
     },
+    /// Watch the build jobs of the forge
+    Jobs {
+        #[command(subcommand)]
+        command: JobCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum JobCommand {
+    /// List the jobs of the forge, newest first
+    List {
+        /// Only the jobs of the change request, like the url of its pull request
+        #[arg(long)]
+        change_request: Option<String>,
+        /// Only the jobs of the gate with this id
+        #[arg(long)]
+        gate: Option<String>,
+        #[arg(long)]
+        state: Option<JobState>,
+    },
+    /// Show a job and how it ended
+    Show { id: String },
+    /// Print the output of a job
+    Logs {
+        id: String,
+        /// Keep printing the output of the running job until it finished
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Queue a failed or cancelled job again
+    Retry { id: String },
+}
+
+#[derive(Debug, ValueEnum, Clone, Copy, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum JobState {
+    Pending,
+    Running,
+    Finished,
+}
+
+/// A job as the jobs API of forged returns it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub id: String,
+    pub reference: String,
+    pub kind: String,
+    pub state: JobState,
+    pub gate_id: Option<String>,
+    pub staging: Option<String>,
+    /// success, failure or cancelled once the job finished
+    pub outcome: Option<String>,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub dispatched_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+/// Where a component the actor maintains stands, see the status endpoint of the gates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintainedComponentStatus {
+    pub name: String,
+    pub version: String,
+    pub revision: String,
+    pub upstream_version: Option<String>,
+    pub outdated: bool,
+    pub open_change_requests: Vec<String>,
+    pub last_build: Option<QueuedJob>,
+}
+
+/// A component added, updated or removed, see the changelog endpoint of the gates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangelogEntry {
+    pub name: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub change_requests: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Changelog {
+    pub gate: String,
+    pub from: String,
+    pub to: String,
+    pub added: Vec<ChangelogEntry>,
+    pub updated: Vec<ChangelogEntry>,
+    pub removed: Vec<ChangelogEntry>,
+    pub markdown: String,
+}
+
+/// Either two snapshots or a time range the changelog of a gate covers.
+#[derive(Debug, Default, Serialize)]
+pub struct ChangelogQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_snapshot: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_snapshot: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobLogLine {
+    pub number: i32,
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobLog {
+    pub lines: Vec<JobLogLine>,
+    pub finished: bool,
 }
 
 #[derive(Debug, ValueEnum, Clone)]
@@ -121,11 +270,37 @@ pub enum Error {
     #[error("login aborted")]
     LoginAborted,
 
+    #[error("login to {0} expired")]
+    #[diagnostic(help("log in again with pkgdev forge login --device"))]
+    LoginExpired(String),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Config(#[from] config::ConfigError),
+
     #[error(transparent)]
     Progenitor(#[from] progenitor_client::Error<types::ApiError>),
 
     #[error("usage error either file or url must be provided")]
     UploadUsageError,
+
+    #[error("forge answered {status}: {error:?}")]
+    ForgeRequest {
+        status: reqwest::StatusCode,
+        error: types::ApiError,
+    },
+
+    #[error("forge has no recipe digest recorded for {0}")]
+    #[diagnostic(help("import the component into the forge or update the forge"))]
+    OriginUnknown(String),
+
+    #[error("local recipe of {component} does not match the one recorded in the forge: local {local} recorded {recorded}")]
+    #[diagnostic(help("update the checkout to the recorded version of the component or import the local changes"))]
+    OriginMismatch {
+        component: String,
+        local: String,
+        recorded: String,
+    },
 }
 
 pub type Result<T, E = Error> = miette::Result<T, E>;
@@ -279,8 +454,170 @@ pub fn save_forge_config(forge_config: &mut ForgeConfig) -> Result<(), Error> {
     Ok(())
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Unix time of an RFC 3339 time like the token expiries forged sends.
+fn unix_time(time: &str) -> Option<u64> {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .ok()
+        .and_then(|t| u64::try_from(t.timestamp()).ok())
+}
+
+/// Log in to the provider with its device flow and connect the actor to the forge at
+/// `target` with the token the provider issued. The token is stored in the forge profile
+/// with the url `target` or a new profile named `profile`.
+async fn connect_actor(
+    forge_config: &mut ForgeConfig,
+    settings: &mut Settings,
+    target: &str,
+    provider: &LoginProvider,
+    handle: &str,
+    display_name: Option<String>,
+    select: bool,
+    profile: Option<&str>,
+) -> Result<()> {
+    let target_url: Url = target.parse()?;
+    let host = target_url
+        .host_str()
+        .ok_or(Error::MissingParameter(String::from(
+            "no host in forge URL",
+        )))?;
+
+    let login_info = get_oauth_login_info(&target_url).await?;
+
+    let token = openid::login_to_provider(provider, &login_info).await?;
+    let forge_client = Client::new(target);
+
+    let resp = forge_client
+        .actor_connect(&types::ActorConnectRequest::GitHub {
+            display_name,
+            handle: handle.to_string(),
+            ssh_keys: vec![],
+            token: token.access_token.expose_secret().clone(),
+        })
+        .await?;
+
+    forge_config.insert_forge(
+        host.to_string(),
+        target.to_string(),
+        resp.handle.clone(),
+        resp.access_token.clone(),
+        resp.refresh_token.clone(),
+        resp.ssh_keys.clone(),
+        select,
+    );
+    save_forge_config(forge_config)?;
+
+    settings.set_forge_token(
+        profile.unwrap_or(host),
+        ForgeToken {
+            target: target.to_string(),
+            handle: Some(resp.handle.clone()),
+            access_token: resp.access_token.clone(),
+            refresh_token: Some(resp.refresh_token.clone()),
+            scope: None,
+            expires_in: None,
+            expires_at: unix_time(&resp.access_token_expires_at),
+        },
+    )?;
+    if select {
+        if let Some(profile) = settings.forges.iter().find(|p| p.url == target) {
+            let name = profile.name.clone();
+            settings.use_forge_profile(&name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Exchange the refresh token of the forge at `target` for new tokens. Refresh tokens
+/// are used once, so both the old access and refresh token are replaced.
+async fn refresh_forge_token(
+    forge_config: &mut ForgeConfig,
+    settings: &mut Settings,
+    target: &str,
+) -> Result<()> {
+    let (host, connection) = forge_config
+        .forges
+        .iter_mut()
+        .find(|(_, c)| c.target == target)
+        .ok_or(Error::NoForgeConnected)?;
+    let host = host.clone();
+    let stored = settings.get_forge_token(target).cloned();
+    let refresh_token = stored
+        .as_ref()
+        .and_then(|t| t.refresh_token.clone())
+        .unwrap_or(connection.refresh_token.clone());
+
+    let resp = match Client::new(target)
+        .refresh_token(&types::RefreshRequest { refresh_token })
+        .await
+    {
+        Ok(resp) => resp.into_inner(),
+        Err(progenitor_client::Error::ErrorResponse(resp)) if resp.status().as_u16() == 401 => {
+            return Err(Error::LoginExpired(target.to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    connection.access_token = resp.access_token.clone();
+    connection.refresh_token = resp.refresh_token.clone();
+    let handle = connection.handle.clone();
+    save_forge_config(forge_config)?;
+
+    settings.set_forge_token(
+        &host,
+        ForgeToken {
+            target: target.to_string(),
+            handle: Some(handle),
+            access_token: resp.access_token,
+            refresh_token: Some(resp.refresh_token),
+            scope: stored.and_then(|t| t.scope),
+            expires_in: None,
+            expires_at: unix_time(&resp.access_token_expires_at),
+        },
+    )?;
+    Ok(())
+}
+
+/// Refresh the token of the selected forge when it is about to expire, so commands do not
+/// fail halfway through with an expired login.
+pub async fn refresh_selected_forge(forge_config: &mut ForgeConfig) -> Result<()> {
+    let Some(connection) = forge_config.get_selected_config() else {
+        return Ok(());
+    };
+    let mut settings = Settings::open()?;
+    if settings
+        .get_forge_token(&connection.target)
+        .is_some_and(|t| t.needs_refresh(unix_now()))
+    {
+        refresh_forge_token(forge_config, &mut settings, &connection.target).await?;
+    }
+    Ok(())
+}
+
+/// The client of the selected forge with a fresh token.
+pub(crate) async fn selected_client() -> Result<Client> {
+    let mut forge_config = get_forge_config()?;
+    refresh_selected_forge(&mut forge_config).await?;
+    forge_config.get_selected().ok_or(Error::NoForgeConnected)
+}
+
 pub async fn handle_forge_interaction(args: &ForgeArgs) -> Result<()> {
     let mut forge_config = get_forge_config()?;
+    if matches!(
+        args,
+        ForgeArgs::DefineGate { .. }
+            | ForgeArgs::ImportComponent { .. }
+            | ForgeArgs::UploadFile { .. }
+            | ForgeArgs::Jobs { .. }
+    ) {
+        refresh_selected_forge(&mut forge_config).await?;
+    }
     let forge_client = forge_config.get_selected();
 
     match args {
@@ -295,6 +632,9 @@ pub async fn handle_forge_interaction(args: &ForgeArgs) -> Result<()> {
                 return Err(Error::NoForgeConnected);
             }
             let forge_client = forge_client.unwrap();
+            let publisher = publisher.clone().or(Settings::open()?
+                .get_current_forge_profile()
+                .and_then(|p| p.default_publisher.clone()));
             let gate: Option<Gate> = if let Some(file) = file {
                 if file.exists() {
                     Some(Gate::new(file)?)
@@ -352,44 +692,113 @@ pub async fn handle_forge_interaction(args: &ForgeArgs) -> Result<()> {
             provider,
             handle,
             display_name,
+            profile,
         } => {
-            let target_url: Url = target.parse()?;
-            let host = target_url.host_str();
-            if host.is_none() {
-                return Err(Error::MissingParameter(String::from(
-                    "no host in forge URL",
-                )));
-            }
-
-            let host = host.unwrap();
+            let mut settings = Settings::open()?;
+            connect_actor(
+                &mut forge_config,
+                &mut settings,
+                target,
+                provider,
+                handle,
+                display_name.clone(),
+                *select,
+                profile.as_deref(),
+            )
+            .await?;
 
-            let login_info = get_oauth_login_info(&target_url).await?;
+            println!("connected");
 
-            let token = openid::login_to_provider(provider, &login_info).await?;
-            let forge_client = Client::new(target);
+            Ok(())
+        }
+        ForgeArgs::Use { profile } => {
+            let mut settings = Settings::open()?;
+            let url = settings.use_forge_profile(profile)?.url.clone();
+            // The connection of the forge holds the tokens the commands authenticate with
+            match forge_config.forges.iter().find(|(_, c)| c.target == url) {
+                Some((host, _)) => {
+                    forge_config.selected_forge = Some(host.clone());
+                    save_forge_config(&mut forge_config)?;
+                    println!("using forge {} at {}", profile, url);
+                }
+                None => println!(
+                    "using forge {} at {}, connect to it with pkgdev forge connect {}",
+                    profile, url, url
+                ),
+            }
+            Ok(())
+        }
+        ForgeArgs::Profile {
+            name,
+            url,
+            default_gate,
+            default_publisher,
+        } => {
+            let mut settings = Settings::open()?;
+            let mut profile = settings
+                .get_forge_profile(name)
+                .cloned()
+                .unwrap_or(ForgeProfile {
+                    name: name.clone(),
+                    ..Default::default()
+                });
+            if let Some(url) = url {
+                profile.url = url.clone();
+            }
+            if profile.url.is_empty() {
+                return Err(Error::MissingParameter(String::from("url")));
+            }
+            if default_gate.is_some() {
+                profile.default_gate = default_gate.clone();
+            }
+            if default_publisher.is_some() {
+                profile.default_publisher = default_publisher.clone();
+            }
+            settings.set_forge_profile(profile)?;
+            Ok(())
+        }
+        ForgeArgs::Login {
+            target,
+            device,
+            provider,
+            handle,
+        } => {
+            let mut settings = Settings::open()?;
+            let connection = match target {
+                Some(target) => forge_config
+                    .forges
+                    .values()
+                    .find(|c| &c.target == target)
+                    .cloned(),
+                None => forge_config.get_selected_config(),
+            };
+            let target = target
+                .clone()
+                .or(connection.as_ref().map(|c| c.target.clone()))
+                .ok_or(Error::NoForgeConnected)?;
 
-            let resp = forge_client
-                .actor_connect(&types::ActorConnectRequest::GitHub {
-                    display_name: display_name.clone(),
-                    handle: handle.to_string(),
-                    ssh_keys: vec![],
-                    token: token.access_token.expose_secret().clone(),
-                })
+            if *device {
+                let handle = handle
+                    .clone()
+                    .or(connection.map(|c| c.handle))
+                    .ok_or(Error::MissingParameter(String::from("handle")))?;
+                let select = forge_config.selected_forge.is_none();
+                connect_actor(
+                    &mut forge_config,
+                    &mut settings,
+                    &target,
+                    provider,
+                    &handle,
+                    None,
+                    select,
+                    None,
+                )
                 .await?;
+            } else {
+                refresh_forge_token(&mut forge_config, &mut settings, &target).await?;
+            }
 
-            forge_config.insert_forge(
-                host.to_string(),
-                target.clone(),
-                resp.handle.clone(),
-                resp.access_token.clone(),
-                resp.refresh_token.clone(),
-                resp.ssh_keys.clone(),
-                *select,
-            );
-
-            save_forge_config(&mut forge_config)?;
-
-            println!("connected");
+            println!("logged in to {}", target);
 
             Ok(())
         }
@@ -418,6 +827,12 @@ pub async fn handle_forge_interaction(args: &ForgeArgs) -> Result<()> {
                 (None, None)
             };
 
+            // Forged suggests a classification from the topics of the upstream
+            let topics = match (&component.recipe.classification, &component.recipe.project_url) {
+                (None, Some(project_url)) => github_topics(project_url).await?,
+                _ => vec![],
+            };
+
             let recipe_value = serde_json::to_value(&component.recipe)?;
             let package_meta_value =
                 serde_json::to_value(&component.package_meta.unwrap_or_default())?;
@@ -429,6 +844,7 @@ pub async fn handle_forge_interaction(args: &ForgeArgs) -> Result<()> {
                     packages: serde_json::from_value(package_meta_value)?,
                     recipe: serde_json::from_value(recipe_value)?,
                     repology_id,
+                    topics,
                 })
                 .await?;
 
@@ -513,12 +929,268 @@ pub async fn handle_forge_interaction(args: &ForgeArgs) -> Result<()> {
 
             Ok(())
         }
+        ForgeArgs::Jobs { command } => {
+            let forge_client = forge_client.ok_or(Error::NoForgeConnected)?;
+            handle_job_command(&forge_client, command).await
+        }
     }
 }
 
+/// How often `--follow` asks for new output of a running job.
+const FOLLOW_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+async fn handle_job_command(forge_client: &Client, command: &JobCommand) -> Result<()> {
+    match command {
+        JobCommand::List {
+            change_request,
+            gate,
+            state,
+        } => {
+            let mut query = vec![];
+            if let Some(change_request) = change_request {
+                query.push(("change_request", change_request.clone()));
+            }
+            if let Some(gate) = gate {
+                query.push(("gate_id", gate.clone()));
+            }
+            if let Some(state) = state {
+                query.push(("state", state.to_string()));
+            }
+            let jobs: Vec<QueuedJob> = forge_request(
+                forge_client
+                    .client()
+                    .get(format!("{}/api/v1/jobs/", forge_client.baseurl()))
+                    .query(&query),
+            )
+            .await?;
+            for job in jobs {
+                println!(
+                    "{} {:<8} {:<10} {} {}",
+                    job.id,
+                    job.state,
+                    job.outcome.unwrap_or_default(),
+                    job.kind,
+                    job.reference
+                );
+            }
+            Ok(())
+        }
+        JobCommand::Show { id } => {
+            let job: QueuedJob = forge_request(forge_client.client().get(format!(
+                "{}/api/v1/jobs/{}",
+                forge_client.baseurl(),
+                id
+            )))
+            .await?;
+            print_job(&job);
+            Ok(())
+        }
+        JobCommand::Logs { id, follow } => {
+            let mut after = None;
+            loop {
+                let mut request = forge_client.client().get(format!(
+                    "{}/api/v1/jobs/{}/logs",
+                    forge_client.baseurl(),
+                    id
+                ));
+                if let Some(after) = after {
+                    request = request.query(&[("after", after)]);
+                }
+                let log: JobLog = forge_request(request).await?;
+                let caught_up = log.lines.is_empty();
+                for line in log.lines {
+                    println!("{}", line.content);
+                    after = Some(line.number);
+                }
+                // Logs longer than a page are read on until the last line
+                if caught_up {
+                    if !follow || log.finished {
+                        break;
+                    }
+                    tokio::time::sleep(FOLLOW_INTERVAL).await;
+                }
+            }
+            Ok(())
+        }
+        JobCommand::Retry { id } => {
+            let job: QueuedJob = forge_request(forge_client.client().post(format!(
+                "{}/api/v1/jobs/{}/retry",
+                forge_client.baseurl(),
+                id
+            )))
+            .await?;
+            println!("queued {} again as {}", id, job.id);
+            Ok(())
+        }
+    }
+}
+
+fn print_job(job: &QueuedJob) {
+    println!("id:         {}", job.id);
+    println!("kind:       {}", job.kind);
+    println!("reference:  {}", job.reference);
+    println!("state:      {}", job.state);
+    if let Some(outcome) = &job.outcome {
+        println!("outcome:    {}", outcome);
+    }
+    if let Some(error) = &job.error {
+        println!("error:      {}", error);
+    }
+    if let Some(gate_id) = &job.gate_id {
+        println!("gate:       {}", gate_id);
+    }
+    if let Some(staging) = &job.staging {
+        println!("staging:    {}", staging);
+    }
+    println!("queued:     {}", job.created_at);
+    if let Some(dispatched_at) = &job.dispatched_at {
+        println!("dispatched: {}", dispatched_at);
+    }
+    if let Some(finished_at) = &job.finished_at {
+        println!("finished:   {}", finished_at);
+    }
+}
+
+/// Send a request to an endpoint the generated client does not know yet.
+pub(crate) async fn forge_request<T: serde::de::DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+) -> Result<T> {
+    let resp = request.send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(Error::ForgeRequest {
+            status,
+            error: resp.json().await?,
+        });
+    }
+    Ok(resp.json().await?)
+}
+
+/// The topics of a project hosted on GitHub, none for projects hosted elsewhere.
+pub async fn github_topics(project_url: &str) -> Result<Vec<String>> {
+    let Ok(url) = Url::parse(project_url) else {
+        return Ok(vec![]);
+    };
+    if url.host_str() != Some("github.com") {
+        return Ok(vec![]);
+    }
+    let mut segments = url.path_segments().into_iter().flatten();
+    let (Some(owner), Some(repo)) = (segments.next(), segments.next()) else {
+        return Ok(vec![]);
+    };
+    let repository = octocrab::instance()
+        .repos(owner, repo.trim_end_matches(".git"))
+        .get()
+        .await?;
+    Ok(repository.topics.unwrap_or_default())
+}
+
 pub async fn get_oauth_login_info(target: &Url) -> Result<AuthConfig, Error> {
     let resp = reqwest::get(target.join("/api/v1/auth/login_info")?).await?;
 
     let login_info: AuthConfig = resp.json().await?;
     Ok(login_info)
 }
+
+/// Ask the selected forge which components deliver `libraries`.
+pub async fn find_library_providers(
+    gate_id: Option<String>,
+    libraries: Vec<String>,
+) -> Result<Vec<types::LibraryProvider>> {
+    let forge_client = selected_client().await?;
+    let gate_id = gate_id.or(Settings::open()?
+        .get_current_forge_profile()
+        .and_then(|p| p.default_gate.clone()));
+    let providers = forge_client
+        .find_library_providers(&types::FindLibraryProvidersRequest { gate_id, libraries })
+        .await?;
+    Ok(providers.into_inner())
+}
+
+/// Ask the selected forge where the components the actor maintains in the gate stand. The
+/// default gate of the forge profile is used if no gate is given.
+pub async fn maintainer_status(gate_id: Option<String>) -> Result<Vec<MaintainedComponentStatus>> {
+    let forge_client = selected_client().await?;
+    let gate_id = gate_or_default(gate_id)?;
+    forge_request(forge_client.client().get(format!(
+        "{}/api/v1/gates/{}/status",
+        forge_client.baseurl(),
+        gate_id
+    )))
+    .await
+}
+
+/// Ask the selected forge for the changelog of the gate, the default gate of the forge
+/// profile if no gate is given.
+pub async fn gate_changelog(gate_id: Option<String>, query: &ChangelogQuery) -> Result<Changelog> {
+    let forge_client = selected_client().await?;
+    let gate_id = gate_or_default(gate_id)?;
+    forge_request(
+        forge_client
+            .client()
+            .get(format!(
+                "{}/api/v1/gates/{}/changelog",
+                forge_client.baseurl(),
+                gate_id
+            ))
+            .query(query),
+    )
+    .await
+}
+
+fn gate_or_default(gate_id: Option<String>) -> Result<String> {
+    gate_id
+        .or(Settings::open()?
+            .get_current_forge_profile()
+            .and_then(|p| p.default_gate.clone()))
+        .ok_or(Error::MissingParameter(String::from("gate")))
+}
+
+/// Compare the recipe and the patches of the local `component` with the digest the
+/// selected forge recorded for it, so tampered or stale checkouts are not built.
+pub async fn verify_component_origin(component: &Component, gate: &Gate) -> Result<()> {
+    let forge_client = selected_client().await?;
+    let gate_id = gate.id.clone().ok_or(Error::GateNoId)?;
+    let recipe = &component.recipe;
+    let version = recipe
+        .version
+        .clone()
+        .ok_or(Error::ComponentIncomplete(String::from("version")))?;
+    let revision = recipe.revision.clone().unwrap_or(String::from("0"));
+
+    let recorded = forge_client
+        .get_component(&types::GetComponentRequest {
+            gate_id,
+            name: recipe.name.clone(),
+            revision,
+            version,
+        })
+        .await?
+        .into_inner()
+        .recipe_digest
+        .ok_or(Error::OriginUnknown(recipe.name.clone()))?;
+
+    let mut patches = vec![];
+    for section in &recipe.sources {
+        for source in &section.sources {
+            if let SourceNode::Patch(patch) = source {
+                let content = std::fs::read(patch.get_bundle_path(component.get_path()))?;
+                patches.push((patch.to_string(), content));
+            }
+        }
+    }
+    let local = recipe.digest(
+        patches
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_slice())),
+    );
+
+    if local != recorded {
+        return Err(Error::OriginMismatch {
+            component: recipe.name.clone(),
+            local,
+            recorded,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,625 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+use component::{
+    ArchiveSourceBuilder, BuildSectionBuilder, Component, ConfigureBuildSectionBuilder, Dependency,
+    DependencyBuilder, FileSource, PatchSource, ScriptBuildSectionBuilder, ScriptNodeBuilder,
+    SourceNode, SourceSection,
+};
+use miette::{Diagnostic, IntoDiagnostic};
+use thiserror::Error;
+
+use crate::args::ComponentArgs;
+use crate::create::archive_sha256;
+
+const BUILD_SCRIPT: &str = "build.sh";
+
+/// Sections of a spec file that end the preamble of the main package.
+const SECTIONS: [&str; 16] = [
+    "description",
+    "package",
+    "prep",
+    "build",
+    "install",
+    "check",
+    "files",
+    "changelog",
+    "pre",
+    "post",
+    "preun",
+    "postun",
+    "pretrans",
+    "posttrans",
+    "generate_buildrequires",
+    "conf",
+];
+
+/// How the RPM directory macros are laid out in the prototype directory.
+const RPM_MACROS: [(&str, &str); 21] = [
+    ("buildroot", "${PROTO_DIR}"),
+    ("_prefix", "/usr"),
+    ("_exec_prefix", "/usr"),
+    ("_bindir", "/usr/bin"),
+    ("_sbindir", "/usr/sbin"),
+    ("_libdir", "/usr/lib"),
+    ("_libexecdir", "/usr/libexec"),
+    ("_includedir", "/usr/include"),
+    ("_datadir", "/usr/share"),
+    ("_mandir", "/usr/share/man"),
+    ("_infodir", "/usr/share/info"),
+    ("_docdir", "/usr/share/doc"),
+    ("_sysconfdir", "/etc"),
+    ("_localstatedir", "/var"),
+    ("_sharedstatedir", "/var/lib"),
+    ("_smp_mflags", ""),
+    ("optflags", ""),
+    ("set_build_flags", ""),
+    ("configure", "./configure --prefix=/usr"),
+    ("make_build", "make"),
+    ("make_install", "make install DESTDIR=\"${PROTO_DIR}\""),
+];
+
+#[derive(Debug, Subcommand)]
+pub enum ImportFrom {
+    /// Import a component from the spec file of an RPM package
+    #[clap(name = "spec")]
+    Spec {
+        file: PathBuf,
+        /// Name of the component, the Name of the spec file by default
+        #[clap(long)]
+        fmri: Option<String>,
+        #[clap(flatten)]
+        args: ComponentArgs,
+    },
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ImportError {
+    #[error("could not read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("the spec file has no {0} tag")]
+    #[diagnostic(help("only the preamble of the main package is read"))]
+    MissingTag(&'static str),
+}
+
+/// What a spec file says about the main package.
+#[derive(Debug, Default)]
+struct RpmSpec {
+    macros: HashMap<String, String>,
+    tags: HashMap<String, String>,
+    sources: Vec<(u32, String)>,
+    patches: Vec<(u32, String)>,
+    build_requires: Vec<String>,
+    requires: Vec<String>,
+    sections: HashMap<String, Vec<String>>,
+}
+
+/// Package names of a Requires or BuildRequires tag without their version constraints.
+fn dependency_names(value: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut tokens = value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty());
+    while let Some(token) = tokens.next() {
+        if token.starts_with(['<', '>', '=']) {
+            tokens.next();
+        } else {
+            names.push(token.to_string());
+        }
+    }
+    names
+}
+
+/// The number of a numbered tag like Source1, tags without a number are number 0.
+fn tag_number(tag: &str, prefix: &str) -> Option<u32> {
+    let number = tag.strip_prefix(prefix)?;
+    if number.is_empty() {
+        Some(0)
+    } else {
+        number.parse().ok()
+    }
+}
+
+impl RpmSpec {
+    fn parse(content: &str) -> Self {
+        let mut spec = RpmSpec::default();
+        let mut section: Option<String> = None;
+
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if let Some(definition) = trimmed
+                .strip_prefix("%global ")
+                .or(trimmed.strip_prefix("%define "))
+            {
+                if let Some((name, value)) = definition.trim().split_once(char::is_whitespace) {
+                    spec.macros
+                        .insert(name.to_string(), value.trim().to_string());
+                }
+                continue;
+            }
+
+            if let Some(keyword) = trimmed.strip_prefix('%') {
+                let keyword = keyword.split_whitespace().next().unwrap_or_default();
+                if SECTIONS.contains(&keyword) {
+                    section = Some(keyword.to_string());
+                    continue;
+                }
+            }
+
+            match &section {
+                None => spec.parse_tag(trimmed),
+                Some(section) => spec
+                    .sections
+                    .entry(section.clone())
+                    .or_default()
+                    .push(line.to_string()),
+            }
+        }
+
+        spec.sources.sort();
+        spec.patches.sort();
+        spec
+    }
+
+    fn parse_tag(&mut self, line: &str) {
+        if line.starts_with('#') {
+            return;
+        }
+        let Some((tag, value)) = line.split_once(':') else {
+            return;
+        };
+        // Tags like Requires(post) only matter to the scriptlets
+        if tag.is_empty() || !tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return;
+        }
+        let tag = tag.to_lowercase();
+        let value = value.trim().to_string();
+
+        if let Some(number) = tag_number(&tag, "source") {
+            self.sources.push((number, value));
+        } else if let Some(number) = tag_number(&tag, "patch") {
+            self.patches.push((number, value));
+        } else if tag == "buildrequires" {
+            self.build_requires.extend(dependency_names(&value));
+        } else if tag == "requires" {
+            self.requires.extend(dependency_names(&value));
+        } else {
+            self.tags.entry(tag).or_insert(value);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        match name {
+            "name" | "version" | "release" => self.tags.get(name).cloned(),
+            _ => self.macros.get(name).cloned().or_else(|| {
+                RPM_MACROS
+                    .iter()
+                    .find(|(macro_name, _)| *macro_name == name)
+                    .map(|(_, value)| value.to_string())
+            }),
+        }
+    }
+
+    /// Expand `%name`, `%{name}`, `%{?name}` and `%{?name:value}` macros. Unknown macros
+    /// are kept as they are so they stand out in the recipe.
+    fn expand(&self, text: &str) -> String {
+        self.expand_depth(text, 0)
+    }
+
+    fn expand_depth(&self, text: &str, depth: usize) -> String {
+        if depth > 16 {
+            return text.to_string();
+        }
+
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('%') {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+
+            if let Some(after) = after.strip_prefix('%') {
+                expanded.push('%');
+                rest = after;
+            } else if let Some(braced) = after.strip_prefix('{') {
+                let Some(end) = braced.find('}') else {
+                    expanded.push_str(&rest[start..]);
+                    return expanded;
+                };
+                let body = &braced[..end];
+                let replacement = match body.strip_prefix('?') {
+                    Some(conditional) => match conditional.split_once(':') {
+                        Some((name, value)) => self
+                            .lookup(name)
+                            .map(|_| value.to_string())
+                            .unwrap_or_default(),
+                        None => self.lookup(conditional).unwrap_or_default(),
+                    },
+                    None => self
+                        .lookup(body)
+                        .unwrap_or_else(|| format!("%{{{}}}", body)),
+                };
+                expanded.push_str(&self.expand_depth(&replacement, depth + 1));
+                rest = &braced[end + 1..];
+            } else {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                let name = &after[..end];
+                match self.lookup(name).filter(|_| !name.is_empty()) {
+                    Some(value) => expanded.push_str(&self.expand_depth(&value, depth + 1)),
+                    None => {
+                        expanded.push('%');
+                        expanded.push_str(name);
+                    }
+                }
+                rest = &after[end..];
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    fn tag(&self, tag: &str) -> Option<String> {
+        self.tags.get(tag).map(|value| self.expand(value))
+    }
+
+    /// The commands of a section with continued lines joined and blank lines dropped.
+    fn commands(&self, section: &str) -> Vec<String> {
+        let mut commands = vec![];
+        let mut current = String::new();
+        for line in self.sections.get(section).into_iter().flatten() {
+            let line = line.trim();
+            if let Some(continued) = line.strip_suffix('\\') {
+                current.push_str(continued.trim_end());
+                current.push(' ');
+                continue;
+            }
+            current.push_str(line);
+            if !current.trim().is_empty() {
+                commands.push(current.trim().to_string());
+            }
+            current.clear();
+        }
+        commands
+    }
+
+    /// The number of leading directories patches are applied with, -p1 unless %prep
+    /// tells otherwise.
+    fn patch_level(&self) -> i64 {
+        self.commands("prep")
+            .iter()
+            .filter(|c| c.starts_with("%autosetup") || c.starts_with("%patch"))
+            .flat_map(|c| c.split_whitespace())
+            .find_map(|arg| arg.strip_prefix("-p").and_then(|level| level.parse().ok()))
+            .unwrap_or(1)
+    }
+}
+
+/// The build styles a spec file can be mapped to.
+#[derive(Debug, PartialEq)]
+enum SpecBuild {
+    Configure(Vec<String>),
+    Cmake(Vec<String>),
+    Meson(Vec<String>),
+    Script(String),
+}
+
+/// Standard builds using only the build system macros map to a build section of that
+/// build system, everything else is carried over as a build script.
+fn spec_build(spec: &RpmSpec) -> SpecBuild {
+    let build = spec.commands("build");
+    let install = spec.commands("install");
+    let steps = build.iter().chain(install.iter()).collect::<Vec<_>>();
+    let only = |allowed: &[&str]| {
+        steps.iter().all(|step| {
+            allowed
+                .iter()
+                .any(|a| step == a || step.starts_with(&format!("{} ", a)))
+        })
+    };
+
+    let uses = |name: &str| steps.iter().any(|step| step.starts_with(name));
+    // The options of the configuration macro, the build system does the rest
+    let options = |name: &str, prefix: &str| -> Vec<String> {
+        steps
+            .iter()
+            .filter(|step| step.split_whitespace().next() == Some(name))
+            .flat_map(|step| step.split_whitespace())
+            .filter(|arg| arg.starts_with(prefix))
+            .map(|arg| spec.expand(arg))
+            .collect()
+    };
+    if uses("%configure")
+        && only(&[
+            "%configure",
+            "%make_build",
+            "%make_install",
+            "make",
+            "%set_build_flags",
+        ])
+    {
+        return SpecBuild::Configure(options("%configure", "--"));
+    }
+    if uses("%cmake") && only(&["%cmake", "%cmake_build", "%cmake_install"]) {
+        return SpecBuild::Cmake(options("%cmake", "-D"));
+    }
+    if uses("%meson") && only(&["%meson", "%meson_build", "%meson_install"]) {
+        return SpecBuild::Meson(options("%meson", "-D"));
+    }
+
+    let mut script = String::from("#!/bin/sh\nset -e\n");
+    for (section, commands) in [("build", build), ("install", install)] {
+        script.push_str(&format!("\n# %{}\n", section));
+        for command in commands {
+            let command = command
+                .replace("${RPM_BUILD_ROOT}", "${PROTO_DIR}")
+                .replace("$RPM_BUILD_ROOT", "${PROTO_DIR}");
+            script.push_str(&spec.expand(&command));
+            script.push('\n');
+        }
+    }
+    SpecBuild::Script(script)
+}
+
+fn is_url(source: &str) -> bool {
+    source.contains("://")
+}
+
+/// Copy a local source or patch the spec file refers to into the component. The
+/// rpmbuild layout keeps them next to the spec file or in the SOURCES directory beside it.
+fn copy_local_file(spec_dir: &Path, name: &str, component_dir: &Path) -> miette::Result<bool> {
+    let candidates = [
+        spec_dir.join(name),
+        spec_dir.join("..").join("SOURCES").join(name),
+    ];
+    match candidates.iter().find(|c| c.is_file()) {
+        Some(found) => {
+            fs::copy(found, component_dir.join(name)).into_diagnostic()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+fn dependencies(names: &[String], dev: bool) -> miette::Result<Vec<Dependency>> {
+    names
+        .iter()
+        .map(|name| Ok(DependencyBuilder::default().name(name).dev(dev).build()?))
+        .collect()
+}
+
+/// Import a component from the recipe of another packaging system.
+pub(crate) async fn import_component(from: ImportFrom) -> miette::Result<()> {
+    match from {
+        ImportFrom::Spec { file, fmri, args } => import_spec(&file, fmri, args).await,
+    }
+}
+
+/// Map the metadata, sources, dependencies and build steps of an RPM spec file into a
+/// recipe. Dependencies keep their RPM names, they need to be mapped to package names
+/// by hand before the component builds.
+async fn import_spec(file: &Path, fmri: Option<String>, args: ComponentArgs) -> miette::Result<()> {
+    let content = fs::read_to_string(file).map_err(|source| ImportError::Read {
+        path: file.to_path_buf(),
+        source,
+    })?;
+    let spec = RpmSpec::parse(&content);
+    let name = spec.tag("name").ok_or(ImportError::MissingTag("Name"))?;
+    let spec_dir = file.parent().unwrap_or(Path::new("."));
+
+    let mut c = Component::new(fmri.unwrap_or(name.clone()), Some(&args.component))?;
+    c.recipe.project_name = Some(name);
+    c.recipe.version = spec.tag("version");
+    c.recipe.summary = spec.tag("summary");
+    c.recipe.license = spec.tag("license");
+    c.recipe.project_url = spec.tag("url");
+
+    let mut sources = vec![];
+    for (_, source) in spec.sources.iter() {
+        let source = spec.expand(source);
+        if is_url(&source) {
+            let mut archive = ArchiveSourceBuilder::default();
+            archive.src(source.clone());
+            match archive_sha256(&source).await {
+                Ok(sha256) => {
+                    archive.sha256(sha256);
+                }
+                Err(e) => println!("{}, add its checksum by hand", e),
+            }
+            sources.push(SourceNode::Archive(archive.build()?));
+        } else {
+            if !copy_local_file(spec_dir, &source, &args.component)? {
+                println!(
+                    "{} was not found next to the spec file, copy it by hand",
+                    source
+                );
+            }
+            sources.push(SourceNode::File(FileSource::new(source, None)?));
+        }
+    }
+    let patch_level = spec.patch_level();
+    for (_, patch) in spec.patches.iter() {
+        let patch = spec.expand(patch);
+        let patch = patch.rsplit('/').next().unwrap_or(&patch).to_string();
+        if !copy_local_file(spec_dir, &patch, &args.component)? {
+            println!(
+                "{} was not found next to the spec file, copy it by hand",
+                patch
+            );
+        }
+        sources.push(SourceNode::Patch(PatchSource::new(
+            patch,
+            Some(patch_level),
+        )?));
+    }
+    if !sources.is_empty() {
+        c.recipe.sources.push(SourceSection {
+            name: None,
+            sources,
+        });
+    }
+
+    let build_requires = spec
+        .build_requires
+        .iter()
+        .map(|r| spec.expand(r))
+        .collect::<Vec<_>>();
+    let requires = spec
+        .requires
+        .iter()
+        .map(|r| spec.expand(r))
+        .collect::<Vec<_>>();
+    c.recipe
+        .dependencies
+        .extend(dependencies(&build_requires, true)?);
+    c.recipe
+        .dependencies
+        .extend(dependencies(&requires, false)?);
+
+    let mut build_section = BuildSectionBuilder::default();
+    match spec_build(&spec) {
+        SpecBuild::Configure(options) => {
+            let mut configure = ConfigureBuildSectionBuilder::default();
+            for option in options {
+                configure.option(option.as_str());
+            }
+            build_section.configure(configure.build()?);
+        }
+        SpecBuild::Cmake(options) => {
+            build_section.cmake(options.join(" "));
+        }
+        SpecBuild::Meson(options) => {
+            build_section.meson(options.join(" "));
+        }
+        SpecBuild::Script(script) => {
+            build_section.script(
+                ScriptBuildSectionBuilder::default()
+                    .script(ScriptNodeBuilder::default().name(BUILD_SCRIPT).build()?)
+                    .build()?,
+            );
+            let script_path = args.component.join(BUILD_SCRIPT);
+            fs::write(&script_path, script).into_diagnostic()?;
+            fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))
+                .into_diagnostic()?;
+            println!(
+                "The build steps were copied into {}, review them before building",
+                BUILD_SCRIPT
+            );
+        }
+    }
+    c.recipe.build_sections.push(build_section.build()?);
+
+    c.save_document()?;
+    if !c.recipe.dependencies.is_empty() {
+        println!("Dependencies keep their RPM package names, rename them with pkgdev edit");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZLIB_SPEC: &str = r#"
+%global soname 1
+Name:           zlib
+Version:        1.3.1
+Release:        1%{?dist}
+Summary:        Compression and decompression library
+License:        Zlib AND Boost
+URL:            https://www.zlib.net/
+Source0:        https://www.zlib.net/%{name}-%{version}.tar.xz
+Source1:        %{name}.pc.in
+Patch0:         zlib-1.2.5-minizip-fixuncrypt.patch
+BuildRequires:  gcc, make >= 4.0
+BuildRequires:  automake autoconf
+Requires(post): ldconfig
+
+%description
+Zlib is a general-purpose, patent-free, lossless data compression library.
+
+%package devel
+Summary:        Header files and libraries for Zlib development
+Requires:       %{name}%{?_isa} = %{version}-%{release}
+
+%prep
+%autosetup -p1
+
+%build
+%configure \
+    --enable-shared \
+    --libdir=%{_libdir}
+%make_build
+
+%install
+%make_install
+"#;
+
+    #[test]
+    fn parses_spec_preamble() {
+        let spec = RpmSpec::parse(ZLIB_SPEC);
+        assert_eq!(spec.tag("name").as_deref(), Some("zlib"));
+        assert_eq!(spec.tag("release").as_deref(), Some("1"));
+        assert_eq!(
+            spec.tag("summary").as_deref(),
+            Some("Compression and decompression library")
+        );
+        assert_eq!(
+            spec.sources
+                .iter()
+                .map(|(_, s)| spec.expand(s))
+                .collect::<Vec<_>>(),
+            vec!["https://www.zlib.net/zlib-1.3.1.tar.xz", "zlib.pc.in"]
+        );
+        assert_eq!(spec.patches.len(), 1);
+        assert_eq!(
+            spec.build_requires,
+            vec!["gcc", "make", "automake", "autoconf"]
+        );
+        // Requires of subpackages and scriptlets are not the ones of the main package
+        assert!(spec.requires.is_empty());
+        assert_eq!(spec.patch_level(), 1);
+        assert_eq!(
+            spec_build(&spec),
+            SpecBuild::Configure(vec![
+                String::from("--enable-shared"),
+                String::from("--libdir=/usr/lib"),
+            ])
+        );
+    }
+
+    #[test]
+    fn cmake_options_are_kept() {
+        let spec = RpmSpec::parse(
+            "Name: json-c\nVersion: 0.17\n\n%build\n%cmake -DBUILD_STATIC_LIBS=OFF \\\n    \
+             -DENABLE_THREADING=ON\n%cmake_build\n\n%install\n%cmake_install\n",
+        );
+        assert_eq!(
+            spec_build(&spec),
+            SpecBuild::Cmake(vec![
+                String::from("-DBUILD_STATIC_LIBS=OFF"),
+                String::from("-DENABLE_THREADING=ON"),
+            ])
+        );
+    }
+
+    #[test]
+    fn custom_build_steps_become_a_script() {
+        let spec = RpmSpec::parse(
+            "Name: hello\nVersion: 2.12\n\n%build\n./bootstrap\n%configure\n%make_build\n\n\
+             %install\n%make_install\nrm -f $RPM_BUILD_ROOT%{_libdir}/*.la\n",
+        );
+        let SpecBuild::Script(script) = spec_build(&spec) else {
+            panic!("expected a build script");
+        };
+        assert!(script.contains("./bootstrap\n./configure --prefix=/usr\nmake\n"));
+        assert!(script.contains("rm -f ${PROTO_DIR}/usr/lib/*.la\n"));
+    }
+}
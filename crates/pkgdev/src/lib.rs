@@ -5,13 +5,22 @@ use thiserror::Error;
 
 pub mod args;
 pub mod build;
+pub mod changelog;
 mod component;
 pub mod create;
+pub mod devshell;
+pub mod exit;
 pub mod forge;
+pub mod import;
+pub mod lint;
 pub mod metadata;
 pub mod modify;
 pub mod openid;
+pub mod patch;
+pub mod review;
+pub mod sbom;
 pub mod sources;
+pub mod status;
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum Error {
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+use clap::Parser;
+use component::{normalize_license, Component, RecipeViolation};
+use kdl::{KdlDocument, KdlNode};
+use miette::{Diagnostic, IntoDiagnostic, NamedSource, SourceSpan, WrapErr};
+use thiserror::Error;
+
+use crate::args::ComponentArgs;
+
+const KNOWN_DEPENDENCY_KINDS: [&str; 3] = ["require", "incorporate", "optional"];
+
+#[derive(Debug, Parser, Clone)]
+pub struct LintArgs {
+    #[clap(flatten)]
+    pub component: ComponentArgs,
+
+    /// Do not check whether the source urls of the recipe are reachable
+    #[arg(long)]
+    pub offline: bool,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum LintFinding {
+    #[error("recipe has no {0} node")]
+    #[diagnostic(
+        code(pkgdev::lint::missing_field),
+        help("add a top level `{0}` node to package.kdl")
+    )]
+    MissingField(String, #[label("recipe starts here")] SourceSpan),
+
+    #[error("source url {url} is not reachable: {reason}")]
+    #[diagnostic(code(pkgdev::lint::dead_source_url))]
+    DeadSourceUrl {
+        url: String,
+        reason: String,
+        #[label("this url")]
+        span: SourceSpan,
+    },
+
+    #[error("unknown dependency kind {0}")]
+    #[diagnostic(
+        code(pkgdev::lint::unknown_dependency_kind),
+        help("use one of 'require', 'incorporate', 'optional'")
+    )]
+    UnknownDependencyKind(String, #[label("unknown kind")] SourceSpan),
+
+    #[error("maintainer {name} is listed more than once")]
+    #[diagnostic(code(pkgdev::lint::duplicate_maintainer))]
+    DuplicateMaintainer {
+        name: String,
+        #[label("first listed here")]
+        first: SourceSpan,
+        #[label("listed again here")]
+        duplicate: SourceSpan,
+    },
+
+    #[error("version {0} is not a valid package version")]
+    #[diagnostic(
+        code(pkgdev::lint::malformed_version),
+        help("versions must be dot separated numbers like 1.2.3")
+    )]
+    MalformedVersion(String, #[label("malformed version")] SourceSpan),
+
+    #[error("license {license} is not an SPDX expression: {reason}")]
+    #[diagnostic(
+        code(pkgdev::lint::malformed_license),
+        help("use SPDX identifiers joined with AND, OR and WITH like `MIT OR Apache-2.0`")
+    )]
+    MalformedLicense {
+        license: String,
+        reason: String,
+        #[label("this license")]
+        span: SourceSpan,
+    },
+
+    #[error("unknown license identifier {0}")]
+    #[diagnostic(
+        code(pkgdev::lint::unknown_license),
+        help("use an SPDX identifier or a LicenseRef- for licenses SPDX does not list")
+    )]
+    UnknownLicense(String, #[label("unknown identifier")] SourceSpan),
+
+    #[error(transparent)]
+    #[diagnostic(code(pkgdev::lint::recipe_violation))]
+    RecipeViolation(#[from] RecipeViolation),
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("recipe {0} has {1} lint findings")]
+#[diagnostic(code(pkgdev::lint::failed))]
+pub struct LintFailed(String, usize);
+
+pub async fn lint_component(args: LintArgs) -> miette::Result<()> {
+    let path = if args.component.component.is_dir() {
+        args.component.component.join("package.kdl")
+    } else {
+        args.component.component.clone()
+    };
+
+    let contents = read_to_string(&path)
+        .into_diagnostic()
+        .wrap_err(format!("could not read {}", path.display()))?;
+    let doc: KdlDocument = contents.parse()?;
+
+    let mut findings = lint_document(&doc);
+    // The raw document findings usually explain why decoding fails, so only
+    // surface the decode error when there is nothing else to report.
+    match Component::open_local(&path) {
+        Ok(component) => findings.extend(component.validate().into_iter().map(LintFinding::from)),
+        Err(err) if findings.is_empty() => return Err(err.into()),
+        Err(_) => {}
+    }
+    if !args.offline {
+        findings.extend(check_source_urls(&doc).await);
+    }
+
+    let name = path.to_string_lossy().to_string();
+    if findings.is_empty() {
+        println!("{}: no findings", name);
+        return Ok(());
+    }
+
+    let count = findings.len();
+    for finding in findings {
+        let report = miette::Report::new(finding)
+            .with_source_code(NamedSource::new(&name, contents.clone()));
+        eprintln!("{:?}", report);
+    }
+
+    Err(LintFailed(name, count).into())
+}
+
+/// Run all checks that only need the document itself.
+pub fn lint_document(doc: &KdlDocument) -> Vec<LintFinding> {
+    let mut findings = vec![];
+    let doc_span = SourceSpan::from(0..0);
+
+    for field in ["summary", "license", "classification"] {
+        if doc.get(field).is_none() {
+            findings.push(LintFinding::MissingField(field.to_string(), doc_span));
+        }
+    }
+
+    let mut maintainers: HashMap<String, SourceSpan> = HashMap::new();
+    for node in doc.nodes() {
+        match node.name().value() {
+            "maintainer" => {
+                if let Some(name) = first_string_argument(node) {
+                    if let Some(first) = maintainers.get(&name) {
+                        findings.push(LintFinding::DuplicateMaintainer {
+                            name,
+                            first: *first,
+                            duplicate: *node.span(),
+                        });
+                    } else {
+                        maintainers.insert(name, *node.span());
+                    }
+                }
+            }
+            "version" => {
+                if let Some(version) = first_string_argument(node) {
+                    if !is_valid_version(&version) {
+                        findings.push(LintFinding::MalformedVersion(version, *node.span()));
+                    }
+                }
+            }
+            "license" => {
+                if let Some(license) = first_string_argument(node) {
+                    findings.extend(lint_license(&license, *node.span()));
+                }
+            }
+            "additional-license" => {
+                let name = node
+                    .entries()
+                    .iter()
+                    .find(|e| e.name().map(|n| n.value()) == Some("name"))
+                    .and_then(|e| e.value().as_string());
+                if let Some(license) = name {
+                    findings.extend(lint_license(license, *node.span()));
+                }
+            }
+            "dependency" => {
+                let kind = node
+                    .entries()
+                    .iter()
+                    .find(|e| e.name().map(|n| n.value()) == Some("kind"));
+                if let Some(kind) = kind {
+                    let value = kind.value().as_string().unwrap_or_default();
+                    if !KNOWN_DEPENDENCY_KINDS.contains(&value) {
+                        findings.push(LintFinding::UnknownDependencyKind(
+                            kind.value().to_string(),
+                            *kind.span(),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    findings
+}
+
+/// Licenses have to be SPDX expressions so the licenses of a gate can be summed up.
+fn lint_license(license: &str, span: SourceSpan) -> Vec<LintFinding> {
+    match normalize_license(license) {
+        Ok(expression) => expression
+            .unknown_identifiers()
+            .into_iter()
+            .map(|id| LintFinding::UnknownLicense(id.to_string(), span))
+            .collect(),
+        Err(e) => vec![LintFinding::MalformedLicense {
+            license: license.to_string(),
+            reason: e.to_string(),
+            span,
+        }],
+    }
+}
+
+async fn check_source_urls(doc: &KdlDocument) -> Vec<LintFinding> {
+    let client = reqwest::Client::new();
+    let mut findings = vec![];
+    for source in doc.nodes().iter().filter(|n| n.name().value() == "source") {
+        let Some(children) = source.children() else {
+            continue;
+        };
+        for archive in children
+            .nodes()
+            .iter()
+            .filter(|n| n.name().value() == "archive")
+        {
+            let Some(url) = first_string_argument(archive) else {
+                continue;
+            };
+            let reason = match client.head(&url).send().await {
+                Ok(resp) if resp.status().is_success() || resp.status().is_redirection() => {
+                    continue
+                }
+                Ok(resp) => resp.status().to_string(),
+                Err(e) => e.to_string(),
+            };
+            findings.push(LintFinding::DeadSourceUrl {
+                url,
+                reason,
+                span: *archive.span(),
+            });
+        }
+    }
+    findings
+}
+
+fn first_string_argument(node: &KdlNode) -> Option<String> {
+    node.entries()
+        .iter()
+        .find(|e| e.name().is_none())
+        .and_then(|e| e.value().as_string())
+        .map(|s| s.to_string())
+}
+
+/// IPS only accepts dot separated sequences of non negative integers as versions.
+fn is_valid_version(version: &str) -> bool {
+    !version.is_empty()
+        && version
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
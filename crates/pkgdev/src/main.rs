@@ -1,10 +1,23 @@
+use std::process::ExitCode;
+
 use clap::Parser;
 use pkgdev::args::run;
 use pkgdev::args::Args;
+use pkgdev::exit::{categorize, install_panic_hook, FailureCategory};
 
 #[tokio::main]
-async fn main() -> miette::Result<()> {
+async fn main() -> ExitCode {
+    install_panic_hook();
+    if let Err(err) = config::take_handed_credentials() {
+        eprintln!("Error: {:?}", miette::Report::new(err));
+        return ExitCode::from(FailureCategory::Environment.exit_code());
+    }
     let args = Args::parse();
-    run(args).await?;
-    Ok(())
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(report) => {
+            eprintln!("Error: {:?}", report);
+            ExitCode::from(categorize(&report).exit_code())
+        }
+    }
 }
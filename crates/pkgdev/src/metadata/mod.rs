@@ -1,11 +1,18 @@
-use clap::ValueEnum;
-use miette::IntoDiagnostic;
+use std::io::Read;
+use std::path::PathBuf;
+
+use clap::{Subcommand, ValueEnum};
+use component::{Component, Recipe};
+use miette::{Diagnostic, IntoDiagnostic};
 use strum::Display;
+use thiserror::Error;
 
 use crate::args::ComponentArgs;
 
 mod repology;
 
+pub use repology::RepologyError;
+
 #[derive(Debug, ValueEnum, Clone, Default, Display)]
 pub enum MetadataFormat {
     #[default]
@@ -13,6 +20,68 @@ pub enum MetadataFormat {
     Repology,
 }
 
+/// Formats recipes are exchanged with tools that cannot read KDL in.
+#[derive(Debug, ValueEnum, Clone, Copy, Default, Display, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum RecipeFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl RecipeFormat {
+    /// The format a file name implies, json unless it ends in .yaml or .yml
+    fn from_path(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => RecipeFormat::Yaml,
+            _ => RecipeFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MetadataAction {
+    /// Print the full recipe of the component as JSON or YAML
+    #[clap(name = "export")]
+    Export {
+        #[clap(long, default_value_t = RecipeFormat::default())]
+        format: RecipeFormat,
+    },
+    /// Replace the recipe of the component with an exported one, - reads it from stdin
+    #[clap(name = "import")]
+    Import {
+        file: PathBuf,
+        /// Format of the file, guessed from its extension if not given
+        #[clap(long)]
+        format: Option<RecipeFormat>,
+    },
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum RecipeExchangeError {
+    #[error("recipe is not valid JSON: {0}")]
+    #[diagnostic(help("the recipe needs to be in the format pkgdev metadata export prints"))]
+    Json(#[from] serde_json::Error),
+
+    #[error("recipe is not valid YAML: {0}")]
+    #[diagnostic(help("the recipe needs to be in the format pkgdev metadata export prints"))]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+pub fn export_recipe(recipe: &Recipe, format: RecipeFormat) -> Result<String, RecipeExchangeError> {
+    Ok(match format {
+        RecipeFormat::Json => serde_json::to_string_pretty(recipe)?,
+        RecipeFormat::Yaml => serde_yaml::to_string(recipe)?,
+    })
+}
+
+pub fn parse_recipe(content: &str, format: RecipeFormat) -> Result<Recipe, RecipeExchangeError> {
+    Ok(match format {
+        RecipeFormat::Json => serde_json::from_str(content)?,
+        RecipeFormat::Yaml => serde_yaml::from_str(content)?,
+    })
+}
+
 pub fn print_component(args: ComponentArgs, format: MetadataFormat) -> miette::Result<()> {
     let component = component::Component::open_local(&args.component)?;
     match format {
@@ -29,3 +98,61 @@ pub fn print_component(args: ComponentArgs, format: MetadataFormat) -> miette::R
     }
     Ok(())
 }
+
+/// Export the recipe of a component or import one into it. Importing into an existing
+/// component edits its package.kdl in place so comments and ordering are kept, otherwise
+/// a new package.kdl is written.
+pub fn exchange_recipe(args: ComponentArgs, action: MetadataAction) -> miette::Result<()> {
+    match action {
+        MetadataAction::Export { format } => {
+            let component = Component::open_local(&args.component)?;
+            println!("{}", export_recipe(&component.recipe, format)?);
+        }
+        MetadataAction::Import { file, format } => {
+            let content = if file.as_os_str() == "-" {
+                let mut content = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut content)
+                    .into_diagnostic()?;
+                content
+            } else {
+                std::fs::read_to_string(&file).into_diagnostic()?
+            };
+            let recipe = parse_recipe(
+                &content,
+                format.unwrap_or_else(|| RecipeFormat::from_path(&file)),
+            )?;
+
+            let mut component = if args.component.join("package.kdl").exists() {
+                Component::open_local(&args.component)?
+            } else {
+                Component::new(recipe.name.clone(), Some(&args.component))?
+            };
+            component.recipe = recipe;
+            component.save_document()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use component::RecipeBuilder;
+
+    use super::*;
+
+    #[test]
+    fn recipe_round_trips() -> miette::Result<()> {
+        let recipe = RecipeBuilder::default()
+            .name("library/zlib")
+            .version("1.3.1")
+            .summary("Compression library")
+            .license("Zlib")
+            .build()?;
+        for format in [RecipeFormat::Json, RecipeFormat::Yaml] {
+            let exported = export_recipe(&recipe, format)?;
+            assert_eq!(parse_recipe(&exported, format)?, recipe);
+        }
+        Ok(())
+    }
+}
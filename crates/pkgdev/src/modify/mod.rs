@@ -5,8 +5,9 @@ use clap::{arg, Subcommand, ValueEnum};
 use miette::IntoDiagnostic;
 
 use component::{
-    ArchiveSourceBuilder, BuildOptionNode, BuildSectionBuilder, Component, ConfigureBuildSection,
-    DependencyBuilder, DependencyKind, ScriptBuildSection, ScriptNode, SourceNode, SourceSection,
+    suggest_classification, ArchiveSourceBuilder, BuildOptionNode, BuildSectionBuilder, Component,
+    ConfigureBuildSectionBuilder, DependencyBuilder, DependencyKind, ScriptBuildSectionBuilder,
+    ScriptNode, SourceNode, SourceSection,
 };
 use gate::Gate;
 
@@ -33,6 +34,12 @@ pub enum SetArgs {
     Classification {
         arg: String,
     },
+    /// Set the classification suggested for topics of the upstream project, like its
+    /// GitHub topics or crates.io categories
+    SuggestedClassification {
+        #[arg(required = true)]
+        topics: Vec<String>,
+    },
     License {
         arg: String,
         #[clap(short, long)]
@@ -127,20 +134,10 @@ pub fn edit_component(
                 let mut bsb = BuildSectionBuilder::default();
                 match kind {
                     BuildKind::Configure => {
-                        bsb.configure(ConfigureBuildSection {
-                            options: vec![],
-                            flags: vec![],
-                            compiler: None,
-                            linker: None,
-                            disable_destdir_configure_option: false,
-                            enable_large_files: false,
-                        });
+                        bsb.configure(ConfigureBuildSectionBuilder::default().build()?);
                     }
                     BuildKind::Script => {
-                        bsb.script(ScriptBuildSection {
-                            scripts: vec![],
-                            install_directives: vec![],
-                        });
+                        bsb.script(ScriptBuildSectionBuilder::default().build()?);
                     }
                     BuildKind::Cmake => {
                         bsb.cmake("");
@@ -154,7 +151,10 @@ pub fn edit_component(
             }
             AddArgs::Source { args } => {
                 if c.recipe.sources.is_empty() {
-                    c.recipe.sources.push(SourceSection { sources: vec![] });
+                    c.recipe.sources.push(SourceSection {
+                        name: None,
+                        sources: vec![],
+                    });
                 }
                 let src_section = c.recipe.sources.first_mut().unwrap();
                 match args {
@@ -221,6 +221,18 @@ pub fn edit_component(
             SetArgs::Classification { arg } => {
                 c.recipe.classification = Some(arg);
             }
+            SetArgs::SuggestedClassification { topics } => {
+                let suggestion = suggest_classification(&topics).ok_or(miette::miette!(
+                    "no classification fits {}, set one with pkgdev edit set classification",
+                    topics.join(", ")
+                ))?;
+                println!(
+                    "Classified as {} because of {}",
+                    suggestion.classification,
+                    suggestion.matched.join(", ")
+                );
+                c.recipe.classification = Some(suggestion.classification);
+            }
             SetArgs::License { arg, file } => {
                 c.recipe.license = Some(arg);
                 c.recipe.license_file = Some(file);
@@ -0,0 +1,383 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::{Parser, Subcommand};
+use component::{Component, PatchSource, SourceNode, SourceSection};
+use miette::{IntoDiagnostic, WrapErr};
+use workspace::{Patch, Workspace};
+
+use crate::sources::derive_source_name;
+use crate::sources::unpack::unpack_sources;
+
+const PATCH_DIR: &str = "patches";
+const REFRESH_DIR: &str = "patch-refresh";
+
+#[derive(Debug, Parser)]
+pub struct PatchArgs {
+    #[arg(short, long, default_value = ".")]
+    pub component: PathBuf,
+
+    #[command(subcommand)]
+    pub action: PatchAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PatchAction {
+    /// Copy a patch into the patches directory and apply it after the patches numbered
+    /// lower. Patches without a number get the next free one.
+    #[clap(name = "add")]
+    Add {
+        file: PathBuf,
+        /// Leading directories to strip from the paths in the patch
+        #[arg(short = 'p', long, default_value_t = 1)]
+        drop_directories: i64,
+    },
+    /// Remove a patch given by file name or number from the recipe and the patches directory
+    #[clap(name = "remove")]
+    Remove { patch: String },
+    /// Number the patches in the order they are applied in steps of 1
+    #[clap(name = "renumber")]
+    Renumber,
+    /// Check that the patches apply in order to freshly unpacked sources
+    #[clap(name = "check")]
+    Check,
+    /// Apply the patches against freshly unpacked sources and regenerate them, so they
+    /// apply without offsets or fuzz to a new version
+    #[clap(name = "refresh")]
+    Refresh,
+}
+
+/// The number a patch file name like `0003-fix-build.patch` starts with.
+fn patch_number(file_name: &str) -> Option<u32> {
+    let (number, _) = file_name.split_once('-')?;
+    number.parse().ok()
+}
+
+fn patch_file_name(patch: &PatchSource) -> String {
+    let path = patch.to_string();
+    Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or(path)
+}
+
+fn patches(section: &SourceSection) -> impl Iterator<Item = (usize, &PatchSource)> {
+    section
+        .sources
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, node)| match node {
+            SourceNode::Patch(patch) => Some((idx, patch)),
+            _ => None,
+        })
+}
+
+/// Where a patch with the number goes: after the patches numbered lower or, for the first
+/// patch, after the archives and repositories the patches apply to.
+fn insert_position(section: &SourceSection, number: u32) -> usize {
+    let after_lower = patches(section)
+        .filter(|(_, patch)| patch_number(&patch_file_name(patch)).is_some_and(|n| n <= number))
+        .map(|(idx, _)| idx + 1)
+        .last();
+    let after_unpacked = section
+        .sources
+        .iter()
+        .rposition(|node| matches!(node, SourceNode::Archive(_) | SourceNode::Git(_)))
+        .map(|idx| idx + 1);
+    after_lower
+        .or(patches(section).map(|(idx, _)| idx).next())
+        .or(after_unpacked)
+        .unwrap_or(section.sources.len())
+}
+
+fn next_number(section: &SourceSection) -> u32 {
+    patches(section)
+        .filter_map(|(_, patch)| patch_number(&patch_file_name(patch)))
+        .max()
+        .unwrap_or(0)
+        + 1
+}
+
+fn add_patch(c: &mut Component, file: &Path, drop_directories: i64) -> miette::Result<()> {
+    let patch_dir = c.get_path().join(PATCH_DIR);
+    if c.recipe.sources.is_empty() {
+        c.recipe.sources.push(SourceSection {
+            name: None,
+            sources: vec![],
+        });
+    }
+    let section = c.recipe.sources.first_mut().unwrap();
+
+    let file_name = file
+        .file_name()
+        .ok_or(miette::miette!("no filename for {}", file.display()))?
+        .to_string_lossy()
+        .to_string();
+    let (number, file_name) = match patch_number(&file_name) {
+        Some(number) => (number, file_name),
+        None => {
+            let number = next_number(section);
+            (number, format!("{:04}-{}", number, file_name))
+        }
+    };
+
+    fs::create_dir_all(&patch_dir).into_diagnostic()?;
+    let target = patch_dir.join(&file_name);
+    if target.exists() {
+        return Err(miette::miette!("{} already exists", target.display()));
+    }
+    fs::copy(file, &target)
+        .into_diagnostic()
+        .wrap_err(format!("cannot copy {}", file.display()))?;
+
+    let position = insert_position(section, number);
+    section.sources.insert(
+        position,
+        SourceNode::Patch(PatchSource::new(
+            format!("{}/{}", PATCH_DIR, file_name),
+            Some(drop_directories),
+        )?),
+    );
+    println!("Added {} as patch {}", file_name, number);
+    Ok(())
+}
+
+/// Write a patch generated from the unpacked sources into the patches directory and
+/// apply it after all others. Returns the file name it got.
+pub(crate) fn append_patch(
+    c: &mut Component,
+    name: &str,
+    content: &[u8],
+) -> miette::Result<String> {
+    let patch_dir = c.get_path().join(PATCH_DIR);
+    if c.recipe.sources.is_empty() {
+        c.recipe.sources.push(SourceSection {
+            name: None,
+            sources: vec![],
+        });
+    }
+    let section = c.recipe.sources.first_mut().unwrap();
+
+    let number = next_number(section);
+    let file_name = format!("{:04}-{}.patch", number, name);
+    fs::create_dir_all(&patch_dir).into_diagnostic()?;
+    fs::write(patch_dir.join(&file_name), content).into_diagnostic()?;
+
+    let position = insert_position(section, number);
+    section.sources.insert(
+        position,
+        SourceNode::Patch(PatchSource::new(
+            format!("{}/{}", PATCH_DIR, file_name),
+            Some(1),
+        )?),
+    );
+    Ok(file_name)
+}
+
+fn remove_patch(c: &mut Component, name: &str) -> miette::Result<()> {
+    let component_path = c.get_path().to_path_buf();
+    for section in c.recipe.sources.iter_mut() {
+        let found = patches(section)
+            .find(|(_, patch)| {
+                let file_name = patch_file_name(patch);
+                file_name == name
+                    || name
+                        .parse::<u32>()
+                        .is_ok_and(|number| patch_number(&file_name) == Some(number))
+            })
+            .map(|(idx, patch)| (idx, patch.get_bundle_path(&component_path)));
+        if let Some((idx, path)) = found {
+            section.sources.remove(idx);
+            if path.exists() {
+                fs::remove_file(&path).into_diagnostic()?;
+            }
+            println!("Removed {}", path.display());
+            return Ok(());
+        }
+    }
+    Err(miette::miette!("the recipe has no patch {}", name))
+}
+
+fn renumber_patches(c: &mut Component) -> miette::Result<()> {
+    let component_path = c.get_path().to_path_buf();
+    let mut number = 0;
+    for section in c.recipe.sources.iter_mut() {
+        for node in section.sources.iter_mut() {
+            let SourceNode::Patch(patch) = node else {
+                continue;
+            };
+            number += 1;
+            let bundle_path = patch.to_string();
+            let file_name = patch_file_name(patch);
+            let name = match file_name.split_once('-') {
+                Some((number, name)) if number.parse::<u32>().is_ok() => name,
+                _ => file_name.as_str(),
+            };
+            let renamed = format!("{:04}-{}", number, name);
+            if renamed == file_name {
+                continue;
+            }
+
+            let renamed_bundle_path = match bundle_path.rsplit_once('/') {
+                Some((dir, _)) => format!("{}/{}", dir, renamed),
+                None => renamed,
+            };
+            fs::rename(
+                component_path.join(&bundle_path),
+                component_path.join(&renamed_bundle_path),
+            )
+            .into_diagnostic()
+            .wrap_err(format!("cannot rename {}", bundle_path))?;
+            println!("{} -> {}", bundle_path, renamed_bundle_path);
+            *patch = PatchSource::new(renamed_bundle_path, patch.drop_directories)?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_tree(from: &Path, to: &Path) -> miette::Result<()> {
+    fs::create_dir_all(to).into_diagnostic()?;
+    let mut copy_opts = fs_extra::dir::CopyOptions::new();
+    copy_opts.content_only = true;
+    fs_extra::dir::copy(from, to, &copy_opts).into_diagnostic()?;
+    Ok(())
+}
+
+/// Apply every patch to a copy of the tree the previous patches produced and, if
+/// `regenerate` is set, replace it with the difference between both trees. The trees are
+/// called a and b so the regenerated patches apply with -p1.
+fn refresh_patches(c: &mut Component, wks: &Workspace, regenerate: bool) -> miette::Result<()> {
+    let pristine = c
+        .recipe
+        .sources
+        .iter()
+        .map(|section| SourceSection {
+            name: section.name.clone(),
+            sources: section
+                .sources
+                .iter()
+                .filter(|node| !matches!(node, SourceNode::Patch(_)))
+                .cloned()
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    let build_dir = wks.get_or_create_build_dir()?;
+    let unpack_path = build_dir.join(derive_source_name(c.recipe.name.clone()));
+    if unpack_path.exists() {
+        println!(
+            "Removing {} to unpack the sources without patches",
+            unpack_path.display()
+        );
+        fs::remove_dir_all(&unpack_path).into_diagnostic()?;
+    }
+    unpack_sources(c, wks, &pristine).wrap_err("unpacking the sources failed")?;
+
+    let refresh_dir = build_dir.join(REFRESH_DIR);
+    if refresh_dir.exists() {
+        fs::remove_dir_all(&refresh_dir).into_diagnostic()?;
+    }
+    let (a, b) = (refresh_dir.join("a"), refresh_dir.join("b"));
+    fs::create_dir_all(&refresh_dir).into_diagnostic()?;
+    fs::rename(&unpack_path, &a).into_diagnostic()?;
+
+    let component_path = c.get_path().to_path_buf();
+    for section in c.recipe.sources.iter_mut() {
+        for node in section.sources.iter_mut() {
+            let SourceNode::Patch(patch) = node else {
+                continue;
+            };
+            let patch_path = patch.get_bundle_path(&component_path);
+            let engine = Patch::open(&patch_path)?;
+            if !regenerate {
+                engine.apply(&a, patch.drop_directories)?;
+                println!("{} applies", patch);
+                continue;
+            }
+
+            copy_tree(&a, &b)?;
+            engine.apply(&b, patch.drop_directories)?;
+
+            let diff = Command::new("gdiff")
+                .current_dir(&refresh_dir)
+                .args(["-Nrup", "a", "b"])
+                .output()
+                .into_diagnostic()
+                .wrap_err("could not run gdiff")?;
+            // diff exits with 1 if the trees differ and 2 on trouble
+            if diff.status.code() != Some(1) {
+                return Err(miette::miette!(
+                    "{} changes nothing or the trees could not be compared",
+                    patch
+                ));
+            }
+            fs::write(&patch_path, diff.stdout).into_diagnostic()?;
+            println!("Refreshed {}", patch);
+            *patch = PatchSource::new(patch.to_string(), Some(1))?;
+
+            fs::remove_dir_all(&a).into_diagnostic()?;
+            fs::rename(&b, &a).into_diagnostic()?;
+        }
+    }
+
+    fs::remove_dir_all(&refresh_dir).into_diagnostic()?;
+    Ok(())
+}
+
+/// Manage the patches of a component together with their source nodes in the recipe.
+pub fn manage_patches(args: PatchArgs, wks: &Workspace) -> miette::Result<()> {
+    let mut c = Component::open_local(&args.component)?;
+    match args.action {
+        PatchAction::Add {
+            file,
+            drop_directories,
+        } => add_patch(&mut c, &file, drop_directories)?,
+        PatchAction::Remove { patch } => remove_patch(&mut c, &patch)?,
+        PatchAction::Renumber => renumber_patches(&mut c)?,
+        PatchAction::Check => refresh_patches(&mut c, wks, false)?,
+        PatchAction::Refresh => refresh_patches(&mut c, wks, true)?,
+    }
+    c.save_document()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use component::ArchiveSourceBuilder;
+
+    use super::*;
+
+    fn patch(name: &str) -> miette::Result<SourceNode> {
+        Ok(SourceNode::Patch(PatchSource::new(
+            format!("patches/{}", name),
+            Some(1),
+        )?))
+    }
+
+    #[test]
+    fn patches_are_inserted_in_number_order() -> miette::Result<()> {
+        let archive = SourceNode::Archive(
+            ArchiveSourceBuilder::default()
+                .src("https://zlib.net/zlib-1.3.1.tar.xz")
+                .build()?,
+        );
+        let mut section = SourceSection {
+            name: None,
+            sources: vec![archive],
+        };
+        assert_eq!(insert_position(&section, 1), 1);
+        assert_eq!(next_number(&section), 1);
+
+        section.sources.push(patch("0002-fix-build.patch")?);
+        section.sources.push(patch("0005-use-system-zlib.patch")?);
+        assert_eq!(insert_position(&section, 1), 1);
+        assert_eq!(insert_position(&section, 3), 2);
+        assert_eq!(insert_position(&section, 7), 3);
+        assert_eq!(next_number(&section), 6);
+
+        assert_eq!(patch_number("0005-use-system-zlib.patch"), Some(5));
+        assert_eq!(patch_number("fix-build.patch"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::HashSet;
+
+use clap::Parser;
+use miette::IntoDiagnostic;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use serde::{Deserialize, Serialize};
+
+use crate::forge::{forge_request, selected_client};
+
+/// Lines of the summary of a change shown above the recipes at most.
+const MAX_SUMMARY_LINES: usize = 8;
+
+#[derive(Debug, Parser, Clone)]
+pub struct ReviewArgs {
+    /// Open this change request right away instead of the list of open ones
+    #[arg(long)]
+    pub change_request: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangeRequestSummary {
+    id: String,
+    state: String,
+    processing: bool,
+    components: Vec<String>,
+    approvals: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewPatch {
+    name: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewComponentChange {
+    name: String,
+    kind: String,
+    version: String,
+    revision: String,
+    current_recipe: Option<String>,
+    proposed_recipe: String,
+    #[serde(default)]
+    summary: Vec<String>,
+    patches: Vec<ReviewPatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ReviewVerdict {
+    Approve,
+    Comment,
+}
+
+#[derive(Debug, Deserialize)]
+struct Review {
+    actor: String,
+    verdict: ReviewVerdict,
+    body: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangeRequestDetail {
+    id: String,
+    state: String,
+    changes: Vec<ReviewComponentChange>,
+    reviews: Vec<Review>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReviewInput<'a> {
+    change_request_id: &'a str,
+    verdict: ReviewVerdict,
+    body: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangeRequestIdentifier<'a> {
+    change_request_id: &'a str,
+}
+
+async fn list_change_requests() -> crate::forge::Result<Vec<ChangeRequestSummary>> {
+    let client = selected_client().await?;
+    forge_request(
+        client
+            .client()
+            .get(format!("{}/api/v1/change_requests/", client.baseurl())),
+    )
+    .await
+}
+
+async fn get_change_request(id: &str) -> crate::forge::Result<ChangeRequestDetail> {
+    let client = selected_client().await?;
+    forge_request(
+        client
+            .client()
+            .post(format!("{}/api/v1/change_requests/get", client.baseurl()))
+            .json(&ChangeRequestIdentifier {
+                change_request_id: id,
+            }),
+    )
+    .await
+}
+
+async fn post_review(id: &str, verdict: ReviewVerdict, body: &str) -> crate::forge::Result<Review> {
+    let client = selected_client().await?;
+    forge_request(
+        client
+            .client()
+            .post(format!(
+                "{}/api/v1/change_requests/review",
+                client.baseurl()
+            ))
+            .json(&ReviewInput {
+                change_request_id: id,
+                verdict,
+                body,
+            }),
+    )
+    .await
+}
+
+/// What the detail view of a change request shows of the selected component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DetailView {
+    Recipe,
+    Patches,
+}
+
+struct Detail {
+    change_request: ChangeRequestDetail,
+    component: usize,
+    view: DetailView,
+    scroll: u16,
+}
+
+/// A review being written, it is posted when the reviewer presses enter.
+struct Draft {
+    verdict: ReviewVerdict,
+    body: String,
+}
+
+struct App {
+    change_requests: Vec<ChangeRequestSummary>,
+    list: ListState,
+    detail: Option<Detail>,
+    draft: Option<Draft>,
+    message: String,
+}
+
+/// Style recipe lines the other side does not have, like a diff would mark them.
+fn recipe_lines<'a>(recipe: &'a str, other: &str, changed: Color) -> Text<'a> {
+    let other = other.lines().collect::<HashSet<_>>();
+    recipe
+        .lines()
+        .map(|line| {
+            if other.contains(line) {
+                Line::raw(line)
+            } else {
+                Line::styled(line, Style::default().fg(changed))
+            }
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+fn patch_lines(patches: &[ReviewPatch]) -> Text<'_> {
+    let mut lines = vec![];
+    for patch in patches {
+        lines.push(Line::styled(
+            patch.name.as_str(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        for line in patch.content.lines() {
+            let style = if line.starts_with('+') && !line.starts_with("+++") {
+                Style::default().fg(Color::Green)
+            } else if line.starts_with('-') && !line.starts_with("---") {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            lines.push(Line::styled(line, style));
+        }
+        lines.push(Line::raw(""));
+    }
+    if lines.is_empty() {
+        lines.push(Line::raw("no patches"));
+    }
+    lines.into()
+}
+
+fn draw_list(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items = app
+        .change_requests
+        .iter()
+        .map(|cr| {
+            let processing = if cr.processing { " (processing)" } else { "" };
+            ListItem::new(format!(
+                "{} [{}{}] approvals: {} {}",
+                cr.id,
+                cr.state,
+                processing,
+                cr.approvals,
+                cr.components.join(", ")
+            ))
+        })
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .block(Block::bordered().title("Open change requests"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut app.list);
+}
+
+fn draw_detail(frame: &mut Frame, detail: &Detail, area: Rect) {
+    let [header, body, reviews] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(5),
+        Constraint::Length(6),
+    ])
+    .areas(area);
+
+    let change_request = &detail.change_request;
+    let components = change_request
+        .changes
+        .iter()
+        .enumerate()
+        .map(|(i, change)| {
+            if i == detail.component {
+                format!("[{} {}]", change.kind, change.name)
+            } else {
+                format!(" {} {} ", change.kind, change.name)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    frame.render_widget(
+        Paragraph::new(components).block(
+            Block::bordered().title(format!("{} ({})", change_request.id, change_request.state)),
+        ),
+        header,
+    );
+
+    match change_request.changes.get(detail.component) {
+        Some(change) => match detail.view {
+            DetailView::Recipe => {
+                let summary_height = match change.summary.len() {
+                    0 => 0,
+                    lines => lines.min(MAX_SUMMARY_LINES) as u16 + 2,
+                };
+                let [summary, recipes] =
+                    Layout::vertical([Constraint::Length(summary_height), Constraint::Min(3)])
+                        .areas(body);
+                frame.render_widget(
+                    Paragraph::new(change.summary.join("\n"))
+                        .block(Block::bordered().title("Changes")),
+                    summary,
+                );
+                let [current, proposed] =
+                    Layout::horizontal([Constraint::Percentage(50); 2]).areas(recipes);
+                let current_recipe = change.current_recipe.as_deref().unwrap_or_default();
+                let current_text = match &change.current_recipe {
+                    Some(recipe) => recipe_lines(recipe, &change.proposed_recipe, Color::Red),
+                    None => Text::raw("new component"),
+                };
+                frame.render_widget(
+                    Paragraph::new(current_text)
+                        .block(Block::bordered().title("In the gate"))
+                        .scroll((detail.scroll, 0)),
+                    current,
+                );
+                frame.render_widget(
+                    Paragraph::new(recipe_lines(
+                        &change.proposed_recipe,
+                        current_recipe,
+                        Color::Green,
+                    ))
+                    .block(
+                        Block::bordered()
+                            .title(format!("Proposed {}-{}", change.version, change.revision)),
+                    )
+                    .scroll((detail.scroll, 0)),
+                    proposed,
+                );
+            }
+            DetailView::Patches => frame.render_widget(
+                Paragraph::new(patch_lines(&change.patches))
+                    .block(Block::bordered().title(format!("Patches of {}", change.name)))
+                    .scroll((detail.scroll, 0)),
+                body,
+            ),
+        },
+        None => frame.render_widget(
+            Paragraph::new("the change request changes no components yet").block(Block::bordered()),
+            body,
+        ),
+    }
+
+    let review_lines = change_request
+        .reviews
+        .iter()
+        .map(|review| {
+            let verdict = match review.verdict {
+                ReviewVerdict::Approve => "approved",
+                ReviewVerdict::Comment => "commented",
+            };
+            Line::raw(format!("{} {}: {}", review.actor, verdict, review.body))
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(review_lines)
+            .block(Block::bordered().title("Reviews"))
+            .wrap(Wrap { trim: true }),
+        reviews,
+    );
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, input, status] = Layout::vertical([
+        Constraint::Min(5),
+        Constraint::Length(if app.draft.is_some() { 3 } else { 0 }),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    match &app.detail {
+        Some(detail) => draw_detail(frame, detail, main),
+        None => draw_list(frame, app, main),
+    }
+
+    if let Some(draft) = &app.draft {
+        let title = match draft.verdict {
+            ReviewVerdict::Approve => "Approve with comment (enter to post, esc to cancel)",
+            ReviewVerdict::Comment => "Comment (enter to post, esc to cancel)",
+        };
+        frame.render_widget(
+            Paragraph::new(draft.body.as_str()).block(Block::bordered().title(title)),
+            input,
+        );
+    }
+
+    let help = match app.detail {
+        Some(_) => "tab next component  p recipe/patches  j/k scroll  a approve  c comment  esc back  q quit",
+        None => "j/k select  enter open  r refresh  q quit",
+    };
+    let status_line = if app.message.is_empty() {
+        help.to_string()
+    } else {
+        format!("{}  |  {}", app.message, help)
+    };
+    frame.render_widget(Paragraph::new(status_line), status);
+}
+
+impl App {
+    async fn refresh(&mut self) {
+        match list_change_requests().await {
+            Ok(change_requests) => {
+                self.change_requests = change_requests;
+                self.list.select(if self.change_requests.is_empty() {
+                    None
+                } else {
+                    Some(0)
+                });
+                self.message = format!("{} open change requests", self.change_requests.len());
+            }
+            Err(e) => self.message = e.to_string(),
+        }
+    }
+
+    async fn open(&mut self, id: &str) {
+        match get_change_request(id).await {
+            Ok(change_request) => {
+                self.detail = Some(Detail {
+                    change_request,
+                    component: 0,
+                    view: DetailView::Recipe,
+                    scroll: 0,
+                });
+                self.message.clear();
+            }
+            Err(e) => self.message = e.to_string(),
+        }
+    }
+
+    async fn post(&mut self) {
+        let (Some(draft), Some(detail)) = (self.draft.take(), &self.detail) else {
+            return;
+        };
+        let id = detail.change_request.id.clone();
+        match post_review(&id, draft.verdict, &draft.body).await {
+            Ok(_) => {
+                self.open(&id).await;
+                self.message = String::from("review posted");
+            }
+            Err(e) => {
+                self.message = e.to_string();
+                self.draft = Some(draft);
+            }
+        }
+    }
+
+    /// Handle a key, returns whether the reviewer quit.
+    async fn key(&mut self, code: KeyCode) -> bool {
+        if let Some(draft) = &mut self.draft {
+            match code {
+                KeyCode::Enter => self.post().await,
+                KeyCode::Esc => self.draft = None,
+                KeyCode::Backspace => {
+                    draft.body.pop();
+                }
+                KeyCode::Char(c) => draft.body.push(c),
+                _ => {}
+            }
+            return false;
+        }
+
+        if let Some(detail) = &mut self.detail {
+            match code {
+                KeyCode::Char('q') => return true,
+                KeyCode::Esc => self.detail = None,
+                KeyCode::Tab | KeyCode::Right => {
+                    let count = detail.change_request.changes.len().max(1);
+                    detail.component = (detail.component + 1) % count;
+                    detail.scroll = 0;
+                }
+                KeyCode::BackTab | KeyCode::Left => {
+                    let count = detail.change_request.changes.len().max(1);
+                    detail.component = (detail.component + count - 1) % count;
+                    detail.scroll = 0;
+                }
+                KeyCode::Char('p') => {
+                    detail.view = match detail.view {
+                        DetailView::Recipe => DetailView::Patches,
+                        DetailView::Patches => DetailView::Recipe,
+                    };
+                    detail.scroll = 0;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    detail.scroll = detail.scroll.saturating_add(1)
+                }
+                KeyCode::Up | KeyCode::Char('k') => detail.scroll = detail.scroll.saturating_sub(1),
+                KeyCode::PageDown => detail.scroll = detail.scroll.saturating_add(20),
+                KeyCode::PageUp => detail.scroll = detail.scroll.saturating_sub(20),
+                KeyCode::Char('a') => {
+                    self.draft = Some(Draft {
+                        verdict: ReviewVerdict::Approve,
+                        body: String::new(),
+                    })
+                }
+                KeyCode::Char('c') => {
+                    self.draft = Some(Draft {
+                        verdict: ReviewVerdict::Comment,
+                        body: String::new(),
+                    })
+                }
+                _ => {}
+            }
+            return false;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+            KeyCode::Char('r') => self.refresh().await,
+            KeyCode::Enter => {
+                let selected = self
+                    .list
+                    .selected()
+                    .and_then(|i| self.change_requests.get(i))
+                    .map(|cr| cr.id.clone());
+                if let Some(id) = selected {
+                    self.open(&id).await;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+async fn run_app(terminal: &mut DefaultTerminal, args: ReviewArgs) -> miette::Result<()> {
+    let mut app = App {
+        change_requests: vec![],
+        list: ListState::default(),
+        detail: None,
+        draft: None,
+        message: String::new(),
+    };
+    app.refresh().await;
+    if let Some(id) = &args.change_request {
+        app.open(id).await;
+    }
+
+    loop {
+        terminal
+            .draw(|frame| draw(frame, &mut app))
+            .into_diagnostic()?;
+        if let Event::Key(key) = event::read().into_diagnostic()? {
+            if key.kind == KeyEventKind::Press && app.key(key.code).await {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Review the open change requests of the selected forge in the terminal.
+pub async fn review_change_requests(args: ReviewArgs) -> miette::Result<()> {
+    // Fail before taking over the terminal if no forge is connected
+    selected_client().await?;
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, args).await;
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,99 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use component::{render_sbom, Component, SbomEntry, SbomFile, SbomFormat};
+use gate::Gate;
+use miette::{IntoDiagnostic, WrapErr};
+use workspace::Workspace;
+
+use crate::build::{generate_actions, ManifestAction};
+use crate::component::open_component_local;
+
+#[derive(Debug, Parser, Clone)]
+pub struct SbomArgs {
+    #[arg(short, long, default_value = ".")]
+    pub component: PathBuf,
+
+    /// cyclonedx or spdx
+    #[arg(long, default_value_t = SbomFormat::default())]
+    pub format: SbomFormat,
+
+    /// Describe every component of the gate given with --gate instead of one
+    #[arg(long)]
+    pub all: bool,
+}
+
+/// The files the last build in the workspace delivers, empty if nothing was built.
+fn built_files(wks: &Workspace) -> miette::Result<Vec<SbomFile>> {
+    let proto_dir = wks.get_proto_dir();
+    if !proto_dir.exists() {
+        return Ok(vec![]);
+    }
+    Ok(generate_actions(&proto_dir)?
+        .into_iter()
+        .filter_map(|action| match action {
+            ManifestAction::File { path, hash, .. } => Some(SbomFile { path, sha256: hash }),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Every component below the components directory of the gate.
+fn gate_components(path: &Path, components: &mut Vec<Component>) -> miette::Result<()> {
+    for entry in std::fs::read_dir(path).into_diagnostic()? {
+        let entry = entry.into_diagnostic()?;
+        let file_type = entry.file_type().into_diagnostic()?;
+        if file_type.is_dir() {
+            gate_components(&entry.path(), components)?;
+        } else if file_type.is_file() && entry.file_name() == "package.kdl" {
+            components.push(
+                Component::open_local(entry.path())
+                    .wrap_err(format!("cannot open {}", entry.path().display()))?,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print the bill of materials of the component or with `--all` of the whole gate. Only
+/// a single component is described with the files its last build delivered, the
+/// workspace holds the build of one component at a time.
+pub fn print_sbom(args: SbomArgs, gate: &Option<Gate>, wks: &Workspace) -> miette::Result<()> {
+    let (name, entries) = if args.all {
+        let gate = gate
+            .as_ref()
+            .ok_or(miette::miette!("--all needs the gate given with --gate"))?;
+        let mut components = vec![];
+        gate_components(&gate.get_gate_path().join("components"), &mut components)?;
+        components.sort_by(|a, b| a.recipe.name.cmp(&b.recipe.name));
+        let entries = components
+            .into_iter()
+            .map(|component| SbomEntry {
+                recipe: component.recipe,
+                packages: component.package_meta,
+                files: vec![],
+            })
+            .collect::<Vec<_>>();
+        (gate.name.clone(), entries)
+    } else {
+        let component = open_component_local(&args.component, gate)?;
+        let entry = SbomEntry {
+            files: built_files(wks)?,
+            recipe: component.recipe,
+            packages: component.package_meta,
+        };
+        (entry.recipe.name.clone(), vec![entry])
+    };
+
+    let document = render_sbom(
+        args.format,
+        &name,
+        &chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        &entries,
+    );
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&document).into_diagnostic()?
+    );
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use component::{ArchiveSource, Component, SourceNode};
+use config::Settings;
+use miette::{Diagnostic, IntoDiagnostic};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use thiserror::Error;
+
+use crate::sources::fetch_archive;
+
+/// File next to the recipe recording the archives the sources were pinned to.
+pub const LOCK_FILE: &str = "sources.lock";
+
+#[derive(Debug, Parser, Clone)]
+pub struct SourcesArgs {
+    #[arg(short, long, default_value = ".")]
+    pub component: PathBuf,
+
+    #[clap(subcommand)]
+    pub action: SourcesAction,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum SourcesAction {
+    /// Download the archives of the recipe, write their sha256 and sha512 into it and
+    /// record them in sources.lock
+    Pin,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum LockError {
+    #[error("could not read {}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{} is not a valid lock file", path.display())]
+    Invalid {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[error("{url} is not in sources.lock")]
+    #[diagnostic(help("pin the sources again with pkgdev sources pin"))]
+    Missing { url: String },
+
+    #[error("{url} does not match sources.lock\nexpected:\t{expected}\nactual:\t{actual}")]
+    #[diagnostic(help(
+        "upstream changed the archive, check the change and pin the sources again"
+    ))]
+    Mismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// An archive as it was when the sources were pinned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedSource {
+    pub url: String,
+    pub size: u64,
+    pub sha256: String,
+    pub sha512: String,
+}
+
+impl LockedSource {
+    pub fn new(url: &str, content: &[u8]) -> Self {
+        Self {
+            url: url.to_string(),
+            size: content.len() as u64,
+            sha256: hex::encode(Sha256::digest(content)),
+            sha512: hex::encode(Sha512::digest(content)),
+        }
+    }
+}
+
+/// The archives of a component pinned with `pkgdev sources pin`. Builds of components
+/// with a lock file only accept archives matching it.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourcesLock {
+    pub sources: Vec<LockedSource>,
+}
+
+impl SourcesLock {
+    /// The lock file of the component in `dir`, if it has one.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Option<Self>, LockError> {
+        let path = dir.as_ref().join(LOCK_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read(&path).map_err(|source| LockError::Read {
+            path: path.clone(),
+            source,
+        })?;
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|source| LockError::Invalid { path, source })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> miette::Result<()> {
+        let mut content = serde_json::to_string_pretty(self).into_diagnostic()?;
+        content.push('\n');
+        fs::write(dir.as_ref().join(LOCK_FILE), content).into_diagnostic()
+    }
+
+    /// Check that the downloaded archive is the one the sources were pinned to.
+    pub fn verify(&self, url: &str, content: &[u8]) -> Result<(), LockError> {
+        let locked = self
+            .sources
+            .iter()
+            .find(|s| s.url == url)
+            .ok_or(LockError::Missing {
+                url: url.to_string(),
+            })?;
+        let actual = LockedSource::new(url, content);
+        let describe = |s: &LockedSource| format!("{} bytes sha256 {}", s.size, s.sha256);
+        if actual.size != locked.size || actual.sha256 != locked.sha256 {
+            return Err(LockError::Mismatch {
+                url: url.to_string(),
+                expected: describe(locked),
+                actual: describe(&actual),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Download every archive of the component, write the checksums into the recipe and
+/// record the archives in the lock file.
+async fn pin_sources(component: &Path, settings: &Settings) -> miette::Result<()> {
+    let mut c = Component::open_local(component)?;
+    let mut lock = SourcesLock::default();
+    for section in c.recipe.sources.iter_mut() {
+        for src in section.sources.iter_mut() {
+            let SourceNode::Archive(archive) = src else {
+                continue;
+            };
+            println!("Downloading archive: {}", forge::redact_url(&archive.src));
+            // Pinning is about what upstream serves now, not what was mirrored before
+            let upstream = ArchiveSource {
+                sha256: None,
+                sha512: None,
+                ..archive.clone()
+            };
+            let content = fetch_archive(&upstream, settings)
+                .await?
+                .bytes()
+                .await
+                .into_diagnostic()?;
+            let locked = LockedSource::new(&archive.src, &content);
+            archive.sha256 = Some(locked.sha256.clone());
+            archive.sha512 = Some(locked.sha512.clone());
+            lock.sources.push(locked);
+        }
+    }
+    c.save_document()?;
+    lock.save(c.get_path())?;
+    println!("Pinned {} archives in {}", lock.sources.len(), LOCK_FILE);
+    Ok(())
+}
+
+pub async fn handle_sources(args: SourcesArgs, settings: &Settings) -> miette::Result<()> {
+    match args.action {
+        SourcesAction::Pin => pin_sources(&args.component, settings).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_archives_are_rejected() {
+        let url = "https://zlib.net/zlib-1.3.1.tar.gz";
+        let lock = SourcesLock {
+            sources: vec![LockedSource::new(url, b"zlib sources")],
+        };
+        assert_eq!(lock.sources[0].size, 12);
+        assert!(lock.verify(url, b"zlib sources").is_ok());
+        assert!(matches!(
+            lock.verify(url, b"zlib sources, changed"),
+            Err(LockError::Mismatch { .. })
+        ));
+        assert!(matches!(
+            lock.verify("https://zlib.net/zlib-1.3.tar.gz", b"zlib sources"),
+            Err(LockError::Missing { .. })
+        ));
+    }
+}
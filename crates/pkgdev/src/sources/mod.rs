@@ -1,14 +1,20 @@
 use std::io::{copy, Cursor};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use miette::{Diagnostic, IntoDiagnostic};
 use thiserror::Error;
 
-use component::{ArchiveSource, Component, GitSource, SourceNode};
+use component::{ArchiveSource, BuildSection, Component, GitSource, SourceNode, SourceSection};
+use config::Settings;
+use forge::{redact_url, SourceCredential};
 use workspace::{HasherKind, Workspace};
 
+use crate::sources::lock::{LockError, SourcesLock};
 use crate::sources::path::add_extension;
 
+pub mod lock;
+pub mod overlay;
 pub(crate) mod path;
 pub mod unpack;
 
@@ -23,30 +29,93 @@ pub async fn download_sources(
     component: &Component,
     wks: &Workspace,
     archive_clean: bool,
+    settings: &Settings,
 ) -> miette::Result<()> {
+    let lock = SourcesLock::open(component.get_path())?;
     for source in component.recipe.sources.iter() {
         for src in source.sources.iter() {
             if let SourceNode::Archive(ar) = src {
-                println!("Downloading archive: {}", &ar.src);
-                match download_archive(&wks, &ar, archive_clean).await {
+                println!("Downloading archive: {}", redact_url(&ar.src));
+                match download_archive(&wks, &ar, archive_clean, settings, lock.as_ref()).await {
                     Ok(_) => println!("Download finished"),
+                    // Archives not matching the lock file must not be built
+                    Err(err) if err.downcast_ref::<LockError>().is_some() => return Err(err),
                     Err(err) => println!("{}\nwill continue with other downloads", err),
                 }
             } else if let SourceNode::Git(g) = src {
-                println!("Downloading git repo: {}", &g.repository);
-                download_git(&wks, &g)?;
+                println!("Downloading git repo: {}", redact_url(&g.repository));
+                download_git(&wks, &g, &settings.source_credentials)?;
             }
         }
     }
     Ok(())
 }
 
+/// Ask the source mirror of the forge for the archive, it fetches archives it does not
+/// have yet from upstream. Archives needing credentials are never sent through it. The
+/// mirror itself is authenticated with the source credential matching its url.
+async fn fetch_from_mirror(
+    client: &reqwest::Client,
+    mirror: &str,
+    archive: &ArchiveSource,
+    credentials: &[SourceCredential],
+) -> Option<reqwest::Response> {
+    let sha256 = archive.sha256.as_ref()?;
+    let url = format!("{}/api/v1/sources/{}", mirror.trim_end_matches('/'), sha256);
+    let mut request = client.get(&url).query(&[("url", &archive.src)]);
+    if let Some(authorization) =
+        SourceCredential::find(credentials, &url).and_then(|c| c.authorization())
+    {
+        request = request.header(reqwest::header::AUTHORIZATION, authorization);
+    }
+    match request.send().await.and_then(|r| r.error_for_status()) {
+        Ok(response) => Some(response),
+        Err(err) => {
+            println!(
+                "Source mirror failed: {}
+will download from upstream",
+                err.without_url()
+            );
+            None
+        }
+    }
+}
+
+pub(crate) async fn fetch_archive(
+    archive: &ArchiveSource,
+    settings: &Settings,
+) -> miette::Result<reqwest::Response> {
+    let client = reqwest::Client::new();
+    let credential = SourceCredential::find(&settings.source_credentials, &archive.src);
+    let has_userinfo = redact_url(&archive.src) != archive.src;
+    if let (Some(mirror), None, false) = (&settings.source_mirror, credential, has_userinfo) {
+        let credentials = &settings.source_credentials;
+        if let Some(response) = fetch_from_mirror(&client, mirror, archive, credentials).await {
+            return Ok(response);
+        }
+    }
+
+    let mut request = client.get(&archive.src);
+    if let Some(authorization) = credential.and_then(|c| c.authorization()) {
+        request = request.header(reqwest::header::AUTHORIZATION, authorization);
+    }
+    // The url may carry credentials, errors must not show it
+    request
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.without_url())
+        .into_diagnostic()
+}
+
 async fn download_archive(
     wks: &Workspace,
     archive: &ArchiveSource,
     archive_clean: bool,
+    settings: &Settings,
+    lock: Option<&SourcesLock>,
 ) -> miette::Result<()> {
-    let response = reqwest::get(&archive.src).await.into_diagnostic()?;
+    let response = fetch_archive(archive, settings).await?;
     let (hash, hasher_kind) = if let Some(sha256) = &archive.sha256 {
         Ok((sha256.clone(), HasherKind::Sha256))
     } else if let Some(sha512) = &archive.sha512 {
@@ -63,7 +132,11 @@ async fn download_archive(
         wks.open_local_file(&archive.src.parse().into_diagnostic()?, hasher_kind)?
     };
 
-    let mut content = Cursor::new(response.bytes().await.into_diagnostic()?);
+    let content = response.bytes().await.into_diagnostic()?;
+    if let Some(lock) = lock {
+        lock.verify(&archive.src, &content)?;
+    }
+    let mut content = Cursor::new(content);
     copy(&mut content, &mut dest).into_diagnostic()?;
     let computed_hash = dest.get_hash();
     if hash != computed_hash {
@@ -77,7 +150,12 @@ async fn download_archive(
     Ok(())
 }
 
-fn download_git(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
+fn download_git(
+    wks: &Workspace,
+    git: &GitSource,
+    credentials: &[SourceCredential],
+) -> miette::Result<()> {
+    let git_env = git_env(credentials, &git.repository);
     let git_prefix = &git.get_repo_prefix();
     let git_repo_path = wks.get_or_create_download_dir()?.join(&git_prefix);
     let archive_path = add_extension(&git_prefix, "tar.gz");
@@ -87,13 +165,14 @@ fn download_git(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
 
     if !archive_path.exists() {
         if !git_repo_path.exists() {
-            if git.archive.is_some() {
-                git_archive_get(wks, &git)?;
+            // Remote archives can neither be pinned to a commit nor include submodules
+            if git.archive.is_some() && git.commit.is_none() && git.recurse_submodules.is_none() {
+                git_archive_get(wks, &git, &git_env)?;
             } else {
-                git_clone_get(wks, &git)?;
+                git_clone_get(wks, &git, &git_env)?;
             }
         } else {
-            if git.must_stay_as_repo.is_some() {
+            if git.must_stay_as_repo.is_some() || git.recurse_submodules.unwrap_or(false) {
                 println!("Creating Archive of full repo");
                 make_git_archive_with_tar(wks, git)?;
             } else {
@@ -105,14 +184,33 @@ fn download_git(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
     Ok(())
 }
 
-fn git_clone_get(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
+/// The environment git needs to authenticate to the repository, empty for public ones.
+fn git_env(credentials: &[SourceCredential], repository: &str) -> Vec<(String, String)> {
+    SourceCredential::find(credentials, repository)
+        .map(|c| c.git_env())
+        .unwrap_or_default()
+}
+
+fn git_clone_get(
+    wks: &Workspace,
+    git: &GitSource,
+    git_env: &[(String, String)],
+) -> miette::Result<()> {
     let mut git_cmd = Command::new("git");
+    git_cmd.envs(git_env.iter().cloned());
 
     let repo_prefix = git.get_repo_prefix();
 
+    // A tag is all that is needed from the history unless a commit is pinned
+    let shallow = git.tag.is_some() && git.commit.is_none();
+    let recurse_submodules = git.recurse_submodules.unwrap_or(false);
+
     git_cmd.current_dir(&wks.get_or_create_download_dir()?);
     git_cmd.arg("clone");
     git_cmd.arg("--single-branch");
+    if shallow {
+        git_cmd.arg("--depth=1");
+    }
     if let Some(tag) = &git.tag {
         git_cmd.arg("--branch");
         git_cmd.arg(tag);
@@ -129,11 +227,30 @@ fn git_clone_get(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
     } else {
         return Err(miette::miette!(format!(
             "Could not git clone {}",
-            git.repository
+            redact_url(&git.repository)
         )));
     }
 
-    if git.must_stay_as_repo.is_some() {
+    let repo_path = wks.get_or_create_download_dir()?.join(&repo_prefix);
+    if let Some(commit) = &git.commit {
+        run_git(
+            &repo_path,
+            &["checkout", "--quiet", "--detach", commit],
+            &[],
+        )?;
+        println!("Checked out commit {}", commit);
+    }
+    if recurse_submodules {
+        let mut args = vec!["submodule", "update", "--init", "--recursive"];
+        if shallow {
+            args.push("--depth=1");
+        }
+        run_git(&repo_path, &args, git_env)?;
+        println!("Checked out submodules");
+    }
+
+    // git archive leaves submodules out
+    if git.must_stay_as_repo.is_some() || recurse_submodules {
         println!("Creating Archive of full repo");
         make_git_archive_with_tar(wks, git)
     } else {
@@ -142,11 +259,36 @@ fn git_clone_get(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
     }
 }
 
+fn run_git(
+    repo_path: &std::path::Path,
+    args: &[&str],
+    git_env: &[(String, String)],
+) -> miette::Result<()> {
+    let status = Command::new("git")
+        .current_dir(repo_path)
+        .envs(git_env.iter().cloned())
+        .args(args)
+        .status()
+        .into_diagnostic()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(miette::miette!(
+            "git {} failed in {}",
+            args.join(" "),
+            repo_path.display()
+        ))
+    }
+}
+
 fn make_git_archive_with_tar(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
     let repo_prefix = git.get_repo_prefix();
 
     let mut archive_cmd = Command::new("gtar");
     archive_cmd.current_dir(&wks.get_or_create_download_dir()?);
+    if git.must_stay_as_repo.is_none() {
+        archive_cmd.arg("--exclude-vcs");
+    }
     archive_cmd.arg("-czf");
     let archive_name_arg = add_extension(&repo_prefix, "tar.gz")
         .to_string_lossy()
@@ -199,8 +341,13 @@ fn make_git_archive(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
     }
 }
 
-fn git_archive_get(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
+fn git_archive_get(
+    wks: &Workspace,
+    git: &GitSource,
+    git_env: &[(String, String)],
+) -> miette::Result<()> {
     let mut git_cmd = Command::new("git");
+    git_cmd.envs(git_env.iter().cloned());
     let repo_prefix = git.get_repo_prefix();
 
     let prefix_arg = format!("--prefix={}", &repo_prefix);
@@ -234,7 +381,7 @@ fn git_archive_get(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
     } else {
         Err(miette::miette!(format!(
             "Could not get git archive for {}",
-            git.repository
+            redact_url(&git.repository)
         )))
     }
 }
@@ -242,3 +389,77 @@ fn git_archive_get(wks: &Workspace, git: &GitSource) -> miette::Result<()> {
 pub fn derive_source_name(package_name: String) -> String {
     package_name.replace("/", "_")
 }
+
+/// The directory below the build directory a source section is unpacked to, the one of
+/// the component unless the section has a name.
+pub fn unpack_dir_name(pkg: &Component, section: &SourceSection) -> String {
+    derive_source_name(section.name.clone().unwrap_or(pkg.recipe.name.clone()))
+}
+
+/// The unpacked sources a build section works on.
+#[derive(Debug, Clone)]
+pub struct SourceTree {
+    /// Names the tree in the build directory and the configure cache
+    pub name: String,
+    /// Where the section builds, the unpacked sources or its directory in them
+    pub path: PathBuf,
+    /// Where the section builds for recipes with a seperate build directory
+    pub out_dir: PathBuf,
+}
+
+impl SourceTree {
+    /// The sources the build section selects with `source` and `directory`.
+    pub fn resolve(
+        wks: &Workspace,
+        pkg: &Component,
+        section: &BuildSection,
+    ) -> miette::Result<Self> {
+        let build_dir = wks.get_or_create_build_dir()?;
+        let unpack_name = match &section.source {
+            Some(name) => {
+                let source = pkg.recipe.source_section(name).ok_or(miette::miette!(
+                    "{} has no source section called {}",
+                    pkg.get_name(),
+                    name
+                ))?;
+                unpack_dir_name(pkg, source)
+            }
+            None => derive_source_name(pkg.recipe.name.clone()),
+        };
+        let mut path = build_dir.join(&unpack_name);
+        let name = match &section.directory {
+            Some(directory) => {
+                path.push(directory);
+                format!("{}_{}", unpack_name, derive_source_name(directory.clone()))
+            }
+            None => unpack_name,
+        };
+        if !path.is_dir() {
+            return Err(miette::miette!(
+                "{} does not exist in the unpacked sources of {}",
+                path.display(),
+                pkg.get_name()
+            ));
+        }
+        // Sections building other sources must not configure into the same directory
+        let out_dir = if section.source.is_none() && section.directory.is_none() {
+            build_dir.join("out")
+        } else {
+            build_dir.join(format!("out-{}", name))
+        };
+        Ok(Self {
+            name,
+            path,
+            out_dir,
+        })
+    }
+
+    /// The directory the build tools of the section run in.
+    pub fn work_dir(&self, pkg: &Component) -> &Path {
+        if pkg.recipe.seperate_build_dir {
+            &self.out_dir
+        } else {
+            &self.path
+        }
+    }
+}
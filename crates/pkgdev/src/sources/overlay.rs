@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use component::Component;
+use miette::{IntoDiagnostic, Result, WrapErr};
+
+/// The `@PLACEHOLDER@`s replaced in the text files of an overlay and their values for the
+/// component. Placeholders of fields the recipe leaves empty are kept as they are.
+pub fn overlay_substitutions(component: &Component) -> Vec<(String, String)> {
+    let recipe = &component.recipe;
+    [
+        ("NAME", Some(recipe.name.clone())),
+        ("PROJECT_NAME", recipe.project_name.clone()),
+        ("VERSION", recipe.version.clone()),
+        ("REVISION", recipe.revision.clone()),
+        (
+            "PREFIX",
+            Some(recipe.prefix.clone().unwrap_or(String::from("/usr"))),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|value| (format!("@{}@", name), value)))
+    .collect()
+}
+
+fn substitute(content: &str, substitutions: &[(String, String)]) -> String {
+    substitutions
+        .iter()
+        .fold(content.to_string(), |content, (placeholder, value)| {
+            content.replace(placeholder, value)
+        })
+}
+
+/// Every path below `dir` sorted, so overlays are layered in the same order on every host.
+fn sorted_paths(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .into_diagnostic()?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    entries.sort();
+    for entry in entries {
+        paths.push(entry.clone());
+        if entry.is_dir() && !entry.is_symlink() {
+            sorted_paths(&entry, paths)?;
+        }
+    }
+    Ok(())
+}
+
+/// Layer the overlay directory over the unpacked sources. Files replace the ones in the
+/// sources, placeholders in UTF-8 files are substituted and binary files are copied as is.
+pub fn apply_overlay(
+    overlay: &Path,
+    target: &Path,
+    substitutions: &[(String, String)],
+) -> Result<()> {
+    let mut paths = vec![];
+    sorted_paths(overlay, &mut paths)?;
+
+    for path in paths {
+        let relative = path.strip_prefix(overlay).into_diagnostic()?;
+        let destination = target.join(relative);
+        let metadata = fs::symlink_metadata(&path).into_diagnostic()?;
+
+        if metadata.is_dir() {
+            fs::create_dir_all(&destination).into_diagnostic()?;
+            continue;
+        }
+        if destination.is_symlink() || destination.is_file() {
+            fs::remove_file(&destination).into_diagnostic()?;
+        }
+        if metadata.is_symlink() {
+            let link = fs::read_link(&path).into_diagnostic()?;
+            std::os::unix::fs::symlink(link, &destination).into_diagnostic()?;
+            continue;
+        }
+
+        let content = fs::read(&path).into_diagnostic()?;
+        match String::from_utf8(content) {
+            Ok(text) => fs::write(&destination, substitute(&text, substitutions)),
+            Err(binary) => fs::write(&destination, binary.into_bytes()),
+        }
+        .into_diagnostic()
+        .wrap_err(format!("cannot overlay {}", relative.display()))?;
+        fs::set_permissions(&destination, metadata.permissions()).into_diagnostic()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_substituted() -> miette::Result<()> {
+        let mut component = Component::new(String::from("library/zlib"), None::<&str>)?;
+        component.recipe.version = Some(String::from("1.3.1"));
+        let substitutions = overlay_substitutions(&component);
+
+        assert_eq!(
+            substitute(
+                "prefix=@PREFIX@\nVersion: @VERSION@\nRevision: @REVISION@\n",
+                &substitutions
+            ),
+            "prefix=/usr\nVersion: 1.3.1\nRevision: @REVISION@\n"
+        );
+        Ok(())
+    }
+}
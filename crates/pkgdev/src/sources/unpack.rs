@@ -1,14 +1,14 @@
 use std::{
     fs::{read_dir, DirBuilder},
     path::{Path, PathBuf},
-    process::Command,
 };
 
-use crate::sources::derive_source_name;
+use crate::sources::unpack_dir_name;
+use crate::sources::overlay::{apply_overlay, overlay_substitutions};
 use crate::sources::path::add_extension;
 use component::{Component, SourceSection};
 use miette::{IntoDiagnostic, Result, WrapErr};
-use workspace::Workspace;
+use workspace::{Patch, Workspace};
 
 pub fn unpack_sources(
     component: &Component,
@@ -20,8 +20,7 @@ pub fn unpack_sources(
     let package_name = component.recipe.name.clone();
 
     for (source_idx, source) in sources.into_iter().enumerate() {
-        let unpack_name = derive_source_name(package_name.clone());
-        let unpack_path = build_dir.join(&unpack_name);
+        let unpack_path = build_dir.join(unpack_dir_name(component, source));
 
         for (node_idx, src) in source.sources.clone().into_iter().enumerate() {
             match src {
@@ -33,7 +32,8 @@ pub fn unpack_sources(
                 component::SourceNode::Git(git_src) => {
                     let file_name = add_extension(git_src.get_repo_prefix(), "tar.gz");
                     let archive_download_path = wks.get_or_create_download_dir()?.join(file_name);
-                    if node_idx == 0 && source_idx == 0 {
+                    // Named sections get a directory of their own to unpack into
+                    if node_idx == 0 && (source_idx == 0 || source.name.is_some()) {
                         archive_unpack(&archive_download_path, &unpack_path, &package_name)?;
                     } else {
                         if let Some(unpack_name) = git_src.directory {
@@ -68,38 +68,15 @@ pub fn unpack_sources(
                     std::fs::copy(src_path, final_path).into_diagnostic()?;
                 }
                 component::SourceNode::Patch(patch) => {
-                    let src_path = patch
-                        .get_bundle_path(component.get_path())
-                        .to_string_lossy()
-                        .to_string();
-                    let unpack_arg = unpack_path.to_string_lossy().to_string();
-                    let mut patch_cmd = Command::new("gpatch");
-                    patch_cmd.arg("-d");
-                    patch_cmd.arg(&unpack_arg);
-                    if let Some(drop_directories) = patch.drop_directories {
-                        let strip_arg = format!("-p{}", drop_directories);
-                        patch_cmd.arg(&strip_arg);
-                    }
-                    patch_cmd.arg("-i");
-                    patch_cmd.arg(&src_path);
-
-                    let status = patch_cmd
-                        .status()
-                        .into_diagnostic()
-                        .wrap_err("could not run gpatch")?;
-
-                    if !status.success() {
-                        return Err(miette::miette!("failed to patch sources"));
-                    }
+                    let src_path = patch.get_bundle_path(component.get_path());
+                    println!("Applying patch {}", patch);
+                    Patch::open(&src_path)?.apply(&unpack_path, patch.drop_directories)?;
                 }
                 component::SourceNode::Overlay(overlay) => {
                     println!("Overlaying directory {}", unpack_path.display());
                     let src_path = overlay.get_bundle_path(component.get_path());
-                    let final_path = unpack_path.clone();
-                    let mut copy_opts = fs_extra::dir::CopyOptions::new();
-                    copy_opts.overwrite = true;
-                    copy_opts.content_only = true;
-                    fs_extra::dir::copy(&src_path, final_path, &copy_opts).into_diagnostic()?;
+                    apply_overlay(&src_path, &unpack_path, &overlay_substitutions(component))
+                        .wrap_err("overlay failed")?;
                 }
                 component::SourceNode::Directory(directory) => {
                     println!(
@@ -0,0 +1,139 @@
+use clap::Parser;
+use gate::Gate;
+use miette::IntoDiagnostic;
+
+use crate::forge::{maintainer_status, MaintainedComponentStatus};
+
+#[derive(Debug, Parser, Clone)]
+pub struct StatusArgs {
+    /// Id of the gate on the forge, the one of the gate given with --gate or the default
+    /// gate of the forge profile if not given
+    #[arg(long)]
+    pub gate_id: Option<String>,
+
+    /// Print the status as JSON for scripts
+    #[arg(long)]
+    pub json: bool,
+}
+
+const HEADER: [&str; 5] = [
+    "COMPONENT",
+    "VERSION",
+    "UPSTREAM",
+    "CHANGE REQUESTS",
+    "LAST BUILD",
+];
+
+fn status_row(status: &MaintainedComponentStatus) -> [String; 5] {
+    let upstream = match &status.upstream_version {
+        Some(version) if status.outdated => format!("{} (newer)", version),
+        Some(version) => version.clone(),
+        None => String::from("-"),
+    };
+    let last_build = match &status.last_build {
+        Some(job) => job.outcome.clone().unwrap_or(job.state.to_string()),
+        None => String::from("-"),
+    };
+    [
+        status.name.clone(),
+        format!("{}-{}", status.version, status.revision),
+        upstream,
+        status.open_change_requests.len().to_string(),
+        last_build,
+    ]
+}
+
+/// The status as a table with a column for every field.
+fn status_table(statuses: &[MaintainedComponentStatus]) -> String {
+    let rows = statuses.iter().map(status_row).collect::<Vec<_>>();
+    let mut widths = HEADER.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let header = HEADER.map(String::from);
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Print where the components the actor maintains in the gate stand on the selected
+/// forge.
+pub async fn print_status(args: StatusArgs, gate: &Option<Gate>) -> miette::Result<()> {
+    let gate_id = args
+        .gate_id
+        .or(gate.as_ref().and_then(|gate| gate.id.clone()));
+    let statuses = maintainer_status(gate_id).await?;
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&statuses).into_diagnostic()?
+        );
+    } else if statuses.is_empty() {
+        println!("You maintain no components in this gate");
+    } else {
+        print!("{}", status_table(&statuses));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forge::{JobState, QueuedJob};
+
+    #[test]
+    fn status_is_printed_as_aligned_table() {
+        let statuses = vec![
+            MaintainedComponentStatus {
+                name: String::from("library/zlib"),
+                version: String::from("1.3"),
+                revision: String::from("0"),
+                upstream_version: Some(String::from("1.3.1")),
+                outdated: true,
+                open_change_requests: vec![String::from("https://github.com/oi/pulls/1")],
+                last_build: Some(QueuedJob {
+                    id: String::from("0b6c1f3e"),
+                    reference: String::from("g/library/zlib@1.3-0"),
+                    kind: String::from("BuildStoredComponent"),
+                    state: JobState::Finished,
+                    gate_id: None,
+                    staging: None,
+                    outcome: Some(String::from("failure")),
+                    error: None,
+                    created_at: String::from("2026-10-16T10:00:00+00:00"),
+                    dispatched_at: None,
+                    finished_at: None,
+                }),
+            },
+            MaintainedComponentStatus {
+                name: String::from("sed"),
+                version: String::from("4.9"),
+                revision: String::from("1"),
+                upstream_version: None,
+                outdated: false,
+                open_change_requests: vec![],
+                last_build: None,
+            },
+        ];
+
+        assert_eq!(
+            status_table(&statuses),
+            "COMPONENT     VERSION  UPSTREAM       CHANGE REQUESTS  LAST BUILD\n\
+             library/zlib  1.3-0    1.3.1 (newer)  1                failure\n\
+             sed           4.9-1    -              0                -\n"
+        );
+    }
+}
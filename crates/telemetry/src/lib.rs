@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use deadpool_lapin::lapin::protocol::basic::AMQPProperties;
+use deadpool_lapin::lapin::types::{AMQPValue, FieldTable};
+use miette::Diagnostic;
+use opentelemetry::global;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::Resource;
+use thiserror::Error;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+/// Spans are exported over OTLP to this endpoint if it is set, otherwise only logged.
+pub const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Trace(#[from] opentelemetry::trace::TraceError),
+}
+
+pub type Result<T> = miette::Result<T, Error>;
+
+/// Set up logging for the service with `default_filter` unless `RUST_LOG` is set, and the
+/// export of its spans if an OTLP endpoint is configured. Has to run inside the tokio
+/// runtime the spans are exported from.
+pub fn init(service: &str, default_filter: &str) -> Result<()> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let otel = match std::env::var(OTLP_ENDPOINT_ENV) {
+        Ok(_) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+                .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+                    Resource::new(vec![KeyValue::new("service.name", service.to_string())]),
+                ))
+                .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        Err(_) => None,
+    };
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| default_filter.into()))
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel)
+        .init();
+    Ok(())
+}
+
+/// The trace context of the current span as W3C trace context fields, to store it with
+/// work that is sent on later.
+pub fn current_context() -> HashMap<String, String> {
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut carrier)
+    });
+    carrier
+}
+
+/// Add the trace context fields to the headers of a message.
+pub fn with_context(properties: AMQPProperties, carrier: &HashMap<String, String>) -> AMQPProperties {
+    if carrier.is_empty() {
+        return properties;
+    }
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    for (name, value) in carrier {
+        headers.insert(
+            name.clone().into(),
+            AMQPValue::LongString(value.clone().into()),
+        );
+    }
+    properties.with_headers(headers)
+}
+
+/// Properties of a message continuing the trace of the current span.
+pub fn inject(properties: AMQPProperties) -> AMQPProperties {
+    with_context(properties, &current_context())
+}
+
+/// The trace context fields in the headers of a message.
+pub fn extract(properties: &AMQPProperties) -> HashMap<String, String> {
+    properties
+        .headers()
+        .as_ref()
+        .map(FieldTable::inner)
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, value)| match value {
+            AMQPValue::LongString(value) => Some((
+                name.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).to_string(),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Make the span a child of the span that sent the message, so one trace covers the work
+/// on both sides of the queue. Messages without a trace context start a new trace.
+pub fn set_parent(span: &Span, properties: &AMQPProperties) {
+    let carrier = extract(properties);
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    span.set_parent(context);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_context_survives_the_message_headers() {
+        let carrier = HashMap::from([(
+            String::from("traceparent"),
+            String::from("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+        )]);
+        let properties = with_context(
+            AMQPProperties::default().with_correlation_id("c0ffee".into()),
+            &carrier,
+        );
+        assert_eq!(extract(&properties), carrier);
+        assert!(extract(&AMQPProperties::default()).is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Read};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, info};
+
+tokio::task_local! {
+    /// The cancellation of the job the task runs. Jobs run side by side, each in its
+    /// own task.
+    static JOB: Arc<Cancellation>;
+}
+
+/// Whether a job was cancelled and the process groups of the subprocesses it waits for.
+#[derive(Debug, Default)]
+pub struct Cancellation {
+    cancelled: AtomicBool,
+    process_groups: Mutex<BTreeSet<u32>>,
+}
+
+impl Cancellation {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Cancel the job by terminating its subprocesses together with everything they
+    /// started, like the compilers of a build.
+    pub fn cancel(&self) {
+        info!("cancelling the running job");
+        self.cancelled.store(true, Ordering::SeqCst);
+        let groups = self
+            .process_groups
+            .lock()
+            .map(|groups| groups.clone())
+            .unwrap_or_default();
+        for group in groups {
+            terminate(group);
+        }
+    }
+}
+
+/// Run the job `f` with `cancellation` deciding whether its commands may run.
+pub async fn scope<F: Future>(cancellation: Arc<Cancellation>, f: F) -> F::Output {
+    JOB.scope(cancellation, f).await
+}
+
+/// The cancellation of the job of the task, one that never happens outside of a job.
+pub fn current() -> Arc<Cancellation> {
+    JOB.try_with(Arc::clone).unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn terminate(group: u32) {
+    debug!("terminating process group {}", group);
+    let _ = Command::new("kill")
+        .arg("-TERM")
+        .arg("--")
+        .arg(format!("-{}", group))
+        .status();
+}
+
+/// Windows has no process groups to signal, the tree of processes below the command
+/// is killed instead.
+#[cfg(windows)]
+fn terminate(group: u32) {
+    debug!("terminating process tree {}", group);
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID"])
+        .arg(group.to_string())
+        .status();
+}
+
+/// Run `cmd` to completion like [`Command::output`] in its own process group, so a
+/// cancellation can terminate it. Jobs cancelled before fail right away. The output is
+/// forwarded to the job log line by line while the command runs.
+pub fn output(cmd: &mut Command) -> io::Result<Output> {
+    let job = current();
+    if job.is_cancelled() {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "job was cancelled",
+        ));
+    }
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let mut child = cmd
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    let group = child.id();
+    if let Ok(mut groups) = job.process_groups.lock() {
+        groups.insert(group);
+    }
+    // The job may have been cancelled while the command started
+    if job.is_cancelled() {
+        terminate(group);
+    }
+    // The readers run on their own threads and only see the log of the job through this
+    let sink = crate::joblog::sink();
+    let stdout = child.stdout.take().map(|pipe| {
+        let sink = sink.clone();
+        thread::spawn(move || forward_lines(pipe, sink))
+    });
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| thread::spawn(move || forward_lines(pipe, sink)));
+    // Other jobs keep running on the runtime while this one waits for its command
+    let status = tokio::task::block_in_place(|| child.wait());
+    if let Ok(mut groups) = job.process_groups.lock() {
+        groups.remove(&group);
+    }
+    Ok(Output {
+        status: status?,
+        stdout: join_output(stdout)?,
+        stderr: join_output(stderr)?,
+    })
+}
+
+/// Read the pipe to the end, forwarding every line to the job log if it has one.
+fn forward_lines(pipe: impl Read, sink: Option<UnboundedSender<String>>) -> io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(pipe);
+    let mut output = vec![];
+    let mut line = vec![];
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        if let Some(sink) = &sink {
+            let _ = sink.send(
+                String::from_utf8_lossy(&line)
+                    .trim_end_matches(['\n', '\r'])
+                    .to_string(),
+            );
+        }
+        output.append(&mut line);
+    }
+    Ok(output)
+}
+
+fn join_output(reader: Option<thread::JoinHandle<io::Result<Vec<u8>>>>) -> io::Result<Vec<u8>> {
+    match reader {
+        Some(reader) => reader
+            .join()
+            .map_err(|_| io::Error::other("reading the output of the command failed"))?,
+        None => Ok(vec![]),
+    }
+}
@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::time::Duration;
+
+use deadpool_lapin::lapin::options::BasicPublishOptions;
+use deadpool_lapin::lapin::protocol::basic::AMQPProperties;
+use deadpool_lapin::lapin::Channel;
+use forge::JobLogChunk;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tracing::error;
+
+/// Lines sent to forged in one message at most.
+const CHUNK_LINES: usize = 100;
+
+/// How long lines wait for more before they are sent anyway.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+tokio::task_local! {
+    /// Where the output of the job the task runs goes. Jobs run side by side, each in its
+    /// own task with its own log.
+    static SINK: UnboundedSender<String>;
+}
+
+/// Where the output of the job of the task goes, unset for jobs without a log.
+pub fn sink() -> Option<UnboundedSender<String>> {
+    SINK.try_with(UnboundedSender::clone).ok()
+}
+
+/// A log of a job being sent to forged.
+pub struct JobLog {
+    sender: UnboundedSender<String>,
+    shipper: JoinHandle<()>,
+}
+
+/// Start sending the output of the job to forged in chunks.
+pub fn start(
+    channel: Channel,
+    inbox_name: String,
+    job_id: String,
+    correlation_id: String,
+) -> JobLog {
+    let (sender, receiver) = unbounded_channel();
+    JobLog {
+        sender,
+        shipper: tokio::spawn(ship(channel, inbox_name, job_id, correlation_id, receiver)),
+    }
+}
+
+/// Run the job `f` with its output going to `log`, or nowhere without one.
+pub async fn scope<F: Future>(log: Option<&JobLog>, f: F) -> F::Output {
+    match log {
+        Some(log) => SINK.scope(log.sender.clone(), f).await,
+        None => f.await,
+    }
+}
+
+/// Stop forwarding output and wait until the lines of the job were sent, so they arrive
+/// before its report.
+pub async fn finish(log: JobLog) {
+    let JobLog { sender, shipper } = log;
+    drop(sender);
+    if let Err(e) = shipper.await {
+        error!("could not send the log of the job: {}", e);
+    }
+}
+
+async fn ship(
+    channel: Channel,
+    inbox_name: String,
+    job_id: String,
+    correlation_id: String,
+    mut receiver: UnboundedReceiver<String>,
+) {
+    let mut first_line = 0;
+    let mut lines = vec![];
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        let (flush, closed) = tokio::select! {
+            line = receiver.recv() => match line {
+                Some(line) => {
+                    lines.push(line);
+                    (lines.len() >= CHUNK_LINES, false)
+                }
+                None => (true, true),
+            },
+            _ = interval.tick() => (true, false),
+        };
+        if flush && !lines.is_empty() {
+            let chunk = JobLogChunk {
+                job_id: job_id.clone(),
+                first_line,
+                lines: std::mem::take(&mut lines),
+            };
+            first_line += chunk.lines.len() as u32;
+            if let Err(e) = publish(&channel, &inbox_name, &correlation_id, &chunk).await {
+                error!(
+                    "could not send {} lines of the job log: {}",
+                    chunk.lines.len(),
+                    e
+                );
+            }
+        }
+        if closed {
+            break;
+        }
+    }
+}
+
+async fn publish(
+    channel: &Channel,
+    inbox_name: &str,
+    correlation_id: &str,
+    chunk: &JobLogChunk,
+) -> crate::Result<()> {
+    channel
+        .basic_publish(
+            inbox_name,
+            "forged.joblog",
+            BasicPublishOptions::default(),
+            &serde_json::to_vec(chunk)?,
+            AMQPProperties::default().with_correlation_id(correlation_id.into()),
+        )
+        .await?;
+    Ok(())
+}
@@ -7,29 +7,48 @@ use deadpool_lapin::lapin::message::Delivery;
 use deadpool_lapin::lapin::options::QueueBindOptions;
 use deadpool_lapin::lapin::options::QueueDeclareOptions;
 use deadpool_lapin::lapin::options::{
-    BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions,
+    BasicAckOptions, BasicCancelOptions, BasicConsumeOptions, BasicGetOptions,
+    BasicNackOptions, BasicPublishOptions, BasicQosOptions, QueueDeleteOptions,
 };
 use deadpool_lapin::lapin::protocol::basic::AMQPProperties;
+use deadpool_lapin::lapin::types::AMQPValue;
 use deadpool_lapin::lapin::{types::FieldTable, Channel};
-use forge::{CommitRef, Scheme, Job, JobReport, JobReportData, PatchFile};
+use forge::provenance::{
+    self, Artifact, BuildDefinition, BuildMetadata, BuildParameters, Builder, Provenance,
+    RunDetails, Statement,
+};
+use forge::{
+    CheckResult, CommitRef, ComponentFileKind, ComponentId, FailureCategory, Job, JobControl, JobKind,
+    JobObject, JobReport, JobReportData, PatchFile, Scheme, SourceCredential,
+};
 use futures::{join, StreamExt};
 use github::GitHubError;
-use integration::{read_forge_manifest, ForgeIntegrationManifest};
+use integration::{read_forge_manifest, ForgeIntegrationManifest, ScriptShell};
 use itertools::Itertools;
 use miette::Diagnostic;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{create_dir_all, remove_dir_all};
 use std::future::IntoFuture;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
 use base64::Engine;
 use thiserror::Error;
 use tokio::net::TcpListener;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::task::JoinHandle;
 use tracing::trace;
 use tracing::{debug, error, event, info, instrument, Level};
 use url::Url;
 
+mod cancel;
+mod joblog;
+mod platform;
+mod sandbox;
+mod vcs;
+
 #[derive(Error, Diagnostic, Debug)]
 pub enum Error {
     #[error(transparent)]
@@ -68,19 +87,36 @@ pub enum Error {
     #[error(transparent)]
     IntegrationError(#[from] integration::IntegrationError),
 
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+
     #[error(transparent)]
     #[diagnostic(transparent)]
     ComponentError(#[from] component::ComponentError),
 
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    GateError(#[from] gate::GateError),
+
     #[error("{0}")]
     String(String),
 
-    #[error("git {0} failed output: {1}")]
-    GitError(String, String),
+    #[error("git {0} failed: {1}")]
+    Git(&'static str, #[source] git2::Error),
 
     #[error("{0} failed output: {1}")]
     ScriptError(String, String),
 
+    #[error("{command} failed with {} output: {output}", category.map(|c| c.to_string()).unwrap_or(String::from("an unknown error")))]
+    Pkgdev {
+        command: String,
+        category: Option<FailureCategory>,
+        output: String,
+    },
+
     #[error("no .forge folder found in repo")]
     NoForgeConfigFolder,
 
@@ -89,6 +125,22 @@ pub enum Error {
 
     #[error("no .forge/manifest.toml,yaml,json file found")]
     NoForgeManifest,
+
+    #[error("{0} is not a plain file name")]
+    InvalidFileName(String),
+}
+
+impl Error {
+    /// Whether pkgdev failed in a way that may succeed when the job is retried.
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Error::Pkgdev {
+                category: Some(FailureCategory::Transient),
+                ..
+            }
+        )
+    }
 }
 
 impl IntoResponse for Error {
@@ -115,16 +167,49 @@ pub struct Config {
     domain: String,
     scheme: String,
     directory: String,
+    #[serde(default)]
+    oneshot: bool,
+    /// Credentials for cloning private gate repositories and fetching private sources.
+    /// They serve the jobs of every gate, forged does not hand out credentials per gate.
+    /// An API key for the url of forged fetches stored components and lets builds use
+    /// its source mirror.
+    #[serde(default)]
+    source_credentials: Vec<SourceCredential>,
+    /// Fetch only the commits a job looks at instead of the whole history of gate
+    /// repositories
+    #[serde(default)]
+    shallow_clone: bool,
+    /// How the scripts of the integration manifests of gate repositories are confined
+    #[serde(default)]
+    script_sandbox: sandbox::Sandbox,
+    /// Jobs run at the same time, each in its own directory below `directory`
+    concurrent_jobs: u16,
+    /// Jobs RabbitMQ hands the worker before it acknowledged the previous ones, the
+    /// number of concurrent jobs if unset
+    #[serde(default)]
+    prefetch: Option<u16>,
+    /// Names the worker as the builder in the provenance of its builds, the host name
+    /// if unset
+    #[serde(default)]
+    builder_id: Option<String>,
+    /// Directory pkgdev keeps the package repositories in. Workers that stage and
+    /// promote for the same forge must share it, the data directory of pkgdev if unset
+    #[serde(default)]
+    repository_dir: Option<String>,
 }
 
 #[derive(Parser)]
 pub struct Args {
     rabbitmq_url: Option<String>,
+    /// Process a single job and exit instead of waiting for jobs. Exits right away
+    /// when no job is queued
+    #[arg(long)]
+    oneshot: bool,
 }
 
 pub fn load_config(args: Args) -> Result<Config> {
     let cfg = config::Config::builder()
-        .add_source(File::with_name("/etc/forge/worker").required(false))
+        .add_source(File::with_name(&platform::config_path().to_string_lossy()).required(false))
         .add_source(
             Environment::with_prefix("WORKER")
                 .separator("_")
@@ -134,7 +219,9 @@ pub fn load_config(args: Args) -> Result<Config> {
         .set_default("job_inbox", "JOB_INBOX")?
         .set_default("inbox", "INBOX")?
         .set_default("scheme", Scheme::HTTPS.to_string())?
+        .set_default("concurrent_jobs", 1)?
         .set_override_option("amqp.url", args.rabbitmq_url)?
+        .set_override("oneshot", args.oneshot)?
         .build()?;
 
     Ok(cfg.try_deserialize()?)
@@ -148,6 +235,13 @@ struct AppState {
     job_inbox: String,
     base_url: Url,
     worker_dir: String,
+    source_credentials: Vec<SourceCredential>,
+    shallow_clone: bool,
+    sandbox: sandbox::Sandbox,
+    concurrent_jobs: u16,
+    prefetch: u16,
+    builder_id: String,
+    repository_dir: Option<String>,
 }
 
 pub async fn listen(cfg: Config) -> Result<()> {
@@ -160,6 +254,13 @@ pub async fn listen(cfg: Config) -> Result<()> {
         job_inbox: cfg.job_inbox,
         base_url: format!("{}://{}", Scheme::from(cfg.scheme), cfg.domain).parse()?,
         worker_dir: cfg.directory,
+        source_credentials: cfg.source_credentials,
+        shallow_clone: cfg.shallow_clone,
+        sandbox: cfg.script_sandbox,
+        concurrent_jobs: cfg.concurrent_jobs.max(1),
+        prefetch: cfg.prefetch.unwrap_or(cfg.concurrent_jobs).max(1),
+        builder_id: cfg.builder_id.unwrap_or_else(host_name),
+        repository_dir: cfg.repository_dir,
     };
     let conn = state.amqp.get().await?;
     debug!(
@@ -208,6 +309,10 @@ pub async fn listen(cfg: Config) -> Result<()> {
         )
         .await?;
 
+    if cfg.oneshot {
+        return process_one_job(state, &channel).await;
+    }
+
     let app = Router::new()
         .route("/healthz", get(health_check))
         //.layer(tower_http::trace::TraceLayer::new_for_http())
@@ -250,6 +355,9 @@ async fn handle_rabbitmq(state: AppState) -> Result<()> {
         .await
         .map_err(|e| Error::String(e.to_string()))?;
     let channel = rmq_con.create_channel().await?;
+    channel
+        .basic_qos(state.prefetch, BasicQosOptions::default())
+        .await?;
 
     let mut consumer = channel
         .basic_consume(
@@ -260,68 +368,203 @@ async fn handle_rabbitmq(state: AppState) -> Result<()> {
         )
         .await?;
 
-    info!("amqp consumer connected, waiting for messages");
+    // A slot is a directory jobs run in, every running job holds one
+    let (slot_sender, mut free_slots) = unbounded_channel();
+    for slot in 0..state.concurrent_jobs {
+        let _ = slot_sender.send(slot);
+    }
+
+    info!(
+        "amqp consumer connected, waiting for messages to run {} at a time",
+        state.concurrent_jobs
+    );
     while let Some(delivery) = consumer.next().await {
-        match delivery {
-            Ok(delivery) => {
-                let tag = delivery.delivery_tag;
-                match handle_message(
-                    delivery,
-                    &channel,
-                    &state.inbox,
-                    &state.worker_dir,
-                )
-                .await
-                {
-                    Ok(_) => {
-                        debug!("handled message");
-                        channel.basic_ack(tag, BasicAckOptions::default()).await?;
-                    }
-                    Err(e) => {
-                        error!(error = ?e, "failed to handle message");
-                        channel.basic_nack(tag, BasicNackOptions::default()).await?;
-                    }
-                }
+        let delivery = delivery.map_err(|err| Error::String(err.to_string()))?;
+        let Some(slot) = free_slots.recv().await else {
+            break;
+        };
+        let state = state.clone();
+        let channel = channel.clone();
+        let slot_sender = slot_sender.clone();
+        tokio::spawn(async move {
+            let worker_dir = slot_dir(&state.worker_dir, slot, state.concurrent_jobs);
+            if let Err(e) = process_delivery(&state, &channel, &worker_dir, delivery).await {
+                error!(error = e.to_string(), "could not acknowledge job");
             }
-            Err(err) => return Err(Error::String(err.to_string())),
+            let _ = slot_sender.send(slot);
+        });
+    }
+
+    Ok(())
+}
+
+/// The directory the jobs of a slot run in. A worker running one job at a time runs it
+/// in the worker directory itself.
+fn slot_dir(worker_dir: &str, slot: u16, concurrent_jobs: u16) -> String {
+    if concurrent_jobs <= 1 {
+        return worker_dir.to_string();
+    }
+    Path::new(worker_dir)
+        .join(format!("slot-{}", slot))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Take one job from the queue, process it and return. Lets job runners scale to zero.
+async fn process_one_job(state: AppState, channel: &Channel) -> Result<()> {
+    match channel
+        .basic_get(&state.job_inbox, BasicGetOptions::default())
+        .await?
+    {
+        Some(message) => {
+            process_delivery(&state, channel, &state.worker_dir, message.delivery).await
+        }
+        None => {
+            info!("no job queued, exiting");
+            Ok(())
         }
     }
+}
 
+/// Handle the job of the delivery and acknowledge it. Failed jobs are requeued when
+/// the failure may go away.
+async fn process_delivery(
+    state: &AppState,
+    channel: &Channel,
+    worker_dir: &str,
+    delivery: Delivery,
+) -> Result<()> {
+    let tag = delivery.delivery_tag;
+    match handle_message(delivery, channel, state, worker_dir).await {
+        Ok(_) => {
+            debug!("handled message");
+            channel.basic_ack(tag, BasicAckOptions::default()).await?;
+        }
+        Err(e) => {
+            error!(error = ?e, "failed to handle message");
+            channel
+                .basic_nack(
+                    tag,
+                    BasicNackOptions {
+                        requeue: e.is_transient(),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+        }
+    }
     Ok(())
 }
 
-#[instrument(skip_all)]
+#[instrument(skip_all, fields(correlation_id = tracing::field::Empty))]
 async fn handle_message(
     delivery: Delivery,
     channel: &Channel,
-    inbox_name: &str,
+    state: &AppState,
     worker_dir: &str,
 ) -> Result<()> {
+    // Jobs forged dispatched carry the id of the webhook or request that caused them
+    let correlation_id = delivery
+        .properties
+        .correlation_id()
+        .as_ref()
+        .map(|id| id.to_string())
+        .unwrap_or_else(forge::new_correlation_id);
+    tracing::Span::current().record("correlation_id", correlation_id.as_str());
+    telemetry::set_parent(&tracing::Span::current(), &delivery.properties);
+    // Jobs forged dispatched carry their id in the queue, cancellations are sent on the
+    // control queue of that id
+    let job_id = delivery
+        .properties
+        .message_id()
+        .as_ref()
+        .map(|id| id.to_string());
+    let redelivered = delivery.redelivered;
     let body = delivery.data;
     let job: Job = serde_json::from_slice(&body)?;
-    let job_report: JobReport = match job {
+    let object = job.object();
+    let kind = job.kind();
+
+    let cancellation = cancel::Cancellation::new();
+    let control = match &job_id {
+        Some(job_id) => Some(watch_job_control(channel, job_id, cancellation.clone()).await?),
+        None => None,
+    };
+    let log = job_id.as_ref().map(|job_id| {
+        joblog::start(
+            channel.clone(),
+            state.inbox.clone(),
+            job_id.clone(),
+            correlation_id.clone(),
+        )
+    });
+    let outcome = cancel::scope(
+        cancellation.clone(),
+        joblog::scope(
+            log.as_ref(),
+            run_job(job, state, worker_dir, &correlation_id, redelivered),
+        ),
+    )
+    .await;
+    if let (Some(job_id), Some(control)) = (&job_id, control) {
+        stop_watching_job_control(channel, job_id, control).await;
+    }
+    if let Some(log) = log {
+        joblog::finish(log).await;
+    }
+    // Whatever failed after the cancellation failed because of it
+    let job_report = if cancellation.is_cancelled() {
+        JobReport::Cancelled { object, kind }
+    } else {
+        outcome?
+    };
+
+    event!(Level::DEBUG, report = ?job_report, "Sent this Report to forged");
+
+    let msg = serde_json::to_vec(&job_report)?;
+
+    channel
+        .basic_publish(
+            &state.inbox,
+            "forged.jobreport",
+            BasicPublishOptions::default(),
+            &msg,
+            // The report continues the trace of the job in forged
+            telemetry::inject(
+                AMQPProperties::default().with_correlation_id(correlation_id.as_str().into()),
+            ),
+        )
+        .await?;
+    event!(Level::INFO, "Job finished");
+    Ok(())
+}
+
+/// Run the job and report its outcome. Failures the job should be retried for are
+/// returned as errors.
+async fn run_job(
+    job: Job,
+    state: &AppState,
+    worker_dir: &str,
+    correlation_id: &str,
+    redelivered: bool,
+) -> Result<JobReport> {
+    let mirror_dir = Path::new(&state.worker_dir).join("mirrors");
+    let git = &vcs::Git::new(&state.source_credentials, state.shallow_clone, &mirror_dir);
+    let sandbox = &state.sandbox;
+    Ok(match job {
         Job::GetRecipes { cr_id, gate_id, cr } => {
             info!("getting recipes for change_request {}", cr.id);
             let build_dir = get_repo_path(worker_dir, &cr.git_url, &cr.head.sha);
             debug!("cleaning workspace {}", &build_dir.display());
             clean_ws(&build_dir)?;
-            debug!("cloning repo {}", &cr.git_url);
-            let manifest = clone_repo(&build_dir, &cr.git_url, &cr.head, None)?;
-            let component_list = get_component_list_in_repo(&build_dir, &manifest)?;
-            let changed_files = get_changed_files(&build_dir, &cr.base)?;
-            let changed_components = get_changed_components(component_list, changed_files);
-            create_gen_meatdata_script(&build_dir, &manifest)?;
-            let mut recipes: Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)> = vec![];
-            for component in changed_components {
-                let (recipe, package_meta) = get_component_metadata(
-                    &build_dir,
-                    &component,
-                    manifest.change_to_component_dir,
-                    &manifest.component_metadata_filename,
-                )?;
-                let patches = get_component_patches(&build_dir, &component, &recipe)?;
-                recipes.push((component, recipe, package_meta, patches));
-            }
+            debug!("cloning repo {}", forge::redact_url(&cr.git_url));
+            let (repo, manifest) = clone_repo(&build_dir, git, &cr.git_url, &cr.head, None)?;
+            let component_list = get_component_list_in_repo(&build_dir, &manifest, sandbox)?;
+            let changed_files = git.changed_files(&repo, &cr.base.sha)?;
+            let changed_components =
+                get_changed_components(&manifest, component_list, changed_files);
+            let recipes =
+                read_component_recipes(&build_dir, &manifest, sandbox, changed_components)?;
             debug!("Fetched recipes successfully");
 
             JobReport::Success(JobReportData::GetRecipes {
@@ -330,42 +573,633 @@ async fn handle_message(
                 recipes,
             })
         }
-    };
+        Job::BuildStoredComponent {
+            component_id,
+            staging,
+            bootstrap,
+        } => {
+            info!("building stored component {}", component_id);
+            // A failed build is reported instead of requeued, retrying will not fix the recipe.
+            // Network failures are retried once.
+            match build_stored_component(
+                &component_id,
+                staging.as_deref(),
+                bootstrap,
+                &state.base_url,
+                worker_dir,
+                &state.source_credentials,
+                &correlation_id,
+                &state.builder_id,
+                state.repository_dir.as_deref(),
+            )
+            .await
+            {
+                Err(e) if e.is_transient() && !redelivered => return Err(e),
+                Ok((packages, provenance)) => {
+                    JobReport::Success(JobReportData::BuildStoredComponent {
+                        checks: read_check_results(&component_id, worker_dir),
+                        network_hosts: read_network_hosts(&component_id, worker_dir),
+                        component_id,
+                        packages,
+                        staging,
+                        bootstrap,
+                        provenance,
+                    })
+                }
+                Err(e) => JobReport::Failure {
+                    checks: read_check_results(&component_id, worker_dir),
+                    network_hosts: read_network_hosts(&component_id, worker_dir),
+                    object: JobObject::Component {
+                        component_id,
+                        staging,
+                        bootstrap,
+                    },
+                    error: e.to_string(),
+                    kind: JobKind::BuildStoredComponent,
+                },
+            }
+        }
+        Job::PromoteStagedPackages {
+            change_request_id,
+            staging,
+        } => {
+            info!(
+                "promoting staging repository {} of change request {}",
+                staging, change_request_id
+            );
+            match promote_staged_packages(&staging, None, state.repository_dir.as_deref()) {
+                Err(e) if e.is_transient() && !redelivered => return Err(e),
+                Ok(packages) => {
+                    JobReport::Success(JobReportData::PromoteStagedPackages { staging, packages })
+                }
+                Err(e) => JobReport::Failure {
+                    object: JobObject::StagingRepository { name: staging },
+                    error: e.to_string(),
+                    kind: JobKind::PromoteStagedPackages,
+                    checks: vec![],
+                    network_hosts: vec![],
+                },
+            }
+        }
+        Job::SyncGate {
+            gate_id,
+            git_url,
+            branch,
+            before,
+            after,
+        } => {
+            info!("syncing gate {} with {} of {}", gate_id, branch, git_url);
+            let head = CommitRef {
+                sha: after.clone(),
+                ref_name: branch,
+            };
+            let recipes = read_gate_recipes(worker_dir, git, sandbox, &git_url, &head, before)?;
+            JobReport::Success(JobReportData::SyncGate {
+                gate_id,
+                commit: after,
+                recipes,
+            })
+        }
+        Job::ReleaseGate {
+            gate_id,
+            git_url,
+            tag,
+        } => {
+            info!("reading release {} of gate {} from {}", tag, gate_id, git_url);
+            // git resolves the tag like a commit
+            let head = CommitRef {
+                sha: tag.clone(),
+                ref_name: tag.clone(),
+            };
+            let recipes = read_gate_recipes(worker_dir, git, sandbox, &git_url, &head, None)?;
+            JobReport::Success(JobReportData::ReleaseGate {
+                gate_id,
+                tag,
+                recipes,
+            })
+        }
+        Job::PublishRelease {
+            gate_id,
+            tag,
+            staging,
+            snapshot,
+        } => {
+            info!("publishing release {} of gate {} as {}", tag, gate_id, snapshot);
+            match promote_staged_packages(
+                &staging,
+                Some(&snapshot),
+                state.repository_dir.as_deref(),
+            ) {
+                Err(e) if e.is_transient() && !redelivered => return Err(e),
+                Ok(packages) => JobReport::Success(JobReportData::PublishRelease {
+                    gate_id,
+                    tag,
+                    staging,
+                    packages,
+                }),
+                Err(e) => JobReport::Failure {
+                    object: JobObject::StagingRepository { name: staging },
+                    error: e.to_string(),
+                    kind: JobKind::PublishRelease,
+                    checks: vec![],
+                    network_hosts: vec![],
+                },
+            }
+        }
+        Job::SyncGateConfig {
+            gate_id,
+            git_url,
+            branch,
+            commit,
+        } => {
+            info!(
+                "reading {} of gate {} at {} of {}",
+                forge::GATE_CONFIG_FILE,
+                gate_id,
+                branch,
+                git_url
+            );
+            // A broken gate.kdl is reported instead of requeued, it stays broken until the
+            // next push.
+            match read_gate_config(worker_dir, git, &git_url, &commit) {
+                Err(Error::GateError(e)) => JobReport::Failure {
+                    object: JobObject::GateConfig { gate_id, commit },
+                    error: e.to_string(),
+                    kind: JobKind::SyncGateConfig,
+                    checks: vec![],
+                    network_hosts: vec![],
+                },
+                Err(e) => return Err(e),
+                Ok(gate) => JobReport::Success(JobReportData::SyncGateConfig {
+                    gate_id,
+                    commit,
+                    gate,
+                }),
+            }
+        }
+    })
+}
 
-    event!(Level::DEBUG, report = ?job_report, "Sent this Report to forged");
+/// Clone the gate repository at `head` and read the recipes of the components changed
+/// since `before`, or of every component without it.
+fn read_gate_recipes(
+    worker_dir: &str,
+    git: &vcs::Git<'_>,
+    sandbox: &sandbox::Sandbox,
+    git_url: &str,
+    head: &CommitRef,
+    before: Option<String>,
+) -> Result<Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)>> {
+    let build_dir = get_repo_path(worker_dir, git_url, &head.sha);
+    debug!("cleaning workspace {}", &build_dir.display());
+    clean_ws(&build_dir)?;
+    debug!("cloning repo {}", forge::redact_url(git_url));
+    let (repo, manifest) = clone_repo(&build_dir, git, git_url, head, None)?;
+    let component_list = get_component_list_in_repo(&build_dir, &manifest, sandbox)?;
+    // A new branch has no previous commit to compare with, all its components are read
+    let components = match before {
+        Some(before) => {
+            let changed_files = git.changed_files(&repo, &before)?;
+            get_changed_components(&manifest, component_list, changed_files)
+        }
+        None => component_list
+            .into_iter()
+            .filter(|c| !c.is_empty())
+            .collect(),
+    };
+    let recipes = read_component_recipes(&build_dir, &manifest, sandbox, components)?;
+    debug!("Fetched recipes of {} components", recipes.len());
+    Ok(recipes)
+}
 
-    let msg = serde_json::to_vec(&job_report)?;
+/// Clone the gate repository at `commit` and read the gate from its
+/// [`forge::GATE_CONFIG_FILE`].
+fn read_gate_config(
+    worker_dir: &str,
+    git: &vcs::Git<'_>,
+    git_url: &str,
+    commit: &str,
+) -> Result<gate::Gate> {
+    let build_dir = get_repo_path(worker_dir, git_url, commit);
+    debug!("cleaning workspace {}", &build_dir.display());
+    clean_ws(&build_dir)?;
+    debug!("cloning repo {}", forge::redact_url(git_url));
+    let repo = git.clone(git_url, &build_dir)?;
+    git.checkout(&repo, commit)?;
+    Ok(gate::Gate::new(build_dir.join(forge::GATE_CONFIG_FILE))?)
+}
 
+/// Read the recipe, package metadata and patches of the components in the cloned
+/// repository.
+fn read_component_recipes(
+    build_dir: &Path,
+    manifest: &ForgeIntegrationManifest,
+    sandbox: &sandbox::Sandbox,
+    components: Vec<String>,
+) -> Result<Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)>> {
+    if !manifest.native {
+        create_gen_meatdata_script(build_dir, manifest)?;
+    }
+    let mut recipes: Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)> = vec![];
+    for component in components {
+        let (recipe, package_meta) =
+            get_component_metadata(build_dir, &component, manifest, sandbox)?;
+        let component_path = manifest.component_path(&component);
+        let patches = get_component_patches(build_dir, &component_path, &recipe)?;
+        recipes.push((component, recipe, package_meta, patches));
+    }
+    Ok(recipes)
+}
+/// Listen on the control queue forged declared for the job and cancel the job when
+/// forged asks to.
+async fn watch_job_control(
+    channel: &Channel,
+    job_id: &str,
+    cancellation: Arc<cancel::Cancellation>,
+) -> Result<JoinHandle<()>> {
+    let queue = forge::job_control_queue(job_id);
+    let mut arguments = FieldTable::default();
+    arguments.insert(
+        "x-expires".into(),
+        AMQPValue::LongLongInt(forge::JOB_CONTROL_QUEUE_EXPIRES_MS),
+    );
     channel
-        .basic_publish(
-            inbox_name,
-            "forged.jobreport",
-            BasicPublishOptions::default(),
-            &msg,
-            AMQPProperties::default(),
+        .queue_declare(&queue, QueueDeclareOptions::default(), arguments)
+        .await?;
+    let mut consumer = channel
+        .basic_consume(
+            &queue,
+            &control_consumer_tag(job_id),
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
         )
         .await?;
-    event!(Level::INFO, "Job finished");
-    Ok(())
+    Ok(tokio::spawn(async move {
+        while let Some(Ok(delivery)) = consumer.next().await {
+            if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                error!("could not acknowledge job control message: {}", e);
+            }
+            match serde_json::from_slice::<JobControl>(&delivery.data) {
+                Ok(JobControl::Cancel) => cancellation.cancel(),
+                Err(e) => error!("invalid job control message: {}", e),
+            }
+        }
+    }))
 }
 
-#[instrument(skip_all)]
-fn get_changed_components(component_list: Vec<String>, changed_files: Vec<String>) -> Vec<String> {
-    let mut changed_components: Vec<String> = vec![];
-    'outer: for file_path in changed_files.iter() {
-        trace!("checking if file: {} is a changed component", &file_path);
-        for component in component_list.iter() {
-            if file_path.contains(component) {
-                trace!("detected changed component {}", component);
-                changed_components.push(component.clone());
-                continue 'outer;
-            }
+/// Stop listening for control messages and remove the control queue of the finished job.
+async fn stop_watching_job_control(channel: &Channel, job_id: &str, control: JoinHandle<()>) {
+    control.abort();
+    if let Err(e) = channel
+        .basic_cancel(&control_consumer_tag(job_id), BasicCancelOptions::default())
+        .await
+    {
+        error!("could not stop listening on the control queue: {}", e);
+    }
+    if let Err(e) = channel
+        .queue_delete(&forge::job_control_queue(job_id), QueueDeleteOptions::default())
+        .await
+    {
+        error!("could not delete the control queue: {}", e);
+    }
+}
+
+fn control_consumer_tag(job_id: &str) -> String {
+    format!("worker.control.{}", job_id)
+}
+
+/// What forged stores about a component, see its /api/v1/components/bundle endpoint.
+#[derive(Deserialize)]
+struct StoredComponentBundle {
+    recipe: Recipe,
+    packages: PackageMeta,
+    patches: Vec<PatchFile>,
+    files: Vec<String>,
+    #[serde(default)]
+    manifest_template: Option<gate::ManifestTemplate>,
+}
+
+#[instrument(skip(base_url, credentials))]
+#[allow(clippy::too_many_arguments)]
+async fn build_stored_component(
+    component_id: &ComponentId,
+    staging: Option<&str>,
+    bootstrap: bool,
+    base_url: &Url,
+    worker_dir: &str,
+    credentials: &[SourceCredential],
+    correlation_id: &str,
+    builder_id: &str,
+    repository_dir: Option<&str>,
+) -> Result<(Vec<String>, Option<Statement>)> {
+    let started_on = chrono::Utc::now();
+    let client = reqwest::Client::new();
+    let identifier = serde_json::json!({
+        "name": &component_id.name,
+        "version": &component_id.version,
+        "revision": &component_id.revision,
+        "gate_id": component_id.gate_id.to_string(),
+    });
+
+    // forged only hands out stored components to authenticated callers
+    let authorization = SourceCredential::find(credentials, base_url.as_str())
+        .and_then(|credential| credential.authorization());
+    let post = |path: &str| -> Result<reqwest::RequestBuilder> {
+        let mut request = client
+            .post(base_url.join(path)?)
+            .header(forge::CORRELATION_ID_HEADER, correlation_id);
+        if let Some(authorization) = &authorization {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization);
         }
+        Ok(request)
+    };
+
+    debug!("fetching stored component {}", component_id);
+    let bundle: StoredComponentBundle = post("/api/v1/components/bundle")?
+        .json(&identifier)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let build_dir = stored_build_dir(component_id, worker_dir);
+    debug!("cleaning workspace {}", &build_dir.display());
+    clean_ws(&build_dir)?;
+    let component_dir = build_dir.join("component");
+    let download_dir = build_dir.join("workspace").join("downloads");
+    create_dir_all(&component_dir)?;
+    create_dir_all(&download_dir)?;
+
+    let mut packages = bundle
+        .recipe
+        .package_sections
+        .iter()
+        .filter_map(|p| p.name.clone())
+        .collect::<Vec<_>>();
+    if packages.is_empty() {
+        packages.push(bundle.recipe.name.clone());
+    }
+
+    let recipe_sha256 = provenance::sha256(&serde_json::to_vec(&bundle.recipe)?);
+    let resolved_dependencies = provenance::resolved_dependencies(&bundle.recipe);
+
+    let mut component = Component::new(bundle.recipe.name.clone(), Some(&component_dir))?;
+    component.recipe = bundle.recipe;
+    component.save_document()?;
+    std::fs::write(
+        component_dir.join("pkg5"),
+        serde_json::to_vec(&bundle.packages)?,
+    )?;
+
+    for patch in bundle.patches {
+        let path = component_dir.join(plain_file_name(&patch.name)?);
+        let content = base64::engine::general_purpose::STANDARD.decode(patch.content)?;
+        std::fs::write(path, content)?;
+    }
+
+    // Archives go where pkgdev downloads them to so the build does not need upstream
+    for file in bundle.files {
+        let mut parts = file.split(':');
+        let (Some(kind), Some(_), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+            error!("stored file {file} has no valid name skipping");
+            continue;
+        };
+        let target = match kind.parse::<ComponentFileKind>() {
+            Ok(ComponentFileKind::Archive) => download_dir.join(plain_file_name(name)?),
+            Ok(ComponentFileKind::Script) => component_dir.join(plain_file_name(name)?),
+            _ => continue,
+        };
+        debug!("fetching stored file {}", &file);
+        let content = post("/api/v1/components/file")?
+            .json(&serde_json::json!({ "identifier": &identifier, "file": &file }))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        std::fs::write(target, content)?;
+    }
+
+    debug!("running pkgdev build for {}", component_id);
+    let mut cmd = Command::new("pkgdev");
+    cmd.arg("--workspace")
+        .arg(build_dir.join("workspace"))
+        .arg("build")
+        .arg("--component")
+        .arg(&component_dir)
+        .arg("--no-clean");
+    if let Some(staging) = staging {
+        cmd.arg("--staging").arg(staging);
+    }
+    if bootstrap {
+        cmd.arg("--bootstrap");
+    }
+    // Archives not stored with the component are fetched through the mirror of forged
+    cmd.env(forge::SOURCE_MIRROR_ENV, base_url.as_str());
+    // pkgdev deletes the file when it starts, the guard when pkgdev did not get to it
+    let credentials_file = if credentials.is_empty() {
+        None
+    } else {
+        Some(CredentialsFile::write(&build_dir, credentials)?)
+    };
+    if let Some(file) = &credentials_file {
+        cmd.env(forge::SOURCE_CREDENTIALS_FILE_ENV, &file.0);
+    }
+    if let Some(template) = &bundle.manifest_template {
+        cmd.env(forge::MANIFEST_TEMPLATE_ENV, serde_json::to_string(template)?);
+    }
+    if let Some(dir) = repository_dir {
+        cmd.env(forge::REPOSITORY_DIR_ENV, dir);
+    }
+    let out = cancel::output(&mut cmd);
+    drop(credentials_file);
+    let out = out?;
+    if !out.status.success() {
+        return Err(pkgdev_error("pkgdev build", out)?);
+    }
+
+    let artifacts = read_artifacts(component_id, worker_dir);
+    let provenance = (!artifacts.is_empty()).then(|| {
+        Statement::new(
+            artifacts,
+            Provenance {
+                build_definition: BuildDefinition {
+                    build_type: provenance::BUILD_TYPE.to_string(),
+                    external_parameters: BuildParameters {
+                        component: component_id.clone(),
+                        staging: staging.map(String::from),
+                        bootstrap,
+                        recipe_sha256,
+                    },
+                    resolved_dependencies,
+                },
+                run_details: RunDetails {
+                    builder: Builder {
+                        id: builder_id.to_string(),
+                        version: toolchain_versions(),
+                    },
+                    metadata: BuildMetadata {
+                        invocation_id: correlation_id.to_string(),
+                        started_on: started_on.to_rfc3339(),
+                        finished_on: chrono::Utc::now().to_rfc3339(),
+                    },
+                },
+            },
+        )
+    });
+
+    Ok((packages, provenance))
+}
+
+/// Tools whose version ends up in the provenance of builds, the ones not installed on
+/// the worker are left out.
+const TOOLCHAIN: &[&str] = &["pkgdev", "gcc", "g++", "clang", "rustc", "cmake", "meson", "gmake"];
+
+fn toolchain_versions() -> BTreeMap<String, String> {
+    TOOLCHAIN
+        .iter()
+        .filter_map(|tool| {
+            let out = Command::new(tool).arg("--version").output().ok()?;
+            if !out.status.success() {
+                return None;
+            }
+            let version = String::from_utf8_lossy(&out.stdout)
+                .lines()
+                .next()?
+                .trim()
+                .to_string();
+            Some((tool.to_string(), version))
+        })
+        .collect()
+}
+
+/// The name of the host the worker runs on.
+fn host_name() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: gethostname writes at most the length of the buffer into it
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return String::from("worker");
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).to_string()
+}
+
+/// Stored patches and files land in fixed directories and must not name anything outside
+fn plain_file_name(name: &str) -> Result<&str> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(std::path::Component::Normal(_)), None) => Ok(name),
+        _ => Err(Error::InvalidFileName(name.to_owned())),
+    }
+}
+
+fn stored_build_dir(component_id: &ComponentId, worker_dir: &str) -> PathBuf {
+    get_repo_path(
+        &Path::new(worker_dir).join("stored").to_string_lossy(),
+        &component_id.name,
+        &format!("{}-{}", component_id.version, component_id.revision),
+    )
+}
+
+/// The results of the recipe checks pkgdev wrote into the workspace. Builds that failed
+/// before the checks ran have none.
+fn read_check_results(component_id: &ComponentId, worker_dir: &str) -> Vec<CheckResult> {
+    let path = stored_build_dir(component_id, worker_dir)
+        .join("workspace")
+        .join(forge::CHECKS_REPORT_FILE);
+    match std::fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+            error!("could not parse check results {}: {}", path.display(), e);
+            vec![]
+        }),
+        Err(_) => vec![],
+    }
+}
+
+/// The packages pkgdev produced with their digests. Builds of pkgdev versions that do
+/// not record them have none.
+fn read_artifacts(component_id: &ComponentId, worker_dir: &str) -> Vec<Artifact> {
+    let path = stored_build_dir(component_id, worker_dir)
+        .join("workspace")
+        .join(provenance::ARTIFACTS_REPORT_FILE);
+    match std::fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+            error!("could not parse artifacts report {}: {}", path.display(), e);
+            vec![]
+        }),
+        Err(_) => vec![],
+    }
+}
+
+/// The hosts the build phase tried to reach, as recorded by pkgdev when the gate
+/// monitors the network of builds.
+fn read_network_hosts(component_id: &ComponentId, worker_dir: &str) -> Vec<String> {
+    let path = stored_build_dir(component_id, worker_dir)
+        .join("workspace")
+        .join(forge::NETWORK_REPORT_FILE);
+    match std::fs::read(&path) {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+            error!("could not parse network report {}: {}", path.display(), e);
+            vec![]
+        }),
+        Err(_) => vec![],
+    }
+}
+
+/// The error of a failed pkgdev run, categorized by its exit code.
+fn pkgdev_error(command: &str, out: std::process::Output) -> Result<Error> {
+    Ok(Error::Pkgdev {
+        command: command.to_string(),
+        category: out.status.code().and_then(FailureCategory::from_exit_code),
+        output: String::from_utf8(out.stderr)?,
+    })
+}
+
+/// Promote the staged packages with pkgdev, snapshotting the package repository afterwards
+/// if given, and return the FMRIs it promoted.
+#[instrument]
+fn promote_staged_packages(
+    staging: &str,
+    snapshot: Option<&str>,
+    repository_dir: Option<&str>,
+) -> Result<Vec<String>> {
+    let mut cmd = Command::new("pkgdev");
+    cmd.arg("promote").arg(staging);
+    if let Some(snapshot) = snapshot {
+        cmd.arg("--snapshot").arg(snapshot);
+    }
+    if let Some(dir) = repository_dir {
+        cmd.env(forge::REPOSITORY_DIR_ENV, dir);
+    }
+    let out = cancel::output(&mut cmd)?;
+    if !out.status.success() {
+        return Err(pkgdev_error("pkgdev promote", out)?);
     }
-    let changed_components = changed_components
-        .into_iter()
+    Ok(String::from_utf8(out.stdout)?
+        .lines()
+        .filter_map(|l| l.strip_prefix("Promoted "))
+        .map(|l| l.trim().to_string())
+        .collect())
+}
+
+/// The components owning the changed files, matched by the directories the manifest
+/// places them in.
+#[instrument(skip_all)]
+fn get_changed_components(
+    manifest: &ForgeIntegrationManifest,
+    component_list: Vec<String>,
+    changed_files: Vec<String>,
+) -> Vec<String> {
+    let changed_components = changed_files
+        .iter()
+        .filter_map(|file_path| {
+            trace!("checking if file: {} is a changed component", &file_path);
+            let component = manifest.component_of(&component_list, file_path)?;
+            trace!("detected changed component {}", component);
+            Some(component.clone())
+        })
         .unique()
-        .filter(|c| c != "")
         .collect();
     debug!(
         "the following components changed: {:?}",
@@ -374,50 +1208,45 @@ fn get_changed_components(component_list: Vec<String>, changed_files: Vec<String
     changed_components
 }
 
-#[instrument]
-fn get_changed_files<P: AsRef<Path> + std::fmt::Debug>(
-    ws: P,
-    target_branch_ref: &CommitRef,
-) -> Result<Vec<String>> {
-    debug!("Detecting changed files");
-    let target_sha = format!("{}..", &target_branch_ref.sha);
-
-    let mut diff_cmd = Command::new("git");
-    diff_cmd.arg("show");
-    diff_cmd.arg("--name-only");
-    diff_cmd.arg("--format=tformat:");
-    diff_cmd.arg(target_sha.as_str());
-    diff_cmd.current_dir(ws.as_ref());
-    let out = diff_cmd.output()?;
-    if !out.status.success() {
-        let out_string = String::from_utf8(out.stderr)?;
-        return Err(Error::GitError("diff".into(), out_string));
-    }
-    let result = String::from_utf8(out.stdout)?;
-    Ok(result.split("\n").map(|s| s.to_owned()).collect())
-}
-
 #[instrument]
 fn get_component_list_in_repo<P: AsRef<Path> + std::fmt::Debug>(
     ws: P,
     manifest: &ForgeIntegrationManifest,
+    sandbox: &sandbox::Sandbox,
 ) -> Result<Vec<String>> {
+    if manifest.native {
+        debug!("finding the components of the native repository");
+        return Ok(manifest.native_components(ws.as_ref())?);
+    }
+    let mut components = manifest.glob_components(ws.as_ref())?;
+    if manifest.component_list_script.is_empty() {
+        return Ok(components);
+    }
     debug!("creating list_component script");
-    let list_script_path = ws.as_ref().join(".forge_script_list_components.sh");
-    let mut list_script = std::fs::File::create(&list_script_path)?;
-    list_script.write_all(manifest.component_list_script.join("\n").as_bytes())?;
+    let list_script_path = write_script(
+        ws.as_ref(),
+        LIST_COMPONENTS_SCRIPT,
+        &manifest.component_list_script,
+        manifest.shell,
+    )?;
+    // The metadata scripts write into the repository as well
+    sandbox.prepare(ws.as_ref())?;
     debug!("running list_component script");
-    let mut script_cmd = Command::new("bash");
-    script_cmd.arg("-ex");
-    script_cmd.current_dir(ws.as_ref());
-    script_cmd.arg("./.forge_script_list_components.sh");
-    let out = script_cmd.output()?;
+    let mut script_cmd = sandbox.command(
+        ws.as_ref(),
+        ws.as_ref(),
+        &platform::script_command(&list_script_path, manifest.shell),
+        &manifest.env,
+    )?;
+    let out = cancel::output(&mut script_cmd)?;
     if !out.status.success() {
         let out_string = String::from_utf8(out.stderr)?;
-        return Err(Error::ScriptError("list_components.sh".into(), out_string));
+        return Err(Error::ScriptError("list_components".into(), out_string));
     }
     let result = String::from_utf8(out.stdout)?;
-    Ok(result.split("\n").map(|s| s.to_owned()).collect())
+    // Scripts on Windows end their lines with \r\n
+    components.extend(result.lines().map(|s| s.to_owned()));
+    Ok(components.into_iter().unique().collect())
 }
 
 #[instrument]
@@ -426,23 +1255,37 @@ fn create_gen_meatdata_script<P: AsRef<Path> + std::fmt::Debug>(
     manifest: &ForgeIntegrationManifest,
 ) -> Result<()> {
     debug!("creating gen metadata hepler script");
-    let list_script_path = ws.as_ref().join(".forge_script_components_gen_metadata.sh");
-    let mut list_script = std::fs::File::create(&list_script_path)?;
-    list_script.write_all(manifest.component_metadata_gen_script.join("\n").as_bytes())?;
+    let scripts = [
+        (PRE_METADATA_HOOK, &manifest.pre_metadata_hook),
+        (GEN_METADATA_SCRIPT, &manifest.component_metadata_gen_script),
+        (POST_METADATA_HOOK, &manifest.post_metadata_hook),
+    ];
+    for (name, lines) in scripts {
+        write_script(ws.as_ref(), name, lines, manifest.shell)?;
+    }
     Ok(())
 }
 
+const LIST_COMPONENTS_SCRIPT: &str = ".forge_script_list_components";
+const PRE_METADATA_HOOK: &str = ".forge_script_pre_metadata_hook";
+const GEN_METADATA_SCRIPT: &str = ".forge_script_components_gen_metadata";
+const POST_METADATA_HOOK: &str = ".forge_script_post_metadata_hook";
+
+/// Write the lines as script `name` for the shell into the workspace.
+fn write_script(ws: &Path, name: &str, lines: &[String], shell: ScriptShell) -> Result<PathBuf> {
+    let path = ws.join(platform::script_file_name(name, shell));
+    let mut script = std::fs::File::create(&path)?;
+    script.write_all(platform::script_contents(lines, shell).as_bytes())?;
+    Ok(path)
+}
+
 #[instrument]
 fn get_component_patches<P: AsRef<Path> + std::fmt::Debug>(
     ws: P,
-    component: &str,
+    component_path: &Path,
     recipe: &Recipe,
 ) -> Result<Vec<PatchFile>> {
-    let patch_base_path = ws
-        .as_ref()
-        .join("components")
-        .join(component)
-        .join("patches");
+    let patch_base_path = ws.as_ref().join(component_path).join("patches");
     let mut files = vec![];
     for src in &recipe.sources {
         for s in &src.sources {
@@ -476,37 +1319,58 @@ fn get_component_patches<P: AsRef<Path> + std::fmt::Debug>(
 fn get_component_metadata<P: AsRef<Path> + std::fmt::Debug>(
     ws: P,
     component: &str,
-    change_to_component_dir: bool,
-    metadata_file_name: &str,
+    manifest: &ForgeIntegrationManifest,
+    sandbox: &sandbox::Sandbox,
 ) -> Result<(Recipe, Option<PackageMeta>)> {
-    debug!("running create_metadata script");
-    let mut script_cmd = Command::new("bash");
-    script_cmd.arg("-ex");
-    script_cmd.arg(
-        ws.as_ref()
-            .join(".forge_script_components_gen_metadata.sh")
-            .as_os_str(),
-    );
-    if change_to_component_dir {
-        script_cmd.current_dir(ws.as_ref().join("components").join(component));
-    } else {
-        script_cmd.current_dir(ws.as_ref());
-        script_cmd.arg(component);
-    }
-    let out = script_cmd.output()?;
-    if !out.status.success() {
-        let out_string = String::from_utf8(out.stderr)?;
-        return Err(Error::ScriptError("gen_metadata.sh".into(), out_string));
+    let component_path = manifest.component_path(component);
+    // Native repositories keep the metadata files, there is nothing to run
+    if !manifest.native {
+        if !manifest.pre_metadata_hook.is_empty() {
+            debug!("running pre_metadata hook");
+            run_component_script(ws.as_ref(), PRE_METADATA_HOOK, component, manifest, sandbox)?;
+        }
+        debug!("running create_metadata script");
+        run_component_script(ws.as_ref(), GEN_METADATA_SCRIPT, component, manifest, sandbox)?;
+        if !manifest.post_metadata_hook.is_empty() {
+            debug!("running post_metadata hook");
+            run_component_script(ws.as_ref(), POST_METADATA_HOOK, component, manifest, sandbox)?;
+        }
     }
     let metadata_file_path = ws
         .as_ref()
-        .join("components")
-        .join(component)
-        .join(metadata_file_name);
+        .join(component_path)
+        .join(&manifest.component_metadata_filename);
     let c = Component::open_local(metadata_file_path)?;
     Ok((c.recipe, c.package_meta))
 }
 
+/// Run the script for the component in its directory, or with the component as argument
+/// from the root of the repository.
+fn run_component_script(
+    ws: &Path,
+    name: &str,
+    component: &str,
+    manifest: &ForgeIntegrationManifest,
+    sandbox: &sandbox::Sandbox,
+) -> Result<()> {
+    let script = ws.join(platform::script_file_name(name, manifest.shell));
+    let mut args = platform::script_command(&script, manifest.shell);
+    let dir = if manifest.change_to_component_dir {
+        ws.join(manifest.component_path(component))
+    } else {
+        args.push(component.into());
+        ws.to_path_buf()
+    };
+    let mut script_cmd = sandbox.command(ws, &dir, &args, &manifest.env)?;
+    let out = cancel::output(&mut script_cmd)?;
+    if !out.status.success() {
+        let out_string = String::from_utf8(out.stderr)?;
+        let name = name.trim_start_matches(".forge_script_");
+        return Err(Error::ScriptError(name.into(), out_string));
+    }
+    Ok(())
+}
+
 #[instrument]
 fn get_repo_path(base_dir: &str, repo: &str, sha: &str) -> PathBuf {
     let repo = repo
@@ -517,6 +1381,30 @@ fn get_repo_path(base_dir: &str, repo: &str, sha: &str) -> PathBuf {
     Path::new(base_dir).join(repo).join(sha).to_path_buf()
 }
 
+/// The file the source credentials are handed to pkgdev in, only the worker can read it.
+/// It is removed when dropped so no early return leaves it behind.
+struct CredentialsFile(PathBuf);
+
+impl CredentialsFile {
+    fn write(dir: &Path, credentials: &[SourceCredential]) -> Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+        let file = Self(dir.join("source-credentials.json"));
+        let mut out = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&file.0)?;
+        out.write_all(&serde_json::to_vec(credentials)?)?;
+        Ok(file)
+    }
+}
+
+impl Drop for CredentialsFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
 #[instrument]
 fn clean_ws<P: AsRef<Path> + std::fmt::Debug>(dir: P) -> Result<()> {
     let path = dir.as_ref();
@@ -527,75 +1415,35 @@ fn clean_ws<P: AsRef<Path> + std::fmt::Debug>(dir: P) -> Result<()> {
     Ok(())
 }
 
-#[instrument]
+/// Clone the repository and check out `checkout_ref`, reading the manifest at `conf_ref`
+/// if the configuration comes from another commit.
+#[instrument(skip(git, repository))]
 fn clone_repo<P: AsRef<Path> + std::fmt::Debug>(
     ws: P,
+    git: &vcs::Git<'_>,
     repository: &str,
     checkout_ref: &CommitRef,
     conf_ref: Option<String>,
-) -> Result<ForgeIntegrationManifest> {
-    debug!(
-        "running git clone {} {}",
-        &repository,
-        ws.as_ref().display()
-    );
-    let mut git_cmd = Command::new("git");
-    git_cmd.arg("clone");
-    git_cmd.arg(&repository);
-    git_cmd.arg(ws.as_ref().as_os_str());
-    let out = git_cmd.output()?;
-    if !out.status.success() {
-        let out_string = String::from_utf8(out.stderr)?;
-        return Err(Error::GitError("clone".into(), out_string));
-    }
-    struct ProcessInfo(bool, ForgeIntegrationManifest);
-    let info: ProcessInfo = if let Some(conf_ref) = conf_ref {
-        debug!("resetting repo to {}, to get config", &conf_ref);
-        let mut git_cmd = Command::new("git");
-        git_cmd.arg("reset");
-        git_cmd.arg("--hard");
-        git_cmd.arg(&conf_ref);
-        git_cmd.current_dir(ws.as_ref());
-
-        let out = git_cmd.output()?;
-        if !out.status.success() {
-            let out_string = String::from_utf8(out.stderr)?;
-            return Err(Error::GitError("reset".into(), out_string));
+) -> Result<(git2::Repository, ForgeIntegrationManifest)> {
+    let repo = git.clone(repository, ws.as_ref())?;
+    let manifest = match conf_ref {
+        Some(conf_ref) => {
+            debug!("resetting repo to {}, to get config", &conf_ref);
+            git.checkout(&repo, &conf_ref)?;
+            let manifest = read_manifest(ws.as_ref())?;
+            debug!("resetting git repo to {}", &checkout_ref.sha);
+            git.checkout(&repo, &checkout_ref.sha)?;
+            manifest
         }
-        let manifest = read_manifest(ws.as_ref())?;
-        ProcessInfo(false, manifest)
-    } else {
-        debug!("resetting git repo to {}", &checkout_ref.sha);
-        let mut git_cmd = Command::new("git");
-        git_cmd.arg("reset");
-        git_cmd.arg("--hard");
-        git_cmd.arg(&checkout_ref.sha);
-        git_cmd.current_dir(ws.as_ref());
-
-        let out = git_cmd.output()?;
-        if !out.status.success() {
-            let out_string = String::from_utf8(out.stderr)?;
-            return Err(Error::GitError("reset".into(), out_string));
+        None => {
+            debug!("resetting git repo to {}", &checkout_ref.sha);
+            git.checkout(&repo, &checkout_ref.sha)?;
+            read_manifest(ws.as_ref())?
         }
-        let manifest = read_manifest(ws.as_ref())?;
-        ProcessInfo(true, manifest)
     };
-    if !info.0 {
-        debug!("resetting git repo to {}", &checkout_ref.sha);
-        let mut git_cmd = Command::new("git");
-        git_cmd.arg("reset");
-        git_cmd.arg("--hard");
-        git_cmd.arg(&checkout_ref.sha);
-        git_cmd.current_dir(ws.as_ref());
-
-        let out = git_cmd.output()?;
-        if !out.status.success() {
-            let out_string = String::from_utf8(out.stderr)?;
-            return Err(Error::GitError("reset".into(), out_string));
-        }
-    }
+    git.update_submodules(&repo)?;
 
-    Ok(info.1)
+    Ok((repo, manifest))
 }
 
 #[instrument]
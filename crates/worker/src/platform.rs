@@ -0,0 +1,59 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use integration::ScriptShell;
+
+/// Where the worker reads its configuration from besides the environment.
+pub fn config_path() -> PathBuf {
+    if cfg!(windows) {
+        let program_data =
+            std::env::var_os("ProgramData").unwrap_or_else(|| OsString::from("C:\\ProgramData"));
+        Path::new(&program_data).join("forge").join("worker")
+    } else {
+        PathBuf::from("/etc/forge/worker")
+    }
+}
+
+/// The file name of the script `name` written for `shell`.
+pub fn script_file_name(name: &str, shell: ScriptShell) -> String {
+    let extension = match shell {
+        ScriptShell::Bash | ScriptShell::Sh => "sh",
+        ScriptShell::Powershell => "ps1",
+    };
+    format!("{}.{}", name, extension)
+}
+
+/// The script running the lines one after the other, stopping at the first that fails.
+pub fn script_contents(lines: &[String], shell: ScriptShell) -> String {
+    match shell {
+        // script_command runs them with -e
+        ScriptShell::Bash | ScriptShell::Sh => lines.join("\n"),
+        ScriptShell::Powershell => {
+            let mut script = vec![
+                String::from("$ErrorActionPreference = 'Stop'"),
+                String::from("Set-PSDebug -Trace 1"),
+            ];
+            script.extend(lines.iter().cloned());
+            script.join("\r\n")
+        }
+    }
+}
+
+/// The command line running the script, tracing the lines it runs.
+pub fn script_command(script: &Path, shell: ScriptShell) -> Vec<OsString> {
+    let mut args: Vec<OsString> = match shell {
+        ScriptShell::Bash => vec!["bash".into(), "-ex".into()],
+        ScriptShell::Sh => vec!["sh".into(), "-ex".into()],
+        // Windows ships Windows PowerShell, other systems only have PowerShell Core
+        ScriptShell::Powershell => vec![
+            if cfg!(windows) { "powershell" } else { "pwsh" }.into(),
+            "-NoProfile".into(),
+            "-NonInteractive".into(),
+            "-ExecutionPolicy".into(),
+            "Bypass".into(),
+            "-File".into(),
+        ],
+    };
+    args.push(script.as_os_str().to_owned());
+    args
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// How the worker runs the scripts gate repositories bring along, which anyone opening a
+/// pull request controls. Without settings they run like the worker itself.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Sandbox {
+    /// Run the scripts as this user, the worker has to run as root for it. The
+    /// workspace is handed over to the user before
+    #[serde(default)]
+    uid: Option<u32>,
+    /// Run the scripts with this group, the primary group of the worker without it
+    #[serde(default)]
+    gid: Option<u32>,
+    /// Run the scripts without network access. Outside of a container this needs
+    /// network namespaces, which only Linux has
+    #[serde(default)]
+    no_network: bool,
+    /// Address space of the script processes in bytes at most
+    #[serde(default)]
+    memory_limit: Option<u64>,
+    /// CPU time of the script processes in seconds at most
+    #[serde(default)]
+    cpu_time_limit: Option<u64>,
+    /// Processes of the script user at most
+    #[serde(default)]
+    process_limit: Option<u64>,
+    /// Run the scripts in a container of this image with the workspace mounted at the
+    /// same path instead of on the worker
+    #[serde(default)]
+    container_image: Option<String>,
+    /// The container runtime, podman or docker
+    #[serde(default = "default_container_runtime")]
+    container_runtime: String,
+}
+
+/// Search path of scripts when the worker runs without one.
+const DEFAULT_PATH: &str = "/usr/bin:/bin:/usr/sbin:/sbin";
+
+fn default_container_runtime() -> String {
+    String::from("podman")
+}
+
+impl Sandbox {
+    /// Hand the workspace over to the script user, so the scripts can write the files
+    /// they generate.
+    pub fn prepare(&self, ws: &Path) -> io::Result<()> {
+        match self.uid {
+            Some(uid) => {
+                debug!("handing {} over to uid {}", ws.display(), uid);
+                chown_recursive(ws, uid, self.gid)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The command running `args` in `dir` below the workspace `ws` with the variables
+    /// of `env` inside the sandbox. Nothing of the environment of the worker is passed on
+    /// but the search path, the workspace is the home directory of the script.
+    pub fn command<S: AsRef<OsStr>>(
+        &self,
+        ws: &Path,
+        dir: &Path,
+        args: &[S],
+        env: &BTreeMap<String, String>,
+    ) -> io::Result<Command> {
+        match &self.container_image {
+            Some(image) => Ok(self.container_command(image, ws, dir, args, env)),
+            None => {
+                let (program, args) = args.split_first().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "no program to run")
+                })?;
+                let path = std::env::var_os("PATH").unwrap_or(DEFAULT_PATH.into());
+                let mut cmd = Command::new(program);
+                cmd.args(args)
+                    .env_clear()
+                    .env("PATH", path)
+                    .env("HOME", ws)
+                    .envs(env)
+                    .current_dir(dir);
+                self.restrict(&mut cmd)?;
+                Ok(cmd)
+            }
+        }
+    }
+
+    fn container_command<S: AsRef<OsStr>>(
+        &self,
+        image: &str,
+        ws: &Path,
+        dir: &Path,
+        args: &[S],
+        env: &BTreeMap<String, String>,
+    ) -> Command {
+        let mut cmd = Command::new(&self.container_runtime);
+        cmd.args(["run", "--rm"]);
+        if self.no_network {
+            cmd.args(["--network", "none"]);
+        }
+        if let Some(uid) = self.uid {
+            let user = match self.gid {
+                Some(gid) => format!("{}:{}", uid, gid),
+                None => uid.to_string(),
+            };
+            cmd.arg("--user").arg(user);
+        }
+        if let Some(bytes) = self.memory_limit {
+            cmd.arg("--memory").arg(bytes.to_string());
+        }
+        if let Some(seconds) = self.cpu_time_limit {
+            cmd.arg("--ulimit").arg(format!("cpu={}", seconds));
+        }
+        if let Some(processes) = self.process_limit {
+            cmd.arg("--pids-limit").arg(processes.to_string());
+        }
+        let mut volume = ws.as_os_str().to_owned();
+        volume.push(":");
+        volume.push(ws.as_os_str());
+        cmd.arg("--volume").arg(volume);
+        cmd.arg("--workdir").arg(dir);
+        for (name, value) in env {
+            cmd.arg("--env").arg(format!("{}={}", name, value));
+        }
+        cmd.arg(image).args(args);
+        cmd
+    }
+
+    /// Switch the user, drop the network and set the limits in the child before it runs
+    /// the script. The user goes last, dropping the network needs the privileges.
+    #[cfg(unix)]
+    fn restrict(&self, cmd: &mut Command) -> io::Result<()> {
+        use forge::limits::set_limit;
+        use std::os::unix::process::CommandExt;
+
+        if self.no_network && !cfg!(target_os = "linux") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "scripts can only be kept off the network in a container on this system",
+            ));
+        }
+        let sandbox = self.clone();
+        // SAFETY: the closure only makes system calls, which are safe after fork
+        unsafe {
+            cmd.pre_exec(move || {
+                #[cfg(target_os = "linux")]
+                if sandbox.no_network && libc::unshare(libc::CLONE_NEWNET) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if let Some(bytes) = sandbox.memory_limit {
+                    set_limit(libc::RLIMIT_AS, bytes)?;
+                }
+                if let Some(seconds) = sandbox.cpu_time_limit {
+                    set_limit(libc::RLIMIT_CPU, seconds)?;
+                }
+                if let Some(processes) = sandbox.process_limit {
+                    set_limit(libc::RLIMIT_NPROC, processes)?;
+                }
+                // The supplementary groups of the worker go with any change of the user
+                let switching = sandbox.uid.is_some() || sandbox.gid.is_some();
+                if switching && libc::setgroups(0, std::ptr::null()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if let Some(gid) = sandbox.gid {
+                    if libc::setgid(gid) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(uid) = sandbox.uid {
+                    if libc::setuid(uid) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn restrict(&self, _cmd: &mut Command) -> io::Result<()> {
+        let restricted = self.uid.is_some()
+            || self.gid.is_some()
+            || self.no_network
+            || self.memory_limit.is_some()
+            || self.cpu_time_limit.is_some()
+            || self.process_limit.is_some();
+        if restricted {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "scripts can only be sandboxed in a container on this system",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn chown_recursive(path: &Path, uid: u32, gid: Option<u32>) -> io::Result<()> {
+    std::os::unix::fs::lchown(path, Some(uid), gid)?;
+    if path.symlink_metadata()?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_recursive(&entry?.path(), uid, gid)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn chown_recursive(_path: &Path, _uid: u32, _gid: Option<u32>) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "scripts can only run as another user on unix",
+    ))
+}
@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use forge::SourceCredential;
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{
+    AutotagOption, BranchType, Cred, CredentialType, FetchOptions, Object, Oid, RemoteCallbacks,
+    Repository, ResetType, SubmoduleUpdateOptions, WorktreeAddOptions, WorktreePruneOptions,
+};
+use itertools::Itertools;
+use tracing::{debug, instrument};
+
+use crate::{cancel, joblog, Error, Result};
+
+/// Clones and checks out gate repositories with libgit2, so the worker needs no git
+/// binary and a cancelled job stops its transfers.
+#[derive(Clone, Copy)]
+pub struct Git<'a> {
+    credentials: &'a [SourceCredential],
+    /// Fetch only the commits a job looks at instead of the whole history
+    shallow: bool,
+    /// Bare mirrors of the repositories, jobs check out worktrees of them so repeated
+    /// jobs on a repository only fetch what is new
+    mirror_dir: &'a Path,
+}
+
+/// Held while a mirror is fetched into or gets a worktree, jobs running at once share
+/// the mirrors.
+static MIRRORS: Mutex<()> = Mutex::new(());
+
+fn failed(operation: &'static str) -> impl FnOnce(git2::Error) -> Error {
+    move |e| Error::Git(operation, e)
+}
+
+impl<'a> Git<'a> {
+    pub fn new(credentials: &'a [SourceCredential], shallow: bool, mirror_dir: &'a Path) -> Self {
+        Self {
+            credentials,
+            shallow,
+            mirror_dir,
+        }
+    }
+
+    /// Check out the repository at `url` into `ws`, as a worktree of its mirror.
+    pub fn clone(&self, url: &str, ws: &Path) -> Result<Repository> {
+        let _mirrors = MIRRORS.lock().unwrap_or_else(|e| e.into_inner());
+        let mirror = self.update_mirror(url)?;
+        debug!(
+            "checking out {} into {}",
+            forge::redact_url(url),
+            ws.display()
+        );
+        add_worktree(&mirror, ws)
+    }
+
+    /// Fetch what is new into the mirror of the repository at `url`, mirroring it first
+    /// if no job used it before.
+    fn update_mirror(&self, url: &str) -> Result<Repository> {
+        let path = self.mirror_path(url);
+        if let Ok(mirror) = Repository::open_bare(&path) {
+            debug!("updating mirror {}", path.display());
+            // Credentials in the url may have been rotated since
+            mirror
+                .remote_set_url("origin", url)
+                .map_err(failed("fetch"))?;
+            let mut remote = mirror.find_remote("origin").map_err(failed("fetch"))?;
+            let mut options = self.fetch_options(url, self.shallow);
+            options.download_tags(AutotagOption::All);
+            tokio::task::block_in_place(|| remote.fetch::<&str>(&[], Some(&mut options), None))
+                .map_err(failed("fetch"))?;
+            drop(remote);
+            return Ok(mirror);
+        }
+
+        debug!(
+            "mirroring {} into {}",
+            forge::redact_url(url),
+            path.display()
+        );
+        // Left over by a mirror run that was cancelled halfway
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        std::fs::create_dir_all(self.mirror_dir)?;
+        let mut builder = RepoBuilder::new();
+        builder
+            .bare(true)
+            .fetch_options(self.fetch_options(url, self.shallow));
+        // Other jobs keep running on the runtime while this one waits for the transfer
+        tokio::task::block_in_place(|| builder.clone(url, &path)).map_err(failed("clone"))
+    }
+
+    fn mirror_path(&self, url: &str) -> PathBuf {
+        self.mirror_dir
+            .join(format!("{}.git", path_name(&forge::redact_url(url))))
+    }
+
+    /// Reset the work tree hard to `rev`, a commit id or a tag. Commits the clone did not
+    /// bring along, like heads of pull requests from forks or anything below the tip of a
+    /// shallow clone, are fetched first.
+    #[instrument(skip(self, repo))]
+    pub fn checkout(&self, repo: &Repository, rev: &str) -> Result<()> {
+        let object = self.resolve(repo, rev)?;
+        repo.reset(
+            &object,
+            ResetType::Hard,
+            Some(CheckoutBuilder::new().force()),
+        )
+        .map_err(failed("reset"))
+    }
+
+    /// The paths of the files changed on the checked out commit since it branched off
+    /// `base`. Without the history in between, like in shallow clones, the trees of both
+    /// commits are compared.
+    #[instrument(skip(self, repo))]
+    pub fn changed_files(&self, repo: &Repository, base: &str) -> Result<Vec<String>> {
+        let base = self
+            .resolve(repo, base)?
+            .peel_to_commit()
+            .map_err(failed("diff"))?;
+        let head = repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(failed("diff"))?;
+        let fork_point = repo
+            .merge_base(base.id(), head.id())
+            .and_then(|id| repo.find_commit(id))
+            .unwrap_or(base);
+        let diff = repo
+            .diff_tree_to_tree(
+                Some(&fork_point.tree().map_err(failed("diff"))?),
+                Some(&head.tree().map_err(failed("diff"))?),
+                None,
+            )
+            .map_err(failed("diff"))?;
+        Ok(diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+            .map(|path| path.to_string_lossy().to_string())
+            .unique()
+            .collect())
+    }
+
+    /// Check out the submodules of the checked out commit and theirs in turn, components
+    /// may bundle sources or shared build files as submodules of the gate repository.
+    pub fn update_submodules(&self, repo: &Repository) -> Result<()> {
+        for mut submodule in repo.submodules().map_err(failed("submodule update"))? {
+            debug!("checking out submodule {}", submodule.path().display());
+            let url = submodule.url().unwrap_or_default().to_string();
+            let mut options = SubmoduleUpdateOptions::new();
+            // The commit recorded for the submodule need not be the tip of any branch
+            options.fetch(self.fetch_options(&url, false));
+            tokio::task::block_in_place(|| submodule.update(true, Some(&mut options)))
+                .map_err(failed("submodule update"))?;
+            let repo = submodule.open().map_err(failed("submodule update"))?;
+            self.update_submodules(&repo)?;
+        }
+        Ok(())
+    }
+
+    /// The object `rev` names, fetching it from origin if the clone does not have it.
+    fn resolve<'r>(&self, repo: &'r Repository, rev: &str) -> Result<Object<'r>> {
+        if let Ok(object) = repo.revparse_single(rev) {
+            return Ok(object);
+        }
+        debug!("fetching {} missing from the clone", rev);
+        let refspec = match Oid::from_str(rev) {
+            Ok(_) => rev.to_string(),
+            Err(_) => format!("+refs/tags/{rev}:refs/tags/{rev}"),
+        };
+        let mut remote = repo.find_remote("origin").map_err(failed("fetch"))?;
+        let url = remote.url().unwrap_or_default().to_string();
+        let mut options = self.fetch_options(&url, self.shallow);
+        tokio::task::block_in_place(|| remote.fetch(&[refspec], Some(&mut options), None))
+            .map_err(failed("fetch"))?;
+        repo.revparse_single(rev).map_err(failed("resolve"))
+    }
+
+    /// Options authenticating with the credential configured for `url`, reporting the
+    /// progress to the job log and aborting once the job is cancelled.
+    fn fetch_options(&self, url: &str, shallow: bool) -> FetchOptions<'a> {
+        let credential = SourceCredential::find(self.credentials, url);
+        let job = cancel::current();
+        let sink = joblog::sink();
+
+        let mut callbacks = RemoteCallbacks::new();
+        let mut asked = false;
+        callbacks.credentials(move |_, username, allowed| {
+            // libgit2 keeps asking as long as the server rejects what it got
+            if std::mem::replace(&mut asked, true) {
+                return Err(git2::Error::from_str(
+                    "the server rejected the configured credential",
+                ));
+            }
+            let username = username.unwrap_or("git");
+            match credential {
+                Some(SourceCredential {
+                    ssh_key: Some(key), ..
+                }) if allowed.contains(CredentialType::SSH_KEY) => {
+                    Cred::ssh_key(username, None, Path::new(key), None)
+                }
+                Some(SourceCredential {
+                    username: Some(username),
+                    password,
+                    token,
+                    ..
+                }) if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) => {
+                    let secret = password.as_ref().or(token.as_ref());
+                    Cred::userpass_plaintext(username, secret.map_or("", String::as_str))
+                }
+                _ if allowed.contains(CredentialType::SSH_KEY) => {
+                    Cred::ssh_key_from_agent(username)
+                }
+                _ => Err(git2::Error::from_str(
+                    "no credential configured for the repository",
+                )),
+            }
+        });
+        let mut reported = None;
+        callbacks.transfer_progress(move |progress| {
+            if progress.total_objects() > 0 {
+                let percent = progress.received_objects() * 100 / progress.total_objects();
+                // One line every ten percent, like git prints on a terminal
+                if reported != Some(percent / 10) {
+                    reported = Some(percent / 10);
+                    let line = format!(
+                        "Receiving objects: {}% ({}/{}), {} KiB",
+                        percent,
+                        progress.received_objects(),
+                        progress.total_objects(),
+                        progress.received_bytes() / 1024
+                    );
+                    debug!("{}", line);
+                    if let Some(sink) = &sink {
+                        let _ = sink.send(line);
+                    }
+                }
+            }
+            !job.is_cancelled()
+        });
+
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks);
+        if shallow {
+            options.depth(1);
+        }
+        // Like the http.extraHeader git was configured with, so bearer tokens work too
+        if let Some(authorization) = credential
+            .filter(|_| url.starts_with("http"))
+            .and_then(SourceCredential::authorization)
+        {
+            options.custom_headers(&[&format!("Authorization: {}", authorization)]);
+        }
+        options
+    }
+}
+
+/// Check out a worktree of the mirror into `ws`. libgit2 checks worktrees out on a branch
+/// of their own, named like the worktree. The worktree a job left in `ws` before and the
+/// ones whose directory is gone are pruned first.
+fn add_worktree(mirror: &Repository, ws: &Path) -> Result<Repository> {
+    let name = path_name(&ws.to_string_lossy());
+    for stale in mirror
+        .worktrees()
+        .map_err(failed("worktree"))?
+        .iter()
+        .flatten()
+    {
+        let worktree = mirror.find_worktree(stale).map_err(failed("worktree"))?;
+        if stale == name || worktree.validate().is_err() {
+            debug!("pruning worktree {}", stale);
+            worktree
+                .prune(Some(
+                    WorktreePruneOptions::new().valid(true).working_tree(false),
+                ))
+                .map_err(failed("worktree"))?;
+            if let Ok(mut branch) = mirror.find_branch(stale, BranchType::Local) {
+                branch.delete().map_err(failed("worktree"))?;
+            }
+        }
+    }
+
+    // The job checks out the commit it needs afterwards, any commit will do to start with
+    let head = mirror
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(failed("worktree"))?;
+    let branch = mirror
+        .branch(&name, &head, true)
+        .map_err(failed("worktree"))?;
+    // libgit2 creates the directory of the worktree itself
+    if ws.exists() {
+        std::fs::remove_dir_all(ws)?;
+    }
+    let mut options = WorktreeAddOptions::new();
+    options.reference(Some(branch.get()));
+    let worktree = mirror
+        .worktree(&name, ws, Some(&options))
+        .map_err(failed("worktree"))?;
+    Repository::open_from_worktree(&worktree).map_err(failed("worktree"))
+}
+
+/// A name for the path or url usable as file and branch name.
+fn path_name(path: &str) -> String {
+    path.trim_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .replace("..", "-")
+}
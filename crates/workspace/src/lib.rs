@@ -1,14 +1,20 @@
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::fs::{create_dir_all, DirBuilder};
 use std::io;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use miette::Diagnostic;
 use sha2::Digest;
 use thiserror::Error;
 use serde::{Deserialize, Serialize};
 
+mod patch;
+
+pub use patch::{Hunk, Patch, PatchError, PatchResult, PatchedFile};
+
 #[derive(Debug, Error, Diagnostic)]
 pub enum WorkspaceError {
     #[error(transparent)]
@@ -21,11 +27,54 @@ pub enum WorkspaceError {
 
     #[error("could not lookup variable {0}")]
     VariableLookupError(String),
+
+    #[error("unknown architecture {0}")]
+    #[diagnostic(
+        code(wk::arch::unknown),
+        help("use one of 'i386', 'amd64', 'aarch64', 'sparc'")
+    )]
+    UnknownArchitecture(String),
 }
 
 type Result<T> = miette::Result<T, WorkspaceError>;
 
-static DEFAULTARCH: &str = "i386";
+/// The architecture a workspace builds for. Every architecture gets its own build and
+/// prototype directories so builds for several of them can share one workspace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Architecture {
+    #[default]
+    I386,
+    Amd64,
+    Aarch64,
+    Sparc,
+}
+
+impl Display for Architecture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Architecture::I386 => "i386",
+            Architecture::Amd64 => "amd64",
+            Architecture::Aarch64 => "aarch64",
+            Architecture::Sparc => "sparc",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Architecture {
+    type Err = WorkspaceError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "i386" | "i86pc" => Ok(Self::I386),
+            "amd64" | "x86_64" => Ok(Self::Amd64),
+            "aarch64" | "arm64" => Ok(Self::Aarch64),
+            "sparc" | "sparcv9" => Ok(Self::Sparc),
+            x => Err(WorkspaceError::UnknownArchitecture(x.to_string())),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkspaceConfig {
@@ -35,6 +84,7 @@ pub struct WorkspaceConfig {
 #[derive(Debug)]
 pub struct Workspace {
     path: PathBuf,
+    arch: Architecture,
     source_dir: PathBuf,
     build_dir: PathBuf,
     proto_dir: PathBuf,
@@ -56,18 +106,34 @@ impl Workspace {
             create_dir_all(&path)?;
         }
         let expanded_root_dir = std::fs::canonicalize(path.as_ref())?;
+        let arch = Architecture::default();
         let ws = Self {
             path: expanded_root_dir.clone(),
-            build_dir: Path::new(&expanded_root_dir)
-                .join("build")
-                .join(DEFAULTARCH),
-            source_dir: Path::new(&expanded_root_dir).join("sources"),
-            proto_dir: Path::new(&expanded_root_dir).join("build").join("proto"),
+            arch,
+            build_dir: expanded_root_dir.join("build").join(arch.to_string()),
+            source_dir: expanded_root_dir.join("sources"),
+            proto_dir: expanded_root_dir.join("proto").join(arch.to_string()),
+        };
+        init_root(&ws)?;
+        Ok(ws)
+    }
+
+    /// Switch the workspace to build for `arch`.
+    pub fn with_arch(self, arch: Architecture) -> Result<Self> {
+        let ws = Self {
+            arch,
+            build_dir: self.path.join("build").join(arch.to_string()),
+            proto_dir: self.path.join("proto").join(arch.to_string()),
+            ..self
         };
         init_root(&ws)?;
         Ok(ws)
     }
 
+    pub fn get_arch(&self) -> Architecture {
+        self.arch
+    }
+
     pub fn from_config(config: &WorkspaceConfig) -> Result<Self> {
         Self::new(&config.path)
     }
@@ -118,7 +184,7 @@ impl Workspace {
     }
 
     pub fn get_or_create_build_dir(&self) -> Result<PathBuf> {
-        let p = self.path.join("build");
+        let p = self.build_dir.clone();
         if !p.exists() {
             DirBuilder::new().recursive(true).create(&p)?;
         }
@@ -126,7 +192,7 @@ impl Workspace {
     }
 
     pub fn get_or_create_prototype_dir(&self) -> Result<PathBuf> {
-        let p = self.path.join("proto");
+        let p = self.proto_dir.clone();
         if !p.exists() {
             DirBuilder::new().recursive(true).create(&p)?;
         }
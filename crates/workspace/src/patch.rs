@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+/// The patch tool used to apply patches, GNU patch is called gpatch on illumos.
+const PATCH_TOOL: &str = "gpatch";
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum PatchError {
+    #[error("could not read patch {}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("could not run gpatch")]
+    #[diagnostic(help("install GNU patch"))]
+    Spawn(#[source] std::io::Error),
+
+    #[error("hunk {hunk} of {patch} does not apply to {file}")]
+    #[diagnostic(help("refresh the patch against the sources with pkgdev patch refresh"))]
+    HunkFailed {
+        patch: String,
+        file: String,
+        hunk: usize,
+        #[source_code]
+        source_code: NamedSource,
+        #[label("this hunk")]
+        span: SourceSpan,
+    },
+
+    #[error("{patch} is already applied to {file}")]
+    #[diagnostic(help("the sources already contain the change, remove the patch"))]
+    AlreadyApplied { patch: String, file: String },
+
+    #[error("{patch} changes {file} which is not in the sources")]
+    #[diagnostic(help("check drop-directories of the patch"))]
+    MissingFile { patch: String, file: String },
+
+    #[error("gpatch could not apply {patch}: {output}")]
+    Failed { patch: String, output: String },
+}
+
+pub type PatchResult<T> = std::result::Result<T, PatchError>;
+
+/// A hunk of a unified diff with the byte range it spans in the patch file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    pub header: String,
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// A file a patch changes together with its hunks in the order of the patch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchedFile {
+    /// The path as given in the patch, leading directories are not stripped
+    pub path: String,
+    pub hunks: Vec<Hunk>,
+}
+
+impl PatchedFile {
+    /// The path after dropping the leading directories like patch -p does.
+    fn stripped_path(&self, drop_directories: Option<i64>) -> &str {
+        let drop = drop_directories.unwrap_or(0).max(0) as usize;
+        let mut path = self.path.as_str();
+        for _ in 0..drop {
+            match path.split_once('/') {
+                Some((_, rest)) => path = rest,
+                None => break,
+            }
+        }
+        path
+    }
+}
+
+/// A patch in unified diff format. Applying it checks with a dry run first so a patch is
+/// applied completely or not at all and failures name the hunk that did not apply.
+#[derive(Debug, Clone)]
+pub struct Patch {
+    path: PathBuf,
+    name: String,
+    content: String,
+    files: Vec<PatchedFile>,
+}
+
+/// The path of a ---/+++ line without the timestamp diff appends after a tab.
+fn diff_path(line: &str) -> String {
+    line.split('\t')
+        .next()
+        .unwrap_or(line)
+        .trim_end()
+        .to_string()
+}
+
+fn parse_files(content: &str) -> Vec<PatchedFile> {
+    let mut files: Vec<PatchedFile> = vec![];
+    let mut old_path = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        if let Some(path) = text.strip_prefix("--- ") {
+            old_path = Some(diff_path(path));
+        } else if let Some(path) = text.strip_prefix("+++ ") {
+            let path = diff_path(path);
+            // New files are diffed against /dev/null and deleted ones against it
+            let path = match old_path.take() {
+                Some(old) if path == "/dev/null" => old,
+                _ => path,
+            };
+            files.push(PatchedFile {
+                path,
+                hunks: vec![],
+            });
+        } else if text.starts_with("@@ ") {
+            if let Some(file) = files.last_mut() {
+                file.hunks.push(Hunk {
+                    header: text.to_string(),
+                    offset,
+                    len: 0,
+                });
+            }
+        }
+        if let Some(hunk) = files.last_mut().and_then(|f| f.hunks.last_mut()) {
+            if !text.starts_with("--- ") && !text.starts_with("diff ") {
+                hunk.len = offset + text.len() - hunk.offset;
+            }
+        }
+        offset += line.len();
+    }
+    files
+}
+
+/// The file name patch reports in lines like `checking file 'src/a b.c'`.
+fn reported_file(line: &str) -> Option<&str> {
+    line.strip_prefix("checking file ")
+        .or(line.strip_prefix("patching file "))
+        .map(|file| file.trim().trim_matches('\''))
+}
+
+impl Patch {
+    pub fn open<P: AsRef<Path>>(path: P) -> PatchResult<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|source| PatchError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Self::from_content(path, content))
+    }
+
+    pub fn from_content<P: AsRef<Path>>(path: P, content: String) -> Self {
+        let path = path.as_ref().to_path_buf();
+        Self {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(path.display().to_string()),
+            files: parse_files(&content),
+            path,
+            content,
+        }
+    }
+
+    pub fn files(&self) -> &[PatchedFile] {
+        &self.files
+    }
+
+    fn run(&self, dir: &Path, drop_directories: Option<i64>, dry_run: bool) -> PatchResult<()> {
+        let mut cmd = Command::new(PATCH_TOOL);
+        cmd.arg("--batch").arg("--forward").arg("-d").arg(dir);
+        if let Some(drop_directories) = drop_directories {
+            cmd.arg(format!("-p{}", drop_directories));
+        }
+        if dry_run {
+            cmd.arg("--dry-run");
+        }
+        // patch changes into the directory before it opens the patch
+        let input = self.path.canonicalize().unwrap_or(self.path.clone());
+        let output = cmd
+            .arg("-i")
+            .arg(input)
+            .output()
+            .map_err(PatchError::Spawn)?;
+        if output.status.success() {
+            return Ok(());
+        }
+        let report = String::from_utf8_lossy(&output.stdout).to_string()
+            + &String::from_utf8_lossy(&output.stderr);
+        Err(self.interpret_failure(&report, drop_directories))
+    }
+
+    /// Check that the patch applies to the sources in `dir` without changing them.
+    pub fn check<P: AsRef<Path>>(&self, dir: P, drop_directories: Option<i64>) -> PatchResult<()> {
+        self.run(dir.as_ref(), drop_directories, true)
+    }
+
+    /// Apply the patch to the sources in `dir` if all of its hunks apply.
+    pub fn apply<P: AsRef<Path>>(&self, dir: P, drop_directories: Option<i64>) -> PatchResult<()> {
+        self.check(dir.as_ref(), drop_directories)?;
+        self.run(dir.as_ref(), drop_directories, false)
+    }
+
+    /// Turn the output of a failed patch run into the error of its first failure.
+    fn interpret_failure(&self, output: &str, drop_directories: Option<i64>) -> PatchError {
+        let mut current_file = None;
+        let mut files_seen = 0;
+        for line in output.lines() {
+            let line = line.trim();
+            if let Some(file) = reported_file(line) {
+                current_file = Some(file.to_string());
+                files_seen += 1;
+            } else if line.starts_with("Reversed (or previously applied) patch detected") {
+                return PatchError::AlreadyApplied {
+                    patch: self.name.clone(),
+                    file: current_file.unwrap_or_default(),
+                };
+            } else if line.starts_with("can't find file to patch") {
+                // patch does not name the missing file, but reports files in patch order
+                return PatchError::MissingFile {
+                    patch: self.name.clone(),
+                    file: self
+                        .files
+                        .get(files_seen)
+                        .map(|f| f.stripped_path(drop_directories).to_string())
+                        .unwrap_or_default(),
+                };
+            } else if let Some(hunk) = line
+                .strip_prefix("Hunk #")
+                .filter(|rest| rest.contains("FAILED"))
+                .and_then(|rest| rest.split_whitespace().next())
+                .and_then(|number| number.parse::<usize>().ok())
+            {
+                let file = current_file.clone().unwrap_or_default();
+                let found = self
+                    .files
+                    .iter()
+                    .find(|f| f.stripped_path(drop_directories) == file)
+                    .and_then(|f| f.hunks.get(hunk.saturating_sub(1)));
+                let span = found
+                    .map(|h| SourceSpan::from((h.offset, h.len)))
+                    .unwrap_or(SourceSpan::from((0, 0)));
+                return PatchError::HunkFailed {
+                    patch: self.name.clone(),
+                    file,
+                    hunk,
+                    source_code: NamedSource::new(&self.name, self.content.clone()),
+                    span,
+                };
+            }
+        }
+        PatchError::Failed {
+            patch: self.name.clone(),
+            output: output.trim().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "\
+diff -Nrup a/Makefile.in b/Makefile.in
+--- a/Makefile.in\t2024-01-22 19:32:37
++++ b/Makefile.in\t2024-02-01 10:00:00
+@@ -1,3 +1,3 @@
+-CC=cc
++CC=gcc
+ CFLAGS=-O
+ LDFLAGS=
+@@ -20,2 +20,2 @@ install:
+-\tcp libz.a /usr/lib
++\tcp libz.a $(DESTDIR)/usr/lib
+--- /dev/null
++++ b/zconf.h.in
+@@ -0,0 +1 @@
++#define Z_SOLO
+";
+
+    #[test]
+    fn failed_hunk_is_located_in_the_patch() {
+        let patch = Patch::from_content("0001-makefile.patch", PATCH.to_string());
+        let files = patch.files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "b/Makefile.in");
+        assert_eq!(files[0].hunks.len(), 2);
+        assert_eq!(files[1].stripped_path(Some(1)), "zconf.h.in");
+
+        let second = &files[0].hunks[1];
+        assert_eq!(second.header, "@@ -20,2 +20,2 @@ install:");
+        assert!(PATCH[second.offset..second.offset + second.len].ends_with("/usr/lib"));
+
+        let output = "checking file Makefile.in\n\
+                      Hunk #2 FAILED at 20.\n\
+                      1 out of 2 hunks FAILED\n";
+        match patch.interpret_failure(output, Some(1)) {
+            PatchError::HunkFailed {
+                file, hunk, span, ..
+            } => {
+                assert_eq!(file, "Makefile.in");
+                assert_eq!(hunk, 2);
+                assert_eq!(span.offset(), second.offset);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        let output = "checking file Makefile.in\n\
+                      Reversed (or previously applied) patch detected!  Skipping patch.\n";
+        assert!(matches!(
+            patch.interpret_failure(output, Some(1)),
+            PatchError::AlreadyApplied { .. }
+        ));
+    }
+}
@@ -10,6 +10,10 @@ use component::{Component, PackageMeta, Recipe, RecipeDiff};
 use gate::Gate;
 use uuid::Uuid;
 
+pub mod limits;
+pub mod provenance;
+pub mod signing;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AuthConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -23,6 +27,20 @@ pub struct OpenIdConfig {
     pub client_id: String,
 }
 
+/// HTTP header the services pass the correlation id of a request on in. AMQP messages
+/// carry it in their correlation-id property.
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+/// A new id to follow everything one webhook or API request caused through the logs of
+/// all services.
+pub fn new_correlation_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// HTTP header forged sets when it replays a recorded webhook to the receiver, holding the
+/// id of the delivery so its result is recorded on it instead of a new one.
+pub const REPLAYED_DELIVERY_HEADER: &str = "x-forge-delivery";
+
 pub enum IdKind {
     Actor,
     ChangeRequest,
@@ -170,6 +188,27 @@ pub struct ChangeRequest {
     pub git_url: String,
 }
 
+/// A command a maintainer gave in a comment on a change request.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ChangeRequestCommand {
+    /// Read the recipes of the change request again on the gate of the webhook and
+    /// rebuild the changed components
+    Rebuild,
+    /// Run the last recipes job of the change request again, on the gate with this name
+    /// or version if given
+    Retry { gate: Option<String> },
+}
+
+/// Sent to forged on `forged.command` for every command of a comment.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommandEnvelope {
+    pub change_request_id: Url,
+    pub gate_id: Uuid,
+    /// Who gave the command
+    pub actor: String,
+    pub command: ChangeRequestCommand,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CommitRef {
     pub sha: String,
@@ -232,6 +271,27 @@ pub struct PatchFile {
     pub name: String,
     pub content: String,
 }
+
+/// File in the workspace root pkgdev writes the [`CheckResult`]s of a build to.
+pub const CHECKS_REPORT_FILE: &str = "checks.json";
+
+/// The outcome of one check of a recipe.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CheckResult {
+    pub name: String,
+    pub passed: bool,
+    /// What the check printed or why it failed
+    pub output: String,
+}
+
+/// File in the workspace root pkgdev writes the hosts a build tried to reach to, when
+/// the network policy of the gate monitors the build phase.
+pub const NETWORK_REPORT_FILE: &str = "network.json";
+
+/// File in the workspace root `pkgdev build --bundle-logs` packs the logs of the build
+/// steps into, for attaching them to the report of a job.
+pub const BUILD_LOGS_FILE: &str = "logs.tar.gz";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum JobReport {
     Success(
@@ -244,16 +304,76 @@ pub enum JobReport {
         error: String,
         /// The Kind of job that ran
         kind: JobKind,
+        /// The checks of the recipe if the build got to run them
+        #[serde(default)]
+        checks: Vec<CheckResult>,
+        /// The hosts the build phase tried to reach instead of using the declared sources
+        #[serde(default)]
+        network_hosts: Vec<String>,
     },
+    /// The job was cancelled through forged before it finished
+    Cancelled {
+        object: JobObject,
+        kind: JobKind,
+    },
+}
+
+/// Messages forged sends on the control queue of a running job.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum JobControl {
+    /// Abort the job and report it as cancelled
+    Cancel,
+}
+
+/// Lines of output a running job produced. Workers send them to forged while the job
+/// runs so its log can be followed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct JobLogChunk {
+    /// The queue id of the job, see [`job_control_queue`]
+    pub job_id: String,
+    /// Number of the first line, the lines of a job are counted from 0
+    pub first_line: u32,
+    pub lines: Vec<String>,
 }
 
+/// How long the control queue of a job outlives its last consumer. forged and the
+/// worker declare the queue with the same arguments.
+pub const JOB_CONTROL_QUEUE_EXPIRES_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// The control queue of the job with the queue id `job_id`, sent to the worker as the
+/// message id of the job.
+pub fn job_control_queue(job_id: &str) -> String {
+    format!("forge.job.control.{}", job_id)
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum JobObject {
     ChangeRequest {
         cr_id: Url,
         gate_id: Uuid,
-    }
+    },
+    Component {
+        component_id: ComponentId,
+        /// The staging repository the build published to
+        #[serde(default)]
+        staging: Option<String>,
+        /// Whether the bootstrap variant was built
+        #[serde(default)]
+        bootstrap: bool,
+    },
+    StagingRepository {
+        name: String,
+    },
+    Gate {
+        gate_id: Uuid,
+        /// The commit of the branch that was synced
+        commit: String,
+    },
+    GateConfig {
+        gate_id: Uuid,
+        /// The commit gate.kdl was read from
+        commit: String,
+    },
 }
 
 impl Display for JobObject {
@@ -262,6 +382,18 @@ impl Display for JobObject {
             JobObject::ChangeRequest { cr_id, gate_id } => {
                 write!(f, "ChangeRequest {cr_id} for gate {gate_id}")
             }
+            JobObject::Component { component_id, .. } => {
+                write!(f, "Component {component_id}")
+            }
+            JobObject::StagingRepository { name } => {
+                write!(f, "StagingRepository {name}")
+            }
+            JobObject::Gate { gate_id, commit } => {
+                write!(f, "Gate {gate_id} at {commit}")
+            }
+            JobObject::GateConfig { gate_id, commit } => {
+                write!(f, "GateConfig {gate_id} at {commit}")
+            }
         }
     }
 }
@@ -269,6 +401,12 @@ impl Display for JobObject {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum JobKind {
     GetRecipes,
+    BuildStoredComponent,
+    PromoteStagedPackages,
+    SyncGate,
+    ReleaseGate,
+    PublishRelease,
+    SyncGateConfig,
 }
 
 impl Display for JobKind {
@@ -277,6 +415,24 @@ impl Display for JobKind {
             JobKind::GetRecipes => {
                 write!(f, "GetRecipes")
             }
+            JobKind::BuildStoredComponent => {
+                write!(f, "BuildStoredComponent")
+            }
+            JobKind::PromoteStagedPackages => {
+                write!(f, "PromoteStagedPackages")
+            }
+            JobKind::SyncGate => {
+                write!(f, "SyncGate")
+            }
+            JobKind::ReleaseGate => {
+                write!(f, "ReleaseGate")
+            }
+            JobKind::PublishRelease => {
+                write!(f, "PublishRelease")
+            }
+            JobKind::SyncGateConfig => {
+                write!(f, "SyncGateConfig")
+            }
         }
     }
 }
@@ -288,9 +444,502 @@ pub enum JobReportData {
         change_request_id: String,
         recipes: Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)>,
     },
+    BuildStoredComponent {
+        component_id: ComponentId,
+        /// The packages that were published
+        packages: Vec<String>,
+        /// The staging repository the packages were published to
+        #[serde(default)]
+        staging: Option<String>,
+        /// Whether the bootstrap variant was built
+        #[serde(default)]
+        bootstrap: bool,
+        /// The checks of the recipe and whether they passed
+        #[serde(default)]
+        checks: Vec<CheckResult>,
+        /// The hosts the build phase tried to reach instead of using the declared sources
+        #[serde(default)]
+        network_hosts: Vec<String>,
+        /// Where the artifacts of the build came from, unsigned as forged signs it with
+        /// the key of the publisher
+        #[serde(default)]
+        provenance: Option<provenance::Statement>,
+    },
+    PromoteStagedPackages {
+        staging: String,
+        /// The FMRIs that were moved into the public repository
+        packages: Vec<String>,
+    },
+    SyncGate {
+        gate_id: Uuid,
+        commit: String,
+        /// The components whose files changed with the push
+        recipes: Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)>,
+    },
+    ReleaseGate {
+        gate_id: Uuid,
+        tag: String,
+        /// Every component of the repository at the tag
+        recipes: Vec<(String, Recipe, Option<PackageMeta>, Vec<PatchFile>)>,
+    },
+    PublishRelease {
+        gate_id: Uuid,
+        tag: String,
+        staging: String,
+        /// The FMRIs that were moved into the public repository
+        packages: Vec<String>,
+    },
+    SyncGateConfig {
+        gate_id: Uuid,
+        commit: String,
+        /// The gate as [`GATE_CONFIG_FILE`] describes it at the commit
+        gate: Gate,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Job {
     GetRecipes { cr_id: Url, gate_id: Uuid, cr: ChangeRequest },
+    /// Build a component from what forged stored about it without cloning its repository
+    BuildStoredComponent {
+        component_id: ComponentId,
+        /// Publish into this staging repository instead of the public repository
+        #[serde(default)]
+        staging: Option<String>,
+        /// Build the bootstrap variant of the recipe to break a build dependency cycle
+        #[serde(default)]
+        bootstrap: bool,
+    },
+    /// Move the packages of a staging repository into the public repository
+    PromoteStagedPackages {
+        change_request_id: String,
+        staging: String,
+    },
+    /// Read the recipes a push to the branch of a gate changed so forged can update its
+    /// components and rebuild them
+    SyncGate {
+        gate_id: Uuid,
+        git_url: String,
+        branch: String,
+        /// The commit of the branch before the push, every component is read if the
+        /// push created the branch
+        before: Option<String>,
+        after: String,
+    },
+    /// Read every recipe of a tag of the gate repository so forged can rebuild the gate
+    /// from scratch for a release
+    ReleaseGate {
+        gate_id: Uuid,
+        git_url: String,
+        tag: String,
+    },
+    /// Promote the packages of a release and snapshot the package repository
+    PublishRelease {
+        gate_id: Uuid,
+        tag: String,
+        staging: String,
+        /// Name of the snapshot of the package repository
+        snapshot: String,
+    },
+    /// Read [`GATE_CONFIG_FILE`] of a push to the branch of a gate so forged can update
+    /// the gate to match it
+    SyncGateConfig {
+        gate_id: Uuid,
+        git_url: String,
+        branch: String,
+        commit: String,
+    },
+}
+
+/// Identifies one version of a component stored in forged.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ComponentId {
+    pub name: String,
+    pub version: String,
+    pub revision: String,
+    pub gate_id: Uuid,
+}
+
+impl Display for ComponentId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{}@{}-{}",
+            self.gate_id, self.name, self.version, self.revision
+        )
+    }
+}
+
+/// The queue of jobs building components stored in forged.
+pub const STORED_COMPONENTS_REPOSITORY: &str = "forged:stored-components";
+
+/// The name of the staging repository the packages of a change request are published to.
+pub fn staging_repository_name(change_request_id: &str) -> String {
+    change_request_id
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(change_request_id)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// The file in the root of a gate repository describing the gate.
+pub const GATE_CONFIG_FILE: &str = "gate.kdl";
+
+/// Syncs of a gate are queued per pushed commit.
+fn sync_reference(gate_id: &Uuid, commit: &str) -> String {
+    format!("{}@{}", gate_id, commit)
+}
+
+/// Syncs of the configuration of a gate are queued per pushed commit next to the sync of
+/// its components.
+fn config_reference(gate_id: &Uuid, commit: &str) -> String {
+    format!("{}/{}@{}", gate_id, GATE_CONFIG_FILE, commit)
+}
+
+/// Bootstrap and full builds of a component are queued as separate jobs.
+fn build_reference(component_id: &ComponentId, bootstrap: bool) -> String {
+    if bootstrap {
+        format!("{} (bootstrap)", component_id)
+    } else {
+        component_id.to_string()
+    }
+}
+
+impl Job {
+    /// The repository the job works on. Used to schedule jobs fairly between repositories.
+    pub fn source_repository(&self) -> &str {
+        match self {
+            Job::GetRecipes { cr, .. } => cr.git_url.as_str(),
+            // Rebuilds share one queue so a campaign cannot crowd out change requests
+            Job::BuildStoredComponent { .. } => STORED_COMPONENTS_REPOSITORY,
+            // Queued behind the staged builds so only finished builds are promoted
+            Job::PromoteStagedPackages { .. } => STORED_COMPONENTS_REPOSITORY,
+            Job::SyncGate { git_url, .. } => git_url.as_str(),
+            Job::ReleaseGate { git_url, .. } => git_url.as_str(),
+            Job::SyncGateConfig { git_url, .. } => git_url.as_str(),
+            // Queued behind the release builds like promotions
+            Job::PublishRelease { .. } => STORED_COMPONENTS_REPOSITORY,
+        }
+    }
+
+    /// The gate the job works for, jobs count towards its concurrency limit.
+    pub fn gate_id(&self) -> Option<Uuid> {
+        match self {
+            Job::GetRecipes { gate_id, .. } => Some(*gate_id),
+            Job::BuildStoredComponent { component_id, .. } => Some(component_id.gate_id),
+            Job::PromoteStagedPackages { .. } => None,
+            Job::SyncGate { gate_id, .. } => Some(*gate_id),
+            Job::ReleaseGate { gate_id, .. } => Some(*gate_id),
+            Job::SyncGateConfig { gate_id, .. } => Some(*gate_id),
+            Job::PublishRelease { .. } => None,
+        }
+    }
+
+    /// The staging repository the job builds into or promotes.
+    pub fn staging(&self) -> Option<&str> {
+        match self {
+            Job::BuildStoredComponent { staging, .. } => staging.as_deref(),
+            Job::PromoteStagedPackages { staging, .. } | Job::PublishRelease { staging, .. } => {
+                Some(staging)
+            }
+            Job::GetRecipes { .. }
+            | Job::SyncGate { .. }
+            | Job::ReleaseGate { .. }
+            | Job::SyncGateConfig { .. } => None,
+        }
+    }
+
+    /// What the job works on, reports of failed or cancelled jobs carry the same object.
+    pub fn object(&self) -> JobObject {
+        match self {
+            Job::GetRecipes { cr_id, gate_id, .. } => JobObject::ChangeRequest {
+                cr_id: cr_id.clone(),
+                gate_id: *gate_id,
+            },
+            Job::BuildStoredComponent {
+                component_id,
+                staging,
+                bootstrap,
+            } => JobObject::Component {
+                component_id: component_id.clone(),
+                staging: staging.clone(),
+                bootstrap: *bootstrap,
+            },
+            Job::PromoteStagedPackages { staging, .. } => JobObject::StagingRepository {
+                name: staging.clone(),
+            },
+            Job::SyncGate { gate_id, after, .. } => JobObject::Gate {
+                gate_id: *gate_id,
+                commit: after.clone(),
+            },
+            Job::ReleaseGate { gate_id, tag, .. } => JobObject::Gate {
+                gate_id: *gate_id,
+                commit: tag.clone(),
+            },
+            Job::PublishRelease { staging, .. } => JobObject::StagingRepository {
+                name: staging.clone(),
+            },
+            Job::SyncGateConfig {
+                gate_id, commit, ..
+            } => JobObject::GateConfig {
+                gate_id: *gate_id,
+                commit: commit.clone(),
+            },
+        }
+    }
+
+    /// The kind of the job, reports and failures carry the same kind.
+    pub fn kind(&self) -> JobKind {
+        match self {
+            Job::GetRecipes { .. } => JobKind::GetRecipes,
+            Job::BuildStoredComponent { .. } => JobKind::BuildStoredComponent,
+            Job::PromoteStagedPackages { .. } => JobKind::PromoteStagedPackages,
+            Job::SyncGate { .. } => JobKind::SyncGate,
+            Job::ReleaseGate { .. } => JobKind::ReleaseGate,
+            Job::PublishRelease { .. } => JobKind::PublishRelease,
+            Job::SyncGateConfig { .. } => JobKind::SyncGateConfig,
+        }
+    }
+
+    /// The id of the object the job works on. Reports for the job carry the same id.
+    pub fn reference(&self) -> String {
+        match self {
+            Job::GetRecipes { cr_id, .. } => cr_id.to_string(),
+            Job::BuildStoredComponent {
+                component_id,
+                bootstrap,
+                ..
+            } => build_reference(component_id, *bootstrap),
+            Job::PromoteStagedPackages { staging, .. } => staging.clone(),
+            Job::SyncGate { gate_id, after, .. } => sync_reference(gate_id, after),
+            Job::ReleaseGate { gate_id, tag, .. } => sync_reference(gate_id, tag),
+            Job::PublishRelease { staging, .. } => staging.clone(),
+            Job::SyncGateConfig {
+                gate_id, commit, ..
+            } => config_reference(gate_id, commit),
+        }
+    }
+}
+
+impl JobReport {
+    /// The kind of the reported job.
+    pub fn kind(&self) -> JobKind {
+        match self {
+            JobReport::Success(JobReportData::GetRecipes { .. }) => JobKind::GetRecipes,
+            JobReport::Success(JobReportData::BuildStoredComponent { .. }) => {
+                JobKind::BuildStoredComponent
+            }
+            JobReport::Success(JobReportData::PromoteStagedPackages { .. }) => {
+                JobKind::PromoteStagedPackages
+            }
+            JobReport::Success(JobReportData::SyncGate { .. }) => JobKind::SyncGate,
+            JobReport::Success(JobReportData::ReleaseGate { .. }) => JobKind::ReleaseGate,
+            JobReport::Success(JobReportData::PublishRelease { .. }) => JobKind::PublishRelease,
+            JobReport::Success(JobReportData::SyncGateConfig { .. }) => JobKind::SyncGateConfig,
+            JobReport::Failure { kind, .. } | JobReport::Cancelled { kind, .. } => kind.clone(),
+        }
+    }
+
+    /// The id of the object the reported job worked on.
+    pub fn reference(&self) -> String {
+        match self {
+            JobReport::Success(JobReportData::GetRecipes {
+                change_request_id, ..
+            }) => change_request_id.clone(),
+            JobReport::Success(JobReportData::BuildStoredComponent {
+                component_id,
+                bootstrap,
+                ..
+            }) => build_reference(component_id, *bootstrap),
+            JobReport::Success(JobReportData::PromoteStagedPackages { staging, .. }) => {
+                staging.clone()
+            }
+            JobReport::Success(JobReportData::SyncGate {
+                gate_id, commit, ..
+            }) => sync_reference(gate_id, commit),
+            JobReport::Success(JobReportData::ReleaseGate { gate_id, tag, .. }) => {
+                sync_reference(gate_id, tag)
+            }
+            JobReport::Success(JobReportData::PublishRelease { staging, .. }) => staging.clone(),
+            JobReport::Success(JobReportData::SyncGateConfig {
+                gate_id, commit, ..
+            }) => config_reference(gate_id, commit),
+            JobReport::Failure { object, .. } | JobReport::Cancelled { object, .. } => match object {
+                JobObject::ChangeRequest { cr_id, .. } => cr_id.to_string(),
+                JobObject::Component {
+                    component_id,
+                    bootstrap,
+                    ..
+                } => build_reference(component_id, *bootstrap),
+                JobObject::StagingRepository { name } => name.clone(),
+                JobObject::Gate { gate_id, commit } => sync_reference(gate_id, commit),
+                JobObject::GateConfig { gate_id, commit } => config_reference(gate_id, commit),
+            },
+        }
+    }
+}
+
+/// Why pkgdev failed, told apart by its exit code so wrappers like the worker can decide
+/// between fixing the recipe, fixing the build host or simply retrying.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FailureCategory {
+    /// The recipe, gate or arguments given are wrong
+    User,
+    /// The build host is missing a tool or is not configured
+    Environment,
+    /// A network failure that may succeed when retried
+    Transient,
+    /// A bug in pkgdev
+    Internal,
+}
+
+impl FailureCategory {
+    /// The exit codes follow sysexits.h: EX_DATAERR, EX_UNAVAILABLE, EX_TEMPFAIL and
+    /// EX_SOFTWARE.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            FailureCategory::User => 65,
+            FailureCategory::Environment => 69,
+            FailureCategory::Transient => 75,
+            FailureCategory::Internal => 70,
+        }
+    }
+
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        match code {
+            65 => Some(FailureCategory::User),
+            69 => Some(FailureCategory::Environment),
+            75 => Some(FailureCategory::Transient),
+            70 => Some(FailureCategory::Internal),
+            _ => None,
+        }
+    }
+}
+
+impl Display for FailureCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureCategory::User => write!(f, "user error"),
+            FailureCategory::Environment => write!(f, "environment error"),
+            FailureCategory::Transient => write!(f, "transient error"),
+            FailureCategory::Internal => write!(f, "internal error"),
+        }
+    }
+}
+
+/// Environment variable with the path of a file only the owner can read holding the source
+/// credentials of a job as JSON list of [`SourceCredential`]. The worker hands them to
+/// pkgdev this way so the secrets do not end up in the environment of the build steps,
+/// pkgdev deletes the file and removes the variable when it starts.
+pub const SOURCE_CREDENTIALS_FILE_ENV: &str = "PKGDEV_SOURCE_CREDENTIALS_FILE";
+
+/// Environment variable with the path of the secret key pkgdev signs the packages it makes
+/// with, overriding the `signing_key` setting.
+pub const SIGNING_KEY_ENV: &str = "PKGDEV_SIGNING_KEY";
+
+/// Extension of the detached signatures written next to signed files.
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// Environment variable the worker passes the manifest template of the publisher it
+/// builds for to pkgdev in, as JSON of a [`gate::ManifestTemplate`]. gate.kdl wins over it.
+pub const MANIFEST_TEMPLATE_ENV: &str = "PKGDEV_MANIFEST_TEMPLATE";
+
+/// Environment variable the worker passes the url of forged to pkgdev in, archives are
+/// fetched through its source mirror first.
+pub const SOURCE_MIRROR_ENV: &str = "PKGDEV_SOURCE_MIRROR";
+
+/// Environment variable with the directory pkgdev keeps its package repositories in,
+/// overriding the `repository_dir` setting.
+pub const REPOSITORY_DIR_ENV: &str = "PKGDEV_REPOSITORY_DIR";
+
+/// Credentials for fetching sources from private git repositories or archives behind
+/// basic auth. They apply to every url starting with `url_prefix`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SourceCredential {
+    pub url_prefix: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sent as bearer token, or as password of basic auth when a username is set
+    pub token: Option<String>,
+    /// Path of the private key used for ssh urls
+    pub ssh_key: Option<String>,
+}
+
+impl std::fmt::Debug for SourceCredential {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let redacted = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>");
+        f.debug_struct("SourceCredential")
+            .field("url_prefix", &self.url_prefix)
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("token", &redacted(&self.token))
+            .field("ssh_key", &self.ssh_key)
+            .finish()
+    }
+}
+
+impl SourceCredential {
+    /// The credential with the longest prefix matching the url.
+    pub fn find<'a>(credentials: &'a [SourceCredential], url: &str) -> Option<&'a Self> {
+        credentials
+            .iter()
+            .filter(|c| url.starts_with(&c.url_prefix))
+            .max_by_key(|c| c.url_prefix.len())
+    }
+
+    /// The value of the Authorization header for HTTP requests.
+    pub fn authorization(&self) -> Option<String> {
+        use base64::Engine;
+        match (&self.username, &self.password, &self.token) {
+            (Some(username), password, token) => {
+                let secret = password.as_ref().or(token.as_ref()).cloned();
+                let basic = format!("{}:{}", username, secret.unwrap_or_default());
+                Some(format!(
+                    "Basic {}",
+                    base64::engine::general_purpose::STANDARD.encode(basic)
+                ))
+            }
+            (None, _, Some(token)) => Some(format!("Bearer {}", token)),
+            (None, _, None) => None,
+        }
+    }
+
+    /// Environment variables making git use the credential. They are passed in the
+    /// environment so the secret does not show up in the arguments of the process.
+    pub fn git_env(&self) -> Vec<(String, String)> {
+        let mut env = vec![];
+        if let Some(authorization) = self.authorization() {
+            env.push((String::from("GIT_CONFIG_COUNT"), String::from("1")));
+            env.push((
+                String::from("GIT_CONFIG_KEY_0"),
+                String::from("http.extraHeader"),
+            ));
+            env.push((
+                String::from("GIT_CONFIG_VALUE_0"),
+                format!("Authorization: {}", authorization),
+            ));
+        }
+        if let Some(ssh_key) = &self.ssh_key {
+            env.push((
+                String::from("GIT_SSH_COMMAND"),
+                format!("ssh -i {} -o IdentitiesOnly=yes", ssh_key),
+            ));
+        }
+        env
+    }
+}
+
+/// The url without the username and password it may carry, for logs and messages.
+pub fn redact_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) if !parsed.username().is_empty() || parsed.password().is_some() => {
+            let _ = parsed.set_username("");
+            let _ = parsed.set_password(None);
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
 }
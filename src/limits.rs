@@ -0,0 +1,21 @@
+//! Resource limits the sandboxes of pkgdev and the worker put on the processes they run.
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+pub type Resource = libc::c_int;
+
+/// Set the soft and hard limit of `resource` for the calling process. Only makes a system
+/// call, so it is safe to use in `pre_exec`.
+#[cfg(unix)]
+pub fn set_limit(resource: Resource, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    // SAFETY: the limit outlives the call
+    match unsafe { libc::setrlimit(resource, &limit) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
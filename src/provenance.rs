@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use component::{Recipe, SourceNode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::ComponentId;
+
+/// The in-toto statement format provenance is wrapped in.
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+/// How forge builds stored components, its external parameters are [`BuildParameters`].
+pub const BUILD_TYPE: &str = "https://github.com/Toasterson/forge/build/stored-component/v1";
+
+/// File in the workspace root pkgdev writes the [`Artifact`]s of a build to.
+pub const ARTIFACTS_REPORT_FILE: &str = "artifacts.json";
+
+/// A file a build produced, a package manifest or a package archive.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Artifact {
+    pub name: String,
+    pub sha256: String,
+}
+
+impl Artifact {
+    pub fn from_file(name: &str, path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            sha256: sha256(&std::fs::read(path)?),
+        })
+    }
+}
+
+/// A SLSA provenance statement about the artifacts of one build.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<ResourceDescriptor>,
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    pub predicate: Provenance,
+}
+
+impl Statement {
+    pub fn new(artifacts: Vec<Artifact>, predicate: Provenance) -> Self {
+        Self {
+            statement_type: STATEMENT_TYPE.to_string(),
+            subject: artifacts
+                .into_iter()
+                .map(|a| ResourceDescriptor {
+                    name: Some(a.name),
+                    uri: None,
+                    digest: BTreeMap::from([(String::from("sha256"), a.sha256)]),
+                })
+                .collect(),
+            predicate_type: PREDICATE_TYPE.to_string(),
+            predicate,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    pub build_definition: BuildDefinition,
+    pub run_details: RunDetails,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildDefinition {
+    pub build_type: String,
+    pub external_parameters: BuildParameters,
+    /// The sources of the recipe
+    pub resolved_dependencies: Vec<ResourceDescriptor>,
+}
+
+/// What was asked to be built.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildParameters {
+    pub component: ComponentId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staging: Option<String>,
+    pub bootstrap: bool,
+    /// SHA-256 of the recipe as forged stores it
+    pub recipe_sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResourceDescriptor {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    /// Algorithm to digest like `sha256` or `gitCommit`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub digest: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RunDetails {
+    pub builder: Builder,
+    pub metadata: BuildMetadata,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Builder {
+    pub id: String,
+    /// Versions of pkgdev and the compilers on the builder
+    #[serde(default)]
+    pub version: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildMetadata {
+    /// The reference of the job that built the artifacts
+    pub invocation_id: String,
+    /// RFC 3339 timestamps
+    pub started_on: String,
+    pub finished_on: String,
+}
+
+/// The sources of the recipe with the digests it pins them to. Git sources followed by
+/// branch or tag only carry the ref as their uri has nothing to digest.
+pub fn resolved_dependencies(recipe: &Recipe) -> Vec<ResourceDescriptor> {
+    recipe
+        .sources
+        .iter()
+        .flat_map(|section| section.sources.iter())
+        .filter_map(|source| match source {
+            SourceNode::Archive(archive) => {
+                let mut digest = BTreeMap::new();
+                if let Some(sha256) = &archive.sha256 {
+                    digest.insert(String::from("sha256"), sha256.clone());
+                }
+                if let Some(sha512) = &archive.sha512 {
+                    digest.insert(String::from("sha512"), sha512.clone());
+                }
+                Some(ResourceDescriptor {
+                    name: None,
+                    uri: Some(archive.src.clone()),
+                    digest,
+                })
+            }
+            SourceNode::Git(git) => {
+                let reference = git.tag.as_ref().or(git.branch.as_ref());
+                Some(ResourceDescriptor {
+                    name: None,
+                    uri: Some(match reference {
+                        Some(reference) => format!("git+{}@{}", git.repository, reference),
+                        None => format!("git+{}", git.repository),
+                    }),
+                    digest: git
+                        .commit
+                        .iter()
+                        .map(|commit| (String::from("gitCommit"), commit.clone()))
+                        .collect(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn sha256(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use component::{ArchiveSourceBuilder, GitSourceBuilder, RecipeBuilder, SourceSection};
+
+    #[test]
+    fn sources_are_resolved_with_their_pins() -> miette::Result<()> {
+        let mut recipe = RecipeBuilder::default().name("library/openssl").build()?;
+        recipe.sources = vec![SourceSection {
+            name: None,
+            sources: vec![
+                SourceNode::Archive(
+                    ArchiveSourceBuilder::default()
+                        .src("https://www.openssl.org/source/openssl-3.3.1.tar.gz")
+                        .sha256("777cd596")
+                        .build()?,
+                ),
+                SourceNode::Git(
+                    GitSourceBuilder::default()
+                        .repository("https://github.com/openssl/openssl.git")
+                        .tag("openssl-3.3.1")
+                        .commit("db2ac4f6")
+                        .build()?,
+                ),
+            ],
+        }];
+
+        let resolved = resolved_dependencies(&recipe);
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(resolved[0].digest["sha256"], "777cd596");
+        assert_eq!(
+            resolved[1].uri.as_deref(),
+            Some("git+https://github.com/openssl/openssl.git@openssl-3.3.1")
+        );
+        assert_eq!(resolved[1].digest["gitCommit"], "db2ac4f6");
+        Ok(())
+    }
+}
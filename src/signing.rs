@@ -0,0 +1,187 @@
+use base64::Engine;
+use ed25519_compact::{KeyPair, PublicKey, Signature};
+use miette::Diagnostic;
+use sha2::{Digest, Sha512};
+use thiserror::Error;
+
+/// Algorithm of the keys and signatures, the only one signify knows.
+const PKALG: &[u8; 2] = b"Ed";
+/// Key derivation of secret keys. Zero rounds mean the key is not encrypted.
+const KDFALG: &[u8; 2] = b"BK";
+const COMMENT_PREFIX: &str = "untrusted comment: ";
+
+#[derive(Error, Debug, Diagnostic)]
+pub enum SigningError {
+    #[error("not a signify {0}: {1}")]
+    Malformed(&'static str, String),
+    #[error("the secret key is encrypted, only keys without a passphrase can be used")]
+    Encrypted,
+    #[error("the signature was made with key {0} not with key {1}")]
+    WrongKey(String, String),
+    #[error("the signature does not match")]
+    BadSignature,
+}
+
+/// An Ed25519 key pair written in the formats of OpenBSD signify, so consumers verify
+/// packages with `signify -V` without any forge tooling.
+pub struct SigningKey {
+    key_id: [u8; 8],
+    keypair: KeyPair,
+}
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        let keypair = KeyPair::generate();
+        // signify draws the key number at random, the hash of the public key is as unique
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&Sha512::digest(keypair.pk.as_ref())[..8]);
+        Self { key_id, keypair }
+    }
+
+    /// Read a secret key written by [`SigningKey::secret_key`] or `signify -G -n`.
+    pub fn from_secret_key(file: &str) -> Result<Self, SigningError> {
+        // pkalg, kdfalg, kdfrounds, salt, checksum, keynum and the key itself
+        let data = decode_file("secret key", file, 2 + 2 + 4 + 16 + 8 + 8 + 64)?;
+        if &data[2..4] != KDFALG {
+            return Err(SigningError::Malformed(
+                "secret key",
+                String::from("unknown key derivation"),
+            ));
+        }
+        if data[4..8] != [0; 4] {
+            return Err(SigningError::Encrypted);
+        }
+        let seckey = &data[40..];
+        if Sha512::digest(seckey)[..8] != data[24..32] {
+            return Err(SigningError::Malformed(
+                "secret key",
+                String::from("checksum mismatch"),
+            ));
+        }
+        let keypair = KeyPair::from_slice(seckey)
+            .map_err(|e| SigningError::Malformed("secret key", e.to_string()))?;
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&data[32..40]);
+        Ok(Self { key_id, keypair })
+    }
+
+    /// The number signatures name their key with, in hex.
+    pub fn key_id(&self) -> String {
+        hex(&self.key_id)
+    }
+
+    pub fn public_key(&self, comment: &str) -> String {
+        encode_file(
+            comment,
+            &[PKALG.as_slice(), &self.key_id, self.keypair.pk.as_ref()].concat(),
+        )
+    }
+
+    /// The secret key, unencrypted. Whoever has it can sign in the name of the publisher.
+    pub fn secret_key(&self, comment: &str) -> String {
+        let seckey = self.keypair.sk.as_ref();
+        let checksum = &Sha512::digest(seckey)[..8];
+        encode_file(
+            comment,
+            &[
+                PKALG.as_slice(),
+                KDFALG,
+                &0u32.to_be_bytes(),
+                &[0u8; 16],
+                checksum,
+                &self.key_id,
+                seckey,
+            ]
+            .concat(),
+        )
+    }
+
+    /// A detached signature of the message like `signify -S` writes it.
+    pub fn sign(&self, message: &[u8], comment: &str) -> String {
+        let signature = self.keypair.sk.sign(message, None);
+        encode_file(
+            comment,
+            &[PKALG.as_slice(), &self.key_id, signature.as_ref()].concat(),
+        )
+    }
+}
+
+/// Check a detached signature of the message against a public key.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<(), SigningError> {
+    let key = decode_file("public key", public_key, 2 + 8 + 32)?;
+    let sig = decode_file("signature", signature, 2 + 8 + 64)?;
+    if key[2..10] != sig[2..10] {
+        return Err(SigningError::WrongKey(hex(&sig[2..10]), hex(&key[2..10])));
+    }
+    let pk = PublicKey::from_slice(&key[10..])
+        .map_err(|e| SigningError::Malformed("public key", e.to_string()))?;
+    let sig = Signature::from_slice(&sig[10..])
+        .map_err(|e| SigningError::Malformed("signature", e.to_string()))?;
+    pk.verify(message, &sig)
+        .map_err(|_| SigningError::BadSignature)
+}
+
+fn encode_file(comment: &str, data: &[u8]) -> String {
+    format!(
+        "{}{}\n{}\n",
+        COMMENT_PREFIX,
+        comment,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    )
+}
+
+fn decode_file(kind: &'static str, file: &str, len: usize) -> Result<Vec<u8>, SigningError> {
+    let mut lines = file.lines();
+    if !lines.next().is_some_and(|l| l.starts_with(COMMENT_PREFIX)) {
+        return Err(SigningError::Malformed(
+            kind,
+            String::from("no comment line"),
+        ));
+    }
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(lines.next().unwrap_or_default().trim())
+        .map_err(|e| SigningError::Malformed(kind, e.to_string()))?;
+    if data.len() != len || &data[..2] != PKALG {
+        return Err(SigningError::Malformed(
+            kind,
+            format!(
+                "expected {} bytes of {}",
+                len,
+                String::from_utf8_lossy(PKALG)
+            ),
+        ));
+    }
+    Ok(data)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_with_the_exported_keys() {
+        let key = SigningKey::generate();
+        let public_key = key.public_key("userland public key");
+        let signature = key.sign(b"package", "verify with userland public key");
+        assert!(verify(&public_key, b"package", &signature).is_ok());
+        assert!(matches!(
+            verify(&public_key, b"tampered", &signature),
+            Err(SigningError::BadSignature)
+        ));
+
+        let restored = SigningKey::from_secret_key(&key.secret_key("userland secret key")).unwrap();
+        assert_eq!(restored.key_id(), key.key_id());
+        let signature = restored.sign(b"package", "verify with userland public key");
+        assert!(verify(&public_key, b"package", &signature).is_ok());
+
+        let other = SigningKey::generate().public_key("other public key");
+        assert!(matches!(
+            verify(&other, b"package", &signature),
+            Err(SigningError::WrongKey(..))
+        ));
+    }
+}